@@ -4,7 +4,7 @@ use sni_proxy::logger::{init_logger, LogConfig, LogLevel};
 fn main() {
     // 示例 1: 使用默认配置
     println!("=== 示例 1: 默认配置 (INFO 级别) ===\n");
-    init_logger(LogConfig::default());
+    init_logger(LogConfig::default()).expect("初始化日志失败");
 
     error!("这是一条错误日志");
     warn!("这是一条警告日志");
@@ -1,4 +1,4 @@
-use log::{debug, error, info, warn};
+use log::{error, info, warn};
 use sni_proxy::logger::{init_logger, LogConfig, LogLevel};
 use std::fs;
 use std::thread;
@@ -80,7 +80,7 @@ fn create_rotating_log_demo() {
     println!("  app.log.3     <- 第3个备份（最旧）");
 
     // 模拟写入大量日志
-    if let Ok(_) = init_logger(config) {
+    if init_logger(config).is_ok() {
         for i in 1..=100 {
             info!("这是第 {} 条日志，用于测试日志轮转功能", i);
         }
@@ -88,12 +88,10 @@ fn create_rotating_log_demo() {
         // 检查生成的文件
         println!("\n生成的日志文件:");
         if let Ok(entries) = fs::read_dir("logs/rotating_test") {
-            for entry in entries {
-                if let Ok(entry) = entry {
-                    let path = entry.path();
-                    if let Ok(metadata) = entry.metadata() {
-                        println!("  {} ({} bytes)", path.display(), metadata.len());
-                    }
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if let Ok(metadata) = entry.metadata() {
+                    println!("  {} ({} bytes)", path.display(), metadata.len());
                 }
             }
         }
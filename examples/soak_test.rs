@@ -0,0 +1,437 @@
+//! 长时间压力测试（soak test）：检测追踪器、连接许可、文件描述符和内存泄漏
+//!
+//! 运行: `cargo run --release --example soak_test -- [秒数]`（默认 60 秒）
+//!
+//! 在进程内启动代理和一个会周期性下线的上游服务器，多个客户端持续制造连接抖动：
+//! 正常转发、连接后立即断开、发送半个 Client Hello、DNS 解析失败、域名不在白名单、上游拒绝连接。
+//! 每轮结束后暂停负载，等待所有连接关闭，然后检查：
+//! - 活跃连接数归零，且所有连接许可都能重新获取
+//! - 文件描述符数量、RSS 相对首轮基线的增长在限定范围内
+//! - IP 流量追踪器、域名-IP 追踪器、DNS 缓存的条目数不超过容量
+//!
+//! 任何检查失败时以非零状态码退出。设置 `RUST_LOG` 可查看代理日志
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use sni_proxy::{PortMapping, SniProxy};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpSocket, TcpStream};
+use tokio::sync::{watch, RwLock};
+
+/// 可以正常解析并转发的域名
+const GOOD_DOMAIN: &str = "localhost";
+/// 在白名单中但 DNS 解析失败的域名
+const DNS_FAIL_DOMAIN: &str = "soak-fail.invalid";
+/// 不在白名单中的域名
+const REJECTED_DOMAIN: &str = "not-allowed.example";
+
+/// 最大并发连接数（同时也是许可检查时打开的连接数）
+const MAX_CONNECTIONS: usize = 64;
+/// IP 流量追踪器最多保留的 IP 数（客户端使用远多于此的源地址）
+const MAX_TRACKED_IPS: usize = 200;
+/// DNS 缓存容量
+const DNS_CACHE_CAPACITY: usize = 16;
+/// 并发客户端数量
+const WORKERS: usize = 32;
+/// 每轮负载持续时间
+const ROUND: Duration = Duration::from_secs(10);
+/// 上游在线 / 下线时长
+const UPSTREAM_UP: Duration = Duration::from_millis(1500);
+const UPSTREAM_DOWN: Duration = Duration::from_millis(500);
+/// 等待连接全部关闭的超时（需要大于读取 Client Hello 的超时和 DNS 解析超时）
+const QUIESCE_TIMEOUT: Duration = Duration::from_secs(30);
+/// 相对首轮基线允许增长的文件描述符数量
+const FD_SLACK: usize = 16;
+/// 相对首轮基线允许增长的 RSS（KB）
+const RSS_SLACK_KB: u64 = 64 * 1024;
+
+/// 客户端各场景的执行次数
+#[derive(Default)]
+struct ChurnStats {
+    relayed: AtomicU64,
+    relay_failed: AtomicU64,
+    storms: AtomicU64,
+    partial_hellos: AtomicU64,
+    dns_failures: AtomicU64,
+    rejected: AtomicU64,
+}
+
+/// 首轮检查记录的基线
+struct Baseline {
+    fds: usize,
+    rss_kb: u64,
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("off")).init();
+
+    let duration = std::env::args()
+        .nth(1)
+        .map(|secs| Duration::from_secs(secs.parse().expect("参数应为测试时长（秒）")))
+        .unwrap_or(Duration::from_secs(60));
+
+    let upstream_port = free_port().await;
+    let proxy_port = free_port().await;
+    let proxy_addr = SocketAddr::from(([127, 0, 0, 1], proxy_port));
+
+    let (stop_tx, stop_rx) = watch::channel(false);
+    tokio::spawn(run_flapping_upstream(upstream_port, stop_rx.clone()));
+
+    let proxy = Arc::new(
+        SniProxy::new(proxy_addr, vec![GOOD_DOMAIN.to_string(), DNS_FAIL_DOMAIN.to_string()])
+            .with_port_mapping(PortMapping::new(upstream_port))
+            // IP 流量追踪只统计白名单内的客户端
+            .with_ip_whitelist(vec!["127.0.0.0/8".to_string()])
+            .with_max_connections(MAX_CONNECTIONS)
+            .with_ip_traffic_tracking(MAX_TRACKED_IPS, None, None)
+            .with_domain_ip_tracking(None)
+            .with_dns_cache_capacity(DNS_CACHE_CAPACITY),
+    );
+    let server = tokio::spawn({
+        let proxy = Arc::clone(&proxy);
+        let stop_rx = stop_rx.clone();
+        async move { proxy.run_with_shutdown(Some(stop_rx)).await }
+    });
+    wait_for_listener(proxy_addr).await;
+
+    println!(
+        "soak test: 代理 {} → 上游 127.0.0.1:{}，持续 {:?}，每轮 {:?}",
+        proxy_addr, upstream_port, duration, ROUND
+    );
+
+    // 负载任务每次迭代持有读锁，检查时获取写锁即可暂停全部负载
+    let gate = Arc::new(RwLock::new(()));
+    let running = Arc::new(AtomicBool::new(true));
+    let stats = Arc::new(ChurnStats::default());
+    let workers: Vec<_> = (0..WORKERS)
+        .map(|id| {
+            tokio::spawn(churn(
+                id,
+                proxy_addr,
+                Arc::clone(&gate),
+                Arc::clone(&running),
+                Arc::clone(&stats),
+            ))
+        })
+        .collect();
+
+    let start = Instant::now();
+    let mut baseline = None;
+    let mut round = 0;
+    let mut failures = Vec::new();
+    while start.elapsed() < duration && failures.is_empty() {
+        tokio::time::sleep(ROUND).await;
+        round += 1;
+
+        let _paused = gate.write().await;
+        failures = check_round(round, &proxy, proxy_addr, &stats, &mut baseline).await;
+    }
+
+    running.store(false, Ordering::Relaxed);
+    for worker in workers {
+        let _ = worker.await;
+    }
+    let _ = stop_tx.send(true);
+    let _ = server.await;
+
+    if failures.is_empty() {
+        println!("✅ soak test 通过（{} 轮）", round);
+    } else {
+        for failure in &failures {
+            println!("❌ {}", failure);
+        }
+        std::process::exit(1);
+    }
+}
+
+/// 单个客户端：按顺序循环执行各种连接场景
+async fn churn(id: usize, proxy_addr: SocketAddr, gate: Arc<RwLock<()>>, running: Arc<AtomicBool>, stats: Arc<ChurnStats>) {
+    let mut iteration = 0usize;
+    while running.load(Ordering::Relaxed) {
+        let _active = gate.read().await;
+        iteration += 1;
+        // 每次使用不同的回环源地址，让 IP 流量追踪器不断淘汰旧条目
+        let source = source_ip(id, iteration);
+
+        match iteration % 6 {
+            0 | 1 => {
+                if relay_once(source, proxy_addr).await {
+                    stats.relayed.fetch_add(1, Ordering::Relaxed);
+                } else {
+                    stats.relay_failed.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            2 => {
+                // 连接后立即断开
+                drop(connect_from(source, proxy_addr).await);
+                stats.storms.fetch_add(1, Ordering::Relaxed);
+            }
+            3 => {
+                // 只发送半个 Client Hello 后断开
+                if let Some(mut stream) = connect_from(source, proxy_addr).await {
+                    let hello = client_hello(GOOD_DOMAIN);
+                    let _ = stream.write_all(&hello[..hello.len() / 2]).await;
+                }
+                stats.partial_hellos.fetch_add(1, Ordering::Relaxed);
+            }
+            4 => {
+                send_hello_and_wait_close(source, proxy_addr, DNS_FAIL_DOMAIN).await;
+                stats.dns_failures.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => {
+                send_hello_and_wait_close(source, proxy_addr, REJECTED_DOMAIN).await;
+                stats.rejected.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// 完整转发一次：发送 Client Hello 和数据，等待上游回显
+async fn relay_once(source: IpAddr, proxy_addr: SocketAddr) -> bool {
+    let Some(mut stream) = connect_from(source, proxy_addr).await else {
+        return false;
+    };
+    let mut request = client_hello(GOOD_DOMAIN);
+    request.extend_from_slice(&[0x5a; 4096]);
+
+    let result = tokio::time::timeout(Duration::from_secs(5), async {
+        stream.write_all(&request).await?;
+        let mut echoed = vec![0u8; request.len()];
+        stream.read_exact(&mut echoed).await?;
+        Ok::<bool, std::io::Error>(echoed == request)
+    })
+    .await;
+    matches!(result, Ok(Ok(true)))
+}
+
+/// 发送 Client Hello，等待代理关闭连接
+async fn send_hello_and_wait_close(source: IpAddr, proxy_addr: SocketAddr, domain: &str) {
+    if let Some(mut stream) = connect_from(source, proxy_addr).await {
+        let _ = stream.write_all(&client_hello(domain)).await;
+        let mut buf = [0u8; 64];
+        let _ = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf)).await;
+    }
+}
+
+/// 一轮负载结束后的检查
+async fn check_round(
+    round: usize,
+    proxy: &SniProxy,
+    proxy_addr: SocketAddr,
+    stats: &ChurnStats,
+    baseline: &mut Option<Baseline>,
+) -> Vec<String> {
+    let mut failures = Vec::new();
+    let metrics = proxy.metrics();
+
+    if !wait_until(QUIESCE_TIMEOUT, || metrics.get_active_connections() == 0).await {
+        failures.push(format!(
+            "负载停止 {:?} 后仍有 {} 个活跃连接",
+            QUIESCE_TIMEOUT,
+            metrics.get_active_connections()
+        ));
+        return failures;
+    }
+
+    // 所有许可都已归还时，才能同时建立 MAX_CONNECTIONS 个连接
+    let mut held = Vec::with_capacity(MAX_CONNECTIONS);
+    for _ in 0..MAX_CONNECTIONS {
+        if let Ok(stream) = TcpStream::connect(proxy_addr).await {
+            held.push(stream);
+        }
+    }
+    if !wait_until(Duration::from_secs(5), || metrics.get_active_connections() == MAX_CONNECTIONS).await {
+        failures.push(format!(
+            "连接许可泄漏：只能同时处理 {}/{} 个连接",
+            metrics.get_active_connections(),
+            MAX_CONNECTIONS
+        ));
+    }
+    drop(held);
+    if !wait_until(QUIESCE_TIMEOUT, || metrics.get_active_connections() == 0).await {
+        failures.push("许可检查连接关闭后活跃连接数未归零".to_string());
+    }
+
+    let fds = open_fds();
+    let rss_kb = rss_kb();
+    let tracked_ips = proxy.ip_traffic_tracker().get_tracked_count();
+    let (domains, domain_ips) = proxy.domain_ip_tracker().get_stats();
+    let dns = sni_proxy::dns::get_dns_cache_stats().await;
+
+    println!(
+        "第 {} 轮 | 连接 {} | 转发 {}（失败 {}）| 断开 {} | 半握手 {} | DNS 失败 {} | 拒绝 {} | fd {} | RSS {} KB | IP {} | 域名 {}/{} | DNS 缓存 {}",
+        round,
+        metrics.get_total_connections(),
+        stats.relayed.load(Ordering::Relaxed),
+        stats.relay_failed.load(Ordering::Relaxed),
+        stats.storms.load(Ordering::Relaxed),
+        stats.partial_hellos.load(Ordering::Relaxed),
+        stats.dns_failures.load(Ordering::Relaxed),
+        stats.rejected.load(Ordering::Relaxed),
+        fds,
+        rss_kb,
+        tracked_ips,
+        domains,
+        domain_ips,
+        dns.size,
+    );
+
+    if tracked_ips > MAX_TRACKED_IPS {
+        failures.push(format!("IP 流量追踪器条目 {} 超过上限 {}", tracked_ips, MAX_TRACKED_IPS));
+    }
+    // 只有 GOOD_DOMAIN 能解析成功，且只解析到回环地址
+    if domains > 1 || domain_ips > 2 {
+        failures.push(format!("域名-IP 追踪器异常增长: {} 个域名 / {} 个 IP", domains, domain_ips));
+    }
+    if dns.size > dns.capacity {
+        failures.push(format!("DNS 缓存条目 {} 超过容量 {}", dns.size, dns.capacity));
+    }
+
+    match baseline {
+        // 首轮包含运行时线程、缓冲区等一次性开销，作为基线
+        None => *baseline = Some(Baseline { fds, rss_kb }),
+        Some(baseline) => {
+            if fds > baseline.fds + FD_SLACK {
+                failures.push(format!("文件描述符泄漏: {} → {}", baseline.fds, fds));
+            }
+            if rss_kb > baseline.rss_kb + RSS_SLACK_KB {
+                failures.push(format!("RSS 持续增长: {} KB → {} KB", baseline.rss_kb, rss_kb));
+            }
+        }
+    }
+
+    failures
+}
+
+/// 上游回显服务器：周期性下线（关闭监听 socket）模拟上游抖动，在线时每 10 个连接直接断开一个
+async fn run_flapping_upstream(port: u16, mut stop_rx: watch::Receiver<bool>) {
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    let mut accepted = 0u64;
+    while !*stop_rx.borrow() {
+        let socket = TcpSocket::new_v4().expect("创建上游 socket 失败");
+        socket.set_reuseaddr(true).expect("设置 SO_REUSEADDR 失败");
+        socket.bind(addr).expect("绑定上游端口失败");
+        let listener = socket.listen(1024).expect("上游监听失败");
+
+        let up = tokio::time::sleep(UPSTREAM_UP);
+        tokio::pin!(up);
+        loop {
+            tokio::select! {
+                _ = &mut up => break,
+                _ = stop_rx.changed() => return,
+                result = listener.accept() => {
+                    let Ok((stream, _)) = result else { continue };
+                    accepted += 1;
+                    if !accepted.is_multiple_of(10) {
+                        tokio::spawn(echo(stream));
+                    }
+                }
+            }
+        }
+
+        drop(listener);
+        tokio::select! {
+            _ = tokio::time::sleep(UPSTREAM_DOWN) => {}
+            _ = stop_rx.changed() => return,
+        }
+    }
+}
+
+async fn echo(mut stream: TcpStream) {
+    let (mut reader, mut writer) = stream.split();
+    let _ = tokio::io::copy(&mut reader, &mut writer).await;
+}
+
+/// 从指定回环源地址连接代理
+async fn connect_from(source: IpAddr, target: SocketAddr) -> Option<TcpStream> {
+    let socket = TcpSocket::new_v4().ok()?;
+    socket.bind(SocketAddr::new(source, 0)).ok()?;
+    socket.connect(target).await.ok()
+}
+
+/// 127.0.0.0/8 内的源地址（每个客户端使用不同的网段）
+fn source_ip(worker: usize, iteration: usize) -> IpAddr {
+    let host = iteration % 250 + 2;
+    IpAddr::V4(Ipv4Addr::new(127, (worker % 250 + 1) as u8, (iteration / 250 % 256) as u8, host as u8))
+}
+
+/// 构造带 SNI 扩展的最小 TLS Client Hello
+fn client_hello(sni: &str) -> Vec<u8> {
+    let name = sni.as_bytes();
+
+    let mut extension = Vec::new();
+    extension.extend_from_slice(&0u16.to_be_bytes()); // server_name
+    extension.extend_from_slice(&((name.len() + 5) as u16).to_be_bytes());
+    extension.extend_from_slice(&((name.len() + 3) as u16).to_be_bytes());
+    extension.push(0x00);
+    extension.extend_from_slice(&(name.len() as u16).to_be_bytes());
+    extension.extend_from_slice(name);
+
+    let mut body = vec![0x03, 0x03]; // TLS 1.2
+    body.extend_from_slice(&[0u8; 32]); // 随机数
+    body.push(0); // Session ID
+    body.extend_from_slice(&[0x00, 0x02, 0x13, 0x01]); // Cipher Suites
+    body.extend_from_slice(&[0x01, 0x00]); // Compression Methods
+    body.extend_from_slice(&(extension.len() as u16).to_be_bytes());
+    body.extend_from_slice(&extension);
+
+    let mut handshake = vec![0x01];
+    handshake.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+    handshake.extend_from_slice(&body);
+
+    let mut record = vec![0x16, 0x03, 0x01];
+    record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+    record.extend_from_slice(&handshake);
+    record
+}
+
+/// 获取一个当前空闲的本地端口
+async fn free_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("绑定临时端口失败");
+    listener.local_addr().expect("获取临时端口失败").port()
+}
+
+async fn wait_for_listener(addr: SocketAddr) {
+    let ready = wait_until_async(Duration::from_secs(5), || async { TcpStream::connect(addr).await.is_ok() }).await;
+    assert!(ready, "代理未能在 5 秒内开始监听 {}", addr);
+}
+
+/// 轮询直到条件成立或超时
+async fn wait_until(timeout: Duration, mut condition: impl FnMut() -> bool) -> bool {
+    wait_until_async(timeout, || std::future::ready(condition())).await
+}
+
+async fn wait_until_async<F: std::future::Future<Output = bool>>(timeout: Duration, mut condition: impl FnMut() -> F) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if condition().await {
+            return true;
+        }
+        if Instant::now() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+/// 当前进程打开的文件描述符数量（仅 Linux，其他平台返回 0）
+fn open_fds() -> usize {
+    std::fs::read_dir("/proc/self/fd").map(|dir| dir.count()).unwrap_or(0)
+}
+
+/// 当前进程的 RSS（KB，仅 Linux，其他平台返回 0）
+fn rss_kb() -> u64 {
+    std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix("VmRSS:"))
+                .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+        })
+        .unwrap_or(0)
+}
@@ -0,0 +1,128 @@
+//! 新连接速率限制
+//!
+//! 连接风暴时所有连接都在争抢 CPU 和并发连接许可，accept 变慢，正常客户端也开始超时。
+//! 开启后对所有监听器接受的新连接统一按令牌桶限速：令牌按速率补充，最多积累 `burst` 个；
+//! 没有令牌时新连接立即关闭（不读取 Client Hello），或者在 `max_queue_wait` 内排队等待令牌，等不到再关闭
+
+use log::{info, warn};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// 新连接速率限制配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AcceptRateConfig {
+    /// 每秒允许的新连接数
+    pub rate: u32,
+    /// 最多积累的令牌数（允许的突发连接数）
+    pub burst: u32,
+    /// 没有令牌时最多排队等待的时间，为 0 时立即关闭
+    pub max_queue_wait: Duration,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// 可用令牌数（为负表示已预约给排队中的连接）
+    tokens: f64,
+    updated: Instant,
+}
+
+/// 新连接令牌桶
+#[derive(Debug)]
+pub struct AcceptLimiter {
+    config: AcceptRateConfig,
+    bucket: Mutex<Bucket>,
+    /// 是否正在丢弃新连接（只在开始和恢复时输出日志）
+    overloaded: AtomicBool,
+}
+
+impl AcceptLimiter {
+    pub fn new(config: AcceptRateConfig) -> Self {
+        let config = AcceptRateConfig { rate: config.rate.max(1), burst: config.burst.max(1), ..config };
+        Self {
+            config,
+            bucket: Mutex::new(Bucket { tokens: config.burst as f64, updated: Instant::now() }),
+            overloaded: AtomicBool::new(false),
+        }
+    }
+
+    /// 预约一个令牌：返回拿到令牌前需要等待的时间，等待超过 `max_queue_wait` 时不预约并返回 None
+    fn reserve(&self, now: Instant) -> Option<Duration> {
+        let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+        let rate = self.config.rate as f64;
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(self.config.burst as f64);
+        bucket.updated = now;
+
+        let wait = if bucket.tokens >= 1.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((1.0 - bucket.tokens) / rate)
+        };
+        if wait > self.config.max_queue_wait {
+            return None;
+        }
+        bucket.tokens -= 1.0;
+        Some(wait)
+    }
+
+    /// 按速率接纳一个新连接（需要时排队等待），返回 false 表示应关闭该连接
+    pub async fn admit(&self) -> bool {
+        match self.reserve(Instant::now()) {
+            Some(wait) => {
+                if !wait.is_zero() {
+                    tokio::time::sleep(wait).await;
+                }
+                if self.overloaded.swap(false, Ordering::Relaxed) {
+                    info!("✅ 新连接速率恢复正常");
+                }
+                true
+            }
+            None => {
+                if !self.overloaded.swap(true, Ordering::Relaxed) {
+                    warn!("⚠️  新连接速率超过上限 {}/s，开始丢弃新连接", self.config.rate);
+                }
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_shed_when_empty() {
+        let limiter = AcceptLimiter::new(AcceptRateConfig { rate: 10, burst: 3, max_queue_wait: Duration::ZERO });
+        let admitted = futures::future::join_all((0..5).map(|_| limiter.admit())).await;
+        assert_eq!(admitted, [true, true, true, false, false]);
+
+        // 100ms 补充一个令牌
+        tokio::time::advance(Duration::from_millis(100)).await;
+        assert!(limiter.admit().await);
+        assert!(!limiter.admit().await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_queue_wait() {
+        let limiter = AcceptLimiter::new(AcceptRateConfig {
+            rate: 10,
+            burst: 1,
+            max_queue_wait: Duration::from_millis(250),
+        });
+        let start = Instant::now();
+        assert!(limiter.admit().await);
+        // 后续连接依次预约 100ms、200ms 后的令牌，第 4 个需要等 300ms，超过上限
+        assert_eq!(limiter.reserve(start), Some(Duration::from_millis(100)));
+        assert_eq!(limiter.reserve(start), Some(Duration::from_millis(200)));
+        assert_eq!(limiter.reserve(start), None);
+        assert!(!limiter.admit().await);
+
+        // 100ms 后排队的连接需要等待 200ms
+        tokio::time::advance(Duration::from_millis(100)).await;
+        assert!(limiter.admit().await);
+        assert_eq!(start.elapsed(), Duration::from_millis(300));
+    }
+}
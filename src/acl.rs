@@ -0,0 +1,157 @@
+//! 访问控制列表（ACL）
+//!
+//! 同时按客户端 IP 和目标域名判断连接是否允许，例如 “10.0.0.0/8 的客户端可以访问 *.internal.example.com，
+//! 其他客户端只能访问 github.com”。规则按顺序匹配，第一条条件全部满足的规则决定允许或拒绝，
+//! 没有规则匹配时拒绝。ACL 在路由之前检查，与 IP 白名单、域名白名单分别生效（都通过才会建立连接）
+
+use std::net::IpAddr;
+
+use crate::domain::DomainMatcher;
+use crate::error::{Result, SniProxyError};
+use crate::ip_matcher::IpMatcher;
+
+/// ACL 动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AclAction {
+    /// 允许连接
+    Allow,
+    /// 拒绝连接
+    Deny,
+}
+
+impl std::str::FromStr for AclAction {
+    type Err = SniProxyError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "allow" => Ok(AclAction::Allow),
+            "deny" => Ok(AclAction::Deny),
+            _ => Err(SniProxyError::InvalidConfig(format!("无效的 ACL 动作: {}（可选: allow, deny）", s))),
+        }
+    }
+}
+
+impl std::fmt::Display for AclAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AclAction::Allow => write!(f, "allow"),
+            AclAction::Deny => write!(f, "deny"),
+        }
+    }
+}
+
+/// 一条 ACL 规则，未设置的条件视为匹配任意值
+#[derive(Debug, Clone)]
+pub struct AclRule {
+    client_ips: Option<IpMatcher>,
+    domains: Option<DomainMatcher>,
+    action: AclAction,
+}
+
+impl AclRule {
+    /// 匹配所有连接的规则
+    pub fn new(action: AclAction) -> Self {
+        Self {
+            client_ips: None,
+            domains: None,
+            action,
+        }
+    }
+
+    /// 客户端 IP 条件（IP 或 CIDR）
+    pub fn with_client_ips(mut self, client_ips: Vec<String>) -> Self {
+        self.client_ips = Some(IpMatcher::new(client_ips));
+        self
+    }
+
+    /// 域名条件（支持通配符）
+    pub fn with_domains(mut self, domains: Vec<String>) -> Self {
+        self.domains = Some(DomainMatcher::new(domains));
+        self
+    }
+
+    fn matches_client(&self, client_ip: IpAddr) -> bool {
+        self.client_ips.as_ref().is_none_or(|m| m.matches(client_ip.to_canonical()))
+    }
+}
+
+/// 有序的 ACL 规则列表
+#[derive(Debug, Clone)]
+pub struct Acl {
+    rules: Vec<AclRule>,
+}
+
+impl Acl {
+    pub fn new(rules: Vec<AclRule>) -> Self {
+        Self { rules }
+    }
+
+    /// 按客户端 IP 和域名检查，返回第一条匹配规则的动作，没有规则匹配时拒绝
+    pub fn check(&self, client_ip: IpAddr, domain: &str) -> AclAction {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches_client(client_ip) && rule.domains.as_ref().is_none_or(|m| m.matches(domain)))
+            .map_or(AclAction::Deny, |rule| rule.action)
+    }
+
+    /// 客户端是否可能访问某个域名（读取 SNI 之前使用，尽早拒绝没有任何允许规则的客户端）
+    ///
+    /// 按顺序检查客户端匹配的规则：遇到 allow 返回 true；不限域名的 deny 对该客户端的所有域名生效，返回 false
+    pub fn may_allow_client(&self, client_ip: IpAddr) -> bool {
+        for rule in self.rules.iter().filter(|rule| rule.matches_client(client_ip)) {
+            match rule.action {
+                AclAction::Allow => return true,
+                AclAction::Deny if rule.domains.is_none() => return false,
+                AclAction::Deny => {}
+            }
+        }
+        false
+    }
+
+    /// 规则数量
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// 是否没有规则
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_check() {
+        let acl = Acl::new(vec![
+            AclRule::new(AclAction::Deny).with_domains(strings(&["secret.internal.example.com"])),
+            AclRule::new(AclAction::Allow)
+                .with_client_ips(strings(&["10.0.0.0/8"]))
+                .with_domains(strings(&["*.internal.example.com"])),
+            AclRule::new(AclAction::Allow).with_domains(strings(&["github.com"])),
+            AclRule::new(AclAction::Deny).with_client_ips(strings(&["192.0.2.0/24"])),
+        ]);
+        let check = |ip: &str, domain: &str| acl.check(ip.parse().unwrap(), domain);
+
+        assert_eq!(check("10.1.2.3", "git.internal.example.com"), AclAction::Allow);
+        assert_eq!(check("::ffff:10.1.2.3", "git.internal.example.com"), AclAction::Allow);
+        assert_eq!(check("10.1.2.3", "secret.internal.example.com"), AclAction::Deny);
+        assert_eq!(check("10.1.2.3", "github.com"), AclAction::Allow);
+        assert_eq!(check("198.51.100.1", "git.internal.example.com"), AclAction::Deny);
+        assert_eq!(check("198.51.100.1", "github.com"), AclAction::Allow);
+        assert_eq!(check("198.51.100.1", "example.org"), AclAction::Deny);
+
+        assert!(acl.may_allow_client("10.1.2.3".parse().unwrap()));
+        assert!(acl.may_allow_client("198.51.100.1".parse().unwrap()));
+        assert!(!Acl::new(vec![AclRule::new(AclAction::Deny).with_client_ips(strings(&["192.0.2.0/24"]))])
+            .may_allow_client("192.0.2.1".parse().unwrap()));
+        assert!("DENY".parse::<AclAction>().is_ok());
+        assert!("reject".parse::<AclAction>().is_err());
+    }
+}
@@ -0,0 +1,563 @@
+use log::{debug, info, warn};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use subtle::ConstantTimeEq;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::connection::ConnectionContext;
+use crate::dns::get_dns_cache_stats;
+use crate::error::{Result, SniProxyError};
+use crate::domain_ip_tracker::DomainIpTracker;
+use crate::explain::{explain, ExplainRequest};
+use crate::http::{read_request_head, RequestHead};
+use crate::ip_traffic::IpTrafficTracker;
+use crate::listener::ListenAddr;
+use crate::metrics::Metrics;
+
+/// 管理接口配置
+#[derive(Debug, Clone)]
+pub struct AdminConfig {
+    /// 管理接口监听地址
+    pub listen_addr: SocketAddr,
+    /// 访问令牌（可选），配置后请求需携带 `Authorization: Bearer <token>`
+    pub auth_token: Option<String>,
+}
+
+/// 管理接口可访问的运行时状态
+#[derive(Clone)]
+pub struct AdminState {
+    /// 代理监听地址
+    pub proxy_listen_addr: ListenAddr,
+    /// 是否配置了 SOCKS5 出口
+    pub socks5_enabled: bool,
+    /// 性能监控指标
+    pub metrics: Metrics,
+    /// IP 流量追踪器
+    pub ip_traffic_tracker: IpTrafficTracker,
+    /// 域名-IP 追踪器
+    pub domain_ip_tracker: DomainIpTracker,
+    /// 运行中的路由配置（`/explain` 使用）
+    pub(crate) routing: ConnectionContext,
+}
+
+/// 解析后的管理接口请求
+#[derive(Debug, Clone, PartialEq)]
+struct AdminRequest {
+    method: String,
+    path: String,
+    query: HashMap<String, String>,
+    authorization: Option<String>,
+}
+
+/// 管理接口响应
+#[derive(Debug)]
+struct AdminResponse {
+    status: u16,
+    body: Value,
+}
+
+impl AdminResponse {
+    fn ok(body: Value) -> Self {
+        Self { status: 200, body }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self {
+            status,
+            body: json!({ "error": message }),
+        }
+    }
+}
+
+/// 启动管理接口（HTTP + JSON）
+///
+/// 提供以下只读端点：
+/// - `GET /status` - 运行概览
+/// - `GET /stats` - 性能监控指标
+/// - `GET /dns` - DNS 缓存状态、命中率与解析超时/失败次数
+/// - `GET /ip-traffic?top=N` - IP 流量统计 TOP N
+/// - `GET /domain-ip` - 域名-IP 追踪统计
+/// - `GET /explain?sni=X&client=Y` - 按运行中的配置逐步解释连接的路由决策（可选 `port`、`alpn`、`resolve=1`）
+pub async fn run_admin_server(config: AdminConfig, state: AdminState) -> Result<()> {
+    let listener = TcpListener::bind(config.listen_addr)
+        .await
+        .map_err(|source| SniProxyError::Bind { addr: config.listen_addr, source })?;
+    info!("✅ 管理接口已启动: http://{}", config.listen_addr);
+    if config.auth_token.is_none() && !config.listen_addr.ip().is_loopback() {
+        warn!("⚠️  管理接口监听在非本地地址且未配置 auth_token，任何人都可以访问");
+    }
+
+    let config = Arc::new(config);
+    let state = Arc::new(state);
+
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("管理接口接受连接失败: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+
+        let config = Arc::clone(&config);
+        let state = Arc::clone(&state);
+        tokio::spawn(async move {
+            if let Err(e) = handle_admin_connection(stream, &config, &state).await {
+                debug!("管理接口请求处理失败 ({}): {}", peer, e);
+            }
+        });
+    }
+}
+
+/// 处理单个管理接口连接（每个连接只处理一个请求）
+async fn handle_admin_connection(
+    mut stream: TcpStream,
+    config: &AdminConfig,
+    state: &AdminState,
+) -> Result<()> {
+    let response = match read_request_head(&mut stream, Duration::from_secs(5)).await? {
+        RequestHead::Complete(buffer) => match parse_request_head(&buffer) {
+            Some(request) => {
+                if authorized(&request, config) {
+                    route(&request, state).await
+                } else {
+                    AdminResponse::error(401, "unauthorized")
+                }
+            }
+            None => AdminResponse::error(400, "bad request"),
+        },
+        RequestHead::Invalid => AdminResponse::error(400, "bad request"),
+        RequestHead::TimedOut => AdminResponse::error(408, "request timeout"),
+    };
+
+    write_response(&mut stream, &response).await
+}
+
+/// 检查访问令牌
+///
+/// 比较两边的 SHA-256 摘要并使用常量时间比较，比较耗时与令牌内容和长度无关，不能通过计时逐字节猜出令牌
+fn authorized(request: &AdminRequest, config: &AdminConfig) -> bool {
+    match &config.auth_token {
+        None => true,
+        Some(token) => request
+            .authorization
+            .as_deref()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|provided| bool::from(Sha256::digest(provided.trim()).ct_eq(&Sha256::digest(token))))
+            .unwrap_or(false),
+    }
+}
+
+/// 根据路径分发请求
+async fn route(request: &AdminRequest, state: &AdminState) -> AdminResponse {
+    if request.method != "GET" {
+        return AdminResponse::error(405, "method not allowed");
+    }
+
+    match request.path.as_str() {
+        "/" | "/status" => AdminResponse::ok(status_json(state)),
+        "/stats" => AdminResponse::ok(metrics_json(&state.metrics)),
+        "/dns" => AdminResponse::ok(dns_json().await),
+        "/ip-traffic" => {
+            let top = request
+                .query
+                .get("top")
+                .and_then(|v| v.parse::<usize>().ok())
+                .unwrap_or(10);
+            AdminResponse::ok(ip_traffic_json(&state.ip_traffic_tracker, top))
+        }
+        "/domain-ip" => AdminResponse::ok(domain_ip_json(&state.domain_ip_tracker)),
+        "/explain" => match explain_request(&request.query) {
+            Ok(explain_request) => AdminResponse::ok(explain(&state.routing, &explain_request).await),
+            Err(message) => AdminResponse::error(400, message),
+        },
+        #[cfg(feature = "alloc-audit")]
+        "/allocations" => AdminResponse::ok(allocations_json()),
+        _ => AdminResponse::error(404, "not found"),
+    }
+}
+
+/// 解析 `/explain` 的查询参数：`sni`、`client` 必填，`port`（监听端口）、`alpn`（逗号分隔）、`resolve=1` 可选
+fn explain_request(query: &HashMap<String, String>) -> std::result::Result<ExplainRequest, &'static str> {
+    let sni = query.get("sni").filter(|sni| !sni.is_empty()).ok_or("missing sni")?;
+    let client_ip = query
+        .get("client")
+        .ok_or("missing client")?
+        .parse::<IpAddr>()
+        .map_err(|_| "invalid client ip")?;
+    let listen_port = match query.get("port") {
+        Some(port) => Some(port.parse::<u16>().map_err(|_| "invalid port")?),
+        None => None,
+    };
+    let alpn = query
+        .get("alpn")
+        .map(|alpn| alpn.split(',').filter(|p| !p.is_empty()).map(str::to_string).collect())
+        .unwrap_or_default();
+    Ok(ExplainRequest {
+        sni: sni.to_ascii_lowercase(),
+        client_ip: client_ip.to_canonical(),
+        listen_port,
+        alpn,
+        resolve: query.get("resolve").is_some_and(|v| v == "1" || v == "true"),
+    })
+}
+
+fn status_json(state: &AdminState) -> Value {
+    let snapshot = state.metrics.snapshot();
+    json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "listen_addr": state.proxy_listen_addr.to_string(),
+        "uptime_secs": snapshot.uptime.as_secs(),
+        "active_connections": snapshot.active_connections,
+        "total_connections": snapshot.total_connections,
+        "components": {
+            "socks5": state.socks5_enabled,
+            "ip_traffic_tracking": state.ip_traffic_tracker.is_enabled(),
+            "domain_ip_tracking": state.domain_ip_tracker.is_enabled(),
+        },
+    })
+}
+
+fn metrics_json(metrics: &Metrics) -> Value {
+    let snapshot = metrics.snapshot();
+    let series: Vec<Value> = metrics
+        .series_snapshot()
+        .into_iter()
+        .map(|(labels, counters)| {
+            json!({
+                "listener": labels.listener.to_string(),
+                "route": labels.route.to_string(),
+                "upstream": labels.upstream.to_string(),
+                "total_connections": counters.total_connections,
+                "failed_connections": counters.failed_connections,
+                "bytes_received": counters.bytes_received,
+                "bytes_sent": counters.bytes_sent,
+                "direct_requests": counters.direct_requests,
+                "socks5_requests": counters.socks5_requests,
+                "rejected_requests": counters.rejected_requests,
+                "sni_parse_errors": counters.sni_parse_errors,
+                "plaintext_http_requests": counters.plaintext_http_requests,
+                "fingerprint_rejections": counters.fingerprint_rejections,
+                "ip_sni_rejections": counters.ip_sni_rejections,
+                "burst_rejections": counters.burst_rejections,
+                "geo_rejections": counters.geo_rejections,
+                "ban_rejections": counters.ban_rejections,
+                "overload_rejections": counters.overload_rejections,
+                "socks5_errors": counters.socks5_errors,
+                "connection_timeouts": counters.connection_timeouts,
+                "idle_timeouts": counters.idle_timeouts,
+                "slow_handshake_drops": counters.slow_handshake_drops,
+                "connect_retries": counters.connect_retries,
+                "connect_retry_failures": counters.connect_retry_failures,
+                "upstream_fallbacks": counters.upstream_fallbacks,
+                "splice_relays": counters.splice_relays,
+                "userspace_relays": counters.userspace_relays,
+                "uring_relays": counters.uring_relays,
+                "dns_prefetched": counters.dns_prefetched,
+                "dns_prefetch_failures": counters.dns_prefetch_failures,
+                "upstream_tls_alerts": counters.upstream_tls_alerts,
+                "upstream_not_tls": counters.upstream_not_tls,
+            })
+        })
+        .collect();
+
+    json!({
+        "uptime_secs": snapshot.uptime.as_secs(),
+        "total_connections": snapshot.total_connections,
+        "active_connections": snapshot.active_connections,
+        "failed_connections": snapshot.failed_connections,
+        "bytes_received": snapshot.bytes_received,
+        "bytes_sent": snapshot.bytes_sent,
+        "direct_requests": snapshot.direct_requests,
+        "socks5_requests": snapshot.socks5_requests,
+        "rejected_requests": snapshot.rejected_requests,
+        "sni_parse_errors": snapshot.sni_parse_errors,
+        "plaintext_http_requests": snapshot.plaintext_http_requests,
+        "fingerprint_rejections": snapshot.fingerprint_rejections,
+        "ip_sni_rejections": snapshot.ip_sni_rejections,
+        "burst_rejections": snapshot.burst_rejections,
+        "geo_rejections": snapshot.geo_rejections,
+        "ban_rejections": snapshot.ban_rejections,
+        "overload_rejections": snapshot.overload_rejections,
+        "socks5_errors": snapshot.socks5_errors,
+        "connection_timeouts": snapshot.connection_timeouts,
+        "idle_timeouts": snapshot.idle_timeouts,
+        "slow_handshake_drops": snapshot.slow_handshake_drops,
+        "connect_retries": snapshot.connect_retries,
+        "connect_retry_failures": snapshot.connect_retry_failures,
+        "upstream_fallbacks": snapshot.upstream_fallbacks,
+        "splice_relays": snapshot.splice_relays,
+        "userspace_relays": snapshot.userspace_relays,
+        "uring_relays": snapshot.uring_relays,
+        "dns_prefetched": snapshot.dns_prefetched,
+        "dns_prefetch_failures": snapshot.dns_prefetch_failures,
+        "upstream_tls_alerts": snapshot.upstream_tls_alerts,
+        "upstream_not_tls": snapshot.upstream_not_tls,
+        "connections_per_cpu": snapshot.connections_per_cpu,
+        "disk_full_errors": snapshot.disk_full_errors,
+        "disk_full": snapshot.disk_full,
+        "series": series,
+    })
+}
+
+async fn dns_json() -> Value {
+    let stats = get_dns_cache_stats().await;
+    json!({
+        "cache_size": stats.size,
+        "cache_capacity": stats.capacity,
+        "cache_hits": stats.hits,
+        "cache_misses": stats.misses,
+        "hit_rate": stats.hit_rate(),
+        "timeouts": stats.timeouts,
+        "failures": stats.failures,
+        "stale_served": stats.stale_served,
+    })
+}
+
+fn ip_traffic_json(tracker: &IpTrafficTracker, top: usize) -> Value {
+    let top_ips: Vec<Value> = tracker
+        .get_top_n(top)
+        .into_iter()
+        .map(|s| {
+            json!({
+                "ip": s.ip.to_string(),
+                "bytes_received": s.bytes_received,
+                "bytes_sent": s.bytes_sent,
+                "total_bytes": s.total_bytes,
+                "connections": s.connections,
+            })
+        })
+        .collect();
+
+    json!({
+        "enabled": tracker.is_enabled(),
+        "tracked_ips": tracker.get_tracked_count(),
+        "top": top_ips,
+    })
+}
+
+#[cfg(feature = "alloc-audit")]
+fn allocations_json() -> Value {
+    let stages: Vec<Value> = crate::alloc_audit::allocation_report()
+        .into_iter()
+        .map(|r| {
+            json!({
+                "stage": r.stage.name(),
+                "samples": r.samples,
+                "avg_allocations": r.avg_allocations,
+                "avg_bytes": r.avg_bytes,
+            })
+        })
+        .collect();
+    json!({ "stages": stages })
+}
+
+fn domain_ip_json(tracker: &DomainIpTracker) -> Value {
+    let (domains, ips) = tracker.get_stats();
+    json!({
+        "enabled": tracker.is_enabled(),
+        "domains": domains,
+        "ips": ips,
+    })
+}
+
+/// 解析 HTTP 请求头（请求行 + Authorization 头）
+fn parse_request_head(data: &[u8]) -> Option<AdminRequest> {
+    let head = std::str::from_utf8(data).ok()?;
+    let mut lines = head.split("\r\n");
+
+    let request_line = lines.next()?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let target = parts.next()?;
+    let version = parts.next()?;
+    if !version.starts_with("HTTP/1.") {
+        return None;
+    }
+
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), parse_query(query)),
+        None => (target.to_string(), HashMap::new()),
+    };
+
+    let mut authorization = None;
+    for line in lines {
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("authorization") {
+                authorization = Some(value.trim().to_string());
+            }
+        }
+    }
+
+    Some(AdminRequest {
+        method,
+        path,
+        query,
+        authorization,
+    })
+}
+
+/// 解析查询字符串（支持 `%XX` 和 `+` 解码）
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((key, value)) => (percent_decode(key), percent_decode(value)),
+            None => (percent_decode(pair), String::new()),
+        })
+        .collect()
+}
+
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => output.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = |b: u8| (b as char).to_digit(16);
+                match (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                    (Some(high), Some(low)) => {
+                        output.push((high * 16 + low) as u8);
+                        i += 2;
+                    }
+                    _ => output.push(b'%'),
+                }
+            }
+            byte => output.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&output).into_owned()
+}
+
+async fn write_response(stream: &mut TcpStream, response: &AdminResponse) -> Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        _ => "Error",
+    };
+    let body = serde_json::to_string_pretty(&response.body).map_err(std::io::Error::from)?;
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason,
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::tests::test_context;
+    use crate::metrics::{ListenerLabel, MetricLabels};
+
+    fn test_state() -> AdminState {
+        AdminState {
+            proxy_listen_addr: "127.0.0.1:8443".parse().unwrap(),
+            socks5_enabled: false,
+            metrics: Metrics::new(),
+            ip_traffic_tracker: IpTrafficTracker::disabled(),
+            domain_ip_tracker: DomainIpTracker::disabled(),
+            routing: test_context(&["example.com"], &[]).0,
+        }
+    }
+
+    #[test]
+    fn test_parse_request_head() {
+        let raw = b"GET /ip-traffic?top=5 HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer secret\r\n\r\n";
+        let request = parse_request_head(raw).unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, "/ip-traffic");
+        assert_eq!(request.query.get("top").map(String::as_str), Some("5"));
+        assert_eq!(request.authorization.as_deref(), Some("Bearer secret"));
+
+        assert!(parse_request_head(b"GARBAGE\r\n\r\n").is_none());
+        assert!(parse_request_head(b"GET / SPDY/3\r\n\r\n").is_none());
+    }
+
+    #[test]
+    fn test_parse_query_decoding() {
+        let query = parse_query("sni=a%2Eexample.com&client=1.2.3.4&flag&name=a+b");
+        assert_eq!(query.get("sni").map(String::as_str), Some("a.example.com"));
+        assert_eq!(query.get("client").map(String::as_str), Some("1.2.3.4"));
+        assert_eq!(query.get("flag").map(String::as_str), Some(""));
+        assert_eq!(query.get("name").map(String::as_str), Some("a b"));
+        assert_eq!(percent_decode("100%"), "100%");
+    }
+
+    #[test]
+    fn test_authorization() {
+        let config = AdminConfig {
+            listen_addr: "127.0.0.1:9090".parse().unwrap(),
+            auth_token: Some("secret".to_string()),
+        };
+        let mut request = parse_request_head(b"GET /stats HTTP/1.1\r\n\r\n").unwrap();
+        assert!(!authorized(&request, &config));
+
+        request.authorization = Some("Bearer wrong".to_string());
+        assert!(!authorized(&request, &config));
+
+        request.authorization = Some("Bearer secret".to_string());
+        assert!(authorized(&request, &config));
+    }
+
+    #[tokio::test]
+    async fn test_route() {
+        let state = test_state();
+        state.metrics.inc_rejected_requests();
+        state
+            .metrics
+            .with_labels(MetricLabels::listener(ListenerLabel::Tcp(8443)))
+            .inc_rejected_requests();
+
+        let request = parse_request_head(b"GET /stats HTTP/1.1\r\n\r\n").unwrap();
+        let response = route(&request, &state).await;
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body["rejected_requests"], 2);
+        assert_eq!(response.body["series"][0]["listener"], "tcp:8443");
+        assert_eq!(response.body["series"][0]["route"], "none");
+        assert_eq!(response.body["series"][0]["rejected_requests"], 1);
+
+        let request = parse_request_head(b"GET /dns HTTP/1.1\r\n\r\n").unwrap();
+        let response = route(&request, &state).await;
+        assert_eq!(response.status, 200);
+        assert!(response.body.get("cache_capacity").is_some());
+
+        let request = parse_request_head(b"GET /explain?sni=Example.com&client=::ffff:10.0.0.1 HTTP/1.1\r\n\r\n").unwrap();
+        let response = route(&request, &state).await;
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body["client"], "10.0.0.1");
+        assert_eq!(response.body["decision"], "connect");
+        for bad in ["/explain?client=10.0.0.1", "/explain?sni=example.com&client=x", "/explain?sni=a&client=10.0.0.1&port=0x"] {
+            let raw = format!("GET {} HTTP/1.1\r\n\r\n", bad);
+            let request = parse_request_head(raw.as_bytes()).unwrap();
+            assert_eq!(route(&request, &state).await.status, 400, "{}", bad);
+        }
+
+        let request = parse_request_head(b"GET /nope HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(route(&request, &state).await.status, 404);
+
+        let request = parse_request_head(b"POST /stats HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(route(&request, &state).await.status, 405);
+    }
+}
@@ -0,0 +1,169 @@
+use crate::error::{Result, SniProxyError};
+
+/// CPU 编号解析错误
+fn invalid_cpu(part: &str) -> SniProxyError {
+    SniProxyError::InvalidConfig(format!("无效的 CPU 编号: {}", part))
+}
+
+/// 解析 Linux cpulist 格式的 CPU 列表
+///
+/// 格式与 `/sys/devices/system/node/nodeN/cpulist`、`/proc/irq/N/smp_affinity_list` 一致，
+/// 例如 `"0-3,8,10-11"` → `[0, 1, 2, 3, 8, 10, 11]`（结果已排序去重）
+pub fn parse_cpu_list(list: &str) -> Result<Vec<usize>> {
+    let mut cpus = Vec::new();
+
+    for part in list.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let start: usize = start.trim().parse().map_err(|_| invalid_cpu(part))?;
+                let end: usize = end.trim().parse().map_err(|_| invalid_cpu(part))?;
+                if start > end {
+                    return Err(SniProxyError::InvalidConfig(format!("无效的 CPU 范围: {}", part)));
+                }
+                cpus.extend(start..=end);
+            }
+            None => {
+                cpus.push(part.parse().map_err(|_| invalid_cpu(part))?);
+            }
+        }
+    }
+
+    cpus.sort_unstable();
+    cpus.dedup();
+    Ok(cpus)
+}
+
+/// 读取 NUMA 节点包含的 CPU 列表（仅 Linux）
+pub fn numa_node_cpus(node: usize) -> Result<Vec<usize>> {
+    let path = format!("/sys/devices/system/node/node{}/cpulist", node);
+    let content = std::fs::read_to_string(&path).map_err(|e| {
+        SniProxyError::InvalidConfig(format!("无法读取 NUMA 节点 {} 的 CPU 列表 ({}): {}", node, path, e))
+    })?;
+    parse_cpu_list(&content)
+}
+
+/// 将当前线程绑定到指定的 CPU 集合
+///
+/// 线程可以在集合内的任意 CPU 上调度（不是绑定到单个 CPU），
+/// 这样同一 NUMA 节点内的负载均衡仍由内核完成
+#[cfg(target_os = "linux")]
+pub fn pin_current_thread(cpus: &[usize]) -> std::io::Result<()> {
+    if cpus.is_empty() {
+        return Ok(());
+    }
+
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_ZERO(&mut set);
+        for &cpu in cpus {
+            if cpu >= libc::CPU_SETSIZE as usize {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("CPU 编号 {} 超出范围", cpu),
+                ));
+            }
+            libc::CPU_SET(cpu, &mut set);
+        }
+
+        // pid 0 表示当前线程
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+/// 将当前线程绑定到指定的 CPU 集合（非 Linux 平台不支持）
+#[cfg(not(target_os = "linux"))]
+pub fn pin_current_thread(cpus: &[usize]) -> std::io::Result<()> {
+    if cpus.is_empty() {
+        return Ok(());
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "当前平台不支持 CPU 亲和性设置",
+    ))
+}
+
+/// 为监听 socket 设置 SO_INCOMING_CPU（仅 Linux 3.19+）
+///
+/// 配合 SO_REUSEPORT 使用：内核优先把在该 CPU 上收到的新连接分配给此监听 socket
+#[cfg(target_os = "linux")]
+pub fn set_incoming_cpu<S: std::os::unix::io::AsRawFd>(socket: &S, cpu: usize) -> std::io::Result<()> {
+    let value = cpu as libc::c_int;
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_INCOMING_CPU,
+            &value as *const _ as *const libc::c_void,
+            std::mem::size_of_val(&value) as libc::socklen_t,
+        )
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// 读取已接受连接的 SO_INCOMING_CPU（处理该连接网络包的 CPU 编号）
+#[cfg(target_os = "linux")]
+pub fn incoming_cpu<S: std::os::unix::io::AsRawFd>(socket: &S) -> Option<usize> {
+    let mut value: libc::c_int = -1;
+    let mut len = std::mem::size_of_val(&value) as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_INCOMING_CPU,
+            &mut value as *mut _ as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if result != 0 || value < 0 {
+        return None;
+    }
+    Some(value as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(parse_cpu_list("0").unwrap(), vec![0]);
+        assert_eq!(parse_cpu_list("0-3").unwrap(), vec![0, 1, 2, 3]);
+        assert_eq!(parse_cpu_list("0-1,4,6-7\n").unwrap(), vec![0, 1, 4, 6, 7]);
+        assert_eq!(parse_cpu_list("3,1,1-2").unwrap(), vec![1, 2, 3]);
+        assert!(parse_cpu_list("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_cpu_list_invalid() {
+        assert!(parse_cpu_list("a").is_err());
+        assert!(parse_cpu_list("3-1").is_err());
+        assert!(parse_cpu_list("1-").is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_pin_current_thread() {
+        // 在独立线程中测试，避免影响其他测试
+        std::thread::spawn(|| {
+            assert!(pin_current_thread(&[]).is_ok());
+            assert!(pin_current_thread(&[usize::MAX]).is_err());
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_incoming_cpu_roundtrip() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        set_incoming_cpu(&listener, 0).unwrap();
+        assert_eq!(incoming_cpu(&listener), Some(0));
+    }
+}
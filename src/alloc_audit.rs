@@ -0,0 +1,213 @@
+//! 热路径内存分配统计（仅在启用 `alloc-audit` feature 时编译）
+//!
+//! 使用计数分配器统计每个连接阶段的分配次数和字节数，为缓冲池、匹配器等优化提供实测数据。
+//! 计数器是线程局部的，每次 poll 阶段 future 前后取差值，因此任务在线程间迁移时统计仍然准确
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+thread_local! {
+    /// 当前线程累计的（分配次数, 分配字节数）
+    static THREAD_ALLOCATIONS: Cell<(u64, u64)> = const { Cell::new((0, 0)) };
+}
+
+/// 计数分配器（包装系统分配器）
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record(layout.size());
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+fn record(size: usize) {
+    // 线程退出阶段 TLS 已销毁时忽略
+    let _ = THREAD_ALLOCATIONS.try_with(|counter| {
+        let (count, bytes) = counter.get();
+        counter.set((count + 1, bytes + size as u64));
+    });
+}
+
+fn thread_allocations() -> (u64, u64) {
+    THREAD_ALLOCATIONS.try_with(Cell::get).unwrap_or((0, 0))
+}
+
+/// 连接处理阶段（与连接状态机的状态一一对应）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// IP 白名单检查
+    CheckIp,
+    /// 读取 Client Hello
+    ReadHello,
+    /// 域名白名单路由
+    Route,
+    /// 连接目标服务器
+    Connect,
+    /// 双向转发
+    Relay,
+    /// 按拒绝方式断开连接
+    Reject,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 6] = [
+        Stage::CheckIp,
+        Stage::ReadHello,
+        Stage::Route,
+        Stage::Connect,
+        Stage::Relay,
+        Stage::Reject,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::CheckIp => "check_ip",
+            Stage::ReadHello => "read_hello",
+            Stage::Route => "route",
+            Stage::Connect => "connect",
+            Stage::Relay => "relay",
+            Stage::Reject => "reject",
+        }
+    }
+}
+
+/// 单个阶段的累计统计
+struct StageCounters {
+    samples: AtomicU64,
+    allocations: AtomicU64,
+    bytes: AtomicU64,
+}
+
+static STAGES: [StageCounters; 6] = [const {
+    StageCounters {
+        samples: AtomicU64::new(0),
+        allocations: AtomicU64::new(0),
+        bytes: AtomicU64::new(0),
+    }
+}; 6];
+
+/// 单个阶段的分配统计快照
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StageAllocations {
+    pub stage: Stage,
+    /// 统计的连接数（执行过该阶段的次数）
+    pub samples: u64,
+    /// 平均每次的分配次数
+    pub avg_allocations: f64,
+    /// 平均每次的分配字节数
+    pub avg_bytes: f64,
+}
+
+/// 获取各阶段的分配统计
+pub fn allocation_report() -> Vec<StageAllocations> {
+    Stage::ALL
+        .iter()
+        .map(|&stage| {
+            let counters = &STAGES[stage as usize];
+            let samples = counters.samples.load(Ordering::Relaxed);
+            let average = |total: &AtomicU64| {
+                if samples == 0 {
+                    0.0
+                } else {
+                    total.load(Ordering::Relaxed) as f64 / samples as f64
+                }
+            };
+            StageAllocations {
+                stage,
+                samples,
+                avg_allocations: average(&counters.allocations),
+                avg_bytes: average(&counters.bytes),
+            }
+        })
+        .collect()
+}
+
+/// 打印各阶段的平均分配次数
+pub fn print_summary() {
+    log::info!("=== 内存分配统计（每连接平均）===");
+    for report in allocation_report() {
+        log::info!(
+            "{:<10} 样本 {:>8} | 分配 {:>8.1} 次 | {:>10.1} 字节",
+            report.stage.name(),
+            report.samples,
+            report.avg_allocations,
+            report.avg_bytes
+        );
+    }
+}
+
+/// 统计 `future` 执行期间（仅 poll 内）的分配，完成时计入 `stage`
+pub fn count<F: Future>(stage: Stage, future: F) -> CountAllocations<F> {
+    CountAllocations {
+        stage,
+        future: Box::pin(future),
+        allocations: 0,
+        bytes: 0,
+    }
+}
+
+/// [`count`] 返回的 future
+pub struct CountAllocations<F> {
+    stage: Stage,
+    future: Pin<Box<F>>,
+    allocations: u64,
+    bytes: u64,
+}
+
+impl<F: Future> Future for CountAllocations<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let (count_before, bytes_before) = thread_allocations();
+        let result = self.future.as_mut().poll(cx);
+        let (count_after, bytes_after) = thread_allocations();
+        self.allocations += count_after - count_before;
+        self.bytes += bytes_after - bytes_before;
+
+        if result.is_ready() {
+            let counters = &STAGES[self.stage as usize];
+            counters.samples.fetch_add(1, Ordering::Relaxed);
+            counters.allocations.fetch_add(self.allocations, Ordering::Relaxed);
+            counters.bytes.fetch_add(self.bytes, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_count_allocations() {
+        let before = allocation_report()[Stage::Route as usize];
+        let data = count(Stage::Route, async { vec![0u8; 1000] }).await;
+        assert_eq!(data.len(), 1000);
+
+        let after = allocation_report()[Stage::Route as usize];
+        assert!(after.samples > before.samples);
+        assert!(after.avg_bytes * after.samples as f64 >= before.avg_bytes * before.samples as f64 + 1000.0);
+    }
+}
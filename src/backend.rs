@@ -0,0 +1,70 @@
+//! 默认后端
+//!
+//! SNI（或 HTTP Host）不在任何白名单中的连接可以转发到一个兜底服务器，而不是直接拒绝
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::{Result, SniProxyError};
+
+/// 后端地址：`host:port`，IPv6 使用 `[addr]:port`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackendAddr {
+    /// 主机名或 IP 地址（主机名在连接时经 DNS 缓存解析）
+    pub host: String,
+    pub port: u16,
+}
+
+impl FromStr for BackendAddr {
+    type Err = SniProxyError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || SniProxyError::InvalidConfig(format!("无效的后端地址: {}（应为 host:port）", s));
+        let (host, port) = match s.strip_prefix('[') {
+            Some(rest) => {
+                let (host, port) = rest.split_once(']').ok_or_else(invalid)?;
+                (host, port.strip_prefix(':').ok_or_else(invalid)?)
+            }
+            None => s.rsplit_once(':').ok_or_else(invalid)?,
+        };
+        let port: u16 = port.parse().map_err(|_| invalid())?;
+        if host.is_empty() || port == 0 || (host.contains(':') && !s.starts_with('[')) {
+            return Err(invalid());
+        }
+        Ok(Self {
+            host: host.to_string(),
+            port,
+        })
+    }
+}
+
+impl fmt::Display for BackendAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "{}:{}", self.host, self.port)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_backend_addr() {
+        let backend: BackendAddr = "catchall.internal:8443".parse().unwrap();
+        assert_eq!(backend.host, "catchall.internal");
+        assert_eq!(backend.port, 8443);
+        assert_eq!(backend.to_string(), "catchall.internal:8443");
+
+        let backend: BackendAddr = "[2001:db8::1]:443".parse().unwrap();
+        assert_eq!(backend.host, "2001:db8::1");
+        assert_eq!(backend.to_string(), "[2001:db8::1]:443");
+
+        for invalid in ["catchall.internal", ":443", "host:0", "host:http", "2001:db8::1:443", "[::1]"] {
+            assert!(invalid.parse::<BackendAddr>().is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! 自动封禁
+//!
+//! 扫描器会不停地发起无法解析 SNI 或不在白名单中的连接，每次都要读取 Client Hello 并输出拒绝日志。
+//! 开启后按客户端 IP 统计失败的连接（被拒绝或无法解析 SNI）：窗口内的失败次数达到阈值时封禁该 IP 一段时间，
+//! 封禁期间的连接在接受后立即关闭，不读取 Client Hello，也不输出警告日志。封禁表只保存在内存中
+
+use lru::LruCache;
+use std::net::IpAddr;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 最多跟踪的客户端 IP 数，超出时淘汰最久未出现的
+const MAX_TRACKED_IPS: usize = 100_000;
+
+/// 自动封禁配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BanConfig {
+    /// 统计窗口
+    pub window: Duration,
+    /// 窗口内允许的失败次数，达到后封禁
+    pub max_failures: u32,
+    /// 封禁时长
+    pub ban_duration: Duration,
+}
+
+impl Default for BanConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(60),
+            max_failures: 10,
+            ban_duration: Duration::from_secs(600),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct IpState {
+    window_start: Instant,
+    failures: u32,
+    banned_until: Option<Instant>,
+}
+
+/// 按客户端 IP 统计失败的连接并自动封禁
+#[derive(Debug)]
+pub struct BanTable {
+    config: BanConfig,
+    ips: Mutex<LruCache<IpAddr, IpState>>,
+}
+
+impl BanTable {
+    pub fn new(config: BanConfig) -> Self {
+        Self {
+            config,
+            ips: Mutex::new(LruCache::new(NonZeroUsize::new(MAX_TRACKED_IPS).unwrap())),
+        }
+    }
+
+    /// 处于封禁期间时返回剩余的封禁时长
+    pub fn check(&self, client_ip: IpAddr, now: Instant) -> Option<Duration> {
+        // 封禁中的 IP 持续连接时保持在表中，不被淘汰
+        let mut ips = self.ips.lock().unwrap();
+        let until = ips.get(&client_ip)?.banned_until?;
+        (now < until).then(|| until - now)
+    }
+
+    /// 记录一次失败的连接，这次失败触发封禁时返回封禁时长
+    pub fn record_failure(&self, client_ip: IpAddr, now: Instant) -> Option<Duration> {
+        let mut ips = self.ips.lock().unwrap();
+        let state = ips.get_or_insert_mut(client_ip, || IpState {
+            window_start: now,
+            failures: 0,
+            banned_until: None,
+        });
+
+        if let Some(until) = state.banned_until {
+            if now < until {
+                return None;
+            }
+            state.banned_until = None;
+            state.window_start = now;
+            state.failures = 0;
+        }
+        if now.saturating_duration_since(state.window_start) >= self.config.window {
+            state.window_start = now;
+            state.failures = 0;
+        }
+
+        state.failures += 1;
+        if state.failures < self.config.max_failures {
+            return None;
+        }
+        state.banned_until = Some(now + self.config.ban_duration);
+        Some(self.config.ban_duration)
+    }
+
+    /// 当前封禁中的 IP 数
+    pub fn banned_ips(&self, now: Instant) -> usize {
+        let ips = self.ips.lock().unwrap();
+        ips.iter().filter(|(_, state)| state.banned_until.is_some_and(|until| now < until)).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ban_after_failures() {
+        let table = BanTable::new(BanConfig {
+            window: Duration::from_secs(10),
+            max_failures: 3,
+            ban_duration: Duration::from_secs(60),
+        });
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+
+        // 窗口过期后重新计数
+        assert_eq!(table.record_failure(ip, at(0)), None);
+        assert_eq!(table.record_failure(ip, at(1)), None);
+        assert_eq!(table.record_failure(ip, at(11)), None);
+        assert_eq!(table.check(ip, at(11)), None);
+
+        // 窗口内第 3 次失败触发封禁
+        assert_eq!(table.record_failure(ip, at(12)), None);
+        assert_eq!(table.record_failure(ip, at(13)), Some(Duration::from_secs(60)));
+        assert_eq!(table.check(ip, at(43)), Some(Duration::from_secs(30)));
+        assert_eq!(table.check("192.0.2.2".parse().unwrap(), at(43)), None);
+        assert_eq!(table.banned_ips(at(43)), 1);
+
+        // 封禁期间的失败不延长封禁，到期后解除
+        assert_eq!(table.record_failure(ip, at(50)), None);
+        assert_eq!(table.check(ip, at(73)), None);
+        assert_eq!(table.banned_ips(at(73)), 0);
+        assert_eq!(table.record_failure(ip, at(73)), None);
+    }
+}
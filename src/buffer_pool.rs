@@ -0,0 +1,138 @@
+//! Client Hello 读取缓冲区池
+//!
+//! 每个连接都需要一个 16–64KB 的缓冲区读取 Client Hello，每秒数万个新连接时反复申请和释放大块内存，
+//! 分配器压力明显（64KB 的块在 glibc 中接近 mmap 阈值）。缓冲区读取完成后只保留实际读到的数据，
+//! 大缓冲区归还缓冲池给下一个连接使用。缓冲池按线程分片，Tokio 工作线程之间不争用同一把锁
+
+use std::cell::Cell;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// 每个分片最多缓存的空闲缓冲区数，超过时归还的缓冲区直接释放
+const MAX_IDLE_PER_SHARD: usize = 16;
+
+/// 下一个线程使用的分片序号
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// 当前线程使用的分片序号（第一次使用时按顺序分配）
+    static SHARD: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// 当前线程的分片序号
+fn shard_index() -> usize {
+    SHARD.with(|shard| match shard.get() {
+        Some(index) => index,
+        None => {
+            let index = NEXT_SHARD.fetch_add(1, Ordering::Relaxed);
+            shard.set(Some(index));
+            index
+        }
+    })
+}
+
+/// 固定大小的缓冲区池
+#[derive(Debug)]
+pub(crate) struct BufferPool {
+    buffer_size: usize,
+    shards: Box<[Mutex<Vec<Vec<u8>>>]>,
+}
+
+impl BufferPool {
+    /// 创建缓冲区大小为 `buffer_size` 的缓冲池（分片数等于可用的 CPU 数）
+    pub(crate) fn new(buffer_size: usize) -> Self {
+        let shards = (0..crate::platform::available_cpus()).map(|_| Mutex::new(Vec::new())).collect();
+        Self { buffer_size: buffer_size.max(1), shards }
+    }
+
+    /// 缓冲区大小
+    pub(crate) fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// 取出一个缓冲区（长度为 `buffer_size`，内容是上一个连接留下的数据），没有空闲缓冲区时新分配
+    pub(crate) fn get(&self) -> PooledBuffer<'_> {
+        let reused = self.shard().lock().ok().and_then(|mut idle| idle.pop());
+        let buffer = reused.unwrap_or_else(|| vec![0u8; self.buffer_size]);
+        PooledBuffer { pool: self, buffer }
+    }
+
+    fn shard(&self) -> &Mutex<Vec<Vec<u8>>> {
+        &self.shards[shard_index() % self.shards.len()]
+    }
+
+    fn put(&self, buffer: Vec<u8>) {
+        if let Ok(mut idle) = self.shard().lock() {
+            if idle.len() < MAX_IDLE_PER_SHARD {
+                idle.push(buffer);
+            }
+        }
+    }
+
+    /// 所有分片中空闲的缓冲区数
+    #[cfg(test)]
+    fn idle(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().unwrap().len()).sum()
+    }
+}
+
+/// 从缓冲池取出的缓冲区，丢弃时归还缓冲池
+pub(crate) struct PooledBuffer<'a> {
+    pool: &'a BufferPool,
+    buffer: Vec<u8>,
+}
+
+impl PooledBuffer<'_> {
+    /// 复制前 `len` 个字节，缓冲区归还缓冲池
+    pub(crate) fn into_vec(self, len: usize) -> Vec<u8> {
+        self.buffer[..len].to_vec()
+    }
+}
+
+impl Deref for PooledBuffer<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buffer
+    }
+}
+
+impl DerefMut for PooledBuffer<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.buffer
+    }
+}
+
+impl Drop for PooledBuffer<'_> {
+    fn drop(&mut self) {
+        self.pool.put(std::mem::take(&mut self.buffer));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffer_reuse() {
+        let pool = BufferPool::new(1024);
+        let mut buffer = pool.get();
+        assert_eq!(buffer.len(), 1024);
+        buffer[..5].copy_from_slice(b"hello");
+        let address = buffer.as_ptr();
+        assert_eq!(buffer.into_vec(5), b"hello");
+        assert_eq!(pool.idle(), 1);
+
+        // 同一线程取回同一块内存
+        let buffer = pool.get();
+        assert_eq!((buffer.as_ptr(), buffer.len()), (address, 1024));
+        assert_eq!(pool.idle(), 0);
+        drop(buffer);
+
+        // 空闲缓冲区数有上限
+        let buffers: Vec<_> = (0..MAX_IDLE_PER_SHARD + 4).map(|_| pool.get()).collect();
+        drop(buffers);
+        assert_eq!(pool.idle(), MAX_IDLE_PER_SHARD);
+    }
+}
@@ -0,0 +1,160 @@
+//! 重复连接退避
+//!
+//! 有问题的客户端重试循环会在短时间内对同一个 SNI 发起大量相同的连接，既压垮上游，
+//! 也占满并发连接许可。开启后按（客户端 IP, SNI）统计：窗口内的连接数超过阈值时进入退避，
+//! 退避期间的连接在解析出 SNI 后立即关闭；连续触发时退避时长指数增长，直到上限
+
+use lru::LruCache;
+use std::net::IpAddr;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 最多跟踪的（客户端 IP, SNI）组合数，超出时淘汰最久未出现的
+const MAX_TRACKED_PAIRS: usize = 10_000;
+
+/// 退避配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BurstConfig {
+    /// 统计窗口
+    pub window: Duration,
+    /// 窗口内允许的连接数，超过后进入退避
+    pub max_attempts: u32,
+    /// 第一次退避的时长
+    pub initial_backoff: Duration,
+    /// 退避时长上限
+    pub max_backoff: Duration,
+}
+
+impl Default for BurstConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(1),
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PairState {
+    window_start: Instant,
+    attempts: u32,
+    /// 连续触发退避的次数（决定下一次退避时长）
+    strikes: u32,
+    blocked_until: Option<Instant>,
+}
+
+/// 按（客户端 IP, SNI）检测重复连接并退避
+#[derive(Debug)]
+pub struct BurstLimiter {
+    config: BurstConfig,
+    pairs: Mutex<LruCache<(IpAddr, String), PairState>>,
+}
+
+impl BurstLimiter {
+    pub fn new(config: BurstConfig) -> Self {
+        Self {
+            config,
+            pairs: Mutex::new(LruCache::new(NonZeroUsize::new(MAX_TRACKED_PAIRS).unwrap())),
+        }
+    }
+
+    /// 记录一次连接，处于退避期间时返回剩余的退避时长
+    pub fn check(&self, client_ip: IpAddr, sni: &str, now: Instant) -> Option<Duration> {
+        let mut pairs = self.pairs.lock().unwrap();
+        let key = (client_ip, sni.to_ascii_lowercase());
+        let state = pairs.get_or_insert_mut(key, || PairState {
+            window_start: now,
+            attempts: 0,
+            strikes: 0,
+            blocked_until: None,
+        });
+
+        if let Some(until) = state.blocked_until {
+            if now < until {
+                return Some(until - now);
+            }
+            state.blocked_until = None;
+            state.window_start = now;
+            state.attempts = 0;
+        }
+
+        if now.saturating_duration_since(state.window_start) >= self.config.window {
+            // 上一个窗口没有触发退避：重新开始计算退避时长
+            if state.attempts <= self.config.max_attempts {
+                state.strikes = 0;
+            }
+            state.window_start = now;
+            state.attempts = 0;
+        }
+
+        state.attempts += 1;
+        if state.attempts <= self.config.max_attempts {
+            return None;
+        }
+
+        let backoff = self
+            .config
+            .initial_backoff
+            .saturating_mul(1 << state.strikes.min(16))
+            .min(self.config.max_backoff);
+        state.strikes += 1;
+        state.blocked_until = Some(now + backoff);
+        Some(backoff)
+    }
+
+    /// 当前跟踪的（客户端 IP, SNI）组合数
+    pub fn tracked_pairs(&self) -> usize {
+        self.pairs.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exponential_backoff() {
+        let limiter = BurstLimiter::new(BurstConfig {
+            window: Duration::from_secs(1),
+            max_attempts: 3,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(3),
+        });
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+
+        // 窗口内前 3 次放行，第 4 次进入 1 秒退避
+        for ms in 0..3 {
+            assert_eq!(limiter.check(ip, "example.com", at(ms)), None);
+        }
+        assert_eq!(limiter.check(ip, "example.com", at(3)), Some(Duration::from_secs(1)));
+        assert_eq!(limiter.check(ip, "EXAMPLE.COM", at(503)), Some(Duration::from_millis(500)));
+
+        // 其他 SNI、其他客户端不受影响
+        assert_eq!(limiter.check(ip, "other.com", at(503)), None);
+        assert_eq!(limiter.check("192.0.2.2".parse().unwrap(), "example.com", at(503)), None);
+
+        // 退避结束后再次突发：退避翻倍，受上限限制
+        for ms in 1003..1006 {
+            assert_eq!(limiter.check(ip, "example.com", at(ms)), None);
+        }
+        assert_eq!(limiter.check(ip, "example.com", at(1006)), Some(Duration::from_secs(2)));
+        for ms in 3006..3009 {
+            assert_eq!(limiter.check(ip, "example.com", at(ms)), None);
+        }
+        assert_eq!(limiter.check(ip, "example.com", at(3009)), Some(Duration::from_secs(3)));
+
+        // 一个完整窗口内没有突发：退避时长重新计算
+        assert_eq!(limiter.check(ip, "example.com", at(6009)), None);
+        for ms in 7010..7012 {
+            assert_eq!(limiter.check(ip, "example.com", at(ms)), None);
+        }
+        assert_eq!(limiter.check(ip, "example.com", at(7012)), None);
+        assert_eq!(limiter.check(ip, "example.com", at(7013)), Some(Duration::from_secs(1)));
+        assert_eq!(limiter.tracked_pairs(), 3);
+    }
+}
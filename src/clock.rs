@@ -0,0 +1,162 @@
+//! 抗时钟跳变的墙上时间
+//!
+//! 持久化数据、统计文件和每日报告需要墙上时间，但 NTP 校时或手动改时间会让系统时间回退，
+//! 导致负的时长、重复的报告日期。这里用单调时钟推算墙上时间：以某个时刻的系统时间为锚点，
+//! 加上锚点之后经过的单调时长。系统时间向前跳（例如启动后 NTP 首次同步）时重新锚定，
+//! 短暂的向后跳（手动误改后又改回）忽略；回退持续超过 [`REANCHOR_AFTER`] 时认为是真正的校正
+//! （例如 RTC 走快、启动后 NTP 把时间往回调），重新以系统时间为锚点，之后的报告日期和统计时间恢复正确。
+//! 存储一律用 UTC 时间戳，时区只影响显示
+
+use chrono::{DateTime, Local};
+use lazy_static::lazy_static;
+use log::warn;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+/// 系统时间回退超过该值时记录警告
+const BACKWARD_JUMP_WARN: Duration = Duration::from_secs(1);
+
+/// 系统时间持续落后于推算时间超过该时长时，重新以系统时间为锚点
+pub const REANCHOR_AFTER: Duration = Duration::from_secs(300);
+
+lazy_static! {
+    static ref CLOCK: MonotonicWallClock = MonotonicWallClock::new(SystemTime::now(), Instant::now());
+}
+
+/// 单调递增的墙上时钟
+#[derive(Debug)]
+pub struct MonotonicWallClock {
+    anchor: Mutex<Anchor>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Anchor {
+    wall: SystemTime,
+    mono: Instant,
+    /// 系统时间落后于推算时间的量（已经警告过的回退）
+    lag: Duration,
+    /// 开始观察到系统时间落后的单调时刻
+    behind_since: Option<Instant>,
+}
+
+impl Anchor {
+    fn at(wall: SystemTime, mono: Instant) -> Self {
+        Self {
+            wall,
+            mono,
+            lag: Duration::ZERO,
+            behind_since: None,
+        }
+    }
+}
+
+impl MonotonicWallClock {
+    /// 以给定的系统时间和单调时刻为锚点创建时钟
+    pub fn new(wall: SystemTime, mono: Instant) -> Self {
+        Self {
+            anchor: Mutex::new(Anchor::at(wall, mono)),
+        }
+    }
+
+    /// 根据当前的系统时间和单调时刻计算墙上时间
+    pub fn observe(&self, system: SystemTime, mono: Instant) -> SystemTime {
+        let mut anchor = self.anchor.lock().unwrap();
+        let estimated = anchor.wall + mono.saturating_duration_since(anchor.mono);
+        match estimated.duration_since(system) {
+            Ok(lag) if !lag.is_zero() => {
+                let behind_since = *anchor.behind_since.get_or_insert(mono);
+                if mono.saturating_duration_since(behind_since) >= REANCHOR_AFTER {
+                    if lag > BACKWARD_JUMP_WARN {
+                        warn!("⚠️  系统时间回退 {} 秒已持续 {:?}，改为以系统时间为准", lag.as_secs(), REANCHOR_AFTER);
+                    }
+                    *anchor = Anchor::at(system, mono);
+                    return system;
+                }
+                // 同一次回退只警告一次
+                if lag > anchor.lag + BACKWARD_JUMP_WARN {
+                    warn!("⚠️  系统时间回退了 {} 秒，暂时使用单调时钟推算的时间", (lag - anchor.lag).as_secs());
+                    anchor.lag = lag;
+                }
+                estimated
+            }
+            _ => {
+                *anchor = Anchor::at(system, mono);
+                system
+            }
+        }
+    }
+}
+
+/// 当前墙上时间（只在系统时间持续回退超过 [`REANCHOR_AFTER`] 时回退一次）
+pub fn now() -> SystemTime {
+    CLOCK.observe(SystemTime::now(), Instant::now())
+}
+
+/// 当前 UNIX 时间戳（秒）
+pub fn unix_secs() -> u64 {
+    now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// 当前本地时间（用于显示和按日期命名文件）
+pub fn local_now() -> DateTime<Local> {
+    DateTime::from(now())
+}
+
+/// 某个 UNIX 时间戳（秒）距今的时长，时间戳晚于当前时间（时钟被回拨过）时返回 None
+pub fn age_of(timestamp: u64) -> Option<Duration> {
+    unix_secs().checked_sub(timestamp).map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_never_goes_backwards() {
+        let start = Instant::now();
+        let wall = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let clock = MonotonicWallClock::new(wall, start);
+
+        // 正常前进
+        let t1 = clock.observe(wall + Duration::from_secs(10), start + Duration::from_secs(10));
+        assert_eq!(t1, wall + Duration::from_secs(10));
+
+        // 系统时间回退一小时：按单调时钟继续前进
+        let t2 = clock.observe(wall - Duration::from_secs(3590), start + Duration::from_secs(20));
+        assert_eq!(t2, wall + Duration::from_secs(20));
+
+        // 系统时间向前跳（NTP 首次同步）：跟随系统时间
+        let t3 = clock.observe(wall + Duration::from_secs(7200), start + Duration::from_secs(30));
+        assert_eq!(t3, wall + Duration::from_secs(7200));
+        assert!(t1 < t2 && t2 < t3);
+    }
+
+    #[test]
+    fn test_clock_reanchors_after_persistent_backward_jump() {
+        let start = Instant::now();
+        let wall = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let clock = MonotonicWallClock::new(wall, start);
+
+        // RTC 走快一小时，NTP 校正后系统时间回退：先继续按单调时钟推算
+        let corrected = wall - Duration::from_secs(3600);
+        let t1 = clock.observe(corrected, start + Duration::from_secs(10));
+        assert_eq!(t1, wall + Duration::from_secs(10));
+        let t2 = clock.observe(corrected + Duration::from_secs(200), start + Duration::from_secs(210));
+        assert_eq!(t2, wall + Duration::from_secs(210));
+
+        // 回退持续超过 REANCHOR_AFTER：改为以系统时间为准，之后正常前进
+        let elapsed = Duration::from_secs(10) + REANCHOR_AFTER;
+        let t3 = clock.observe(corrected + elapsed - Duration::from_secs(10), start + elapsed);
+        assert_eq!(t3, corrected + elapsed - Duration::from_secs(10));
+        let t4 = clock.observe(t3 + Duration::from_secs(5), start + elapsed + Duration::from_secs(5));
+        assert_eq!(t4, t3 + Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_age_of() {
+        let age = age_of(unix_secs() - 60).unwrap();
+        assert!(age >= Duration::from_secs(60) && age < Duration::from_secs(120));
+        assert!(age_of(0).is_some());
+        assert!(age_of(u64::MAX).is_none());
+    }
+}
@@ -0,0 +1,2262 @@
+//! 配置文件格式
+//!
+//! 与 JSON 配置文件一一对应的结构体，以及把各配置块转换为库中构建器参数的 `build` 方法；
+//! [`validate_config`] 在启动前检查整个配置
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::accept_limit::AcceptRateConfig;
+use crate::acl::AclRule;
+use crate::affinity::parse_cpu_list;
+use crate::backend::BackendAddr;
+use crate::ban::BanConfig;
+use crate::burst::BurstConfig;
+use crate::dns::{DnsOptions, DnsPrefetchConfig, DnsRoute, DnsUpstream};
+use crate::domain::DomainMatcher;
+use crate::error::{Result, SniProxyError};
+use crate::fingerprint::FingerprintFilter;
+use crate::geoip::{CountryCode, GeoIpUpdate};
+use crate::handshake::HandshakeConfig;
+use crate::http::PlaintextHttpAction;
+use crate::influx::{InfluxConfig, InfluxTarget};
+use crate::ip_matcher::IpMatcher;
+use crate::ip_sni::{IpSniAction, IpSniPolicy};
+use crate::listener::{ListenAddr, ListenerProtocol, ListenerSpec};
+use crate::metrics::RouteLabel;
+use crate::outbound::OutboundOptions;
+use crate::output_file::OutputPermissions;
+use crate::profile::MemoryProfile;
+use crate::rejection::RejectionMode;
+use crate::remote_whitelist::RemoteWhitelists;
+use crate::report::ReportConfig;
+use crate::retry::ConnectRetryConfig;
+use crate::route_table::{RouteAction, RouteRule, DEFAULT_SOCKS5_UPSTREAM};
+use crate::schedule::Schedule;
+use crate::server::SniProxy;
+use crate::sni_map::{PinnedIps, SniBackendMap};
+use crate::socks5::Socks5Config;
+use crate::throttle::ClientRateLimits;
+use crate::timeouts::TimeoutConfig;
+use crate::tls::NoSniAction;
+use crate::transparent::TransparentMode;
+use crate::tuning::TcpTuning;
+use crate::whitelist_file::WhitelistFiles;
+
+
+/// 配置文件（JSON）
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Config {
+    /// 主监听地址（可选，未配置时使用 listeners 中第一个 tls_sni 监听器）
+    pub listen_addr: Option<String>,
+    /// 监听器列表（可选）：按入站协议配置监听地址和只作用于该监听器的规则，
+    /// 例如 [{"addr": "0.0.0.0:993", "protocol": "tls_sni", "rules": {"target_port": 993}}]
+    #[serde(default)]
+    pub listeners: Vec<ListenerConfigFile>,
+    /// 双栈监听（可选）：监听 IPv6 地址时同时接受 IPv4 连接
+    #[serde(default)]
+    pub dual_stack: bool,
+    /// 额外监听地址（可选），例如 ["0.0.0.0:993"] 同时代理 IMAP over TLS
+    #[serde(default)]
+    pub extra_listen_addrs: Vec<String>,
+    /// 默认目标端口（可选，默认 443）
+    #[serde(default = "default_target_port")]
+    pub target_port: u16,
+    /// 目标端口映射（可选）：监听端口 → 目标端口，例如 {"8443": 443, "993": 993}
+    #[serde(default)]
+    pub port_map: HashMap<u16, u16>,
+    /// HTTP 嗅探（可选）：非 TLS 连接按 Host 头做白名单判断并转发到 80 端口
+    #[serde(default)]
+    pub http_sniffing: bool,
+    /// TLS 端口收到明文 HTTP 请求时的处理方式（可选，未开启 HTTP 嗅探时生效）：
+    /// "close"（默认）、"bad_request"（返回 400）或 "redirect"（白名单域名 301 到 https://）
+    pub plaintext_http_response: Option<String>,
+    /// IP、域名或 TLS 指纹被拒绝时断开连接的方式（可选）：
+    /// "close"（默认）、"reset"（发送 RST）、"alert"（发送 TLS alert）或 "tarpit"（慢速读取拖住对方）
+    pub rejection_mode: Option<String>,
+    /// tarpit 保持连接的秒数（可选，默认 30，仅 rejection_mode 为 "tarpit" 时生效）
+    pub tarpit_seconds: Option<u64>,
+    /// Client Hello 没有 SNI 时的处理方式（可选）：
+    /// "reject"（默认）、"backend"（转发到 no_sni_backend）或 "original_dst"（透明代理模式下转发到原始目标地址）
+    pub no_sni_action: Option<String>,
+    /// 没有 SNI 的连接转发到的后端地址（no_sni_action 为 "backend" 时必填），例如 "10.0.0.1:443"
+    pub no_sni_backend: Option<String>,
+    /// 默认后端（可选）：不在任何白名单中的域名转发到这里而不是拒绝，例如 "catchall.internal:443"
+    pub default_backend: Option<String>,
+    /// SNI → 后端映射表（可选）：匹配的域名直接转发到映射的后端，不按 SNI 解析 DNS，
+    /// 例如 {"internal.example.com": "10.0.0.5:8443", "*.svc.example.com": "10.0.0.6:443"}
+    #[serde(default)]
+    pub sni_backends: HashMap<String, String>,
+    /// 固定 IP（可选）：直连这些域名时使用配置的 IP，不经过 DNS 解析（支持通配符），
+    /// 例如 {"cdn.example.com": ["203.0.113.10", "203.0.113.11"]}
+    #[serde(default)]
+    pub pinned_ips: HashMap<String, Vec<String>>,
+    /// SNI（或 HTTP Host）为 IP 地址时的处理方式（可选）：
+    /// "domain"（默认，按域名白名单匹配）、"ip_whitelist"（按 ip_sni_whitelist 检查）或 "reject"
+    pub ip_sni_action: Option<String>,
+    /// 按监听端口覆盖 ip_sni_action（可选），例如 {"8443": "reject"}
+    #[serde(default)]
+    pub ip_sni_action_by_port: HashMap<u16, String>,
+    /// SNI 为 IP 地址时允许的目标 IP 或 CIDR 网段（可选）
+    #[serde(default)]
+    pub ip_sni_whitelist: Vec<String>,
+    /// QUIC（HTTP/3）UDP 监听地址（可选），例如 "0.0.0.0:443"
+    pub quic_listen_addr: Option<String>,
+    /// 内存配置预设（可选）："default" 或 "low_memory"（小内存路由器）
+    pub memory_profile: Option<String>,
+    /// 透明代理模式（可选）："redirect"（iptables REDIRECT）或 "tproxy"（TPROXY）
+    pub transparent_mode: Option<String>,
+    /// 直连白名单（配置了 whitelist_file 时可以为空）
+    #[serde(default)]
+    pub whitelist: Vec<String>,
+    /// SOCKS5 白名单（可选）
+    #[serde(default)]
+    pub socks5_whitelist: Vec<String>,
+    /// 直连白名单文件（可选）：每行一条规则，追加在 whitelist 之后，修改后自动重新加载
+    pub whitelist_file: Option<String>,
+    /// SOCKS5 白名单文件（可选），格式同 whitelist_file
+    pub socks5_whitelist_file: Option<String>,
+    /// 直连时发送 PROXY protocol v2 头部的域名（可选，支持通配符）
+    /// 只应配置我们自己控制、并已开启 PROXY protocol 接收的下游服务器
+    #[serde(default)]
+    pub proxy_protocol_domains: Vec<String>,
+    /// IP 白名单（可选）
+    /// 支持单个 IP 地址（如 "192.168.1.1"）或 CIDR 网段（如 "192.168.1.0/24"）
+    /// 如果为空，则不进行 IP 白名单检查
+    #[serde(default)]
+    pub ip_whitelist: Vec<String>,
+    /// IP 白名单文件（可选）：每行一个 IP 或 CIDR，追加在 ip_whitelist 之后，修改后自动重新加载
+    pub ip_whitelist_file: Option<String>,
+    /// 白名单文件定期重新读取的周期（秒，可选），文件变化通知不可靠时（例如网络文件系统）使用
+    pub whitelist_reload_secs: Option<u64>,
+    /// IP 黑名单（可选）：IP 或 CIDR，匹配的客户端一律拒绝，优先于 IP 白名单
+    #[serde(default)]
+    pub ip_blacklist: Vec<String>,
+    /// GeoIP 数据库配置（可选），geo_whitelist / geo_blacklist 和路由规则的 client_countries 需要
+    pub geoip: Option<GeoIpConfigFile>,
+    /// 允许的客户端国家（可选），例如 ["CN", "HK"]，配置后其他国家的客户端一律拒绝
+    #[serde(default)]
+    pub geo_whitelist: Vec<String>,
+    /// 拒绝的客户端国家（可选）
+    #[serde(default)]
+    pub geo_blacklist: Vec<String>,
+    /// 远程白名单配置（可选）：从 HTTP 地址获取白名单并定期刷新
+    pub remote_whitelist: Option<RemoteWhitelistConfigFile>,
+    /// IP 流量追踪配置（可选）
+    pub ip_traffic_tracking: Option<IpTrafficTrackingConfig>,
+    /// 域名-IP 追踪配置（可选）
+    pub domain_ip_tracking: Option<DomainIpTrackingConfig>,
+    /// Client Hello 采集配置（可选，用于解析器回归测试语料库）
+    pub hello_capture: Option<HelloCaptureConfig>,
+    /// 数据预览配置（可选，诊断协议不匹配）
+    pub log_body_preview: Option<BodyPreviewConfig>,
+    /// TLS 指纹（JA3 / JA4）配置（可选）
+    pub tls_fingerprint: Option<FingerprintConfig>,
+    /// 出站选项（可选）：对所有直连和 SOCKS5 连接生效，例如 {"bind_addr": "192.0.2.10", "interface": "wan1", "fwmark": 16}
+    pub outbound: Option<OutboundConfigFile>,
+    /// SOCKS5 代理配置（可选）
+    pub socks5: Option<Socks5ConfigFile>,
+    /// 命名的 SOCKS5 上游（可选），供 routes 中的 socks5:<名称> 动作引用
+    #[serde(default)]
+    pub socks5_upstreams: HashMap<String, Socks5ConfigFile>,
+    /// 路由规则（可选），按顺序匹配，排在 whitelist / socks5_whitelist 之前
+    #[serde(default)]
+    pub routes: Vec<RouteConfigFile>,
+    /// 域名分类（可选）：按名称分组的域名，每个分类有自己的动作、生效时段和带宽上限，排在 routes 之后、白名单之前
+    #[serde(default)]
+    pub categories: Vec<CategoryConfigFile>,
+    /// 日志配置（可选）
+    pub log: Option<LogConfigFile>,
+    /// 管理接口配置（可选）
+    pub admin: Option<AdminConfigFile>,
+    /// HTTP → HTTPS 重定向配置（可选）
+    pub http_redirect: Option<HttpRedirectConfigFile>,
+    /// 每日汇总报告配置（可选）
+    pub daily_report: Option<DailyReportConfigFile>,
+    /// InfluxDB 行协议导出配置（可选）
+    pub influx_export: Option<InfluxExportConfigFile>,
+    /// 重复连接退避配置（可选）
+    pub burst_backoff: Option<BurstBackoffConfigFile>,
+    /// 自动封禁配置（可选）
+    pub auto_ban: Option<AutoBanConfigFile>,
+    /// 新连接速率限制配置（可选）
+    pub accept_rate_limit: Option<AcceptRateLimitConfigFile>,
+    /// 握手阶段保护配置（可选）
+    pub handshake_protection: Option<HandshakeProtectionConfigFile>,
+    /// 按客户端 IP 的带宽限制（可选）
+    pub client_bandwidth: Option<ClientBandwidthConfigFile>,
+    /// 超时配置（可选），覆盖按 CPU 核心数自适应的超时
+    pub timeouts: Option<TimeoutsConfigFile>,
+    /// 上游连接重试配置（可选）
+    pub connect_retry: Option<ConnectRetryConfigFile>,
+    /// 直连失败时改用 SOCKS5 上游重新连接（可选，需要配置 socks5）
+    #[serde(default)]
+    pub direct_fallback_to_socks5: bool,
+    /// 通过 SOCKS5 连接失败时改用直连（可选）
+    #[serde(default)]
+    pub socks5_fallback_to_direct: bool,
+    /// 转发空闲超时（秒，可选）：两个方向都超过该时长没有数据时断开连接（QUIC 会话同样适用，未配置时为 60 秒）
+    pub idle_timeout_secs: Option<u64>,
+    /// 最长连接时间（秒，可选）：转发超过该时长的连接（包括 QUIC 会话）一律断开
+    pub max_connection_lifetime_secs: Option<u64>,
+    /// 使用 splice 转发（可选，仅 Linux）
+    #[serde(default)]
+    pub splice_relay: bool,
+    /// io_uring 数据路径（可选，实验性，需要 io-uring 功能编译）
+    pub io_uring: Option<IoUringConfigFile>,
+    /// DNS 解析配置（可选）
+    pub dns: Option<DnsConfigFile>,
+    /// 域名黑名单（可选），支持通配符，优先于所有白名单和路由规则
+    #[serde(default)]
+    pub blacklist: Vec<String>,
+    /// 域名黑名单文件（可选）：追加在 blacklist 之后，支持 dnsmasq、AdGuard、hosts 等列表格式，修改后需要重启
+    pub blacklist_file: Option<String>,
+    /// ACL（可选）：客户端 IP 和域名一起匹配，按顺序第一条匹配的规则决定允许或拒绝，没有规则匹配时拒绝
+    #[serde(default)]
+    pub acl: Vec<AclConfigFile>,
+    /// 输出文件（日志、统计、持久化文件、报告）的权限和属主（可选）
+    pub output_files: Option<OutputFilesConfig>,
+    /// TCP 调优配置（可选）
+    pub tuning: Option<TuningConfigFile>,
+    /// 命名的 TCP 调优配置（可选），供 listeners[].tuning 和 route_tuning 引用
+    #[serde(default)]
+    pub tuning_profiles: HashMap<String, TcpTuningConfigFile>,
+    /// 按路由（direct、socks5、fallback）为上游连接指定调优配置名（可选）
+    #[serde(default)]
+    pub route_tuning: HashMap<String, String>,
+    /// CPU 亲和性配置（可选）
+    pub cpu_affinity: Option<CpuAffinityConfig>,
+    /// Tokio 运行时和并发连接数配置（可选），未配置的项按可用 CPU 数自适应
+    pub runtime: Option<RuntimeConfigFile>,
+    /// 功能开关（可选）：统一关闭较重的子系统，即使对应配置块已启用
+    #[serde(default)]
+    pub features: FeaturesConfig,
+}
+
+/// 功能开关
+///
+/// 同一个二进制可以在小型设备上以精简模式运行（关闭追踪器、管理接口等），
+/// 在服务器上以完整模式运行；默认全部开启，由各子系统自己的配置决定是否启用
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FeaturesConfig {
+    /// IP 流量追踪
+    #[serde(default = "default_feature_enabled")]
+    pub ip_traffic_tracking: bool,
+    /// 域名-IP 追踪
+    #[serde(default = "default_feature_enabled")]
+    pub domain_ip_tracking: bool,
+    /// 管理接口
+    #[serde(default = "default_feature_enabled")]
+    pub admin_api: bool,
+    /// HTTP → HTTPS 重定向监听
+    #[serde(default = "default_feature_enabled")]
+    pub http_redirect: bool,
+    /// 每个连接结束时的访问记录（info 级别）
+    #[serde(default = "default_feature_enabled")]
+    pub access_log: bool,
+}
+
+impl Default for FeaturesConfig {
+    fn default() -> Self {
+        Self {
+            ip_traffic_tracking: true,
+            domain_ip_tracking: true,
+            admin_api: true,
+            http_redirect: true,
+            access_log: true,
+        }
+    }
+}
+
+fn default_feature_enabled() -> bool {
+    true
+}
+
+/// 检查功能开关：子系统已配置启用但被 features 关闭时输出提示
+pub fn feature_enabled(configured: bool, switch: bool, name: &str) -> bool {
+    if configured && !switch {
+        log::info!("{}: 已通过 features 关闭", name);
+    }
+    configured && switch
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IpTrafficTrackingConfig {
+    /// 是否启用 IP 流量追踪（仅对 IP 白名单中的 IP）
+    #[serde(default)]
+    pub enabled: bool,
+    /// 最大跟踪的 IP 数量（使用 LRU 缓存）
+    #[serde(default = "default_max_tracked_ips")]
+    pub max_tracked_ips: usize,
+    /// 统计数据输出文件路径（可选，每次覆盖写入最新数据）
+    pub output_file: Option<String>,
+    /// 持久化数据文件路径（可选，用于服务重启后恢复数据）
+    pub persistence_file: Option<String>,
+}
+
+fn default_max_tracked_ips() -> usize {
+    1000
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DomainIpTrackingConfig {
+    /// 是否启用域名-IP 追踪
+    #[serde(default)]
+    pub enabled: bool,
+    /// 输出文件路径
+    pub output_file: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HelloCaptureConfig {
+    /// 是否启用 Client Hello 采集
+    #[serde(default)]
+    pub enabled: bool,
+    /// 语料文件路径（JSON Lines，追加写入）
+    pub corpus_file: String,
+    /// 是否保留真实 SNI（默认脱敏为 xxx.xxxxxxx.xxx 形式）
+    #[serde(default)]
+    pub include_sni: bool,
+    /// 语料文件最多保存的条目数（按脱敏后的内容去重）
+    #[serde(default = "default_max_capture_entries")]
+    pub max_entries: usize,
+}
+
+fn default_max_capture_entries() -> usize {
+    1000
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BodyPreviewConfig {
+    /// 是否启用数据预览
+    #[serde(default)]
+    pub enabled: bool,
+    /// 需要预览的域名（支持通配符）
+    #[serde(default)]
+    pub domains: Vec<String>,
+    /// 需要预览的客户端 IP（支持 CIDR，无法解析 SNI 的连接只能按 IP 匹配）
+    #[serde(default)]
+    pub client_ips: Vec<String>,
+    /// 每个方向最多记录的字节数
+    #[serde(default = "default_preview_bytes")]
+    pub max_bytes: usize,
+}
+
+fn default_preview_bytes() -> usize {
+    64
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FingerprintConfig {
+    /// 是否启用 TLS 指纹
+    #[serde(default)]
+    pub enabled: bool,
+    /// 是否同时计算 JA4 指纹（默认只计算 JA3）
+    #[serde(default)]
+    pub ja4: bool,
+    /// 是否在日志中记录每个连接的指纹
+    #[serde(default = "default_true")]
+    pub log: bool,
+    /// 允许的指纹（JA3 或 JA4，非空时只放行列表中的指纹）
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// 拒绝的指纹（JA3 或 JA4，优先于允许列表）
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl FingerprintConfig {
+    pub fn build_filter(&self) -> Result<FingerprintFilter> {
+        FingerprintFilter::new(self.allow.clone(), self.deny.clone(), self.ja4, self.log)
+    }
+}
+
+/// 数据预览最多记录的字节数
+const MAX_PREVIEW_BYTES: usize = 4096;
+
+/// tarpit 最长保持连接的秒数（被拖住的连接仍占用并发连接数）
+const MAX_TARPIT_SECONDS: u64 = 600;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Socks5ConfigFile {
+    /// SOCKS5 代理服务器地址，格式：ip:port 或 domain:port
+    pub addr: String,
+    /// 用户名（可选）
+    pub username: Option<String>,
+    /// 密码（可选）
+    pub password: Option<String>,
+}
+
+impl Socks5ConfigFile {
+    pub fn build(&self) -> Result<Socks5Config> {
+        let addr = self.addr.parse::<SocketAddr>().map_err(invalid("无效的 SOCKS5 代理地址格式"))?;
+        // 检查用户名和密码的一致性
+        if self.username.is_some() != self.password.is_some() {
+            return Err(SniProxyError::InvalidConfig("SOCKS5 用户名和密码必须同时提供或同时省略".to_string()));
+        }
+        Ok(Socks5Config {
+            addr,
+            username: self.username.clone(),
+            password: self.password.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RouteConfigFile {
+    /// 域名条件（支持通配符和 :端口），为空表示任意域名
+    #[serde(default)]
+    pub domains: Vec<String>,
+    /// 客户端 IP 或 CIDR 条件，为空表示任意客户端
+    #[serde(default)]
+    pub client_ips: Vec<String>,
+    /// ALPN 条件，为空表示不限
+    #[serde(default)]
+    pub alpn: Vec<String>,
+    /// 客户端所在国家条件（需要配置 geoip），为空表示不限
+    #[serde(default)]
+    pub client_countries: Vec<String>,
+    /// 动作：direct、socks5、socks5:<名称>、backend:<host:port> 或 reject
+    pub action: String,
+    /// 带宽上限（kbps，可选），匹配该规则的所有连接共享
+    pub bandwidth_limit_kbps: Option<u64>,
+    /// 出站选项（可选），覆盖全局 outbound 配置
+    #[serde(flatten)]
+    pub outbound: OutboundConfigFile,
+    /// TCP 调优配置名（可选），tuning_profiles 中定义或内置的 streaming、low_latency
+    pub tuning: Option<String>,
+    /// DSCP 标记（可选），例如 "AF41"、"EF" 或 0-63 的数字
+    pub dscp: Option<String>,
+    /// 拥塞控制算法（可选，仅 Linux），例如 "bbr"
+    pub congestion_control: Option<String>,
+}
+
+impl RouteConfigFile {
+    pub fn build(&self, profiles: &HashMap<String, TcpTuningConfigFile>) -> Result<RouteRule> {
+        let mut rule = RouteRule::new(self.action.parse()?);
+        if !self.domains.is_empty() {
+            rule = rule.with_domains(self.domains.clone());
+        }
+        if !self.client_ips.is_empty() {
+            rule = rule.with_client_ips(self.client_ips.clone());
+        }
+        if !self.alpn.is_empty() {
+            rule = rule.with_alpn(self.alpn.clone());
+        }
+        if !self.client_countries.is_empty() {
+            rule = rule.with_client_countries(parse_countries(&self.client_countries)?);
+        }
+        let outbound = self.outbound.build()?;
+        if !outbound.is_empty() {
+            if *rule.action() == RouteAction::Reject {
+                return Err(SniProxyError::InvalidConfig("出站选项不能用于 reject 动作".to_string()));
+            }
+            rule = rule.with_outbound(outbound);
+        }
+        let rule = with_tuning(rule, self.tuning.as_deref(), profiles)?;
+        let rule = with_dscp(rule, self.dscp.as_deref())?;
+        let rule = with_congestion_control(rule, self.congestion_control.as_deref())?;
+        with_bandwidth_limit_kbps(rule, self.bandwidth_limit_kbps)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct OutboundConfigFile {
+    /// 出站连接的源地址（可选），必须是本机地址
+    pub bind_addr: Option<IpAddr>,
+    /// 出口网络接口（可选，仅 Linux），例如 "wg0"
+    pub interface: Option<String>,
+    /// 防火墙标记（可选，仅 Linux，需要 CAP_NET_ADMIN），供策略路由和 nftables 匹配
+    pub fwmark: Option<u32>,
+    /// 客户端 TCP Fast Open（可选，仅 Linux 4.11+）：SOCKS5 握手请求随 SYN 发送（只用于连接 SOCKS5 代理，
+    /// 直连和 QUIC 不使用）
+    pub fastopen: Option<bool>,
+}
+
+impl OutboundConfigFile {
+    pub fn build(&self) -> Result<OutboundOptions> {
+        if let Some(ip) = self.bind_addr {
+            // 绑定一次临时端口，确认是本机地址
+            std::net::UdpSocket::bind((ip, 0))
+                .map_err(|e| SniProxyError::InvalidConfig(format!("bind_addr {} 不是本机地址: {}", ip, e)))?;
+        }
+        if let Some(ref interface) = self.interface {
+            crate::outbound::check_interface(interface)
+                .map_err(|e| SniProxyError::InvalidConfig(format!("网络接口 {} 不可用: {}", interface, e)))?;
+        }
+        if let Some(mark) = self.fwmark {
+            if mark == 0 {
+                return Err(SniProxyError::InvalidConfig("fwmark 必须大于 0".to_string()));
+            }
+            crate::outbound::check_fwmark(mark)
+                .map_err(|e| SniProxyError::InvalidConfig(format!("无法设置 fwmark {}: {}", mark, e)))?;
+        }
+        Ok(OutboundOptions {
+            bind_addr: self.bind_addr,
+            interface: self.interface.clone(),
+            fwmark: self.fwmark,
+            fastopen: self.fastopen,
+        })
+    }
+}
+
+/// 设置规则的 TCP 调优参数
+fn with_tuning(
+    rule: RouteRule,
+    name: Option<&str>,
+    profiles: &HashMap<String, TcpTuningConfigFile>,
+) -> Result<RouteRule> {
+    let Some(name) = name else {
+        return Ok(rule);
+    };
+    if *rule.action() == RouteAction::Reject {
+        return Err(SniProxyError::InvalidConfig("tuning 不能用于 reject 动作".to_string()));
+    }
+    Ok(rule.with_tuning(tuning_profile(profiles, name)?))
+}
+
+/// 设置规则的 DSCP 标记
+fn with_dscp(rule: RouteRule, dscp: Option<&str>) -> Result<RouteRule> {
+    let Some(dscp) = dscp else {
+        return Ok(rule);
+    };
+    if *rule.action() == RouteAction::Reject {
+        return Err(SniProxyError::InvalidConfig("dscp 不能用于 reject 动作".to_string()));
+    }
+    Ok(rule.with_dscp(crate::tuning::parse_dscp(dscp)?))
+}
+
+/// 设置规则的拥塞控制算法
+fn with_congestion_control(rule: RouteRule, name: Option<&str>) -> Result<RouteRule> {
+    let Some(name) = name else {
+        return Ok(rule);
+    };
+    if *rule.action() == RouteAction::Reject {
+        return Err(SniProxyError::InvalidConfig("congestion_control 不能用于 reject 动作".to_string()));
+    }
+    crate::tuning::check_congestion_control(name)
+        .map_err(|e| SniProxyError::InvalidConfig(format!("无法使用拥塞控制算法 {}: {}", name, e)))?;
+    Ok(rule.with_congestion_control(name))
+}
+
+/// 设置规则的带宽上限（kbps 转换为每秒字节数）
+fn with_bandwidth_limit_kbps(rule: RouteRule, kbps: Option<u64>) -> Result<RouteRule> {
+    let Some(kbps) = kbps else {
+        return Ok(rule);
+    };
+    if kbps == 0 || *rule.action() == RouteAction::Reject {
+        return Err(SniProxyError::InvalidConfig(
+            "bandwidth_limit_kbps 必须大于 0，且不能用于 reject 动作".to_string(),
+        ));
+    }
+    Ok(rule.with_bandwidth_limit(kbps * 1000 / 8))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CategoryConfigFile {
+    /// 分类名称，例如 "streaming"、"work"、"ads"
+    pub name: String,
+    /// 分类中的域名（同白名单格式）
+    pub domains: Vec<String>,
+    /// 动作：direct（默认）、socks5、socks5:<名称>、backend:<host:port> 或 reject
+    #[serde(default = "default_category_action")]
+    pub action: String,
+    /// 生效时段（可选），例如 ["mon-fri 09:00-18:00"]，时段外不匹配该分类
+    #[serde(default)]
+    pub schedule: Vec<String>,
+    /// 带宽上限（kbit/s，可选），该分类的所有连接共享，上下行合计
+    pub bandwidth_limit_kbps: Option<u64>,
+    /// TCP 调优配置名（可选），例如 "streaming"
+    pub tuning: Option<String>,
+    /// DSCP 标记（可选），例如 "AF41"
+    pub dscp: Option<String>,
+    /// 拥塞控制算法（可选，仅 Linux），例如 "bbr"
+    pub congestion_control: Option<String>,
+}
+
+fn default_category_action() -> String {
+    "direct".to_string()
+}
+
+impl CategoryConfigFile {
+    pub fn build(&self, profiles: &HashMap<String, TcpTuningConfigFile>) -> Result<RouteRule> {
+        if self.name.is_empty() || self.domains.is_empty() {
+            return Err(SniProxyError::InvalidConfig("分类的 name 和 domains 不能为空".to_string()));
+        }
+        let mut rule = RouteRule::new(self.action.parse()?)
+            .with_domains(self.domains.clone())
+            .with_category(self.name.clone());
+        if !self.schedule.is_empty() {
+            rule = rule.with_schedule(Schedule::parse(&self.schedule)?);
+        }
+        let rule = with_tuning(rule, self.tuning.as_deref(), profiles)?;
+        let rule = with_dscp(rule, self.dscp.as_deref())?;
+        let rule = with_congestion_control(rule, self.congestion_control.as_deref())?;
+        with_bandwidth_limit_kbps(rule, self.bandwidth_limit_kbps)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AclConfigFile {
+    /// 客户端 IP 或 CIDR 条件，为空表示任意客户端
+    #[serde(default)]
+    pub client_ips: Vec<String>,
+    /// 域名条件（支持通配符），为空表示任意域名
+    #[serde(default)]
+    pub domains: Vec<String>,
+    /// 动作：allow 或 deny
+    pub action: String,
+}
+
+impl AclConfigFile {
+    pub fn build(&self) -> Result<AclRule> {
+        let mut rule = AclRule::new(self.action.parse()?);
+        if !self.client_ips.is_empty() {
+            rule = rule.with_client_ips(self.client_ips.clone());
+        }
+        if !self.domains.is_empty() {
+            rule = rule.with_domains(self.domains.clone());
+        }
+        Ok(rule)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CpuAffinityConfig {
+    /// 工作线程可运行的 CPU 列表（cpulist 格式，如 "0-3,8"），通常与网卡 IRQ 亲和性一致
+    pub worker_cpus: Option<String>,
+    /// 工作线程绑定的 NUMA 节点（其 CPU 与 worker_cpus 合并）
+    #[serde(default)]
+    pub numa_nodes: Vec<usize>,
+    /// 接受连接线程绑定的 CPU（可选）
+    pub acceptor_cpu: Option<usize>,
+    /// 启用 SO_INCOMING_CPU 连接分流：每个 acceptor 绑定一个 CPU，连接留在接收它的 CPU 上处理
+    #[serde(default)]
+    pub incoming_cpu_steering: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RuntimeConfigFile {
+    /// Tokio 工作线程数（覆盖 memory_profile 和 cpu_affinity 的自适应值）
+    pub worker_threads: Option<usize>,
+    /// 工作线程栈大小（KB）
+    pub thread_stack_size_kb: Option<usize>,
+    /// 每处理多少个本地任务检查一次全局队列
+    pub global_queue_interval: Option<u32>,
+    /// 每处理多少个任务检查一次 I/O 和定时器事件
+    pub event_interval: Option<u32>,
+    /// 最大并发连接数
+    pub max_connections: Option<usize>,
+}
+
+/// 工作线程栈的最小值（KB），更小的栈在解析和日志格式化时容易溢出
+const MIN_THREAD_STACK_SIZE_KB: usize = 64;
+
+impl RuntimeConfigFile {
+    pub fn validate(&self) -> Result<()> {
+        for (name, value) in [
+            ("worker_threads", self.worker_threads),
+            ("global_queue_interval", self.global_queue_interval.map(|v| v as usize)),
+            ("event_interval", self.event_interval.map(|v| v as usize)),
+            ("max_connections", self.max_connections),
+        ] {
+            if value == Some(0) {
+                return Err(SniProxyError::InvalidConfig(format!("runtime.{} 必须大于 0", name)));
+            }
+        }
+        if self.thread_stack_size_kb.is_some_and(|kb| kb < MIN_THREAD_STACK_SIZE_KB) {
+            return Err(SniProxyError::InvalidConfig(format!(
+                "runtime.thread_stack_size_kb 不能小于 {}",
+                MIN_THREAD_STACK_SIZE_KB
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TuningConfigFile {
+    /// 默认 TCP 调优参数
+    #[serde(flatten)]
+    pub tcp: TcpTuningConfigFile,
+    /// acceptor 数量（每个一个 SO_REUSEPORT 监听 socket），默认 1；
+    /// 启用 incoming_cpu_steering 时默认为进程可以调度到的 CPU 数
+    pub acceptors: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TcpTuningConfigFile {
+    /// 监听 socket 的 backlog（受 net.core.somaxconn 限制）
+    #[serde(default = "default_backlog")]
+    pub backlog: i32,
+    /// SO_RCVBUF 大小（字节），0 表示使用系统默认值
+    #[serde(default = "default_buffer_size")]
+    pub recv_buffer_size: usize,
+    /// SO_SNDBUF 大小（字节），0 表示使用系统默认值
+    #[serde(default = "default_buffer_size")]
+    pub send_buffer_size: usize,
+    /// 是否设置 TCP_NODELAY
+    #[serde(default = "default_true")]
+    pub nodelay: bool,
+    /// TCP keepalive 空闲时间（秒），不配置时不启用
+    pub keepalive_secs: Option<u64>,
+    /// keepalive 探测间隔（秒，仅 Linux），不配置时使用系统默认值
+    pub keepalive_interval_secs: Option<u64>,
+    /// keepalive 探测次数（仅 Linux），不配置时使用系统默认值
+    pub keepalive_count: Option<u32>,
+    /// TCP_USER_TIMEOUT（毫秒，仅 Linux），不配置时使用系统默认值
+    pub user_timeout_ms: Option<u64>,
+    /// 是否设置 TCP_QUICKACK（仅 Linux）
+    #[serde(default)]
+    pub quickack: bool,
+    /// 是否启用 TCP Fast Open
+    #[serde(default = "default_true")]
+    pub fastopen: bool,
+    /// 拥塞控制算法（仅 Linux），例如 "bbr"，不配置时使用系统默认值
+    pub congestion_control: Option<String>,
+}
+
+impl TcpTuningConfigFile {
+    pub fn validate(&self, name: &str) -> Result<()> {
+        if self.backlog <= 0 {
+            return Err(SniProxyError::InvalidConfig(format!("{}.backlog 必须大于 0", name)));
+        }
+        if self.recv_buffer_size > i32::MAX as usize || self.send_buffer_size > i32::MAX as usize {
+            return Err(SniProxyError::InvalidConfig(format!("{} 中的 socket 缓冲区大小不能超过 {} 字节", name, i32::MAX)));
+        }
+        if self.keepalive_secs == Some(0) || self.keepalive_interval_secs == Some(0) || self.keepalive_count == Some(0) {
+            return Err(SniProxyError::InvalidConfig(format!(
+                "{} 中的 keepalive_secs、keepalive_interval_secs 和 keepalive_count 必须大于 0",
+                name
+            )));
+        }
+        if self.keepalive_secs.is_none() && (self.keepalive_interval_secs.is_some() || self.keepalive_count.is_some()) {
+            return Err(SniProxyError::InvalidConfig(format!(
+                "{} 配置 keepalive_interval_secs 或 keepalive_count 时需要同时配置 keepalive_secs",
+                name
+            )));
+        }
+        if let Some(ms) = self.user_timeout_ms {
+            if ms == 0 || ms > i32::MAX as u64 {
+                return Err(SniProxyError::InvalidConfig(format!("{}.user_timeout_ms 必须在 1 到 {} 之间", name, i32::MAX)));
+            }
+        }
+        if let Some(ref algorithm) = self.congestion_control {
+            crate::tuning::check_congestion_control(algorithm)
+                .map_err(|e| SniProxyError::InvalidConfig(format!("{}.congestion_control 无法使用 {}: {}", name, algorithm, e)))?;
+        }
+        Ok(())
+    }
+
+    pub fn build(&self) -> TcpTuning {
+        TcpTuning {
+            backlog: self.backlog,
+            recv_buffer_size: self.recv_buffer_size,
+            send_buffer_size: self.send_buffer_size,
+            nodelay: self.nodelay,
+            keepalive: self.keepalive_secs.map(Duration::from_secs),
+            keepalive_interval: self.keepalive_interval_secs.map(Duration::from_secs),
+            keepalive_count: self.keepalive_count,
+            user_timeout: self.user_timeout_ms.map(Duration::from_millis),
+            quickack: self.quickack,
+            fastopen: self.fastopen,
+            congestion_control: self.congestion_control.clone(),
+        }
+    }
+}
+
+/// 按名称查找调优配置，tuning_profiles 中没有定义时使用内置配置
+pub fn tuning_profile(profiles: &HashMap<String, TcpTuningConfigFile>, name: &str) -> Result<TcpTuning> {
+    profiles
+        .get(name)
+        .map(TcpTuningConfigFile::build)
+        .or_else(|| TcpTuning::builtin(name))
+        .ok_or_else(|| {
+            SniProxyError::InvalidConfig(format!(
+                "未定义的调优配置: {}（内置: {}）",
+                name,
+                crate::tuning::BUILTIN_PROFILES.join(", ")
+            ))
+        })
+}
+
+/// 解析 route_tuning 中的路由名
+pub fn parse_tuning_route(route: &str) -> Result<RouteLabel> {
+    match route.to_lowercase().as_str() {
+        "direct" => Ok(RouteLabel::Direct),
+        "socks5" => Ok(RouteLabel::Socks5),
+        "fallback" => Ok(RouteLabel::Fallback),
+        _ => Err(SniProxyError::InvalidConfig(format!("无效的路由: {}（可选: direct, socks5, fallback）", route))),
+    }
+}
+
+fn default_target_port() -> u16 {
+    crate::port_map::DEFAULT_TARGET_PORT
+}
+
+fn default_backlog() -> i32 {
+    crate::tuning::DEFAULT_BACKLOG
+}
+
+fn default_buffer_size() -> usize {
+    crate::tuning::DEFAULT_BUFFER_SIZE
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AdminConfigFile {
+    /// 是否启用管理接口
+    #[serde(default)]
+    pub enabled: bool,
+    /// 管理接口监听地址（建议只监听本地地址）
+    #[serde(default = "default_admin_listen_addr")]
+    pub listen_addr: String,
+    /// 访问令牌（可选），请求需携带 `Authorization: Bearer <token>`
+    pub auth_token: Option<String>,
+}
+
+fn default_admin_listen_addr() -> String {
+    "127.0.0.1:9090".to_string()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ListenerConfigFile {
+    /// 监听地址（tls_sni 也可以是 unix:<路径>）
+    pub addr: String,
+    /// 入站协议：tls_sni（默认）、http（HTTP → HTTPS 重定向）、quic 或 socks5（尚未支持）
+    #[serde(default = "default_listener_protocol")]
+    pub protocol: String,
+    /// 只作用于该监听器的规则（target_port 适用于 tls_sni 和 quic，ip_sni_action 仅 tls_sni）
+    #[serde(default)]
+    pub rules: ListenerRulesConfigFile,
+    /// TCP 调优配置名（tuning_profiles 中定义，仅 tls_sni）
+    pub tuning: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ListenerRulesConfigFile {
+    /// 目标端口（覆盖 port_map，tls_sni 和 quic）
+    pub target_port: Option<u16>,
+    /// SNI 为 IP 地址时的处理方式（覆盖 ip_sni_action）
+    pub ip_sni_action: Option<String>,
+}
+
+fn default_listener_protocol() -> String {
+    ListenerProtocol::TlsSni.to_string()
+}
+
+impl ListenerConfigFile {
+    pub fn build(&self, config: &Config) -> Result<ListenerSpec> {
+        Ok(ListenerSpec {
+            addr: self.addr.parse()?,
+            protocol: self.protocol.parse()?,
+            target_port: self.rules.target_port,
+            ip_sni_action: self.rules.ip_sni_action.as_deref().map(str::parse).transpose()?,
+            tuning: self.tuning.as_deref().map(|name| tuning_profile(&config.tuning_profiles, name)).transpose()?,
+        })
+    }
+}
+
+/// 汇总所有监听器：先是 listen_addr、extra_listen_addrs、quic_listen_addr（兼容旧配置），
+/// 再是 listeners；第一个 tls_sni 监听器为主监听地址
+pub fn listener_specs(config: &Config) -> Result<Vec<ListenerSpec>> {
+    let mut specs = Vec::new();
+    if let Some(ref addr) = config.listen_addr {
+        let addr: ListenAddr = addr.parse().map_err(invalid("无效的监听地址格式"))?;
+        specs.push(ListenerSpec::new(addr, ListenerProtocol::TlsSni));
+    }
+    for addr in &config.extra_listen_addrs {
+        let addr: SocketAddr = addr.parse().map_err(invalid(format!("无效的额外监听地址格式: {}", addr)))?;
+        specs.push(ListenerSpec::new(addr, ListenerProtocol::TlsSni));
+    }
+    if let Some(ref addr) = config.quic_listen_addr {
+        let addr: SocketAddr = addr.parse().map_err(invalid(format!("无效的 QUIC 监听地址格式: {}", addr)))?;
+        specs.push(ListenerSpec::new(addr, ListenerProtocol::Quic));
+    }
+    for listener in &config.listeners {
+        specs.push(listener.build(config).map_err(invalid(format!("无效的监听器配置: {}", listener.addr)))?);
+    }
+    Ok(specs)
+}
+
+/// 主监听地址：第一个 tls_sni 监听器
+pub fn primary_listen_addr(specs: &[ListenerSpec]) -> Result<ListenAddr> {
+    specs
+        .iter()
+        .find(|spec| spec.protocol == ListenerProtocol::TlsSni)
+        .map(|spec| spec.addr.clone())
+        .ok_or_else(|| SniProxyError::InvalidConfig("至少需要一个 tls_sni 监听地址（listen_addr 或 listeners）".to_string()))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BurstBackoffConfigFile {
+    /// 是否启用重复连接退避
+    #[serde(default)]
+    pub enabled: bool,
+    /// 统计窗口（毫秒）
+    #[serde(default = "default_burst_window_ms")]
+    pub window_ms: u64,
+    /// 窗口内同一客户端对同一 SNI 允许的连接数
+    #[serde(default = "default_burst_max_attempts")]
+    pub max_attempts: u32,
+    /// 第一次退避的时长（毫秒），连续触发时翻倍
+    #[serde(default = "default_burst_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    /// 退避时长上限（秒）
+    #[serde(default = "default_burst_max_backoff_secs")]
+    pub max_backoff_secs: u64,
+}
+
+fn default_burst_window_ms() -> u64 {
+    BurstConfig::default().window.as_millis() as u64
+}
+
+fn default_burst_max_attempts() -> u32 {
+    BurstConfig::default().max_attempts
+}
+
+fn default_burst_initial_backoff_ms() -> u64 {
+    BurstConfig::default().initial_backoff.as_millis() as u64
+}
+
+fn default_burst_max_backoff_secs() -> u64 {
+    BurstConfig::default().max_backoff.as_secs()
+}
+
+impl BurstBackoffConfigFile {
+    pub fn build(&self) -> Result<BurstConfig> {
+        if self.window_ms == 0 || self.max_attempts == 0 || self.initial_backoff_ms == 0 {
+            return Err(SniProxyError::InvalidConfig(
+                "burst_backoff 的 window_ms、max_attempts 和 initial_backoff_ms 必须大于 0".to_string(),
+            ));
+        }
+        let config = BurstConfig {
+            window: Duration::from_millis(self.window_ms),
+            max_attempts: self.max_attempts,
+            initial_backoff: Duration::from_millis(self.initial_backoff_ms),
+            max_backoff: Duration::from_secs(self.max_backoff_secs),
+        };
+        if config.max_backoff < config.initial_backoff {
+            return Err(SniProxyError::InvalidConfig(
+                "burst_backoff.max_backoff_secs 不能小于 initial_backoff_ms".to_string(),
+            ));
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AutoBanConfigFile {
+    /// 是否启用自动封禁
+    #[serde(default)]
+    pub enabled: bool,
+    /// 统计窗口（秒）
+    #[serde(default = "default_ban_window_secs")]
+    pub window_secs: u64,
+    /// 窗口内同一客户端 IP 允许的失败次数（被拒绝或无法解析 SNI），达到后封禁
+    #[serde(default = "default_ban_max_failures")]
+    pub max_failures: u32,
+    /// 封禁时长（秒）
+    #[serde(default = "default_ban_duration_secs")]
+    pub ban_secs: u64,
+}
+
+fn default_ban_window_secs() -> u64 {
+    BanConfig::default().window.as_secs()
+}
+
+fn default_ban_max_failures() -> u32 {
+    BanConfig::default().max_failures
+}
+
+fn default_ban_duration_secs() -> u64 {
+    BanConfig::default().ban_duration.as_secs()
+}
+
+impl AutoBanConfigFile {
+    pub fn build(&self) -> Result<BanConfig> {
+        if self.window_secs == 0 || self.max_failures == 0 || self.ban_secs == 0 {
+            return Err(SniProxyError::InvalidConfig(
+                "auto_ban 的 window_secs、max_failures 和 ban_secs 必须大于 0".to_string(),
+            ));
+        }
+        Ok(BanConfig {
+            window: Duration::from_secs(self.window_secs),
+            max_failures: self.max_failures,
+            ban_duration: Duration::from_secs(self.ban_secs),
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AcceptRateLimitConfigFile {
+    /// 是否启用新连接速率限制
+    #[serde(default)]
+    pub enabled: bool,
+    /// 所有监听器每秒允许的新连接数
+    pub rate: u32,
+    /// 允许的突发连接数（默认等于 rate）
+    pub burst: Option<u32>,
+    /// 超过速率时最多排队等待的时间（毫秒），为 0 时立即关闭新连接
+    #[serde(default)]
+    pub max_queue_ms: u64,
+}
+
+impl AcceptRateLimitConfigFile {
+    pub fn build(&self) -> Result<AcceptRateConfig> {
+        let burst = self.burst.unwrap_or(self.rate);
+        if self.rate == 0 || burst == 0 {
+            return Err(SniProxyError::InvalidConfig("accept_rate_limit 的 rate 和 burst 必须大于 0".to_string()));
+        }
+        Ok(AcceptRateConfig {
+            rate: self.rate,
+            burst,
+            max_queue_wait: Duration::from_millis(self.max_queue_ms),
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IoUringConfigFile {
+    /// 是否用 io_uring 接受连接和转发数据
+    #[serde(default)]
+    pub enabled: bool,
+    /// 转发线程数（默认为 CPU 核心数）
+    pub workers: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TimeoutsConfigFile {
+    /// 读取完整 Client Hello 的截止时间（毫秒）
+    pub client_hello_read_ms: Option<u64>,
+    /// 直连目标服务器的超时（毫秒）
+    pub connect_ms: Option<u64>,
+    /// 通过 SOCKS5 连接的总超时（毫秒），包括连接代理服务器、认证和 CONNECT 请求
+    pub socks5_handshake_ms: Option<u64>,
+    /// 转发空闲超时（秒），与 idle_timeout_secs 相同
+    pub idle_secs: Option<u64>,
+}
+
+impl TimeoutsConfigFile {
+    pub fn build(&self) -> Result<TimeoutConfig> {
+        let values = [
+            ("client_hello_read_ms", self.client_hello_read_ms),
+            ("connect_ms", self.connect_ms),
+            ("socks5_handshake_ms", self.socks5_handshake_ms),
+            ("idle_secs", self.idle_secs),
+        ];
+        if let Some((name, _)) = values.iter().find(|(_, value)| *value == Some(0)) {
+            return Err(SniProxyError::InvalidConfig(format!("timeouts.{} 必须大于 0", name)));
+        }
+        Ok(TimeoutConfig {
+            client_hello_read: self.client_hello_read_ms.map(Duration::from_millis),
+            connect: self.connect_ms.map(Duration::from_millis),
+            socks5_handshake: self.socks5_handshake_ms.map(Duration::from_millis),
+            idle: self.idle_secs.map(Duration::from_secs),
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ConnectRetryConfigFile {
+    /// 是否启用上游连接重试
+    #[serde(default)]
+    pub enabled: bool,
+    /// 最多重试次数
+    #[serde(default = "default_connect_max_retries")]
+    pub max_retries: u32,
+    /// 第一次重试前的等待时间（毫秒），之后每次翻倍
+    #[serde(default = "default_connect_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    /// 最长等待时间（毫秒）
+    #[serde(default = "default_connect_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+}
+
+fn default_connect_max_retries() -> u32 {
+    ConnectRetryConfig::default().max_retries
+}
+
+fn default_connect_initial_backoff_ms() -> u64 {
+    ConnectRetryConfig::default().initial_backoff.as_millis() as u64
+}
+
+fn default_connect_max_backoff_ms() -> u64 {
+    ConnectRetryConfig::default().max_backoff.as_millis() as u64
+}
+
+impl ConnectRetryConfigFile {
+    pub fn build(&self) -> Result<ConnectRetryConfig> {
+        if self.max_retries == 0 {
+            return Err(SniProxyError::InvalidConfig("connect_retry.max_retries 必须大于 0".to_string()));
+        }
+        if self.max_backoff_ms < self.initial_backoff_ms {
+            return Err(SniProxyError::InvalidConfig(
+                "connect_retry.max_backoff_ms 不能小于 initial_backoff_ms".to_string(),
+            ));
+        }
+        Ok(ConnectRetryConfig {
+            max_retries: self.max_retries,
+            initial_backoff: Duration::from_millis(self.initial_backoff_ms),
+            max_backoff: Duration::from_millis(self.max_backoff_ms),
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HandshakeProtectionConfigFile {
+    /// 是否启用握手阶段保护
+    #[serde(default)]
+    pub enabled: bool,
+    /// 最多同时处于握手阶段（读取到 Client Hello 之前）的连接数
+    #[serde(default = "default_max_handshaking")]
+    pub max_handshaking: usize,
+    /// 读取完整 Client Hello 的截止时间（毫秒）
+    #[serde(default = "default_hello_timeout_ms")]
+    pub hello_timeout_ms: u64,
+}
+
+fn default_max_handshaking() -> usize {
+    HandshakeConfig::default().max_handshaking
+}
+
+fn default_hello_timeout_ms() -> u64 {
+    HandshakeConfig::default().hello_timeout.as_millis() as u64
+}
+
+impl HandshakeProtectionConfigFile {
+    pub fn build(&self) -> Result<HandshakeConfig> {
+        if self.max_handshaking == 0 || self.hello_timeout_ms == 0 {
+            return Err(SniProxyError::InvalidConfig(
+                "handshake_protection 的 max_handshaking 和 hello_timeout_ms 必须大于 0".to_string(),
+            ));
+        }
+        Ok(HandshakeConfig {
+            max_handshaking: self.max_handshaking,
+            hello_timeout: Duration::from_millis(self.hello_timeout_ms),
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ClientBandwidthConfigFile {
+    /// 每个客户端 IP 的默认带宽上限（kbps，可选），不配置时只限制 ranges 中的客户端
+    pub default_kbps: Option<u64>,
+    /// 按网段设置每个客户端 IP 的带宽上限，先配置的网段优先
+    #[serde(default)]
+    pub ranges: Vec<ClientBandwidthRangeConfigFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ClientBandwidthRangeConfigFile {
+    /// 客户端 IP 或 CIDR
+    pub client_ips: Vec<String>,
+    /// 网段内每个客户端 IP 的带宽上限（kbps）
+    pub kbps: u64,
+}
+
+impl ClientBandwidthConfigFile {
+    pub fn build(&self) -> Result<ClientRateLimits> {
+        if self.default_kbps.is_none() && self.ranges.is_empty() {
+            return Err(SniProxyError::InvalidConfig("client_bandwidth 至少需要配置 default_kbps 或 ranges".to_string()));
+        }
+        if self.default_kbps == Some(0) || self.ranges.iter().any(|range| range.kbps == 0) {
+            return Err(SniProxyError::InvalidConfig("client_bandwidth 的 kbps 必须大于 0".to_string()));
+        }
+        let mut limits = ClientRateLimits::new(self.default_kbps.map(|kbps| kbps * 1000 / 8));
+        for (i, range) in self.ranges.iter().enumerate() {
+            if range.client_ips.is_empty() {
+                return Err(SniProxyError::InvalidConfig(format!("client_bandwidth.ranges[{}] 的 client_ips 不能为空", i)));
+            }
+            if let Some(invalid) = range.client_ips.iter().find(|pattern| !IpMatcher::is_valid_pattern(pattern.trim())) {
+                return Err(SniProxyError::InvalidConfig(format!(
+                    "client_bandwidth.ranges[{}] 中的 IP 或 CIDR 无效: {}",
+                    i, invalid
+                )));
+            }
+            limits = limits.with_range(range.client_ips.clone(), range.kbps * 1000 / 8);
+        }
+        Ok(limits)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HttpRedirectConfigFile {
+    /// 是否启用 HTTP → HTTPS 重定向
+    #[serde(default)]
+    pub enabled: bool,
+    /// 重定向监听地址
+    #[serde(default = "default_http_redirect_listen_addr")]
+    pub listen_addr: String,
+}
+
+fn default_http_redirect_listen_addr() -> String {
+    "0.0.0.0:80".to_string()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DailyReportConfigFile {
+    /// 是否启用每日报告
+    #[serde(default)]
+    pub enabled: bool,
+    /// 报告输出目录
+    #[serde(default = "default_report_directory")]
+    pub directory: String,
+    /// 报告格式："markdown"（默认）或 "html"
+    #[serde(default = "default_report_format")]
+    pub format: String,
+    /// 每天生成报告的时间（本地时间，0-23 点）
+    #[serde(default)]
+    pub hour: u32,
+    /// 报告中列出的客户端 IP 数量
+    #[serde(default = "default_report_top_ips")]
+    pub top_ips: usize,
+    /// 报告生成后推送的 Webhook（可选，http:// 或 https://）
+    pub webhook_url: Option<String>,
+}
+
+fn default_report_directory() -> String {
+    "reports".to_string()
+}
+
+fn default_report_format() -> String {
+    "markdown".to_string()
+}
+
+fn default_report_top_ips() -> usize {
+    10
+}
+
+impl DailyReportConfigFile {
+    pub fn build(&self) -> Result<ReportConfig> {
+        if self.hour > 23 {
+            return Err(SniProxyError::InvalidConfig("daily_report.hour 必须在 0 到 23 之间".to_string()));
+        }
+        Ok(ReportConfig {
+            directory: self.directory.clone().into(),
+            format: self.format.parse()?,
+            hour: self.hour,
+            top_ips: self.top_ips,
+            webhook: self.webhook_url.as_deref().map(str::parse).transpose()?,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InfluxExportConfigFile {
+    /// 是否启用 InfluxDB 导出
+    #[serde(default)]
+    pub enabled: bool,
+    /// 追加写入的文件路径（与 url 二选一）
+    pub file: Option<String>,
+    /// 写入接口（与 file 二选一，http:// 或 https://），例如 "http://127.0.0.1:8086/api/v2/write?org=o&bucket=b&precision=ns"
+    pub url: Option<String>,
+    /// HTTP 接口的 Authorization 头（可选），例如 "Token <token>"
+    pub authorization: Option<String>,
+    /// 导出周期（秒）
+    #[serde(default = "default_influx_interval_secs")]
+    pub interval_secs: u64,
+}
+
+fn default_influx_interval_secs() -> u64 {
+    60
+}
+
+impl InfluxExportConfigFile {
+    pub fn build(&self) -> Result<InfluxConfig> {
+        let target = match (&self.file, &self.url) {
+            (Some(file), None) => InfluxTarget::File(file.into()),
+            (None, Some(url)) => InfluxTarget::Http(url.parse()?),
+            _ => {
+                return Err(SniProxyError::InvalidConfig(
+                    "influx_export 必须且只能配置 file 或 url 之一".to_string(),
+                ))
+            }
+        };
+        if self.interval_secs == 0 {
+            return Err(SniProxyError::InvalidConfig("influx_export.interval_secs 必须大于 0".to_string()));
+        }
+        Ok(InfluxConfig {
+            target,
+            interval: Duration::from_secs(self.interval_secs),
+            authorization: self.authorization.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GeoIpConfigFile {
+    /// MaxMind GeoLite2 / GeoIP2 数据库文件（Country 或 City，MMDB 格式）
+    pub database: String,
+    /// 自动更新的下载地址（可选，http:// 或 https://），响应为 MMDB 文件或 MaxMind 的 tar.gz 压缩包
+    pub update_url: Option<String>,
+    /// MaxMind 账号 ID（可选）
+    pub account_id: Option<String>,
+    /// MaxMind 许可证密钥（可选），和账号 ID 一起以 HTTP Basic 认证发送（update_url 必须是 https://）
+    pub license_key: Option<String>,
+    /// 自动更新周期（小时）
+    #[serde(default = "default_geoip_update_hours")]
+    pub update_hours: u64,
+}
+
+fn default_geoip_update_hours() -> u64 {
+    crate::geoip::DEFAULT_UPDATE_INTERVAL.as_secs() / 3600
+}
+
+impl GeoIpConfigFile {
+    /// 自动更新配置（没有配置 update_url 时为 None）
+    pub fn update(&self) -> Result<Option<GeoIpUpdate>> {
+        let Some(url) = &self.update_url else {
+            if self.account_id.is_some() || self.license_key.is_some() {
+                return Err(SniProxyError::InvalidConfig("geoip.account_id / license_key 需要配置 update_url".to_string()));
+            }
+            return Ok(None);
+        };
+        if self.account_id.is_some() && self.license_key.is_none() {
+            return Err(SniProxyError::InvalidConfig("geoip.account_id 需要同时配置 license_key".to_string()));
+        }
+        if self.update_hours == 0 {
+            return Err(SniProxyError::InvalidConfig("geoip.update_hours 必须大于 0".to_string()));
+        }
+        let mut update = GeoIpUpdate::new(url.parse()?, PathBuf::from(&self.database));
+        update.account_id = self.account_id.clone();
+        update.license_key = self.license_key.clone();
+        update.interval = Duration::from_secs(self.update_hours * 3600);
+        update.validate()?;
+        Ok(Some(update))
+    }
+}
+
+/// 解析国家代码列表
+pub fn parse_countries(codes: &[String]) -> Result<Vec<CountryCode>> {
+    codes.iter().map(|code| code.parse()).collect()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RemoteWhitelistConfigFile {
+    /// 是否启用远程白名单
+    #[serde(default)]
+    pub enabled: bool,
+    /// 直连白名单地址（http:// 或 https://），内容格式同 whitelist_file
+    pub url: Option<String>,
+    /// SOCKS5 白名单地址
+    pub socks5_url: Option<String>,
+    /// IP 白名单地址
+    pub ip_url: Option<String>,
+    /// 请求的 Authorization 头（可选），例如 "Bearer <token>"
+    pub authorization: Option<String>,
+    /// 刷新周期（秒）
+    #[serde(default = "default_remote_whitelist_refresh_secs")]
+    pub refresh_secs: u64,
+    /// 缓存目录（可选），保存上一次成功获取的名单，启动时获取失败则使用缓存
+    pub cache_dir: Option<PathBuf>,
+}
+
+fn default_remote_whitelist_refresh_secs() -> u64 {
+    crate::remote_whitelist::DEFAULT_REFRESH_INTERVAL.as_secs()
+}
+
+impl RemoteWhitelistConfigFile {
+    pub fn build(&self) -> Result<RemoteWhitelists> {
+        let parse = |url: &Option<String>| url.as_deref().map(str::parse).transpose();
+        let remote = RemoteWhitelists {
+            direct: parse(&self.url)?,
+            socks5: parse(&self.socks5_url)?,
+            ip: parse(&self.ip_url)?,
+            authorization: self.authorization.clone(),
+            refresh_interval: Duration::from_secs(self.refresh_secs),
+            cache_dir: self.cache_dir.clone(),
+        };
+        if remote.is_empty() {
+            return Err(SniProxyError::InvalidConfig(
+                "remote_whitelist 至少需要配置 url、socks5_url 或 ip_url 之一".to_string(),
+            ));
+        }
+        if self.refresh_secs == 0 {
+            return Err(SniProxyError::InvalidConfig("remote_whitelist.refresh_secs 必须大于 0".to_string()));
+        }
+        remote.validate()?;
+        Ok(remote)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DnsConfigFile {
+    /// 单次解析的超时时间（毫秒）
+    #[serde(default = "default_dns_timeout_ms")]
+    pub timeout_ms: u64,
+    /// 缓存条目的有效期（秒），不配置表示永不过期
+    pub cache_ttl_secs: Option<u64>,
+    /// 缓存过期后重新解析超时或失败时，是否继续使用过期的结果
+    #[serde(default = "default_true")]
+    pub serve_stale: bool,
+    /// 地址族偏好: ipv6_first（默认）, ipv4_first, ipv4_only, ipv6_only
+    pub family: Option<String>,
+    /// DNS 上游（不配置时使用系统解析器）
+    #[serde(flatten)]
+    pub upstream: DnsUpstreamConfigFile,
+    /// 按域名选择上游的规则，没有匹配的域名使用上面的上游
+    #[serde(default)]
+    pub routes: Vec<DnsRouteConfigFile>,
+    /// 静态解析：域名 → 地址列表，优先于 DNS 查询
+    #[serde(default)]
+    pub hosts: HashMap<String, Vec<IpAddr>>,
+    /// 后台预取直连白名单中的精确域名（可选）
+    pub prefetch: Option<DnsPrefetchConfigFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DnsPrefetchConfigFile {
+    /// 是否启用
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// 预取周期（秒）
+    #[serde(default = "default_dns_prefetch_interval_secs")]
+    pub interval_secs: u64,
+    /// 同时解析的域名数
+    #[serde(default = "default_dns_prefetch_concurrency")]
+    pub concurrency: usize,
+}
+
+fn default_dns_prefetch_interval_secs() -> u64 {
+    DnsPrefetchConfig::default().interval.as_secs()
+}
+
+fn default_dns_prefetch_concurrency() -> usize {
+    DnsPrefetchConfig::default().concurrency
+}
+
+impl DnsPrefetchConfigFile {
+    pub fn build(&self) -> Result<DnsPrefetchConfig> {
+        if self.interval_secs == 0 {
+            return Err(SniProxyError::InvalidConfig("dns.prefetch.interval_secs 必须大于 0".to_string()));
+        }
+        if self.concurrency == 0 {
+            return Err(SniProxyError::InvalidConfig("dns.prefetch.concurrency 必须大于 0".to_string()));
+        }
+        Ok(DnsPrefetchConfig { interval: Duration::from_secs(self.interval_secs), concurrency: self.concurrency })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DnsUpstreamConfigFile {
+    /// 自定义 DNS 服务器（`IP` 或 `IP:端口`，默认端口 53）
+    #[serde(default)]
+    pub nameservers: Vec<String>,
+    /// 搜索域（仅在配置了 nameservers、doh 或 dot 时生效）
+    #[serde(default)]
+    pub search: Vec<String>,
+    /// 向单个服务器查询一次的超时时间（毫秒）
+    pub query_timeout_ms: Option<u64>,
+    /// 每个服务器的查询次数（包括第一次）
+    pub attempts: Option<usize>,
+    /// DNS-over-HTTPS 上游（nameservers、doh、dot 三选一）
+    pub doh: Option<DohConfigFile>,
+    /// DNS-over-TLS 上游（nameservers、doh、dot 三选一）
+    pub dot: Option<DotConfigFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DnsRouteConfigFile {
+    /// 域名后缀：`corp.example` 匹配该域名和所有子域名，`*.corp.example` 只匹配子域名
+    pub domains: Vec<String>,
+    /// 匹配的域名使用的上游，nameservers、doh、dot 都不配置时使用系统解析器
+    #[serde(flatten)]
+    pub upstream: DnsUpstreamConfigFile,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DotConfigFile {
+    /// DoT 服务器（`IP` 或 `IP:端口`，默认端口 853）
+    pub servers: Vec<String>,
+    /// 服务器证书中的名称（SNI 和证书校验使用）
+    pub tls_name: String,
+    /// 服务器公钥指纹 `sha256/<Base64>`，配置后按指纹认证服务器，不再按 CA 校验证书
+    #[serde(default)]
+    pub spki_pins: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DohConfigFile {
+    /// DoH 服务器 URL，例如 `https://dns.google/dns-query`
+    pub url: String,
+    /// DoH 服务器的 IP 地址（URL 中的主机是域名时必须配置）
+    #[serde(default)]
+    pub bootstrap: Vec<IpAddr>,
+}
+
+fn default_dns_timeout_ms() -> u64 {
+    DnsOptions::default().timeout.as_millis() as u64
+}
+
+impl DnsConfigFile {
+    pub fn build(&self) -> Result<DnsOptions> {
+        if self.timeout_ms == 0 {
+            return Err(SniProxyError::InvalidConfig("dns.timeout_ms 必须大于 0".to_string()));
+        }
+        if self.cache_ttl_secs == Some(0) {
+            return Err(SniProxyError::InvalidConfig("dns.cache_ttl_secs 必须大于 0".to_string()));
+        }
+        if let Some((host, _)) = self.hosts.iter().find(|(host, ips)| host.is_empty() || ips.is_empty()) {
+            return Err(SniProxyError::InvalidConfig(format!("dns.hosts 中 {:?} 的地址列表不能为空", host)));
+        }
+        Ok(DnsOptions {
+            timeout: Duration::from_millis(self.timeout_ms),
+            cache_ttl: self.cache_ttl_secs.map(Duration::from_secs),
+            serve_stale: self.serve_stale,
+            family: self.family.as_deref().map(str::parse).transpose()?.unwrap_or_default(),
+        })
+    }
+
+    /// 按域名选择上游的规则
+    pub fn routes(&self) -> Result<Vec<DnsRoute>> {
+        self.routes
+            .iter()
+            .enumerate()
+            .map(|(index, route)| {
+                let upstream = route.upstream.build(&format!("dns.routes[{}]", index))?;
+                DnsRoute::new(route.domains.clone(), upstream)
+            })
+            .collect()
+    }
+}
+
+impl DnsUpstreamConfigFile {
+    /// 自定义 DNS 上游，没有配置 nameservers、doh 和 dot 时返回 None（错误信息中的配置项加上 `prefix`）
+    pub fn build(&self, prefix: &str) -> Result<Option<DnsUpstream>> {
+        let mut upstream = match (self.nameservers.is_empty(), &self.doh, &self.dot) {
+            (true, None, None) => {
+                if !self.search.is_empty() || self.query_timeout_ms.is_some() || self.attempts.is_some() {
+                    return Err(SniProxyError::InvalidConfig(format!(
+                        "{0}.search、{0}.query_timeout_ms 和 {0}.attempts 需要同时配置 {0}.nameservers、{0}.doh 或 {0}.dot",
+                        prefix
+                    )));
+                }
+                return Ok(None);
+            }
+            (false, None, None) => DnsUpstream::new(parse_nameservers(&self.nameservers, 53)?),
+            (true, Some(doh), None) => DnsUpstream::doh(&doh.url, &doh.bootstrap)?,
+            (true, None, Some(dot)) => {
+                let pins = dot.spki_pins.iter().map(|pin| pin.parse()).collect::<Result<_>>()?;
+                DnsUpstream::dot(parse_nameservers(&dot.servers, 853)?, &dot.tls_name, pins)?
+            }
+            _ => {
+                return Err(SniProxyError::InvalidConfig(format!(
+                    "{0}.nameservers、{0}.doh 和 {0}.dot 只能配置一个",
+                    prefix
+                )));
+            }
+        };
+        if let Some(domain) = self.search.iter().find(|domain| domain.trim_matches('.').is_empty()) {
+            return Err(SniProxyError::InvalidConfig(format!("无效的搜索域: {:?}", domain)));
+        }
+        upstream.search = self.search.clone();
+        match self.query_timeout_ms {
+            Some(0) => return Err(SniProxyError::InvalidConfig(format!("{}.query_timeout_ms 必须大于 0", prefix))),
+            Some(ms) => upstream.query_timeout = Duration::from_millis(ms),
+            None => {}
+        }
+        match self.attempts {
+            Some(0) => return Err(SniProxyError::InvalidConfig(format!("{}.attempts 必须大于 0", prefix))),
+            Some(attempts) => upstream.attempts = attempts,
+            None => {}
+        }
+        Ok(Some(upstream))
+    }
+}
+
+/// 解析 DNS 服务器地址列表（`IP` 或 `IP:端口`）
+fn parse_nameservers(servers: &[String], default_port: u16) -> Result<Vec<SocketAddr>> {
+    servers
+        .iter()
+        .map(|server| {
+            server
+                .parse::<SocketAddr>()
+                .or_else(|_| server.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, default_port)))
+                .map_err(|_| SniProxyError::InvalidConfig(format!("无效的 DNS 服务器地址: {}", server)))
+        })
+        .collect()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LogConfigFile {
+    /// 日志级别: off, error, warn, info, debug, trace
+    #[serde(default = "default_log_level")]
+    pub level: String,
+    /// 日志输出目标: stdout, file, both
+    #[serde(default = "default_log_output")]
+    pub output: String,
+    /// 日志文件路径（当 output 为 file 或 both 时需要）
+    pub file_path: Option<String>,
+    /// 是否启用日志轮转
+    #[serde(default)]
+    pub enable_rotation: bool,
+    /// 单个日志文件最大大小（MB）
+    #[serde(default = "default_max_size_mb")]
+    pub max_size_mb: u64,
+    /// 保留的日志文件数量
+    #[serde(default = "default_max_backups")]
+    pub max_backups: usize,
+    /// 是否显示时间戳
+    #[serde(default = "default_true")]
+    pub show_timestamp: bool,
+    /// 是否显示模块路径
+    #[serde(default = "default_true")]
+    pub show_module: bool,
+    /// 是否使用颜色输出
+    #[serde(default = "default_true")]
+    pub use_color: bool,
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+fn default_log_output() -> String {
+    "stdout".to_string()
+}
+
+fn default_max_size_mb() -> u64 {
+    100
+}
+
+fn default_max_backups() -> usize {
+    5
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for LogConfigFile {
+    fn default() -> Self {
+        Self {
+            level: default_log_level(),
+            output: default_log_output(),
+            file_path: None,
+            enable_rotation: false,
+            max_size_mb: default_max_size_mb(),
+            max_backups: default_max_backups(),
+            show_timestamp: true,
+            show_module: true,
+            use_color: true,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OutputFilesConfig {
+    /// 文件模式（八进制字符串），例如 "0640"
+    pub mode: Option<String>,
+    /// 属主（用户名或 UID，修改属主需要 root 权限）
+    pub owner: Option<String>,
+    /// 属组（组名或 GID），例如 "proxy-ops"
+    pub group: Option<String>,
+}
+
+impl OutputFilesConfig {
+    pub fn build(&self) -> Result<OutputPermissions> {
+        Ok(OutputPermissions {
+            mode: self.mode.as_deref().map(OutputPermissions::parse_mode).transpose()?,
+            uid: self.owner.as_deref().map(OutputPermissions::lookup_user).transpose()?,
+            gid: self.group.as_deref().map(OutputPermissions::lookup_group).transpose()?,
+        })
+    }
+}
+
+/// 解析没有 SNI 时的处理方式
+pub fn parse_no_sni_action(action: Option<&str>, backend: Option<&str>, transparent: bool) -> Result<NoSniAction> {
+    let parsed = match action.map(str::to_lowercase).as_deref() {
+        None | Some("reject") => NoSniAction::Reject,
+        Some("backend") => {
+            let backend = backend.ok_or_else(|| {
+                SniProxyError::InvalidConfig("no_sni_action 为 backend 时必须配置 no_sni_backend".to_string())
+            })?;
+            let addr = backend
+                .parse::<SocketAddr>()
+                .map_err(invalid(format!("无效的 no_sni_backend: {}", backend)))?;
+            NoSniAction::Backend(addr)
+        }
+        Some("original_dst") => {
+            if !transparent {
+                return Err(SniProxyError::InvalidConfig(
+                    "no_sni_action 为 original_dst 时需要开启 transparent_mode".to_string(),
+                ));
+            }
+            NoSniAction::OriginalDst
+        }
+        Some(other) => {
+            return Err(SniProxyError::InvalidConfig(format!(
+                "无效的 no_sni_action: {}（可选: reject, backend, original_dst）",
+                other
+            )))
+        }
+    };
+    Ok(parsed)
+}
+
+/// 解析 SNI → 后端映射表
+pub fn parse_sni_backends(entries: &HashMap<String, String>) -> Result<SniBackendMap> {
+    let mut parsed = Vec::with_capacity(entries.len());
+    for (domain, backend) in entries {
+        if domain.is_empty() || domain == "*." {
+            return Err(SniProxyError::InvalidConfig("sni_backends 中的域名不能为空".to_string()));
+        }
+        let backend = backend
+            .parse::<BackendAddr>()
+            .map_err(invalid(format!("sni_backends 中 {} 的后端地址无效", domain)))?;
+        parsed.push((domain.clone(), Arc::new(backend)));
+    }
+    Ok(SniBackendMap::new(parsed))
+}
+
+/// 解析固定 IP 表
+pub fn parse_pinned_ips(entries: &HashMap<String, Vec<String>>) -> Result<PinnedIps> {
+    let mut parsed = Vec::with_capacity(entries.len());
+    for (domain, ips) in entries {
+        if domain.is_empty() || domain == "*." {
+            return Err(SniProxyError::InvalidConfig("pinned_ips 中的域名不能为空".to_string()));
+        }
+        if ips.is_empty() {
+            return Err(SniProxyError::InvalidConfig(format!("pinned_ips 中 {} 至少需要一个 IP", domain)));
+        }
+        let ips = ips
+            .iter()
+            .map(|ip| ip.parse::<IpAddr>().map_err(invalid(format!("pinned_ips 中 {} 的 IP 无效: {}", domain, ip))))
+            .collect::<Result<Vec<_>>>()?;
+        parsed.push((domain.clone(), Arc::from(ips)));
+    }
+    Ok(PinnedIps::new(parsed))
+}
+
+/// 解析 SNI 为 IP 地址时的处理方式
+pub fn parse_ip_sni_policy(action: Option<&str>, by_port: &HashMap<u16, String>) -> Result<IpSniPolicy> {
+    let default_action = match action {
+        Some(action) => action.parse::<IpSniAction>()?,
+        None => IpSniAction::Domain,
+    };
+    let mut policy = IpSniPolicy::new(default_action);
+    for (&listen_port, action) in by_port {
+        policy = policy.with_listener(listen_port, action.parse()?);
+    }
+    Ok(policy)
+}
+
+/// 配置的白名单文件
+fn whitelist_files(config: &Config) -> WhitelistFiles {
+    WhitelistFiles {
+        direct: config.whitelist_file.as_ref().map(Into::into),
+        socks5: config.socks5_whitelist_file.as_ref().map(Into::into),
+        ip: config.ip_whitelist_file.as_ref().map(Into::into),
+        reload_interval: config.whitelist_reload_secs.map(Duration::from_secs),
+    }
+}
+
+/// 启用的远程白名单
+fn remote_whitelists(config: &Config) -> Result<RemoteWhitelists> {
+    match config.remote_whitelist {
+        Some(ref remote) if remote.enabled => remote.build(),
+        _ => Ok(RemoteWhitelists::default()),
+    }
+}
+
+/// 在错误信息前加上出错的配置项
+fn invalid<E: std::fmt::Display>(context: impl std::fmt::Display) -> impl FnOnce(E) -> SniProxyError {
+    move |e| SniProxyError::InvalidConfig(format!("{}: {}", context, e))
+}
+
+/// 验证配置的有效性
+pub fn validate_config(config: &Config) -> Result<()> {
+    // 验证监听器（TCP 地址或 unix:<路径>）
+    let specs = listener_specs(config)?;
+    let listen_addr = primary_listen_addr(&specs)?;
+    if specs.iter().any(|spec| matches!(spec.addr, ListenAddr::Unix(_))) && !cfg!(unix) {
+        return Err(SniProxyError::InvalidConfig("Unix socket 监听仅支持 Unix 平台".to_string()));
+    }
+    // 按协议分发一遍，检查协议、地址和监听器规则是否匹配
+    let mut dispatch = SniProxy::new(listen_addr.clone(), Vec::new());
+    for spec in &specs {
+        dispatch = dispatch.with_listener(spec.clone())?;
+    }
+
+    // 双栈选项仅对 IPv6 监听地址有意义
+    if config.dual_stack && !listen_addr.as_tcp().is_some_and(|addr| addr.is_ipv6()) {
+        log::warn!("⚠️  dual_stack 仅对 IPv6 监听地址生效，当前监听地址不是 IPv6，该选项将被忽略");
+    }
+
+    // 验证目标端口映射
+    if config.target_port == 0 {
+        return Err(SniProxyError::InvalidConfig("target_port 不能为 0".to_string()));
+    }
+    if let Some((listen_port, _)) = config.port_map.iter().find(|(_, &target)| target == 0) {
+        return Err(SniProxyError::InvalidConfig(format!("port_map 中监听端口 {} 的目标端口不能为 0", listen_port)));
+    }
+
+    // 验证内存配置预设
+    if let Some(ref profile) = config.memory_profile {
+        profile.parse::<MemoryProfile>()?;
+    }
+
+    // 验证运行时配置
+    if let Some(ref runtime) = config.runtime {
+        runtime.validate()?;
+    }
+
+    // 验证明文 HTTP 处理方式
+    if let Some(ref action) = config.plaintext_http_response {
+        action.parse::<PlaintextHttpAction>()?;
+        if config.http_sniffing {
+            log::warn!("⚠️  已开启 http_sniffing，plaintext_http_response 不生效");
+        }
+    }
+
+    // 验证拒绝连接方式
+    let rejection_mode = match config.rejection_mode {
+        Some(ref mode) => mode.parse::<RejectionMode>()?,
+        None => RejectionMode::Close,
+    };
+    if let Some(seconds) = config.tarpit_seconds {
+        if seconds == 0 || seconds > MAX_TARPIT_SECONDS {
+            return Err(SniProxyError::InvalidConfig(format!("tarpit_seconds 必须在 1 到 {} 之间", MAX_TARPIT_SECONDS)));
+        }
+        if !matches!(rejection_mode, RejectionMode::Tarpit(_)) {
+            log::warn!("⚠️  rejection_mode 不是 tarpit，tarpit_seconds 不生效");
+        }
+    }
+
+    // 验证默认后端
+    if let Some(ref backend) = config.default_backend {
+        backend.parse::<BackendAddr>()?;
+    }
+
+    // 验证 SNI 映射表和固定 IP
+    parse_sni_backends(&config.sni_backends)?;
+    parse_pinned_ips(&config.pinned_ips)?;
+
+    // 验证 SNI 为 IP 地址时的处理方式
+    let ip_sni_policy = parse_ip_sni_policy(config.ip_sni_action.as_deref(), &config.ip_sni_action_by_port)?;
+    if ip_sni_policy.uses_ip_whitelist() && config.ip_sni_whitelist.is_empty() {
+        return Err(SniProxyError::InvalidConfig("ip_sni_action 为 ip_whitelist 时必须配置 ip_sni_whitelist".to_string()));
+    }
+    if !config.ip_sni_whitelist.is_empty() && !ip_sni_policy.uses_ip_whitelist() {
+        log::warn!("⚠️  没有监听端口使用 ip_whitelist，ip_sni_whitelist 不生效");
+    }
+
+    // 验证没有 SNI 时的处理方式
+    let no_sni_action = parse_no_sni_action(
+        config.no_sni_action.as_deref(),
+        config.no_sni_backend.as_deref(),
+        config.transparent_mode.is_some(),
+    )?;
+    if config.no_sni_backend.is_some() && !matches!(no_sni_action, NoSniAction::Backend(_)) {
+        log::warn!("⚠️  no_sni_action 不是 backend，no_sni_backend 不生效");
+    }
+
+    // 验证透明代理模式
+    if let Some(ref mode) = config.transparent_mode {
+        mode.parse::<TransparentMode>()?;
+        if !cfg!(target_os = "linux") {
+            return Err(SniProxyError::InvalidConfig("透明代理模式仅支持 Linux".to_string()));
+        }
+    }
+
+    // 验证白名单不能为空
+    let whitelist_files = whitelist_files(config);
+    let remote_whitelists = remote_whitelists(config)?;
+    if config.whitelist.is_empty()
+        && config.socks5_whitelist.is_empty()
+        && config.routes.is_empty()
+        && config.categories.is_empty()
+        && whitelist_files.direct.is_none()
+        && whitelist_files.socks5.is_none()
+        && remote_whitelists.direct.is_none()
+        && remote_whitelists.socks5.is_none()
+    {
+        return Err(SniProxyError::InvalidConfig("直连白名单、SOCKS5 白名单和路由规则不能同时为空".to_string()));
+    }
+
+    // 验证白名单文件可以读取
+    let mut file_lists = Vec::new();
+    for path in [&whitelist_files.direct, &whitelist_files.socks5].into_iter().flatten() {
+        file_lists.push(crate::whitelist_file::load_domain_list(path)?);
+    }
+    if let Some(ref path) = whitelist_files.ip {
+        crate::whitelist_file::load_list(path)?;
+    }
+    match config.whitelist_reload_secs {
+        Some(0) => return Err(SniProxyError::InvalidConfig("whitelist_reload_secs 必须大于 0".to_string())),
+        Some(_) if whitelist_files.is_empty() => log::warn!("⚠️  没有配置白名单文件，whitelist_reload_secs 不生效"),
+        _ => {}
+    }
+    if let Some(ref path) = config.blacklist_file {
+        file_lists.push(crate::whitelist_file::load_domain_list(Path::new(path))?);
+    }
+
+    // 验证域名规则中的正则表达式（"~" 前缀）
+    let domain_lists = [&config.whitelist, &config.socks5_whitelist, &config.blacklist, &config.proxy_protocol_domains]
+        .into_iter()
+        .chain(&file_lists)
+        .chain(config.routes.iter().map(|route| &route.domains))
+        .chain(config.categories.iter().map(|category| &category.domains))
+        .chain(config.acl.iter().map(|rule| &rule.domains));
+    for domains in domain_lists {
+        DomainMatcher::validate_rules(domains)?;
+    }
+
+    // 验证 SOCKS5 配置
+    if let Some(ref socks5) = config.socks5 {
+        socks5.build()?;
+    }
+    for (name, socks5) in &config.socks5_upstreams {
+        if name == DEFAULT_SOCKS5_UPSTREAM {
+            return Err(SniProxyError::InvalidConfig(format!("SOCKS5 上游名称 {} 保留给 socks5 配置块", name)));
+        }
+        socks5.build().map_err(invalid(format!("SOCKS5 上游 {} 配置无效", name)))?;
+    }
+
+    if let Some(invalid) = config.ip_blacklist.iter().find(|pattern| !IpMatcher::is_valid_pattern(pattern.trim())) {
+        return Err(SniProxyError::InvalidConfig(format!("ip_blacklist 中的 IP 或 CIDR 无效: {}", invalid)));
+    }
+    if let Some(ref geoip) = config.geoip {
+        geoip.update()?;
+    }
+    parse_countries(&config.geo_whitelist).map_err(invalid("geo_whitelist 无效"))?;
+    parse_countries(&config.geo_blacklist).map_err(invalid("geo_blacklist 无效"))?;
+    if (!config.geo_whitelist.is_empty() || !config.geo_blacklist.is_empty()) && config.geoip.is_none() {
+        return Err(SniProxyError::InvalidConfig("geo_whitelist / geo_blacklist 需要配置 geoip.database".to_string()));
+    }
+    if let Some(i) = config.routes.iter().position(|route| !route.client_countries.is_empty()) {
+        if config.geoip.is_none() {
+            return Err(SniProxyError::InvalidConfig(format!("routes[{}] 的 client_countries 需要配置 geoip.database", i)));
+        }
+    }
+
+    // 验证路由规则和域名分类：动作有效，引用的 SOCKS5 上游存在，分类名称不重复
+    let routes = config.routes.iter().enumerate().map(|(i, route)| {
+        let name = format!("routes[{}]", i);
+        route.build(&config.tuning_profiles).map_err(invalid(format!("{} 无效", name))).map(|rule| (name, rule))
+    });
+    let categories = config.categories.iter().map(|category| {
+        let name = format!("分类 {}", category.name);
+        category.build(&config.tuning_profiles).map_err(invalid(format!("{} 无效", name))).map(|rule| (name, rule))
+    });
+    for item in routes.chain(categories) {
+        let (name, rule) = item?;
+        if let RouteAction::Socks5(upstream) = rule.action() {
+            let defined = if upstream == DEFAULT_SOCKS5_UPSTREAM {
+                config.socks5.is_some()
+            } else {
+                config.socks5_upstreams.contains_key(upstream)
+            };
+            if !defined {
+                return Err(SniProxyError::InvalidConfig(format!("{} 引用了未配置的 SOCKS5 上游: {}", name, upstream)));
+            }
+        }
+    }
+    let mut category_names = std::collections::HashSet::new();
+    for category in &config.categories {
+        if !category_names.insert(&category.name) {
+            return Err(SniProxyError::InvalidConfig(format!("分类名称重复: {}", category.name)));
+        }
+    }
+
+    // 验证 IP 流量追踪配置
+    if let Some(ref tracking) = config.ip_traffic_tracking {
+        if tracking.enabled {
+            // 验证 max_tracked_ips 合理性
+            if tracking.max_tracked_ips == 0 {
+                return Err(SniProxyError::InvalidConfig("IP 流量追踪的 max_tracked_ips 必须大于 0".to_string()));
+            }
+            if tracking.max_tracked_ips > 1_000_000 {
+                log::warn!("⚠️  max_tracked_ips 设置过大 ({})，可能占用大量内存", tracking.max_tracked_ips);
+            }
+
+            // 验证输出文件路径可写
+            if let Some(ref output_file) = tracking.output_file {
+                if let Some(parent) = std::path::Path::new(output_file).parent() {
+                    if !parent.exists() {
+                        log::warn!("⚠️  输出文件目录不存在: {:?}，尝试创建...", parent);
+                        std::fs::create_dir_all(parent)
+                            .map_err(invalid(format!("无法创建输出文件目录: {:?}", parent)))?;
+                    }
+                }
+            }
+
+            // 验证持久化文件路径可写
+            if let Some(ref persistence_file) = tracking.persistence_file {
+                if let Some(parent) = std::path::Path::new(persistence_file).parent() {
+                    if !parent.exists() {
+                        log::warn!("⚠️  持久化文件目录不存在: {:?}，尝试创建...", parent);
+                        std::fs::create_dir_all(parent)
+                            .map_err(invalid(format!("无法创建持久化文件目录: {:?}", parent)))?;
+                    }
+                }
+            }
+        }
+    }
+
+    // 验证 Client Hello 采集配置
+    if let Some(ref capture) = config.hello_capture {
+        if capture.enabled {
+            if capture.corpus_file.is_empty() {
+                return Err(SniProxyError::InvalidConfig("hello_capture.corpus_file 不能为空".to_string()));
+            }
+            if capture.max_entries == 0 {
+                return Err(SniProxyError::InvalidConfig("hello_capture.max_entries 必须大于 0".to_string()));
+            }
+            if let Some(parent) = std::path::Path::new(&capture.corpus_file).parent() {
+                if !parent.as_os_str().is_empty() && !parent.exists() {
+                    log::warn!("⚠️  语料文件目录不存在: {:?}，尝试创建...", parent);
+                    std::fs::create_dir_all(parent).map_err(invalid(format!("无法创建语料文件目录: {:?}", parent)))?;
+                }
+            }
+        }
+    }
+
+    // 验证数据预览配置
+    if let Some(ref preview) = config.log_body_preview {
+        if preview.enabled {
+            if preview.domains.is_empty() && preview.client_ips.is_empty() {
+                return Err(SniProxyError::InvalidConfig("log_body_preview 需要至少配置 domains 或 client_ips 之一".to_string()));
+            }
+            if preview.max_bytes == 0 || preview.max_bytes > MAX_PREVIEW_BYTES {
+                return Err(SniProxyError::InvalidConfig(format!(
+                    "log_body_preview.max_bytes 必须在 1 到 {} 之间",
+                    MAX_PREVIEW_BYTES
+                )));
+            }
+        }
+    }
+
+    // 验证 TLS 指纹配置
+    if let Some(ref fingerprint) = config.tls_fingerprint {
+        if fingerprint.enabled {
+            fingerprint.build_filter()?;
+        }
+    }
+
+    // 验证 TCP 调优配置
+    if let Some(ref tuning) = config.tuning {
+        tuning.tcp.validate("tuning")?;
+        if tuning.acceptors == Some(0) {
+            return Err(SniProxyError::InvalidConfig("tuning.acceptors 必须大于 0".to_string()));
+        }
+    }
+    for (name, profile) in &config.tuning_profiles {
+        profile.validate(&format!("tuning_profiles.{}", name))?;
+    }
+    for (route, name) in &config.route_tuning {
+        parse_tuning_route(route)?;
+        tuning_profile(&config.tuning_profiles, name).map_err(invalid(format!("无效的 route_tuning.{}", route)))?;
+    }
+
+    // 验证 CPU 亲和性配置
+    if let Some(ref affinity) = config.cpu_affinity {
+        if let Some(ref list) = affinity.worker_cpus {
+            parse_cpu_list(list).map_err(invalid("无效的 cpu_affinity.worker_cpus"))?;
+        }
+        if !cfg!(target_os = "linux")
+            && (affinity.worker_cpus.is_some()
+                || !affinity.numa_nodes.is_empty()
+                || affinity.acceptor_cpu.is_some()
+                || affinity.incoming_cpu_steering)
+        {
+            log::warn!("⚠️  CPU 亲和性仅在 Linux 上生效，当前平台将忽略 cpu_affinity 配置");
+        }
+    }
+
+    // 验证管理接口配置
+    if let Some(ref admin) = config.admin {
+        if admin.enabled {
+            let admin_addr = admin
+                .listen_addr
+                .parse::<SocketAddr>()
+                .map_err(invalid("无效的管理接口监听地址格式"))?;
+            if specs.iter().any(|spec| spec.protocol == ListenerProtocol::TlsSni && spec.addr.as_tcp() == Some(admin_addr)) {
+                return Err(SniProxyError::InvalidConfig("管理接口监听地址不能与代理监听地址相同".to_string()));
+            }
+        }
+    }
+
+    // 验证每日报告配置
+    if let Some(ref report) = config.daily_report {
+        if report.enabled {
+            report.build()?;
+        }
+    }
+
+    // 验证 InfluxDB 导出配置
+    if let Some(ref influx) = config.influx_export {
+        if influx.enabled {
+            influx.build()?;
+        }
+    }
+
+    // 验证 DNS 配置
+    if let Some(ref dns) = config.dns {
+        dns.build()?;
+        dns.upstream.build("dns")?;
+        dns.routes()?;
+        if let Some(ref prefetch) = dns.prefetch {
+            prefetch.build()?;
+        }
+    }
+
+    // 验证重复连接退避配置
+    if let Some(ref burst) = config.burst_backoff {
+        if burst.enabled {
+            burst.build()?;
+        }
+    }
+
+    // 验证自动封禁配置
+    if let Some(ref auto_ban) = config.auto_ban {
+        if auto_ban.enabled {
+            auto_ban.build()?;
+        }
+    }
+
+    if let Some(ref client_bandwidth) = config.client_bandwidth {
+        client_bandwidth.build()?;
+    }
+
+    if let Some(ref timeouts) = config.timeouts {
+        timeouts.build()?;
+        if timeouts.idle_secs.is_some() && config.idle_timeout_secs.is_some() {
+            return Err(SniProxyError::InvalidConfig("timeouts.idle_secs 和 idle_timeout_secs 只能配置一个".to_string()));
+        }
+    }
+    if config.idle_timeout_secs == Some(0) {
+        return Err(SniProxyError::InvalidConfig("idle_timeout_secs 必须大于 0".to_string()));
+    }
+    if config.max_connection_lifetime_secs == Some(0) {
+        return Err(SniProxyError::InvalidConfig("max_connection_lifetime_secs 必须大于 0".to_string()));
+    }
+
+    // 验证 io_uring 配置
+    if let Some(ref io_uring) = config.io_uring {
+        if io_uring.enabled {
+            if !cfg!(all(feature = "io-uring", target_os = "linux")) {
+                return Err(SniProxyError::InvalidConfig("io_uring 需要在 Linux 上用 --features io-uring 编译".to_string()));
+            }
+            if io_uring.workers == Some(0) {
+                return Err(SniProxyError::InvalidConfig("io_uring.workers 必须大于 0".to_string()));
+            }
+        }
+    }
+
+    // 验证新连接速率限制配置
+    if let Some(ref accept_rate_limit) = config.accept_rate_limit {
+        if accept_rate_limit.enabled {
+            accept_rate_limit.build()?;
+        }
+    }
+
+    // 验证出站选项
+    if let Some(ref outbound) = config.outbound {
+        outbound.build().map_err(invalid("outbound 配置无效"))?;
+    }
+
+    // 验证上游连接重试配置
+    if let Some(ref connect_retry) = config.connect_retry {
+        if connect_retry.enabled {
+            connect_retry.build()?;
+        }
+    }
+
+    if config.direct_fallback_to_socks5 && config.socks5.is_none() {
+        return Err(SniProxyError::InvalidConfig("direct_fallback_to_socks5 需要配置 socks5".to_string()));
+    }
+    if config.socks5_fallback_to_direct && config.socks5.is_none() && config.socks5_upstreams.is_empty() {
+        return Err(SniProxyError::InvalidConfig(
+            "socks5_fallback_to_direct 需要配置 socks5 或 socks5_upstreams".to_string(),
+        ));
+    }
+
+    // 验证握手阶段保护配置
+    if let Some(ref handshake_protection) = config.handshake_protection {
+        if handshake_protection.enabled {
+            handshake_protection.build()?;
+        }
+    }
+
+    // 验证 ACL
+    for (i, rule) in config.acl.iter().enumerate() {
+        rule.build().map_err(invalid(format!("acl[{}] 无效", i)))?;
+    }
+    if !config.acl.is_empty() && config.acl.iter().all(|rule| rule.action.eq_ignore_ascii_case("deny")) {
+        log::warn!("⚠️  acl 中没有 allow 规则，所有连接都会被拒绝");
+    }
+
+    // 验证输出文件权限配置
+    if let Some(ref output_files) = config.output_files {
+        output_files.build()?;
+    }
+
+    // 验证 HTTP 重定向配置
+    if let Some(ref redirect) = config.http_redirect {
+        if redirect.enabled {
+            let redirect_addr = redirect
+                .listen_addr
+                .parse::<SocketAddr>()
+                .map_err(invalid("无效的 HTTP 重定向监听地址格式"))?;
+            let proxy_addrs = specs.iter().filter(|spec| spec.protocol == ListenerProtocol::TlsSni);
+            for spec in proxy_addrs {
+                if Some(redirect_addr) == spec.addr.as_tcp() {
+                    return Err(SniProxyError::InvalidConfig(format!("HTTP 重定向监听地址不能与代理监听地址相同: {}", spec.addr)));
+                }
+            }
+        }
+    }
+
+    // 验证日志配置
+    if let Some(ref log_config) = config.log {
+        // 验证日志级别
+        let valid_levels = ["off", "error", "warn", "info", "debug", "trace"];
+        if !valid_levels.contains(&log_config.level.as_str()) {
+            return Err(SniProxyError::InvalidConfig(format!(
+                "无效的日志级别: {}，有效值: {:?}",
+                log_config.level, valid_levels
+            )));
+        }
+
+        // 验证日志输出
+        let valid_outputs = ["stdout", "file", "both"];
+        if !valid_outputs.contains(&log_config.output.as_str()) {
+            return Err(SniProxyError::InvalidConfig(format!(
+                "无效的日志输出: {}，有效值: {:?}",
+                log_config.output, valid_outputs
+            )));
+        }
+
+        // 如果输出到文件，验证文件路径
+        if log_config.output == "file" || log_config.output == "both" {
+            if log_config.file_path.is_none() {
+                log::warn!("⚠️  日志输出到文件但未指定路径，将使用默认路径: logs/sni-proxy.log");
+            } else if let Some(ref file_path) = log_config.file_path {
+                if let Some(parent) = std::path::Path::new(file_path).parent() {
+                    if !parent.exists() {
+                        log::warn!("⚠️  日志文件目录不存在: {:?}，尝试创建...", parent);
+                        std::fs::create_dir_all(parent)
+                            .map_err(invalid(format!("无法创建日志文件目录: {:?}", parent)))?;
+                    }
+                }
+            }
+        }
+
+        // 验证日志轮转配置
+        if log_config.enable_rotation {
+            if log_config.max_size_mb == 0 {
+                return Err(SniProxyError::InvalidConfig("启用日志轮转时，max_size_mb 必须大于 0".to_string()));
+            }
+            if log_config.max_backups == 0 {
+                log::warn!("⚠️  max_backups 为 0，日志文件将不保留备份");
+            }
+        }
+    }
+
+    Ok(())
+}
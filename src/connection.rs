@@ -0,0 +1,2685 @@
+use log::{debug, error, info, warn};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::{watch, OwnedSemaphorePermit};
+use tokio::time::{timeout, timeout_at};
+
+use crate::accept_limit::AcceptLimiter;
+use crate::acl::{Acl, AclAction};
+use crate::backend::BackendAddr;
+use crate::ban::BanTable;
+use crate::buffer_pool::BufferPool;
+use crate::burst::BurstLimiter;
+use crate::dns::resolve_host_cached;
+use crate::domain::DomainMatcher;
+use crate::domain_ip_tracker::DomainIpTracker;
+use crate::error::SniProxyError;
+use crate::events::{EventBus, ProxyEvent, RejectReason};
+use crate::fingerprint::{Fingerprint, FingerprintFilter};
+use crate::happy_eyeballs;
+use crate::geoip::{CountryCode, GeoFilter, SharedGeoIp};
+use crate::handshake::{HandshakeLimit, HandshakePermit};
+use crate::hello_corpus::HelloRecorder;
+use crate::http::{head_complete, looks_like_http, parse_http_host, PlaintextHttpAction, DEFAULT_HTTP_PORT};
+use crate::ip_matcher::{IpMatcher, SharedIpMatcher};
+use crate::ip_sni::{parse_ip_literal, IpSniAction, IpSniPolicy};
+use crate::ip_traffic::IpTrafficTracker;
+use crate::metrics::{MetricLabels, Metrics, RouteLabel, UpstreamLabel};
+use crate::outbound::OutboundOptions;
+use crate::port_map::PortMapping;
+use crate::preview::BodyPreview;
+use crate::proxy::{proxy_data_with_buffer_size, PrefixedStream};
+#[cfg(target_os = "linux")]
+use crate::proxy::{splice_data, SpliceRelay};
+use crate::upstream_tls::UpstreamTlsCheck;
+use crate::proxy_protocol::encode_v2_header;
+use crate::sni_map::{PinnedIps, SniBackendMap};
+use crate::listener::ClientStream;
+use crate::redirect::{https_required_response, redirect_response, RedirectWhitelist};
+use crate::relay_timeout::{RelayExpiry, RelayTimer, TimedStream};
+use crate::rejection::{tls_alert, RejectionMode, ALERT_ACCESS_DENIED, ALERT_UNRECOGNIZED_NAME};
+use crate::retry::ConnectRetryConfig;
+use crate::route_table::{
+    RouteAction, RouteQuery, RouteRule, RouteTable, SharedRouteTable, Socks5Upstreams, DEFAULT_SOCKS5_UPSTREAM,
+};
+use crate::socks5::{connect_via_socks5_with, Socks5Config};
+use crate::throttle::{ClientRateLimits, RateLimiter, Throttled};
+use crate::timeouts::TimeoutConfig;
+use crate::tls::{handshake_record_len, parse_client_hello, parse_sni, ClientHelloInfo, NoSniAction};
+use crate::transparent::TransparentMode;
+use crate::tuning::{TcpTuning, TuningPolicy};
+
+/// 单个连接处理所需的共享状态
+///
+/// 每个连接持有一份克隆（内部均为 Arc 或廉价克隆的句柄）
+#[derive(Clone)]
+pub(crate) struct ConnectionContext {
+    /// 路由表（包括由直连白名单和 SOCKS5 白名单生成的规则，白名单文件修改后整体替换）
+    pub(crate) routes: SharedRouteTable,
+    /// IP 白名单（白名单文件修改后整体替换）
+    pub(crate) ip_matcher: SharedIpMatcher,
+    /// 按名称索引的 SOCKS5 上游
+    pub(crate) socks5_upstreams: Arc<Socks5Upstreams>,
+    /// 出站选项（源地址等），路由规则中的选项覆盖这里的
+    pub(crate) outbound: Arc<OutboundOptions>,
+    /// 直连时需要发送 PROXY protocol v2 头部的域名
+    pub(crate) proxy_protocol_matcher: Option<Arc<DomainMatcher>>,
+    pub(crate) metrics: Metrics,
+    pub(crate) ip_traffic_tracker: IpTrafficTracker,
+    pub(crate) domain_ip_tracker: DomainIpTracker,
+    pub(crate) tcp_tuning: Arc<TuningPolicy>,
+    pub(crate) listen_addrs: Arc<[SocketAddr]>,
+    pub(crate) port_mapping: Arc<PortMapping>,
+    pub(crate) transparent_mode: Option<TransparentMode>,
+    /// 非 TLS 连接是否按 HTTP Host 头路由（转发到 80 端口）
+    pub(crate) http_sniffing: bool,
+    /// 读取 Client Hello 的缓冲区池
+    pub(crate) hello_buffers: Arc<BufferPool>,
+    /// 每个转发方向的缓冲区大小
+    pub(crate) relay_buffer_size: usize,
+    /// 显式配置的超时（覆盖按 CPU 核心数自适应的值）
+    pub(crate) timeouts: TimeoutConfig,
+    /// 上游连接重试（None 表示不重试）
+    pub(crate) connect_retry: Option<ConnectRetryConfig>,
+    /// 直连失败时改用默认 SOCKS5 上游重新连接
+    pub(crate) direct_fallback_to_socks5: bool,
+    /// 通过 SOCKS5 连接失败时改用直连重新连接
+    pub(crate) socks5_fallback_to_direct: bool,
+    /// 转发空闲超时（None 表示不限制）
+    pub(crate) idle_timeout: Option<Duration>,
+    /// 最长连接时间（None 表示不限制）
+    pub(crate) max_connection_lifetime: Option<Duration>,
+    /// 条件允许时使用 splice 转发（仅 Linux）
+    pub(crate) splice_relay: bool,
+    /// 连接结束时输出访问记录
+    pub(crate) access_log: bool,
+    /// io_uring 转发线程（io_uring 接受的连接在转发阶段交给它）
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    pub(crate) uring: Option<Arc<crate::uring::UringRuntime>>,
+    /// 运行时事件通道
+    pub(crate) events: EventBus,
+    /// 最大并发连接数（用于 QuotaExceeded 事件）
+    pub(crate) max_connections: usize,
+    /// 服务器关闭信号：握手阶段的连接收到后立即结束
+    pub(crate) shutdown: watch::Receiver<bool>,
+    /// Client Hello 采集器（可选，用于解析器回归测试语料库）
+    pub(crate) hello_recorder: Option<HelloRecorder>,
+    /// 数据预览规则（可选，诊断用）
+    pub(crate) body_preview: Option<Arc<BodyPreview>>,
+    /// TLS 端口收到明文 HTTP 请求时的处理方式（未开启 HTTP 嗅探时生效）
+    pub(crate) plaintext_http: PlaintextHttpAction,
+    /// TLS 指纹过滤规则（可选）
+    pub(crate) fingerprint_filter: Option<Arc<FingerprintFilter>>,
+    /// Client Hello 没有 SNI 时的处理方式
+    pub(crate) no_sni_action: NoSniAction,
+    /// 不在任何白名单中的域名转发到的默认后端（可选）
+    pub(crate) default_backend: Option<Arc<BackendAddr>>,
+    /// SNI → 后端映射表（可选）
+    pub(crate) sni_backends: Option<Arc<SniBackendMap>>,
+    /// 域名 → 固定 IP（可选，直连时跳过 DNS 解析）
+    pub(crate) pinned_ips: Option<Arc<PinnedIps>>,
+    /// IP、域名或指纹被拒绝时断开连接的方式
+    pub(crate) rejection_mode: RejectionMode,
+    /// SNI 为 IP 地址时的处理方式（按监听端口）
+    pub(crate) ip_sni_policy: Arc<IpSniPolicy>,
+    /// SNI 为 IP 地址时允许的目标 IP / CIDR
+    pub(crate) ip_sni_matcher: Option<Arc<IpMatcher>>,
+    /// 重复连接退避（可选）
+    pub(crate) burst_limiter: Option<Arc<BurstLimiter>>,
+    /// 自动封禁（可选）
+    pub(crate) ban_table: Option<Arc<BanTable>>,
+    /// 新连接速率限制（可选，所有监听器共享）
+    pub(crate) accept_limiter: Option<Arc<AcceptLimiter>>,
+    /// 握手阶段保护（可选，所有监听器共享）
+    pub(crate) handshake_limit: Option<Arc<HandshakeLimit>>,
+    /// 按客户端 IP 的带宽限制（可选）
+    pub(crate) client_rate_limits: Option<Arc<ClientRateLimits>>,
+    /// 按客户端 IP 和域名组合检查的 ACL（可选）
+    pub(crate) acl: Option<Arc<Acl>>,
+    /// 域名黑名单（可选），优先于所有白名单和路由规则
+    pub(crate) blacklist: Option<Arc<DomainMatcher>>,
+    /// 客户端 IP 黑名单（可选），优先于 IP 白名单
+    pub(crate) ip_blacklist: Option<Arc<IpMatcher>>,
+    /// GeoIP 数据库（可选）
+    pub(crate) geoip: Option<SharedGeoIp>,
+    /// 按客户端所在国家允许或拒绝（可选，需要 GeoIP 数据库）
+    pub(crate) geo_filter: Option<Arc<GeoFilter>>,
+}
+
+/// 客户端协议
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Protocol {
+    /// TLS（按 SNI 路由）
+    Tls,
+    /// 明文 HTTP（按 Host 头路由）
+    Http,
+}
+
+/// 连接的路由方式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Route {
+    /// 直接连接目标服务器
+    Direct,
+    /// 通过 SOCKS5 代理连接（没有配置对应的上游时直接连接）
+    Socks5(Option<Arc<Socks5Config>>),
+    /// 没有 SNI，直接连接兜底地址（不经过域名白名单）
+    Fallback(SocketAddr),
+    /// 不在白名单中，直接连接默认后端
+    DefaultBackend,
+    /// 在 SNI 映射表中或匹配 backend 路由规则，直接连接映射的后端（不按 SNI 解析 DNS）
+    Mapped(Arc<BackendAddr>),
+}
+
+impl Route {
+    /// 指标中的路由动作标签
+    fn label(&self) -> RouteLabel {
+        match self {
+            Route::Direct | Route::Mapped(_) => RouteLabel::Direct,
+            Route::Socks5(_) => RouteLabel::Socks5,
+            Route::Fallback(_) | Route::DefaultBackend => RouteLabel::Fallback,
+        }
+    }
+}
+
+/// 连接结束原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CloseReason {
+    /// 转发正常结束
+    Completed,
+    /// 客户端 IP 不在白名单中
+    IpRejected,
+    /// 客户端在发送 Client Hello 前关闭连接
+    ClientClosed,
+    /// 读取 Client Hello 失败
+    ReadError,
+    /// 读取 Client Hello 超时
+    ReadTimeout,
+    /// 无法解析 SNI（或开启 HTTP 嗅探时无法解析 Host 头）
+    SniParseError,
+    /// TLS 端口收到明文 HTTP 请求（未开启 HTTP 嗅探）
+    PlaintextHttp,
+    /// 客户端 TLS 指纹被过滤规则拒绝
+    FingerprintRejected,
+    /// 域名不在白名单中
+    DomainRejected,
+    /// SNI 为 IP 地址，按配置拒绝或不在 IP SNI 白名单中
+    IpSniRejected,
+    /// 同一客户端对同一 SNI 的重复连接处于退避期间
+    BurstThrottled,
+    /// 客户端 IP 和域名的组合被 ACL 拒绝
+    AclRejected,
+    /// 客户端所在国家不被允许
+    GeoRejected,
+    /// 客户端 IP 处于自动封禁期间
+    IpBanned,
+    /// DNS 解析失败
+    DnsError,
+    /// 连接目标服务器失败
+    ConnectError,
+    /// 连接目标服务器超时
+    ConnectTimeout,
+    /// 通过 SOCKS5 连接失败
+    Socks5Error,
+    /// 握手阶段收到服务器关闭信号
+    Shutdown,
+    /// 转发阶段两个方向都超过空闲超时没有数据
+    IdleTimeout,
+    /// 转发时间超过最长连接时间
+    MaxLifetime,
+}
+
+impl std::fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            CloseReason::Completed => "completed",
+            CloseReason::IpRejected => "ip_rejected",
+            CloseReason::ClientClosed => "client_closed",
+            CloseReason::ReadError => "read_error",
+            CloseReason::ReadTimeout => "read_timeout",
+            CloseReason::SniParseError => "sni_parse_error",
+            CloseReason::PlaintextHttp => "plaintext_http",
+            CloseReason::FingerprintRejected => "fingerprint_rejected",
+            CloseReason::DomainRejected => "domain_rejected",
+            CloseReason::IpSniRejected => "ip_sni_rejected",
+            CloseReason::BurstThrottled => "burst_throttled",
+            CloseReason::AclRejected => "acl_rejected",
+            CloseReason::GeoRejected => "geo_rejected",
+            CloseReason::IpBanned => "ip_banned",
+            CloseReason::DnsError => "dns_error",
+            CloseReason::ConnectError => "connect_error",
+            CloseReason::ConnectTimeout => "connect_timeout",
+            CloseReason::Socks5Error => "socks5_error",
+            CloseReason::Shutdown => "shutdown",
+            CloseReason::IdleTimeout => "idle_timeout",
+            CloseReason::MaxLifetime => "max_lifetime",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+impl CloseReason {
+    /// 是否计入自动封禁的失败次数：被拒绝或无法解析 SNI（退避、超时和上游错误不计入）
+    pub(crate) fn is_client_failure(self) -> bool {
+        matches!(
+            self,
+            CloseReason::IpRejected
+                | CloseReason::SniParseError
+                | CloseReason::PlaintextHttp
+                | CloseReason::FingerprintRejected
+                | CloseReason::DomainRejected
+                | CloseReason::IpSniRejected
+                | CloseReason::AclRejected
+                | CloseReason::GeoRejected
+        )
+    }
+}
+
+/// 一次连接目标服务器失败
+#[derive(Debug)]
+struct ConnectFailure {
+    reason: CloseReason,
+    /// 上游事件中的主机（DNS 解析失败时为实际解析的后端主机名）
+    host: String,
+    /// 日志内容
+    message: String,
+    /// 上游事件中的错误
+    error: String,
+    /// 是否为可以重试的临时错误
+    retryable: bool,
+}
+
+/// 连接结束时输出的访问记录（只记录已选定上游的连接，被拒绝的连接另有警告日志）
+#[derive(Debug)]
+struct AccessRecord {
+    sni: String,
+    route: RouteLabel,
+    /// 使用的上游：direct 或 socks5（代理服务器地址）
+    upstream: String,
+    /// 实际连接的目标地址（DNS 解析之后）；通过 SOCKS5 时由代理服务器解析，为 None
+    target: Option<SocketAddr>,
+    port: u16,
+    /// 客户端 TLS 指纹（配置了指纹过滤时计算）
+    fingerprint: Option<Fingerprint>,
+    bytes_received: u64,
+    bytes_sent: u64,
+}
+
+/// 路由匹配结果
+pub(crate) enum RouteMatch<'a> {
+    /// 匹配 SNI 映射表
+    Mapped(&'a Arc<BackendAddr>),
+    /// 匹配路由规则
+    Rule(&'a RouteRule),
+    /// 都不匹配，转发到默认后端
+    DefaultBackend(&'a Arc<BackendAddr>),
+    /// 都不匹配且没有默认后端
+    None,
+}
+
+impl RouteMatch<'_> {
+    /// 对应的路由方式，reject 规则和没有匹配时返回 None
+    pub(crate) fn route(&self, ctx: &ConnectionContext) -> Option<Route> {
+        match self {
+            RouteMatch::Mapped(backend) => Some(Route::Mapped(Arc::clone(backend))),
+            RouteMatch::Rule(rule) => match rule.action() {
+                RouteAction::Direct => Some(Route::Direct),
+                RouteAction::Socks5(name) => Some(Route::Socks5(ctx.socks5_upstreams.get(name).cloned())),
+                RouteAction::Backend(backend) => Some(Route::Mapped(Arc::clone(backend))),
+                RouteAction::Reject => None,
+            },
+            RouteMatch::DefaultBackend(_) => Some(Route::DefaultBackend),
+            RouteMatch::None => None,
+        }
+    }
+}
+
+impl ConnectionContext {
+    /// 映射表优先，其次按顺序匹配路由规则，都不匹配时转发到默认后端（如果配置了）
+    ///
+    /// 连接处理和管理接口 `/explain` 共用，保证解释的结果与实际路由一致
+    pub(crate) fn match_route<'a>(&'a self, routes: &'a RouteTable, query: &RouteQuery<'_>) -> RouteMatch<'a> {
+        if let Some(backend) = self.sni_backends.as_ref().and_then(|map| map.lookup(query.domain)) {
+            RouteMatch::Mapped(backend)
+        } else if let Some(rule) = routes.lookup(query) {
+            RouteMatch::Rule(rule)
+        } else if let Some(backend) = &self.default_backend {
+            RouteMatch::DefaultBackend(backend)
+        } else {
+            RouteMatch::None
+        }
+    }
+}
+
+/// 连接处理状态
+///
+/// 每个状态只持有自己需要的数据，状态转换在 `await` 点之间完成，
+/// 因此在任意 `await` 点取消（关闭信号、超时）都不会留下半更新的状态
+#[derive(Debug)]
+pub(crate) enum ConnectionState {
+    /// 已接受连接，检查 IP 白名单
+    Accepted,
+    /// 读取 Client Hello
+    ReadingHello,
+    /// 已解析 SNI（或 HTTP Host），按路由表决定路由（`alpn` 为客户端提供的 ALPN 协议）
+    Routing { hello: Vec<u8>, sni: String, protocol: Protocol, alpn: Vec<String> },
+    /// 连接目标服务器
+    Connecting { hello: Vec<u8>, sni: String, route: Route, port: u16 },
+    /// 双向转发（Client Hello 作为客户端流的前缀数据发送）
+    Relaying { hello: Vec<u8>, sni: String, target: TcpStream },
+    /// 连接被拒绝，按配置的拒绝方式断开（`alert` 为 TLS 连接要发送的 alert 描述）
+    Rejecting { reason: CloseReason, alert: Option<u8> },
+    /// 连接结束
+    Closed(CloseReason),
+}
+
+/// 向明文 HTTP 客户端发送 400 / 301 响应的超时
+const PLAINTEXT_HTTP_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// tarpit 每次读取的间隔（每次只读一个字节）
+const TARPIT_READ_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 连接处理的超时配置
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ConnectionTimeouts {
+    /// 读取 Client Hello 超时
+    pub(crate) read_hello: Duration,
+    /// 连接目标服务器超时
+    pub(crate) connect: Duration,
+    /// 通过 SOCKS5 连接的总超时（None 时只有每一步各自的超时）
+    pub(crate) socks5_handshake: Option<Duration>,
+}
+
+impl ConnectionTimeouts {
+    /// ⚡ 自适应超时配置：根据服务器规模调整
+    /// 小型服务器：更短超时，快速失败，节省资源
+    /// 大型服务器：更长超时，容忍网络抖动
+    pub(crate) fn adaptive() -> Self {
+        let num_cpus = crate::platform::available_cpus();
+        let (read_hello_secs, connect_secs) = if num_cpus <= 2 {
+            (2, 3) // 小型服务器：读取 2 秒，连接 3 秒（快速失败）
+        } else if num_cpus <= 8 {
+            (3, 5) // 中型服务器：读取 3 秒，连接 5 秒
+        } else {
+            (5, 8) // 大型服务器：读取 5 秒，连接 8 秒（容忍慢网络）
+        };
+        Self {
+            read_hello: Duration::from_secs(read_hello_secs),
+            connect: Duration::from_secs(connect_secs),
+            socks5_handshake: None,
+        }
+    }
+
+    /// 按配置覆盖自适应超时；开启握手阶段保护时使用其 Client Hello 截止时间
+    pub(crate) fn for_context(ctx: &ConnectionContext) -> Self {
+        let mut timeouts = Self::adaptive();
+        let config = &ctx.timeouts;
+        if let Some(read_hello) = config.client_hello_read {
+            timeouts.read_hello = read_hello;
+        }
+        if let Some(connect) = config.connect {
+            timeouts.connect = connect;
+        }
+        timeouts.socks5_handshake = config.socks5_handshake;
+        if let Some(limit) = &ctx.handshake_limit {
+            timeouts.read_hello = limit.config().hello_timeout;
+        }
+        timeouts
+    }
+}
+
+/// 访问记录和日志中显示的上游：direct 或 socks5（代理服务器地址）
+fn describe_upstream(route: &Route) -> String {
+    match route {
+        Route::Socks5(Some(socks5)) => format!("socks5 ({})", socks5.addr),
+        _ => "direct".to_string(),
+    }
+}
+
+/// 日志中显示的目标地址列表（多个地址时以 `/` 分隔，IPv6 地址加方括号）
+fn describe_ips(ips: &[IpAddr]) -> String {
+    let describe = |ip: &IpAddr| match ip {
+        IpAddr::V4(v4) => v4.to_string(),
+        IpAddr::V6(v6) => format!("[{}]", v6),
+    };
+    ips.iter().map(describe).collect::<Vec<_>>().join("/")
+}
+
+/// ⚡ 自适应 Client Hello 缓冲区大小：根据系统资源调整
+/// TLS Client Hello 通常 < 4KB，但保留余量
+/// 小型服务器（1-2核）：16KB（节省内存）
+/// 中型服务器（4-8核）：32KB（平衡）
+/// 大型服务器（16+核）：64KB（高性能）
+pub(crate) fn adaptive_hello_buffer_size() -> usize {
+    let num_cpus = crate::platform::available_cpus();
+    if num_cpus <= 2 {
+        16384 // 16KB
+    } else if num_cpus <= 8 {
+        32768 // 32KB
+    } else {
+        65536 // 64KB
+    }
+}
+
+/// 单个连接的状态机
+pub(crate) struct ConnectionHandler<S> {
+    client: S,
+    client_addr: SocketAddr,
+    /// 双栈监听时已转换为 IPv4 的客户端地址
+    client_ip: IpAddr,
+    /// 透明代理的原始目标地址（直连时替代 DNS 解析结果）
+    original_dst: Option<SocketAddr>,
+    /// 目标端口
+    target_port: u16,
+    /// 接受连接的监听端口（Unix socket 为 None）
+    listen_port: Option<u16>,
+    timeouts: ConnectionTimeouts,
+    hello_buffer_size: usize,
+    start_time: Instant,
+    /// 访问记录（连接上游时创建）
+    access: Option<AccessRecord>,
+    /// 匹配的路由规则的带宽上限
+    bandwidth_limit: Option<Arc<RateLimiter>>,
+    /// 出站选项（匹配的路由规则覆盖全局配置）
+    outbound: Arc<OutboundOptions>,
+    /// 匹配的路由规则的 TCP 调优参数
+    tuning: Option<Arc<TcpTuning>>,
+    /// 匹配的路由规则的 DSCP 标记
+    dscp: Option<u8>,
+    /// 匹配的路由规则的拥塞控制算法
+    congestion_control: Option<Arc<str>>,
+    /// 客户端所在国家（配置了 GeoIP 数据库时在接受连接时查询）
+    country: Option<CountryCode>,
+    /// 客户端 TLS 指纹（配置了指纹过滤时在读取 Client Hello 后计算）
+    fingerprint: Option<Fingerprint>,
+    /// 握手许可（开启握手阶段保护时），读取到 Client Hello 后换成 `connection_permit`
+    handshake_permit: Option<HandshakePermit>,
+    connection_permit: Option<OwnedSemaphorePermit>,
+    ctx: ConnectionContext,
+}
+
+impl<S: ClientStream> ConnectionHandler<S> {
+    pub(crate) fn new(client: S, client_addr: SocketAddr, target_port: u16, ctx: ConnectionContext) -> Self {
+        let listen_port = client.local_port();
+        Self {
+            client,
+            client_addr,
+            // 双栈监听时 IPv4 客户端以 ::ffff:a.b.c.d 形式出现，统一转换为 IPv4 地址
+            client_ip: client_addr.ip().to_canonical(),
+            original_dst: None,
+            target_port,
+            listen_port,
+            timeouts: ConnectionTimeouts::for_context(&ctx),
+            hello_buffer_size: ctx.hello_buffers.buffer_size(),
+            start_time: Instant::now(),
+            access: None,
+            bandwidth_limit: None,
+            outbound: Arc::clone(&ctx.outbound),
+            tuning: None,
+            dscp: None,
+            congestion_control: None,
+            country: None,
+            fingerprint: None,
+            handshake_permit: None,
+            connection_permit: None,
+            ctx,
+        }
+    }
+
+    /// 设置握手许可（握手阶段只占用握手许可，读取到 Client Hello 后再获取并发连接许可）
+    pub(crate) fn with_handshake_permit(mut self, permit: Option<HandshakePermit>) -> Self {
+        self.handshake_permit = permit;
+        self
+    }
+
+    /// 设置透明代理的原始目标地址
+    pub(crate) fn with_original_dst(mut self, original_dst: Option<SocketAddr>) -> Self {
+        self.original_dst = original_dst;
+        self
+    }
+
+    /// 运行状态机直到连接结束
+    ///
+    /// 握手阶段（转发之前）收到关闭信号会立即结束连接；
+    /// 转发阶段不响应关闭信号，由服务器的优雅关闭逻辑等待其完成
+    pub(crate) async fn run(mut self) -> CloseReason {
+        let mut shutdown = self.ctx.shutdown.clone();
+        let mut state = ConnectionState::Accepted;
+
+        loop {
+            state = match state {
+                ConnectionState::Closed(reason) => {
+                    self.record_failure(reason);
+                    self.log_access(reason);
+                    return reason;
+                }
+                state @ ConnectionState::Relaying { .. } => self.step(state).await,
+                state => {
+                    tokio::select! {
+                        next = self.step(state) => next,
+                        _ = shutdown.wait_for(|&stop| stop) => {
+                            debug!("握手阶段收到关闭信号，断开 {}", self.client_addr);
+                            ConnectionState::Closed(CloseReason::Shutdown)
+                        }
+                    }
+                }
+            };
+        }
+    }
+
+    /// 握手完成：把握手许可换成并发连接许可（并发连接数已满时排队等待）
+    async fn establish(&mut self) {
+        let Some(permit) = self.handshake_permit.take() else {
+            return;
+        };
+        if permit.connections_full() {
+            debug!("并发连接数已达上限 {}，{} 等待许可", self.ctx.max_connections, self.client_addr);
+            let client_addr = self.client_addr;
+            let limit = self.ctx.max_connections;
+            self.ctx.events.emit(|| ProxyEvent::QuotaExceeded { client_addr, limit });
+        }
+        self.connection_permit = permit.establish().await;
+    }
+
+    /// 失败的连接计入自动封禁，达到阈值时封禁客户端 IP
+    fn record_failure(&self, reason: CloseReason) {
+        let Some(ban_table) = &self.ctx.ban_table else {
+            return;
+        };
+        if !reason.is_client_failure() {
+            return;
+        }
+        if let Some(duration) = ban_table.record_failure(self.client_ip, Instant::now()) {
+            warn!("🚫 IP {} 失败的连接过多（最后一次: {}），封禁 {:?}", self.client_ip, reason, duration);
+        }
+    }
+
+    /// 输出一条访问记录：目标 IP、上游、流量、耗时和结束原因
+    fn log_access(&mut self, reason: CloseReason) {
+        let Some(record) = self.access.take() else {
+            return;
+        };
+        let target = match record.target {
+            Some(addr) => addr.to_string(),
+            None => format!("{}:{}", record.sni, record.port),
+        };
+        let fingerprint = match &record.fingerprint {
+            Some(fingerprint) => {
+                format!(" | JA3: {} | JA4: {}", fingerprint.ja3, fingerprint.ja4.as_deref().unwrap_or("-"))
+            }
+            None => String::new(),
+        };
+        info!(
+            "📋 {} → {} | 路由: {} | 上游: {} | 目标: {} | 上传: {} B | 下载: {} B | 耗时: {:?} | 结束: {}{}",
+            self.client_addr,
+            record.sni,
+            record.route,
+            record.upstream,
+            target,
+            record.bytes_received,
+            record.bytes_sent,
+            self.start_time.elapsed(),
+            reason,
+            fingerprint
+        );
+    }
+
+    /// 执行一次状态转换
+    pub(crate) async fn step(&mut self, state: ConnectionState) -> ConnectionState {
+        #[cfg(feature = "alloc-audit")]
+        let stage = match state {
+            ConnectionState::Accepted => crate::alloc_audit::Stage::CheckIp,
+            ConnectionState::ReadingHello => crate::alloc_audit::Stage::ReadHello,
+            ConnectionState::Routing { .. } => crate::alloc_audit::Stage::Route,
+            ConnectionState::Connecting { .. } => crate::alloc_audit::Stage::Connect,
+            ConnectionState::Relaying { .. } => crate::alloc_audit::Stage::Relay,
+            ConnectionState::Rejecting { .. } => crate::alloc_audit::Stage::Reject,
+            closed @ ConnectionState::Closed(_) => return closed,
+        };
+
+        let next = async {
+            match state {
+                ConnectionState::Accepted => self.check_ip(),
+                ConnectionState::ReadingHello => {
+                    let next = self.read_hello().await;
+                    if !matches!(next, ConnectionState::Closed(_)) {
+                        self.establish().await;
+                    }
+                    next
+                }
+                ConnectionState::Routing { hello, sni, protocol, alpn } => self.route(hello, sni, protocol, alpn),
+                ConnectionState::Connecting { hello, sni, route, port } => self.connect(hello, sni, route, port).await,
+                ConnectionState::Relaying { hello, sni, target } => self.relay(hello, sni, target).await,
+                ConnectionState::Rejecting { reason, alert } => self.reject(reason, alert).await,
+                closed @ ConnectionState::Closed(_) => closed,
+            }
+        };
+
+        // 分配统计模式下按阶段统计分配次数
+        #[cfg(feature = "alloc-audit")]
+        let next = crate::alloc_audit::count(stage, next);
+
+        next.await
+    }
+
+    /// Accepted → ReadingHello：检查自动封禁、IP 黑名单和 IP 白名单（如果配置了）
+    fn check_ip(&mut self) -> ConnectionState {
+        let client_ip = self.client_ip;
+        let client_addr = self.client_addr;
+        self.ctx.events.emit(|| ProxyEvent::ConnectionOpened { client_addr });
+
+        // 自动封禁：直接关闭，不读取 Client Hello，也不输出警告日志
+        if let Some(remaining) = self.ctx.ban_table.as_ref().and_then(|table| table.check(client_ip, Instant::now())) {
+            debug!("IP {} 处于封禁期间（剩余 {:?}），关闭连接", client_ip, remaining);
+            self.ctx.metrics.inc_rejected_requests();
+            self.ctx.metrics.inc_ban_rejections();
+            self.emit_rejected(None, RejectReason::IpBanned);
+            return ConnectionState::Closed(CloseReason::IpBanned);
+        }
+
+        // 客户端所在国家（国家限制和路由规则使用）
+        self.country = self.ctx.geoip.as_ref().and_then(|geoip| geoip.load().country(client_ip));
+        let metrics = &self.ctx.metrics;
+
+        // IP 黑名单：优先于 IP 白名单，没有配置 IP 白名单时同样生效
+        if self.ctx.ip_blacklist.as_ref().is_some_and(|m| m.matches(client_ip)) {
+            let rejected = metrics.get_rejected_requests() + 1;
+            warn!("❌ IP {} 在黑名单中，拒绝连接 | 累计拒绝: {}", client_ip, rejected);
+            metrics.inc_rejected_requests();
+            self.emit_rejected(None, RejectReason::IpBlacklisted);
+            return ConnectionState::Rejecting {
+                reason: CloseReason::IpRejected,
+                alert: Some(ALERT_ACCESS_DENIED),
+            };
+        }
+
+        let ip_in_whitelist = if let Some(ip_matcher) = &*self.ctx.ip_matcher.load() {
+            if !ip_matcher.matches(client_ip) {
+                let rejected = metrics.get_rejected_requests() + 1;
+                warn!("❌ IP {} 不在白名单中，拒绝连接 | 累计拒绝: {}", client_ip, rejected);
+                metrics.inc_rejected_requests();
+                self.emit_rejected(None, RejectReason::IpNotAllowed);
+                return ConnectionState::Rejecting {
+                    reason: CloseReason::IpRejected,
+                    alert: Some(ALERT_ACCESS_DENIED),
+                };
+            }
+            debug!("✅ IP {} 通过白名单检查 (来自 {})", client_ip, self.client_addr);
+            true
+        } else {
+            false
+        };
+
+        // 按国家允许或拒绝（数据库中查不到国家的地址不受限制）
+        if let Some(geo_filter) = &self.ctx.geo_filter {
+            if let Some(country) = self.country.filter(|&country| !geo_filter.allows(Some(country))) {
+                let rejected = metrics.get_rejected_requests() + 1;
+                warn!("❌ IP {} 所在国家 {} 不被允许，拒绝连接 | 累计拒绝: {}", client_ip, country, rejected);
+                metrics.inc_rejected_requests();
+                metrics.inc_geo_rejections();
+                self.emit_rejected(None, RejectReason::GeoBlocked);
+                return ConnectionState::Rejecting {
+                    reason: CloseReason::GeoRejected,
+                    alert: Some(ALERT_ACCESS_DENIED),
+                };
+            }
+        }
+
+        // ACL 中没有任何规则允许该客户端时，不必等待 Client Hello
+        if let Some(ref acl) = self.ctx.acl {
+            if !acl.may_allow_client(client_ip) {
+                let rejected = metrics.get_rejected_requests() + 1;
+                warn!("❌ IP {} 不被 ACL 允许访问任何域名，拒绝连接 | 累计拒绝: {}", client_ip, rejected);
+                metrics.inc_rejected_requests();
+                self.emit_rejected(None, RejectReason::AclDenied);
+                return ConnectionState::Rejecting {
+                    reason: CloseReason::AclRejected,
+                    alert: Some(ALERT_ACCESS_DENIED),
+                };
+            }
+        }
+
+        // 如果 IP 在白名单中，记录连接（用于流量统计）
+        if ip_in_whitelist {
+            self.ctx.ip_traffic_tracker.record_connection(client_ip);
+        }
+
+        ConnectionState::ReadingHello
+    }
+
+    /// ReadingHello → Routing：读取 Client Hello 并解析 SNI
+    async fn read_hello(&mut self) -> ConnectionState {
+        let metrics = &self.ctx.metrics;
+        let mut buffer = self.ctx.hello_buffers.get();
+        let mut filled = 0;
+
+        // ⚡ 优化：读取 Client Hello 超时自适应（整个读取过程共用一个截止时间）
+        let read_start = Instant::now();
+        let deadline = tokio::time::Instant::now() + self.timeouts.read_hello;
+
+        // 较大的 Client Hello（后量子密钥交换、大量扩展）可能分多次到达，
+        // 循环读取直到握手记录完整、缓冲区已满或超时
+        loop {
+            let n = match timeout_at(deadline, self.client.read(&mut buffer[filled..])).await {
+                Ok(Ok(n)) => n,
+                Ok(Err(e)) => {
+                    warn!("读取客户端数据失败: {}", e);
+                    metrics.inc_failed_connections();
+                    return ConnectionState::Closed(CloseReason::ReadError);
+                }
+                Err(_) => {
+                    warn!("读取客户端数据超时（已读取 {} 字节）", filled);
+                    metrics.inc_connection_timeouts();
+                    metrics.inc_slow_handshake_drops();
+                    metrics.inc_failed_connections();
+                    return ConnectionState::Closed(CloseReason::ReadTimeout);
+                }
+            };
+
+            if n == 0 {
+                if filled == 0 {
+                    debug!("客户端连接已关闭");
+                    return ConnectionState::Closed(CloseReason::ClientClosed);
+                }
+                // 客户端半关闭：按已读取的数据解析
+                break;
+            }
+
+            filled += n;
+            if filled == buffer.len() || self.hello_complete(&buffer[..filled]) {
+                break;
+            }
+            debug!("Client Hello 不完整（已读取 {} 字节），继续读取", filled);
+        }
+
+        // 只保留读取到的数据，缓冲区归还缓冲池给下一个连接使用
+        let buffer = buffer.into_vec(filled);
+        debug!("⏱️  读取 Client Hello 耗时: {:?}", read_start.elapsed());
+
+        // 明文 HTTP：按 Host 头路由（仅在开启 HTTP 嗅探时）
+        if self.ctx.http_sniffing && looks_like_http(&buffer) {
+            return match parse_http_host(&buffer) {
+                Some(host) => {
+                    debug!("解析到 HTTP Host: {}", host);
+                    ConnectionState::Routing { hello: buffer, sni: host, protocol: Protocol::Http, alpn: Vec::new() }
+                }
+                None => {
+                    warn!("无法解析 HTTP Host 头，拒绝连接");
+                    metrics.inc_sni_parse_errors();
+                    metrics.inc_failed_connections();
+                    self.emit_rejected(None, RejectReason::SniParseError);
+                    ConnectionState::Closed(CloseReason::SniParseError)
+                }
+            };
+        }
+
+        if let Some(recorder) = &self.ctx.hello_recorder {
+            recorder.record(&buffer);
+        }
+        if let Some(preview) = &self.ctx.body_preview {
+            let sni = parse_sni(&buffer);
+            if preview.matches(self.client_ip, sni.as_deref()) {
+                preview.log_client(self.client_addr, sni.as_deref(), &buffer);
+            }
+        }
+
+        // 未开启 HTTP 嗅探时收到明文 HTTP：客户端配置错误，单独统计，不算 SNI 解析错误
+        if looks_like_http(&buffer) {
+            return self.reject_plaintext_http(&buffer).await;
+        }
+
+        // 解析 SNI
+        let info = parse_client_hello(&buffer);
+        match info.as_ref().and_then(|info| info.sni.clone()) {
+            Some(sni) => {
+                debug!("解析到 SNI: {}", sni);
+                if let (Some(filter), Some(info)) = (self.ctx.fingerprint_filter.clone(), &info) {
+                    if !self.check_fingerprint(&filter, info, &sni) {
+                        return ConnectionState::Rejecting {
+                            reason: CloseReason::FingerprintRejected,
+                            alert: Some(ALERT_ACCESS_DENIED),
+                        };
+                    }
+                }
+                let alpn = info.map(|info| info.alpn).unwrap_or_default();
+                ConnectionState::Routing { hello: buffer, sni, protocol: Protocol::Tls, alpn }
+            }
+            None => {
+                // 合法的 Client Hello 只是没有 SNI：按配置转发到兜底地址
+                if let Some(target) = info.and_then(|_| self.no_sni_target()) {
+                    debug!("Client Hello 没有 SNI，转发到兜底地址 {} (来自 {})", target, self.client_addr);
+                    let route = Route::Fallback(target);
+                    self.set_metric_labels(|labels| labels.route = route.label());
+                    self.ctx.metrics.inc_direct_requests();
+                    return ConnectionState::Connecting {
+                        hello: buffer,
+                        sni: target.ip().to_string(),
+                        route,
+                        port: target.port(),
+                    };
+                }
+
+                warn!("无法解析 SNI，拒绝连接");
+                let metrics = &self.ctx.metrics;
+                metrics.inc_sni_parse_errors();
+                metrics.inc_failed_connections();
+                self.emit_rejected(None, RejectReason::SniParseError);
+                ConnectionState::Closed(CloseReason::SniParseError)
+            }
+        }
+    }
+
+    /// 没有 SNI 的连接要转发到的地址（拒绝时返回 None）
+    fn no_sni_target(&self) -> Option<SocketAddr> {
+        match self.ctx.no_sni_action {
+            NoSniAction::Reject => None,
+            NoSniAction::Backend(addr) => Some(addr),
+            NoSniAction::OriginalDst => self.original_dst,
+        }
+    }
+
+    /// TLS 端口收到明文 HTTP 请求：按配置关闭连接、返回 400 或重定向到 https://
+    async fn reject_plaintext_http(&mut self, head: &[u8]) -> ConnectionState {
+        let host = parse_http_host(head);
+        debug!(
+            "TLS 端口收到明文 HTTP 请求 (来自 {}，Host: {})",
+            self.client_addr,
+            host.as_deref().unwrap_or("-")
+        );
+        self.ctx.metrics.inc_plaintext_http_requests();
+        self.emit_rejected(host.as_deref(), RejectReason::PlaintextHttp);
+
+        let response = match self.ctx.plaintext_http {
+            PlaintextHttpAction::Close => None,
+            PlaintextHttpAction::BadRequest => Some(https_required_response()),
+            PlaintextHttpAction::Redirect => {
+                let whitelist = RedirectWhitelist {
+                    routes: Arc::clone(&self.ctx.routes),
+                    blacklist: self.ctx.blacklist.clone(),
+                };
+                Some(redirect_response(head, &whitelist))
+            }
+        };
+        if let Some(response) = response {
+            let write = async {
+                self.client.write_all(response.as_bytes()).await?;
+                self.client.shutdown().await
+            };
+            match timeout(PLAINTEXT_HTTP_WRITE_TIMEOUT, write).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => debug!("发送明文 HTTP 响应失败 ({}): {}", self.client_addr, e),
+                Err(_) => debug!("发送明文 HTTP 响应超时 ({})", self.client_addr),
+            }
+        }
+
+        ConnectionState::Closed(CloseReason::PlaintextHttp)
+    }
+
+    /// 计算客户端 TLS 指纹（保存供访问记录使用）并按过滤规则检查，被拒绝时返回 false
+    fn check_fingerprint(&mut self, filter: &FingerprintFilter, info: &ClientHelloInfo, sni: &str) -> bool {
+        let fingerprint = self.fingerprint.insert(filter.fingerprint(info));
+        let ja4 = fingerprint.ja4.as_deref().unwrap_or("-");
+        if filter.log_enabled() {
+            info!("🔍 {} SNI: {} JA3: {} JA4: {}", self.client_addr, sni, fingerprint.ja3, ja4);
+        }
+        if filter.allows(fingerprint) {
+            return true;
+        }
+
+        warn!(
+            "❌ 客户端 {} 的 TLS 指纹被拒绝 (SNI: {}, JA3: {}, JA4: {})",
+            self.client_addr, sni, fingerprint.ja3, ja4
+        );
+        self.ctx.metrics.inc_fingerprint_rejections();
+        self.ctx.metrics.inc_rejected_requests();
+        self.emit_rejected(Some(sni), RejectReason::FingerprintBlocked);
+        false
+    }
+
+    /// 已读取的数据是否足以解析 SNI（或 HTTP Host 头）
+    ///
+    /// TLS 需要完整的握手记录（记录长度超过缓冲区时无法完整读取，直接按已有数据解析）；
+    /// 其他数据不再等待，交给解析逻辑判断
+    fn hello_complete(&self, data: &[u8]) -> bool {
+        // 明文 HTTP 需要完整的请求头：HTTP 嗅探按 Host 路由，未开启时按 Host 重定向
+        if looks_like_http(data) {
+            return head_complete(data);
+        }
+        match handshake_record_len(data) {
+            Some(len) => data.len() >= len || len > self.hello_buffer_size,
+            // 不足一个记录头时继续读取
+            None => data[0] != 0x16,
+        }
+    }
+
+    /// Routing → Connecting：按路由表决定连接方式
+    /// ⚡ 延迟优化：减少热路径日志，只在 debug 模式或失败时输出
+    fn route(&mut self, hello: Vec<u8>, sni: String, protocol: Protocol, alpn: Vec<String>) -> ConnectionState {
+        // 重复连接退避：直接关闭，不连接上游，尽快释放并发连接许可
+        if let Some(limiter) = &self.ctx.burst_limiter {
+            if let Some(remaining) = limiter.check(self.client_ip, &sni, Instant::now()) {
+                debug!("客户端 {} 对 {} 的重复连接过多，退避中（剩余 {:?}）", self.client_ip, sni, remaining);
+                self.ctx.metrics.inc_burst_rejections();
+                self.emit_rejected(Some(&sni), RejectReason::BurstBackoff);
+                return ConnectionState::Closed(CloseReason::BurstThrottled);
+            }
+        }
+
+        // 黑名单：优先于白名单、路由规则、映射表和默认后端
+        if self.ctx.blacklist.as_ref().is_some_and(|m| m.matches(&sni)) {
+            self.set_metric_labels(|labels| labels.route = RouteLabel::Rejected);
+            let metrics = &self.ctx.metrics;
+            let rejected = metrics.get_rejected_requests() + 1;
+            warn!("❌ 域名 {} 在黑名单中，拒绝连接 | 累计拒绝: {}", sni, rejected);
+            metrics.inc_rejected_requests();
+            self.emit_rejected(Some(&sni), RejectReason::DomainBlacklisted);
+            return ConnectionState::Rejecting {
+                reason: CloseReason::DomainRejected,
+                alert: (protocol == Protocol::Tls).then_some(ALERT_ACCESS_DENIED),
+            };
+        }
+
+        // ACL：客户端 IP 和域名一起匹配，在路由之前检查
+        if let Some(ref acl) = self.ctx.acl {
+            if acl.check(self.client_ip, &sni) == AclAction::Deny {
+                self.set_metric_labels(|labels| labels.route = RouteLabel::Rejected);
+                let metrics = &self.ctx.metrics;
+                let rejected = metrics.get_rejected_requests() + 1;
+                warn!("❌ ACL 拒绝 {} 访问 {} | 累计拒绝: {}", self.client_ip, sni, rejected);
+                metrics.inc_rejected_requests();
+                self.emit_rejected(Some(&sni), RejectReason::AclDenied);
+                return ConnectionState::Rejecting {
+                    reason: CloseReason::AclRejected,
+                    alert: (protocol == Protocol::Tls).then_some(ALERT_ACCESS_DENIED),
+                };
+            }
+        }
+
+        // SNI 为 IP 地址：按监听端口的配置决定是否绕过域名白名单
+        if let Some(ip) = parse_ip_literal(&sni) {
+            let action = self.ctx.ip_sni_policy.action(self.listen_port);
+            if action != IpSniAction::Domain {
+                return self.route_ip_literal(hello, sni, ip, action, protocol);
+            }
+        }
+
+        let query = RouteQuery { domain: &sni, client_ip: self.client_ip, country: self.country, alpn: &alpn };
+        let mut rule_port = None;
+        let routes = self.ctx.routes.load();
+        let matched = self.ctx.match_route(&routes, &query);
+        match &matched {
+            RouteMatch::Mapped(_) => debug!("域名 {} 匹配 SNI 映射表", sni),
+            RouteMatch::Rule(rule) => {
+                match rule.category() {
+                    Some(category) => debug!("域名 {} 匹配分类 {}: {}", sni, category, rule.action()),
+                    None => debug!("域名 {} 匹配路由规则: {}", sni, rule.action()),
+                }
+                rule_port = rule.target_port(&sni);
+                self.bandwidth_limit = rule.bandwidth_limit().cloned();
+                if let Some(outbound) = rule.outbound() {
+                    self.outbound = Arc::new(outbound.or(&self.ctx.outbound));
+                }
+                self.tuning = rule.tuning().cloned();
+                self.dscp = rule.dscp();
+                self.congestion_control = rule.congestion_control().cloned();
+            }
+            RouteMatch::DefaultBackend(backend) => debug!("域名 {} 不在白名单中，转发到默认后端 {}", sni, backend),
+            RouteMatch::None => {}
+        }
+        let route = matched.route(&self.ctx);
+
+        self.set_metric_labels(|labels| labels.route = route.as_ref().map_or(RouteLabel::Rejected, Route::label));
+        let metrics = &self.ctx.metrics;
+        let route = match route {
+            Some(route @ Route::Socks5(_)) => {
+                metrics.inc_socks5_requests();
+                route
+            }
+            Some(route) => {
+                metrics.inc_direct_requests();
+                route
+            }
+            None => {
+                let rejected = metrics.get_rejected_requests() + 1;
+                warn!("❌ 域名 {} 不在白名单中，拒绝连接 | 累计拒绝: {}", sni, rejected);
+                metrics.inc_rejected_requests();
+                self.emit_rejected(Some(&sni), RejectReason::DomainNotAllowed);
+                return ConnectionState::Rejecting {
+                    reason: CloseReason::DomainRejected,
+                    alert: (protocol == Protocol::Tls).then_some(ALERT_UNRECOGNIZED_NAME),
+                };
+            }
+        };
+
+        let port = self.upstream_port(&route, rule_port, protocol);
+        ConnectionState::Connecting { hello, sni, route, port }
+    }
+
+    /// SNI 为 IP 地址且不按域名白名单匹配：在 IP SNI 白名单中时直连该地址，否则拒绝
+    fn route_ip_literal(
+        &mut self,
+        hello: Vec<u8>,
+        sni: String,
+        ip: IpAddr,
+        action: IpSniAction,
+        protocol: Protocol,
+    ) -> ConnectionState {
+        let allowed = action == IpSniAction::IpWhitelist
+            && self.ctx.ip_sni_matcher.as_ref().is_some_and(|m| m.matches(ip.to_canonical()));
+        if allowed {
+            debug!("IP SNI {} 匹配 IP 白名单", ip);
+            let route = Route::Direct;
+            self.set_metric_labels(|labels| labels.route = route.label());
+            self.ctx.metrics.inc_direct_requests();
+            let port = self.upstream_port(&route, None, protocol);
+            return ConnectionState::Connecting { hello, sni: ip.to_string(), route, port };
+        }
+
+        self.set_metric_labels(|labels| labels.route = RouteLabel::Rejected);
+        let metrics = &self.ctx.metrics;
+        let rejected = metrics.get_rejected_requests() + 1;
+        if action == IpSniAction::Reject {
+            warn!("❌ SNI 为 IP 地址 {}，拒绝连接 | 累计拒绝: {}", sni, rejected);
+        } else {
+            warn!("❌ IP SNI {} 不在 IP 白名单中，拒绝连接 | 累计拒绝: {}", sni, rejected);
+        }
+        metrics.inc_ip_sni_rejections();
+        metrics.inc_rejected_requests();
+        self.emit_rejected(Some(&sni), RejectReason::IpLiteralSni);
+        ConnectionState::Rejecting {
+            reason: CloseReason::IpSniRejected,
+            alert: (protocol == Protocol::Tls).then_some(ALERT_UNRECOGNIZED_NAME),
+        }
+    }
+
+    /// 目标端口：映射表和默认后端使用配置的端口；TLS 连接优先使用路由规则（白名单规则）指定的端口；
+    /// 透明代理使用原始目标端口；否则 TLS 按端口映射，HTTP 使用 80
+    fn upstream_port(&self, route: &Route, rule_port: Option<u16>, protocol: Protocol) -> u16 {
+        if let Some(backend) = self.backend(route) {
+            return backend.port;
+        }
+        if let (Some(port), Protocol::Tls) = (rule_port, protocol) {
+            return port;
+        }
+        match (self.original_dst, protocol) {
+            (Some(dst), _) => dst.port(),
+            (None, Protocol::Tls) => self.target_port,
+            (None, Protocol::Http) => DEFAULT_HTTP_PORT,
+        }
+    }
+
+    /// 主机名配置的固定 IP，按客户端 IP 轮转顺序，使同一客户端总是先连接同一个 IP
+    fn pinned_ips(&self, host: &str) -> Option<Vec<IpAddr>> {
+        let ips = self.ctx.pinned_ips.as_ref()?.lookup(host)?;
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        std::hash::Hash::hash(&self.client_ip, &mut hasher);
+        let start = (std::hash::Hasher::finish(&hasher) % ips.len() as u64) as usize;
+        debug!("{} 使用固定 IP: {:?}", host, ips);
+        Some(ips.iter().cycle().skip(start).take(ips.len()).copied().collect())
+    }
+
+    /// 路由对应的配置后端（映射表或默认后端），其他路由返回 None
+    fn backend(&self, route: &Route) -> Option<Arc<BackendAddr>> {
+        match route {
+            Route::Mapped(backend) => Some(Arc::clone(backend)),
+            Route::DefaultBackend => self.ctx.default_backend.clone(),
+            _ => None,
+        }
+    }
+
+    /// Connecting → Relaying：连接到目标服务器
+    async fn connect(&mut self, hello: Vec<u8>, sni: String, mut route: Route, target_port: u16) -> ConnectionState {
+        // 关闭访问记录时不收集，省去每个连接的分配和格式化
+        if self.ctx.access_log {
+            self.access = Some(AccessRecord {
+                sni: sni.clone(),
+                route: route.label(),
+                upstream: String::new(),
+                target: None,
+                port: target_port,
+                fingerprint: self.fingerprint.clone(),
+                bytes_received: 0,
+                bytes_sent: 0,
+            });
+        }
+        self.set_upstream(&route);
+        let connect_start = Instant::now();
+
+        let target = match self.connect_with_fallback(&sni, &mut route, target_port).await {
+            Ok(stream) => stream,
+            Err(failure) => {
+                error!("{}", failure.message);
+                self.ctx.metrics.inc_failed_connections();
+                self.emit_upstream_down(&failure.host, target_port, &route, &failure.error);
+                return ConnectionState::Closed(failure.reason);
+            }
+        };
+
+        // ⚡ 流媒体优化：按路由设置目标连接的 TCP 参数，匹配的规则指定了调优参数时客户端连接也改用规则的参数
+        match self.tuning {
+            Some(ref tuning) => {
+                let _ = crate::proxy::apply_tcp_tuning(&target, tuning);
+                self.client.apply_tuning(tuning);
+            }
+            None => {
+                let _ = crate::proxy::apply_tcp_tuning(&target, self.ctx.tcp_tuning.upstream(route.label(), self.listen_port));
+            }
+        }
+        // 按规则设置 DSCP 标记（客户端和上游两个方向）
+        if let Some(dscp) = self.dscp {
+            if let Err(e) = crate::tuning::set_dscp(&target, dscp).and_then(|_| self.client.set_dscp(dscp)) {
+                debug!("设置 DSCP {} 失败: {}", dscp, e);
+            }
+        }
+        // 按规则设置拥塞控制算法（覆盖调优参数中的设置）
+        if let Some(ref name) = self.congestion_control {
+            let result = crate::tuning::set_congestion_control(&target, name)
+                .and_then(|_| self.client.set_congestion_control(name));
+            if let Err(e) = result {
+                debug!("设置拥塞控制算法 {} 失败: {}", name, e);
+            }
+        }
+
+        // ⚡ 延迟优化：只在 debug 模式记录成功连接
+        debug!("✅ 连接到 {}:{} 成功 (耗时: {:?})", sni, target_port, connect_start.elapsed());
+
+        ConnectionState::Relaying { hello, sni, target }
+    }
+
+    /// 连接目标服务器，失败时按配置改用其他上游重新连接（`route` 更新为最后使用的路由）
+    async fn connect_with_fallback(
+        &mut self,
+        sni: &str,
+        route: &mut Route,
+        target_port: u16,
+    ) -> Result<TcpStream, ConnectFailure> {
+        let failure = match self.connect_with_retry(sni, route, target_port).await {
+            Ok(stream) => return Ok(stream),
+            Err(failure) => failure,
+        };
+        let Some(fallback) = self.fallback_route(route) else {
+            return Err(failure);
+        };
+        warn!("🔄 {}，改用 {} 重新连接", failure.message, describe_upstream(&fallback));
+        self.ctx.metrics.inc_upstream_fallbacks();
+        self.emit_upstream_down(&failure.host, target_port, route, &failure.error);
+        self.set_upstream(&fallback);
+        *route = fallback;
+        self.connect_with_retry(sni, route, target_port).await
+    }
+
+    /// 连接失败时改用的路由：直连白名单中的域名改用默认 SOCKS5 上游，通过 SOCKS5 的域名改用直连，
+    /// 其他路由不回退
+    fn fallback_route(&self, route: &Route) -> Option<Route> {
+        match route {
+            Route::Direct if self.ctx.direct_fallback_to_socks5 => {
+                self.ctx.socks5_upstreams.get(DEFAULT_SOCKS5_UPSTREAM).cloned().map(|socks5| Route::Socks5(Some(socks5)))
+            }
+            Route::Socks5(Some(_)) if self.ctx.socks5_fallback_to_direct => Some(Route::Direct),
+            _ => None,
+        }
+    }
+
+    /// 记录使用的上游（指标标签和访问记录）
+    fn set_upstream(&mut self, route: &Route) {
+        let via_socks5 = matches!(route, Route::Socks5(Some(_)));
+        self.set_metric_labels(|labels| {
+            labels.upstream = if via_socks5 { UpstreamLabel::Socks5 } else { UpstreamLabel::Direct };
+        });
+        if let Some(record) = self.access.as_mut() {
+            record.upstream = describe_upstream(route);
+            record.target = None;
+        }
+    }
+
+    /// 连接目标服务器，临时错误按配置的退避时间重试
+    async fn connect_with_retry(&mut self, sni: &str, route: &Route, target_port: u16) -> Result<TcpStream, ConnectFailure> {
+        let mut retries = 0;
+        loop {
+            let failure = match self.try_connect(sni, route, target_port).await {
+                Ok(stream) => {
+                    if retries > 0 {
+                        debug!("重试 {} 次后连接到 {}:{} 成功", retries, sni, target_port);
+                    }
+                    return Ok(stream);
+                }
+                Err(failure) => failure,
+            };
+            let retry = self.ctx.connect_retry.filter(|retry| failure.retryable && retries < retry.max_retries);
+            let Some(retry) = retry else {
+                if retries > 0 {
+                    self.ctx.metrics.inc_connect_retry_failures();
+                }
+                return Err(failure);
+            };
+            let backoff = retry.backoff(retries);
+            retries += 1;
+            warn!("{}，{:?} 后第 {} 次重试", failure.message, backoff, retries);
+            self.ctx.metrics.inc_connect_retries();
+            tokio::time::sleep(backoff).await;
+        }
+    }
+
+    /// 连接目标服务器一次（SOCKS5 或直连）
+    async fn try_connect(&mut self, sni: &str, route: &Route, target_port: u16) -> Result<TcpStream, ConnectFailure> {
+        let metrics = &self.ctx.metrics;
+        let connect_start = Instant::now();
+        let outbound = Arc::clone(&self.outbound);
+
+        if let Route::Socks5(Some(socks5)) = route {
+            // 通过 SOCKS5 连接
+            debug!("通过 SOCKS5 连接到 {}:{}", sni, target_port);
+            let connecting = connect_via_socks5_with(sni, target_port, socks5.as_ref(), &outbound);
+            let result = match self.timeouts.socks5_handshake {
+                Some(limit) => timeout(limit, connecting)
+                    .await
+                    .unwrap_or(Err(SniProxyError::Timeout { operation: "通过 SOCKS5 连接" })),
+                None => connecting.await,
+            };
+            return match result {
+                Ok(stream) => {
+                    debug!("⏱️  SOCKS5 连接 {} 耗时: {:?}", sni, connect_start.elapsed());
+                    // 记录通过 SOCKS5 的域名（无法获取实际解析的 IP）
+                    self.ctx.domain_ip_tracker.record_socks5(sni);
+                    Ok(stream)
+                }
+                Err(e) => {
+                    metrics.inc_socks5_errors();
+                    Err(ConnectFailure {
+                        reason: CloseReason::Socks5Error,
+                        host: sni.to_string(),
+                        message: format!(
+                            "通过 SOCKS5 连接到 {}:{} 失败: {} (耗时 {:?})",
+                            sni,
+                            target_port,
+                            e,
+                            connect_start.elapsed()
+                        ),
+                        error: e.to_string(),
+                        retryable: e.is_transient(),
+                    })
+                }
+            };
+        }
+
+        // 直接连接
+        // 没有 SNI 的兜底地址：直接连接，不记录域名-IP
+        // 映射表和默认后端：解析后端主机名（默认后端不记录域名-IP）
+        // 透明代理：直接连接原始目标 IP，不重新解析 DNS
+        // 配置了固定 IP：使用固定 IP，不解析 DNS
+        // 否则 ⚡ 先解析 DNS，获取 IP 地址，用于域名-IP 追踪
+        // 有多个地址时按 Happy Eyeballs（RFC 8305）交替尝试 IPv6 和 IPv4
+        let backend = self.backend(route);
+        let target_ips = match (route, backend.as_deref(), self.original_dst) {
+            (Route::Fallback(addr), _, _) => vec![addr.ip()],
+            (_, None, Some(dst)) => {
+                self.ctx.domain_ip_tracker.record(sni, dst.ip());
+                vec![dst.ip()]
+            }
+            _ => {
+                let host = backend.as_deref().map_or(sni, |backend| backend.host.as_str());
+                let resolved = match self.pinned_ips(host) {
+                    Some(ips) => Ok(ips),
+                    None => resolve_host_cached(host).await,
+                };
+                match resolved {
+                    Ok(ips) => {
+                        // 记录域名和所有解析出的 IP
+                        if *route != Route::DefaultBackend {
+                            for ip in &ips {
+                                self.ctx.domain_ip_tracker.record(sni, *ip);
+                            }
+                        }
+                        ips
+                    }
+                    Err(e) => {
+                        return Err(ConnectFailure {
+                            reason: CloseReason::DnsError,
+                            host: host.to_string(),
+                            message: format!("DNS 解析失败 {}: {}", host, e),
+                            error: e.to_string(),
+                            retryable: false,
+                        });
+                    }
+                }
+            }
+        };
+
+        if let Some(record) = self.access.as_mut() {
+            record.target = Some(SocketAddr::new(target_ips[0], target_port));
+        }
+
+        let connecting = happy_eyeballs::connect(&target_ips, target_port, &outbound);
+        let (mut stream, target_addr) = match timeout(self.timeouts.connect, connecting).await {
+            Ok(Ok(connected)) => connected,
+            Ok(Err(e)) => {
+                return Err(ConnectFailure {
+                    reason: CloseReason::ConnectError,
+                    host: sni.to_string(),
+                    message: format!("连接到目标服务器 {}:{} 失败: {}", describe_ips(&target_ips), target_port, e),
+                    error: e.to_string(),
+                    retryable: crate::retry::is_transient_io(&e),
+                });
+            }
+            Err(_) => {
+                metrics.inc_connection_timeouts();
+                return Err(ConnectFailure {
+                    reason: CloseReason::ConnectTimeout,
+                    host: sni.to_string(),
+                    message: format!("连接到目标服务器 {}:{} 超时", describe_ips(&target_ips), target_port),
+                    error: "连接超时".to_string(),
+                    retryable: true,
+                });
+            }
+        };
+
+        if let Some(record) = self.access.as_mut() {
+            record.target = Some(target_addr);
+        }
+
+        // 按规则在转发前发送 PROXY protocol v2 头部，让下游服务器看到客户端真实 IP
+        if self.ctx.proxy_protocol_matcher.as_ref().is_some_and(|m| m.matches(sni)) {
+            let header = encode_v2_header(self.client_addr, target_addr);
+            if let Err(e) = stream.write_all(&header).await {
+                return Err(ConnectFailure {
+                    reason: CloseReason::ConnectError,
+                    host: sni.to_string(),
+                    message: format!("发送 PROXY protocol 头部到 {} 失败: {}", target_addr, e),
+                    error: e.to_string(),
+                    retryable: false,
+                });
+            }
+            debug!("已发送 PROXY protocol v2 头部: {} → {}", self.client_addr, sni);
+        }
+
+        Ok(stream)
+    }
+
+    /// Relaying → Closed：双向转发数据
+    /// Client Hello 作为客户端流的前缀数据，由转发循环直接发送，省去单独的一次写入
+    /// 客户端发送的是 TLS 握手时，被动检查上游的第一份响应是否也是 TLS
+    async fn relay(&mut self, hello: Vec<u8>, sni: String, target: TcpStream) -> ConnectionState {
+        let proxy_start = Instant::now();
+        let is_tls = hello.first() == Some(&0x16); // TLS 握手记录
+        let client_limit = self.ctx.client_rate_limits.as_ref().and_then(|limits| limits.limiter(self.client_ip));
+        let timer = RelayTimer::new(self.ctx.idle_timeout, self.ctx.max_connection_lifetime);
+        #[cfg(target_os = "linux")]
+        let offloaded = match client_limit {
+            None => self.relay_offloaded(hello, &sni, target, is_tls, &timer).await,
+            Some(_) => Err((hello, target)),
+        };
+        #[cfg(not(target_os = "linux"))]
+        let offloaded = Err::<crate::error::Result<(u64, u64)>, _>((hello, target));
+        let result = match offloaded {
+            Ok(result) => result,
+            Err((hello, target)) => {
+                self.ctx.metrics.inc_userspace_relays();
+                let target = UpstreamTlsCheck::new(target, &sni, self.ctx.metrics.clone(), is_tls);
+                let target = Throttled::new(target, self.bandwidth_limit.take());
+                let target = Throttled::new(target, client_limit);
+                let target = TimedStream::new(target, timer.clone());
+                let client = TimedStream::new(PrefixedStream::new(hello, &mut self.client), timer.clone());
+                match &self.ctx.body_preview {
+                    Some(preview) if preview.matches(self.client_ip, Some(&sni)) => {
+                        proxy_data_with_buffer_size(
+                            client,
+                            preview.wrap_upstream(target, &sni),
+                            self.ctx.relay_buffer_size,
+                            self.ctx.metrics.clone(),
+                            self.client_ip,
+                            self.ctx.ip_traffic_tracker.clone(),
+                        )
+                        .await
+                    }
+                    _ => {
+                        proxy_data_with_buffer_size(
+                            client,
+                            target,
+                            self.ctx.relay_buffer_size,
+                            self.ctx.metrics.clone(),
+                            self.client_ip,
+                            self.ctx.ip_traffic_tracker.clone(),
+                        )
+                        .await
+                    }
+                }
+            }
+        };
+        let (bytes_received, bytes_sent) = result.unwrap_or_else(|e| {
+            debug!("数据转发结束: {}", e);
+            (0, 0)
+        });
+        if let Some(record) = self.access.as_mut() {
+            record.bytes_received = bytes_received;
+            record.bytes_sent = bytes_sent;
+        }
+        let client_addr = self.client_addr;
+        self.ctx.events.emit(|| ProxyEvent::ConnectionClosed {
+            client_addr,
+            host: sni.clone(),
+            bytes_received,
+            bytes_sent,
+        });
+
+        // ⚡ 延迟优化：性能统计只在 debug 模式输出
+        debug!(
+            "⏱️  {} 总耗时: {:?} (转发: {:?})",
+            sni,
+            self.start_time.elapsed(),
+            proxy_start.elapsed()
+        );
+        match timer.and_then(|timer| timer.expired()) {
+            Some(RelayExpiry::Idle) => {
+                self.ctx.metrics.inc_idle_timeouts();
+                ConnectionState::Closed(CloseReason::IdleTimeout)
+            }
+            Some(RelayExpiry::Lifetime) => {
+                info!(
+                    "⏱️  {} → {} 超过最长连接时间 {:?}，已断开",
+                    self.client_addr,
+                    sni,
+                    self.ctx.max_connection_lifetime.unwrap_or_default()
+                );
+                ConnectionState::Closed(CloseReason::MaxLifetime)
+            }
+            None => ConnectionState::Closed(CloseReason::Completed),
+        }
+    }
+
+    /// 客户端是 TCP 连接且不需要在用户态处理数据（限速、正文预览）时，io_uring 接受的连接用 io_uring 转发，
+    /// 开启了 splice 转发时用 splice 转发；都不满足时交还 Client Hello 和上游连接，由调用方用户态转发
+    /// （调用方已确认没有客户端 IP 限速）
+    #[cfg(target_os = "linux")]
+    async fn relay_offloaded(
+        &mut self,
+        hello: Vec<u8>,
+        sni: &str,
+        target: TcpStream,
+        is_tls: bool,
+        timer: &Option<Arc<RelayTimer>>,
+    ) -> std::result::Result<crate::error::Result<(u64, u64)>, (Vec<u8>, TcpStream)> {
+        if self.bandwidth_limit.is_some()
+            || self.ctx.body_preview.as_ref().is_some_and(|preview| preview.matches(self.client_ip, Some(sni)))
+        {
+            return Err((hello, target));
+        }
+
+        #[cfg(feature = "io-uring")]
+        if let Some(runtime) = self.ctx.uring.clone() {
+            if let Some(client) = self.client.take_tcp_stream() {
+                let relay = crate::uring::UringRelay::new(hello, self.ctx.relay_buffer_size).with_timer(timer.clone());
+                let relay = if is_tls { relay.with_tls_check(sni, self.ctx.metrics.clone()) } else { relay };
+                self.ctx.metrics.inc_uring_relays();
+                debug!("{} 使用 io_uring 转发", sni);
+                let result = crate::uring::uring_data(
+                    &runtime,
+                    relay,
+                    client,
+                    target,
+                    self.ctx.metrics.clone(),
+                    self.client_ip,
+                    self.ctx.ip_traffic_tracker.clone(),
+                )
+                .await;
+                return Ok(result);
+            }
+        }
+
+        if !self.ctx.splice_relay {
+            return Err((hello, target));
+        }
+        let Some(client) = self.client.as_tcp_stream() else {
+            return Err((hello, target));
+        };
+        let relay = match SpliceRelay::new() {
+            Ok(relay) => relay.with_timer(timer.clone()),
+            Err(e) => {
+                debug!("创建 splice 管道失败，改用用户态转发: {}", e);
+                return Err((hello, target));
+            }
+        };
+        let relay = if is_tls { relay.with_tls_check(sni, self.ctx.metrics.clone()) } else { relay };
+        self.ctx.metrics.inc_splice_relays();
+        debug!("{} 使用 splice 转发", sni);
+        let result = splice_data(
+            relay,
+            &hello,
+            client,
+            &target,
+            self.ctx.metrics.clone(),
+            self.client_ip,
+            self.ctx.ip_traffic_tracker.clone(),
+        )
+        .await;
+        Ok(result)
+    }
+
+    /// Rejecting → Closed：按配置的拒绝方式断开连接
+    ///
+    /// 明文 HTTP 连接没有 TLS alert 可发，`alert` 方式退化为直接关闭
+    async fn reject(&mut self, reason: CloseReason, alert: Option<u8>) -> ConnectionState {
+        match self.ctx.rejection_mode {
+            RejectionMode::Close => {}
+            RejectionMode::Reset => {
+                if let Err(e) = self.client.reset_on_close() {
+                    debug!("设置 SO_LINGER 失败 ({}): {}", self.client_addr, e);
+                }
+            }
+            RejectionMode::Alert => {
+                if let Some(description) = alert {
+                    let write = async {
+                        self.client.write_all(&tls_alert(description)).await?;
+                        self.client.shutdown().await
+                    };
+                    match timeout(PLAINTEXT_HTTP_WRITE_TIMEOUT, write).await {
+                        Ok(Ok(())) => {}
+                        Ok(Err(e)) => debug!("发送 TLS alert 失败 ({}): {}", self.client_addr, e),
+                        Err(_) => debug!("发送 TLS alert 超时 ({})", self.client_addr),
+                    }
+                }
+            }
+            RejectionMode::Tarpit(duration) => {
+                debug!("⏳ tarpit 拖住被拒绝的连接 {} (最长 {:?})", self.client_addr, duration);
+                let _ = timeout(duration, self.tarpit()).await;
+            }
+        }
+        ConnectionState::Closed(reason)
+    }
+
+    /// 每隔一段时间读取一个字节，直到对方断开（由调用方限制总时长）
+    async fn tarpit(&mut self) {
+        let mut byte = [0u8; 1];
+        loop {
+            tokio::time::sleep(TARPIT_READ_INTERVAL).await;
+            match self.client.read(&mut byte).await {
+                Ok(0) | Err(_) => return,
+                Ok(_) => {}
+            }
+        }
+    }
+
+    /// 更新指标标签，之后的计数同时记入新的标签组合
+    fn set_metric_labels(&mut self, update: impl FnOnce(&mut MetricLabels)) {
+        let mut labels = self.ctx.metrics.labels().unwrap_or_default();
+        update(&mut labels);
+        self.ctx.metrics = self.ctx.metrics.with_labels(labels);
+    }
+
+    /// 发送连接被拒绝事件
+    fn emit_rejected(&self, host: Option<&str>, reason: RejectReason) {
+        self.ctx.events.emit(|| ProxyEvent::Rejected {
+            client_addr: self.client_addr,
+            host: host.map(str::to_string),
+            reason,
+        });
+    }
+
+    /// 发送上游不可用事件
+    fn emit_upstream_down(&self, host: &str, port: u16, route: &Route, error: impl std::fmt::Display) {
+        self.ctx.events.emit(|| ProxyEvent::UpstreamDown {
+            host: host.to_string(),
+            port,
+            via_socks5: matches!(route, Route::Socks5(_)),
+            error: error.to_string(),
+        });
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::acl::AclRule;
+    use crate::burst::BurstConfig;
+    use crate::tls::tests::client_hello;
+    use arc_swap::{ArcSwap, ArcSwapOption};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+    use tokio::net::TcpListener;
+
+    pub(crate) fn test_context(whitelist: &[&str], socks5_whitelist: &[&str]) -> (ConnectionContext, watch::Sender<bool>) {
+        let (shutdown_tx, shutdown) = watch::channel(false);
+        let to_vec = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let ctx = ConnectionContext {
+            routes: Arc::new(ArcSwap::from_pointee(RouteTable::from_whitelists(
+                to_vec(whitelist),
+                to_vec(socks5_whitelist),
+            ))),
+            ip_matcher: Arc::new(ArcSwapOption::empty()),
+            socks5_upstreams: Arc::new(Socks5Upstreams::new()),
+            outbound: Arc::new(OutboundOptions::default()),
+            proxy_protocol_matcher: None,
+            metrics: Metrics::new(),
+            ip_traffic_tracker: IpTrafficTracker::disabled(),
+            domain_ip_tracker: DomainIpTracker::disabled(),
+            tcp_tuning: Arc::new(TuningPolicy::default()),
+            listen_addrs: Arc::from(Vec::new()),
+            port_mapping: Arc::new(PortMapping::default()),
+            transparent_mode: None,
+            http_sniffing: false,
+            hello_buffers: Arc::new(BufferPool::new(adaptive_hello_buffer_size())),
+            relay_buffer_size: crate::proxy::DEFAULT_RELAY_BUFFER_SIZE,
+            timeouts: TimeoutConfig::default(),
+            connect_retry: None,
+            direct_fallback_to_socks5: false,
+            splice_relay: false,
+            access_log: true,
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            uring: None,
+            socks5_fallback_to_direct: false,
+            idle_timeout: None,
+            max_connection_lifetime: None,
+            events: EventBus::default(),
+            max_connections: 100,
+            shutdown,
+            hello_recorder: None,
+            body_preview: None,
+            plaintext_http: PlaintextHttpAction::Close,
+            fingerprint_filter: None,
+            rejection_mode: RejectionMode::Close,
+            no_sni_action: NoSniAction::Reject,
+            default_backend: None,
+            sni_backends: None,
+            pinned_ips: None,
+            ip_sni_policy: Arc::new(IpSniPolicy::default()),
+            ip_sni_matcher: None,
+            burst_limiter: None,
+            ban_table: None,
+            accept_limiter: None,
+            handshake_limit: None,
+            client_rate_limits: None,
+            acl: None,
+            blacklist: None,
+            ip_blacklist: None,
+            geoip: None,
+            geo_filter: None,
+        };
+        (ctx, shutdown_tx)
+    }
+
+    fn handler(ctx: ConnectionContext) -> (ConnectionHandler<DuplexStream>, DuplexStream) {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let handler = ConnectionHandler::new(server, "192.168.1.10:50000".parse().unwrap(), 443, ctx);
+        (handler, client)
+    }
+
+    #[tokio::test]
+    async fn test_accepted_ip_whitelist() {
+        let (mut ctx, _tx) = test_context(&["example.com"], &[]);
+        ctx.ip_matcher = Arc::new(ArcSwapOption::from_pointee(IpMatcher::new(vec!["10.0.0.0/8".to_string()])));
+        let (mut h, _client) = handler(ctx.clone());
+        let next = h.step(ConnectionState::Accepted).await;
+        assert!(matches!(next, ConnectionState::Rejecting { reason: CloseReason::IpRejected, .. }));
+        assert_eq!(ctx.metrics.get_rejected_requests(), 1);
+
+        ctx.ip_matcher = Arc::new(ArcSwapOption::from_pointee(IpMatcher::new(vec!["192.168.1.0/24".to_string()])));
+        let (mut h, _client) = handler(ctx.clone());
+        let next = h.step(ConnectionState::Accepted).await;
+        assert!(matches!(next, ConnectionState::ReadingHello));
+
+        // IP 黑名单优先于 IP 白名单
+        ctx.ip_blacklist = Some(Arc::new(IpMatcher::new(vec!["192.168.1.10".to_string()])));
+        let mut events = ctx.events.subscribe();
+        let (mut h, _client) = handler(ctx);
+        let next = h.step(ConnectionState::Accepted).await;
+        assert!(matches!(next, ConnectionState::Rejecting { reason: CloseReason::IpRejected, .. }));
+        assert!(matches!(events.try_recv(), Ok(ProxyEvent::ConnectionOpened { .. })));
+        assert!(matches!(events.try_recv(), Ok(ProxyEvent::Rejected { reason: RejectReason::IpBlacklisted, .. })));
+    }
+
+    #[tokio::test]
+    async fn test_accepted_geo_filter() {
+        let (mut ctx, _tx) = test_context(&["example.com"], &[]);
+        let database = crate::geoip::tests::test_database(&[("192.168.0.0/16", "RU")]);
+        ctx.geoip = Some(Arc::new(ArcSwap::from_pointee(crate::geoip::GeoIp::from_bytes(database).unwrap())));
+        let codes = |codes: &[&str]| codes.iter().map(|c| c.parse().unwrap()).collect();
+
+        ctx.geo_filter = Some(Arc::new(GeoFilter::new(codes(&["US"]), Vec::new())));
+        let (mut h, _client) = handler(ctx.clone());
+        let next = h.step(ConnectionState::Accepted).await;
+        assert!(matches!(next, ConnectionState::Rejecting { reason: CloseReason::GeoRejected, .. }));
+        assert_eq!(ctx.metrics.snapshot().geo_rejections, 1);
+
+        ctx.geo_filter = Some(Arc::new(GeoFilter::new(Vec::new(), codes(&["CN"]))));
+        let (mut h, _client) = handler(ctx);
+        let next = h.step(ConnectionState::Accepted).await;
+        assert!(matches!(next, ConnectionState::ReadingHello));
+    }
+
+    #[tokio::test]
+    async fn test_reading_hello() {
+        let (ctx, _tx) = test_context(&["example.com"], &[]);
+        let (mut h, mut client) = handler(ctx);
+        client.write_all(&client_hello("example.com")).await.unwrap();
+
+        match h.step(ConnectionState::ReadingHello).await {
+            ConnectionState::Routing { hello, sni, protocol, .. } => {
+                assert_eq!(sni, "example.com");
+                assert_eq!(protocol, Protocol::Tls);
+                assert_eq!(hello, client_hello("example.com"));
+            }
+            other => panic!("unexpected state: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reading_hello_fingerprint_filter() {
+        let hello = client_hello("example.com");
+        let ja3 = crate::fingerprint::ja3(&parse_client_hello(&hello).unwrap());
+
+        let (mut ctx, _tx) = test_context(&["example.com"], &[]);
+        ctx.fingerprint_filter = Some(Arc::new(FingerprintFilter::new(Vec::new(), vec![ja3], true, false).unwrap()));
+        let (mut h, mut client) = handler(ctx.clone());
+        client.write_all(&hello).await.unwrap();
+        let next = h.step(ConnectionState::ReadingHello).await;
+        assert!(matches!(next, ConnectionState::Rejecting { reason: CloseReason::FingerprintRejected, .. }));
+        let snapshot = ctx.metrics.snapshot();
+        assert_eq!(snapshot.fingerprint_rejections, 1);
+        assert_eq!(snapshot.rejected_requests, 1);
+
+        // 不在拒绝列表中的指纹正常路由
+        let other = crate::tls::tests::client_hello_with_extensions("example.com", &[(23, &[])]);
+        let (mut h, mut client) = handler(ctx);
+        client.write_all(&other).await.unwrap();
+        assert!(matches!(
+            h.step(ConnectionState::ReadingHello).await,
+            ConnectionState::Routing { .. }
+        ));
+        // 计算出的指纹保存在连接上，供访问记录使用
+        let other_ja3 = crate::fingerprint::ja3(&parse_client_hello(&other).unwrap());
+        assert_eq!(h.fingerprint.as_ref().map(|fingerprint| fingerprint.ja3.as_str()), Some(other_ja3.as_str()));
+    }
+
+    #[tokio::test]
+    async fn test_reading_hello_split() {
+        let (ctx, _tx) = test_context(&["example.com"], &[]);
+        let (mut h, mut client) = handler(ctx);
+        let hello = client_hello("example.com");
+
+        // Client Hello 分三次到达（第一次连记录头都不完整）
+        let data = hello.clone();
+        tokio::spawn(async move {
+            for chunk in [&data[..3], &data[3..20], &data[20..]] {
+                client.write_all(chunk).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            // 保持连接直到读取完成
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        });
+
+        match h.step(ConnectionState::ReadingHello).await {
+            ConnectionState::Routing { hello: read, sni, .. } => {
+                assert_eq!(sni, "example.com");
+                assert_eq!(read, hello);
+            }
+            other => panic!("unexpected state: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reading_hello_failures() {
+        let (ctx, _tx) = test_context(&["example.com"], &[]);
+
+        // 客户端直接关闭
+        let (mut h, client) = handler(ctx.clone());
+        drop(client);
+        let next = h.step(ConnectionState::ReadingHello).await;
+        assert!(matches!(next, ConnectionState::Closed(CloseReason::ClientClosed)));
+
+        // 非 TLS 数据
+        let (mut h, mut client) = handler(ctx.clone());
+        client.write_all(b"SSH-2.0-OpenSSH_9.6\r\n").await.unwrap();
+        let next = h.step(ConnectionState::ReadingHello).await;
+        assert!(matches!(next, ConnectionState::Closed(CloseReason::SniParseError)));
+        assert_eq!(ctx.metrics.snapshot().sni_parse_errors, 1);
+    }
+
+    #[tokio::test]
+    async fn test_reading_plaintext_http() {
+        // 默认直接关闭，不计入 SNI 解析错误
+        let (ctx, _tx) = test_context(&["example.com"], &[]);
+        let (mut h, mut client) = handler(ctx.clone());
+        client.write_all(b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n").await.unwrap();
+        let next = h.step(ConnectionState::ReadingHello).await;
+        assert!(matches!(next, ConnectionState::Closed(CloseReason::PlaintextHttp)));
+        drop(h);
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).await.unwrap();
+        assert!(response.is_empty());
+
+        // 返回 400
+        let (mut ctx, _tx) = test_context(&["example.com"], &[]);
+        ctx.plaintext_http = PlaintextHttpAction::BadRequest;
+        let (mut h, mut client) = handler(ctx);
+        client.write_all(b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n").await.unwrap();
+        h.step(ConnectionState::ReadingHello).await;
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+
+        // 白名单内的域名重定向到 HTTPS，其他域名返回 403
+        let (mut ctx, _tx) = test_context(&["example.com"], &[]);
+        ctx.plaintext_http = PlaintextHttpAction::Redirect;
+        let (mut h, mut client) = handler(ctx.clone());
+        client.write_all(b"GET /path?q=1 HTTP/1.1\r\nHost: example.com:443\r\n\r\n").await.unwrap();
+        h.step(ConnectionState::ReadingHello).await;
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 301 Moved Permanently\r\n"));
+        assert!(response.contains("Location: https://example.com/path?q=1\r\n"));
+
+        let (mut h, mut client) = handler(ctx.clone());
+        client.write_all(b"GET / HTTP/1.1\r\nHost: other.com\r\n\r\n").await.unwrap();
+        h.step(ConnectionState::ReadingHello).await;
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+
+        let snapshot = ctx.metrics.snapshot();
+        assert_eq!(snapshot.plaintext_http_requests, 2);
+        assert_eq!(snapshot.sni_parse_errors, 0);
+    }
+
+    #[tokio::test]
+    async fn test_reading_http_host() {
+        let (mut ctx, _tx) = test_context(&["example.com"], &[]);
+        ctx.http_sniffing = true;
+        let (mut h, mut client) = handler(ctx);
+        client.write_all(b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n").await.unwrap();
+
+        let next = h.step(ConnectionState::ReadingHello).await;
+        assert!(matches!(
+            &next,
+            ConnectionState::Routing { sni, protocol: Protocol::Http, .. } if sni == "example.com"
+        ));
+
+        // HTTP 连接转发到 80 端口
+        assert!(matches!(
+            h.step(next).await,
+            ConnectionState::Connecting { route: Route::Direct, port: 80, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_reading_hello_timeout() {
+        let (ctx, _tx) = test_context(&["example.com"], &[]);
+        let (mut h, _client) = handler(ctx.clone());
+        h.timeouts = ConnectionTimeouts {
+            read_hello: Duration::from_millis(20),
+            connect: Duration::from_millis(20),
+            socks5_handshake: None,
+        };
+        let next = h.step(ConnectionState::ReadingHello).await;
+        assert!(matches!(next, ConnectionState::Closed(CloseReason::ReadTimeout)));
+        assert_eq!(ctx.metrics.snapshot().connection_timeouts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_configured_timeouts() {
+        let (mut ctx, _tx) = test_context(&["example.com"], &[]);
+        ctx.timeouts = TimeoutConfig {
+            client_hello_read: Some(Duration::from_millis(1500)),
+            socks5_handshake: Some(Duration::from_secs(8)),
+            ..Default::default()
+        };
+        let (h, _client) = handler(ctx.clone());
+        assert_eq!(h.timeouts.read_hello, Duration::from_millis(1500));
+        assert_eq!(h.timeouts.connect, ConnectionTimeouts::adaptive().connect);
+        assert_eq!(h.timeouts.socks5_handshake, Some(Duration::from_secs(8)));
+
+        // 握手阶段保护的截止时间优先
+        ctx.handshake_limit = Some(Arc::new(HandshakeLimit::new(crate::handshake::HandshakeConfig {
+            max_handshaking: 10,
+            hello_timeout: Duration::from_secs(1),
+        })));
+        let (h, _client) = handler(ctx);
+        assert_eq!(h.timeouts.read_hello, Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_routing() {
+        let (ctx, _tx) = test_context(&["example.com"], &["*.proxied.com"]);
+        let (mut h, _client) = handler(ctx.clone());
+
+        let routing = |sni: &str| ConnectionState::Routing {
+            hello: Vec::new(),
+            sni: sni.to_string(),
+            protocol: Protocol::Tls,
+            alpn: Vec::new(),
+        };
+        assert!(matches!(
+            h.step(routing("example.com")).await,
+            ConnectionState::Connecting { route: Route::Direct, .. }
+        ));
+        assert!(matches!(
+            h.step(routing("www.proxied.com")).await,
+            ConnectionState::Connecting { route: Route::Socks5(_), .. }
+        ));
+        assert!(matches!(
+            h.step(routing("other.com")).await,
+            ConnectionState::Rejecting {
+                reason: CloseReason::DomainRejected,
+                alert: Some(ALERT_UNRECOGNIZED_NAME)
+            }
+        ));
+        assert_eq!(ctx.metrics.get_rejected_requests(), 1);
+
+        // 每个路由动作分别计入对应的标签组合
+        let series = ctx.metrics.series_snapshot();
+        let count = |route: RouteLabel| series.iter().find(|(labels, _)| labels.route == route).unwrap().1.clone();
+        assert_eq!(count(RouteLabel::Direct).direct_requests, 1);
+        assert_eq!(count(RouteLabel::Socks5).socks5_requests, 1);
+        assert_eq!(count(RouteLabel::Rejected).rejected_requests, 1);
+        assert_eq!(count(RouteLabel::Direct).rejected_requests, 0);
+    }
+
+    #[tokio::test]
+    async fn test_routing_default_backend() {
+        let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target_addr = target.local_addr().unwrap();
+
+        let (mut ctx, _tx) = test_context(&["example.com"], &[]);
+        ctx.default_backend = Some(Arc::new(BackendAddr {
+            host: "127.0.0.1".to_string(),
+            port: target_addr.port(),
+        }));
+        let (h, _client) = handler(ctx.clone());
+        // 透明代理的原始目标地址不影响默认后端
+        let mut h = h.with_original_dst(Some("192.0.2.1:443".parse().unwrap()));
+
+        let next = h
+            .step(ConnectionState::Routing {
+                hello: Vec::new(),
+                sni: "other.com".to_string(),
+                protocol: Protocol::Tls,
+                alpn: Vec::new(),
+            })
+            .await;
+        assert!(
+            matches!(next, ConnectionState::Connecting { route: Route::DefaultBackend, port, .. } if port == target_addr.port())
+        );
+        assert_eq!(ctx.metrics.get_rejected_requests(), 0);
+
+        assert!(matches!(h.step(next).await, ConnectionState::Relaying { .. }));
+        target.accept().await.unwrap();
+
+        // 访问记录包含解析后的目标地址
+        let record = h.access.as_ref().unwrap();
+        assert_eq!(record.target, Some(target_addr));
+        assert_eq!((record.route, record.upstream.as_str()), (RouteLabel::Fallback, "direct"));
+        assert_eq!(CloseReason::ConnectTimeout.to_string(), "connect_timeout");
+
+        // 关闭访问记录时不收集
+        ctx.access_log = false;
+        let (mut h, _client) = handler(ctx.clone());
+        let next = ConnectionState::Connecting {
+            hello: Vec::new(),
+            sni: "other.com".to_string(),
+            route: Route::DefaultBackend,
+            port: target_addr.port(),
+        };
+        assert!(matches!(h.step(next).await, ConnectionState::Relaying { .. }));
+        target.accept().await.unwrap();
+        assert!(h.access.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_routing_sni_backends() {
+        let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target_addr = target.local_addr().unwrap();
+
+        // 映射表优先于白名单，映射的域名无需在白名单中
+        let (mut ctx, _tx) = test_context(&["internal.example.com"], &[]);
+        ctx.sni_backends = Some(Arc::new(SniBackendMap::new([(
+            "*.example.com".to_string(),
+            Arc::new(BackendAddr { host: "127.0.0.1".to_string(), port: target_addr.port() }),
+        )])));
+        let (mut h, _client) = handler(ctx.clone());
+
+        let next = h
+            .step(ConnectionState::Routing {
+                hello: Vec::new(),
+                sni: "internal.example.com".to_string(),
+                protocol: Protocol::Tls,
+                alpn: Vec::new(),
+            })
+            .await;
+        assert!(
+            matches!(next, ConnectionState::Connecting { route: Route::Mapped(_), port, .. } if port == target_addr.port())
+        );
+        assert_eq!(ctx.metrics.snapshot().direct_requests, 1);
+
+        assert!(matches!(h.step(next).await, ConnectionState::Relaying { .. }));
+        target.accept().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_routing_rules() {
+        let (mut ctx, _tx) = test_context(&["*.example.com"], &[]);
+        let to_vec = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        // 显式规则排在白名单之前
+        let routes = (**ctx.routes.load()).clone().with_rules_first(vec![
+            RouteRule::new(RouteAction::Reject).with_domains(to_vec(&["admin.example.com"])),
+            RouteRule::new("socks5:eu".parse().unwrap())
+                .with_client_ips(to_vec(&["192.168.0.0/16"]))
+                .with_alpn(to_vec(&["h2"])),
+            RouteRule::new("backend:10.0.0.9:8443".parse().unwrap()).with_domains(to_vec(&["internal.example.org"])),
+        ]);
+        ctx.routes.store(Arc::new(routes));
+        let eu = Arc::new(Socks5Config { addr: "127.0.0.1:1080".parse().unwrap(), username: None, password: None });
+        ctx.socks5_upstreams = Arc::new(Socks5Upstreams::from([("eu".to_string(), Arc::clone(&eu))]));
+        let (mut h, _client) = handler(ctx.clone());
+        let mut route = |sni: &str, alpn: &[&str]| h.route(Vec::new(), sni.to_string(), Protocol::Tls, to_vec(alpn));
+
+        assert!(matches!(
+            route("admin.example.com", &["h2"]),
+            ConnectionState::Rejecting { reason: CloseReason::DomainRejected, .. }
+        ));
+        assert!(matches!(
+            route("www.example.com", &["h2"]),
+            ConnectionState::Connecting { route: Route::Socks5(Some(upstream)), .. } if upstream == eu
+        ));
+        assert!(matches!(
+            route("www.example.com", &["http/1.1"]),
+            ConnectionState::Connecting { route: Route::Direct, .. }
+        ));
+        assert!(matches!(
+            route("internal.example.org", &[]),
+            ConnectionState::Connecting { route: Route::Mapped(backend), port: 8443, .. } if backend.host == "10.0.0.9"
+        ));
+        assert_eq!(ctx.metrics.get_rejected_requests(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_acl() {
+        let to_vec = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let (mut ctx, _tx) = test_context(&["*.internal.example.com", "github.com"], &[]);
+        ctx.acl = Some(Arc::new(Acl::new(vec![
+            AclRule::new(AclAction::Allow)
+                .with_client_ips(to_vec(&["192.168.0.0/16"]))
+                .with_domains(to_vec(&["*.internal.example.com"])),
+            AclRule::new(AclAction::Allow).with_domains(to_vec(&["github.com"])),
+        ])));
+        let (mut h, _client) = handler(ctx.clone());
+        assert!(matches!(h.step(ConnectionState::Accepted).await, ConnectionState::ReadingHello));
+        assert!(matches!(
+            h.route(Vec::new(), "git.internal.example.com".to_string(), Protocol::Tls, Vec::new()),
+            ConnectionState::Connecting { route: Route::Direct, .. }
+        ));
+
+        // 其他客户端只能访问 github.com
+        let (client, server) = tokio::io::duplex(1024);
+        drop(client);
+        let mut h = ConnectionHandler::new(server, "10.0.0.1:50000".parse().unwrap(), 443, ctx.clone());
+        assert!(matches!(
+            h.route(Vec::new(), "git.internal.example.com".to_string(), Protocol::Tls, Vec::new()),
+            ConnectionState::Rejecting { reason: CloseReason::AclRejected, alert: Some(ALERT_ACCESS_DENIED) }
+        ));
+        assert!(matches!(
+            h.route(Vec::new(), "github.com".to_string(), Protocol::Tls, Vec::new()),
+            ConnectionState::Connecting { .. }
+        ));
+
+        // 没有任何允许规则的客户端在读取 Client Hello 之前拒绝
+        ctx.acl = Some(Arc::new(Acl::new(vec![AclRule::new(AclAction::Allow)
+            .with_client_ips(to_vec(&["10.0.0.0/8"]))])));
+        let (mut h, _client) = handler(ctx.clone());
+        assert!(matches!(
+            h.step(ConnectionState::Accepted).await,
+            ConnectionState::Rejecting { reason: CloseReason::AclRejected, .. }
+        ));
+        assert_eq!(ctx.metrics.get_rejected_requests(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_blacklist() {
+        let (mut ctx, _tx) = test_context(&["*.example.com"], &[]);
+        let blacklist = ["ads.example.com", "*.tracker.example.com"].map(String::from).to_vec();
+        ctx.blacklist = Some(Arc::new(DomainMatcher::new(blacklist)));
+        ctx.default_backend = Some(Arc::new("10.0.0.1:443".parse().unwrap()));
+        let mut events = ctx.events.subscribe();
+        let (mut h, _client) = handler(ctx.clone());
+        let mut route = |sni: &str| h.route(Vec::new(), sni.to_string(), Protocol::Tls, Vec::new());
+
+        assert!(matches!(route("www.example.com"), ConnectionState::Connecting { route: Route::Direct, .. }));
+        // 黑名单优先于白名单通配符和默认后端
+        for sni in ["ads.example.com", "ADS.example.com", "a.tracker.example.com"] {
+            assert!(matches!(
+                route(sni),
+                ConnectionState::Rejecting { reason: CloseReason::DomainRejected, alert: Some(ALERT_ACCESS_DENIED) }
+            ));
+        }
+        assert_eq!(ctx.metrics.get_rejected_requests(), 3);
+        assert!(matches!(
+            events.try_recv(),
+            Ok(ProxyEvent::Rejected { reason: RejectReason::DomainBlacklisted, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_routing_rule_port() {
+        let (ctx, _tx) = test_context(&["example.com:8443", "*.example.com"], &["*.example.org:9443"]);
+        let (mut h, _client) = handler(ctx);
+        let mut route = |sni: &str, protocol| {
+            h.route(Vec::new(), sni.to_string(), protocol, Vec::new())
+        };
+
+        assert!(matches!(route("example.com", Protocol::Tls), ConnectionState::Connecting { port: 8443, .. }));
+        assert!(matches!(route("www.example.com", Protocol::Tls), ConnectionState::Connecting { port: 443, .. }));
+        assert!(matches!(
+            route("www.example.org", Protocol::Tls),
+            ConnectionState::Connecting { route: Route::Socks5(_), port: 9443, .. }
+        ));
+        // HTTP 不使用规则端口
+        assert!(matches!(route("example.com", Protocol::Http), ConnectionState::Connecting { port: 80, .. }));
+    }
+
+    #[tokio::test]
+    async fn test_connecting_pinned_ips() {
+        let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target_addr = target.local_addr().unwrap();
+
+        // 域名无法解析，固定 IP 跳过 DNS
+        let (mut ctx, _tx) = test_context(&["*.invalid"], &[]);
+        ctx.pinned_ips = Some(Arc::new(PinnedIps::new([(
+            "pinned.invalid".to_string(),
+            Arc::from(vec![target_addr.ip()]),
+        )])));
+        let (mut h, _client) = handler(ctx);
+
+        let next = h
+            .step(ConnectionState::Connecting {
+                hello: Vec::new(),
+                sni: "pinned.invalid".to_string(),
+                route: Route::Direct,
+                port: target_addr.port(),
+            })
+            .await;
+        assert!(matches!(next, ConnectionState::Relaying { .. }));
+        target.accept().await.unwrap();
+
+        let ips: Vec<IpAddr> = vec!["192.0.2.1".parse().unwrap(), "192.0.2.2".parse().unwrap()];
+        h.ctx.pinned_ips = Some(Arc::new(PinnedIps::new([("*.example.com".to_string(), Arc::from(ips.clone()))])));
+        let picked = h.pinned_ips("www.example.com").unwrap();
+        assert_eq!(picked.len(), 2);
+        assert!(ips.contains(&picked[0]) && picked[0] != picked[1]);
+        assert_eq!(h.pinned_ips("www.example.com"), Some(picked));
+        assert_eq!(h.pinned_ips("example.com"), None);
+    }
+
+    #[tokio::test]
+    async fn test_routing_burst_backoff() {
+        let (mut ctx, _tx) = test_context(&["example.com"], &[]);
+        ctx.burst_limiter = Some(Arc::new(BurstLimiter::new(BurstConfig {
+            max_attempts: 2,
+            ..BurstConfig::default()
+        })));
+        let routing = || ConnectionState::Routing {
+            hello: Vec::new(),
+            sni: "example.com".to_string(),
+            protocol: Protocol::Tls,
+            alpn: Vec::new(),
+        };
+
+        for _ in 0..2 {
+            let (mut h, _client) = handler(ctx.clone());
+            assert!(matches!(h.step(routing()).await, ConnectionState::Connecting { .. }));
+        }
+        let (mut h, _client) = handler(ctx.clone());
+        assert!(matches!(h.step(routing()).await, ConnectionState::Closed(CloseReason::BurstThrottled)));
+        assert_eq!(ctx.metrics.snapshot().burst_rejections, 1);
+        // 退避不计入拒绝请求
+        assert_eq!(ctx.metrics.get_rejected_requests(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_routing_ip_literal_sni() {
+        let routing = |sni: &str| ConnectionState::Routing {
+            hello: Vec::new(),
+            sni: sni.to_string(),
+            protocol: Protocol::Tls,
+            alpn: Vec::new(),
+        };
+
+        // 拒绝：即使 IP 在域名白名单中也不放行，单独计数
+        let (mut ctx, _tx) = test_context(&["192.0.2.1"], &[]);
+        ctx.ip_sni_policy = Arc::new(IpSniPolicy::new(IpSniAction::Reject));
+        let (mut h, _client) = handler(ctx.clone());
+        let next = h.step(routing("192.0.2.1")).await;
+        assert!(matches!(next, ConnectionState::Rejecting { reason: CloseReason::IpSniRejected, .. }));
+        assert_eq!(ctx.metrics.snapshot().ip_sni_rejections, 1);
+
+        // 按 IP 白名单：IPv6 方括号形式规范化为 IP 地址后直连
+        ctx.ip_sni_policy = Arc::new(IpSniPolicy::new(IpSniAction::IpWhitelist));
+        ctx.ip_sni_matcher = Some(Arc::new(IpMatcher::new(vec!["2001:db8::/32".to_string()])));
+        let (mut h, _client) = handler(ctx.clone());
+        let next = h.step(routing("[2001:db8::1]")).await;
+        assert!(
+            matches!(next, ConnectionState::Connecting { route: Route::Direct, ref sni, port: 443, .. } if sni == "2001:db8::1")
+        );
+        let next = h.step(routing("192.0.2.1")).await;
+        assert!(matches!(next, ConnectionState::Rejecting { reason: CloseReason::IpSniRejected, .. }));
+        assert_eq!(ctx.metrics.snapshot().ip_sni_rejections, 2);
+
+        // 域名不受影响
+        let (mut h, _client) = handler(ctx.clone());
+        let next = h.step(routing("example.com")).await;
+        assert!(matches!(next, ConnectionState::Rejecting { reason: CloseReason::DomainRejected, .. }));
+    }
+
+    #[tokio::test]
+    async fn test_reading_hello_without_sni() {
+        let hello = crate::tls::tests::client_hello_without_sni();
+        let (mut ctx, _tx) = test_context(&["example.com"], &[]);
+
+        // 默认拒绝
+        let (mut h, mut client) = handler(ctx.clone());
+        client.write_all(&hello).await.unwrap();
+        let next = h.step(ConnectionState::ReadingHello).await;
+        assert!(matches!(next, ConnectionState::Closed(CloseReason::SniParseError)));
+
+        // 转发到固定后端（不经过域名白名单）
+        let backend: SocketAddr = "10.0.0.1:8443".parse().unwrap();
+        ctx.no_sni_action = NoSniAction::Backend(backend);
+        let (mut h, mut client) = handler(ctx.clone());
+        client.write_all(&hello).await.unwrap();
+        match h.step(ConnectionState::ReadingHello).await {
+            ConnectionState::Connecting { route, port, .. } => {
+                assert_eq!(route, Route::Fallback(backend));
+                assert_eq!(port, 8443);
+            }
+            other => panic!("unexpected state: {:?}", other),
+        }
+        assert_eq!(ctx.metrics.snapshot().sni_parse_errors, 1);
+
+        // original_dst：只有透明代理的连接有原始目标地址
+        ctx.no_sni_action = NoSniAction::OriginalDst;
+        let (h, mut client) = handler(ctx.clone());
+        let original_dst: SocketAddr = "192.0.2.1:443".parse().unwrap();
+        let mut h = h.with_original_dst(Some(original_dst));
+        client.write_all(&hello).await.unwrap();
+        assert!(matches!(
+            h.step(ConnectionState::ReadingHello).await,
+            ConnectionState::Connecting { route: Route::Fallback(dst), .. } if dst == original_dst
+        ));
+
+        let (mut h, mut client) = handler(ctx);
+        client.write_all(&hello).await.unwrap();
+        let next = h.step(ConnectionState::ReadingHello).await;
+        assert!(matches!(next, ConnectionState::Closed(CloseReason::SniParseError)));
+    }
+
+    #[tokio::test]
+    async fn test_rejection_modes() {
+        let rejecting = ConnectionState::Rejecting {
+            reason: CloseReason::DomainRejected,
+            alert: Some(ALERT_UNRECOGNIZED_NAME),
+        };
+
+        let (mut ctx, _tx) = test_context(&["example.com"], &[]);
+        ctx.rejection_mode = RejectionMode::Alert;
+        let (mut h, mut client) = handler(ctx.clone());
+        let next = h.step(rejecting).await;
+        assert!(matches!(next, ConnectionState::Closed(CloseReason::DomainRejected)));
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, tls_alert(ALERT_UNRECOGNIZED_NAME));
+
+        // 明文 HTTP 连接不发送 alert
+        let (mut h, mut client) = handler(ctx.clone());
+        let next = h
+            .step(ConnectionState::Rejecting { reason: CloseReason::DomainRejected, alert: None })
+            .await;
+        assert!(matches!(next, ConnectionState::Closed(CloseReason::DomainRejected)));
+        drop(h);
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).await.unwrap();
+        assert!(received.is_empty());
+
+        // tarpit：对方不断开时拖到超时为止
+        ctx.rejection_mode = RejectionMode::Tarpit(Duration::from_millis(100));
+        let (mut h, _client) = handler(ctx);
+        let start = Instant::now();
+        let next = h.step(ConnectionState::Rejecting { reason: CloseReason::IpRejected, alert: None }).await;
+        assert!(matches!(next, ConnectionState::Closed(CloseReason::IpRejected)));
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn test_connecting_and_relaying() {
+        let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target_addr = target.local_addr().unwrap();
+
+        let (ctx, _tx) = test_context(&["example.com"], &[]);
+        let (h, mut client) = handler(ctx);
+        // 使用原始目标地址，避免测试依赖 DNS
+        let mut h = h.with_original_dst(Some(target_addr));
+
+        let hello = client_hello("example.com");
+        let next = h
+            .step(ConnectionState::Connecting {
+                hello: hello.clone(),
+                sni: "example.com".to_string(),
+                route: Route::Direct,
+                port: target_addr.port(),
+            })
+            .await;
+        assert!(matches!(next, ConnectionState::Relaying { .. }));
+
+        let (mut upstream, _) = target.accept().await.unwrap();
+        client.write_all(b"after-hello").await.unwrap();
+        client.shutdown().await.unwrap();
+
+        let relay = tokio::spawn(async move { h.step(next).await });
+        let mut received = Vec::new();
+        upstream.read_to_end(&mut received).await.unwrap();
+        drop(upstream);
+
+        let mut expected = hello;
+        expected.extend_from_slice(b"after-hello");
+        assert_eq!(received, expected);
+        assert!(matches!(relay.await.unwrap(), ConnectionState::Closed(CloseReason::Completed)));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_relaying_with_splice() {
+        let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target_addr = target.local_addr().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (client, accepted) = tokio::join!(TcpStream::connect(listener.local_addr().unwrap()), listener.accept());
+        let mut client = client.unwrap();
+
+        let (mut ctx, _tx) = test_context(&["example.com"], &[]);
+        ctx.splice_relay = true;
+        let h = ConnectionHandler::new(accepted.unwrap().0, "192.168.1.10:50000".parse().unwrap(), 443, ctx.clone());
+        let mut h = h.with_original_dst(Some(target_addr));
+
+        let hello = client_hello("example.com");
+        let next = h
+            .step(ConnectionState::Connecting {
+                hello: hello.clone(),
+                sni: "example.com".to_string(),
+                route: Route::Direct,
+                port: target_addr.port(),
+            })
+            .await;
+        let (mut upstream, _) = target.accept().await.unwrap();
+        let relay = tokio::spawn(async move { h.step(next).await });
+        client.write_all(b"after-hello").await.unwrap();
+        client.shutdown().await.unwrap();
+
+        let mut received = Vec::new();
+        upstream.read_to_end(&mut received).await.unwrap();
+        drop(upstream);
+        let mut expected = hello;
+        expected.extend_from_slice(b"after-hello");
+        assert_eq!(received, expected);
+        assert!(matches!(relay.await.unwrap(), ConnectionState::Closed(CloseReason::Completed)));
+
+        let snapshot = ctx.metrics.snapshot();
+        assert_eq!((snapshot.splice_relays, snapshot.userspace_relays), (1, 0));
+        assert_eq!(snapshot.bytes_received, expected.len() as u64);
+    }
+
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    #[tokio::test]
+    async fn test_relaying_with_io_uring() {
+        use crate::listener::Accept;
+        use crate::uring::{UringAcceptor, UringRuntime};
+
+        let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target_addr = target.local_addr().unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let acceptor = UringAcceptor::new(listener).unwrap();
+        let (client, accepted) = tokio::join!(TcpStream::connect(addr), acceptor.accept());
+        let mut client = client.unwrap();
+
+        // io_uring 优先于 splice
+        let (mut ctx, _tx) = test_context(&["example.com"], &[]);
+        ctx.splice_relay = true;
+        ctx.uring = Some(Arc::new(UringRuntime::start(1).unwrap()));
+        let h = ConnectionHandler::new(accepted.unwrap().0, "192.168.1.10:50000".parse().unwrap(), 443, ctx.clone());
+        let mut h = h.with_original_dst(Some(target_addr));
+
+        let hello = client_hello("example.com");
+        let next = h
+            .step(ConnectionState::Connecting {
+                hello: hello.clone(),
+                sni: "example.com".to_string(),
+                route: Route::Direct,
+                port: target_addr.port(),
+            })
+            .await;
+        let (mut upstream, _) = target.accept().await.unwrap();
+        let relay = tokio::spawn(async move { h.step(next).await });
+        client.write_all(b"after-hello").await.unwrap();
+        client.shutdown().await.unwrap();
+
+        let mut received = Vec::new();
+        upstream.read_to_end(&mut received).await.unwrap();
+        drop(upstream);
+        let mut expected = hello;
+        expected.extend_from_slice(b"after-hello");
+        assert_eq!(received, expected);
+        assert!(matches!(relay.await.unwrap(), ConnectionState::Closed(CloseReason::Completed)));
+
+        let snapshot = ctx.metrics.snapshot();
+        assert_eq!((snapshot.uring_relays, snapshot.splice_relays, snapshot.userspace_relays), (1, 0, 0));
+        assert_eq!(snapshot.bytes_received, expected.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn test_connecting_with_proxy_protocol() {
+        let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target_addr = target.local_addr().unwrap();
+
+        let (mut ctx, _tx) = test_context(&["example.com"], &[]);
+        ctx.proxy_protocol_matcher = Some(Arc::new(DomainMatcher::new(vec!["example.com".to_string()])));
+        let (h, _client) = handler(ctx);
+        let mut h = h.with_original_dst(Some(target_addr));
+
+        let next = h
+            .step(ConnectionState::Connecting {
+                hello: Vec::new(),
+                sni: "example.com".to_string(),
+                route: Route::Direct,
+                port: target_addr.port(),
+            })
+            .await;
+        assert!(matches!(next, ConnectionState::Relaying { .. }));
+
+        let (mut upstream, _) = target.accept().await.unwrap();
+        let mut header = [0u8; 28];
+        upstream.read_exact(&mut header).await.unwrap();
+        assert_eq!(header.to_vec(), encode_v2_header(h.client_addr, target_addr));
+    }
+
+    #[tokio::test]
+    async fn test_connecting_refused() {
+        // 先绑定再关闭，得到一个（大概率）没有监听的端口
+        let addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+
+        let (ctx, _tx) = test_context(&["example.com"], &[]);
+        let mut events = ctx.events.subscribe();
+        let (h, _client) = handler(ctx);
+        let mut h = h.with_original_dst(Some(addr));
+
+        let next = h
+            .step(ConnectionState::Connecting {
+                hello: Vec::new(),
+                sni: "example.com".to_string(),
+                route: Route::Direct,
+                port: addr.port(),
+            })
+            .await;
+        assert!(matches!(next, ConnectionState::Closed(CloseReason::ConnectError)));
+        assert!(matches!(
+            events.try_recv().unwrap(),
+            ProxyEvent::UpstreamDown { host, port, via_socks5: false, .. } if host == "example.com" && port == addr.port()
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_connecting_direct_fallback_to_socks5() {
+        let addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let socks5 = Arc::new(Socks5Config { addr: listener.local_addr().unwrap(), username: None, password: None });
+
+        // 模拟 SOCKS5 服务器：接受无认证握手和连接请求
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut greeting = [0u8; 3];
+            stream.read_exact(&mut greeting).await.unwrap();
+            stream.write_all(&[5, 0]).await.unwrap();
+            let mut request = [0u8; 256];
+            let _ = stream.read(&mut request).await.unwrap();
+            stream.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).await.unwrap();
+            let _ = stream.read(&mut [0u8; 1]).await;
+        });
+
+        let (mut ctx, _tx) = test_context(&["example.com"], &[]);
+        ctx.socks5_upstreams =
+            Arc::new(Socks5Upstreams::from([(DEFAULT_SOCKS5_UPSTREAM.to_string(), Arc::clone(&socks5))]));
+        ctx.direct_fallback_to_socks5 = true;
+        let mut events = ctx.events.subscribe();
+        let (h, _client) = handler(ctx.clone());
+        let mut h = h.with_original_dst(Some(addr));
+
+        let next = h
+            .step(ConnectionState::Connecting {
+                hello: Vec::new(),
+                sni: "example.com".to_string(),
+                route: Route::Direct,
+                port: addr.port(),
+            })
+            .await;
+        assert!(matches!(next, ConnectionState::Relaying { .. }));
+        assert_eq!(ctx.metrics.snapshot().upstream_fallbacks, 1);
+        let record = h.access.as_ref().unwrap();
+        assert_eq!(record.upstream, format!("socks5 ({})", socks5.addr));
+        assert_eq!(record.target, None);
+        assert!(matches!(events.try_recv().unwrap(), ProxyEvent::UpstreamDown { via_socks5: false, .. }));
+    }
+
+    #[tokio::test]
+    async fn test_connecting_socks5_fallback_to_direct() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let proxy_addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let socks5 = Arc::new(Socks5Config { addr: proxy_addr, username: None, password: None });
+
+        let (mut ctx, _tx) = test_context(&[], &["example.com"]);
+        ctx.socks5_fallback_to_direct = true;
+        let mut events = ctx.events.subscribe();
+        let (h, _client) = handler(ctx.clone());
+        let mut h = h.with_original_dst(Some(addr));
+
+        // SOCKS5 代理服务器无法连接：改用直连
+        let next = h
+            .step(ConnectionState::Connecting {
+                hello: Vec::new(),
+                sni: "example.com".to_string(),
+                route: Route::Socks5(Some(socks5)),
+                port: addr.port(),
+            })
+            .await;
+        assert!(matches!(next, ConnectionState::Relaying { .. }));
+        assert_eq!(ctx.metrics.snapshot().upstream_fallbacks, 1);
+        let record = h.access.as_ref().unwrap();
+        assert_eq!((record.upstream.as_str(), record.target), ("direct", Some(addr)));
+        assert!(matches!(events.try_recv().unwrap(), ProxyEvent::UpstreamDown { via_socks5: true, .. }));
+    }
+
+    #[tokio::test]
+    async fn test_connecting_retry() {
+        let addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let (mut ctx, _tx) = test_context(&["example.com"], &[]);
+        ctx.connect_retry = Some(ConnectRetryConfig {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_millis(50),
+        });
+        let connecting = || ConnectionState::Connecting {
+            hello: Vec::new(),
+            sni: "example.com".to_string(),
+            route: Route::Direct,
+            port: addr.port(),
+        };
+
+        // 一直被拒绝：重试 2 次后失败
+        let (h, _client) = handler(ctx.clone());
+        let mut h = h.with_original_dst(Some(addr));
+        assert!(matches!(h.step(connecting()).await, ConnectionState::Closed(CloseReason::ConnectError)));
+        let snapshot = ctx.metrics.snapshot();
+        assert_eq!((snapshot.connect_retries, snapshot.connect_retry_failures), (2, 1));
+
+        // 目标服务器在重试期间恢复
+        let (h, _client) = handler(ctx.clone());
+        let mut h = h.with_original_dst(Some(addr));
+        let recover = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            TcpListener::bind(addr).await.unwrap()
+        });
+        assert!(matches!(h.step(connecting()).await, ConnectionState::Relaying { .. }));
+        recover.await.unwrap();
+        let snapshot = ctx.metrics.snapshot();
+        assert_eq!((snapshot.connect_retries, snapshot.connect_retry_failures), (3, 1));
+    }
+
+    #[tokio::test]
+    async fn test_rejected_events() {
+        let (ctx, _tx) = test_context(&["example.com"], &[]);
+        let mut events = ctx.events.subscribe();
+        let (mut h, mut client) = handler(ctx);
+        client.write_all(&client_hello("evil.com")).await.unwrap();
+
+        let mut state = ConnectionState::Accepted;
+        while !matches!(state, ConnectionState::Closed(_)) {
+            state = h.step(state).await;
+        }
+
+        assert!(matches!(events.try_recv().unwrap(), ProxyEvent::ConnectionOpened { .. }));
+        assert_eq!(
+            events.try_recv().unwrap(),
+            ProxyEvent::Rejected {
+                client_addr: h.client_addr,
+                host: Some("evil.com".to_string()),
+                reason: RejectReason::DomainNotAllowed,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_auto_ban() {
+        let (mut ctx, _tx) = test_context(&["example.com"], &[]);
+        ctx.ban_table = Some(Arc::new(BanTable::new(crate::ban::BanConfig {
+            window: Duration::from_secs(60),
+            max_failures: 2,
+            ban_duration: Duration::from_secs(60),
+        })));
+
+        // 两次被拒绝的连接后封禁，之后的连接不读取 Client Hello
+        for _ in 0..2 {
+            let (h, mut client) = handler(ctx.clone());
+            client.write_all(&client_hello("evil.com")).await.unwrap();
+            assert_eq!(h.run().await, CloseReason::DomainRejected);
+        }
+        let (mut h, _client) = handler(ctx.clone());
+        assert!(matches!(h.step(ConnectionState::Accepted).await, ConnectionState::Closed(CloseReason::IpBanned)));
+        assert_eq!(ctx.metrics.snapshot().ban_rejections, 1);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_cancels_handshake() {
+        let (ctx, shutdown_tx) = test_context(&["example.com"], &[]);
+        let (h, _client) = handler(ctx);
+
+        // 客户端不发送数据，连接停在 ReadingHello；关闭信号应立即结束连接
+        let run = tokio::spawn(h.run());
+        tokio::task::yield_now().await;
+        shutdown_tx.send(true).unwrap();
+        assert_eq!(run.await.unwrap(), CloseReason::Shutdown);
+    }
+}
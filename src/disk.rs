@@ -0,0 +1,185 @@
+//! 磁盘写满（ENOSPC）处理
+//!
+//! 统计文件、持久化文件、日志文件和每日报告在磁盘写满时不再每个周期都重试并刷屏：
+//! 第一次遇到 ENOSPC 时记录一条错误并暂停该子系统的写入，之后每隔 [`RETRY_INTERVAL`]
+//! 重试一次，写入成功后自动恢复。暂停中的子系统通过监控指标（`disk_full`）暴露
+
+use lazy_static::lazy_static;
+use log::{error, info};
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// 暂停期间的重试间隔
+pub const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 日志文件
+pub static LOG_FILE: DiskGuard = DiskGuard::new("日志文件", true);
+/// IP 流量统计输出文件
+pub static IP_TRAFFIC_OUTPUT: DiskGuard = DiskGuard::new("IP 流量统计文件", false);
+/// IP 流量持久化文件
+pub static IP_TRAFFIC_PERSISTENCE: DiskGuard = DiskGuard::new("IP 流量持久化文件", false);
+/// 域名-IP 映射文件
+pub static DOMAIN_IP_OUTPUT: DiskGuard = DiskGuard::new("域名-IP 映射文件", false);
+/// 每日报告
+pub static DAILY_REPORT: DiskGuard = DiskGuard::new("每日报告", false);
+/// InfluxDB 行协议导出文件
+pub static INFLUX_EXPORT: DiskGuard = DiskGuard::new("InfluxDB 导出文件", false);
+
+static GUARDS: [&DiskGuard; 6] = [
+    &LOG_FILE,
+    &IP_TRAFFIC_OUTPUT,
+    &IP_TRAFFIC_PERSISTENCE,
+    &DOMAIN_IP_OUTPUT,
+    &DAILY_REPORT,
+    &INFLUX_EXPORT,
+];
+
+/// 遇到磁盘写满的累计次数（不含暂停期间跳过的写入）
+static DISK_FULL_ERRORS: AtomicU64 = AtomicU64::new(0);
+
+lazy_static! {
+    static ref START: Instant = Instant::now();
+}
+
+/// 单个子系统的磁盘写满状态
+#[derive(Debug)]
+pub struct DiskGuard {
+    name: &'static str,
+    /// 通知写到 stderr 而不是日志（日志文件本身不能再通过日志报告）
+    stderr: bool,
+    suppressed: AtomicBool,
+    /// 下次重试的时间（进程启动后的毫秒数）
+    retry_at_ms: AtomicU64,
+}
+
+impl DiskGuard {
+    pub const fn new(name: &'static str, stderr: bool) -> Self {
+        Self {
+            name,
+            stderr,
+            suppressed: AtomicBool::new(false),
+            retry_at_ms: AtomicU64::new(0),
+        }
+    }
+
+    /// 子系统名称
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// 是否因磁盘写满暂停写入
+    pub fn is_suppressed(&self) -> bool {
+        self.suppressed.load(Ordering::Relaxed)
+    }
+
+    /// 执行一次写入
+    ///
+    /// 暂停期间未到重试时间时直接跳过；磁盘写满的错误在这里统一报告，不返回给调用方，
+    /// 其他错误照常返回
+    pub fn write(&self, write: impl FnOnce() -> io::Result<()>) -> io::Result<()> {
+        if self.is_suppressed() && now_ms() < self.retry_at_ms.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        match write() {
+            Ok(()) => {
+                if self.suppressed.swap(false, Ordering::Relaxed) {
+                    self.notify(false, format!("✅ {}恢复写入", self.name));
+                }
+                Ok(())
+            }
+            Err(e) if is_disk_full(&e) => {
+                DISK_FULL_ERRORS.fetch_add(1, Ordering::Relaxed);
+                self.retry_at_ms
+                    .store(now_ms() + RETRY_INTERVAL.as_millis() as u64, Ordering::Relaxed);
+                // 先标记暂停再报告：日志文件的报告可能重新进入日志器
+                if !self.suppressed.swap(true, Ordering::Relaxed) {
+                    self.notify(
+                        true,
+                        format!(
+                            "❌ 磁盘已满，暂停写入{}（每 {} 秒重试一次）: {}",
+                            self.name,
+                            RETRY_INTERVAL.as_secs(),
+                            e
+                        ),
+                    );
+                }
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn notify(&self, is_error: bool, message: String) {
+        if self.stderr {
+            eprintln!("{}", message);
+        } else if is_error {
+            error!("{}", message);
+        } else {
+            info!("{}", message);
+        }
+    }
+}
+
+/// 是否为磁盘写满错误（ENOSPC，或配额用尽 EDQUOT）
+pub fn is_disk_full(e: &io::Error) -> bool {
+    matches!(e.kind(), io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded)
+        || e.raw_os_error() == Some(libc::ENOSPC)
+}
+
+/// 因磁盘写满暂停写入的子系统
+pub fn suppressed_subsystems() -> Vec<&'static str> {
+    GUARDS
+        .iter()
+        .filter(|guard| guard.is_suppressed())
+        .map(|guard| guard.name())
+        .collect()
+}
+
+/// 遇到磁盘写满的累计次数
+pub fn disk_full_errors() -> u64 {
+    DISK_FULL_ERRORS.load(Ordering::Relaxed)
+}
+
+fn now_ms() -> u64 {
+    START.elapsed().as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guard_suppresses_and_recovers() {
+        let guard = DiskGuard::new("测试文件", false);
+        let attempts = std::cell::Cell::new(0);
+        let full = || {
+            attempts.set(attempts.get() + 1);
+            Err(io::Error::from_raw_os_error(libc::ENOSPC))
+        };
+
+        // 磁盘写满：不返回错误，进入暂停状态，重试时间之前的写入被跳过
+        assert!(guard.write(full).is_ok());
+        assert!(guard.is_suppressed());
+        assert!(guard.write(full).is_ok());
+        assert_eq!(attempts.get(), 1);
+
+        // 到达重试时间后写入成功：恢复
+        guard.retry_at_ms.store(0, Ordering::Relaxed);
+        assert!(guard.write(|| Ok(())).is_ok());
+        assert!(!guard.is_suppressed());
+
+        // 其他错误照常返回
+        let denied = guard.write(|| Err(io::Error::from(io::ErrorKind::PermissionDenied)));
+        assert!(denied.is_err());
+        assert!(!guard.is_suppressed());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_dev_full_is_disk_full() {
+        let err = std::fs::write("/dev/full", b"x").unwrap_err();
+        assert!(is_disk_full(&err));
+    }
+}
@@ -1,18 +1,345 @@
-use anyhow::Result;
+use hickory_resolver::config::{
+    NameServerConfig, NameServerConfigGroup, Protocol, ResolverConfig, ResolverOpts, TlsClientConfig,
+};
+use hickory_resolver::error::ResolveErrorKind;
+use hickory_resolver::{Name, TokioAsyncResolver};
 use lazy_static::lazy_static;
-use log::{debug, info};
+use log::{debug, info, warn};
 use lru::LruCache;
-use std::net::IpAddr;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::num::NonZeroUsize;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
 
+use crate::error::{Result, SniProxyError};
+pub use crate::spki_pin::SpkiPin;
+
+/// 默认 DNS 预取周期
+pub const DEFAULT_PREFETCH_INTERVAL: Duration = Duration::from_secs(300);
+
+/// 默认 DNS 预取并发数
+pub const DEFAULT_PREFETCH_CONCURRENCY: usize = 8;
+
+/// 默认 DNS 解析超时
+pub const DEFAULT_DNS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 自定义上游单次查询的默认超时
+pub const DEFAULT_UPSTREAM_QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// DNS-over-HTTPS 的查询路径（解析器只支持 RFC 8484 建议的路径）
+const DOH_PATH: &str = "/dns-query";
+
+/// 没有地址的结果（域名不存在或没有该地址族的记录）最长缓存时间，避免缓存不过期时永久记住临时的解析结果
+const NEGATIVE_CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// DNS 缓存命中次数（全局）
+static DNS_CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+/// DNS 缓存未命中次数（全局）
+static DNS_CACHE_MISSES: AtomicU64 = AtomicU64::new(0);
+/// DNS 解析超时次数（全局）
+static DNS_TIMEOUTS: AtomicU64 = AtomicU64::new(0);
+/// DNS 解析失败次数（全局，不含超时）
+static DNS_FAILURES: AtomicU64 = AtomicU64::new(0);
+/// 刷新失败后使用过期缓存的次数（全局）
+static DNS_STALE_SERVED: AtomicU64 = AtomicU64::new(0);
+
+/// 地址族
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum IpFamily {
+    V4,
+    V6,
+}
+
+impl IpFamily {
+    fn af(self) -> libc::c_int {
+        match self {
+            IpFamily::V4 => libc::AF_INET,
+            IpFamily::V6 => libc::AF_INET6,
+        }
+    }
+
+    fn matches(self, ip: &IpAddr) -> bool {
+        match self {
+            IpFamily::V4 => ip.is_ipv4(),
+            IpFamily::V6 => ip.is_ipv6(),
+        }
+    }
+}
+
+impl std::fmt::Display for IpFamily {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IpFamily::V4 => write!(f, "A"),
+            IpFamily::V6 => write!(f, "AAAA"),
+        }
+    }
+}
+
+/// 地址族偏好：决定查询哪些地址族，以及解析结果中地址的顺序（连接使用第一个地址）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IpFamilyPreference {
+    /// IPv6 地址在前（默认，与系统解析器在有 IPv6 地址的主机上的排序一致）
+    #[default]
+    Ipv6First,
+    /// IPv4 地址在前
+    Ipv4First,
+    /// 只查询 IPv4
+    Ipv4Only,
+    /// 只查询 IPv6
+    Ipv6Only,
+}
+
+impl IpFamilyPreference {
+    /// 按顺序需要查询的地址族
+    fn families(self) -> (IpFamily, Option<IpFamily>) {
+        match self {
+            IpFamilyPreference::Ipv6First => (IpFamily::V6, Some(IpFamily::V4)),
+            IpFamilyPreference::Ipv4First => (IpFamily::V4, Some(IpFamily::V6)),
+            IpFamilyPreference::Ipv4Only => (IpFamily::V4, None),
+            IpFamilyPreference::Ipv6Only => (IpFamily::V6, None),
+        }
+    }
+}
+
+impl std::str::FromStr for IpFamilyPreference {
+    type Err = SniProxyError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "ipv6_first" => Ok(IpFamilyPreference::Ipv6First),
+            "ipv4_first" => Ok(IpFamilyPreference::Ipv4First),
+            "ipv4_only" => Ok(IpFamilyPreference::Ipv4Only),
+            "ipv6_only" => Ok(IpFamilyPreference::Ipv6Only),
+            _ => Err(SniProxyError::InvalidConfig(format!(
+                "无效的地址族偏好: {}（可选: ipv6_first, ipv4_first, ipv4_only, ipv6_only）",
+                s
+            ))),
+        }
+    }
+}
+
+impl std::fmt::Display for IpFamilyPreference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IpFamilyPreference::Ipv6First => write!(f, "ipv6_first"),
+            IpFamilyPreference::Ipv4First => write!(f, "ipv4_first"),
+            IpFamilyPreference::Ipv4Only => write!(f, "ipv4_only"),
+            IpFamilyPreference::Ipv6Only => write!(f, "ipv6_only"),
+        }
+    }
+}
+
+/// DNS 解析策略
+///
+/// IPv4（A）和 IPv6（AAAA）并行查询，分别缓存、分别过期和刷新，其中一个地址族超时或失败不影响另一个。
+/// 缓存条目默认永不过期（与之前的行为一致）；设置 `cache_ttl` 后，过期条目在下次使用时重新解析，
+/// 重新解析超时或失败时，`serve_stale` 为 true 则继续使用过期的结果，否则返回错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DnsOptions {
+    /// 单次解析的超时时间（每个地址族分别计时）
+    pub timeout: Duration,
+    /// 缓存条目的有效期，None 表示永不过期
+    pub cache_ttl: Option<Duration>,
+    /// 重新解析失败时是否使用过期的缓存结果
+    pub serve_stale: bool,
+    /// 地址族偏好
+    pub family: IpFamilyPreference,
+}
+
+impl Default for DnsOptions {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_DNS_TIMEOUT,
+            cache_ttl: None,
+            serve_stale: true,
+            family: IpFamilyPreference::default(),
+        }
+    }
+}
+
+/// 自定义 DNS 上游
+///
+/// 代理主机上的 /etc/resolv.conf 经常指向很慢或不可用的服务器（或者只能由运维统一修改），
+/// 配置后直接向这里列出的服务器查询，不再使用系统解析器。/etc/hosts 仍然生效；
+/// 系统解析器的 AI_ADDRCONFIG 不适用，不需要的地址族通过地址族偏好关闭
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DnsUpstream {
+    /// DNS 服务器地址，按顺序使用
+    pub nameservers: Vec<SocketAddr>,
+    /// 搜索域：不含点的域名依次加上这些后缀查询
+    pub search: Vec<String>,
+    /// 向单个服务器查询一次的超时时间
+    pub query_timeout: Duration,
+    /// 每个服务器的查询次数（包括第一次）
+    pub attempts: usize,
+    /// 传输协议
+    pub transport: DnsTransport,
+}
+
+/// DNS 上游的传输协议
+///
+/// DoH 和 DoT 的查询经过加密，本地网络无法观察或篡改；两者的 `tls_name` 用于 SNI 和证书校验
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum DnsTransport {
+    /// UDP，响应被截断时改用 TCP
+    #[default]
+    Plain,
+    /// DNS-over-HTTPS（RFC 8484）
+    Https { tls_name: String },
+    /// DNS-over-TLS（RFC 7858）。`spki_pins` 非空时按公钥指纹认证服务器（不再按 CA 校验证书链），
+    /// 服务器证书的公钥匹配其中一个指纹即可
+    Tls { tls_name: String, spki_pins: Vec<SpkiPin> },
+}
+
+impl DnsUpstream {
+    /// 使用默认超时和查询次数
+    pub fn new(nameservers: Vec<SocketAddr>) -> Self {
+        Self {
+            nameservers,
+            search: Vec::new(),
+            query_timeout: DEFAULT_UPSTREAM_QUERY_TIMEOUT,
+            attempts: 2,
+            transport: DnsTransport::Plain,
+        }
+    }
+
+    /// DNS-over-HTTPS 上游，例如 `https://dns.google/dns-query`
+    ///
+    /// 查询 DoH 服务器本身的地址不能依赖被替代的解析器，`bootstrap` 给出服务器的 IP 地址；
+    /// URL 中的主机是 IP 地址时可以不提供
+    pub fn doh(url: &str, bootstrap: &[IpAddr]) -> Result<Self> {
+        let (host, port) = parse_doh_url(url)?;
+        let bootstrap = match crate::ip_sni::parse_ip_literal(&host) {
+            Some(ip) if bootstrap.is_empty() => vec![ip],
+            _ if bootstrap.is_empty() => {
+                return Err(SniProxyError::InvalidConfig(format!("DoH 服务器 {} 需要配置 bootstrap IP 地址", host)));
+            }
+            _ => bootstrap.to_vec(),
+        };
+        let nameservers = bootstrap.into_iter().map(|ip| SocketAddr::new(ip, port)).collect();
+        Ok(Self { transport: DnsTransport::Https { tls_name: host }, ..Self::new(nameservers) })
+    }
+
+    /// DNS-over-TLS 上游，`servers` 是服务器地址（通常是 853 端口），`tls_name` 是服务器证书中的名称
+    pub fn dot(servers: Vec<SocketAddr>, tls_name: &str, spki_pins: Vec<SpkiPin>) -> Result<Self> {
+        if tls_name.is_empty() {
+            return Err(SniProxyError::InvalidConfig("DoT 服务器需要配置 TLS 名称".to_string()));
+        }
+        let transport = DnsTransport::Tls { tls_name: tls_name.to_string(), spki_pins };
+        Ok(Self { transport, ..Self::new(servers) })
+    }
+
+    /// 创建解析器（结果由 DNS 缓存统一缓存，解析器本身不缓存）
+    fn build_resolver(&self) -> Result<TokioAsyncResolver> {
+        if self.nameservers.is_empty() {
+            return Err(SniProxyError::InvalidConfig("DNS 服务器列表不能为空".to_string()));
+        }
+        let search = self
+            .search
+            .iter()
+            .map(|domain| {
+                Name::from_str(domain)
+                    .map_err(|e| SniProxyError::InvalidConfig(format!("无效的搜索域 {}: {}", domain, e)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut group = NameServerConfigGroup::with_capacity(self.nameservers.len() * 2);
+        for addr in &self.nameservers {
+            match &self.transport {
+                DnsTransport::Plain => {
+                    group.push(NameServerConfig::new(*addr, Protocol::Udp));
+                    group.push(NameServerConfig::new(*addr, Protocol::Tcp));
+                }
+                DnsTransport::Https { tls_name } => {
+                    let mut server = NameServerConfig::new(*addr, Protocol::Https);
+                    server.tls_dns_name = Some(tls_name.clone());
+                    group.push(server);
+                }
+                DnsTransport::Tls { tls_name, spki_pins } => {
+                    let mut server = NameServerConfig::new(*addr, Protocol::Tls);
+                    server.tls_dns_name = Some(tls_name.clone());
+                    if !spki_pins.is_empty() {
+                        server.tls_config = Some(TlsClientConfig(crate::spki_pin::client_config(spki_pins.clone())));
+                    }
+                    group.push(server);
+                }
+            }
+        }
+        let mut opts = ResolverOpts::default();
+        opts.timeout = self.query_timeout;
+        opts.attempts = self.attempts.max(1);
+        opts.cache_size = 0;
+        Ok(TokioAsyncResolver::tokio(ResolverConfig::from_parts(None, search, group), opts))
+    }
+}
+
+impl std::fmt::Display for DnsTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DnsTransport::Plain => write!(f, "UDP/TCP"),
+            DnsTransport::Https { tls_name } => write!(f, "DNS-over-HTTPS {}", tls_name),
+            DnsTransport::Tls { tls_name, spki_pins } if spki_pins.is_empty() => write!(f, "DNS-over-TLS {}", tls_name),
+            DnsTransport::Tls { tls_name, spki_pins } => {
+                write!(f, "DNS-over-TLS {}，{} 个公钥指纹", tls_name, spki_pins.len())
+            }
+        }
+    }
+}
+
+/// 解析 DoH URL，返回主机和端口（默认 443）
+fn parse_doh_url(url: &str) -> Result<(String, u16)> {
+    let invalid = |reason: &str| SniProxyError::InvalidConfig(format!("无效的 DoH URL {}: {}", url, reason));
+    let rest = url.strip_prefix("https://").ok_or_else(|| invalid("必须以 https:// 开头"))?;
+    let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    if path != DOH_PATH {
+        return Err(invalid("路径必须是 /dns-query"));
+    }
+    let (host, port) = match authority.rsplit_once(':') {
+        // IPv6 地址必须带方括号：[2001:db8::1]:443
+        Some((host, port)) if !host.starts_with('[') || host.ends_with(']') => {
+            (host, port.parse::<u16>().map_err(|_| invalid("无效的端口"))?)
+        }
+        _ => (authority, 443),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        return Err(invalid("缺少主机"));
+    }
+    Ok((host.to_string(), port))
+}
+
+/// 缓存条目（一个域名的一个地址族）
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    ips: Vec<IpAddr>,
+    resolved_at: Instant,
+}
+
+impl CacheEntry {
+    fn is_expired(&self, ttl: Option<Duration>) -> bool {
+        let ttl = if self.ips.is_empty() {
+            Some(ttl.map_or(NEGATIVE_CACHE_TTL, |ttl| ttl.min(NEGATIVE_CACHE_TTL)))
+        } else {
+            ttl
+        };
+        ttl.is_some_and(|ttl| self.resolved_at.elapsed() >= ttl)
+    }
+}
+
 lazy_static! {
     // 🚀 自适应 DNS 缓存大小：根据 CPU 核心数调整
     // 小型服务器（1-2核）：500 条
     // 中型服务器（4-8核）：1000 条
     // 大型服务器（16+核）：2000 条
-    static ref DNS_CACHE: Mutex<LruCache<String, Vec<IpAddr>>> = {
-        let num_cpus = num_cpus::get();
+    // 每个域名的 IPv4 和 IPv6 结果分别占一条
+    static ref DNS_CACHE: Mutex<LruCache<(String, IpFamily), CacheEntry>> = {
+        let num_cpus = crate::platform::available_cpus();
         let cache_size = if num_cpus <= 2 {
             500
         } else if num_cpus <= 8 {
@@ -22,39 +349,464 @@ lazy_static! {
         };
         Mutex::new(LruCache::new(NonZeroUsize::new(cache_size).unwrap()))
     };
+
+    static ref DNS_OPTIONS: std::sync::RwLock<DnsOptions> = std::sync::RwLock::new(DnsOptions::default());
+
+    /// 自定义上游的解析器，None 表示使用系统解析器
+    static ref DNS_RESOLVER: std::sync::RwLock<Option<Arc<TokioAsyncResolver>>> = std::sync::RwLock::new(None);
+
+    /// 按域名后缀选择解析器（按后缀长度从长到短排列）
+    static ref DNS_ROUTES: std::sync::RwLock<Vec<ResolverRoute>> = std::sync::RwLock::new(Vec::new());
+
+    /// 静态解析（键是小写、去掉末尾点的域名）
+    static ref DNS_HOSTS: std::sync::RwLock<HashMap<String, Vec<IpAddr>>> = std::sync::RwLock::new(HashMap::new());
+}
+
+/// 设置 DNS 解析策略（全局，在服务器启动时生效）
+pub fn set_dns_options(options: DnsOptions) {
+    *DNS_OPTIONS.write().unwrap_or_else(|e| e.into_inner()) = options;
+    debug!("DNS 解析策略: {:?}", options);
+}
+
+/// 当前的 DNS 解析策略
+pub fn dns_options() -> DnsOptions {
+    *DNS_OPTIONS.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// 设置自定义 DNS 上游（全局，在服务器启动时生效），None 表示使用系统解析器
+pub fn set_dns_upstream(upstream: Option<&DnsUpstream>) -> Result<()> {
+    let resolver = upstream.map(DnsUpstream::build_resolver).transpose()?.map(Arc::new);
+    *DNS_RESOLVER.write().unwrap_or_else(|e| e.into_inner()) = resolver;
+    debug!("DNS 上游: {:?}", upstream);
+    Ok(())
+}
+
+/// 按域名选择 DNS 上游的规则
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DnsRoute {
+    /// 域名后缀：`corp.example` 匹配该域名和所有子域名，`*.corp.example` 只匹配子域名
+    pub domains: Vec<String>,
+    /// 匹配的域名使用的上游，None 表示使用系统解析器
+    pub upstream: Option<DnsUpstream>,
+}
+
+impl DnsRoute {
+    /// 检查域名后缀的格式
+    pub fn new(domains: Vec<String>, upstream: Option<DnsUpstream>) -> Result<Self> {
+        if domains.is_empty() {
+            return Err(SniProxyError::InvalidConfig("DNS 路由的域名列表不能为空".to_string()));
+        }
+        for domain in &domains {
+            parse_route_domain(domain)?;
+        }
+        Ok(Self { domains, upstream })
+    }
+}
+
+/// 解析路由域名，返回后缀和是否只匹配子域名
+fn parse_route_domain(domain: &str) -> Result<(String, bool)> {
+    let pattern = normalize_host(domain);
+    let (suffix, subdomains_only) = match pattern.strip_prefix("*.") {
+        Some(suffix) => (suffix.to_string(), true),
+        None => (pattern.clone(), false),
+    };
+    if suffix.is_empty() || suffix.contains('*') {
+        return Err(SniProxyError::InvalidConfig(format!("无效的 DNS 路由域名: {:?}", domain)));
+    }
+    Ok((suffix, subdomains_only))
+}
+
+/// 解析器路由表中的一条（每个域名后缀一条）
+struct ResolverRoute {
+    /// 小写、不含 `*.` 前缀和末尾点的后缀
+    suffix: String,
+    subdomains_only: bool,
+    resolver: Option<Arc<TokioAsyncResolver>>,
+}
+
+impl ResolverRoute {
+    /// `host` 已经是小写、去掉末尾点的域名
+    fn matches(&self, host: &str) -> bool {
+        match host.strip_suffix(self.suffix.as_str()) {
+            Some("") => !self.subdomains_only,
+            Some(prefix) => prefix.ends_with('.'),
+            None => false,
+        }
+    }
+}
+
+/// 设置按域名选择的 DNS 上游（全局，在服务器启动时生效）
+///
+/// 域名匹配多条规则时使用后缀最长的一条，没有匹配的域名使用 [`set_dns_upstream`] 设置的上游（或系统解析器）
+pub fn set_dns_routes(routes: &[DnsRoute]) -> Result<()> {
+    let mut table = Vec::new();
+    for route in routes {
+        let resolver = route.upstream.as_ref().map(DnsUpstream::build_resolver).transpose()?.map(Arc::new);
+        for domain in &route.domains {
+            let (suffix, subdomains_only) = parse_route_domain(domain)?;
+            table.push(ResolverRoute { suffix, subdomains_only, resolver: resolver.clone() });
+        }
+    }
+    // 稳定排序：后缀长度相同时保持配置顺序
+    table.sort_by_key(|route| std::cmp::Reverse(route.suffix.len()));
+    *DNS_ROUTES.write().unwrap_or_else(|e| e.into_inner()) = table;
+    debug!("DNS 路由: {:?}", routes);
+    Ok(())
+}
+
+fn find_route<'a>(routes: &'a [ResolverRoute], host: &str) -> Option<&'a ResolverRoute> {
+    if routes.is_empty() {
+        return None;
+    }
+    let host = normalize_host(host);
+    routes.iter().find(|route| route.matches(&host))
+}
+
+/// 域名使用的解析器，None 表示使用系统解析器
+fn dns_resolver(host: &str) -> Option<Arc<TokioAsyncResolver>> {
+    if let Some(route) = find_route(&DNS_ROUTES.read().unwrap_or_else(|e| e.into_inner()), host) {
+        return route.resolver.clone();
+    }
+    DNS_RESOLVER.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// 设置静态解析（全局，在服务器启动时生效）：这些域名直接使用配置的地址，不查询 DNS、不写入缓存，
+/// 用于内外网解析不同（split-horizon）或者固定使用某个 CDN 节点。域名不区分大小写
+pub fn set_dns_hosts(hosts: HashMap<String, Vec<IpAddr>>) {
+    let hosts: HashMap<_, _> = hosts.into_iter().map(|(host, ips)| (normalize_host(&host), ips)).collect();
+    debug!("DNS 静态解析: {:?}", hosts);
+    *DNS_HOSTS.write().unwrap_or_else(|e| e.into_inner()) = hosts;
+}
+
+fn normalize_host(host: &str) -> String {
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// 查询静态解析，地址按地址族偏好过滤和排序；没有配置该域名时返回 None
+fn static_lookup(hosts: &HashMap<String, Vec<IpAddr>>, host: &str, family: IpFamilyPreference) -> Option<Vec<IpAddr>> {
+    if hosts.is_empty() {
+        return None;
+    }
+    let ips = hosts.get(&normalize_host(host))?;
+    let (first, second) = family.families();
+    let ordered = [Some(first), second]
+        .into_iter()
+        .flatten()
+        .flat_map(|family| ips.iter().copied().filter(move |ip| family.matches(ip)))
+        .collect();
+    Some(ordered)
 }
 
 /// 带缓存的 DNS 解析
+///
+/// IPv4 和 IPv6 并行查询，按地址族偏好排序后合并；只要有一个地址族解析出地址就返回成功。
+/// 解析超时后放弃等待并返回 [`SniProxyError::Timeout`]：系统解析器（getaddrinfo）在阻塞线程池中运行，
+/// 无法中断，超时后它的结果会被丢弃，不写入缓存。配置了自定义上游（[`set_dns_upstream`]）时直接向上游查询；
+/// 静态解析（[`set_dns_hosts`]）中的域名优先，不查询 DNS；按域名选择上游见 [`set_dns_routes`]
 pub async fn resolve_host_cached(host: &str) -> Result<Vec<IpAddr>> {
-    // 1. 检查缓存
-    {
-        let mut cache = DNS_CACHE.lock().await;
-        if let Some(ips) = cache.get(host) {
-            debug!("DNS 缓存命中: {} -> {:?}", host, ips);
-            return Ok(ips.clone());
+    let (result, source) = resolve_host(host, dns_options()).await;
+    match source {
+        ResolveSource::Cache => DNS_CACHE_HITS.fetch_add(1, Ordering::Relaxed),
+        ResolveSource::Lookup => DNS_CACHE_MISSES.fetch_add(1, Ordering::Relaxed),
+        ResolveSource::Static => 0,
+    };
+    result
+}
+
+/// 解析结果的来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResolveSource {
+    /// IP 地址或静态解析，不经过缓存
+    Static,
+    /// 都来自未过期的缓存
+    Cache,
+    /// 至少一个地址族重新查询
+    Lookup,
+}
+
+/// 解析域名（不计入缓存命中统计）
+async fn resolve_host(host: &str, options: DnsOptions) -> (Result<Vec<IpAddr>>, ResolveSource) {
+    // IP 地址不需要解析
+    if let Some(ip) = crate::ip_sni::parse_ip_literal(host) {
+        return (Ok(vec![ip]), ResolveSource::Static);
+    }
+    let overridden = static_lookup(&DNS_HOSTS.read().unwrap_or_else(|e| e.into_inner()), host, options.family);
+    if let Some(ips) = overridden {
+        debug!("DNS 静态解析: {} -> {:?}", host, ips);
+        let result = if ips.is_empty() { Err(SniProxyError::DnsEmpty(host.to_string())) } else { Ok(ips) };
+        return (result, ResolveSource::Static);
+    }
+    let (result, cached) = match dns_resolver(host) {
+        Some(resolver) => {
+            resolve_with(host, options, |host, family| upstream_lookup(Arc::clone(&resolver), host, family)).await
         }
+        None => resolve_with(host, options, system_lookup).await,
+    };
+    (result, if cached { ResolveSource::Cache } else { ResolveSource::Lookup })
+}
+
+/// 向自定义上游查询一个地址族，域名不存在或没有该地址族的记录时返回空列表
+async fn upstream_lookup(resolver: Arc<TokioAsyncResolver>, host: String, family: IpFamily) -> Result<Vec<IpAddr>> {
+    let result = match family {
+        IpFamily::V4 => resolver
+            .ipv4_lookup(host.as_str())
+            .await
+            .map(|lookup| lookup.iter().map(|a| IpAddr::V4(a.0)).collect()),
+        IpFamily::V6 => resolver
+            .ipv6_lookup(host.as_str())
+            .await
+            .map(|lookup| lookup.iter().map(|aaaa| IpAddr::V6(aaaa.0)).collect()),
+    };
+    match result {
+        Ok(ips) => Ok(ips),
+        Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => Ok(Vec::new()),
+        Err(e) => Err(SniProxyError::DnsResolve { host, source: io::Error::other(e) }),
+    }
+}
+
+/// 使用系统解析器查询一个地址族（在阻塞线程池中运行）
+async fn system_lookup(host: String, family: IpFamily) -> Result<Vec<IpAddr>> {
+    let name = host.clone();
+    match tokio::task::spawn_blocking(move || getaddrinfo(&name, family)).await {
+        Ok(result) => result.map_err(|source| SniProxyError::DnsResolve { host, source }),
+        Err(e) => Err(SniProxyError::DnsResolve { host, source: io::Error::other(e) }),
     }
+}
 
-    // 2. 执行 DNS 查询
-    debug!("DNS 查询: {}", host);
-    let addr_str = format!("{}:443", host);
-    let ips: Vec<IpAddr> = tokio::net::lookup_host(&addr_str)
-        .await?
-        .map(|addr| addr.ip())
-        .collect();
+/// glibc 的 EAI_ADDRFAMILY（libc crate 未导出）：域名没有该地址族的地址
+#[cfg(target_os = "linux")]
+const EAI_ADDRFAMILY: libc::c_int = -9;
 
-    if ips.is_empty() {
-        return Err(anyhow::anyhow!("DNS 查询返回空列表: {}", host));
+/// 调用 getaddrinfo 查询一个地址族
+///
+/// 使用 AI_ADDRCONFIG：本机没有配置该地址族的地址时不查询（例如没有 IPv6 的主机不发 AAAA 查询）。
+/// 域名不存在或没有该地址族的记录时返回空列表
+fn getaddrinfo(host: &str, family: IpFamily) -> io::Result<Vec<IpAddr>> {
+    let name = CString::new(host).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "域名包含 NUL 字符"))?;
+    // SAFETY: addrinfo 是普通 C 结构体，全零是合法的 hints
+    let mut hints: libc::addrinfo = unsafe { std::mem::zeroed() };
+    hints.ai_family = family.af();
+    hints.ai_socktype = libc::SOCK_STREAM;
+    hints.ai_flags = libc::AI_ADDRCONFIG;
+
+    let mut list: *mut libc::addrinfo = std::ptr::null_mut();
+    // SAFETY: name 和 hints 在调用期间有效，成功时 list 由下面的 freeaddrinfo 释放
+    let code = unsafe { libc::getaddrinfo(name.as_ptr(), std::ptr::null(), &hints, &mut list) };
+    if code != 0 {
+        #[cfg(target_os = "linux")]
+        if code == EAI_ADDRFAMILY {
+            return Ok(Vec::new());
+        }
+        return match code {
+            libc::EAI_NONAME | libc::EAI_NODATA => Ok(Vec::new()),
+            libc::EAI_SYSTEM => Err(io::Error::last_os_error()),
+            _ => {
+                // SAFETY: gai_strerror 返回静态字符串
+                let message = unsafe { CStr::from_ptr(libc::gai_strerror(code)) };
+                Err(io::Error::other(message.to_string_lossy().into_owned()))
+            }
+        };
     }
 
-    // 3. 缓存结果
-    {
+    let mut ips = Vec::new();
+    let mut cursor = list;
+    while !cursor.is_null() {
+        // SAFETY: cursor 指向 getaddrinfo 返回的链表节点，ai_addr 的实际类型由 ai_family 决定
+        let entry = unsafe { &*cursor };
+        let ip = match entry.ai_family {
+            libc::AF_INET if !entry.ai_addr.is_null() => {
+                let addr = unsafe { &*(entry.ai_addr as *const libc::sockaddr_in) };
+                Some(IpAddr::V4(Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr))))
+            }
+            libc::AF_INET6 if !entry.ai_addr.is_null() => {
+                let addr = unsafe { &*(entry.ai_addr as *const libc::sockaddr_in6) };
+                Some(IpAddr::V6(Ipv6Addr::from(addr.sin6_addr.s6_addr)))
+            }
+            _ => None,
+        };
+        if let Some(ip) = ip.filter(|ip| !ips.contains(ip)) {
+            ips.push(ip);
+        }
+        cursor = entry.ai_next;
+    }
+    // SAFETY: list 由成功的 getaddrinfo 分配，只释放一次
+    unsafe { libc::freeaddrinfo(list) };
+    Ok(ips)
+}
+
+/// 按地址族偏好解析，返回结果和是否都来自未过期的缓存
+async fn resolve_with<F, Fut>(host: &str, options: DnsOptions, lookup: F) -> (Result<Vec<IpAddr>>, bool)
+where
+    F: Fn(String, IpFamily) -> Fut,
+    Fut: Future<Output = Result<Vec<IpAddr>>>,
+{
+    // 1. 按地址族并行查询（各自检查缓存）
+    let results = match options.family.families() {
+        (first, Some(second)) => {
+            let (first, second) = tokio::join!(
+                resolve_family(host, first, options, &lookup),
+                resolve_family(host, second, options, &lookup)
+            );
+            vec![first, second]
+        }
+        (first, None) => vec![resolve_family(host, first, options, &lookup).await],
+    };
+
+    // 2. 按偏好顺序合并，有一个地址族成功即可
+    let mut ips = Vec::new();
+    let mut error = None;
+    let mut all_cached = true;
+    for (result, cached) in results {
+        all_cached &= cached;
+        match result {
+            Ok(found) => ips.extend(found),
+            Err(e) => {
+                debug!("DNS 查询部分失败 {}: {}", host, e);
+                error.get_or_insert(e);
+            }
+        }
+    }
+    if !ips.is_empty() {
+        return (Ok(ips), all_cached);
+    }
+    (Err(error.unwrap_or_else(|| SniProxyError::DnsEmpty(host.to_string()))), all_cached)
+}
+
+/// 解析一个地址族，返回结果和是否来自未过期的缓存
+async fn resolve_family<F, Fut>(host: &str, family: IpFamily, options: DnsOptions, lookup: &F) -> (Result<Vec<IpAddr>>, bool)
+where
+    F: Fn(String, IpFamily) -> Fut,
+    Fut: Future<Output = Result<Vec<IpAddr>>>,
+{
+    let key = (host.to_string(), family);
+
+    // 1. 检查缓存（过期条目保留下来，刷新失败时使用）
+    let stale = {
         let mut cache = DNS_CACHE.lock().await;
-        cache.put(host.to_string(), ips.clone());
-        debug!("DNS 缓存写入: {} -> {:?}", host, ips);
+        match cache.get(&key) {
+            Some(entry) if !entry.is_expired(options.cache_ttl) => {
+                debug!("DNS 缓存命中: {} ({}) -> {:?}", host, family, entry.ips);
+                return (Ok(entry.ips.clone()), true);
+            }
+            Some(entry) => Some(entry.ips.clone()),
+            None => None,
+        }
+    };
+
+    // 2. 执行 DNS 查询（缓存锁已释放，超时只影响当前连接）
+    debug!("DNS 查询: {} ({})", host, family);
+    let result = match tokio::time::timeout(options.timeout, lookup(host.to_string(), family)).await {
+        Ok(result) => result,
+        Err(_) => Err(SniProxyError::Timeout { operation: "DNS 解析" }),
+    };
+
+    match result {
+        // 3. 缓存结果（包括没有地址的结果，见 NEGATIVE_CACHE_TTL）
+        Ok(ips) => {
+            let mut cache = DNS_CACHE.lock().await;
+            cache.put(key, CacheEntry { ips: ips.clone(), resolved_at: Instant::now() });
+            debug!("DNS 缓存写入: {} ({}) -> {:?}", host, family, ips);
+            (Ok(ips), false)
+        }
+        Err(e) => {
+            if e.is_timeout() {
+                DNS_TIMEOUTS.fetch_add(1, Ordering::Relaxed);
+            } else {
+                DNS_FAILURES.fetch_add(1, Ordering::Relaxed);
+            }
+            match stale {
+                Some(ips) if options.serve_stale => {
+                    DNS_STALE_SERVED.fetch_add(1, Ordering::Relaxed);
+                    warn!("⚠️  DNS 刷新失败 {} ({}): {}，继续使用过期的缓存结果 {:?}", host, family, e, ips);
+                    (Ok(ips), false)
+                }
+                _ => (Err(e), false),
+            }
+        }
     }
+}
 
-    Ok(ips)
+/// DNS 预取配置
+///
+/// 启动时和之后每个周期，在后台解析直连白名单中的精确域名，用户连接到这些域名时不需要等待 DNS 解析。
+/// 缓存中没有、已过期或在下一个周期之前过期的结果才重新解析，预取不计入缓存命中统计
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DnsPrefetchConfig {
+    /// 预取周期
+    pub interval: Duration,
+    /// 同时解析的域名数
+    pub concurrency: usize,
+}
+
+impl Default for DnsPrefetchConfig {
+    fn default() -> Self {
+        Self { interval: DEFAULT_PREFETCH_INTERVAL, concurrency: DEFAULT_PREFETCH_CONCURRENCY }
+    }
+}
+
+/// 预取一个域名，返回是否重新解析（缓存中的结果在 `horizon` 之后才过期时不解析）
+async fn prefetch_host(host: &str, horizon: Duration) -> Result<bool> {
+    let mut options = dns_options();
+    options.cache_ttl = options.cache_ttl.map(|ttl| ttl.saturating_sub(horizon));
+    let (result, source) = resolve_host(host, options).await;
+    result.map(|_| source == ResolveSource::Lookup)
+}
+
+/// 定期预取 `domains` 返回的域名（每个周期重新获取，白名单重新加载后使用新的域名）
+pub(crate) async fn run_dns_prefetch<F>(config: DnsPrefetchConfig, domains: F, metrics: crate::metrics::Metrics)
+where
+    F: Fn() -> Vec<String>,
+{
+    use futures::StreamExt;
+
+    let mut interval = tokio::time::interval(config.interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    info!("✅ DNS 预取已启用（每 {:?}，并发 {}）", config.interval, config.concurrency);
+
+    let mut first = true;
+    loop {
+        interval.tick().await;
+        let domains = domains();
+        let started = Instant::now();
+        let total = domains.len();
+        let results: Vec<_> = futures::stream::iter(domains)
+            .map(|host| async move {
+                let result = prefetch_host(&host, config.interval).await;
+                (host, result)
+            })
+            .buffer_unordered(config.concurrency.max(1))
+            .collect()
+            .await;
+
+        let (mut refreshed, mut failed) = (0, 0);
+        for (host, result) in results {
+            match result {
+                Ok(true) => {
+                    refreshed += 1;
+                    metrics.inc_dns_prefetched();
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    failed += 1;
+                    metrics.inc_dns_prefetch_failures();
+                    debug!("DNS 预取失败 {}: {}", host, e);
+                }
+            }
+        }
+        let summary = format!(
+            "{} 个域名，重新解析 {} 个，失败 {} 个，用时 {:?}",
+            total,
+            refreshed,
+            failed,
+            started.elapsed()
+        );
+        if first {
+            info!("🔄 DNS 预热完成: {}", summary);
+            first = false;
+        } else {
+            debug!("DNS 预取完成: {}", summary);
+        }
+    }
 }
 
 /// 清除 DNS 缓存（可选）
@@ -64,8 +816,303 @@ pub async fn clear_dns_cache() {
     info!("DNS 缓存已清除");
 }
 
+/// 设置 DNS 缓存容量（缩小时淘汰最久未使用的条目）
+pub async fn set_dns_cache_capacity(capacity: usize) {
+    let Some(capacity) = NonZeroUsize::new(capacity) else {
+        return;
+    };
+    let mut cache = DNS_CACHE.lock().await;
+    cache.resize(capacity);
+    debug!("DNS 缓存容量设置为 {}", capacity);
+}
+
 /// 获取缓存大小（用于监控）
 pub async fn get_dns_cache_size() -> usize {
     let cache = DNS_CACHE.lock().await;
     cache.len()
 }
+
+/// 缓存中一个地址族的解析结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedRecord {
+    /// 地址族：A 或 AAAA
+    pub family: String,
+    /// 解析出的地址（为空表示该地址族没有记录）
+    pub ips: Vec<IpAddr>,
+    /// 距离解析完成的时间
+    pub age: Duration,
+    /// 按当前解析策略是否已过期（下次使用时重新解析）
+    pub expired: bool,
+}
+
+/// 查看域名在缓存中的解析结果（按地址族偏好排序），不解析、不影响 LRU 顺序和命中统计
+pub async fn peek_dns_cache(host: &str) -> Vec<CachedRecord> {
+    let options = dns_options();
+    let (first, second) = options.family.families();
+    let cache = DNS_CACHE.lock().await;
+    [Some(first), second]
+        .into_iter()
+        .flatten()
+        .filter_map(|family| {
+            let entry = cache.peek(&(host.to_string(), family))?;
+            Some(CachedRecord {
+                family: family.to_string(),
+                ips: entry.ips.clone(),
+                age: entry.resolved_at.elapsed(),
+                expired: entry.is_expired(options.cache_ttl),
+            })
+        })
+        .collect()
+}
+
+/// DNS 缓存统计
+#[derive(Debug, Clone, Copy)]
+pub struct DnsCacheStats {
+    /// 当前缓存条目数（每个域名的 IPv4 和 IPv6 结果分别占一条）
+    pub size: usize,
+    /// 缓存容量
+    pub capacity: usize,
+    /// 缓存命中次数
+    pub hits: u64,
+    /// 缓存未命中次数（包括过期条目的重新解析）
+    pub misses: u64,
+    /// 解析超时次数（按地址族计数）
+    pub timeouts: u64,
+    /// 解析失败次数（不含超时，按地址族计数）
+    pub failures: u64,
+    /// 重新解析失败后使用过期缓存的次数（按地址族计数）
+    pub stale_served: u64,
+}
+
+impl DnsCacheStats {
+    /// 缓存命中率（0.0 - 1.0），没有查询时返回 0
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+/// 获取 DNS 缓存统计（用于监控和管理接口）
+pub async fn get_dns_cache_stats() -> DnsCacheStats {
+    let cache = DNS_CACHE.lock().await;
+    DnsCacheStats {
+        size: cache.len(),
+        capacity: cache.cap().get(),
+        hits: DNS_CACHE_HITS.load(Ordering::Relaxed),
+        misses: DNS_CACHE_MISSES.load(Ordering::Relaxed),
+        timeouts: DNS_TIMEOUTS.load(Ordering::Relaxed),
+        failures: DNS_FAILURES.load(Ordering::Relaxed),
+        stale_served: DNS_STALE_SERVED.load(Ordering::Relaxed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(cache_ttl: Option<Duration>, serve_stale: bool) -> DnsOptions {
+        DnsOptions { timeout: Duration::from_millis(50), cache_ttl, serve_stale, ..DnsOptions::default() }
+    }
+
+    async fn hang(_host: String, _family: IpFamily) -> Result<Vec<IpAddr>> {
+        std::future::pending().await
+    }
+
+    #[tokio::test]
+    async fn test_lookup_timeout() {
+        let timeouts = DNS_TIMEOUTS.load(Ordering::Relaxed);
+        let err = resolve_with("timeout.dns-test.invalid", options(None, true), hang).await.0.unwrap_err();
+        assert!(err.is_timeout());
+        assert!(DNS_TIMEOUTS.load(Ordering::Relaxed) >= timeouts + 2);
+        // 超时的结果不写入缓存
+        let key = ("timeout.dns-test.invalid".to_string(), IpFamily::V4);
+        assert!(DNS_CACHE.lock().await.peek(&key).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_serve_stale() {
+        let host = "stale.dns-test.invalid";
+        let ip: IpAddr = "192.0.2.10".parse().unwrap();
+        let ttl = Some(Duration::ZERO);
+        let only_v4 = |ip: IpAddr| {
+            move |_host: String, family: IpFamily| async move {
+                Ok(if family == IpFamily::V4 { vec![ip] } else { Vec::new() })
+            }
+        };
+        assert_eq!(resolve_with(host, options(ttl, true), only_v4(ip)).await.0.unwrap(), [ip]);
+
+        // 条目已过期：刷新超时时使用过期结果，关闭 serve_stale 时返回超时错误
+        assert_eq!(resolve_with(host, options(ttl, true), hang).await.0.unwrap(), [ip]);
+        assert!(resolve_with(host, options(ttl, false), hang).await.0.unwrap_err().is_timeout());
+
+        // 刷新成功时更新缓存；不过期时不再查询
+        let new_ip: IpAddr = "192.0.2.11".parse().unwrap();
+        assert_eq!(resolve_with(host, options(ttl, true), only_v4(new_ip)).await.0.unwrap(), [new_ip]);
+        assert_eq!(resolve_with(host, options(None, true), hang).await.0.unwrap(), [new_ip]);
+    }
+
+    #[tokio::test]
+    async fn test_family_preference() {
+        let v4: IpAddr = "192.0.2.20".parse().unwrap();
+        let v6: IpAddr = "2001:db8::20".parse().unwrap();
+        let lookup = |_host: String, family: IpFamily| async move {
+            Ok(vec![if family == IpFamily::V4 { v4 } else { v6 }])
+        };
+        let resolve = |host: &'static str, family| {
+            resolve_with(host, DnsOptions { family, ..options(None, true) }, lookup)
+        };
+        assert_eq!(resolve("a.dns-test.invalid", IpFamilyPreference::Ipv6First).await.0.unwrap(), [v6, v4]);
+        assert_eq!(resolve("b.dns-test.invalid", IpFamilyPreference::Ipv4First).await.0.unwrap(), [v4, v6]);
+        assert_eq!(resolve("c.dns-test.invalid", IpFamilyPreference::Ipv4Only).await.0.unwrap(), [v4]);
+        assert_eq!(resolve("d.dns-test.invalid", IpFamilyPreference::Ipv6Only).await.0.unwrap(), [v6]);
+
+        // 一个地址族超时不影响另一个，成功的结果单独缓存
+        let v4_only = |_host: String, family: IpFamily| async move {
+            if family == IpFamily::V6 {
+                std::future::pending::<()>().await;
+            }
+            Ok(vec![v4])
+        };
+        let host = "partial.dns-test.invalid";
+        assert_eq!(resolve_with(host, options(None, true), v4_only).await.0.unwrap(), [v4]);
+        let cache = DNS_CACHE.lock().await;
+        assert!(cache.peek(&(host.to_string(), IpFamily::V4)).is_some());
+        assert!(cache.peek(&(host.to_string(), IpFamily::V6)).is_none());
+        drop(cache);
+
+        assert_eq!("IPv4_ONLY".parse::<IpFamilyPreference>().unwrap(), IpFamilyPreference::Ipv4Only);
+        assert!("dual".parse::<IpFamilyPreference>().is_err());
+    }
+
+    /// 只回答 A 查询的 DNS 服务器（AAAA 返回没有记录），返回收到的查询域名
+    async fn fake_nameserver(ip: Ipv4Addr) -> (SocketAddr, tokio::sync::mpsc::UnboundedReceiver<String>) {
+        let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            loop {
+                let (len, peer) = socket.recv_from(&mut buf).await.unwrap();
+                // 问题部分：以 0 结尾的标签序列，后面是类型和类
+                let mut pos = 12;
+                let mut labels = Vec::new();
+                while buf[pos] != 0 {
+                    let label_len = buf[pos] as usize;
+                    labels.push(String::from_utf8_lossy(&buf[pos + 1..pos + 1 + label_len]).into_owned());
+                    pos += 1 + label_len;
+                }
+                let question_end = (pos + 5).min(len);
+                let is_a = buf[pos + 2] == 1;
+                let _ = tx.send(labels.join("."));
+
+                let mut reply = buf[..question_end].to_vec();
+                reply[2..4].copy_from_slice(&[0x81, 0x80]);
+                reply[6..12].copy_from_slice(&[0, is_a as u8, 0, 0, 0, 0]);
+                if is_a {
+                    reply.extend_from_slice(&[0xc0, 0x0c, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4]);
+                    reply.extend_from_slice(&ip.octets());
+                }
+                socket.send_to(&reply, peer).await.unwrap();
+            }
+        });
+        (addr, rx)
+    }
+
+    #[tokio::test]
+    async fn test_upstream_lookup() {
+        let ip = Ipv4Addr::new(192, 0, 2, 30);
+        let (addr, mut queries) = fake_nameserver(ip).await;
+        let upstream = DnsUpstream { search: vec!["corp.example".to_string()], ..DnsUpstream::new(vec![addr]) };
+        let resolver = Arc::new(upstream.build_resolver().unwrap());
+
+        let ips = upstream_lookup(Arc::clone(&resolver), "origin.example.com".to_string(), IpFamily::V4).await;
+        assert_eq!(ips.unwrap(), [IpAddr::V4(ip)]);
+        assert_eq!(queries.recv().await.unwrap(), "origin.example.com");
+
+        // 没有 AAAA 记录时返回空列表
+        let ips = upstream_lookup(Arc::clone(&resolver), "origin.example.com".to_string(), IpFamily::V6).await;
+        assert!(ips.unwrap().is_empty());
+
+        // 不含点的域名加上搜索域查询
+        let ips = upstream_lookup(resolver, "backend".to_string(), IpFamily::V4).await;
+        assert_eq!(ips.unwrap(), [IpAddr::V4(ip)]);
+        let mut names = Vec::new();
+        while let Ok(name) = queries.try_recv() {
+            names.push(name);
+        }
+        assert!(names.contains(&"backend.corp.example".to_string()), "{:?}", names);
+
+        assert!(DnsUpstream::new(Vec::new()).build_resolver().is_err());
+    }
+
+    #[test]
+    fn test_doh_upstream() {
+        let bootstrap: Vec<IpAddr> = vec!["8.8.8.8".parse().unwrap(), "2001:4860:4860::8888".parse().unwrap()];
+        let upstream = DnsUpstream::doh("https://dns.google/dns-query", &bootstrap).unwrap();
+        assert_eq!(upstream.transport, DnsTransport::Https { tls_name: "dns.google".to_string() });
+        assert_eq!(upstream.nameservers, ["8.8.8.8:443".parse().unwrap(), "[2001:4860:4860::8888]:443".parse().unwrap()]);
+
+        // 主机是 IP 地址时不需要 bootstrap
+        let upstream = DnsUpstream::doh("https://[2606:4700:4700::1111]:8443/dns-query", &[]).unwrap();
+        assert_eq!(upstream.transport, DnsTransport::Https { tls_name: "2606:4700:4700::1111".to_string() });
+        assert_eq!(upstream.nameservers, ["[2606:4700:4700::1111]:8443".parse().unwrap()]);
+
+        assert!(DnsUpstream::doh("https://dns.google/dns-query", &[]).is_err());
+        assert!(DnsUpstream::doh("http://dns.google/dns-query", &bootstrap).is_err());
+        assert!(DnsUpstream::doh("https://dns.google/resolve", &bootstrap).is_err());
+        assert!(DnsUpstream::doh("https://dns.google:https/dns-query", &bootstrap).is_err());
+    }
+
+    #[test]
+    fn test_route_matching() {
+        let route = |suffix: &str, subdomains_only| ResolverRoute { suffix: suffix.to_string(), subdomains_only, resolver: None };
+        let routes = [route("db.corp.example", false), route("corp.example", true), route("example.com", false)];
+        let matched = |host| find_route(&routes, host).map(|route| route.suffix.as_str());
+
+        assert_eq!(matched("db.corp.example"), Some("db.corp.example"));
+        assert_eq!(matched("primary.DB.corp.example."), Some("db.corp.example"));
+        assert_eq!(matched("git.corp.example"), Some("corp.example"));
+        // *.corp.example 不匹配 corp.example 本身
+        assert_eq!(matched("corp.example"), None);
+        assert_eq!(matched("example.com"), Some("example.com"));
+        assert_eq!(matched("www.example.com"), Some("example.com"));
+        // 只按完整的标签匹配
+        assert_eq!(matched("notexample.com"), None);
+    }
+
+    #[test]
+    fn test_static_lookup() {
+        let v4: IpAddr = "10.0.0.5".parse().unwrap();
+        let v6: IpAddr = "fd00::5".parse().unwrap();
+        let hosts = HashMap::from([("internal.example.com".to_string(), vec![v4, v6])]);
+
+        assert_eq!(static_lookup(&hosts, "Internal.Example.COM.", IpFamilyPreference::Ipv6First).unwrap(), [v6, v4]);
+        assert_eq!(static_lookup(&hosts, "internal.example.com", IpFamilyPreference::Ipv4First).unwrap(), [v4, v6]);
+        assert_eq!(static_lookup(&hosts, "internal.example.com", IpFamilyPreference::Ipv6Only).unwrap(), [v6]);
+        assert!(static_lookup(&hosts, "other.example.com", IpFamilyPreference::Ipv4First).is_none());
+
+        // 没有偏好地址族的地址时不回退到 DNS
+        let hosts = HashMap::from([("v6.example.com".to_string(), vec![v6])]);
+        assert!(static_lookup(&hosts, "v6.example.com", IpFamilyPreference::Ipv4Only).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_host() {
+        // 缓存不过期：第一次解析，之后不再解析
+        assert!(prefetch_host("localhost", DEFAULT_PREFETCH_INTERVAL).await.unwrap());
+        assert!(!prefetch_host("localhost", DEFAULT_PREFETCH_INTERVAL).await.unwrap());
+        // IP 地址不需要解析
+        assert!(!prefetch_host("192.0.2.1", DEFAULT_PREFETCH_INTERVAL).await.unwrap());
+    }
+
+    #[test]
+    fn test_getaddrinfo_localhost() {
+        let ips = getaddrinfo("localhost", IpFamily::V4).unwrap();
+        assert!(ips.contains(&IpAddr::V4(Ipv4Addr::LOCALHOST)));
+        assert!(ips.iter().all(IpAddr::is_ipv4));
+    }
+}
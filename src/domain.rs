@@ -1,70 +1,162 @@
-use log::info;
-use std::collections::HashSet;
-
-/// 域名匹配器，支持精确匹配和通配符匹配
+use log::{error, info};
+use regex::{Regex, RegexBuilder};
+use std::collections::{HashMap, HashSet};
+
+use crate::error::{Result, SniProxyError};
+
+/// 正则规则前缀
+const REGEX_PREFIX: char = '~';
+
+/// 域名匹配器，支持精确匹配、通配符匹配和正则匹配
+///
+/// `+example.com`（或 `.example.com`）表示主域名及所有子域名，等同于同时配置 `example.com` 和 `*.example.com`。
+/// 规则可以带目标端口（例如 "example.com:8443"、"*.example.com:8443"），
+/// 匹配的域名连接该端口，而不是按监听端口映射的默认端口。
+/// 以 `~` 开头的规则是正则表达式（例如 `~[a-z]{2}-\d+\.cdn\.example\.com`），匹配整个域名、不区分大小写，
+/// 在精确匹配和通配符匹配都不命中后按配置顺序检查
 #[derive(Debug, Clone)]
 pub struct DomainMatcher {
     /// 精确匹配的域名列表
     exact_domains: HashSet<String>,
-    /// 通配符域名列表（例如 "*.example.com"），已排序以优化匹配
-    wildcard_domains: Vec<String>,
+    /// 通配符域名的后缀集合（"*.example.com" 存为 "example.com"），按域名的各级后缀逐一查找
+    wildcard_domains: HashSet<String>,
+    /// 带端口的规则：精确域名或通配符（带 "*." 前缀）→ 目标端口
+    ports: HashMap<String, u16>,
+    /// 正则规则（启动时编译一次）和目标端口
+    regexes: Vec<RegexRule>,
+}
+
+/// 一条正则规则
+#[derive(Debug, Clone)]
+struct RegexRule {
+    /// 配置中的表达式（不含 `~` 前缀）
+    pattern: String,
+    /// 编译后的表达式（已加上首尾锚定）
+    regex: Regex,
+    port: Option<u16>,
 }
 
 impl DomainMatcher {
     /// 创建新的域名匹配器
     pub fn new(domains: Vec<String>) -> Self {
         let mut exact_domains = HashSet::new();
-        let mut wildcard_domains = Vec::new();
+        let mut wildcard_domains = HashSet::new();
+        let mut ports = HashMap::new();
+        let mut regexes = Vec::new();
 
         for domain in domains {
+            if let Some(rule) = domain.strip_prefix(REGEX_PREFIX) {
+                let (pattern, port) = split_regex_port(rule);
+                match compile_regex(pattern) {
+                    Ok(regex) => {
+                        info!("添加正则域名规则: ~{}", pattern);
+                        regexes.push(RegexRule { pattern: pattern.to_string(), regex, port });
+                    }
+                    // 配置文件中的规则已在启动时验证，这里只会遇到直接调用库的无效规则
+                    Err(e) => error!("❌ 忽略无效的正则域名规则 ~{}: {}", pattern, e),
+                }
+                continue;
+            }
+
+            let (domain, port) = split_port(&domain);
             let domain_lower = domain.to_lowercase(); // 统一转换为小写
 
-            if domain_lower.starts_with("*.") {
+            if let Some(apex) = domain_lower.strip_prefix('+').or_else(|| domain_lower.strip_prefix('.')) {
+                // 主域名及所有子域名：等同于同时配置 "example.com" 和 "*.example.com"
+                if !apex.is_empty() {
+                    if let Some(port) = port {
+                        ports.insert(apex.to_string(), port);
+                        ports.insert(format!("*.{}", apex), port);
+                    }
+                    exact_domains.insert(apex.to_string());
+                    wildcard_domains.insert(apex.to_string());
+                    info!("添加主域名及子域名: {}", apex);
+                }
+            } else if let Some(suffix) = domain_lower.strip_prefix("*.") {
                 // 通配符域名
-                let suffix = domain_lower[2..].to_string();
+                let suffix = suffix.to_string();
                 if !suffix.is_empty() {
-                    wildcard_domains.push(suffix);
+                    if let Some(port) = port {
+                        ports.insert(domain_lower.clone(), port);
+                    }
+                    wildcard_domains.insert(suffix);
                     info!("添加通配符域名: {}", domain_lower);
                 }
             } else if !domain_lower.is_empty() {
                 // 精确匹配域名
+                if let Some(port) = port {
+                    ports.insert(domain_lower.clone(), port);
+                }
                 exact_domains.insert(domain_lower.clone());
                 info!("添加精确匹配域名: {}", domain_lower);
             }
         }
 
-        // 按长度排序通配符域名（更长的优先匹配，提高准确性）
-        wildcard_domains.sort_by(|a, b| b.len().cmp(&a.len()));
-
         Self {
             exact_domains,
             wildcard_domains,
+            ports,
+            regexes,
+        }
+    }
+
+    /// 检查规则列表中的正则规则能否编译（用于启动时验证配置）
+    pub fn validate_rules(domains: &[String]) -> Result<()> {
+        for rule in domains.iter().filter_map(|domain| domain.strip_prefix(REGEX_PREFIX)) {
+            let (pattern, _) = split_regex_port(rule);
+            compile_regex(pattern)
+                .map_err(|e| SniProxyError::InvalidConfig(format!("无效的正则域名规则 ~{}: {}", pattern, e)))?;
         }
+        Ok(())
     }
 
     /// 检查域名是否匹配白名单
     #[inline]
     pub fn matches(&self, domain: &str) -> bool {
+        self.matched_rule(&domain.to_lowercase()).is_some()
+    }
+
+    /// 匹配规则配置的目标端口（规则没有端口或域名不匹配时返回 None）
+    pub fn target_port(&self, domain: &str) -> Option<u16> {
+        if self.ports.is_empty() && self.regexes.iter().all(|rule| rule.port.is_none()) {
+            return None;
+        }
         let domain_lower = domain.to_lowercase();
+        match self.matched_rule(&domain_lower)? {
+            Rule::Exact => self.ports.get(&domain_lower).copied(),
+            Rule::Wildcard(suffix) => self.ports.get(&format!("*.{}", suffix)).copied(),
+            Rule::Regex(rule) => rule.port,
+        }
+    }
 
+    /// 匹配的规则
+    fn matched_rule(&self, domain_lower: &str) -> Option<Rule<'_>> {
         // 先检查精确匹配（O(1)）
-        if self.exact_domains.contains(&domain_lower) {
-            return true;
+        if self.exact_domains.contains(domain_lower) {
+            return Some(Rule::Exact);
         }
 
-        // 再检查通配符匹配（O(n)，但已优化）
-        for wildcard_suffix in &self.wildcard_domains {
-            if domain_lower.len() > wildcard_suffix.len()
-                && domain_lower.ends_with(wildcard_suffix) {
-                // 确保匹配的是完整的子域名
-                let prefix_len = domain_lower.len() - wildcard_suffix.len();
-                if &domain_lower[prefix_len - 1..prefix_len] == "." {
-                    return true;
-                }
+        // 再检查通配符匹配：从最长的后缀开始逐级查找（O(标签数)，与规则数量无关），
+        // 例如 "v1.api.example.com" 依次查找 "api.example.com"、"example.com"、"com"，
+        // 因此更具体的规则优先；域名本身不参与查找，通配符不匹配主域名
+        let mut rest = domain_lower;
+        while let Some((_, suffix)) = rest.split_once('.') {
+            if let Some(wildcard_suffix) = self.wildcard_domains.get(suffix) {
+                return Some(Rule::Wildcard(wildcard_suffix));
             }
+            rest = suffix;
         }
 
-        false
+        // 最后按顺序检查正则规则
+        self.regexes
+            .iter()
+            .find(|rule| rule.regex.is_match(domain_lower))
+            .map(Rule::Regex)
+    }
+
+    /// 精确匹配的域名（包括 `+example.com` 规则中的主域名）
+    pub fn exact_domains(&self) -> impl Iterator<Item = &str> {
+        self.exact_domains.iter().map(String::as_str)
     }
 
     /// 获取所有域名模式（用于 DNS 预热等场景）
@@ -80,10 +172,56 @@ impl DomainMatcher {
             patterns.push(format!("*.{}", wildcard_suffix));
         }
 
+        // 添加正则规则（带 "~" 前缀）
+        for rule in &self.regexes {
+            patterns.push(format!("{}{}", REGEX_PREFIX, rule.pattern));
+        }
+
         patterns
     }
 }
 
+/// 匹配到的规则类型
+enum Rule<'a> {
+    /// 精确匹配
+    Exact,
+    /// 通配符匹配（后缀不含 "*."）
+    Wildcard(&'a str),
+    /// 正则匹配
+    Regex(&'a RegexRule),
+}
+
+/// 编译正则规则：匹配整个域名，不区分大小写
+fn compile_regex(pattern: &str) -> std::result::Result<Regex, regex::Error> {
+    RegexBuilder::new(&format!("^(?:{})$", pattern)).case_insensitive(true).build()
+}
+
+/// 拆分正则规则中的目标端口，例如 "[a-z]+\.example\.com:8443" → ("[a-z]+\.example\.com", Some(8443))
+///
+/// 表达式本身可能含冒号（例如 "(?:a|b)"），只要最后一个冒号后是 1-65535 的数字就视为端口
+fn split_regex_port(rule: &str) -> (&str, Option<u16>) {
+    match rule.rsplit_once(':') {
+        Some((pattern, port)) => match port.parse::<u16>() {
+            Ok(port) if port != 0 => (pattern, Some(port)),
+            _ => (rule, None),
+        },
+        None => (rule, None),
+    }
+}
+
+/// 拆分规则中的目标端口，例如 "example.com:8443" → ("example.com", Some(8443))
+///
+/// 冒号前仍含冒号（IPv6 地址）或端口不是 1-65535 的数字时，整条规则视为域名
+fn split_port(rule: &str) -> (&str, Option<u16>) {
+    match rule.rsplit_once(':') {
+        Some((domain, port)) if !domain.contains(':') => match port.parse::<u16>() {
+            Ok(port) if port != 0 => (domain, Some(port)),
+            _ => (rule, None),
+        },
+        _ => (rule, None),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -202,4 +340,88 @@ mod tests {
         assert!(matcher.matches("www.example.com"));
         assert!(matcher.matches("test.com"));
     }
+
+    #[test]
+    fn test_domain_matcher_target_port() {
+        let matcher = DomainMatcher::new(vec![
+            "example.com:8443".to_string(),
+            "*.example.com".to_string(),
+            "*.api.example.com:9443".to_string(),
+            "2001:db8::1".to_string(),
+        ]);
+
+        assert!(matcher.matches("example.com"));
+        assert_eq!(matcher.target_port("EXAMPLE.COM"), Some(8443));
+        assert_eq!(matcher.target_port("www.example.com"), None);
+        assert_eq!(matcher.target_port("v1.api.example.com"), Some(9443));
+        assert_eq!(matcher.target_port("other.com"), None);
+        // IPv6 地址不被当成带端口的规则
+        assert!(matcher.matches("2001:db8::1"));
+        assert_eq!(matcher.target_port("2001:db8::1"), None);
+    }
+
+    #[test]
+    fn test_domain_matcher_regex() {
+        let matcher = DomainMatcher::new(vec![
+            "static.example.com".to_string(),
+            "~(?:us|eu)-\\d+\\.cdn\\.example\\.com:8443".to_string(),
+            "~[a-z]{2}\\.example\\.net".to_string(),
+            "~(".to_string(),
+        ]);
+
+        assert!(matcher.matches("us-1.cdn.example.com"));
+        assert!(matcher.matches("EU-42.CDN.example.com")); // 大小写不敏感
+        assert_eq!(matcher.target_port("eu-42.cdn.example.com"), Some(8443));
+        assert!(matcher.matches("jp.example.net"));
+        assert_eq!(matcher.target_port("jp.example.net"), None);
+        // 匹配整个域名
+        assert!(!matcher.matches("ap-1.cdn.example.com"));
+        assert!(!matcher.matches("www.jp.example.net"));
+        assert!(!matcher.matches("jp.example.net.evil.com"));
+        // 精确规则仍然生效，无效的正则被忽略
+        assert!(matcher.matches("static.example.com"));
+        assert_eq!(matcher.get_patterns().len(), 3);
+
+        assert!(DomainMatcher::validate_rules(&["~[a-z]+\\.example\\.com:443".to_string()]).is_ok());
+        assert!(DomainMatcher::validate_rules(&["~(".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_domain_matcher_many_wildcards() {
+        let mut rules: Vec<String> = (0..20_000).map(|i| format!("*.site{}.example.com", i)).collect();
+        rules.push("*.example.com:8443".to_string());
+        rules.push("*.site7.example.com:9443".to_string());
+        let matcher = DomainMatcher::new(rules);
+
+        assert!(matcher.matches("www.site19999.example.com"));
+        assert!(matcher.matches("a.b.site42.example.com"));
+        assert!(matcher.matches("site42.example.com")); // 匹配 *.example.com
+        assert!(!matcher.matches("example.com"));
+        assert!(!matcher.matches("www.site42.example.org"));
+        // 更具体的规则优先
+        assert_eq!(matcher.target_port("www.site7.example.com"), Some(9443));
+        assert_eq!(matcher.target_port("www.site8.example.com"), None);
+        assert_eq!(matcher.target_port("site8.example.com"), Some(8443));
+    }
+
+    #[test]
+    fn test_domain_matcher_apex_and_subdomains() {
+        let matcher = DomainMatcher::new(vec![
+            "+example.com".to_string(),
+            ".Example.org:8443".to_string(),
+            "+".to_string(),
+        ]);
+
+        assert!(matcher.matches("example.com"));
+        assert!(matcher.matches("www.example.com"));
+        assert!(matcher.matches("a.b.example.com"));
+        assert!(!matcher.matches("notexample.com"));
+        assert!(matcher.matches("example.org"));
+        assert!(matcher.matches("WWW.EXAMPLE.ORG"));
+        assert_eq!(matcher.target_port("example.org"), Some(8443));
+        assert_eq!(matcher.target_port("www.example.org"), Some(8443));
+        assert_eq!(matcher.target_port("example.com"), None);
+        assert!(!matcher.matches("+"));
+        assert_eq!(matcher.get_patterns().len(), 4);
+    }
 }
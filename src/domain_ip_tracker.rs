@@ -2,7 +2,6 @@ use log::info;
 use std::collections::{HashMap, HashSet};
 use std::net::IpAddr;
 use std::sync::{Arc, Mutex};
-use std::fs::File;
 use std::io::Write as IoWrite;
 
 /// 域名-IP 追踪器
@@ -49,7 +48,7 @@ impl DomainIpTracker {
 
         let mut data = self.data.lock().unwrap();
         data.entry(domain.to_string())
-            .or_insert_with(HashSet::new)
+            .or_default()
             .insert(ip);
     }
 
@@ -64,7 +63,7 @@ impl DomainIpTracker {
         let socks5_marker = "0.0.0.0".parse::<IpAddr>().unwrap();
         let mut data = self.data.lock().unwrap();
         data.entry(domain.to_string())
-            .or_insert_with(HashSet::new)
+            .or_default()
             .insert(socks5_marker);
     }
 
@@ -87,15 +86,21 @@ impl DomainIpTracker {
             None => return Ok(()), // 没有指定输出文件，直接返回
         };
 
+        // 磁盘写满时暂停写入，定期重试
+        crate::disk::DOMAIN_IP_OUTPUT.write(|| self.write_file(output_path))
+    }
+
+    /// 写入映射文件
+    fn write_file(&self, output_path: &str) -> Result<(), std::io::Error> {
         let data = self.data.lock().unwrap();
 
         // 创建或覆盖文件
-        let mut file = File::create(output_path)?;
+        let mut file = crate::output_file::create(output_path)?;
 
         // 写入表头
         writeln!(file, "# SNI 代理域名-IP 映射表")?;
         writeln!(file, "# 格式: 域名 -> IP地址列表")?;
-        writeln!(file, "# 生成时间: {}", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"))?;
+        writeln!(file, "# 生成时间: {}", crate::clock::local_now().format("%Y-%m-%d %H:%M:%S"))?;
         writeln!(file, "# 总域名数: {}", data.len())?;
         writeln!(file)?;
 
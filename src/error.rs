@@ -0,0 +1,145 @@
+use std::net::SocketAddr;
+
+/// SNI 代理库的错误类型
+///
+/// 按失败类别划分，嵌入方可以用 `match` 区分处理（例如 DNS 失败重试、SOCKS5 拒绝时切换出口）
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum SniProxyError {
+    /// 底层 I/O 错误
+    #[error("I/O 错误: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// 绑定监听地址失败
+    #[error("绑定监听地址 {addr} 失败: {source}")]
+    Bind {
+        addr: SocketAddr,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// 设置 socket 选项失败
+    #[error("设置 {option} 失败: {source}")]
+    SocketOption {
+        option: &'static str,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// DNS 解析失败
+    #[error("DNS 解析失败 {host}: {source}")]
+    DnsResolve {
+        host: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// DNS 查询没有返回任何地址
+    #[error("DNS 查询返回空列表: {0}")]
+    DnsEmpty(String),
+
+    /// 操作超时
+    #[error("{operation}超时")]
+    Timeout { operation: &'static str },
+
+    /// 无法连接到 SOCKS5 服务器
+    #[error("无法连接到 SOCKS5 服务器 {addr}: {source}")]
+    Socks5Connect {
+        addr: SocketAddr,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// 连接 SOCKS5 服务器超时
+    #[error("连接到 SOCKS5 服务器 {addr} 超时")]
+    Socks5ConnectTimeout { addr: SocketAddr },
+
+    /// 与 SOCKS5 服务器通信时的 I/O 错误
+    #[error("{stage}失败: {source}")]
+    Socks5Io {
+        stage: &'static str,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// SOCKS5 服务器返回了不符合协议的数据
+    #[error("无效的 SOCKS5 响应: {0}")]
+    Socks5Protocol(String),
+
+    /// SOCKS5 用户名/密码认证失败
+    #[error("SOCKS5 认证失败")]
+    Socks5AuthFailed,
+
+    /// SOCKS5 服务器拒绝了连接请求（REP 字段非 0）
+    #[error("SOCKS5: {}", socks5_reply_message(*code))]
+    Socks5Rejected { code: u8 },
+
+    /// 配置无效
+    #[error("{0}")]
+    InvalidConfig(String),
+
+    /// 目标域名不合法（例如超过 255 字节）
+    #[error("无效的目标域名: {0}")]
+    InvalidTarget(String),
+}
+
+/// 库内使用的 Result 别名
+pub type Result<T, E = SniProxyError> = std::result::Result<T, E>;
+
+impl SniProxyError {
+    /// 是否为超时错误
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, SniProxyError::Timeout { .. } | SniProxyError::Socks5ConnectTimeout { .. })
+    }
+
+    /// 是否为值得重试的临时连接错误：超时、无法连接 SOCKS5 服务器、连接被拒绝或重置，
+    /// 以及 SOCKS5 服务器报告目标主机无法访问或拒绝连接
+    pub fn is_transient(&self) -> bool {
+        match self {
+            SniProxyError::Timeout { .. }
+            | SniProxyError::Socks5ConnectTimeout { .. }
+            | SniProxyError::Socks5Connect { .. } => true,
+            SniProxyError::Io(e) | SniProxyError::Socks5Io { source: e, .. } => crate::retry::is_transient_io(e),
+            SniProxyError::Socks5Rejected { code } => matches!(code, 4 | 5),
+            _ => false,
+        }
+    }
+}
+
+/// SOCKS5 REP 状态码说明（RFC 1928）
+fn socks5_reply_message(code: u8) -> String {
+    match code {
+        1 => "一般 SOCKS 服务器故障".to_string(),
+        2 => "连接规则集不允许的连接".to_string(),
+        3 => "网络无法访问".to_string(),
+        4 => "主机无法访问".to_string(),
+        5 => "连接被拒绝".to_string(),
+        6 => "TTL 过期".to_string(),
+        7 => "不支持的命令".to_string(),
+        8 => "不支持的地址类型".to_string(),
+        code => format!("未知错误代码 {}", code),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_messages() {
+        assert_eq!(SniProxyError::Socks5Rejected { code: 5 }.to_string(), "SOCKS5: 连接被拒绝");
+        assert_eq!(SniProxyError::Socks5Rejected { code: 42 }.to_string(), "SOCKS5: 未知错误代码 42");
+        assert_eq!(
+            SniProxyError::Timeout { operation: "读取 SOCKS5 握手响应" }.to_string(),
+            "读取 SOCKS5 握手响应超时"
+        );
+        assert!(SniProxyError::Timeout { operation: "连接" }.is_timeout());
+        assert!(!SniProxyError::Socks5AuthFailed.is_timeout());
+        let connect_timeout = SniProxyError::Socks5ConnectTimeout { addr: "127.0.0.1:1080".parse().unwrap() };
+        assert_eq!(connect_timeout.to_string(), "连接到 SOCKS5 服务器 127.0.0.1:1080 超时");
+        assert!(connect_timeout.is_timeout() && connect_timeout.is_transient());
+        assert!(SniProxyError::Socks5Rejected { code: 5 }.is_transient());
+        assert!(!SniProxyError::Socks5Rejected { code: 2 }.is_transient());
+        assert!(!SniProxyError::Socks5AuthFailed.is_transient());
+    }
+}
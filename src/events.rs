@@ -0,0 +1,148 @@
+use std::net::SocketAddr;
+use tokio::sync::broadcast;
+
+/// 事件通道默认容量（订阅方处理过慢时，最旧的事件会被丢弃）
+pub const DEFAULT_EVENT_CAPACITY: usize = 1024;
+
+/// 连接被拒绝的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum RejectReason {
+    /// 客户端 IP 不在白名单中
+    IpNotAllowed,
+    /// 域名不在白名单中
+    DomainNotAllowed,
+    /// 无法解析 SNI（或 HTTP Host 头）
+    SniParseError,
+    /// TLS 端口收到明文 HTTP 请求
+    PlaintextHttp,
+    /// 客户端 TLS 指纹（JA3 / JA4）被过滤规则拒绝
+    FingerprintBlocked,
+    /// SNI 为 IP 地址，按配置拒绝或不在 IP SNI 白名单中
+    IpLiteralSni,
+    /// 同一客户端对同一 SNI 的重复连接处于退避期间
+    BurstBackoff,
+    /// 客户端 IP 和域名的组合被 ACL 拒绝
+    AclDenied,
+    /// 域名在黑名单中
+    DomainBlacklisted,
+    /// 客户端 IP 在黑名单中
+    IpBlacklisted,
+    /// 客户端所在国家不被允许
+    GeoBlocked,
+    /// 客户端 IP 被自动封禁
+    IpBanned,
+}
+
+impl std::fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RejectReason::IpNotAllowed => write!(f, "ip_not_allowed"),
+            RejectReason::DomainNotAllowed => write!(f, "domain_not_allowed"),
+            RejectReason::SniParseError => write!(f, "sni_parse_error"),
+            RejectReason::PlaintextHttp => write!(f, "plaintext_http"),
+            RejectReason::FingerprintBlocked => write!(f, "fingerprint_blocked"),
+            RejectReason::IpLiteralSni => write!(f, "ip_literal_sni"),
+            RejectReason::BurstBackoff => write!(f, "burst_backoff"),
+            RejectReason::AclDenied => write!(f, "acl_denied"),
+            RejectReason::DomainBlacklisted => write!(f, "domain_blacklisted"),
+            RejectReason::IpBlacklisted => write!(f, "ip_blacklisted"),
+            RejectReason::GeoBlocked => write!(f, "geo_blocked"),
+            RejectReason::IpBanned => write!(f, "ip_banned"),
+        }
+    }
+}
+
+/// 代理运行时事件
+///
+/// 通过 [`SniProxy::subscribe_events`](crate::SniProxy::subscribe_events) 订阅，
+/// 嵌入方可以据此实现自定义告警、封禁等逻辑，而不必解析日志
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ProxyEvent {
+    /// 接受了新的客户端连接
+    ConnectionOpened { client_addr: SocketAddr },
+    /// 连接被拒绝（`host` 为已解析出的 SNI / Host，IP 被拒绝时为 `None`）
+    Rejected {
+        client_addr: SocketAddr,
+        host: Option<String>,
+        reason: RejectReason,
+    },
+    /// 无法连接到上游（目标服务器或 SOCKS5 出口）
+    UpstreamDown {
+        host: String,
+        port: u16,
+        via_socks5: bool,
+        error: String,
+    },
+    /// 并发连接数达到上限，新连接需要排队等待
+    QuotaExceeded { client_addr: SocketAddr, limit: usize },
+    /// 转发结束（`bytes_received` 为客户端上传的字节数，`bytes_sent` 为发给客户端的字节数）
+    ConnectionClosed {
+        client_addr: SocketAddr,
+        host: String,
+        bytes_received: u64,
+        bytes_sent: u64,
+    },
+}
+
+/// 事件广播通道
+///
+/// 廉价克隆的句柄；没有订阅者时不会构造事件，热路径上几乎没有开销
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<ProxyEvent>,
+}
+
+impl EventBus {
+    /// 创建指定容量的事件通道
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        Self { sender }
+    }
+
+    /// 订阅事件
+    pub fn subscribe(&self) -> broadcast::Receiver<ProxyEvent> {
+        self.sender.subscribe()
+    }
+
+    /// 是否有订阅者
+    pub fn has_subscribers(&self) -> bool {
+        self.sender.receiver_count() > 0
+    }
+
+    /// 发送事件（仅在有订阅者时调用 `event` 构造事件）
+    pub fn emit(&self, event: impl FnOnce() -> ProxyEvent) {
+        if self.has_subscribers() {
+            let _ = self.sender.send(event());
+        }
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_EVENT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_emit_without_subscribers() {
+        let bus = EventBus::default();
+        assert!(!bus.has_subscribers());
+        bus.emit(|| unreachable!("没有订阅者时不应构造事件"));
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let bus = EventBus::new(4);
+        let mut rx = bus.subscribe();
+        let client_addr: SocketAddr = "127.0.0.1:1234".parse().unwrap();
+
+        bus.emit(|| ProxyEvent::ConnectionOpened { client_addr });
+        assert_eq!(rx.recv().await.unwrap(), ProxyEvent::ConnectionOpened { client_addr });
+    }
+}
@@ -0,0 +1,377 @@
+//! 路由决策解释（管理接口 `/explain`）
+//!
+//! 对给定的 SNI 和客户端 IP，按连接处理的顺序（IP 白名单 → 黑名单 → ACL → IP SNI → 映射表 / 路由规则 /
+//! 默认后端 → DNS → 上游）逐步检查运行中的配置，返回每一步的结果。只读：不连接上游，不计入指标和事件；
+//! 重复连接退避取决于连接历史，不做检查。假定连接是 TLS（路由规则指定的端口只对 TLS 生效）
+
+use serde_json::{json, Map, Value};
+use std::net::IpAddr;
+use std::time::Instant;
+
+use crate::acl::AclAction;
+use crate::connection::{CloseReason, ConnectionContext, Route, RouteMatch};
+use crate::dns::{dns_options, peek_dns_cache, resolve_host_cached};
+use crate::ip_sni::{parse_ip_literal, IpSniAction};
+use crate::route_table::RouteQuery;
+
+/// 要解释的连接
+#[derive(Debug, Clone)]
+pub(crate) struct ExplainRequest {
+    /// SNI（或 HTTP Host）
+    pub(crate) sni: String,
+    /// 客户端 IP
+    pub(crate) client_ip: IpAddr,
+    /// 接受连接的监听端口（None 时使用第一个监听地址的端口）
+    pub(crate) listen_port: Option<u16>,
+    /// 客户端提供的 ALPN 协议
+    pub(crate) alpn: Vec<String>,
+    /// 是否实际解析 DNS（否则只查看缓存）
+    pub(crate) resolve: bool,
+}
+
+/// 决策过程的每一步
+#[derive(Default)]
+struct Trace {
+    steps: Vec<Value>,
+}
+
+impl Trace {
+    /// 记录一步，`result` 为 pass / reject / skip / info，返回该步的对象以便补充字段
+    fn step(&mut self, step: &str, result: &str, detail: String) -> &mut Map<String, Value> {
+        self.steps.push(json!({ "step": step, "result": result, "detail": detail }));
+        self.steps.last_mut().and_then(Value::as_object_mut).expect("step is an object")
+    }
+}
+
+/// 最终决策
+enum Verdict {
+    Rejected(CloseReason),
+    Upstream(Value),
+}
+
+/// 按运行中的配置解释一个连接的路由决策
+pub(crate) async fn explain(ctx: &ConnectionContext, request: &ExplainRequest) -> Value {
+    let mut trace = Trace::default();
+    let listen_port = request
+        .listen_port
+        .or_else(|| ctx.listen_addrs.first().map(|addr| addr.port()));
+    let verdict = decide(ctx, request, listen_port, &mut trace).await;
+    let (decision, reason, upstream) = match verdict {
+        Verdict::Rejected(reason) => ("reject", Some(reason.to_string()), Value::Null),
+        Verdict::Upstream(upstream) => ("connect", None, upstream),
+    };
+    json!({
+        "sni": request.sni,
+        "client": request.client_ip.to_string(),
+        "listen_port": listen_port,
+        "decision": decision,
+        "reason": reason,
+        "upstream": upstream,
+        "steps": trace.steps,
+    })
+}
+
+async fn decide(ctx: &ConnectionContext, request: &ExplainRequest, listen_port: Option<u16>, trace: &mut Trace) -> Verdict {
+    let sni = request.sni.as_str();
+    let client_ip = request.client_ip;
+
+    // 1. 客户端 IP（接受连接时检查）
+    if let Some(ban_table) = &ctx.ban_table {
+        if let Some(remaining) = ban_table.check(client_ip, Instant::now()) {
+            trace.step("auto_ban", "reject", format!("{} 处于自动封禁期间（剩余 {:?}）", client_ip, remaining));
+            return Verdict::Rejected(CloseReason::IpBanned);
+        }
+        trace.step("auto_ban", "pass", format!("{} 没有被自动封禁", client_ip));
+    }
+    if let Some(ip_blacklist) = &ctx.ip_blacklist {
+        if ip_blacklist.matches(client_ip) {
+            trace.step("ip_blacklist", "reject", format!("{} 在 IP 黑名单中", client_ip));
+            return Verdict::Rejected(CloseReason::IpRejected);
+        }
+        trace.step("ip_blacklist", "pass", format!("{} 不在 IP 黑名单中", client_ip));
+    }
+    match &*ctx.ip_matcher.load() {
+        None => trace.step("ip_whitelist", "skip", "未配置 IP 白名单".to_string()),
+        Some(matcher) if matcher.matches(client_ip) => {
+            trace.step("ip_whitelist", "pass", format!("{} 在 IP 白名单中", client_ip))
+        }
+        Some(_) => {
+            trace.step("ip_whitelist", "reject", format!("{} 不在 IP 白名单中", client_ip));
+            return Verdict::Rejected(CloseReason::IpRejected);
+        }
+    };
+    if let (Some(geo_filter), Some(geoip)) = (&ctx.geo_filter, &ctx.geoip) {
+        let country = geoip.load().country(client_ip);
+        let name = country.map_or_else(|| "未知".to_string(), |c| c.to_string());
+        if !geo_filter.allows(country) {
+            trace.step("geoip", "reject", format!("{} 所在国家 {} 不被允许", client_ip, name));
+            return Verdict::Rejected(CloseReason::GeoRejected);
+        }
+        trace.step("geoip", "pass", format!("{} 所在国家 {}", client_ip, name));
+    }
+    if let Some(acl) = &ctx.acl {
+        if !acl.may_allow_client(client_ip) {
+            trace.step("acl_client", "reject", format!("ACL 不允许 {} 访问任何域名", client_ip));
+            return Verdict::Rejected(CloseReason::AclRejected);
+        }
+    }
+    if ctx.burst_limiter.is_some() {
+        trace.step("burst_backoff", "skip", "重复连接退避取决于连接历史，不做检查".to_string());
+    }
+
+    // 2. 域名规则
+    match &ctx.blacklist {
+        Some(blacklist) if blacklist.matches(sni) => {
+            trace.step("blacklist", "reject", format!("{} 在域名黑名单中", sni));
+            return Verdict::Rejected(CloseReason::DomainRejected);
+        }
+        Some(_) => trace.step("blacklist", "pass", format!("{} 不在域名黑名单中", sni)),
+        None => trace.step("blacklist", "skip", "未配置域名黑名单".to_string()),
+    };
+    match &ctx.acl {
+        Some(acl) if acl.check(client_ip, sni) == AclAction::Deny => {
+            trace.step("acl", "reject", format!("ACL 拒绝 {} 访问 {}", client_ip, sni));
+            return Verdict::Rejected(CloseReason::AclRejected);
+        }
+        Some(_) => trace.step("acl", "pass", format!("ACL 允许 {} 访问 {}", client_ip, sni)),
+        None => trace.step("acl", "skip", "未配置 ACL".to_string()),
+    };
+
+    if let Some(ip) = parse_ip_literal(sni) {
+        let action = ctx.ip_sni_policy.action(listen_port);
+        if action != IpSniAction::Domain {
+            let allowed = action == IpSniAction::IpWhitelist
+                && ctx.ip_sni_matcher.as_ref().is_some_and(|m| m.matches(ip.to_canonical()));
+            if !allowed {
+                trace.step("ip_sni", "reject", format!("SNI 为 IP 地址，处理方式 {}，不允许连接", action));
+                return Verdict::Rejected(CloseReason::IpSniRejected);
+            }
+            trace.step("ip_sni", "pass", format!("SNI 为 IP 地址，在 IP SNI 白名单中（处理方式 {}）", action));
+            let port = upstream_port(ctx, &Route::Direct, None, listen_port);
+            return Verdict::Upstream(upstream(ctx, &Route::Direct, sni, port, trace, request.resolve).await);
+        }
+        trace.step("ip_sni", "info", "SNI 为 IP 地址，按域名规则匹配".to_string());
+    }
+
+    // 3. 路由（不持有路由表跨越 await）
+    let (route, rule_port) = {
+        let country = ctx.geoip.as_ref().and_then(|geoip| geoip.load().country(client_ip));
+        let query = RouteQuery { domain: sni, client_ip, country, alpn: &request.alpn };
+        let routes = ctx.routes.load();
+        let matched = ctx.match_route(&routes, &query);
+        let mut rule_port = None;
+        let route = matched.route(ctx);
+        match &matched {
+            RouteMatch::Mapped(backend) => {
+                trace.step("route", "pass", format!("匹配 SNI 映射表 → {}", backend));
+            }
+            RouteMatch::Rule(rule) => {
+                rule_port = rule.target_port(sni);
+                let result = if route.is_some() { "pass" } else { "reject" };
+                let step = trace.step("route", result, format!("匹配路由规则: {}", rule.action()));
+                step.insert("action".to_string(), json!(rule.action().to_string()));
+                if let Some(port) = rule_port {
+                    step.insert("rule_port".to_string(), json!(port));
+                }
+                if let Some(category) = rule.category() {
+                    step.insert("category".to_string(), json!(category));
+                }
+                if let Some(country) = country {
+                    step.insert("client_country".to_string(), json!(country.to_string()));
+                }
+                if let Some(schedule) = rule.schedule() {
+                    step.insert("schedule".to_string(), json!(schedule.to_string()));
+                }
+                if let Some(limiter) = rule.bandwidth_limit() {
+                    step.insert("bandwidth_limit_bytes_per_sec".to_string(), json!(limiter.rate()));
+                }
+            }
+            RouteMatch::DefaultBackend(backend) => {
+                trace.step("route", "pass", format!("不匹配任何路由规则，转发到默认后端 {}", backend));
+            }
+            RouteMatch::None => {
+                trace.step("route", "reject", "不匹配任何路由规则，且未配置默认后端".to_string());
+            }
+        }
+        (route, rule_port)
+    };
+    let Some(route) = route else {
+        return Verdict::Rejected(CloseReason::DomainRejected);
+    };
+
+    // 4. DNS 和上游
+    let port = upstream_port(ctx, &route, rule_port, listen_port);
+    Verdict::Upstream(upstream(ctx, &route, sni, port, trace, request.resolve).await)
+}
+
+/// 目标端口，与连接处理的规则相同（透明代理的原始目标端口在连接时才知道，这里按端口映射计算）
+fn upstream_port(ctx: &ConnectionContext, route: &Route, rule_port: Option<u16>, listen_port: Option<u16>) -> u16 {
+    match route {
+        Route::Mapped(backend) => backend.port,
+        Route::DefaultBackend => ctx.default_backend.as_ref().map_or(0, |backend| backend.port),
+        _ => rule_port.unwrap_or_else(|| {
+            listen_port.map_or(ctx.port_mapping.default_port(), |port| ctx.port_mapping.target_port(port))
+        }),
+    }
+}
+
+/// DNS 和上游两步，返回上游描述
+async fn upstream(ctx: &ConnectionContext, route: &Route, sni: &str, port: u16, trace: &mut Trace, resolve: bool) -> Value {
+    let backend = match route {
+        Route::Mapped(backend) => Some(backend.as_ref()),
+        Route::DefaultBackend => ctx.default_backend.as_deref(),
+        _ => None,
+    };
+
+    if let Route::Socks5(Some(socks5)) = route {
+        trace.step("dns", "skip", format!("由 SOCKS5 上游 {} 解析", socks5.addr));
+        trace.step("upstream", "pass", format!("通过 SOCKS5 {} 连接 {}:{}", socks5.addr, sni, port));
+        return json!({ "kind": "socks5", "socks5": socks5.addr.to_string(), "host": sni, "port": port });
+    }
+
+    let host = backend.map_or(sni, |backend| backend.host.as_str());
+    if backend.is_none() && ctx.transparent_mode.is_some() {
+        trace.step("dns", "skip", "透明代理直接连接原始目标地址，不解析 DNS".to_string());
+    } else {
+        explain_dns(ctx, host, trace, resolve).await;
+    }
+
+    let kind = if backend.is_some() { "backend" } else { "direct" };
+    let detail = match route {
+        Route::Socks5(None) => format!("路由为 SOCKS5 但未配置对应的上游，直接连接 {}:{}", host, port),
+        _ => format!("直接连接 {}:{}", host, port),
+    };
+    trace.step("upstream", "pass", detail);
+    json!({ "kind": kind, "host": host, "port": port })
+}
+
+async fn explain_dns(ctx: &ConnectionContext, host: &str, trace: &mut Trace, resolve: bool) {
+    if parse_ip_literal(host).is_some() {
+        trace.step("dns", "skip", format!("{} 是 IP 地址，不需要解析", host));
+        return;
+    }
+    if let Some(ips) = ctx.pinned_ips.as_ref().and_then(|pinned| pinned.lookup(host)) {
+        let step = trace.step("dns", "pass", format!("{} 使用固定 IP（按客户端 IP 选择起始地址）", host));
+        step.insert("ips".to_string(), json!(ips.iter().map(IpAddr::to_string).collect::<Vec<_>>()));
+        return;
+    }
+
+    let options = dns_options();
+    let policy = json!({
+        "timeout_ms": options.timeout.as_millis() as u64,
+        "cache_ttl_secs": options.cache_ttl.map(|ttl| ttl.as_secs()),
+        "serve_stale": options.serve_stale,
+        "family": options.family.to_string(),
+    });
+    let cache: Vec<Value> = peek_dns_cache(host)
+        .await
+        .into_iter()
+        .map(|record| {
+            json!({
+                "family": record.family,
+                "ips": record.ips.iter().map(IpAddr::to_string).collect::<Vec<_>>(),
+                "age_secs": record.age.as_secs(),
+                "expired": record.expired,
+            })
+        })
+        .collect();
+
+    let step = if resolve {
+        match resolve_host_cached(host).await {
+            Ok(ips) => {
+                let step = trace.step("dns", "pass", format!("{} 解析成功，连接第一个地址", host));
+                step.insert("ips".to_string(), json!(ips.iter().map(IpAddr::to_string).collect::<Vec<_>>()));
+                step
+            }
+            Err(e) => trace.step("dns", "reject", format!("{} 解析失败: {}", host, e)),
+        }
+    } else if cache.is_empty() {
+        trace.step("dns", "info", format!("{} 不在 DNS 缓存中，连接时解析", host))
+    } else {
+        trace.step("dns", "info", format!("{} 在 DNS 缓存中", host))
+    };
+    step.insert("policy".to_string(), policy);
+    step.insert("cache".to_string(), Value::Array(cache));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acl::{Acl, AclRule};
+    use crate::connection::tests::test_context;
+    use crate::domain::DomainMatcher;
+    use crate::ip_matcher::IpMatcher;
+    use crate::sni_map::PinnedIps;
+    use std::sync::Arc;
+
+    fn request(sni: &str, client: &str) -> ExplainRequest {
+        ExplainRequest {
+            sni: sni.to_string(),
+            client_ip: client.parse().unwrap(),
+            listen_port: Some(8443),
+            alpn: Vec::new(),
+            resolve: false,
+        }
+    }
+
+    fn steps(trace: &Value) -> Vec<(String, String)> {
+        trace["steps"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|step| (step["step"].as_str().unwrap().to_string(), step["result"].as_str().unwrap().to_string()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_explain_direct() {
+        let (mut ctx, _tx) = test_context(&["example.com:8443"], &[]);
+        let pinned = PinnedIps::new([("example.com".to_string(), Arc::from(vec!["192.0.2.10".parse().unwrap()]))]);
+        ctx.pinned_ips = Some(Arc::new(pinned));
+
+        let trace = explain(&ctx, &request("example.com", "10.0.0.1")).await;
+        assert_eq!(trace["decision"], "connect");
+        assert_eq!(trace["upstream"], json!({ "kind": "direct", "host": "example.com", "port": 8443 }));
+        let names: Vec<String> = steps(&trace).into_iter().map(|(step, _)| step).collect();
+        assert_eq!(names, ["ip_whitelist", "blacklist", "acl", "route", "dns", "upstream"]);
+        assert_eq!(trace["steps"][3]["rule_port"], 8443);
+        assert_eq!(trace["steps"][4]["ips"], json!(["192.0.2.10"]));
+
+        let trace = explain(&ctx, &request("other.com", "10.0.0.1")).await;
+        assert_eq!(trace["decision"], "reject");
+        assert_eq!(trace["reason"], "domain_rejected");
+        assert_eq!(steps(&trace).last().unwrap(), &("route".to_string(), "reject".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_explain_rejections() {
+        let (mut ctx, _tx) = test_context(&["*.example.com"], &[]);
+        ctx.ip_matcher.store(Some(Arc::new(IpMatcher::new(vec!["10.0.0.0/8".to_string()]))));
+        ctx.blacklist = Some(Arc::new(DomainMatcher::new(vec!["ads.example.com".to_string()])));
+        ctx.acl = Some(Arc::new(Acl::new(vec![
+            AclRule::new(AclAction::Deny).with_domains(vec!["secret.example.com".to_string()]),
+            AclRule::new(AclAction::Allow),
+        ])));
+
+        let trace = explain(&ctx, &request("www.example.com", "192.0.2.1")).await;
+        assert_eq!(trace["reason"], "ip_rejected");
+        assert_eq!(steps(&trace).len(), 1);
+
+        ctx.ip_blacklist = Some(Arc::new(IpMatcher::new(vec!["10.0.0.66".to_string()])));
+        let trace = explain(&ctx, &request("www.example.com", "10.0.0.66")).await;
+        assert_eq!(trace["reason"], "ip_rejected");
+        assert_eq!(steps(&trace), [("ip_blacklist".to_string(), "reject".to_string())]);
+
+        let trace = explain(&ctx, &request("ads.example.com", "10.0.0.1")).await;
+        assert_eq!(trace["reason"], "domain_rejected");
+        assert_eq!(steps(&trace).last().unwrap().0, "blacklist");
+
+        let trace = explain(&ctx, &request("secret.example.com", "10.0.0.1")).await;
+        assert_eq!(trace["reason"], "acl_rejected");
+        assert_eq!(steps(&trace).last().unwrap().0, "acl");
+
+        let trace = explain(&ctx, &request("www.example.com", "10.0.0.1")).await;
+        assert_eq!(trace["decision"], "connect");
+        assert_eq!(trace["upstream"]["port"], 443);
+    }
+}
@@ -0,0 +1,292 @@
+//! TLS 客户端指纹（JA3 / JA4）
+//!
+//! 根据 Client Hello 计算客户端 TLS 栈的指纹，配合允许 / 拒绝列表过滤已知的扫描器和爬虫。
+//! 指纹由 [`parse_client_hello`](crate::tls::parse_client_hello) 的解析结果计算，不需要重新解析
+
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use crate::error::{Result, SniProxyError};
+use crate::tls::{is_grease, ClientHelloInfo, EXT_ALPN, EXT_SERVER_NAME};
+
+/// JA3 指纹长度（MD5 十六进制）
+const JA3_LEN: usize = 32;
+/// JA4 指纹长度（`t13d1516h2_8daaf6152771_e5627efa2ab1`）
+const JA4_LEN: usize = 36;
+
+/// 一个连接的客户端指纹
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fingerprint {
+    /// JA3 指纹（MD5 十六进制）
+    pub ja3: String,
+    /// JA4 指纹（未开启 JA4 时为 None）
+    pub ja4: Option<String>,
+}
+
+/// JA3 原始字符串：`版本,Cipher Suites,扩展,椭圆曲线,点格式`（十进制，忽略 GREASE）
+pub fn ja3_string(info: &ClientHelloInfo) -> String {
+    fn join(values: impl Iterator<Item = u16>) -> String {
+        values
+            .filter(|&value| !is_grease(value))
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>()
+            .join("-")
+    }
+
+    format!(
+        "{},{},{},{},{}",
+        info.legacy_version,
+        join(info.cipher_suites.iter().copied()),
+        join(info.extensions.iter().copied()),
+        join(info.supported_groups.iter().copied()),
+        join(info.ec_point_formats.iter().map(|&format| format as u16)),
+    )
+}
+
+/// JA3 指纹：JA3 原始字符串的 MD5
+pub fn ja3(info: &ClientHelloInfo) -> String {
+    format!("{:x}", md5::compute(ja3_string(info)))
+}
+
+/// JA4 指纹（TCP）：`t<版本><d|i><Cipher 数><扩展数><ALPN>_<Cipher 哈希>_<扩展哈希>`
+pub fn ja4(info: &ClientHelloInfo) -> String {
+    let ciphers: Vec<u16> = info.cipher_suites.iter().copied().filter(|&c| !is_grease(c)).collect();
+    let extensions: Vec<u16> = info.extensions.iter().copied().filter(|&e| !is_grease(e)).collect();
+
+    let version = match info.tls_version() {
+        0x0304 => "13",
+        0x0303 => "12",
+        0x0302 => "11",
+        0x0301 => "10",
+        0x0300 => "s3",
+        _ => "00",
+    };
+    let sni = if info.sni.is_some() { 'd' } else { 'i' };
+    let prefix = format!(
+        "t{}{}{:02}{:02}{}",
+        version,
+        sni,
+        ciphers.len().min(99),
+        extensions.len().min(99),
+        ja4_alpn(info.alpn.first().map(String::as_str))
+    );
+
+    let mut sorted_ciphers = ciphers;
+    sorted_ciphers.sort_unstable();
+
+    // 扩展哈希不包含 SNI 和 ALPN（已体现在前缀中），签名算法保持客户端顺序
+    let mut sorted_extensions: Vec<u16> = extensions
+        .into_iter()
+        .filter(|&e| e != EXT_SERVER_NAME && e != EXT_ALPN)
+        .collect();
+    sorted_extensions.sort_unstable();
+    let mut extension_input = hex_list(&sorted_extensions);
+    if !info.signature_algorithms.is_empty() {
+        extension_input.push('_');
+        extension_input.push_str(&hex_list(&info.signature_algorithms));
+    }
+
+    format!(
+        "{}_{}_{}",
+        prefix,
+        ja4_hash(&sorted_ciphers, &hex_list(&sorted_ciphers)),
+        ja4_hash(&sorted_extensions, &extension_input)
+    )
+}
+
+/// JA4 前缀中的 ALPN：第一个协议的首尾字符，不是字母或数字时改用十六进制的首尾字符
+fn ja4_alpn(alpn: Option<&str>) -> String {
+    let Some(alpn) = alpn.filter(|alpn| !alpn.is_empty()) else {
+        return "00".to_string();
+    };
+    let bytes = alpn.as_bytes();
+    let (first, last) = (bytes[0], bytes[bytes.len() - 1]);
+    if first.is_ascii_alphanumeric() && last.is_ascii_alphanumeric() {
+        format!("{}{}", first as char, last as char)
+    } else {
+        let hex = format!("{:02x}{:02x}", first, last);
+        format!("{}{}", &hex[..1], &hex[3..])
+    }
+}
+
+/// 四位十六进制、逗号分隔
+fn hex_list(values: &[u16]) -> String {
+    let mut out = String::with_capacity(values.len() * 5);
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let _ = write!(out, "{:04x}", value);
+    }
+    out
+}
+
+/// SHA-256 的前 12 个十六进制字符（列表为空时为全 0）
+fn ja4_hash(values: &[u16], input: &str) -> String {
+    if values.is_empty() {
+        return "000000000000".to_string();
+    }
+    Sha256::digest(input.as_bytes())
+        .iter()
+        .take(6)
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// 指纹过滤规则
+///
+/// 列表项可以是 JA3（32 位十六进制）或 JA4 指纹。拒绝列表优先；允许列表非空时，
+/// 只放行指纹在允许列表中的连接
+#[derive(Debug, Default)]
+pub struct FingerprintFilter {
+    allow: HashSet<String>,
+    deny: HashSet<String>,
+    /// 是否计算 JA4
+    ja4: bool,
+    /// 是否在 info 日志中记录每个连接的指纹
+    log: bool,
+}
+
+impl FingerprintFilter {
+    /// 创建过滤规则（列表项格式错误，或未开启 JA4 却配置了 JA4 指纹时返回错误）
+    pub fn new(allow: Vec<String>, deny: Vec<String>, ja4: bool, log: bool) -> Result<Self> {
+        let normalize = |list: Vec<String>| -> Result<HashSet<String>> {
+            list.into_iter()
+                .map(|entry| {
+                    let entry = entry.trim();
+                    if is_ja3(entry) {
+                        Ok(entry.to_ascii_lowercase())
+                    } else if is_ja4(entry) {
+                        if !ja4 {
+                            return Err(SniProxyError::InvalidConfig(format!(
+                                "指纹 {} 是 JA4 格式，需要开启 ja4",
+                                entry
+                            )));
+                        }
+                        Ok(entry.to_string())
+                    } else {
+                        Err(SniProxyError::InvalidConfig(format!(
+                            "无效的指纹: {}（应为 JA3 或 JA4 格式）",
+                            entry
+                        )))
+                    }
+                })
+                .collect()
+        };
+
+        Ok(Self {
+            allow: normalize(allow)?,
+            deny: normalize(deny)?,
+            ja4,
+            log,
+        })
+    }
+
+    /// 计算客户端指纹
+    pub fn fingerprint(&self, info: &ClientHelloInfo) -> Fingerprint {
+        Fingerprint {
+            ja3: ja3(info),
+            ja4: self.ja4.then(|| ja4(info)),
+        }
+    }
+
+    /// 是否放行该指纹
+    pub fn allows(&self, fingerprint: &Fingerprint) -> bool {
+        let listed = |list: &HashSet<String>| {
+            list.contains(&fingerprint.ja3) || fingerprint.ja4.as_ref().is_some_and(|ja4| list.contains(ja4))
+        };
+        !listed(&self.deny) && (self.allow.is_empty() || listed(&self.allow))
+    }
+
+    /// 是否记录每个连接的指纹
+    pub fn log_enabled(&self) -> bool {
+        self.log
+    }
+}
+
+fn is_ja3(entry: &str) -> bool {
+    entry.len() == JA3_LEN && entry.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn is_ja4(entry: &str) -> bool {
+    let parts: Vec<&str> = entry.split('_').collect();
+    entry.len() == JA4_LEN
+        && parts.len() == 3
+        && parts[0].len() == 10
+        && parts[0].is_ascii()
+        && parts[1..].iter().all(|part| part.len() == 12 && part.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 常见浏览器风格的 Client Hello 解析结果（带 GREASE）
+    fn browser_hello() -> ClientHelloInfo {
+        ClientHelloInfo {
+            sni: Some("www.example.com".to_string()),
+            legacy_version: 0x0303,
+            supported_versions: vec![0x7a7a, 0x0304, 0x0303],
+            alpn: vec!["h2".to_string(), "http/1.1".to_string()],
+            cipher_suites: vec![0x0a0a, 0x1301, 0x1302, 0xc02b],
+            extensions: vec![0x1a1a, 0, 23, 65281, 10, 11, 35, 16, 13, 43],
+            supported_groups: vec![0x2a2a, 0x001d, 0x0017],
+            ec_point_formats: vec![0],
+            signature_algorithms: vec![0x0403, 0x0804, 0x0401],
+        }
+    }
+
+    #[test]
+    fn test_ja3() {
+        let info = browser_hello();
+        assert_eq!(ja3_string(&info), "771,4865-4866-49195,0-23-65281-10-11-35-16-13-43,29-23,0");
+        assert_eq!(ja3(&info), format!("{:x}", md5::compute(ja3_string(&info))));
+        assert_eq!(ja3(&info).len(), JA3_LEN);
+    }
+
+    #[test]
+    fn test_ja4() {
+        let info = browser_hello();
+        let fingerprint = ja4(&info);
+        assert!(fingerprint.starts_with("t13d0309h2_"), "{}", fingerprint);
+        assert!(is_ja4(&fingerprint));
+
+        // 没有 SNI、ALPN、扩展
+        let info = ClientHelloInfo {
+            legacy_version: 0x0303,
+            cipher_suites: vec![0x002f],
+            ..ClientHelloInfo::default()
+        };
+        assert_eq!(&ja4(&info)[..23], "t12i010000_ba72b8082249");
+        assert!(ja4(&info).ends_with("_000000000000"));
+
+        assert_eq!(ja4_alpn(Some("http/1.1")), "h1");
+        assert_eq!(ja4_alpn(Some("\u{1}x")), "08");
+        assert_eq!(ja4_alpn(None), "00");
+    }
+
+    #[test]
+    fn test_filter() {
+        let info = browser_hello();
+        let ja3 = ja3(&info);
+        let ja4 = ja4(&info);
+
+        let deny = FingerprintFilter::new(Vec::new(), vec![ja3.to_uppercase()], false, false).unwrap();
+        let fingerprint = deny.fingerprint(&info);
+        assert_eq!(fingerprint.ja4, None);
+        assert!(!deny.allows(&fingerprint));
+
+        let allow = FingerprintFilter::new(vec![ja4.clone()], Vec::new(), true, false).unwrap();
+        assert!(allow.allows(&allow.fingerprint(&info)));
+        let other = ClientHelloInfo {
+            legacy_version: 0x0303,
+            cipher_suites: vec![0x002f],
+            ..ClientHelloInfo::default()
+        };
+        assert!(!allow.allows(&allow.fingerprint(&other)));
+
+        assert!(FingerprintFilter::new(vec![ja4], Vec::new(), false, false).is_err());
+        assert!(FingerprintFilter::new(vec!["not-a-fingerprint".to_string()], Vec::new(), true, false).is_err());
+    }
+}
@@ -0,0 +1,520 @@
+//! GeoIP
+//!
+//! 使用 MaxMind GeoLite2 / GeoIP2 数据库（Country 或 City，MMDB 格式）按客户端 IP 查询所在国家
+//! （ISO 3166-1 两位代码，例如 `CN`、`US`），用于按国家允许或拒绝客户端。
+//! 数据库中查不到国家的地址（私有地址、回环地址等）不受国家限制。
+//!
+//! 可以配置自动更新：按固定周期从 HTTP(S) 地址下载数据库（条件请求，带数据库文件的修改时间），
+//! 响应可以是 MMDB 文件或 MaxMind 官方下载地址提供的 tar.gz 压缩包，
+//! 比当前数据库新时写入数据库文件并替换内存中的数据库，正在处理的连接不受影响；
+//! 下载失败或内容无效时继续使用当前数据库
+
+use arc_swap::ArcSwap;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use log::{debug, info, warn};
+use maxminddb::{geoip2, Reader};
+use flate2::read::GzDecoder;
+use std::collections::HashSet;
+use std::fmt;
+use std::io::Read;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::{timeout, MissedTickBehavior};
+
+use crate::error::{Result, SniProxyError};
+use crate::http_client::{http_get_following, HttpUrl};
+
+/// 默认更新周期
+pub const DEFAULT_UPDATE_INTERVAL: Duration = Duration::from_secs(24 * 3600);
+
+/// 单次下载的超时时间
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// 数据库大小上限（下载的响应和解压后的数据库分别限制）
+const MAX_DATABASE_LEN: usize = 256 * 1024 * 1024;
+
+/// 下载时最多跟随的重定向次数
+const MAX_REDIRECTS: usize = 5;
+
+/// 共享的 GeoIP 数据库（自动更新时整体替换）
+pub type SharedGeoIp = Arc<ArcSwap<GeoIp>>;
+
+/// 国家代码（ISO 3166-1 两位大写字母）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CountryCode([u8; 2]);
+
+impl CountryCode {
+    /// 国家代码字符串
+    pub fn as_str(&self) -> &str {
+        // 构造时已保证是 ASCII 字母
+        std::str::from_utf8(&self.0).unwrap_or("??")
+    }
+}
+
+impl FromStr for CountryCode {
+    type Err = SniProxyError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().as_bytes() {
+            &[a, b] if a.is_ascii_alphabetic() && b.is_ascii_alphabetic() => {
+                Ok(Self([a.to_ascii_uppercase(), b.to_ascii_uppercase()]))
+            }
+            _ => Err(SniProxyError::InvalidConfig(format!("无效的国家代码: {}（应为两位字母，例如 CN、US）", s))),
+        }
+    }
+}
+
+impl fmt::Display for CountryCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// GeoIP 数据库（整个文件读入内存）
+pub struct GeoIp {
+    reader: Reader<Vec<u8>>,
+}
+
+impl fmt::Debug for GeoIp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GeoIp")
+            .field("database_type", &self.reader.metadata.database_type)
+            .field("build_epoch", &self.reader.metadata.build_epoch)
+            .finish()
+    }
+}
+
+impl GeoIp {
+    /// 加载数据库文件
+    pub fn open(path: &Path) -> Result<Self> {
+        let data = std::fs::read(path)
+            .map_err(|e| SniProxyError::InvalidConfig(format!("无法读取 GeoIP 数据库 {}: {}", path.display(), e)))?;
+        Self::from_bytes(data)
+            .map_err(|e| SniProxyError::InvalidConfig(format!("GeoIP 数据库 {} 无效: {}", path.display(), e)))
+    }
+
+    /// 从内存中的数据库内容创建
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self> {
+        let reader = Reader::from_source(data).map_err(|e| SniProxyError::InvalidConfig(e.to_string()))?;
+        // ASN 等数据库中没有国家信息，所有地址都会查不到国家
+        let database_type = &reader.metadata.database_type;
+        if !["Country", "City", "Enterprise"].iter().any(|kind| database_type.contains(kind)) {
+            return Err(SniProxyError::InvalidConfig(format!(
+                "数据库类型 {} 不包含国家信息（需要 Country 或 City 数据库，例如 GeoLite2-Country）",
+                reader.metadata.database_type
+            )));
+        }
+        Ok(Self { reader })
+    }
+
+    /// 数据库类型，例如 `GeoLite2-Country`
+    pub fn database_type(&self) -> &str {
+        &self.reader.metadata.database_type
+    }
+
+    /// 数据库构建时间（Unix 时间戳）
+    pub fn build_epoch(&self) -> u64 {
+        self.reader.metadata.build_epoch
+    }
+
+    /// 数据库构建日期（YYYY-MM-DD，日志使用）
+    pub fn build_date(&self) -> String {
+        chrono::DateTime::from_timestamp(self.build_epoch() as i64, 0)
+            .map_or_else(|| self.build_epoch().to_string(), |time| time.format("%Y-%m-%d").to_string())
+    }
+
+    /// 查询 IP 所在国家（查不到时返回 None），没有国家信息时使用注册国家
+    pub fn country(&self, ip: IpAddr) -> Option<CountryCode> {
+        let record: geoip2::Country = self.reader.lookup(ip.to_canonical()).ok()?;
+        let country = record.country.and_then(|c| c.iso_code);
+        let iso_code = country.or_else(|| record.registered_country.and_then(|c| c.iso_code))?;
+        iso_code.parse().ok()
+    }
+}
+
+/// 按客户端所在国家允许或拒绝连接
+///
+/// 配置了允许列表时只允许列表中的国家，拒绝列表中的国家一律拒绝；查不到国家的地址不受限制
+#[derive(Debug, Clone, Default)]
+pub struct GeoFilter {
+    allow: HashSet<CountryCode>,
+    deny: HashSet<CountryCode>,
+}
+
+impl GeoFilter {
+    pub fn new(allow: Vec<CountryCode>, deny: Vec<CountryCode>) -> Self {
+        Self { allow: allow.into_iter().collect(), deny: deny.into_iter().collect() }
+    }
+
+    /// 是否允许来自该国家的客户端
+    pub fn allows(&self, country: Option<CountryCode>) -> bool {
+        let Some(country) = country else {
+            return true;
+        };
+        !self.deny.contains(&country) && (self.allow.is_empty() || self.allow.contains(&country))
+    }
+
+    /// 允许的国家数量
+    pub fn allow_len(&self) -> usize {
+        self.allow.len()
+    }
+
+    /// 拒绝的国家数量
+    pub fn deny_len(&self) -> usize {
+        self.deny.len()
+    }
+}
+
+/// GeoIP 数据库自动更新配置（响应可以是 MMDB 文件或包含 MMDB 文件的 tar.gz 压缩包）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeoIpUpdate {
+    /// 下载地址
+    pub url: HttpUrl,
+    /// 数据库文件（下载的数据库写入这里）
+    pub path: PathBuf,
+    /// MaxMind 账号 ID（可选），和许可证密钥一起以 HTTP Basic 认证发送（只允许 https:// 地址）
+    pub account_id: Option<String>,
+    /// MaxMind 许可证密钥（可选）
+    pub license_key: Option<String>,
+    /// 更新周期
+    pub interval: Duration,
+}
+
+impl GeoIpUpdate {
+    pub fn new(url: HttpUrl, path: PathBuf) -> Self {
+        Self { url, path, account_id: None, license_key: None, interval: DEFAULT_UPDATE_INTERVAL }
+    }
+
+    /// 检查配置：许可证密钥不能通过明文 http:// 发送
+    pub fn validate(&self) -> Result<()> {
+        if self.license_key.is_some() && !self.url.is_tls() {
+            return Err(SniProxyError::InvalidConfig(format!(
+                "GeoIP 下载地址 {} 不是 https://，不能发送 license_key",
+                self.url
+            )));
+        }
+        Ok(())
+    }
+
+    /// 请求的 Authorization 头（配置了许可证密钥时）
+    fn authorization(&self) -> Option<String> {
+        let license_key = self.license_key.as_ref()?;
+        let credentials = format!("{}:{}", self.account_id.as_deref().unwrap_or(""), license_key);
+        Some(format!("Basic {}", BASE64.encode(credentials)))
+    }
+
+    /// 下载数据库：比 `newer_than`（构建时间）新时写入数据库文件并返回，没有更新时返回 None。
+    /// 数据库文件存在时带上它的修改时间发送条件请求
+    pub async fn download(&self, newer_than: u64) -> Result<Option<GeoIp>> {
+        self.validate()?;
+        let if_modified_since = std::fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .map(|modified| chrono::DateTime::<chrono::Utc>::from(modified).format("%a, %d %b %Y %H:%M:%S GMT").to_string());
+        let authorization = self.authorization();
+        let mut headers = Vec::new();
+        if let Some(if_modified_since) = &if_modified_since {
+            headers.push(("If-Modified-Since", if_modified_since.as_str()));
+        }
+        if let Some(authorization) = &authorization {
+            headers.push(("Authorization", authorization.as_str()));
+        }
+        let response = timeout(DOWNLOAD_TIMEOUT, http_get_following(&self.url, &headers, MAX_DATABASE_LEN, MAX_REDIRECTS))
+            .await
+            .map_err(|_| SniProxyError::Timeout { operation: "下载 GeoIP 数据库" })??;
+        match response.status {
+            304 => return Ok(None),
+            200 => {}
+            status => {
+                return Err(SniProxyError::InvalidConfig(format!("{} 返回 HTTP {}", self.url, status)));
+            }
+        }
+
+        let database = tokio::task::spawn_blocking(move || extract_database(response.body))
+            .await
+            .map_err(|e| SniProxyError::InvalidConfig(format!("解压 GeoIP 数据库失败: {}", e)))?
+            .map_err(|e| SniProxyError::InvalidConfig(format!("{} 返回的压缩包无效: {}", self.url, e)))?;
+
+        // 先写入临时文件，验证通过后再替换数据库文件
+        let mut temp_path = self.path.clone().into_os_string();
+        temp_path.push(".download");
+        let temp_path = PathBuf::from(temp_path);
+        tokio::fs::write(&temp_path, &database).await?;
+        let geoip = match GeoIp::from_bytes(database) {
+            Ok(geoip) if geoip.build_epoch() > newer_than => geoip,
+            result => {
+                let _ = tokio::fs::remove_file(&temp_path).await;
+                return match result {
+                    Ok(_) => Ok(None),
+                    Err(e) => Err(SniProxyError::InvalidConfig(format!("{} 返回的数据库无效: {}", self.url, e))),
+                };
+            }
+        };
+        tokio::fs::rename(&temp_path, &self.path).await?;
+        Ok(Some(geoip))
+    }
+}
+
+/// 取出下载内容中的 MMDB 数据库：gzip 压缩的内容按 tar 包解开，取第一个 `.mmdb` 文件，其他内容原样返回
+fn extract_database(body: Vec<u8>) -> std::io::Result<Vec<u8>> {
+    if !body.starts_with(&[0x1f, 0x8b]) {
+        return Ok(body);
+    }
+    let mut archive = tar::Archive::new(GzDecoder::new(body.as_slice()));
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if entry.path()?.extension().is_none_or(|ext| ext != "mmdb") {
+            continue;
+        }
+        let mut database = Vec::new();
+        entry.take(MAX_DATABASE_LEN as u64 + 1).read_to_end(&mut database)?;
+        if database.len() > MAX_DATABASE_LEN {
+            return Err(std::io::Error::other(format!("数据库超过 {} 字节", MAX_DATABASE_LEN)));
+        }
+        return Ok(database);
+    }
+    Err(std::io::Error::other("压缩包中没有 .mmdb 文件"))
+}
+
+/// 定期下载 GeoIP 数据库，有更新时替换（下载失败时继续使用当前数据库）
+pub(crate) async fn run_geoip_update(geoip: SharedGeoIp, update: GeoIpUpdate) {
+    let mut interval = tokio::time::interval(update.interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    info!("✅ GeoIP 数据库自动更新已启用（{}，每 {:?}）", update.url, update.interval);
+
+    loop {
+        interval.tick().await;
+        let current = geoip.load().build_epoch();
+        match update.download(current).await {
+            Ok(Some(downloaded)) => {
+                info!("🔄 GeoIP 数据库已更新: {}（构建于 {}）", downloaded.database_type(), downloaded.build_date());
+                geoip.store(Arc::new(downloaded));
+            }
+            Ok(None) => debug!("GeoIP 数据库没有更新"),
+            Err(e) => warn!("⚠️  更新 GeoIP 数据库失败: {}，继续使用当前数据库", e),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn put_string(out: &mut Vec<u8>, s: &str) {
+        out.push((2 << 5) | s.len() as u8);
+        out.extend_from_slice(s.as_bytes());
+    }
+
+    fn put_map(out: &mut Vec<u8>, len: usize) {
+        out.push((7 << 5) | len as u8);
+    }
+
+    fn put_uint(out: &mut Vec<u8>, kind: u8, value: u64) {
+        let bytes: Vec<u8> = value.to_be_bytes().into_iter().skip_while(|&b| b == 0).collect();
+        if kind < 8 {
+            out.push((kind << 5) | bytes.len() as u8);
+        } else {
+            out.extend_from_slice(&[bytes.len() as u8, kind - 7]);
+        }
+        out.extend_from_slice(&bytes);
+    }
+
+    /// 构造测试用的 GeoIP 数据库（IPv6 树，24 位记录），`networks` 为（网段，国家代码）
+    pub(crate) fn test_database(networks: &[(&str, &str)]) -> Vec<u8> {
+        test_database_built_at(networks, 1_700_000_000)
+    }
+
+    fn test_database_built_at(networks: &[(&str, &str)], build_epoch: u64) -> Vec<u8> {
+        // 数据区：每个国家一条 {"country": {"iso_code": ...}}
+        let mut data = Vec::new();
+        let mut offsets = BTreeMap::new();
+        for &(_, country) in networks {
+            offsets.entry(country).or_insert_with(|| {
+                let offset = data.len();
+                put_map(&mut data, 1);
+                put_string(&mut data, "country");
+                put_map(&mut data, 1);
+                put_string(&mut data, "iso_code");
+                put_string(&mut data, country);
+                offset
+            });
+        }
+
+        // 搜索树：记录为子节点下标或数据偏移（None 表示没有数据）
+        enum Record {
+            Node(usize),
+            Data(usize),
+        }
+        let mut nodes: Vec<[Option<Record>; 2]> = vec![[None, None]];
+        for &(network, country) in networks {
+            let (ip, prefix_len) = network.split_once('/').unwrap();
+            let prefix_len: usize = prefix_len.parse().unwrap();
+            let (bits, prefix_len) = match ip.parse::<IpAddr>().unwrap() {
+                IpAddr::V4(ip) => (u32::from(ip) as u128, prefix_len + 96),
+                IpAddr::V6(ip) => (u128::from(ip), prefix_len),
+            };
+            let mut node = 0;
+            for i in 0..prefix_len {
+                let bit = ((bits >> (127 - i)) & 1) as usize;
+                if i + 1 == prefix_len {
+                    nodes[node][bit] = Some(Record::Data(offsets[country]));
+                    break;
+                }
+                node = match nodes[node][bit] {
+                    Some(Record::Node(child)) => child,
+                    _ => {
+                        nodes.push([None, None]);
+                        nodes[node][bit] = Some(Record::Node(nodes.len() - 1));
+                        nodes.len() - 1
+                    }
+                };
+            }
+        }
+
+        let node_count = nodes.len();
+        let mut out = Vec::new();
+        for node in &nodes {
+            for record in node {
+                let value = match record {
+                    Some(Record::Node(child)) => *child,
+                    Some(Record::Data(offset)) => node_count + 16 + offset,
+                    None => node_count,
+                };
+                out.extend_from_slice(&(value as u32).to_be_bytes()[1..]);
+            }
+        }
+        out.extend_from_slice(&[0; 16]);
+        out.extend_from_slice(&data);
+
+        out.extend_from_slice(b"\xab\xcd\xefMaxMind.com");
+        put_map(&mut out, 9);
+        put_string(&mut out, "binary_format_major_version");
+        put_uint(&mut out, 5, 2);
+        put_string(&mut out, "binary_format_minor_version");
+        put_uint(&mut out, 5, 0);
+        put_string(&mut out, "build_epoch");
+        put_uint(&mut out, 9, build_epoch);
+        put_string(&mut out, "database_type");
+        put_string(&mut out, "GeoLite2-Country");
+        put_string(&mut out, "description");
+        put_map(&mut out, 0);
+        put_string(&mut out, "ip_version");
+        put_uint(&mut out, 5, 6);
+        put_string(&mut out, "languages");
+        out.extend_from_slice(&[0, 4]);
+        put_string(&mut out, "node_count");
+        put_uint(&mut out, 6, node_count as u64);
+        put_string(&mut out, "record_size");
+        put_uint(&mut out, 5, 24);
+        out
+    }
+
+    #[test]
+    fn test_country_lookup() {
+        let geoip = GeoIp::from_bytes(test_database(&[
+            ("1.0.0.0/8", "US"),
+            ("2.2.0.0/16", "CN"),
+            ("2001:db8::/32", "DE"),
+        ]))
+        .unwrap();
+        assert_eq!(geoip.database_type(), "GeoLite2-Country");
+        let country = |ip: &str| geoip.country(ip.parse().unwrap()).map(|c| c.to_string());
+        assert_eq!(country("1.2.3.4").as_deref(), Some("US"));
+        assert_eq!(country("::ffff:2.2.9.9").as_deref(), Some("CN"));
+        assert_eq!(country("2001:db8::1").as_deref(), Some("DE"));
+        assert_eq!(country("2.3.0.1"), None);
+        assert_eq!(country("192.168.1.1"), None);
+
+        assert!(GeoIp::from_bytes(b"not a database".to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_geo_filter() {
+        let codes = |codes: &[&str]| codes.iter().map(|c| c.parse().unwrap()).collect::<Vec<CountryCode>>();
+        let us: Option<CountryCode> = "us".parse().ok();
+        let cn: Option<CountryCode> = "CN".parse().ok();
+        assert_eq!(us.unwrap().as_str(), "US");
+        assert!("USA".parse::<CountryCode>().is_err() && "1A".parse::<CountryCode>().is_err());
+
+        let allow = GeoFilter::new(codes(&["US", "CA"]), Vec::new());
+        assert!(allow.allows(us) && !allow.allows(cn) && allow.allows(None));
+        let deny = GeoFilter::new(Vec::new(), codes(&["CN"]));
+        assert!(deny.allows(us) && !deny.allows(cn) && deny.allows(None));
+    }
+
+    #[tokio::test]
+    async fn test_download() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let server = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://127.0.0.1:{}/GeoLite2-Country.mmdb", server.local_addr().unwrap().port());
+        let path = std::env::temp_dir().join(format!("sni-proxy-geoip-{}.mmdb", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let update = GeoIpUpdate::new(url.parse().unwrap(), path.clone());
+
+        let database = test_database_built_at(&[("1.0.0.0/8", "US")], 1_800_000_000);
+        // 和 MaxMind 官方下载一样，数据库放在 tar.gz 压缩包的子目录中
+        let mut archive = tar::Builder::new(flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(database.len() as u64);
+        header.set_cksum();
+        archive
+            .append_data(&mut header, "GeoLite2-Country_20270115/GeoLite2-Country.mmdb", database.as_slice())
+            .unwrap();
+        let body = archive.into_inner().unwrap().finish().unwrap();
+        let responses = [
+            format!("HTTP/1.0 200 OK\r\nContent-Length: {}\r\n\r\n", body.len()).into_bytes(),
+            format!("HTTP/1.0 200 OK\r\nContent-Length: {}\r\n\r\n", body.len()).into_bytes(),
+            b"HTTP/1.0 304 Not Modified\r\n\r\n".to_vec(),
+            b"HTTP/1.0 200 OK\r\n\r\nnot a database".to_vec(),
+        ];
+        let handle = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for (i, head) in responses.into_iter().enumerate() {
+                let (mut stream, _) = server.accept().await.unwrap();
+                let mut request = vec![0u8; 4096];
+                let n = stream.read(&mut request).await.unwrap();
+                requests.push(String::from_utf8_lossy(&request[..n]).into_owned());
+                stream.write_all(&head).await.unwrap();
+                if i < 2 {
+                    stream.write_all(&body).await.unwrap();
+                }
+            }
+            requests
+        });
+
+        let geoip = update.download(1_700_000_000).await.unwrap().unwrap();
+        assert_eq!(geoip.build_epoch(), 1_800_000_000);
+        assert_eq!(std::fs::read(&path).unwrap(), database);
+        // 不比当前数据库新时不替换
+        assert!(update.download(1_800_000_000).await.unwrap().is_none());
+        assert!(update.download(0).await.unwrap().is_none());
+        assert!(update.download(0).await.is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), database);
+
+        let requests = handle.await.unwrap();
+        assert!(!requests[0].contains("If-Modified-Since"));
+        assert!(requests[1].contains("If-Modified-Since: "));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_license_key_requires_https() {
+        let mut update = GeoIpUpdate::new("http://127.0.0.1/db.tar.gz".parse().unwrap(), PathBuf::from("db.mmdb"));
+        update.validate().unwrap();
+        update.account_id = Some("42".to_string());
+        update.license_key = Some("key".to_string());
+        assert!(update.validate().is_err());
+
+        update.url = "https://download.maxmind.com/geoip/databases/GeoLite2-Country/download?suffix=tar.gz"
+            .parse()
+            .unwrap();
+        update.validate().unwrap();
+        assert_eq!(update.authorization().as_deref(), Some("Basic NDI6a2V5"));
+    }
+}
@@ -0,0 +1,101 @@
+//! 握手阶段保护
+//!
+//! 慢速客户端（slowloris）建立连接后迟迟不发送完整的 Client Hello，每个连接都占用一个并发连接许可直到读取超时，
+//! 大量这样的连接会占满并发连接数，正常客户端只能排队。开启后握手阶段（读取到 Client Hello 之前）的连接
+//! 只占用单独的握手许可：握手中的连接数达到上限时新连接立即关闭，读取 Client Hello 使用单独的截止时间；
+//! 读取到 Client Hello 后释放握手许可，再获取并发连接许可进入转发阶段
+
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// 握手阶段保护配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandshakeConfig {
+    /// 最多同时处于握手阶段的连接数
+    pub max_handshaking: usize,
+    /// 读取完整 Client Hello 的截止时间（从开始读取算起）
+    pub hello_timeout: Duration,
+}
+
+impl Default for HandshakeConfig {
+    fn default() -> Self {
+        Self {
+            max_handshaking: 1024,
+            hello_timeout: Duration::from_secs(2),
+        }
+    }
+}
+
+/// 握手中的连接数限制
+#[derive(Debug)]
+pub(crate) struct HandshakeLimit {
+    config: HandshakeConfig,
+    semaphore: Arc<Semaphore>,
+}
+
+impl HandshakeLimit {
+    pub(crate) fn new(config: HandshakeConfig) -> Self {
+        let config = HandshakeConfig { max_handshaking: config.max_handshaking.max(1), ..config };
+        Self { config, semaphore: Arc::new(Semaphore::new(config.max_handshaking)) }
+    }
+
+    pub(crate) fn config(&self) -> HandshakeConfig {
+        self.config
+    }
+
+    /// 获取握手许可（握手完成后换成 `connections` 的并发连接许可），握手中的连接数已满时返回 None
+    pub(crate) fn try_acquire(&self, connections: &Arc<Semaphore>) -> Option<HandshakePermit> {
+        let permit = Arc::clone(&self.semaphore).try_acquire_owned().ok()?;
+        Some(HandshakePermit { permit, connections: Arc::clone(connections) })
+    }
+}
+
+/// 握手许可
+#[derive(Debug)]
+pub(crate) struct HandshakePermit {
+    permit: OwnedSemaphorePermit,
+    connections: Arc<Semaphore>,
+}
+
+impl HandshakePermit {
+    /// 并发连接许可是否已经用完（进入转发阶段需要排队）
+    pub(crate) fn connections_full(&self) -> bool {
+        self.connections.available_permits() == 0
+    }
+
+    /// 握手完成：先释放握手许可（排队的连接不再占用握手名额），再等待并发连接许可
+    pub(crate) async fn establish(self) -> Option<OwnedSemaphorePermit> {
+        let HandshakePermit { permit, connections } = self;
+        drop(permit);
+        connections.acquire_owned().await.ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_handshake_permits() {
+        let limit = HandshakeLimit::new(HandshakeConfig { max_handshaking: 2, ..Default::default() });
+        let connections = Arc::new(Semaphore::new(1));
+        let first = limit.try_acquire(&connections).unwrap();
+        let second = limit.try_acquire(&connections).unwrap();
+        assert!(limit.try_acquire(&connections).is_none());
+
+        // 握手完成后释放握手许可，换成并发连接许可
+        let established = first.establish().await.unwrap();
+        assert_eq!(connections.available_permits(), 0);
+        assert!(second.connections_full());
+        let third = limit.try_acquire(&connections).unwrap();
+
+        // 并发连接数已满时排队等待，不占用握手许可
+        let waiting = tokio::spawn(second.establish());
+        tokio::task::yield_now().await;
+        assert!(limit.try_acquire(&connections).is_some());
+        drop(established);
+        assert!(waiting.await.unwrap().is_some());
+        drop(third);
+    }
+}
@@ -0,0 +1,154 @@
+//! Happy Eyeballs 双栈连接（RFC 8305）
+//!
+//! DNS 同时返回 IPv6 和 IPv4 地址时，只连接第一个地址会在 IPv6（或 IPv4）不通的网络上一直等到连接超时。
+//! 这里按 RFC 8305 把地址按地址族交替排列（偏好的地址族在前，见 `dns.family`），依次发起连接：
+//! 前一个连接在 [`CONNECTION_ATTEMPT_DELAY`] 内没有结果时发起下一个（失败时立即发起下一个），
+//! 第一个成功的连接胜出，其余的连接被取消
+
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+use crate::outbound::OutboundOptions;
+
+/// 发起下一个连接前等待的时间（RFC 8305 推荐 250 毫秒）
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// 按地址族交替排列地址，第一个地址的地址族在前，同一地址族内保持原来的顺序
+pub fn interleave_families(ips: &[IpAddr]) -> Vec<IpAddr> {
+    let Some(first) = ips.first() else {
+        return Vec::new();
+    };
+    let (preferred, other): (Vec<IpAddr>, Vec<IpAddr>) =
+        ips.iter().partition(|ip| ip.is_ipv6() == first.is_ipv6());
+    let mut preferred = preferred.into_iter();
+    let mut other = other.into_iter();
+    let mut sorted = Vec::with_capacity(ips.len());
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => return sorted,
+            (a, b) => sorted.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+/// 按 Happy Eyeballs 连接到地址列表，返回第一个成功的连接和它的地址
+///
+/// 每个连接都按 `outbound` 创建 socket；所有连接都失败时返回最后一个错误，总超时由调用方控制。
+/// 不使用 TCP Fast Open：有 cookie 时 connect 在发送 SYN 之前就返回成功，无法判断哪个地址可达，
+/// 连接超时、重试和上游回退也都依赖 connect 的结果
+pub async fn connect(ips: &[IpAddr], port: u16, outbound: &OutboundOptions) -> io::Result<(TcpStream, SocketAddr)> {
+    let addrs = interleave_families(ips).into_iter().map(|ip| SocketAddr::new(ip, port)).collect();
+    let outbound = OutboundOptions { fastopen: None, ..outbound.clone() };
+    race(addrs, CONNECTION_ATTEMPT_DELAY, |addr| outbound.connect(addr)).await
+}
+
+/// 依次发起连接，前一个在 `delay` 内没有结果（或已经失败）时发起下一个，第一个成功的胜出
+pub(crate) async fn race<T, F, Fut>(addrs: Vec<SocketAddr>, delay: Duration, connect: F) -> io::Result<(T, SocketAddr)>
+where
+    F: Fn(SocketAddr) -> Fut,
+    Fut: Future<Output = io::Result<T>>,
+{
+    let attempt = |addr: SocketAddr| {
+        let connecting = connect(addr);
+        async move { (addr, connecting.await) }
+    };
+    let mut remaining = addrs.into_iter();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_error = None;
+
+    loop {
+        if attempts.is_empty() {
+            match remaining.next() {
+                Some(addr) => attempts.push(attempt(addr)),
+                None => {
+                    return Err(last_error.unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "没有可连接的地址")));
+                }
+            }
+        }
+
+        tokio::select! {
+            Some((addr, result)) = attempts.next() => match result {
+                Ok(stream) => return Ok((stream, addr)),
+                Err(e) => {
+                    log::debug!("连接 {} 失败: {}", addr, e);
+                    last_error = Some(e);
+                    if let Some(next) = remaining.next() {
+                        attempts.push(attempt(next));
+                    }
+                }
+            },
+            _ = tokio::time::sleep(delay), if !remaining.as_slice().is_empty() => {
+                if let Some(next) = remaining.next() {
+                    attempts.push(attempt(next));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::Instant;
+
+    fn ips(list: &[&str]) -> Vec<IpAddr> {
+        list.iter().map(|ip| ip.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_interleave_families() {
+        assert_eq!(
+            interleave_families(&ips(&["2001:db8::1", "2001:db8::2", "2001:db8::3", "192.0.2.1"])),
+            ips(&["2001:db8::1", "192.0.2.1", "2001:db8::2", "2001:db8::3"])
+        );
+        // ipv4_first 时 IPv4 地址在前
+        assert_eq!(
+            interleave_families(&ips(&["192.0.2.1", "192.0.2.2", "2001:db8::1"])),
+            ips(&["192.0.2.1", "2001:db8::1", "192.0.2.2"])
+        );
+        assert!(interleave_families(&[]).is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_race_falls_back_after_delay() {
+        let addrs: Vec<SocketAddr> = vec!["[2001:db8::1]:443".parse().unwrap(), "192.0.2.1:443".parse().unwrap()];
+        let start = Instant::now();
+
+        // IPv6 没有响应：250 毫秒后发起 IPv4 连接并胜出
+        let (_, addr) = race(addrs.clone(), CONNECTION_ATTEMPT_DELAY, |addr: SocketAddr| async move {
+            if addr.is_ipv6() {
+                std::future::pending::<()>().await;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            Ok(())
+        })
+        .await
+        .unwrap();
+        assert!(addr.is_ipv4());
+        assert_eq!(start.elapsed(), Duration::from_millis(260));
+
+        // IPv6 立即失败：不等待，直接发起 IPv4 连接
+        let start = Instant::now();
+        let (_, addr) = race(addrs.clone(), CONNECTION_ATTEMPT_DELAY, |addr: SocketAddr| async move {
+            if addr.is_ipv6() {
+                return Err(io::Error::from(io::ErrorKind::ConnectionRefused));
+            }
+            Ok(())
+        })
+        .await
+        .unwrap();
+        assert!(addr.is_ipv4());
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        // 全部失败时返回最后一个错误
+        let result = race(addrs, CONNECTION_ATTEMPT_DELAY, |addr: SocketAddr| async move {
+            Err::<(), _>(io::Error::new(io::ErrorKind::ConnectionRefused, addr.to_string()))
+        })
+        .await;
+        assert_eq!(result.unwrap_err().to_string(), "192.0.2.1:443");
+    }
+}
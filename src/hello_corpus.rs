@@ -0,0 +1,286 @@
+//! Client Hello 语料库
+//!
+//! 线上采集（需显式开启）的 Client Hello 经过脱敏后追加写入 JSON Lines 语料文件，
+//! 每行记录当时解析出的 SNI 和 Client Hello 原始字节（十六进制）。
+//! 语料文件提交到 `testdata/` 后由回归测试重放，防止解析器在版本之间出现回归
+
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::tls::{handshake_record_len, parse_sni};
+
+/// server_name 扩展
+const EXT_SERVER_NAME: u16 = 0;
+/// session_ticket 扩展（包含可关联用户的会话票据）
+const EXT_SESSION_TICKET: u16 = 35;
+/// pre_shared_key 扩展（包含 PSK 身份和绑定值）
+const EXT_PRE_SHARED_KEY: u16 = 41;
+
+/// 语料库中的一条记录
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CorpusEntry {
+    /// 采集时解析出的 SNI（脱敏后的 Client Hello 上的解析结果，无法解析时为 None）
+    pub sni: Option<String>,
+    /// 脱敏后的 Client Hello（十六进制）
+    pub hello: String,
+}
+
+impl CorpusEntry {
+    /// 解码 Client Hello 字节
+    pub fn hello_bytes(&self) -> Option<Vec<u8>> {
+        from_hex(&self.hello)
+    }
+}
+
+/// 读取语料文件（JSON Lines，忽略空行）
+pub fn load_corpus(path: &str) -> std::io::Result<Vec<CorpusEntry>> {
+    parse_corpus(BufReader::new(File::open(path)?))
+}
+
+fn parse_corpus(reader: impl BufRead) -> std::io::Result<Vec<CorpusEntry>> {
+    let mut entries = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// 脱敏 Client Hello
+///
+/// 清零随机数、Session ID、会话票据和 PSK 数据；`redact_sni` 时把 SNI 中的字母和数字替换为 `x`
+/// （保留长度和点号，脱敏后仍是合法的域名）。只保留第一个握手记录，结构无法解析时返回 None
+pub fn sanitize_client_hello(data: &[u8], redact_sni: bool) -> Option<Vec<u8>> {
+    let record_len = handshake_record_len(data)?;
+    let mut hello = data[..record_len.min(data.len())].to_vec();
+
+    // 记录头(5) + 握手类型(1) + 握手长度(3) + 版本(2)
+    if hello.get(5) != Some(&0x01) {
+        return None;
+    }
+    let mut pos = 11;
+
+    // 随机数
+    hello.get_mut(pos..pos + 32)?.fill(0);
+    pos += 32;
+
+    // Session ID
+    let session_id_len = *hello.get(pos)? as usize;
+    pos += 1;
+    hello.get_mut(pos..pos + session_id_len)?.fill(0);
+    pos += session_id_len;
+
+    // Cipher Suites、Compression Methods
+    pos += 2 + read_u16(&hello, pos)? as usize;
+    pos += 1 + *hello.get(pos)? as usize;
+
+    // 扩展
+    let extensions_end = (pos + 2 + read_u16(&hello, pos)? as usize).min(hello.len());
+    pos += 2;
+    while pos + 4 <= extensions_end {
+        let ext_type = read_u16(&hello, pos)?;
+        let ext_len = read_u16(&hello, pos + 2)? as usize;
+        let body = pos + 4..pos + 4 + ext_len;
+        if body.end > extensions_end {
+            return None;
+        }
+        match ext_type {
+            EXT_SERVER_NAME if redact_sni => redact_server_names(hello.get_mut(body.clone())?)?,
+            EXT_SESSION_TICKET | EXT_PRE_SHARED_KEY => hello[body.clone()].fill(0),
+            _ => {}
+        }
+        pos = body.end;
+    }
+
+    Some(hello)
+}
+
+/// 替换 server_name 扩展中的全部域名
+fn redact_server_names(ext: &mut [u8]) -> Option<()> {
+    let list_end = (2 + read_u16(ext, 0)? as usize).min(ext.len());
+    let mut pos = 2;
+    while pos + 3 <= list_end {
+        let name_len = read_u16(ext, pos + 1)? as usize;
+        pos += 3;
+        for byte in ext.get_mut(pos..pos + name_len)? {
+            if byte.is_ascii_alphanumeric() {
+                *byte = b'x';
+            }
+        }
+        pos += name_len;
+    }
+    Some(())
+}
+
+fn read_u16(data: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*data.get(pos)?, *data.get(pos + 1)?]))
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Client Hello 采集器（追加写入语料文件，按脱敏后的内容去重）
+#[derive(Clone)]
+pub struct HelloRecorder {
+    inner: Arc<Mutex<RecorderInner>>,
+    /// 是否保留真实 SNI（默认脱敏）
+    include_sni: bool,
+    /// 语料文件最多保存的条目数（包含文件中已有的条目）
+    max_entries: usize,
+}
+
+struct RecorderInner {
+    file: File,
+    path: String,
+    /// 已记录内容的哈希（去重）
+    seen: HashSet<u64>,
+}
+
+impl HelloRecorder {
+    /// 打开语料文件（不存在时创建），已有条目计入去重和数量上限
+    pub fn new(path: impl Into<String>, include_sni: bool, max_entries: usize) -> std::io::Result<Self> {
+        let path = path.into();
+        let existing = match File::open(&path) {
+            Ok(file) => parse_corpus(BufReader::new(file))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        let seen = existing
+            .iter()
+            .filter_map(CorpusEntry::hello_bytes)
+            .map(|hello| content_hash(&hello))
+            .collect();
+        let file = crate::output_file::append(&path)?;
+
+        Ok(Self {
+            inner: Arc::new(Mutex::new(RecorderInner { file, path, seen })),
+            include_sni,
+            max_entries,
+        })
+    }
+
+    /// 已记录的条目数
+    pub fn recorded(&self) -> usize {
+        self.inner.lock().unwrap().seen.len()
+    }
+
+    /// 记录一个 Client Hello（达到数量上限、重复或结构无法解析时忽略）
+    pub fn record(&self, data: &[u8]) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.seen.len() >= self.max_entries {
+            return;
+        }
+        let Some(hello) = sanitize_client_hello(data, !self.include_sni) else {
+            debug!("Client Hello 结构无法解析，不加入语料库");
+            return;
+        };
+        if !inner.seen.insert(content_hash(&hello)) {
+            return;
+        }
+
+        let entry = CorpusEntry {
+            sni: parse_sni(&hello),
+            hello: to_hex(&hello),
+        };
+        let mut line = serde_json::to_string(&entry).expect("CorpusEntry 序列化不会失败");
+        line.push('\n');
+        if let Err(e) = inner.file.write_all(line.as_bytes()) {
+            error!("写入 Client Hello 语料文件 {} 失败: {}", inner.path, e);
+            return;
+        }
+
+        if inner.seen.len() == self.max_entries {
+            info!("💾 Client Hello 语料库已达到 {} 条，停止采集: {}", self.max_entries, inner.path);
+        }
+    }
+}
+
+fn content_hash(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tls::parse_client_hello;
+    use crate::tls::tests::client_hello;
+
+    #[test]
+    fn test_corpus_regression() {
+        let corpus = parse_corpus(include_str!("../testdata/client_hello_corpus.jsonl").as_bytes()).unwrap();
+        assert!(!corpus.is_empty());
+        for (line, entry) in corpus.iter().enumerate() {
+            let hello = entry.hello_bytes().unwrap_or_else(|| panic!("第 {} 条记录不是合法的十六进制", line + 1));
+            assert_eq!(parse_sni(&hello), entry.sni, "第 {} 条记录解析结果变化", line + 1);
+            // 语料库中的记录结构完整，必须能解析出 Cipher Suites
+            let info = parse_client_hello(&hello).unwrap_or_else(|| panic!("第 {} 条记录无法解析", line + 1));
+            assert_eq!(info.sni, entry.sni);
+            assert!(!info.cipher_suites.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_sanitize_client_hello() {
+        let mut hello = client_hello("www.example-1.com");
+        hello[11..43].fill(0xab);
+
+        let sanitized = sanitize_client_hello(&hello, true).unwrap();
+        assert!(sanitized[11..43].iter().all(|&b| b == 0));
+        assert_eq!(parse_sni(&sanitized), Some("xxx.xxxxxxx-x.xxx".to_string()));
+
+        let kept = sanitize_client_hello(&hello, false).unwrap();
+        assert_eq!(parse_sni(&kept), Some("www.example-1.com".to_string()));
+
+        assert_eq!(sanitize_client_hello(b"GET / HTTP/1.1\r\n", true), None);
+        assert_eq!(sanitize_client_hello(&hello[..40], true), None);
+    }
+
+    #[test]
+    fn test_recorder_dedup_and_limit() {
+        let path = std::env::temp_dir().join(format!("sni-proxy-corpus-{}.jsonl", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        let _ = std::fs::remove_file(&path);
+
+        let recorder = HelloRecorder::new(&path, false, 2).unwrap();
+        recorder.record(&client_hello("a.example.com"));
+        // 脱敏后与上一条相同
+        recorder.record(&client_hello("b.example.com"));
+        recorder.record(&client_hello("long.example.com"));
+        recorder.record(&client_hello("longer.example.com"));
+        assert_eq!(recorder.recorded(), 2);
+
+        let entries = load_corpus(&path).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].sni.as_deref(), Some("x.xxxxxxx.xxx"));
+        assert_eq!(entries[1].sni.as_deref(), Some("xxxx.xxxxxxx.xxx"));
+
+        // 重新打开时已有条目计入上限
+        let reopened = HelloRecorder::new(&path, false, 2).unwrap();
+        reopened.record(&client_hello("other.example.org"));
+        assert_eq!(load_corpus(&path).unwrap().len(), 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,255 @@
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::time::timeout;
+
+use crate::error::{Result, SniProxyError};
+
+/// 默认 HTTP 目标端口
+pub const DEFAULT_HTTP_PORT: u16 = 80;
+
+/// TLS 端口收到明文 HTTP 请求时的处理方式（未开启 HTTP 嗅探时生效）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PlaintextHttpAction {
+    /// 直接关闭连接
+    #[default]
+    Close,
+    /// 返回 400，说明此端口只接受 HTTPS
+    BadRequest,
+    /// 白名单域名返回 301 重定向到 `https://<host><path>`，其他返回 403
+    Redirect,
+}
+
+impl std::str::FromStr for PlaintextHttpAction {
+    type Err = SniProxyError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "close" => Ok(PlaintextHttpAction::Close),
+            "bad_request" => Ok(PlaintextHttpAction::BadRequest),
+            "redirect" => Ok(PlaintextHttpAction::Redirect),
+            _ => Err(SniProxyError::InvalidConfig(format!(
+                "无效的明文 HTTP 处理方式: {}（可选: close, bad_request, redirect）",
+                s
+            ))),
+        }
+    }
+}
+
+impl std::fmt::Display for PlaintextHttpAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlaintextHttpAction::Close => write!(f, "close"),
+            PlaintextHttpAction::BadRequest => write!(f, "bad_request"),
+            PlaintextHttpAction::Redirect => write!(f, "redirect"),
+        }
+    }
+}
+
+/// 判断数据是否像 HTTP 请求（以常见方法名加空格开头）
+pub fn looks_like_http(data: &[u8]) -> bool {
+    const METHODS: [&[u8]; 9] = [
+        b"GET ", b"POST ", b"HEAD ", b"PUT ", b"DELETE ", b"OPTIONS ", b"PATCH ", b"CONNECT ", b"TRACE ",
+    ];
+    METHODS.iter().any(|method| data.starts_with(method))
+}
+
+/// 从 HTTP 请求头中解析 Host（去掉端口并转为小写）
+///
+/// 只解析已读取数据中的完整头部行；请求行必须是 HTTP/1.x 格式
+pub fn parse_http_host(data: &[u8]) -> Option<String> {
+    if !looks_like_http(data) {
+        return None;
+    }
+
+    let text = std::str::from_utf8(head_bytes(data)).ok()?;
+    let mut lines = text.split("\r\n");
+
+    // 请求行: METHOD SP request-target SP HTTP/1.x
+    let request_line = lines.next()?;
+    if !request_line.rsplit(' ').next()?.starts_with("HTTP/1.") {
+        return None;
+    }
+
+    for line in lines {
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(':')?;
+        if name.trim().eq_ignore_ascii_case("host") {
+            return normalize_host(value.trim());
+        }
+    }
+
+    None
+}
+
+/// 从 HTTP 请求行中解析请求目标（路径 + 查询参数）
+///
+/// 绝对形式（`http://host/path`）会去掉协议和主机部分；`*` 或空路径返回 `/`
+pub fn parse_request_target(data: &[u8]) -> Option<String> {
+    let line_end = data.windows(2).position(|w| w == b"\r\n")?;
+    let request_line = std::str::from_utf8(&data[..line_end]).ok()?;
+
+    let mut parts = request_line.split(' ');
+    let (_method, target, version) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() || !version.starts_with("HTTP/1.") {
+        return None;
+    }
+
+    let path = match target.split_once("://") {
+        Some((_, rest)) => rest.find('/').map(|pos| &rest[pos..]).unwrap_or("/"),
+        None => target,
+    };
+    if path.starts_with('/') {
+        Some(path.to_string())
+    } else {
+        Some("/".to_string())
+    }
+}
+
+/// 请求头是否已完整接收（遇到空行）
+pub fn head_complete(data: &[u8]) -> bool {
+    data.windows(4).any(|w| w == b"\r\n\r\n")
+}
+
+/// 单独处理 HTTP 请求的监听（管理接口、重定向）读取请求头的长度上限
+pub(crate) const MAX_REQUEST_HEAD: usize = 8192;
+
+/// [`read_request_head`] 的结果
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum RequestHead {
+    /// 已读到空行，包含请求头（可能带有部分正文）
+    Complete(Vec<u8>),
+    /// 连接在请求头结束前关闭，或请求头超过 [`MAX_REQUEST_HEAD`]
+    Invalid,
+    /// 超时
+    TimedOut,
+}
+
+/// 读取一个请求头，直到遇到空行
+pub(crate) async fn read_request_head<S>(stream: &mut S, read_timeout: Duration) -> std::io::Result<RequestHead>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut buffer = Vec::with_capacity(1024);
+    let mut chunk = [0u8; 1024];
+    let read = async {
+        loop {
+            let n = stream.read(&mut chunk).await?;
+            if n == 0 {
+                return Ok(RequestHead::Invalid);
+            }
+            buffer.extend_from_slice(&chunk[..n]);
+            if head_complete(&buffer) {
+                return Ok(RequestHead::Complete(std::mem::take(&mut buffer)));
+            }
+            if buffer.len() > MAX_REQUEST_HEAD {
+                return Ok(RequestHead::Invalid);
+            }
+        }
+    };
+    timeout(read_timeout, read).await.unwrap_or(Ok(RequestHead::TimedOut))
+}
+
+/// 截取请求头部分（到空行为止；没有空行时截到最后一个完整行）
+fn head_bytes(data: &[u8]) -> &[u8] {
+    if let Some(pos) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+        return &data[..pos + 2];
+    }
+    match data.windows(2).rposition(|w| w == b"\r\n") {
+        Some(pos) => &data[..pos + 2],
+        None => &[],
+    }
+}
+
+/// 规范化 Host 值：去掉端口、IPv6 方括号保留，转为小写
+fn normalize_host(value: &str) -> Option<String> {
+    let host = if value.starts_with('[') {
+        // IPv6 字面量: [::1]:8080
+        let end = value.find(']')?;
+        &value[..=end]
+    } else {
+        match value.rsplit_once(':') {
+            Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host,
+            _ => value,
+        }
+    };
+
+    if host.is_empty() || host.contains(char::is_whitespace) {
+        return None;
+    }
+    Some(host.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_http_host() {
+        let request = b"GET /index.html HTTP/1.1\r\nHost: Example.COM\r\nUser-Agent: curl\r\n\r\n";
+        assert_eq!(parse_http_host(request), Some("example.com".to_string()));
+
+        let request = b"POST / HTTP/1.0\r\nContent-Length: 0\r\nhost: example.com:8080\r\n\r\n";
+        assert_eq!(parse_http_host(request), Some("example.com".to_string()));
+
+        let request = b"GET / HTTP/1.1\r\nHost: [::1]:80\r\n\r\n";
+        assert_eq!(parse_http_host(request), Some("[::1]".to_string()));
+    }
+
+    #[test]
+    fn test_parse_http_host_partial_head() {
+        // 头部未读完时，只使用已完整读到的行
+        let request = b"GET / HTTP/1.1\r\nHost: example.com\r\nAccept: */";
+        assert_eq!(parse_http_host(request), Some("example.com".to_string()));
+
+        let request = b"GET / HTTP/1.1\r\nHost: exam";
+        assert_eq!(parse_http_host(request), None);
+    }
+
+    #[test]
+    fn test_parse_request_target() {
+        let request = b"GET /a/b?c=1 HTTP/1.1\r\nHost: example.com\r\n\r\n";
+        assert_eq!(parse_request_target(request), Some("/a/b?c=1".to_string()));
+
+        let request = b"GET http://example.com/x HTTP/1.1\r\n\r\n";
+        assert_eq!(parse_request_target(request), Some("/x".to_string()));
+
+        assert_eq!(parse_request_target(b"OPTIONS * HTTP/1.1\r\n\r\n"), Some("/".to_string()));
+        assert_eq!(parse_request_target(b"GET / HTTP/1.1"), None);
+        assert_eq!(parse_request_target(b"GET /a b HTTP/1.1\r\n\r\n"), None);
+    }
+
+    #[test]
+    fn test_parse_http_host_invalid() {
+        assert_eq!(parse_http_host(b"\x16\x03\x01\x00\x05hello"), None);
+        assert_eq!(parse_http_host(b"GET / HTTP/1.1\r\nAccept: */*\r\n\r\n"), None);
+        assert_eq!(parse_http_host(b"GET / SPDY/3\r\nHost: example.com\r\n\r\n"), None);
+        assert_eq!(parse_http_host(b"GET / HTTP/1.1\r\nHost: \r\n\r\n"), None);
+    }
+
+    #[test]
+    fn test_parse_plaintext_http_action() {
+        assert_eq!("close".parse::<PlaintextHttpAction>().unwrap(), PlaintextHttpAction::Close);
+        assert_eq!("bad_request".parse::<PlaintextHttpAction>().unwrap(), PlaintextHttpAction::BadRequest);
+        assert_eq!("redirect".parse::<PlaintextHttpAction>().unwrap(), PlaintextHttpAction::Redirect);
+        assert_eq!(PlaintextHttpAction::BadRequest.to_string(), "bad_request");
+        assert!("400".parse::<PlaintextHttpAction>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_request_head() {
+        let timeout = Duration::from_secs(1);
+        let mut complete: &[u8] = b"GET / HTTP/1.1\r\nHost: a.com\r\n\r\n";
+        assert!(matches!(read_request_head(&mut complete, timeout).await.unwrap(), RequestHead::Complete(head) if head.ends_with(b"\r\n\r\n")));
+        let mut truncated: &[u8] = b"GET / HTTP/1.1\r\nHost: a.com\r\n";
+        assert_eq!(read_request_head(&mut truncated, timeout).await.unwrap(), RequestHead::Invalid);
+        let oversized = vec![b'a'; MAX_REQUEST_HEAD + 1024];
+        assert_eq!(read_request_head(&mut oversized.as_slice(), timeout).await.unwrap(), RequestHead::Invalid);
+
+        let (mut client, mut server) = tokio::io::duplex(64);
+        tokio::io::AsyncWriteExt::write_all(&mut client, b"GET / HTTP/1.1\r\n").await.unwrap();
+        let result = read_request_head(&mut server, Duration::from_millis(50)).await.unwrap();
+        assert_eq!(result, RequestHead::TimedOut);
+    }
+}
@@ -0,0 +1,347 @@
+//! 最小 HTTP/1.x 客户端
+//!
+//! 供 Webhook 推送、远程白名单、GeoIP 数据库下载和 InfluxDB 写入共用，只实现这些场景需要的
+//! 单次 GET / POST，不做连接复用；GET 可以跟随重定向（[`http_get_following`]，不允许从 https 降级到 http）。
+//! https:// 地址使用 rustls 和内置的 webpki 根证书验证服务器
+
+use std::fmt::Write as _;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::{ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName};
+use tokio_rustls::TlsConnector;
+
+use crate::error::{Result, SniProxyError};
+
+/// HTTP 地址（http:// 或 https://）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpUrl {
+    tls: bool,
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl HttpUrl {
+    /// 是否使用 HTTPS
+    pub fn is_tls(&self) -> bool {
+        self.tls
+    }
+
+    /// 主机是否为回环地址（经本地转发时明文也不会离开本机）
+    pub fn is_loopback(&self) -> bool {
+        self.host == "localhost" || self.host.parse::<std::net::IpAddr>().is_ok_and(|ip| ip.is_loopback())
+    }
+}
+
+impl FromStr for HttpUrl {
+    type Err = SniProxyError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || SniProxyError::InvalidConfig(format!("无效的 HTTP 地址: {}（应为 http(s)://host[:port]/path）", s));
+        let (tls, rest) = match s.strip_prefix("https://") {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix("http://").ok_or_else(invalid)?),
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        // IPv6 地址使用 [::1]:port 形式
+        let (host, port) = match authority.strip_prefix('[') {
+            Some(rest) => {
+                let (host, port) = rest.split_once(']').ok_or_else(invalid)?;
+                (host, port.strip_prefix(':'))
+            }
+            None => match authority.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            },
+        };
+        let port = match port {
+            Some(port) => port.parse().map_err(|_| invalid())?,
+            None if tls => 443,
+            None => 80,
+        };
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            tls,
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+}
+
+impl std::fmt::Display for HttpUrl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let scheme = if self.tls { "https" } else { "http" };
+        if self.host.contains(':') {
+            write!(f, "{}://[{}]:{}{}", scheme, self.host, self.port, self.path)
+        } else {
+            write!(f, "{}://{}:{}{}", scheme, self.host, self.port, self.path)
+        }
+    }
+}
+
+/// 明文或 TLS 连接
+trait HttpStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> HttpStream for T {}
+
+/// 使用 webpki 根证书的 TLS 客户端配置（进程内共享）
+fn tls_config() -> Arc<ClientConfig> {
+    static CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+    CONFIG
+        .get_or_init(|| {
+            let mut roots = RootCertStore::empty();
+            roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+                OwnedTrustAnchor::from_subject_spki_name_constraints(ta.subject, ta.spki, ta.name_constraints)
+            }));
+            let config = ClientConfig::builder()
+                .with_safe_defaults()
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            Arc::new(config)
+        })
+        .clone()
+}
+
+/// 连接到地址，https:// 时完成 TLS 握手并验证证书
+async fn connect(url: &HttpUrl) -> std::io::Result<Box<dyn HttpStream>> {
+    let stream = TcpStream::connect((url.host.as_str(), url.port)).await?;
+    if !url.tls {
+        return Ok(Box::new(stream));
+    }
+    let server_name = ServerName::try_from(url.host.as_str())
+        .map_err(|_| std::io::Error::other(format!("无效的 TLS 服务器名: {}", url.host)))?;
+    let stream = TlsConnector::from(tls_config()).connect(server_name, stream).await?;
+    Ok(Box::new(stream))
+}
+
+/// 发送 HTTP POST 请求（可选 Authorization 头），非 2xx 响应视为失败
+pub async fn http_post(
+    url: &HttpUrl,
+    content_type: &str,
+    authorization: Option<&str>,
+    body: &str,
+) -> std::io::Result<()> {
+    let mut stream = connect(url).await?;
+    let mut request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        url.path,
+        url.host,
+        content_type,
+        body.len()
+    );
+    if let Some(authorization) = authorization {
+        let _ = write!(request, "Authorization: {}\r\n", authorization);
+    }
+    request.push_str("\r\n");
+    request.push_str(body);
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut head = [0u8; 32];
+    let n = stream.read(&mut head).await?;
+    let status_line = String::from_utf8_lossy(&head[..n]);
+    let status = status_line.split_whitespace().nth(1).unwrap_or("");
+    if !status.starts_with('2') {
+        return Err(std::io::Error::other(format!(
+            "HTTP 接口返回非成功状态: {}",
+            status_line.lines().next().unwrap_or("")
+        )));
+    }
+    Ok(())
+}
+
+/// HTTP 响应
+#[derive(Debug)]
+pub struct HttpResponse {
+    pub status: u16,
+    headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// 响应头的值（名称不区分大小写）
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+}
+
+/// 发送 HTTP GET 请求并读取完整响应（整个响应最多 `max_len` 字节）
+///
+/// 使用 HTTP/1.0，服务器不会使用分块编码，读到连接关闭即为完整响应；有 Content-Length 时检查正文是否完整
+pub async fn http_get(url: &HttpUrl, headers: &[(&str, &str)], max_len: usize) -> std::io::Result<HttpResponse> {
+    let mut stream = connect(url).await?;
+    let mut request = format!("GET {} HTTP/1.0\r\nHost: {}\r\n", url.path, url.host);
+    for (name, value) in headers {
+        let _ = write!(request, "{}: {}\r\n", name, value);
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut response = Vec::new();
+    match (&mut stream).take(max_len as u64 + 1).read_to_end(&mut response).await {
+        Ok(_) => {}
+        // 不少 HTTPS 服务器直接关闭连接而不发送 close_notify，已读到的数据由下面的 Content-Length 检查兜底
+        Err(e) if url.tls && e.kind() == std::io::ErrorKind::UnexpectedEof && !response.is_empty() => {}
+        Err(e) => return Err(e),
+    }
+    if response.len() > max_len {
+        return Err(std::io::Error::other(format!("响应超过 {} 字节", max_len)));
+    }
+    let head_end = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| std::io::Error::other("响应头不完整"))?;
+    let head = String::from_utf8_lossy(&response[..head_end]).into_owned();
+    let mut lines = head.lines();
+    let status_line = lines.next().unwrap_or("");
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| std::io::Error::other(format!("无效的 HTTP 状态行: {}", status_line)))?;
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+    response.drain(..head_end + 4);
+
+    let response = HttpResponse { status, headers, body: response };
+    if let Some(expected) = response.header("Content-Length").and_then(|len| len.parse::<usize>().ok()) {
+        if response.body.len() < expected {
+            return Err(std::io::Error::other(format!(
+                "响应正文不完整（{} / {} 字节）",
+                response.body.len(),
+                expected
+            )));
+        }
+    }
+    Ok(response)
+}
+
+/// 发送 HTTP GET 请求，跟随最多 `max_redirects` 次重定向（301/302/303/307/308，Location 必须是绝对地址）
+///
+/// 重定向到其他来源（协议、主机或端口不同）时不再发送 Authorization 头，例如 MaxMind 的下载地址
+/// 会重定向到预签名的对象存储地址；https 地址重定向到 http 时返回错误
+pub async fn http_get_following(
+    url: &HttpUrl,
+    headers: &[(&str, &str)],
+    max_len: usize,
+    max_redirects: usize,
+) -> std::io::Result<HttpResponse> {
+    let mut current = url.clone();
+    let mut headers = headers.to_vec();
+    for _ in 0..=max_redirects {
+        let response = http_get(&current, &headers, max_len).await?;
+        if !matches!(response.status, 301 | 302 | 303 | 307 | 308) {
+            return Ok(response);
+        }
+        let location = response
+            .header("Location")
+            .ok_or_else(|| std::io::Error::other(format!("{} 返回重定向但没有 Location", current)))?;
+        let next = redirect_target(&current, location)?;
+        if (next.tls, &next.host, next.port) != (current.tls, &current.host, current.port) {
+            headers.retain(|(name, _)| !name.eq_ignore_ascii_case("Authorization"));
+        }
+        current = next;
+    }
+    Err(std::io::Error::other(format!("{} 重定向次数超过 {} 次", url, max_redirects)))
+}
+
+/// 解析重定向的目标地址，拒绝从 https 降级到 http（降级后下载内容可以被篡改）
+fn redirect_target(current: &HttpUrl, location: &str) -> std::io::Result<HttpUrl> {
+    let next: HttpUrl = location
+        .parse()
+        .map_err(|_| std::io::Error::other(format!("{} 重定向到不支持的地址: {}", current, location)))?;
+    if current.tls && !next.tls {
+        return Err(std::io::Error::other(format!("{} 不允许重定向到明文 HTTP 地址: {}", current, location)));
+    }
+    Ok(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_parse_http_url() {
+        let url: HttpUrl = "http://127.0.0.1:8080/hooks/report".parse().unwrap();
+        assert_eq!((url.host.as_str(), url.port, url.path.as_str()), ("127.0.0.1", 8080, "/hooks/report"));
+        let url: HttpUrl = "http://example.com".parse().unwrap();
+        assert_eq!((url.host.as_str(), url.port, url.path.as_str()), ("example.com", 80, "/"));
+        let url: HttpUrl = "http://[::1]:9000/x".parse().unwrap();
+        assert_eq!((url.host.as_str(), url.port), ("::1", 9000));
+        assert!(url.is_loopback());
+        let url: HttpUrl = "https://example.com/list.txt".parse().unwrap();
+        assert!(url.is_tls() && !url.is_loopback());
+        assert_eq!(url.port, 443);
+        assert_eq!(url.to_string(), "https://example.com:443/list.txt");
+        assert!("ftp://example.com/".parse::<HttpUrl>().is_err());
+        assert!("http://example.com:abc/".parse::<HttpUrl>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_http_post() {
+        let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = server.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = server.accept().await.unwrap();
+            let mut request = vec![0u8; 4096];
+            let n = stream.read(&mut request).await.unwrap();
+            stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").await.unwrap();
+            String::from_utf8_lossy(&request[..n]).into_owned()
+        });
+
+        let url: HttpUrl = format!("http://127.0.0.1:{}/write", port).parse().unwrap();
+        http_post(&url, "text/plain", Some("Token abc"), "m v=1").await.unwrap();
+        let request = handle.await.unwrap();
+        assert!(request.starts_with("POST /write HTTP/1.1\r\n"));
+        assert!(request.contains("Authorization: Token abc\r\n"));
+        assert!(request.ends_with("m v=1"));
+    }
+
+    #[test]
+    fn test_redirect_target() {
+        let https: HttpUrl = "https://example.com/db".parse().unwrap();
+        assert!(redirect_target(&https, "https://cdn.example.com/db").unwrap().is_tls());
+        assert!(redirect_target(&https, "http://cdn.example.com/db").is_err());
+        assert!(redirect_target(&https, "/relative").is_err());
+        let http: HttpUrl = "http://example.com/db".parse().unwrap();
+        assert!(redirect_target(&http, "https://cdn.example.com/db").unwrap().is_tls());
+        assert!(!redirect_target(&http, "http://cdn.example.com/db").unwrap().is_tls());
+    }
+
+    #[tokio::test]
+    async fn test_http_get_following_drops_authorization() {
+        let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target_port = target.local_addr().unwrap().port();
+        let origin = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let origin_port = origin.local_addr().unwrap().port();
+
+        async fn answer(server: TcpListener, response: String) -> String {
+            let (mut stream, _) = server.accept().await.unwrap();
+            let mut request = vec![0u8; 4096];
+            let n = stream.read(&mut request).await.unwrap();
+            stream.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8_lossy(&request[..n]).into_owned()
+        }
+        let redirect = format!("HTTP/1.0 302 Found\r\nLocation: http://127.0.0.1:{}/signed?x=1\r\n\r\n", target_port);
+        let origin = tokio::spawn(answer(origin, redirect));
+        let target = tokio::spawn(answer(target, "HTTP/1.0 200 OK\r\nContent-Length: 2\r\n\r\nok".to_string()));
+
+        let url: HttpUrl = format!("http://127.0.0.1:{}/download", origin_port).parse().unwrap();
+        let response = http_get_following(&url, &[("Authorization", "Basic eA==")], 1024, 3).await.unwrap();
+        assert_eq!((response.status, response.body.as_slice()), (200, b"ok".as_slice()));
+        assert!(origin.await.unwrap().contains("Authorization: Basic eA==\r\n"));
+        let request = target.await.unwrap();
+        assert!(request.starts_with("GET /signed?x=1 HTTP/1.0\r\n"));
+        assert!(!request.contains("Authorization"));
+    }
+}
@@ -0,0 +1,263 @@
+//! InfluxDB 行协议导出
+//!
+//! 定期把按域名（SNI）和按客户端 IP 汇总的流量写成 InfluxDB 行协议，追加到文件或 POST 到 HTTP 接口
+//! （InfluxDB `/api/v2/write`、VictoriaMetrics `/write` 等），供不使用 Prometheus 抓取的时序数据库使用。
+//! 每个周期只写出该周期内结束的连接的流量（增量），没有流量的域名和 IP 不输出
+
+use log::{debug, error, warn};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::{Duration, UNIX_EPOCH};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::timeout;
+
+use crate::clock;
+use crate::disk;
+use crate::events::{EventBus, ProxyEvent};
+use crate::http_client::{http_post, HttpUrl};
+
+/// 按域名统计的 measurement 名称
+pub const DOMAIN_MEASUREMENT: &str = "sni_proxy_domain";
+
+/// 按客户端 IP 统计的 measurement 名称
+pub const CLIENT_MEASUREMENT: &str = "sni_proxy_client";
+
+/// 每个周期最多单独输出的域名 / IP 数量，超出的合并到 `_other`，避免异常流量撑大内存和序列数
+const MAX_SERIES_PER_PERIOD: usize = 10_000;
+
+/// 超出数量上限时合并使用的标签值
+const OTHER_TAG: &str = "_other";
+
+/// HTTP 写入超时
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 导出目标
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InfluxTarget {
+    /// 追加到文件
+    File(PathBuf),
+    /// POST 到 HTTP 接口（http:// 或 https://）
+    Http(HttpUrl),
+}
+
+/// InfluxDB 导出配置
+#[derive(Debug, Clone)]
+pub struct InfluxConfig {
+    /// 导出目标
+    pub target: InfluxTarget,
+    /// 导出周期
+    pub interval: Duration,
+    /// HTTP 接口的 Authorization 头（可选），例如 InfluxDB 2.x 的 `Token <token>`
+    pub authorization: Option<String>,
+}
+
+/// 一个域名或客户端 IP 在周期内的流量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Traffic {
+    bytes_received: u64,
+    bytes_sent: u64,
+    connections: u64,
+}
+
+impl Traffic {
+    fn add(&mut self, bytes_received: u64, bytes_sent: u64) {
+        self.bytes_received += bytes_received;
+        self.bytes_sent += bytes_sent;
+        self.connections += 1;
+    }
+}
+
+/// 周期内的流量汇总
+#[derive(Debug, Default)]
+struct TrafficCollector {
+    domains: HashMap<String, Traffic>,
+    clients: HashMap<IpAddr, Traffic>,
+    /// 超出数量上限的域名合并到这里
+    other_domains: Traffic,
+    /// 超出数量上限的客户端 IP 合并到这里
+    other_clients: Traffic,
+    /// 订阅方处理过慢而丢失的事件数
+    lost_events: u64,
+}
+
+impl TrafficCollector {
+    fn record(&mut self, event: ProxyEvent) {
+        let ProxyEvent::ConnectionClosed {
+            client_addr,
+            host,
+            bytes_received,
+            bytes_sent,
+        } = event
+        else {
+            return;
+        };
+
+        let domain = host.to_ascii_lowercase();
+        let domains_full = self.domains.len() >= MAX_SERIES_PER_PERIOD;
+        match self.domains.get_mut(&domain) {
+            Some(traffic) => traffic.add(bytes_received, bytes_sent),
+            None if domains_full => self.other_domains.add(bytes_received, bytes_sent),
+            None => self.domains.entry(domain).or_default().add(bytes_received, bytes_sent),
+        }
+
+        let ip = client_addr.ip().to_canonical();
+        let clients_full = self.clients.len() >= MAX_SERIES_PER_PERIOD;
+        match self.clients.get_mut(&ip) {
+            Some(traffic) => traffic.add(bytes_received, bytes_sent),
+            None if clients_full => self.other_clients.add(bytes_received, bytes_sent),
+            None => self.clients.entry(ip).or_default().add(bytes_received, bytes_sent),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.domains.is_empty() && self.clients.is_empty()
+    }
+
+    /// 渲染为行协议（时间戳为纳秒），按标签排序保证输出稳定
+    fn render(&self, timestamp_ns: u128) -> String {
+        let mut out = String::new();
+        let mut domains: Vec<(&str, &Traffic)> = self.domains.iter().map(|(d, t)| (d.as_str(), t)).collect();
+        domains.sort_by_key(|(domain, _)| *domain);
+        if self.other_domains.connections > 0 {
+            domains.push((OTHER_TAG, &self.other_domains));
+        }
+        for (domain, traffic) in domains {
+            write_line(&mut out, DOMAIN_MEASUREMENT, "domain", domain, traffic, timestamp_ns);
+        }
+
+        let mut clients: Vec<(&IpAddr, &Traffic)> = self.clients.iter().collect();
+        clients.sort_by_key(|(ip, _)| **ip);
+        let mut clients: Vec<(String, &Traffic)> = clients.into_iter().map(|(ip, t)| (ip.to_string(), t)).collect();
+        if self.other_clients.connections > 0 {
+            clients.push((OTHER_TAG.to_string(), &self.other_clients));
+        }
+        for (ip, traffic) in clients {
+            write_line(&mut out, CLIENT_MEASUREMENT, "ip", &ip, traffic, timestamp_ns);
+        }
+        out
+    }
+}
+
+/// 写入一行：`<measurement>,<tag>=<value> bytes_received=<n>i,bytes_sent=<n>i,connections=<n>i <timestamp>`
+fn write_line(out: &mut String, measurement: &str, tag: &str, value: &str, traffic: &Traffic, timestamp_ns: u128) {
+    let _ = writeln!(
+        out,
+        "{},{}={} bytes_received={}i,bytes_sent={}i,connections={}i {}",
+        measurement,
+        tag,
+        escape_tag(value),
+        traffic.bytes_received,
+        traffic.bytes_sent,
+        traffic.connections,
+        timestamp_ns
+    );
+}
+
+/// 转义标签值中的逗号、等号和空格
+fn escape_tag(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, ',' | '=' | ' ' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// 导出任务：汇总连接结束事件，每个周期写出一次
+pub(crate) async fn run_influx_exporter(config: InfluxConfig, events: EventBus) {
+    let mut receiver = events.subscribe();
+    let mut collector = TrafficCollector::default();
+    let mut interval = tokio::time::interval(config.interval);
+    interval.tick().await;
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                let finished = std::mem::take(&mut collector);
+                if finished.lost_events > 0 {
+                    warn!("⚠️  InfluxDB 导出丢失了 {} 个事件，本周期流量可能偏少", finished.lost_events);
+                }
+                if !finished.is_empty() {
+                    let timestamp_ns = clock::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+                    export(&config, &finished.render(timestamp_ns)).await;
+                }
+            }
+            event = receiver.recv() => match event {
+                Ok(event) => collector.record(event),
+                Err(RecvError::Lagged(lost)) => collector.lost_events += lost,
+                Err(RecvError::Closed) => return,
+            },
+        }
+    }
+}
+
+/// 写出一个周期的数据（失败只记录日志，该周期的数据丢弃）
+async fn export(config: &InfluxConfig, lines: &str) {
+    match &config.target {
+        InfluxTarget::File(path) => {
+            let written = disk::INFLUX_EXPORT.write(|| {
+                let mut file = crate::output_file::append(path)?;
+                file.write_all(lines.as_bytes())
+            });
+            match written {
+                Ok(()) => debug!("InfluxDB 行协议已写入 {}", path.display()),
+                Err(e) => error!("写入 InfluxDB 行协议文件 {} 失败: {}", path.display(), e),
+            }
+        }
+        InfluxTarget::Http(url) => {
+            let post = http_post(url, "text/plain; charset=utf-8", config.authorization.as_deref(), lines);
+            match timeout(HTTP_TIMEOUT, post).await {
+                Ok(Ok(())) => debug!("InfluxDB 行协议已写入 {}", url),
+                Ok(Err(e)) => warn!("⚠️  写入 InfluxDB 接口 {} 失败: {}", url, e),
+                Err(_) => warn!("⚠️  写入 InfluxDB 接口 {} 超时", url),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn closed(client: &str, host: &str, bytes_received: u64, bytes_sent: u64) -> ProxyEvent {
+        ProxyEvent::ConnectionClosed {
+            client_addr: client.parse().unwrap(),
+            host: host.to_string(),
+            bytes_received,
+            bytes_sent,
+        }
+    }
+
+    #[test]
+    fn test_render_line_protocol() {
+        let mut collector = TrafficCollector::default();
+        collector.record(closed("192.0.2.1:5000", "example.com", 100, 2000));
+        collector.record(closed("192.0.2.1:5001", "example.com", 50, 500));
+        collector.record(closed("[::ffff:192.0.2.2]:5000", "a b,c=d", 1, 2));
+        collector.record(ProxyEvent::ConnectionOpened { client_addr: "192.0.2.3:5000".parse().unwrap() });
+
+        assert_eq!(
+            collector.render(1_700_000_000_000_000_000),
+            "sni_proxy_domain,domain=a\\ b\\,c\\=d bytes_received=1i,bytes_sent=2i,connections=1i 1700000000000000000\n\
+             sni_proxy_domain,domain=example.com bytes_received=150i,bytes_sent=2500i,connections=2i 1700000000000000000\n\
+             sni_proxy_client,ip=192.0.2.1 bytes_received=150i,bytes_sent=2500i,connections=2i 1700000000000000000\n\
+             sni_proxy_client,ip=192.0.2.2 bytes_received=1i,bytes_sent=2i,connections=1i 1700000000000000000\n"
+        );
+    }
+
+    #[test]
+    fn test_series_limit() {
+        let mut collector = TrafficCollector::default();
+        for i in 0..MAX_SERIES_PER_PERIOD + 2 {
+            collector.record(closed("192.0.2.1:5000", &format!("host{}.example.com", i), 1, 1));
+        }
+        assert_eq!(collector.domains.len(), MAX_SERIES_PER_PERIOD);
+        assert_eq!(collector.other_domains.connections, 2);
+        assert!(collector.render(0).contains("sni_proxy_domain,domain=_other bytes_received=2i"));
+    }
+}
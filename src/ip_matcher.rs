@@ -1,34 +1,97 @@
+use arc_swap::ArcSwapOption;
 use log::{info, warn};
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::collections::HashSet;
+use std::sync::Arc;
+
+/// 可在运行中替换的 IP 白名单（None 表示不检查），白名单文件重新加载时整体替换
+pub type SharedIpMatcher = Arc<ArcSwapOption<IpMatcher>>;
 
 /// IP 匹配器，支持单个 IP 和 CIDR 网段匹配
+///
+/// 网段存放在按位的前缀树中，查找次数只与地址长度有关，与网段数量无关，
+/// 导入成千上万个网段（例如云服务商的地址段）时每个连接的检查开销不变
 #[derive(Debug, Clone)]
 pub struct IpMatcher {
     /// 精确匹配的 IP 地址列表
     exact_ips: HashSet<IpAddr>,
-    /// CIDR 网段列表（IPv4）
-    ipv4_networks: Vec<Ipv4Network>,
-    /// CIDR 网段列表（IPv6）
-    ipv6_networks: Vec<Ipv6Network>,
+    /// CIDR 网段（IPv4）
+    ipv4_networks: PrefixTrie,
+    /// CIDR 网段（IPv6）
+    ipv6_networks: PrefixTrie,
 }
 
-/// IPv4 网段
-#[derive(Debug, Clone)]
-struct Ipv4Network {
-    network: u32,
-    mask: u32,
-    #[allow(dead_code)]
-    prefix_len: u8,
+/// 按位的前缀树（从最高位开始），节点存放在数组中
+#[derive(Debug, Clone, Default)]
+struct PrefixTrie {
+    /// 节点数组，第一个为根节点
+    nodes: Vec<TrieNode>,
+    /// 网段数量
+    len: usize,
 }
 
-/// IPv6 网段
-#[derive(Debug, Clone)]
-struct Ipv6Network {
-    network: u128,
-    mask: u128,
-    #[allow(dead_code)]
-    prefix_len: u8,
+#[derive(Debug, Clone, Default)]
+struct TrieNode {
+    /// 子节点下标（0 表示没有，根节点不会是子节点）
+    children: [u32; 2],
+    /// 是否是某个网段的终点（其下所有地址都匹配）
+    terminal: bool,
+}
+
+impl PrefixTrie {
+    /// 地址的第 `i` 位（从最高位开始）
+    #[inline]
+    fn bit(addr: u128, width: u8, i: u8) -> usize {
+        ((addr >> (width - 1 - i)) & 1) as usize
+    }
+
+    /// 添加网段（`width` 位地址的前 `prefix_len` 位）
+    fn insert(&mut self, network: u128, prefix_len: u8, width: u8) {
+        if self.nodes.is_empty() {
+            self.nodes.push(TrieNode::default());
+        }
+        self.len += 1;
+        let mut node = 0;
+        for i in 0..prefix_len {
+            if self.nodes[node].terminal {
+                // 已被更短的网段覆盖
+                return;
+            }
+            let bit = Self::bit(network, width, i);
+            node = match self.nodes[node].children[bit] {
+                0 => {
+                    self.nodes.push(TrieNode::default());
+                    let child = self.nodes.len() - 1;
+                    self.nodes[node].children[bit] = child as u32;
+                    child
+                }
+                child => child as usize,
+            };
+        }
+        self.nodes[node].terminal = true;
+    }
+
+    /// 地址是否在任一网段内
+    #[inline]
+    fn contains(&self, addr: u128, width: u8) -> bool {
+        let Some(mut node) = self.nodes.first() else {
+            return false;
+        };
+        for i in 0..width {
+            if node.terminal {
+                return true;
+            }
+            match node.children[Self::bit(addr, width, i)] {
+                0 => return false,
+                child => node = &self.nodes[child as usize],
+            }
+        }
+        node.terminal
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
 }
 
 impl IpMatcher {
@@ -40,8 +103,8 @@ impl IpMatcher {
     ///   - CIDR 网段：`192.168.1.0/24` 或 `2001:db8::/32`
     pub fn new(ip_patterns: Vec<String>) -> Self {
         let mut exact_ips = HashSet::new();
-        let mut ipv4_networks = Vec::new();
-        let mut ipv6_networks = Vec::new();
+        let mut ipv4_networks = PrefixTrie::default();
+        let mut ipv6_networks = PrefixTrie::default();
 
         for pattern in ip_patterns {
             let pattern = pattern.trim();
@@ -74,11 +137,23 @@ impl IpMatcher {
         }
     }
 
+    /// 是否为有效的 IP 地址或 CIDR 网段
+    pub fn is_valid_pattern(pattern: &str) -> bool {
+        match pattern.split_once('/') {
+            Some((ip, prefix_len)) => match (ip.trim().parse::<IpAddr>(), prefix_len.trim().parse::<u8>()) {
+                (Ok(IpAddr::V4(_)), Ok(len)) => len <= 32,
+                (Ok(IpAddr::V6(_)), Ok(len)) => len <= 128,
+                _ => false,
+            },
+            None => pattern.parse::<IpAddr>().is_ok(),
+        }
+    }
+
     /// 解析 CIDR 格式的网段
     fn parse_cidr(
         cidr: &str,
-        ipv4_networks: &mut Vec<Ipv4Network>,
-        ipv6_networks: &mut Vec<Ipv6Network>,
+        ipv4_networks: &mut PrefixTrie,
+        ipv6_networks: &mut PrefixTrie,
     ) {
         let parts: Vec<&str> = cidr.split('/').collect();
         if parts.len() != 2 {
@@ -113,11 +188,7 @@ impl IpMatcher {
             };
             let network = ip_u32 & mask;
 
-            ipv4_networks.push(Ipv4Network {
-                network,
-                mask,
-                prefix_len,
-            });
+            ipv4_networks.insert(network as u128, prefix_len, 32);
 
             let network_addr = Ipv4Addr::from(network);
             info!("添加 IPv4 网段白名单: {}/{} (网络地址: {})", ip_str, prefix_len, network_addr);
@@ -137,11 +208,7 @@ impl IpMatcher {
             };
             let network = ip_u128 & mask;
 
-            ipv6_networks.push(Ipv6Network {
-                network,
-                mask,
-                prefix_len,
-            });
+            ipv6_networks.insert(network, prefix_len, 128);
 
             let network_addr = Ipv6Addr::from(network);
             info!("添加 IPv6 网段白名单: {}/{} (网络地址: {})", ip_str, prefix_len, network_addr);
@@ -160,25 +227,14 @@ impl IpMatcher {
 
         // 检查 CIDR 网段匹配
         match ip {
-            IpAddr::V4(ipv4) => {
-                let ip_u32 = u32::from(ipv4);
-                for network in &self.ipv4_networks {
-                    if (ip_u32 & network.mask) == network.network {
-                        return true;
-                    }
-                }
-            }
-            IpAddr::V6(ipv6) => {
-                let ip_u128 = u128::from(ipv6);
-                for network in &self.ipv6_networks {
-                    if (ip_u128 & network.mask) == network.network {
-                        return true;
-                    }
-                }
-            }
+            IpAddr::V4(ipv4) => self.ipv4_networks.contains(u32::from(ipv4) as u128, 32),
+            IpAddr::V6(ipv6) => self.ipv6_networks.contains(u128::from(ipv6), 128),
         }
+    }
 
-        false
+    /// 规则数量（IP 和网段）
+    pub fn len(&self) -> usize {
+        self.exact_ips.len() + self.ipv4_networks.len + self.ipv6_networks.len
     }
 
     /// 检查是否没有配置任何 IP 白名单（即禁用 IP 白名单功能）
@@ -346,6 +402,9 @@ mod tests {
 
         // 无效的模式被忽略，所以匹配器应该是空的
         assert!(matcher.is_empty());
+
+        assert!(IpMatcher::is_valid_pattern("10.0.0.0/8") && IpMatcher::is_valid_pattern("::1"));
+        assert!(!IpMatcher::is_valid_pattern("192.168.1.0/33") && !IpMatcher::is_valid_pattern("invalid"));
     }
 
     #[test]
@@ -367,6 +426,30 @@ mod tests {
         assert!(!matcher_v6.matches("2001:db8::2".parse().unwrap()));
     }
 
+    #[test]
+    fn test_many_overlapping_networks() {
+        // 大量网段，包括互相覆盖的网段（先长后短、先短后长）
+        let mut patterns: Vec<String> = (0..4096).map(|i| format!("100.{}.{}.0/24", i / 256, i % 256)).collect();
+        patterns.extend(
+            ["100.200.1.0/24", "100.200.0.0/16", "100.200.5.128/25", "2001:db8:1::/48", "2001:db8::/32", "2001:db8:1:2::/64"]
+                .map(String::from),
+        );
+        let matcher = IpMatcher::new(patterns);
+
+        assert!(matcher.matches("100.0.0.1".parse().unwrap()));
+        assert!(matcher.matches("100.15.255.255".parse().unwrap()));
+        assert!(!matcher.matches("100.16.0.1".parse().unwrap()));
+        assert!(matcher.matches("100.200.1.1".parse().unwrap()));
+        assert!(matcher.matches("100.200.77.1".parse().unwrap()));
+        assert!(matcher.matches("100.200.5.1".parse().unwrap()));
+        assert!(!matcher.matches("100.201.0.1".parse().unwrap()));
+        assert!(matcher.matches("2001:db8:ffff::1".parse().unwrap()));
+        assert!(matcher.matches("2001:db8:1:2::1".parse().unwrap()));
+        assert!(!matcher.matches("2001:db9::1".parse().unwrap()));
+        // IPv4 网段不匹配 IPv6 地址
+        assert!(!matcher.matches("::6400:1".parse().unwrap()));
+    }
+
     #[test]
     fn test_cidr_all() {
         // 0.0.0.0/0 匹配所有 IPv4 地址
@@ -0,0 +1,118 @@
+//! SNI 为 IP 地址时的处理
+//!
+//! 有些客户端直接把目标 IP 写进 SNI（RFC 6066 不允许，但实际存在）。默认仍按域名白名单匹配；
+//! 可以按监听端口改为用 IP / CIDR 白名单检查目标地址，或者直接拒绝并单独计数
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use crate::error::{Result, SniProxyError};
+
+/// SNI 为 IP 地址时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IpSniAction {
+    /// 和域名一样按域名白名单匹配
+    #[default]
+    Domain,
+    /// 按 IP / CIDR 白名单检查目标地址（不经过域名白名单）
+    IpWhitelist,
+    /// 拒绝连接
+    Reject,
+}
+
+impl std::str::FromStr for IpSniAction {
+    type Err = SniProxyError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "domain" => Ok(IpSniAction::Domain),
+            "ip_whitelist" => Ok(IpSniAction::IpWhitelist),
+            "reject" => Ok(IpSniAction::Reject),
+            _ => Err(SniProxyError::InvalidConfig(format!(
+                "无效的 IP SNI 处理方式: {}（可选: domain, ip_whitelist, reject）",
+                s
+            ))),
+        }
+    }
+}
+
+impl std::fmt::Display for IpSniAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IpSniAction::Domain => write!(f, "domain"),
+            IpSniAction::IpWhitelist => write!(f, "ip_whitelist"),
+            IpSniAction::Reject => write!(f, "reject"),
+        }
+    }
+}
+
+/// 按监听端口决定 IP SNI 的处理方式（未单独配置的监听端口使用默认方式）
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IpSniPolicy {
+    default_action: IpSniAction,
+    by_listen_port: HashMap<u16, IpSniAction>,
+}
+
+impl IpSniPolicy {
+    /// 所有监听端口都使用 `default_action`
+    pub fn new(default_action: IpSniAction) -> Self {
+        Self {
+            default_action,
+            by_listen_port: HashMap::new(),
+        }
+    }
+
+    /// 为某个监听端口单独设置处理方式
+    pub fn with_listener(mut self, listen_port: u16, action: IpSniAction) -> Self {
+        self.by_listen_port.insert(listen_port, action);
+        self
+    }
+
+    /// 连接所在监听端口的处理方式（Unix socket 没有端口，使用默认方式）
+    pub fn action(&self, listen_port: Option<u16>) -> IpSniAction {
+        listen_port
+            .and_then(|port| self.by_listen_port.get(&port).copied())
+            .unwrap_or(self.default_action)
+    }
+
+    /// 是否有监听端口会用到 IP / CIDR 白名单
+    pub fn uses_ip_whitelist(&self) -> bool {
+        self.default_action == IpSniAction::IpWhitelist
+            || self.by_listen_port.values().any(|&action| action == IpSniAction::IpWhitelist)
+    }
+}
+
+/// 把 SNI 解析为 IP 地址（IPv6 可以带方括号），不是 IP 时返回 None
+pub fn parse_ip_literal(sni: &str) -> Option<IpAddr> {
+    let literal = sni
+        .strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+        .unwrap_or(sni);
+    literal.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ip_literal() {
+        assert_eq!(parse_ip_literal("192.0.2.1"), Some("192.0.2.1".parse().unwrap()));
+        assert_eq!(parse_ip_literal("2001:db8::1"), Some("2001:db8::1".parse().unwrap()));
+        assert_eq!(parse_ip_literal("[2001:db8::1]"), Some("2001:db8::1".parse().unwrap()));
+        assert_eq!(parse_ip_literal("example.com"), None);
+        assert_eq!(parse_ip_literal("[example.com]"), None);
+    }
+
+    #[test]
+    fn test_policy_by_listener() {
+        let policy = IpSniPolicy::new(IpSniAction::Reject).with_listener(8443, IpSniAction::IpWhitelist);
+        assert_eq!(policy.action(Some(443)), IpSniAction::Reject);
+        assert_eq!(policy.action(Some(8443)), IpSniAction::IpWhitelist);
+        assert_eq!(policy.action(None), IpSniAction::Reject);
+        assert!(policy.uses_ip_whitelist());
+        assert!(!IpSniPolicy::default().uses_ip_whitelist());
+        assert_eq!("IP_WHITELIST".parse::<IpSniAction>().unwrap(), IpSniAction::IpWhitelist);
+        assert!("allow".parse::<IpSniAction>().is_err());
+    }
+}
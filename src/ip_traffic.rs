@@ -9,6 +9,10 @@ use std::num::NonZeroUsize;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 
+use crate::clock;
+use crate::disk;
+use crate::output_file;
+
 /// IP 流量统计
 #[derive(Debug, Clone)]
 pub struct IpTrafficStats {
@@ -131,7 +135,7 @@ impl IpTrafficTracker {
         let mut inner = self.inner.lock().unwrap();
         let stats = inner
             .stats
-            .get_or_insert(ip, || IpTrafficStats::new())
+            .get_or_insert(ip, IpTrafficStats::new)
             .clone();
         drop(inner); // 尽早释放锁
 
@@ -206,7 +210,7 @@ impl IpTrafficTracker {
     /// 获取流量最大的 TOP N
     pub fn get_top_n(&self, n: usize) -> Vec<IpTrafficSnapshot> {
         let mut all_stats = self.get_all_stats();
-        all_stats.sort_by(|a, b| b.total_bytes.cmp(&a.total_bytes));
+        all_stats.sort_by_key(|s| std::cmp::Reverse(s.total_bytes));
         all_stats.truncate(n);
         all_stats
     }
@@ -223,7 +227,7 @@ impl IpTrafficTracker {
             info!("=== IP 流量统计（无数据） ===");
             // 写入空数据到文件
             if let Some(ref path) = self.output_file {
-                if let Err(e) = self.write_to_file(path, &[], 0) {
+                if let Err(e) = disk::IP_TRAFFIC_OUTPUT.write(|| self.write_to_file(path, &[], 0)) {
                     warn!("写入统计文件失败: {}", e);
                 }
             }
@@ -254,14 +258,14 @@ impl IpTrafficTracker {
 
         // 写入到文件（如果配置了）
         if let Some(ref path) = self.output_file {
-            if let Err(e) = self.write_to_file(path, &top_ips, total_count) {
+            if let Err(e) = disk::IP_TRAFFIC_OUTPUT.write(|| self.write_to_file(path, &top_ips, total_count)) {
                 warn!("写入统计文件失败: {}", e);
             }
         }
 
         // 保存到持久化文件（如果配置了）
         if let Some(ref path) = self.persistence_file {
-            if let Err(e) = self.save_to_persistence_file_internal(path) {
+            if let Err(e) = disk::IP_TRAFFIC_PERSISTENCE.write(|| self.save_to_persistence_file_internal(path)) {
                 warn!("保存持久化数据失败: {}", e);
             }
         }
@@ -269,16 +273,10 @@ impl IpTrafficTracker {
 
     /// 写入统计数据到文件（覆盖写入）
     fn write_to_file(&self, path: &str, top_ips: &[IpTrafficSnapshot], total_count: usize) -> std::io::Result<()> {
-        use std::time::SystemTime;
-
-        let mut file = File::create(path)?;
+        let mut file = output_file::create(path)?;
 
         // 写入时间戳
-        if let Ok(duration) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
-            writeln!(file, "更新时间: {}", chrono::DateTime::<chrono::Local>::from(
-                SystemTime::UNIX_EPOCH + duration
-            ).format("%Y-%m-%d %H:%M:%S"))?;
-        }
+        writeln!(file, "更新时间: {}", clock::local_now().format("%Y-%m-%d %H:%M:%S"))?;
         writeln!(file)?;
 
         if top_ips.is_empty() {
@@ -313,8 +311,6 @@ impl IpTrafficTracker {
 
     /// 保存统计数据到持久化文件（JSON 格式）
     fn save_to_persistence_file_internal(&self, path: &str) -> std::io::Result<()> {
-        use std::time::SystemTime;
-
         let inner = self.inner.lock().unwrap();
 
         // 转换为可序列化的格式
@@ -330,23 +326,18 @@ impl IpTrafficTracker {
             );
         }
 
-        let saved_at = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-
         let data = PersistenceData {
             stats: stats_map,
-            saved_at,
+            saved_at: clock::unix_secs(),
         };
 
         drop(inner); // 释放锁
 
         // 序列化并写入文件
         let json = serde_json::to_string_pretty(&data)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+            .map_err(std::io::Error::other)?;
 
-        let mut file = File::create(path)?;
+        let mut file = output_file::create(path)?;
         file.write_all(json.as_bytes())?;
         file.flush()?;
 
@@ -356,8 +347,6 @@ impl IpTrafficTracker {
 
     /// 从持久化文件加载统计数据
     fn load_from_file(&mut self, path: &str) -> std::io::Result<()> {
-        use std::time::SystemTime;
-
         let mut file = File::open(path)?;
         let mut contents = String::new();
         file.read_to_string(&mut contents)?;
@@ -382,13 +371,13 @@ impl IpTrafficTracker {
 
         drop(inner);
 
-        info!("从持久化文件加载了 {} 个 IP 的统计数据 (保存于 {} 秒前)",
-            loaded_count,
-            SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_secs()
-                .saturating_sub(data.saved_at));
+        match clock::age_of(data.saved_at) {
+            Some(age) => info!("从持久化文件加载了 {} 个 IP 的统计数据 (保存于 {} 秒前)", loaded_count, age.as_secs()),
+            None => warn!(
+                "⚠️  从持久化文件加载了 {} 个 IP 的统计数据，但保存时间晚于当前时间（系统时钟可能被回拨）",
+                loaded_count
+            ),
+        }
 
         Ok(())
     }
@@ -423,7 +412,7 @@ impl IpTrafficTracker {
         }
 
         if let Some(ref path) = self.persistence_file {
-            if let Err(e) = self.save_to_persistence_file_internal(path) {
+            if let Err(e) = disk::IP_TRAFFIC_PERSISTENCE.write(|| self.save_to_persistence_file_internal(path)) {
                 warn!("保存持久化数据失败: {}", e);
             } else {
                 debug!("持久化数据已保存");
@@ -447,7 +436,7 @@ pub struct IpTrafficSnapshot {
 struct PersistenceData {
     /// 统计数据映射表 (IP -> 统计信息)
     stats: HashMap<String, PersistedStats>,
-    /// 保存时间戳
+    /// 保存时间（UTC UNIX 时间戳，秒）
     saved_at: u64,
 }
 
@@ -484,7 +473,7 @@ mod tests {
 
     #[test]
     fn test_ip_traffic_tracker() {
-        let tracker = IpTrafficTracker::new(100, None);
+        let tracker = IpTrafficTracker::new(100, None, None);
         let ip: IpAddr = "192.168.1.1".parse().unwrap();
 
         // 记录连接
@@ -505,7 +494,7 @@ mod tests {
 
     #[test]
     fn test_top_n() {
-        let tracker = IpTrafficTracker::new(100, None);
+        let tracker = IpTrafficTracker::new(100, None, None);
 
         let ip1: IpAddr = "192.168.1.1".parse().unwrap();
         let ip2: IpAddr = "192.168.1.2".parse().unwrap();
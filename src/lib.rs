@@ -1,25 +1,115 @@
 // 模块声明
+pub mod accept_limit;
+pub mod acl;
+pub mod admin;
+pub mod affinity;
+#[cfg(feature = "alloc-audit")]
+pub mod alloc_audit;
+pub mod backend;
+pub mod ban;
+mod buffer_pool;
+pub mod burst;
+pub mod clock;
+pub mod config;
+mod connection;
+pub mod disk;
 pub mod dns;
 pub mod domain;
 pub mod domain_ip_tracker;
+pub mod error;
+pub mod events;
+mod explain;
+pub mod fingerprint;
+pub mod geoip;
+pub mod handshake;
+pub mod happy_eyeballs;
+pub mod hello_corpus;
+pub mod http;
+pub mod http_client;
+pub mod influx;
 pub mod ip_matcher;
+pub mod ip_sni;
 pub mod ip_traffic;
+pub mod listener;
 pub mod logger;
 pub mod metrics;
+pub mod outbound;
+pub mod output_file;
+pub mod platform;
+pub mod port_map;
+pub mod preview;
+pub mod profile;
 pub mod proxy;
+pub mod proxy_protocol;
+pub mod quic;
+pub mod redirect;
+pub mod relay_timeout;
+pub mod remote_whitelist;
+pub mod rejection;
+pub mod report;
+pub mod retry;
+pub mod route_table;
+pub mod schedule;
 pub mod server;
+pub mod sni_map;
 pub mod socks5;
+pub mod spki_pin;
+pub mod throttle;
+pub mod timeouts;
 pub mod tls;
+pub mod transparent;
+pub mod tuning;
+mod udp_relay;
+pub mod upstream_tls;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+mod uring;
+pub mod whitelist_file;
 
 // 重新导出主要的公共类型和函数
-pub use dns::{clear_dns_cache, get_dns_cache_size, resolve_host_cached};
+pub use accept_limit::AcceptRateConfig;
+pub use acl::{Acl, AclAction, AclRule};
+pub use admin::AdminConfig;
+pub use backend::BackendAddr;
+pub use ban::BanConfig;
+pub use burst::BurstConfig;
+pub use dns::{
+    clear_dns_cache, get_dns_cache_size, get_dns_cache_stats, resolve_host_cached, DnsCacheStats, DnsOptions,
+    DnsPrefetchConfig, DnsRoute, DnsTransport, DnsUpstream, IpFamilyPreference,
+};
 pub use domain::DomainMatcher;
 pub use domain_ip_tracker::DomainIpTracker;
+pub use error::SniProxyError;
+pub use events::{EventBus, ProxyEvent, RejectReason};
+pub use fingerprint::{Fingerprint, FingerprintFilter};
+pub use geoip::{CountryCode, GeoFilter, GeoIp, GeoIpUpdate};
+pub use handshake::HandshakeConfig;
+pub use hello_corpus::HelloRecorder;
+pub use http::PlaintextHttpAction;
+pub use influx::{InfluxConfig, InfluxTarget};
 pub use ip_matcher::IpMatcher;
+pub use ip_sni::{IpSniAction, IpSniPolicy};
 pub use ip_traffic::{IpTrafficTracker, IpTrafficSnapshot};
+pub use listener::{ListenAddr, ListenerProtocol, ListenerSpec};
 pub use logger::{init_default_logger, init_from_env, init_logger, LogConfig, LogLevel};
-pub use metrics::{Metrics, MetricsSnapshot};
-pub use proxy::proxy_data;
+pub use metrics::{CounterSnapshot, ListenerLabel, MetricLabels, Metrics, MetricsSnapshot, RouteLabel, UpstreamLabel};
+pub use outbound::OutboundOptions;
+pub use output_file::OutputPermissions;
+pub use port_map::PortMapping;
+pub use preview::BodyPreview;
+pub use profile::MemoryProfile;
+pub use proxy::{proxy_data, proxy_data_with_buffer_size, PrefixedStream};
+pub use rejection::RejectionMode;
+pub use remote_whitelist::RemoteWhitelists;
+pub use report::{ReportConfig, ReportFormat};
+pub use retry::ConnectRetryConfig;
+pub use route_table::{RouteAction, RouteQuery, RouteRule, RouteTable};
+pub use schedule::Schedule;
 pub use server::SniProxy;
-pub use socks5::{connect_via_socks5, Socks5Config};
-pub use tls::parse_sni;
+pub use sni_map::{DomainMap, PinnedIps, SniBackendMap};
+pub use socks5::{connect_via_socks5, connect_via_socks5_with, Socks5Config};
+pub use throttle::{ClientRateLimits, RateLimiter};
+pub use timeouts::TimeoutConfig;
+pub use tls::{parse_client_hello, parse_sni, ClientHelloInfo, NoSniAction};
+pub use transparent::TransparentMode;
+pub use tuning::{TcpTuning, TuningPolicy};
+pub use whitelist_file::WhitelistFiles;
@@ -0,0 +1,324 @@
+use std::fmt;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::path::PathBuf;
+use std::str::FromStr;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::error::SniProxyError;
+use crate::ip_sni::IpSniAction;
+use crate::transparent::TransparentMode;
+use crate::tuning::TcpTuning;
+
+/// Unix socket 路径前缀，例如 `unix:/run/sni-proxy.sock`
+pub const UNIX_PREFIX: &str = "unix:";
+
+/// Unix socket 客户端没有 IP 地址，统一视为本机回环地址（IP 白名单、PROXY protocol 等按此处理）
+pub const UNIX_CLIENT_ADDR: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0));
+
+/// 代理监听地址：TCP 地址或 Unix socket 路径
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    /// TCP 监听地址
+    Tcp(SocketAddr),
+    /// Unix socket 路径（同机前端，例如 nginx stream 模块，无需经过 TCP 协议栈）
+    Unix(PathBuf),
+}
+
+impl ListenAddr {
+    /// TCP 监听地址（Unix socket 时为 None）
+    pub fn as_tcp(&self) -> Option<SocketAddr> {
+        match self {
+            ListenAddr::Tcp(addr) => Some(*addr),
+            ListenAddr::Unix(_) => None,
+        }
+    }
+}
+
+impl From<SocketAddr> for ListenAddr {
+    fn from(addr: SocketAddr) -> Self {
+        ListenAddr::Tcp(addr)
+    }
+}
+
+impl FromStr for ListenAddr {
+    type Err = SniProxyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix(UNIX_PREFIX) {
+            if path.is_empty() {
+                return Err(SniProxyError::InvalidConfig("Unix socket 路径不能为空".to_string()));
+            }
+            return Ok(ListenAddr::Unix(PathBuf::from(path)));
+        }
+        s.parse::<SocketAddr>()
+            .map(ListenAddr::Tcp)
+            .map_err(|_| SniProxyError::InvalidConfig(format!("无效的监听地址: {}（Unix socket 使用 unix:<路径>）", s)))
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddr::Tcp(addr) => write!(f, "{}", addr),
+            ListenAddr::Unix(path) => write!(f, "{}{}", UNIX_PREFIX, path.display()),
+        }
+    }
+}
+
+/// 监听器的入站协议
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ListenerProtocol {
+    /// TLS，按 SNI 路由（开启 HTTP 嗅探时也接受明文 HTTP）
+    #[default]
+    TlsSni,
+    /// 明文 HTTP，对白名单域名 301 重定向到 https://
+    Http,
+    /// SOCKS5 入站（尚未实现）
+    Socks5,
+    /// QUIC（HTTP/3），按 Initial 包中的 SNI 路由
+    Quic,
+}
+
+impl FromStr for ListenerProtocol {
+    type Err = SniProxyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "tls_sni" | "tls" => Ok(ListenerProtocol::TlsSni),
+            "http" => Ok(ListenerProtocol::Http),
+            "socks5" => Ok(ListenerProtocol::Socks5),
+            "quic" => Ok(ListenerProtocol::Quic),
+            _ => Err(SniProxyError::InvalidConfig(format!(
+                "无效的监听协议: {}（可选: tls_sni, http, socks5, quic）",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for ListenerProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenerProtocol::TlsSni => write!(f, "tls_sni"),
+            ListenerProtocol::Http => write!(f, "http"),
+            ListenerProtocol::Socks5 => write!(f, "socks5"),
+            ListenerProtocol::Quic => write!(f, "quic"),
+        }
+    }
+}
+
+/// 单个监听器：地址、入站协议和只作用于该监听器的规则
+///
+/// 通过 [`SniProxy::with_listener`](crate::SniProxy::with_listener) 按协议分发到对应的监听逻辑
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListenerSpec {
+    pub addr: ListenAddr,
+    pub protocol: ListenerProtocol,
+    /// 目标端口（tls_sni 和 quic，覆盖端口映射）
+    pub target_port: Option<u16>,
+    /// SNI 为 IP 地址时的处理方式（仅 tls_sni，覆盖全局配置）
+    pub ip_sni_action: Option<IpSniAction>,
+    /// TCP 调优参数（仅 tls_sni，覆盖默认参数）
+    pub tuning: Option<TcpTuning>,
+}
+
+impl ListenerSpec {
+    /// 没有额外规则的监听器
+    pub fn new(addr: impl Into<ListenAddr>, protocol: ListenerProtocol) -> Self {
+        Self {
+            addr: addr.into(),
+            protocol,
+            target_port: None,
+            ip_sni_action: None,
+            tuning: None,
+        }
+    }
+
+    /// 是否配置了监听器规则
+    pub fn has_rules(&self) -> bool {
+        self.target_port.is_some() || self.ip_sni_action.is_some() || self.tuning.is_some()
+    }
+}
+
+/// 可以接受客户端连接的监听 socket
+pub(crate) trait Accept {
+    type Stream: ClientStream;
+
+    /// 接受一个连接，返回客户端流和客户端地址
+    async fn accept(&self) -> io::Result<(Self::Stream, SocketAddr)>;
+}
+
+impl Accept for TcpListener {
+    type Stream = TcpStream;
+
+    async fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        TcpListener::accept(self).await
+    }
+}
+
+#[cfg(unix)]
+impl Accept for tokio::net::UnixListener {
+    type Stream = tokio::net::UnixStream;
+
+    async fn accept(&self) -> io::Result<(tokio::net::UnixStream, SocketAddr)> {
+        let (stream, _) = tokio::net::UnixListener::accept(self).await?;
+        Ok((stream, UNIX_CLIENT_ADDR))
+    }
+}
+
+/// 客户端连接
+///
+/// TCP 相关的准备工作（socket 调优、透明代理原始目标、按监听端口映射）对 Unix socket 不适用，默认为空操作
+pub(crate) trait ClientStream: AsyncRead + AsyncWrite + Unpin + Send + Sync + 'static {
+    /// 设置 socket 参数
+    fn apply_tuning(&self, _tuning: &TcpTuning) {}
+
+    /// 透明代理的原始目标地址
+    fn original_destination(&self, _mode: TransparentMode, _listen_addrs: &[SocketAddr]) -> Option<SocketAddr> {
+        None
+    }
+
+    /// 本地监听端口（用于端口映射）
+    fn local_port(&self) -> Option<u16> {
+        None
+    }
+
+    /// 处理该连接网络包的 CPU（SO_INCOMING_CPU）
+    fn incoming_cpu(&self) -> Option<usize> {
+        None
+    }
+
+    /// 关闭时发送 RST 而不是 FIN（SO_LINGER 0）
+    fn reset_on_close(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// 设置 DSCP 标记
+    fn set_dscp(&self, _dscp: u8) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// 设置拥塞控制算法
+    fn set_congestion_control(&self, _name: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// 底层的 TCP 连接（用于 splice 转发），其他类型的连接为 None
+    fn as_tcp_stream(&self) -> Option<&TcpStream> {
+        None
+    }
+
+    /// 取出底层的 TCP 连接交给 io_uring 转发，不支持时为 None
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    fn take_tcp_stream(&mut self) -> Option<TcpStream> {
+        None
+    }
+}
+
+impl ClientStream for TcpStream {
+    fn apply_tuning(&self, tuning: &TcpTuning) {
+        let _ = crate::proxy::apply_tcp_tuning(self, tuning);
+    }
+
+    fn original_destination(&self, mode: TransparentMode, listen_addrs: &[SocketAddr]) -> Option<SocketAddr> {
+        crate::transparent::original_destination(self, mode, listen_addrs)
+    }
+
+    fn local_port(&self) -> Option<u16> {
+        self.local_addr().ok().map(|addr| addr.port())
+    }
+
+    fn incoming_cpu(&self) -> Option<usize> {
+        #[cfg(target_os = "linux")]
+        return crate::affinity::incoming_cpu(self);
+        #[cfg(not(target_os = "linux"))]
+        return None;
+    }
+
+    fn reset_on_close(&self) -> io::Result<()> {
+        socket2::SockRef::from(self).set_linger(Some(std::time::Duration::ZERO))
+    }
+
+    fn set_dscp(&self, dscp: u8) -> io::Result<()> {
+        crate::tuning::set_dscp(self, dscp)
+    }
+
+    fn set_congestion_control(&self, name: &str) -> io::Result<()> {
+        crate::tuning::set_congestion_control(self, name)
+    }
+
+    fn as_tcp_stream(&self) -> Option<&TcpStream> {
+        Some(self)
+    }
+}
+
+#[cfg(unix)]
+impl ClientStream for tokio::net::UnixStream {}
+
+#[cfg(test)]
+impl ClientStream for tokio::io::DuplexStream {}
+
+/// 绑定 Unix socket 监听
+///
+/// 路径上残留的 socket 文件（例如上次异常退出）会先被删除；其他类型的文件不会被覆盖
+#[cfg(unix)]
+pub(crate) fn bind_unix_listener(path: &std::path::Path) -> crate::error::Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::FileTypeExt;
+
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(SniProxyError::InvalidConfig(format!(
+                "Unix socket 路径已存在且不是 socket 文件: {}",
+                path.display()
+            )));
+        }
+        std::fs::remove_file(path)?;
+    }
+
+    Ok(tokio::net::UnixListener::bind(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_listen_addr() {
+        assert_eq!(
+            "0.0.0.0:8443".parse::<ListenAddr>().unwrap(),
+            ListenAddr::Tcp("0.0.0.0:8443".parse().unwrap())
+        );
+        let unix = "unix:/run/sni-proxy.sock".parse::<ListenAddr>().unwrap();
+        assert_eq!(unix, ListenAddr::Unix(PathBuf::from("/run/sni-proxy.sock")));
+        assert_eq!(unix.to_string(), "unix:/run/sni-proxy.sock");
+        assert_eq!(unix.as_tcp(), None);
+        assert!("unix:".parse::<ListenAddr>().is_err());
+        assert!("/run/sni-proxy.sock".parse::<ListenAddr>().is_err());
+    }
+
+    #[test]
+    fn test_parse_listener_protocol() {
+        assert_eq!("tls_sni".parse::<ListenerProtocol>().unwrap(), ListenerProtocol::TlsSni);
+        assert_eq!("TLS".parse::<ListenerProtocol>().unwrap(), ListenerProtocol::TlsSni);
+        assert_eq!("quic".parse::<ListenerProtocol>().unwrap(), ListenerProtocol::Quic);
+        assert_eq!(ListenerProtocol::Socks5.to_string(), "socks5");
+        assert!("http_connect".parse::<ListenerProtocol>().is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_bind_unix_listener_replaces_stale_socket() {
+        let path = std::env::temp_dir().join(format!("sni-proxy-test-{}.sock", std::process::id()));
+        drop(bind_unix_listener(&path).unwrap());
+        // 上次运行残留的 socket 文件
+        assert!(path.exists());
+
+        let listener = bind_unix_listener(&path).unwrap();
+        let _client = tokio::net::UnixStream::connect(&path).await.unwrap();
+        let (_, client_addr) = Accept::accept(&listener).await.unwrap();
+        assert_eq!(client_addr, UNIX_CLIENT_ADDR);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
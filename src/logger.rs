@@ -1,6 +1,6 @@
 use chrono::Local;
 use log::{LevelFilter, Log, Metadata, Record};
-use std::fs::{File, OpenOptions};
+use std::fs::File;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
@@ -71,6 +71,7 @@ impl LogLevel {
     }
 
     /// 从字符串解析日志级别
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<Self> {
         match s.to_lowercase().as_str() {
             "off" => Some(LogLevel::Off),
@@ -179,10 +180,7 @@ impl FileWriter {
             std::fs::create_dir_all(parent)?;
         }
 
-        let file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&path)?;
+        let file = crate::output_file::append(&path)?;
 
         let current_size = file.metadata()?.len();
 
@@ -237,10 +235,7 @@ impl FileWriter {
         }
 
         // 创建新文件
-        self.file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&self.path)?;
+        self.file = crate::output_file::append(&self.path)?;
         self.current_size = 0;
 
         Ok(())
@@ -273,10 +268,12 @@ impl Log for CustomLogger {
         }
 
         // 输出到文件（文件中不使用颜色）
+        // 磁盘写满时暂停写入，不占用文件锁
         if let Some(writer) = &self.file_writer {
-            if let Ok(mut w) = writer.lock() {
-                let _ = w.write(&format!("{}\n", formatted));
-            }
+            let _ = crate::disk::LOG_FILE.write(|| match writer.lock() {
+                Ok(mut w) => w.write(&format!("{}\n", formatted)),
+                Err(_) => Ok(()),
+            });
         }
     }
 
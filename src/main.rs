@@ -1,294 +1,177 @@
 use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
+use sni_proxy::affinity::{numa_node_cpus, parse_cpu_list, pin_current_thread};
+use sni_proxy::config::{
+    feature_enabled, listener_specs, parse_countries, parse_ip_sni_policy, parse_no_sni_action, parse_pinned_ips,
+    parse_sni_backends, parse_tuning_route, primary_listen_addr, tuning_profile, validate_config, AclConfigFile, Config,
+    CpuAffinityConfig,
+};
 use sni_proxy::logger::{init_logger, LogConfig, LogLevel};
-use sni_proxy::{SniProxy, Socks5Config};
+use sni_proxy::platform::PlatformInfo;
+use sni_proxy::{
+    AdminConfig, BackendAddr, BodyPreview, GeoFilter, GeoIp, HelloRecorder, IpSniPolicy, ListenerProtocol,
+    MemoryProfile, NoSniAction, PlaintextHttpAction, PortMapping, RejectionMode, RemoteWhitelists, SniProxy,
+    TransparentMode, WhitelistFiles,
+};
 use std::fs;
 use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Config {
-    listen_addr: String,
-    /// 直连白名单
-    whitelist: Vec<String>,
-    /// SOCKS5 白名单（可选）
-    #[serde(default)]
-    socks5_whitelist: Vec<String>,
-    /// IP 白名单（可选）
-    /// 支持单个 IP 地址（如 "192.168.1.1"）或 CIDR 网段（如 "192.168.1.0/24"）
-    /// 如果为空，则不进行 IP 白名单检查
-    #[serde(default)]
-    ip_whitelist: Vec<String>,
-    /// IP 流量追踪配置（可选）
-    ip_traffic_tracking: Option<IpTrafficTrackingConfig>,
-    /// 域名-IP 追踪配置（可选）
-    domain_ip_tracking: Option<DomainIpTrackingConfig>,
-    /// SOCKS5 代理配置（可选）
-    socks5: Option<Socks5ConfigFile>,
-    /// 日志配置（可选）
-    log: Option<LogConfigFile>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct IpTrafficTrackingConfig {
-    /// 是否启用 IP 流量追踪（仅对 IP 白名单中的 IP）
-    #[serde(default)]
-    enabled: bool,
-    /// 最大跟踪的 IP 数量（使用 LRU 缓存）
-    #[serde(default = "default_max_tracked_ips")]
-    max_tracked_ips: usize,
-    /// 统计数据输出文件路径（可选，每次覆盖写入最新数据）
-    output_file: Option<String>,
-    /// 持久化数据文件路径（可选，用于服务重启后恢复数据）
-    persistence_file: Option<String>,
-}
-
-fn default_max_tracked_ips() -> usize {
-    1000
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct DomainIpTrackingConfig {
-    /// 是否启用域名-IP 追踪
-    #[serde(default)]
-    enabled: bool,
-    /// 输出文件路径
-    output_file: Option<String>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct Socks5ConfigFile {
-    /// SOCKS5 代理服务器地址，格式：ip:port 或 domain:port
-    addr: String,
-    /// 用户名（可选）
-    username: Option<String>,
-    /// 密码（可选）
-    password: Option<String>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct LogConfigFile {
-    /// 日志级别: off, error, warn, info, debug, trace
-    #[serde(default = "default_log_level")]
-    level: String,
-    /// 日志输出目标: stdout, file, both
-    #[serde(default = "default_log_output")]
-    output: String,
-    /// 日志文件路径（当 output 为 file 或 both 时需要）
-    file_path: Option<String>,
-    /// 是否启用日志轮转
-    #[serde(default)]
-    enable_rotation: bool,
-    /// 单个日志文件最大大小（MB）
-    #[serde(default = "default_max_size_mb")]
-    max_size_mb: u64,
-    /// 保留的日志文件数量
-    #[serde(default = "default_max_backups")]
-    max_backups: usize,
-    /// 是否显示时间戳
-    #[serde(default = "default_true")]
-    show_timestamp: bool,
-    /// 是否显示模块路径
-    #[serde(default = "default_true")]
-    show_module: bool,
-    /// 是否使用颜色输出
-    #[serde(default = "default_true")]
-    use_color: bool,
-}
-
-fn default_log_level() -> String {
-    "info".to_string()
-}
-
-fn default_log_output() -> String {
-    "stdout".to_string()
-}
-
-fn default_max_size_mb() -> u64 {
-    100
+/// 格式化缓冲区大小（0 表示系统默认值）
+fn format_buffer_size(size: usize) -> String {
+    if size == 0 {
+        "系统默认".to_string()
+    } else {
+        format!("{} KB", size / 1024)
+    }
 }
 
-fn default_max_backups() -> usize {
-    5
+/// 运行时设置（在创建 Tokio 运行时之前确定，启动后用于日志输出）
+struct RuntimeSettings {
+    memory_profile: MemoryProfile,
+    worker_threads: usize,
+    /// 工作线程栈大小（字节，None 表示 Tokio 默认值）
+    thread_stack_size: Option<usize>,
+    global_queue_interval: u32,
+    event_interval: u32,
+    /// 工作线程绑定的 CPU 集合（未配置时为 None）
+    worker_cpus: Option<Vec<usize>>,
+    /// 接受连接线程绑定的 CPU 及绑定结果
+    acceptor_cpu: Option<(usize, std::io::Result<()>)>,
 }
 
-fn default_true() -> bool {
-    true
-}
+/// 解析 CPU 亲和性配置，合并 worker_cpus 和 NUMA 节点的 CPU 列表
+fn resolve_worker_cpus(affinity: &CpuAffinityConfig) -> Result<Option<Vec<usize>>> {
+    let mut cpus = Vec::new();
 
-impl Default for LogConfigFile {
-    fn default() -> Self {
-        Self {
-            level: default_log_level(),
-            output: default_log_output(),
-            file_path: None,
-            enable_rotation: false,
-            max_size_mb: default_max_size_mb(),
-            max_backups: default_max_backups(),
-            show_timestamp: true,
-            show_module: true,
-            use_color: true,
-        }
+    if let Some(ref list) = affinity.worker_cpus {
+        cpus.extend(parse_cpu_list(list).context("无效的 cpu_affinity.worker_cpus")?);
     }
-}
-
-/// 验证配置的有效性
-fn validate_config(config: &Config) -> Result<()> {
-    // 验证监听地址
-    config
-        .listen_addr
-        .parse::<SocketAddr>()
-        .context("无效的监听地址格式")?;
-
-    // 验证白名单不能为空
-    if config.whitelist.is_empty() && config.socks5_whitelist.is_empty() {
-        anyhow::bail!("直连白名单和 SOCKS5 白名单不能同时为空");
+    for &node in &affinity.numa_nodes {
+        cpus.extend(numa_node_cpus(node)?);
     }
 
-    // 验证 SOCKS5 配置
-    if let Some(ref socks5) = config.socks5 {
-        socks5
-            .addr
-            .parse::<SocketAddr>()
-            .context("无效的 SOCKS5 代理地址格式")?;
-
-        // 检查用户名和密码的一致性
-        if socks5.username.is_some() != socks5.password.is_some() {
-            anyhow::bail!("SOCKS5 用户名和密码必须同时提供或同时省略");
-        }
+    if cpus.is_empty() {
+        return Ok(None);
     }
 
-    // 验证 IP 流量追踪配置
-    if let Some(ref tracking) = config.ip_traffic_tracking {
-        if tracking.enabled {
-            // 验证 max_tracked_ips 合理性
-            if tracking.max_tracked_ips == 0 {
-                anyhow::bail!("IP 流量追踪的 max_tracked_ips 必须大于 0");
-            }
-            if tracking.max_tracked_ips > 1_000_000 {
-                log::warn!("⚠️  max_tracked_ips 设置过大 ({})，可能占用大量内存", tracking.max_tracked_ips);
-            }
-
-            // 验证输出文件路径可写
-            if let Some(ref output_file) = tracking.output_file {
-                if let Some(parent) = std::path::Path::new(output_file).parent() {
-                    if !parent.exists() {
-                        log::warn!("⚠️  输出文件目录不存在: {:?}，尝试创建...", parent);
-                        std::fs::create_dir_all(parent)
-                            .context(format!("无法创建输出文件目录: {:?}", parent))?;
-                    }
-                }
-            }
+    cpus.sort_unstable();
+    cpus.dedup();
+    Ok(Some(cpus))
+}
 
-            // 验证持久化文件路径可写
-            if let Some(ref persistence_file) = tracking.persistence_file {
-                if let Some(parent) = std::path::Path::new(persistence_file).parent() {
-                    if !parent.exists() {
-                        log::warn!("⚠️  持久化文件目录不存在: {:?}，尝试创建...", parent);
-                        std::fs::create_dir_all(parent)
-                            .context(format!("无法创建持久化文件目录: {:?}", parent))?;
-                    }
-                }
-            }
-        }
-    }
+fn main() -> Result<()> {
+    // 读取配置文件路径（命令行参数或默认值）
+    let config_path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "config.json".to_string());
 
-    // 验证日志配置
-    if let Some(ref log_config) = config.log {
-        // 验证日志级别
-        let valid_levels = ["off", "error", "warn", "info", "debug", "trace"];
-        if !valid_levels.contains(&log_config.level.as_str()) {
-            anyhow::bail!(
-                "无效的日志级别: {}，有效值: {:?}",
-                log_config.level,
-                valid_levels
-            );
-        }
+    // 读取并解析配置文件
+    let config_content = fs::read_to_string(&config_path)
+        .context(format!("无法读取配置文件: {}", config_path))?;
 
-        // 验证日志输出
-        let valid_outputs = ["stdout", "file", "both"];
-        if !valid_outputs.contains(&log_config.output.as_str()) {
-            anyhow::bail!(
-                "无效的日志输出: {}，有效值: {:?}",
-                log_config.output,
-                valid_outputs
-            );
-        }
+    let config: Config = serde_json::from_str(&config_content)
+        .context("解析配置文件失败")?;
 
-        // 如果输出到文件，验证文件路径
-        if log_config.output == "file" || log_config.output == "both" {
-            if log_config.file_path.is_none() {
-                log::warn!("⚠️  日志输出到文件但未指定路径，将使用默认路径: logs/sni-proxy.log");
-            } else if let Some(ref file_path) = log_config.file_path {
-                if let Some(parent) = std::path::Path::new(file_path).parent() {
-                    if !parent.exists() {
-                        log::warn!("⚠️  日志文件目录不存在: {:?}，尝试创建...", parent);
-                        std::fs::create_dir_all(parent)
-                            .context(format!("无法创建日志文件目录: {:?}", parent))?;
-                    }
-                }
-            }
-        }
+    // 验证配置
+    validate_config(&config)
+        .context("配置验证失败")?;
 
-        // 验证日志轮转配置
-        if log_config.enable_rotation {
-            if log_config.max_size_mb == 0 {
-                anyhow::bail!("启用日志轮转时，max_size_mb 必须大于 0");
-            }
-            if log_config.max_backups == 0 {
-                log::warn!("⚠️  max_backups 为 0，日志文件将不保留备份");
-            }
-        }
+    // 输出文件权限必须在日志器创建日志文件之前设置
+    if let Some(ref output_files) = config.output_files {
+        sni_proxy::output_file::set_output_permissions(output_files.build()?)?;
     }
 
-    Ok(())
-}
-
-fn main() -> Result<()> {
     // ⚡ 性能优化：自定义 Tokio 运行时配置
     // 小型服务器优化（<= 2核）：使用 CPU 核心数作为工作线程数
     // 大型服务器优化（> 2核）：使用 CPU 核心数的一半
-    let num_cpus = num_cpus::get();
-    let worker_threads = if num_cpus <= 2 {
+    // 容器中按 cgroup CPU 配额计算，而不是宿主机的核心数
+    let num_cpus = sni_proxy::platform::available_cpus();
+    let mut worker_threads = if num_cpus <= 2 {
         num_cpus  // 小型服务器：使用所有核心
     } else {
         std::cmp::max(4, num_cpus / 2)  // 大型服务器：使用一半
     };
+    let runtime_config = config.runtime.clone().unwrap_or_default();
+    let event_interval = runtime_config.event_interval.unwrap_or(if num_cpus <= 2 { 61 } else { 31 });
+    let global_queue_interval = runtime_config.global_queue_interval.unwrap_or(31);
+    let thread_stack_size = runtime_config.thread_stack_size_kb.map(|kb| kb * 1024);
+
+    // 低内存模式：只使用一个工作线程，减少线程栈和调度队列占用
+    let memory_profile = match config.memory_profile {
+        Some(ref profile) => profile.parse::<MemoryProfile>()?,
+        None => MemoryProfile::Default,
+    };
+    if memory_profile == MemoryProfile::LowMemory {
+        worker_threads = 1;
+    }
+
+    // CPU 亲和性：工作线程绑定到指定 CPU / NUMA 节点
+    let worker_cpus = match config.cpu_affinity {
+        Some(ref affinity) => resolve_worker_cpus(affinity)?,
+        None => None,
+    };
+    if let Some(ref cpus) = worker_cpus {
+        // 工作线程数不超过可用 CPU 数，避免线程在少量核心上争抢
+        worker_threads = std::cmp::min(worker_threads, cpus.len());
+    }
+    // 显式配置的工作线程数优先
+    if let Some(threads) = runtime_config.worker_threads {
+        worker_threads = threads;
+    }
 
-    let runtime = tokio::runtime::Builder::new_multi_thread()
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    builder
         // 工作线程数：根据 CPU 核心数自适应
         .worker_threads(worker_threads)
         // 线程命名：便于调试和监控
         .thread_name("sni-proxy-worker")
-        // 使用默认栈大小（约 512KB-1MB，节省内存）
         // 启用所有 Tokio 功能（I/O、时间、信号等）
         .enable_all()
-        // 全局队列间隔：31（默认值，平衡公平性和性能）
-        .global_queue_interval(31)
+        // 全局队列间隔：默认 31（平衡公平性和性能）
+        .global_queue_interval(global_queue_interval)
         // 事件间隔：小型服务器使用 61 减少 CPU 开销
-        .event_interval(if num_cpus <= 2 { 61 } else { 31 })
-        .build()
-        .context("创建 Tokio 运行时失败")?;
-
-    // 在运行时中执行主逻辑
-    runtime.block_on(async_main())
-}
+        .event_interval(event_interval);
+    // 未配置时使用默认栈大小（约 512KB-1MB，节省内存）
+    if let Some(size) = thread_stack_size {
+        builder.thread_stack_size(size);
+    }
 
-async fn async_main() -> Result<()> {
-    // 读取配置文件路径（命令行参数或默认值）
-    let config_path = std::env::args()
-        .nth(1)
-        .unwrap_or_else(|| "config.json".to_string());
+    if let Some(ref cpus) = worker_cpus {
+        let cpus = cpus.clone();
+        // 日志系统此时尚未初始化，绑定失败在启动后通过配置输出排查
+        builder.on_thread_start(move || {
+            let _ = pin_current_thread(&cpus);
+        });
+    }
 
-    // 读取并解析配置文件
-    let config_content = fs::read_to_string(&config_path)
-        .context(format!("无法读取配置文件: {}", config_path))?;
+    let runtime = builder.build().context("创建 Tokio 运行时失败")?;
+
+    // 接受连接的循环运行在 block_on 所在的主线程上，按需绑定
+    let acceptor_cpu = config
+        .cpu_affinity
+        .as_ref()
+        .and_then(|affinity| affinity.acceptor_cpu)
+        .map(|cpu| (cpu, pin_current_thread(&[cpu])));
+
+    let runtime_settings = RuntimeSettings {
+        memory_profile,
+        worker_threads,
+        thread_stack_size,
+        global_queue_interval,
+        event_interval,
+        worker_cpus,
+        acceptor_cpu,
+    };
 
-    let config: Config = serde_json::from_str(&config_content)
-        .context("解析配置文件失败")?;
+    // 在运行时中执行主逻辑
+    runtime.block_on(async_main(config_path, config, runtime_settings))
+}
 
-    // 验证配置
-    validate_config(&config)
-        .context("配置验证失败")?;
+async fn async_main(config_path: String, config: Config, runtime_settings: RuntimeSettings) -> Result<()> {
+    // 监听器（已在 validate_config 中验证）
+    let listener_specs = listener_specs(&config)?;
+    let listen_addr = primary_listen_addr(&listener_specs)?;
 
     // 初始化日志系统
     let log_config_file = config.log.unwrap_or_default();
@@ -348,30 +231,43 @@ async fn async_main() -> Result<()> {
     log::info!("配置文件: {}", config_path);
 
     // ⚡ 显示运行时配置
-    let num_cpus = num_cpus::get();
+    let platform = PlatformInfo::detect();
+    let num_cpus = platform.available_cpus();
     let num_physical_cpus = num_cpus::get_physical();
-    let worker_threads = if num_cpus <= 2 {
-        num_cpus
-    } else {
-        std::cmp::max(4, num_cpus / 2)
-    };
-    let event_interval = if num_cpus <= 2 { 61 } else { 31 };
 
     log::info!("🚀 Tokio 运行时配置:");
-    log::info!("  CPU 核心: {} 物理, {} 逻辑", num_physical_cpus, num_cpus);
-    log::info!("  工作线程数: {} ({})", worker_threads,
+    log::info!("  CPU 核心: {} 物理, {} 逻辑", num_physical_cpus, platform.affinity_cpus.len());
+    if let Some(quota) = platform.cpu_quota {
+        log::info!("  cgroup CPU 配额: {:.2}（按 {} 个 CPU 计算）", quota, num_cpus);
+    }
+    log::info!(
+        "  运行平台: libc={}{}，内核 {}",
+        platform.libc,
+        if platform.static_binary { "（静态链接）" } else { "" },
+        platform.kernel_release.as_deref().unwrap_or("未知")
+    );
+    log::info!("  工作线程数: {} ({})", runtime_settings.worker_threads,
         if num_cpus <= 2 { "小型服务器模式" } else { "大型服务器模式" });
-    log::info!("  线程栈大小: 默认 (~1MB)");
-    log::info!("  全局队列间隔: 31");
-    log::info!("  事件间隔: {} ({})", event_interval,
+    match runtime_settings.thread_stack_size {
+        Some(size) => log::info!("  线程栈大小: {} KB", size / 1024),
+        None => log::info!("  线程栈大小: 默认 (~1MB)"),
+    }
+    log::info!("  全局队列间隔: {}", runtime_settings.global_queue_interval);
+    log::info!("  事件间隔: {} ({})", runtime_settings.event_interval,
         if num_cpus <= 2 { "节省 CPU" } else { "I/O 优化" });
-
-    let listen_addr: SocketAddr = config
-        .listen_addr
-        .parse()
-        .context("无效的监听地址")?;
+    if let Some(ref cpus) = runtime_settings.worker_cpus {
+        log::info!("  工作线程 CPU 亲和性: {:?}", cpus);
+    }
+    match runtime_settings.acceptor_cpu {
+        Some((cpu, Ok(()))) => log::info!("  接受连接线程绑定到 CPU {}", cpu),
+        Some((cpu, Err(ref e))) => log::warn!("⚠️  接受连接线程绑定到 CPU {} 失败: {}", cpu, e),
+        None => {}
+    }
 
     log::info!("监听地址: {}", listen_addr);
+    if listen_addr.as_tcp().is_some_and(|addr| addr.is_ipv6()) {
+        log::info!("双栈监听: {}", if config.dual_stack { "启用" } else { "禁用（仅 IPv6）" });
+    }
     log::info!("日志级别: {}", log_config_file.level);
     log::info!("日志输出: {}", log_config_file.output);
 
@@ -402,6 +298,10 @@ async fn async_main() -> Result<()> {
     }
 
     // 显示 IP 白名单
+    let remote_whitelists = match config.remote_whitelist {
+        Some(ref remote) if remote.enabled => remote.build()?,
+        _ => RemoteWhitelists::default(),
+    };
     if !config.ip_whitelist.is_empty() {
         log::info!("加载了 {} 个 IP 白名单规则", config.ip_whitelist.len());
         for (i, ip_pattern) in config.ip_whitelist.iter().take(10).enumerate() {
@@ -410,32 +310,240 @@ async fn async_main() -> Result<()> {
         if config.ip_whitelist.len() > 10 {
             log::info!("  ... 还有 {} 个 IP 规则", config.ip_whitelist.len() - 10);
         }
-    } else {
+    } else if config.ip_whitelist_file.is_none() && remote_whitelists.ip.is_none() {
         log::info!("未配置 IP 白名单，允许所有 IP 访问");
     }
 
     // 创建代理实例
-    let has_socks5_whitelist = !config.socks5_whitelist.is_empty();
+    let whitelist_files = WhitelistFiles {
+        direct: config.whitelist_file.map(Into::into),
+        socks5: config.socks5_whitelist_file.map(Into::into),
+        ip: config.ip_whitelist_file.map(Into::into),
+        reload_interval: config.whitelist_reload_secs.map(Duration::from_secs),
+    };
+    let has_socks5_whitelist =
+        !config.socks5_whitelist.is_empty() || whitelist_files.socks5.is_some() || remote_whitelists.socks5.is_some();
     let mut proxy = if has_socks5_whitelist {
         // 使用双白名单模式
         SniProxy::new_with_dual_whitelist(
-            listen_addr,
+            listen_addr.clone(),
             config.whitelist,
             config.socks5_whitelist,
         )
     } else {
         // 使用单一白名单模式（仅直连）
-        SniProxy::new(listen_addr, config.whitelist)
+        SniProxy::new(listen_addr.clone(), config.whitelist)
     };
 
+    proxy = proxy.with_dual_stack(config.dual_stack);
+
+    // 应用内存配置预设（之后的 tuning 等单项配置会覆盖预设值）
+    if runtime_settings.memory_profile != MemoryProfile::Default {
+        log::info!("内存配置预设: {}", runtime_settings.memory_profile);
+        proxy = proxy.with_memory_profile(runtime_settings.memory_profile);
+    }
+    if let Some(max_connections) = config.runtime.as_ref().and_then(|runtime| runtime.max_connections) {
+        proxy = proxy.with_max_connections(max_connections);
+    }
+
+    // 配置目标端口映射
+    let mut port_mapping = PortMapping::new(config.target_port);
+    for (&listen_port, &target_port) in &config.port_map {
+        port_mapping = port_mapping.with_mapping(listen_port, target_port);
+    }
+    proxy = proxy.with_port_mapping(port_mapping);
+
+    if config.http_sniffing {
+        log::info!("HTTP Host 路由: 启用");
+        proxy = proxy.with_http_sniffing(true);
+    }
+
+    // 配置明文 HTTP 处理方式（如果提供，已在 validate_config 中验证）
+    if let Some(ref action) = config.plaintext_http_response {
+        let action: PlaintextHttpAction = action.parse()?;
+        log::info!("明文 HTTP 请求处理方式: {}", action);
+        proxy = proxy.with_plaintext_http_response(action);
+    }
+
+    // 配置拒绝连接方式（如果提供，已在 validate_config 中验证）
+    if let Some(ref mode) = config.rejection_mode {
+        let mode = match mode.parse()? {
+            RejectionMode::Tarpit(default) => {
+                RejectionMode::Tarpit(config.tarpit_seconds.map_or(default, Duration::from_secs))
+            }
+            mode => mode,
+        };
+        log::info!("拒绝连接方式: {}", mode);
+        proxy = proxy.with_rejection_mode(mode);
+    }
+
+    // 配置默认后端（如果提供，已在 validate_config 中验证）
+    if let Some(ref backend) = config.default_backend {
+        let backend: BackendAddr = backend.parse()?;
+        log::info!("默认后端: {}", backend);
+        proxy = proxy.with_default_backend(backend);
+    }
+
+    // 配置 SNI 映射表（已在 validate_config 中验证）
+    if !config.sni_backends.is_empty() {
+        for (domain, backend) in &config.sni_backends {
+            log::info!("SNI 映射: {} → {}", domain, backend);
+        }
+        proxy = proxy.with_sni_backends(parse_sni_backends(&config.sni_backends)?);
+    }
+
+    // 配置固定 IP（已在 validate_config 中验证）
+    if !config.pinned_ips.is_empty() {
+        for (domain, ips) in &config.pinned_ips {
+            log::info!("固定 IP: {} → {}", domain, ips.join(", "));
+        }
+        proxy = proxy.with_pinned_ips(parse_pinned_ips(&config.pinned_ips)?);
+    }
+
+    // 配置 SNI 为 IP 地址时的处理方式（已在 validate_config 中验证）
+    let ip_sni_policy = parse_ip_sni_policy(config.ip_sni_action.as_deref(), &config.ip_sni_action_by_port)?;
+    if ip_sni_policy != IpSniPolicy::default() {
+        proxy = proxy
+            .with_ip_sni_policy(ip_sni_policy)
+            .with_ip_sni_whitelist(config.ip_sni_whitelist);
+    }
+
+    // 配置监听器（已在 validate_config 中验证；监听器规则覆盖上面的端口映射和 IP SNI 处理方式）
+    for spec in listener_specs {
+        if spec.protocol == ListenerProtocol::Http && !config.features.http_redirect {
+            log::info!("功能开关已关闭 HTTP → HTTPS 重定向，跳过监听器 {}", spec.addr);
+            continue;
+        }
+        if spec.addr != listen_addr || spec.protocol != ListenerProtocol::TlsSni {
+            log::info!("监听器: {} ({})", spec.addr, spec.protocol);
+        }
+        proxy = proxy.with_listener(spec)?;
+    }
+
+    // 配置没有 SNI 时的处理方式（已在 validate_config 中验证）
+    let no_sni_action = parse_no_sni_action(
+        config.no_sni_action.as_deref(),
+        config.no_sni_backend.as_deref(),
+        config.transparent_mode.is_some(),
+    )?;
+    if no_sni_action != NoSniAction::Reject {
+        log::info!("没有 SNI 的连接: {}", no_sni_action);
+        proxy = proxy.with_no_sni_action(no_sni_action);
+    }
+
+    // 配置透明代理模式（如果提供，已在 validate_config 中验证）
+    if let Some(ref mode) = config.transparent_mode {
+        let mode: TransparentMode = mode.parse()?;
+        log::info!("透明代理模式: {}", mode);
+        proxy = proxy.with_transparent_mode(mode);
+    }
+
+    // 配置 acceptor 数量和 SO_INCOMING_CPU 分流
+    let incoming_cpu_steering = config
+        .cpu_affinity
+        .as_ref()
+        .is_some_and(|affinity| affinity.incoming_cpu_steering);
+    let acceptors = config
+        .tuning
+        .as_ref()
+        .and_then(|tuning| tuning.acceptors)
+        .unwrap_or(if incoming_cpu_steering { PlatformInfo::detect().affinity_cpus.len() } else { 1 });
+    if acceptors > 1 || incoming_cpu_steering {
+        log::info!(
+            "acceptor 数量: {}，SO_INCOMING_CPU 分流: {}",
+            acceptors,
+            if incoming_cpu_steering { "启用" } else { "禁用" }
+        );
+    }
+    proxy = proxy
+        .with_acceptors(acceptors)
+        .with_incoming_cpu_steering(incoming_cpu_steering);
+
+    // 配置 TCP 调优参数（如果提供）
+    if let Some(ref tuning) = config.tuning {
+        let tuning = &tuning.tcp;
+        log::info!("TCP 调优配置:");
+        log::info!("  backlog: {}", tuning.backlog);
+        log::info!("  接收缓冲区: {}", format_buffer_size(tuning.recv_buffer_size));
+        log::info!("  发送缓冲区: {}", format_buffer_size(tuning.send_buffer_size));
+        if let Some(secs) = tuning.keepalive_secs {
+            log::info!("  keepalive: {} 秒", secs);
+        }
+        if let Some(secs) = tuning.keepalive_interval_secs {
+            log::info!("  keepalive 探测间隔: {} 秒", secs);
+        }
+        if let Some(count) = tuning.keepalive_count {
+            log::info!("  keepalive 探测次数: {}", count);
+        }
+        if let Some(ms) = tuning.user_timeout_ms {
+            log::info!("  TCP_USER_TIMEOUT: {} 毫秒", ms);
+        }
+        if tuning.quickack {
+            log::info!("  TCP_QUICKACK: 启用");
+        }
+        if let Some(ref algorithm) = tuning.congestion_control {
+            log::info!("  拥塞控制算法: {}", algorithm);
+        }
+        proxy = proxy.with_tcp_tuning(tuning.build());
+    }
+
+    // 按路由为上游连接配置调优参数（已在 validate_config 中验证）
+    for (route, name) in &config.route_tuning {
+        log::info!("上游 TCP 调优: {} 路由使用 {}", route, name);
+        proxy = proxy.with_route_tuning(parse_tuning_route(route)?, tuning_profile(&config.tuning_profiles, name)?);
+    }
+
+    // 配置 PROXY protocol 出站头部（如果提供）
+    if !config.proxy_protocol_domains.is_empty() {
+        log::info!("PROXY protocol v2 出站头部: {} 个域名", config.proxy_protocol_domains.len());
+        proxy = proxy.with_proxy_protocol(config.proxy_protocol_domains);
+    }
+
+    if !config.ip_blacklist.is_empty() {
+        log::info!("加载了 {} 个 IP 黑名单规则", config.ip_blacklist.len());
+        proxy = proxy.with_ip_blacklist(config.ip_blacklist);
+    }
+    if let Some(ref geoip) = config.geoip {
+        let path = Path::new(&geoip.database);
+        let update = geoip.update()?;
+        let database = match &update {
+            // 配置了自动更新而数据库文件还不存在时先下载
+            Some(update) if !path.exists() => {
+                log::info!("GeoIP 数据库 {} 不存在，从 {} 下载", geoip.database, update.url);
+                update
+                    .download(0)
+                    .await
+                    .context("下载 GeoIP 数据库失败")?
+                    .context("下载 GeoIP 数据库失败: 服务器没有返回数据库")?
+            }
+            _ => GeoIp::open(path)?,
+        };
+        proxy = proxy.with_geoip(database);
+        if let Some(update) = update {
+            proxy = proxy.with_geoip_update(update);
+        }
+    }
+    if !config.geo_whitelist.is_empty() || !config.geo_blacklist.is_empty() {
+        log::info!("国家限制: 允许 {:?}，拒绝 {:?}", config.geo_whitelist, config.geo_blacklist);
+        let filter = GeoFilter::new(parse_countries(&config.geo_whitelist)?, parse_countries(&config.geo_blacklist)?);
+        proxy = proxy.with_geo_filter(filter);
+    }
+
     // 配置 IP 白名单（如果提供）
     if !config.ip_whitelist.is_empty() {
         proxy = proxy.with_ip_whitelist(config.ip_whitelist);
     }
+    if !whitelist_files.is_empty() {
+        proxy = proxy.with_whitelist_files(whitelist_files);
+    }
+    if !remote_whitelists.is_empty() {
+        log::info!("远程白名单: 每 {} 秒刷新", remote_whitelists.refresh_interval.as_secs());
+        proxy = proxy.with_remote_whitelists(remote_whitelists);
+    }
 
     // 配置 IP 流量追踪（如果启用且有 IP 白名单）
     if let Some(tracking_config) = config.ip_traffic_tracking {
-        if tracking_config.enabled {
+        if feature_enabled(tracking_config.enabled, config.features.ip_traffic_tracking, "IP 流量追踪") {
             log::info!("配置 IP 流量追踪");
             log::info!("  最大跟踪 IP 数量: {}", tracking_config.max_tracked_ips);
             if let Some(ref output_file) = tracking_config.output_file {
@@ -454,7 +562,11 @@ async fn async_main() -> Result<()> {
 
     // 配置域名-IP 追踪（如果启用）
     if let Some(domain_ip_tracking_config) = config.domain_ip_tracking {
-        if domain_ip_tracking_config.enabled {
+        if feature_enabled(
+            domain_ip_tracking_config.enabled,
+            config.features.domain_ip_tracking,
+            "域名-IP 追踪",
+        ) {
             log::info!("启用域名-IP 追踪:");
             if let Some(ref output_file) = domain_ip_tracking_config.output_file {
                 log::info!("  输出文件: {}", output_file);
@@ -465,38 +577,353 @@ async fn async_main() -> Result<()> {
         }
     }
 
+    // 配置 Client Hello 采集（如果启用）
+    if let Some(capture) = config.hello_capture {
+        if capture.enabled {
+            log::info!("启用 Client Hello 采集:");
+            log::info!("  语料文件: {}", capture.corpus_file);
+            log::info!("  最多条目: {}", capture.max_entries);
+            if capture.include_sni {
+                log::warn!("⚠️  Client Hello 采集保留真实 SNI，语料文件包含用户访问的域名");
+            }
+            let recorder = HelloRecorder::new(capture.corpus_file.as_str(), capture.include_sni, capture.max_entries)
+                .context(format!("无法打开语料文件: {}", capture.corpus_file))?;
+            proxy = proxy.with_hello_capture(recorder);
+        }
+    }
+
+    // 配置数据预览（如果启用）
+    if let Some(preview) = config.log_body_preview {
+        if preview.enabled {
+            log::warn!("⚠️  数据预览已启用（诊断用，排查完成后请关闭）:");
+            if !preview.domains.is_empty() {
+                log::warn!("  域名: {:?}", preview.domains);
+            }
+            if !preview.client_ips.is_empty() {
+                log::warn!("  客户端 IP: {:?}", preview.client_ips);
+            }
+            log::warn!("  每个方向最多记录 {} 字节", preview.max_bytes);
+            proxy = proxy.with_body_preview(BodyPreview::new(preview.domains, preview.client_ips, preview.max_bytes));
+        }
+    }
+
+    // 配置 TLS 指纹（如果启用，已在 validate_config 中验证）
+    if let Some(fingerprint) = config.tls_fingerprint {
+        if fingerprint.enabled {
+            log::info!(
+                "TLS 指纹已启用（{}），允许列表 {} 项，拒绝列表 {} 项",
+                if fingerprint.ja4 { "JA3 + JA4" } else { "JA3" },
+                fingerprint.allow.len(),
+                fingerprint.deny.len()
+            );
+            proxy = proxy.with_fingerprint_filter(fingerprint.build_filter()?);
+        }
+    }
+
     // 配置 SOCKS5（如果提供）
     if let Some(socks5_config_file) = config.socks5 {
         log::info!("配置 SOCKS5 代理");
 
-        // 解析 SOCKS5 地址
-        let socks5_addr: SocketAddr = socks5_config_file
-            .addr
-            .parse()
-            .context("无效的 SOCKS5 代理地址")?;
+        let socks5_config = socks5_config_file.build()?;
+        log::info!("SOCKS5 代理服务器: {}", socks5_config.addr);
 
-        log::info!("SOCKS5 代理服务器: {}", socks5_addr);
-
-        if socks5_config_file.username.is_some() {
+        if socks5_config.username.is_some() {
             log::info!("SOCKS5 认证方式: 用户名/密码");
         } else {
             log::info!("SOCKS5 认证方式: 无认证");
         }
 
-        let socks5_config = Socks5Config {
-            addr: socks5_addr,
-            username: socks5_config_file.username,
-            password: socks5_config_file.password,
-        };
-
         proxy = proxy.with_socks5(socks5_config);
     } else if has_socks5_whitelist {
         log::warn!("配置了 SOCKS5 白名单但未配置 SOCKS5 代理服务器！");
         log::warn!("SOCKS5 白名单将无法生效，请检查配置文件");
-    } else {
+    } else if config.socks5_upstreams.is_empty() {
         log::info!("未配置 SOCKS5，所有流量使用直接连接");
     }
 
+    // 命名的 SOCKS5 上游和路由规则
+    for (name, socks5_config_file) in &config.socks5_upstreams {
+        proxy = proxy.with_named_socks5(name.clone(), socks5_config_file.build()?);
+    }
+    if !config.routes.is_empty() || !config.categories.is_empty() {
+        let mut rules = config
+            .routes
+            .iter()
+            .map(|route| route.build(&config.tuning_profiles))
+            .collect::<sni_proxy::error::Result<Vec<_>>>()?;
+        if !rules.is_empty() {
+            log::info!("加载了 {} 条路由规则", rules.len());
+        }
+        for (i, route) in config.routes.iter().enumerate() {
+            if let Some(kbps) = route.bandwidth_limit_kbps {
+                log::info!("  routes[{}] 带宽上限: {} kbit/s", i, kbps);
+            }
+            if let Some(bind_addr) = route.outbound.bind_addr {
+                log::info!("  routes[{}] 出站源地址: {}", i, bind_addr);
+            }
+            if let Some(ref interface) = route.outbound.interface {
+                log::info!("  routes[{}] 出站网络接口: {}", i, interface);
+            }
+            if let Some(mark) = route.outbound.fwmark {
+                log::info!("  routes[{}] fwmark: {:#x}", i, mark);
+            }
+            if let Some(fastopen) = route.outbound.fastopen {
+                log::info!("  routes[{}] TCP Fast Open: {}", i, if fastopen { "启用" } else { "禁用" });
+            }
+            if let Some(ref tuning) = route.tuning {
+                log::info!("  routes[{}] TCP 调优: {}", i, tuning);
+            }
+            if let Some(ref dscp) = route.dscp {
+                log::info!("  routes[{}] DSCP: {}", i, dscp);
+            }
+            if let Some(ref algorithm) = route.congestion_control {
+                log::info!("  routes[{}] 拥塞控制算法: {}", i, algorithm);
+            }
+        }
+        for category in &config.categories {
+            let rule = category.build(&config.tuning_profiles)?;
+            log::info!("域名分类 {}: {} 个域名 → {}", category.name, category.domains.len(), rule.action());
+            if let Some(schedule) = rule.schedule() {
+                log::info!("  生效时段: {}", schedule);
+            }
+            if let Some(kbps) = category.bandwidth_limit_kbps {
+                log::info!("  带宽上限: {} kbit/s", kbps);
+            }
+            if let Some(ref tuning) = category.tuning {
+                log::info!("  TCP 调优: {}", tuning);
+            }
+            if let Some(ref dscp) = category.dscp {
+                log::info!("  DSCP: {}", dscp);
+            }
+            if let Some(ref algorithm) = category.congestion_control {
+                log::info!("  拥塞控制算法: {}", algorithm);
+            }
+            rules.push(rule);
+        }
+        proxy = proxy.with_routes(rules);
+    }
+
+    // 配置管理接口（如果启用）
+    if let Some(admin_config_file) = config.admin {
+        if feature_enabled(admin_config_file.enabled, config.features.admin_api, "管理接口") {
+            let admin_addr: SocketAddr = admin_config_file
+                .listen_addr
+                .parse()
+                .context("无效的管理接口监听地址")?;
+            log::info!("启用管理接口: {}", admin_addr);
+            if admin_config_file.auth_token.is_some() {
+                log::info!("  管理接口认证: Bearer Token");
+            }
+            proxy = proxy.with_admin_api(AdminConfig {
+                listen_addr: admin_addr,
+                auth_token: admin_config_file.auth_token,
+            });
+        }
+    }
+
+    // 配置 HTTP → HTTPS 重定向（如果启用）
+    if let Some(redirect_config_file) = config.http_redirect {
+        if feature_enabled(redirect_config_file.enabled, config.features.http_redirect, "HTTP → HTTPS 重定向") {
+            let redirect_addr: SocketAddr = redirect_config_file
+                .listen_addr
+                .parse()
+                .context("无效的 HTTP 重定向监听地址")?;
+            log::info!("启用 HTTP → HTTPS 重定向: {}", redirect_addr);
+            proxy = proxy.with_http_redirect(redirect_addr);
+        }
+    }
+
+    // 配置重复连接退避（如果启用，已在 validate_config 中验证）
+    if let Some(burst) = config.burst_backoff {
+        if burst.enabled {
+            let burst = burst.build()?;
+            log::info!(
+                "启用重复连接退避: {} ms 内超过 {} 次后退避 {:?}（上限 {:?}）",
+                burst.window.as_millis(),
+                burst.max_attempts,
+                burst.initial_backoff,
+                burst.max_backoff
+            );
+            proxy = proxy.with_burst_backoff(burst);
+        }
+    }
+
+    // 配置自动封禁（如果启用，已在 validate_config 中验证）
+    if let Some(auto_ban) = config.auto_ban {
+        if auto_ban.enabled {
+            let ban = auto_ban.build()?;
+            log::info!(
+                "启用自动封禁: {:?} 内失败 {} 次后封禁 {:?}",
+                ban.window,
+                ban.max_failures,
+                ban.ban_duration
+            );
+            proxy = proxy.with_auto_ban(ban);
+        }
+    }
+
+    // 配置按客户端 IP 的带宽限制
+    if let Some(client_bandwidth) = config.client_bandwidth {
+        log::info!(
+            "按客户端 IP 限制带宽: 默认 {}，{} 个网段单独设置",
+            client_bandwidth.default_kbps.map_or_else(|| "不限".to_string(), |kbps| format!("{} kbps", kbps)),
+            client_bandwidth.ranges.len()
+        );
+        proxy = proxy.with_client_rate_limits(client_bandwidth.build()?);
+    }
+
+    // 配置超时（已在 validate_config 中验证）
+    if let Some(timeouts) = config.timeouts {
+        let timeouts = timeouts.build()?;
+        let describe = |value: Option<Duration>| value.map_or_else(|| "自适应".to_string(), |d| format!("{:?}", d));
+        log::info!(
+            "超时配置: 读取 Client Hello {}，连接目标 {}，SOCKS5 连接 {}，空闲 {}",
+            describe(timeouts.client_hello_read),
+            describe(timeouts.connect),
+            timeouts.socks5_handshake.map_or_else(|| "每步 5s".to_string(), |d| format!("{:?}", d)),
+            timeouts.idle.map_or_else(|| "不限".to_string(), |d| format!("{:?}", d))
+        );
+        proxy = proxy.with_timeouts(timeouts);
+    }
+
+    // 配置出站选项（已在 validate_config 中验证）
+    if let Some(outbound) = config.outbound {
+        let outbound = outbound.build()?;
+        if let Some(bind_addr) = outbound.bind_addr {
+            log::info!("出站源地址: {}", bind_addr);
+        }
+        if let Some(ref interface) = outbound.interface {
+            log::info!("出站网络接口: {}", interface);
+        }
+        if let Some(mark) = outbound.fwmark {
+            log::info!("出站连接 fwmark: {:#x}", mark);
+        }
+        if outbound.fastopen == Some(true) {
+            log::info!("SOCKS5 代理连接 TCP Fast Open: 启用（直连不使用）");
+        }
+        proxy = proxy.with_outbound(outbound);
+    }
+
+    // 配置上游连接重试（如果启用，已在 validate_config 中验证）
+    if let Some(connect_retry) = config.connect_retry {
+        if connect_retry.enabled {
+            let retry = connect_retry.build()?;
+            log::info!(
+                "启用上游连接重试: 最多 {} 次，退避 {:?} 起每次翻倍（最长 {:?}）",
+                retry.max_retries,
+                retry.initial_backoff,
+                retry.max_backoff
+            );
+            proxy = proxy.with_connect_retry(retry);
+        }
+    }
+
+    if config.direct_fallback_to_socks5 {
+        log::info!("直连失败时改用 SOCKS5: 启用");
+        proxy = proxy.with_direct_fallback_to_socks5(true);
+    }
+    if config.socks5_fallback_to_direct {
+        log::info!("SOCKS5 失败时改用直连: 启用");
+        proxy = proxy.with_socks5_fallback_to_direct(true);
+    }
+
+    // 配置转发空闲超时（已在 validate_config 中验证）
+    if let Some(secs) = config.idle_timeout_secs {
+        log::info!("转发空闲超时: {} 秒", secs);
+        proxy = proxy.with_idle_timeout(Duration::from_secs(secs));
+    }
+    if let Some(secs) = config.max_connection_lifetime_secs {
+        log::info!("最长连接时间: {} 秒", secs);
+        proxy = proxy.with_max_connection_lifetime(Duration::from_secs(secs));
+    }
+    if config.splice_relay {
+        log::info!("splice 转发: 启用");
+        proxy = proxy.with_splice_relay(true);
+    }
+    if !config.features.access_log {
+        log::info!("访问记录: 已通过 features 关闭");
+        proxy = proxy.with_access_log(false);
+    }
+    if let Some(io_uring) = config.io_uring.as_ref().filter(|io_uring| io_uring.enabled) {
+        let workers = io_uring.workers.unwrap_or_else(sni_proxy::platform::available_cpus);
+        log::info!("io_uring 数据路径: 启用（{} 个转发线程）", workers);
+        proxy = proxy.with_io_uring(workers);
+    }
+
+    // 配置新连接速率限制（如果启用，已在 validate_config 中验证）
+    if let Some(accept_rate_limit) = config.accept_rate_limit {
+        if accept_rate_limit.enabled {
+            let limit = accept_rate_limit.build()?;
+            log::info!(
+                "启用新连接速率限制: 每秒 {} 个（突发 {} 个），超过时{}",
+                limit.rate,
+                limit.burst,
+                if limit.max_queue_wait.is_zero() {
+                    "立即关闭".to_string()
+                } else {
+                    format!("最多排队 {:?}", limit.max_queue_wait)
+                }
+            );
+            proxy = proxy.with_accept_rate_limit(limit);
+        }
+    }
+
+    // 配置握手阶段保护（如果启用，已在 validate_config 中验证）
+    if let Some(handshake_protection) = config.handshake_protection {
+        if handshake_protection.enabled {
+            let handshake = handshake_protection.build()?;
+            log::info!(
+                "启用握手阶段保护: 最多 {} 个握手中的连接，Client Hello 截止时间 {:?}",
+                handshake.max_handshaking,
+                handshake.hello_timeout
+            );
+            proxy = proxy.with_handshake_limit(handshake);
+        }
+    }
+
+    // 配置域名黑名单（黑名单文件中的规则追加在后面）
+    let mut blacklist = config.blacklist.clone();
+    if let Some(ref path) = config.blacklist_file {
+        let rules = sni_proxy::whitelist_file::load_domain_list(Path::new(path))?;
+        log::info!("域名黑名单文件 {}: {} 条规则", path, rules.len());
+        blacklist.extend(rules);
+    }
+    if !blacklist.is_empty() {
+        proxy = proxy.with_blacklist(blacklist);
+    }
+
+    // 配置 ACL（已在 validate_config 中验证）
+    if !config.acl.is_empty() {
+        let rules = config.acl.iter().map(AclConfigFile::build).collect::<sni_proxy::error::Result<Vec<_>>>()?;
+        proxy = proxy.with_acl(rules);
+    }
+
+    // 配置每日报告（如果启用，已在 validate_config 中验证）
+    if let Some(report) = config.daily_report {
+        if report.enabled {
+            proxy = proxy.with_daily_report(report.build()?);
+        }
+    }
+
+    // 配置 InfluxDB 导出（如果启用，已在 validate_config 中验证）
+    if let Some(influx) = config.influx_export {
+        if influx.enabled {
+            proxy = proxy.with_influx_export(influx.build()?);
+        }
+    }
+
+    // 配置 DNS 解析（已在 validate_config 中验证）
+    if let Some(dns) = config.dns {
+        proxy = proxy.with_dns_options(dns.build()?).with_dns_hosts(dns.hosts.clone());
+        proxy = proxy.with_dns_routes(dns.routes()?);
+        if let Some(prefetch) = dns.prefetch.as_ref().filter(|prefetch| prefetch.enabled) {
+            proxy = proxy.with_dns_prefetch(prefetch.build()?);
+        }
+        if let Some(upstream) = dns.upstream.build("dns")? {
+            proxy = proxy.with_dns_upstream(upstream);
+        }
+    }
+
     log::info!("=== 服务器准备就绪 ===");
 
     // 创建优雅关闭信号通道
@@ -1,18 +1,45 @@
+use std::collections::HashMap;
+use std::fmt;
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 /// 服务器性能监控指标
+///
+/// 通过 [`with_labels`](Metrics::with_labels) 得到的句柄在累加全局计数器的同时，
+/// 累加对应标签组合的计数器，导出时可以按监听器、路由和上游拆分总量
 #[derive(Debug, Clone)]
 pub struct Metrics {
     inner: Arc<MetricsInner>,
+    /// 当前句柄的标签组合（未设置标签时只累加全局计数器）
+    series: Option<Arc<Series>>,
 }
 
 #[derive(Debug)]
 struct MetricsInner {
+    /// 全局计数器
+    totals: Counters,
+    active_connections: AtomicUsize,
+
+    // DNS 统计
+    dns_cache_hits: AtomicU64,
+    dns_cache_misses: AtomicU64,
+
+    // 按接收 CPU 统计的连接数（SO_INCOMING_CPU，下标为 CPU 编号）
+    connections_per_cpu: Box<[AtomicU64]>,
+
+    // 按标签组合统计的计数器（标签取值固定，组合数量有上限）
+    series: Mutex<HashMap<MetricLabels, Arc<Series>>>,
+
+    // 启动时间
+    start_time: Instant,
+}
+
+/// 支持标签的计数器
+#[derive(Debug, Default)]
+struct Counters {
     // 连接统计
     total_connections: AtomicU64,
-    active_connections: AtomicUsize,
     failed_connections: AtomicU64,
 
     // 流量统计
@@ -24,17 +51,173 @@ struct MetricsInner {
     socks5_requests: AtomicU64,
     rejected_requests: AtomicU64,
 
-    // DNS 统计
-    dns_cache_hits: AtomicU64,
-    dns_cache_misses: AtomicU64,
-
     // 错误统计
     sni_parse_errors: AtomicU64,
+    plaintext_http_requests: AtomicU64,
+    fingerprint_rejections: AtomicU64,
+    ip_sni_rejections: AtomicU64,
+    burst_rejections: AtomicU64,
+    geo_rejections: AtomicU64,
+    ban_rejections: AtomicU64,
+    overload_rejections: AtomicU64,
     socks5_errors: AtomicU64,
     connection_timeouts: AtomicU64,
+    idle_timeouts: AtomicU64,
+    slow_handshake_drops: AtomicU64,
+    connect_retries: AtomicU64,
+    connect_retry_failures: AtomicU64,
+    upstream_fallbacks: AtomicU64,
+    splice_relays: AtomicU64,
+    userspace_relays: AtomicU64,
+    uring_relays: AtomicU64,
+    dns_prefetched: AtomicU64,
+    dns_prefetch_failures: AtomicU64,
+    upstream_tls_alerts: AtomicU64,
+    upstream_not_tls: AtomicU64,
+}
 
-    // 启动时间
-    start_time: Instant,
+impl Counters {
+    fn snapshot(&self) -> CounterSnapshot {
+        CounterSnapshot {
+            total_connections: self.total_connections.load(Ordering::Relaxed),
+            failed_connections: self.failed_connections.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            direct_requests: self.direct_requests.load(Ordering::Relaxed),
+            socks5_requests: self.socks5_requests.load(Ordering::Relaxed),
+            rejected_requests: self.rejected_requests.load(Ordering::Relaxed),
+            sni_parse_errors: self.sni_parse_errors.load(Ordering::Relaxed),
+            plaintext_http_requests: self.plaintext_http_requests.load(Ordering::Relaxed),
+            fingerprint_rejections: self.fingerprint_rejections.load(Ordering::Relaxed),
+            ip_sni_rejections: self.ip_sni_rejections.load(Ordering::Relaxed),
+            burst_rejections: self.burst_rejections.load(Ordering::Relaxed),
+            geo_rejections: self.geo_rejections.load(Ordering::Relaxed),
+            ban_rejections: self.ban_rejections.load(Ordering::Relaxed),
+            overload_rejections: self.overload_rejections.load(Ordering::Relaxed),
+            socks5_errors: self.socks5_errors.load(Ordering::Relaxed),
+            connection_timeouts: self.connection_timeouts.load(Ordering::Relaxed),
+            idle_timeouts: self.idle_timeouts.load(Ordering::Relaxed),
+            slow_handshake_drops: self.slow_handshake_drops.load(Ordering::Relaxed),
+            connect_retries: self.connect_retries.load(Ordering::Relaxed),
+            connect_retry_failures: self.connect_retry_failures.load(Ordering::Relaxed),
+            upstream_fallbacks: self.upstream_fallbacks.load(Ordering::Relaxed),
+            splice_relays: self.splice_relays.load(Ordering::Relaxed),
+            userspace_relays: self.userspace_relays.load(Ordering::Relaxed),
+            uring_relays: self.uring_relays.load(Ordering::Relaxed),
+            dns_prefetched: self.dns_prefetched.load(Ordering::Relaxed),
+            dns_prefetch_failures: self.dns_prefetch_failures.load(Ordering::Relaxed),
+            upstream_tls_alerts: self.upstream_tls_alerts.load(Ordering::Relaxed),
+            upstream_not_tls: self.upstream_not_tls.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Series {
+    labels: MetricLabels,
+    counters: Counters,
+}
+
+/// 标签组合数量上限（超出后新组合只累加全局计数器）
+const MAX_SERIES: usize = 256;
+
+/// 指标标签
+///
+/// 取值集合很小且固定，不包含域名、客户端 IP 等高基数字段。
+/// 标签在连接处理过程中逐步确定：接受连接时只有监听器，路由后才有路由动作，连接上游后才有上游
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct MetricLabels {
+    /// 接受连接的监听器
+    pub listener: ListenerLabel,
+    /// 白名单决定的路由动作
+    pub route: RouteLabel,
+    /// 实际使用的上游
+    pub upstream: UpstreamLabel,
+}
+
+impl MetricLabels {
+    /// 只有监听器的标签组合
+    pub fn listener(listener: ListenerLabel) -> Self {
+        Self {
+            listener,
+            ..Self::default()
+        }
+    }
+}
+
+/// 监听器标签
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ListenerLabel {
+    /// 未知（例如未设置标签的句柄）
+    #[default]
+    Unknown,
+    /// TCP 监听端口
+    Tcp(u16),
+    /// Unix socket
+    Unix,
+    /// QUIC（UDP）监听端口
+    Quic(u16),
+}
+
+impl fmt::Display for ListenerLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenerLabel::Unknown => write!(f, "unknown"),
+            ListenerLabel::Tcp(port) => write!(f, "tcp:{}", port),
+            ListenerLabel::Unix => write!(f, "unix"),
+            ListenerLabel::Quic(port) => write!(f, "udp:{}", port),
+        }
+    }
+}
+
+/// 路由动作标签
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum RouteLabel {
+    /// 尚未路由（例如读取 Client Hello 阶段失败）
+    #[default]
+    None,
+    /// 直连白名单
+    Direct,
+    /// SOCKS5 白名单
+    Socks5,
+    /// 拒绝
+    Rejected,
+    /// 转发到兜底地址（没有 SNI，或不在白名单中时转发到默认后端）
+    Fallback,
+}
+
+impl fmt::Display for RouteLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteLabel::None => write!(f, "none"),
+            RouteLabel::Direct => write!(f, "direct"),
+            RouteLabel::Socks5 => write!(f, "socks5"),
+            RouteLabel::Rejected => write!(f, "rejected"),
+            RouteLabel::Fallback => write!(f, "fallback"),
+        }
+    }
+}
+
+/// 上游标签
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum UpstreamLabel {
+    /// 尚未连接上游
+    #[default]
+    None,
+    /// 直接连接目标服务器
+    Direct,
+    /// 通过 SOCKS5 出口
+    Socks5,
+}
+
+impl fmt::Display for UpstreamLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpstreamLabel::None => write!(f, "none"),
+            UpstreamLabel::Direct => write!(f, "direct"),
+            UpstreamLabel::Socks5 => write!(f, "socks5"),
+        }
+    }
 }
 
 impl Default for Metrics {
@@ -48,27 +231,66 @@ impl Metrics {
     pub fn new() -> Self {
         Self {
             inner: Arc::new(MetricsInner {
-                total_connections: AtomicU64::new(0),
+                totals: Counters::default(),
                 active_connections: AtomicUsize::new(0),
-                failed_connections: AtomicU64::new(0),
-                bytes_received: AtomicU64::new(0),
-                bytes_sent: AtomicU64::new(0),
-                direct_requests: AtomicU64::new(0),
-                socks5_requests: AtomicU64::new(0),
-                rejected_requests: AtomicU64::new(0),
                 dns_cache_hits: AtomicU64::new(0),
                 dns_cache_misses: AtomicU64::new(0),
-                sni_parse_errors: AtomicU64::new(0),
-                socks5_errors: AtomicU64::new(0),
-                connection_timeouts: AtomicU64::new(0),
+                connections_per_cpu: {
+                    let max_cpu = crate::platform::PlatformInfo::detect().affinity_cpus.last().copied().unwrap_or(0);
+                    (0..=max_cpu).map(|_| AtomicU64::new(0)).collect()
+                },
+                series: Mutex::new(HashMap::new()),
                 start_time: Instant::now(),
             }),
+            series: None,
+        }
+    }
+
+    /// 返回带指定标签的句柄（与原句柄共享全局计数器）
+    ///
+    /// 标签组合数量达到上限时返回不带标签的句柄
+    pub fn with_labels(&self, labels: MetricLabels) -> Metrics {
+        if self.labels() == Some(labels) {
+            return self.clone();
+        }
+        let series = {
+            let mut map = self.inner.series.lock().unwrap();
+            match map.get(&labels) {
+                Some(series) => Some(Arc::clone(series)),
+                None if map.len() < MAX_SERIES => {
+                    let series = Arc::new(Series {
+                        labels,
+                        counters: Counters::default(),
+                    });
+                    map.insert(labels, Arc::clone(&series));
+                    Some(series)
+                }
+                None => None,
+            }
+        };
+        Metrics {
+            inner: Arc::clone(&self.inner),
+            series,
+        }
+    }
+
+    /// 当前句柄的标签（未设置标签时为 None）
+    pub fn labels(&self) -> Option<MetricLabels> {
+        self.series.as_ref().map(|series| series.labels)
+    }
+
+    /// 累加全局计数器和当前标签组合的计数器
+    #[inline]
+    fn add(&self, counter: impl Fn(&Counters) -> &AtomicU64, value: u64) {
+        counter(&self.inner.totals).fetch_add(value, Ordering::Relaxed);
+        if let Some(series) = &self.series {
+            counter(&series.counters).fetch_add(value, Ordering::Relaxed);
         }
     }
 
     // 连接统计
     pub fn inc_total_connections(&self) {
-        self.inner.total_connections.fetch_add(1, Ordering::Relaxed);
+        self.add(|c| &c.total_connections, 1);
     }
 
     pub fn inc_active_connections(&self) {
@@ -80,29 +302,29 @@ impl Metrics {
     }
 
     pub fn inc_failed_connections(&self) {
-        self.inner.failed_connections.fetch_add(1, Ordering::Relaxed);
+        self.add(|c| &c.failed_connections, 1);
     }
 
     // 流量统计
     pub fn add_bytes_received(&self, bytes: u64) {
-        self.inner.bytes_received.fetch_add(bytes, Ordering::Relaxed);
+        self.add(|c| &c.bytes_received, bytes);
     }
 
     pub fn add_bytes_sent(&self, bytes: u64) {
-        self.inner.bytes_sent.fetch_add(bytes, Ordering::Relaxed);
+        self.add(|c| &c.bytes_sent, bytes);
     }
 
     // 请求统计
     pub fn inc_direct_requests(&self) {
-        self.inner.direct_requests.fetch_add(1, Ordering::Relaxed);
+        self.add(|c| &c.direct_requests, 1);
     }
 
     pub fn inc_socks5_requests(&self) {
-        self.inner.socks5_requests.fetch_add(1, Ordering::Relaxed);
+        self.add(|c| &c.socks5_requests, 1);
     }
 
     pub fn inc_rejected_requests(&self) {
-        self.inner.rejected_requests.fetch_add(1, Ordering::Relaxed);
+        self.add(|c| &c.rejected_requests, 1);
     }
 
     // DNS 统计
@@ -116,20 +338,122 @@ impl Metrics {
 
     // 错误统计
     pub fn inc_sni_parse_errors(&self) {
-        self.inner.sni_parse_errors.fetch_add(1, Ordering::Relaxed);
+        self.add(|c| &c.sni_parse_errors, 1);
+    }
+
+    /// TLS 端口收到明文 HTTP 请求（不计入 SNI 解析错误）
+    pub fn inc_plaintext_http_requests(&self) {
+        self.add(|c| &c.plaintext_http_requests, 1);
+    }
+
+    /// TLS 指纹被过滤规则拒绝（同时计入拒绝请求）
+    pub fn inc_fingerprint_rejections(&self) {
+        self.add(|c| &c.fingerprint_rejections, 1);
+    }
+
+    /// SNI 为 IP 地址被拒绝（同时计入拒绝请求）
+    pub fn inc_ip_sni_rejections(&self) {
+        self.add(|c| &c.ip_sni_rejections, 1);
+    }
+
+    /// 重复连接处于退避期间被关闭（不计入拒绝请求）
+    pub fn inc_burst_rejections(&self) {
+        self.add(|c| &c.burst_rejections, 1);
+    }
+
+    /// 客户端所在国家不被允许（同时计入拒绝请求）
+    pub fn inc_geo_rejections(&self) {
+        self.add(|c| &c.geo_rejections, 1);
+    }
+
+    /// 客户端 IP 被自动封禁（同时计入拒绝请求）
+    pub fn inc_ban_rejections(&self) {
+        self.add(|c| &c.ban_rejections, 1);
+    }
+
+    /// 新连接速率超过上限被丢弃（不计入拒绝请求）
+    pub fn inc_overload_rejections(&self) {
+        self.add(|c| &c.overload_rejections, 1);
     }
 
     pub fn inc_socks5_errors(&self) {
-        self.inner.socks5_errors.fetch_add(1, Ordering::Relaxed);
+        self.add(|c| &c.socks5_errors, 1);
     }
 
     pub fn inc_connection_timeouts(&self) {
-        self.inner.connection_timeouts.fetch_add(1, Ordering::Relaxed);
+        self.add(|c| &c.connection_timeouts, 1);
+    }
+
+    /// 转发阶段超过空闲超时没有数据而断开
+    pub fn inc_idle_timeouts(&self) {
+        self.add(|c| &c.idle_timeouts, 1);
+    }
+
+    /// 握手阶段被丢弃：Client Hello 超过截止时间，或握手中的连接数已满
+    pub fn inc_slow_handshake_drops(&self) {
+        self.add(|c| &c.slow_handshake_drops, 1);
+    }
+
+    /// 上游连接失败后重试一次
+    pub fn inc_connect_retries(&self) {
+        self.add(|c| &c.connect_retries, 1);
+    }
+
+    /// 重试后仍然连接失败
+    pub fn inc_connect_retry_failures(&self) {
+        self.add(|c| &c.connect_retry_failures, 1);
+    }
+
+    /// 连接失败后改用其他上游重新连接
+    pub fn inc_upstream_fallbacks(&self) {
+        self.add(|c| &c.upstream_fallbacks, 1);
+    }
+
+    /// 增加 splice 转发的连接数
+    pub fn inc_splice_relays(&self) {
+        self.add(|c| &c.splice_relays, 1);
+    }
+
+    /// 增加用户态转发的连接数
+    pub fn inc_userspace_relays(&self) {
+        self.add(|c| &c.userspace_relays, 1);
+    }
+
+    /// 增加 io_uring 转发的连接数
+    pub fn inc_uring_relays(&self) {
+        self.add(|c| &c.uring_relays, 1);
+    }
+
+    /// 增加 DNS 预取重新解析的域名数
+    pub fn inc_dns_prefetched(&self) {
+        self.add(|c| &c.dns_prefetched, 1);
+    }
+
+    /// 增加 DNS 预取失败的域名数
+    pub fn inc_dns_prefetch_failures(&self) {
+        self.add(|c| &c.dns_prefetch_failures, 1);
+    }
+
+    /// 上游用 TLS alert 回应握手（连接本身成功）
+    pub fn inc_upstream_tls_alerts(&self) {
+        self.add(|c| &c.upstream_tls_alerts, 1);
+    }
+
+    /// 上游返回的不是 TLS 握手（HTTP 响应或其他数据，连接本身成功）
+    pub fn inc_upstream_not_tls(&self) {
+        self.add(|c| &c.upstream_not_tls, 1);
+    }
+
+    /// 记录一个由指定 CPU 接收的连接（超出 CPU 数量的编号会被忽略）
+    pub fn inc_connections_on_cpu(&self, cpu: usize) {
+        if let Some(counter) = self.inner.connections_per_cpu.get(cpu) {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
     }
 
     // 获取当前计数器值
     pub fn get_total_connections(&self) -> u64 {
-        self.inner.total_connections.load(Ordering::Relaxed)
+        self.inner.totals.total_connections.load(Ordering::Relaxed)
     }
 
     pub fn get_active_connections(&self) -> usize {
@@ -137,29 +461,76 @@ impl Metrics {
     }
 
     pub fn get_rejected_requests(&self) -> u64 {
-        self.inner.rejected_requests.load(Ordering::Relaxed)
+        self.inner.totals.rejected_requests.load(Ordering::Relaxed)
     }
 
     // 获取指标快照
     pub fn snapshot(&self) -> MetricsSnapshot {
+        let totals = self.inner.totals.snapshot();
         MetricsSnapshot {
-            total_connections: self.inner.total_connections.load(Ordering::Relaxed),
+            total_connections: totals.total_connections,
             active_connections: self.inner.active_connections.load(Ordering::Relaxed),
-            failed_connections: self.inner.failed_connections.load(Ordering::Relaxed),
-            bytes_received: self.inner.bytes_received.load(Ordering::Relaxed),
-            bytes_sent: self.inner.bytes_sent.load(Ordering::Relaxed),
-            direct_requests: self.inner.direct_requests.load(Ordering::Relaxed),
-            socks5_requests: self.inner.socks5_requests.load(Ordering::Relaxed),
-            rejected_requests: self.inner.rejected_requests.load(Ordering::Relaxed),
+            failed_connections: totals.failed_connections,
+            bytes_received: totals.bytes_received,
+            bytes_sent: totals.bytes_sent,
+            direct_requests: totals.direct_requests,
+            socks5_requests: totals.socks5_requests,
+            rejected_requests: totals.rejected_requests,
             dns_cache_hits: self.inner.dns_cache_hits.load(Ordering::Relaxed),
             dns_cache_misses: self.inner.dns_cache_misses.load(Ordering::Relaxed),
-            sni_parse_errors: self.inner.sni_parse_errors.load(Ordering::Relaxed),
-            socks5_errors: self.inner.socks5_errors.load(Ordering::Relaxed),
-            connection_timeouts: self.inner.connection_timeouts.load(Ordering::Relaxed),
+            sni_parse_errors: totals.sni_parse_errors,
+            plaintext_http_requests: totals.plaintext_http_requests,
+            fingerprint_rejections: totals.fingerprint_rejections,
+            ip_sni_rejections: totals.ip_sni_rejections,
+            burst_rejections: totals.burst_rejections,
+            geo_rejections: totals.geo_rejections,
+            ban_rejections: totals.ban_rejections,
+            overload_rejections: totals.overload_rejections,
+            socks5_errors: totals.socks5_errors,
+            connection_timeouts: totals.connection_timeouts,
+            idle_timeouts: totals.idle_timeouts,
+            slow_handshake_drops: totals.slow_handshake_drops,
+            connect_retries: totals.connect_retries,
+            connect_retry_failures: totals.connect_retry_failures,
+            upstream_fallbacks: totals.upstream_fallbacks,
+            splice_relays: totals.splice_relays,
+            userspace_relays: totals.userspace_relays,
+            uring_relays: totals.uring_relays,
+            dns_prefetched: totals.dns_prefetched,
+            dns_prefetch_failures: totals.dns_prefetch_failures,
+            upstream_tls_alerts: totals.upstream_tls_alerts,
+            upstream_not_tls: totals.upstream_not_tls,
+            connections_per_cpu: self
+                .inner
+                .connections_per_cpu
+                .iter()
+                .map(|c| c.load(Ordering::Relaxed))
+                .collect(),
             uptime: self.inner.start_time.elapsed(),
+            disk_full_errors: crate::disk::disk_full_errors(),
+            disk_full: crate::disk::suppressed_subsystems(),
         }
     }
 
+    /// 全局计数器快照
+    pub fn counters(&self) -> CounterSnapshot {
+        self.inner.totals.snapshot()
+    }
+
+    /// 按标签组合的计数器快照（按标签排序，便于导出）
+    pub fn series_snapshot(&self) -> Vec<(MetricLabels, CounterSnapshot)> {
+        let mut series: Vec<_> = self
+            .inner
+            .series
+            .lock()
+            .unwrap()
+            .values()
+            .map(|series| (series.labels, series.counters.snapshot()))
+            .collect();
+        series.sort_by_key(|(labels, _)| (labels.listener.to_string(), labels.route as u8, labels.upstream as u8));
+        series
+    }
+
     /// 打印监控指标
     pub fn print_summary(&self) {
         let snapshot = self.snapshot();
@@ -183,8 +554,44 @@ impl Metrics {
         }
 
         log::info!("SNI 解析错误: {}", snapshot.sni_parse_errors);
+        log::info!("明文 HTTP 请求: {}", snapshot.plaintext_http_requests);
+        log::info!("TLS 指纹拒绝: {}", snapshot.fingerprint_rejections);
+        log::info!("IP SNI 拒绝: {}", snapshot.ip_sni_rejections);
+        log::info!("重复连接退避: {}", snapshot.burst_rejections);
+        log::info!("国家限制拒绝: {}", snapshot.geo_rejections);
+        log::info!("自动封禁拒绝: {}", snapshot.ban_rejections);
+        log::info!("新连接速率超限: {}", snapshot.overload_rejections);
         log::info!("SOCKS5 错误: {}", snapshot.socks5_errors);
         log::info!("连接超时: {}", snapshot.connection_timeouts);
+        log::info!("空闲超时: {}", snapshot.idle_timeouts);
+        log::info!("握手阶段丢弃: {}", snapshot.slow_handshake_drops);
+        log::info!("上游连接重试: {}", snapshot.connect_retries);
+        log::info!("重试后仍失败: {}", snapshot.connect_retry_failures);
+        log::info!("改用其他上游: {}", snapshot.upstream_fallbacks);
+        log::info!("splice 转发的连接: {}", snapshot.splice_relays);
+        log::info!("用户态转发的连接: {}", snapshot.userspace_relays);
+        log::info!("io_uring 转发的连接: {}", snapshot.uring_relays);
+        log::info!("DNS 预取重新解析的域名: {}", snapshot.dns_prefetched);
+        log::info!("DNS 预取失败: {}", snapshot.dns_prefetch_failures);
+        log::info!("上游 TLS alert: {}", snapshot.upstream_tls_alerts);
+        log::info!("上游非 TLS 响应: {}", snapshot.upstream_not_tls);
+        if !snapshot.disk_full.is_empty() {
+            log::warn!("⚠️  磁盘已满，暂停写入: {}", snapshot.disk_full.join(", "));
+        }
+
+        // 仅在有按 CPU 统计的数据时打印（需要启用 SO_INCOMING_CPU 分流）
+        if snapshot.connections_per_cpu.iter().any(|&c| c > 0) {
+            let per_cpu: Vec<String> = snapshot
+                .connections_per_cpu
+                .iter()
+                .enumerate()
+                .map(|(cpu, count)| format!("CPU{}={}", cpu, count))
+                .collect();
+            log::info!("按 CPU 接收连接数: {}", per_cpu.join(" "));
+        }
+
+        #[cfg(feature = "alloc-audit")]
+        crate::alloc_audit::print_summary();
     }
 }
 
@@ -202,9 +609,157 @@ pub struct MetricsSnapshot {
     pub dns_cache_hits: u64,
     pub dns_cache_misses: u64,
     pub sni_parse_errors: u64,
+    /// TLS 端口收到的明文 HTTP 请求数
+    pub plaintext_http_requests: u64,
+    /// 被 TLS 指纹过滤规则拒绝的连接数
+    pub fingerprint_rejections: u64,
+    /// SNI 为 IP 地址被拒绝的连接数
+    pub ip_sni_rejections: u64,
+    /// 重复连接处于退避期间被关闭的连接数
+    pub burst_rejections: u64,
+    /// 客户端所在国家不被允许而拒绝的连接数
+    pub geo_rejections: u64,
+    /// 客户端 IP 被自动封禁而拒绝的连接数
+    pub ban_rejections: u64,
+    /// 新连接速率超过上限而丢弃的连接数
+    pub overload_rejections: u64,
     pub socks5_errors: u64,
     pub connection_timeouts: u64,
+    /// 转发阶段空闲超时断开的连接数
+    pub idle_timeouts: u64,
+    /// 握手阶段被丢弃的连接数（Client Hello 超过截止时间，或握手中的连接数已满）
+    pub slow_handshake_drops: u64,
+    /// 上游连接失败后重试的次数
+    pub connect_retries: u64,
+    /// 重试后仍然连接失败的连接数
+    pub connect_retry_failures: u64,
+    /// 连接失败后改用其他上游（直连 / SOCKS5）重新连接的次数
+    pub upstream_fallbacks: u64,
+    /// splice 转发的连接数（仅 Linux）
+    pub splice_relays: u64,
+    /// 用户态缓冲区转发的连接数
+    pub userspace_relays: u64,
+    /// io_uring 转发的连接数
+    pub uring_relays: u64,
+    /// DNS 预取重新解析的域名数
+    pub dns_prefetched: u64,
+    /// DNS 预取失败的域名数
+    pub dns_prefetch_failures: u64,
+    /// 上游用 TLS alert 回应握手的连接数
+    pub upstream_tls_alerts: u64,
+    /// 上游返回的不是 TLS 握手的连接数
+    pub upstream_not_tls: u64,
+    /// 按接收 CPU 统计的连接数（下标为 CPU 编号）
+    pub connections_per_cpu: Vec<u64>,
     pub uptime: Duration,
+    /// 遇到磁盘写满的累计次数
+    pub disk_full_errors: u64,
+    /// 因磁盘写满暂停写入的子系统（为空表示正常）
+    pub disk_full: Vec<&'static str>,
+}
+
+/// 单个标签组合的计数器快照
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CounterSnapshot {
+    pub total_connections: u64,
+    pub failed_connections: u64,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    pub direct_requests: u64,
+    pub socks5_requests: u64,
+    pub rejected_requests: u64,
+    pub sni_parse_errors: u64,
+    pub plaintext_http_requests: u64,
+    pub fingerprint_rejections: u64,
+    pub ip_sni_rejections: u64,
+    pub burst_rejections: u64,
+    pub geo_rejections: u64,
+    pub ban_rejections: u64,
+    pub overload_rejections: u64,
+    pub socks5_errors: u64,
+    pub connection_timeouts: u64,
+    pub idle_timeouts: u64,
+    pub slow_handshake_drops: u64,
+    pub connect_retries: u64,
+    pub connect_retry_failures: u64,
+    pub upstream_fallbacks: u64,
+    pub splice_relays: u64,
+    pub userspace_relays: u64,
+    pub uring_relays: u64,
+    pub dns_prefetched: u64,
+    pub dns_prefetch_failures: u64,
+    pub upstream_tls_alerts: u64,
+    pub upstream_not_tls: u64,
+}
+
+impl CounterSnapshot {
+    /// 相对较早快照的增量（计数器只增不减）
+    pub fn since(&self, earlier: &CounterSnapshot) -> CounterSnapshot {
+        CounterSnapshot {
+            total_connections: self.total_connections.saturating_sub(earlier.total_connections),
+            failed_connections: self.failed_connections.saturating_sub(earlier.failed_connections),
+            bytes_received: self.bytes_received.saturating_sub(earlier.bytes_received),
+            bytes_sent: self.bytes_sent.saturating_sub(earlier.bytes_sent),
+            direct_requests: self.direct_requests.saturating_sub(earlier.direct_requests),
+            socks5_requests: self.socks5_requests.saturating_sub(earlier.socks5_requests),
+            rejected_requests: self.rejected_requests.saturating_sub(earlier.rejected_requests),
+            sni_parse_errors: self.sni_parse_errors.saturating_sub(earlier.sni_parse_errors),
+            plaintext_http_requests: self.plaintext_http_requests.saturating_sub(earlier.plaintext_http_requests),
+            fingerprint_rejections: self.fingerprint_rejections.saturating_sub(earlier.fingerprint_rejections),
+            ip_sni_rejections: self.ip_sni_rejections.saturating_sub(earlier.ip_sni_rejections),
+            burst_rejections: self.burst_rejections.saturating_sub(earlier.burst_rejections),
+            geo_rejections: self.geo_rejections.saturating_sub(earlier.geo_rejections),
+            ban_rejections: self.ban_rejections.saturating_sub(earlier.ban_rejections),
+            overload_rejections: self.overload_rejections.saturating_sub(earlier.overload_rejections),
+            socks5_errors: self.socks5_errors.saturating_sub(earlier.socks5_errors),
+            connection_timeouts: self.connection_timeouts.saturating_sub(earlier.connection_timeouts),
+            idle_timeouts: self.idle_timeouts.saturating_sub(earlier.idle_timeouts),
+            slow_handshake_drops: self.slow_handshake_drops.saturating_sub(earlier.slow_handshake_drops),
+            connect_retries: self.connect_retries.saturating_sub(earlier.connect_retries),
+            connect_retry_failures: self.connect_retry_failures.saturating_sub(earlier.connect_retry_failures),
+            upstream_fallbacks: self.upstream_fallbacks.saturating_sub(earlier.upstream_fallbacks),
+            splice_relays: self.splice_relays.saturating_sub(earlier.splice_relays),
+            userspace_relays: self.userspace_relays.saturating_sub(earlier.userspace_relays),
+            uring_relays: self.uring_relays.saturating_sub(earlier.uring_relays),
+            dns_prefetched: self.dns_prefetched.saturating_sub(earlier.dns_prefetched),
+            dns_prefetch_failures: self.dns_prefetch_failures.saturating_sub(earlier.dns_prefetch_failures),
+            upstream_tls_alerts: self.upstream_tls_alerts.saturating_sub(earlier.upstream_tls_alerts),
+            upstream_not_tls: self.upstream_not_tls.saturating_sub(earlier.upstream_not_tls),
+        }
+    }
+
+    /// 累加另一个快照（合并多个标签组合）
+    pub fn add(&mut self, other: &CounterSnapshot) {
+        self.total_connections += other.total_connections;
+        self.failed_connections += other.failed_connections;
+        self.bytes_received += other.bytes_received;
+        self.bytes_sent += other.bytes_sent;
+        self.direct_requests += other.direct_requests;
+        self.socks5_requests += other.socks5_requests;
+        self.rejected_requests += other.rejected_requests;
+        self.sni_parse_errors += other.sni_parse_errors;
+        self.plaintext_http_requests += other.plaintext_http_requests;
+        self.fingerprint_rejections += other.fingerprint_rejections;
+        self.ip_sni_rejections += other.ip_sni_rejections;
+        self.burst_rejections += other.burst_rejections;
+        self.geo_rejections += other.geo_rejections;
+        self.ban_rejections += other.ban_rejections;
+        self.overload_rejections += other.overload_rejections;
+        self.socks5_errors += other.socks5_errors;
+        self.connection_timeouts += other.connection_timeouts;
+        self.idle_timeouts += other.idle_timeouts;
+        self.slow_handshake_drops += other.slow_handshake_drops;
+        self.connect_retries += other.connect_retries;
+        self.connect_retry_failures += other.connect_retry_failures;
+        self.upstream_fallbacks += other.upstream_fallbacks;
+        self.splice_relays += other.splice_relays;
+        self.userspace_relays += other.userspace_relays;
+        self.uring_relays += other.uring_relays;
+        self.dns_prefetched += other.dns_prefetched;
+        self.dns_prefetch_failures += other.dns_prefetch_failures;
+        self.upstream_tls_alerts += other.upstream_tls_alerts;
+        self.upstream_not_tls += other.upstream_not_tls;
+    }
 }
 
 /// RAII 风格的连接计数器
@@ -236,3 +791,42 @@ impl Drop for ConnectionGuard {
         log::debug!("📊 连接关闭 | 总连接数: {} | 活跃连接: {}", total, active);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_labeled_counters() {
+        let metrics = Metrics::new();
+        let listener = metrics.with_labels(MetricLabels::listener(ListenerLabel::Tcp(8443)));
+        let direct = listener.with_labels(MetricLabels {
+            route: RouteLabel::Direct,
+            upstream: UpstreamLabel::Direct,
+            ..listener.labels().unwrap()
+        });
+
+        metrics.inc_failed_connections();
+        listener.inc_total_connections();
+        direct.inc_direct_requests();
+        direct.add_bytes_sent(100);
+        // 相同标签组合共享计数器
+        metrics
+            .with_labels(direct.labels().unwrap())
+            .add_bytes_sent(20);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.failed_connections, 1);
+        assert_eq!(snapshot.total_connections, 1);
+        assert_eq!(snapshot.bytes_sent, 120);
+
+        let series = metrics.series_snapshot();
+        assert_eq!(series.len(), 2);
+        assert_eq!(series[0].0, MetricLabels::listener(ListenerLabel::Tcp(8443)));
+        assert_eq!(series[0].1.total_connections, 1);
+        assert_eq!(series[1].0.route, RouteLabel::Direct);
+        assert_eq!(series[1].1.direct_requests, 1);
+        assert_eq!(series[1].1.bytes_sent, 120);
+        assert_eq!(series[1].1.failed_connections, 0);
+    }
+}
@@ -0,0 +1,294 @@
+//! 出站连接选项
+//!
+//! 多网卡或多地址的主机上，出站连接的源地址和出口由系统路由表决定。上游按源地址做访问控制，或者需要走特定线路时，
+//! 可以在连接前把出站 socket 绑定到指定的本地地址或网络接口（SO_BINDTODEVICE，例如 `wan1`、`wg0`）。
+//! 还可以给出站连接打上防火墙标记（SO_MARK），由策略路由或 nftables 按路由类别分流和统计流量。
+//! 开启客户端 TCP Fast Open 后，连接 SOCKS5 代理前设置 TCP_FASTOPEN_CONNECT，缓存了服务器 TFO cookie 时
+//! 第一次写入的数据（SOCKS5 握手请求）随 SYN 一起发送，每个新的上游连接节省 1 RTT。
+//! 直连不使用 TFO（见 [`crate::happy_eyeballs::connect`]）：有 cookie 时 connect 不等握手就返回，
+//! Happy Eyeballs、连接超时、重试和上游回退都无法判断目标是否可达。
+//! 全局配置对所有直连和 SOCKS5 连接生效，路由规则可以单独指定（规则中设置的选项覆盖全局配置）。
+//! QUIC 转发的出站 UDP socket 同样应用源地址、网络接口和防火墙标记，TCP Fast Open 只适用于 TCP
+
+use log::debug;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use tokio::net::{TcpSocket, TcpStream, UdpSocket};
+
+/// 出站 socket 选项
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OutboundOptions {
+    /// 源地址（端口由系统分配）
+    pub bind_addr: Option<IpAddr>,
+    /// 出口网络接口名称（仅 Linux）
+    pub interface: Option<String>,
+    /// 防火墙标记 fwmark（仅 Linux）
+    pub fwmark: Option<u32>,
+    /// 客户端 TCP Fast Open（仅 Linux 4.11+），None 表示不设置
+    pub fastopen: Option<bool>,
+}
+
+impl OutboundOptions {
+    /// 是否没有设置任何选项
+    pub fn is_empty(&self) -> bool {
+        self.bind_addr.is_none() && self.interface.is_none() && self.fwmark.is_none() && self.fastopen.is_none()
+    }
+
+    /// 合并选项：自己设置的选项优先，没有设置的使用 `fallback` 中的
+    pub fn or(&self, fallback: &OutboundOptions) -> OutboundOptions {
+        OutboundOptions {
+            bind_addr: self.bind_addr.or(fallback.bind_addr),
+            interface: self.interface.clone().or_else(|| fallback.interface.clone()),
+            fwmark: self.fwmark.or(fallback.fwmark),
+            fastopen: self.fastopen.or(fallback.fastopen),
+        }
+    }
+
+    /// 按选项创建 socket 并连接到 `addr`
+    ///
+    /// 源地址与目标地址的地址族不同时返回 `InvalidInput` 错误（Happy Eyeballs 会继续尝试另一个地址族）
+    pub async fn connect(&self, addr: SocketAddr) -> io::Result<TcpStream> {
+        if self.is_empty() {
+            return TcpStream::connect(addr).await;
+        }
+        let socket = if addr.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+        if let Some(ip) = self.bind_addr {
+            if ip.is_ipv4() != addr.is_ipv4() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("源地址 {} 与目标地址 {} 的地址族不同", ip, addr),
+                ));
+            }
+            socket.bind(SocketAddr::new(ip, 0))?;
+        }
+        if let Some(interface) = &self.interface {
+            bind_device(&socket, interface)?;
+        }
+        if let Some(mark) = self.fwmark {
+            set_mark(&socket, mark)?;
+        }
+        // TFO 只是优化，设置失败时按普通连接处理
+        if self.fastopen == Some(true) {
+            if let Err(e) = enable_fastopen_connect(&socket) {
+                debug!("⚠️  客户端 TCP Fast Open 启用失败: {}", e);
+            }
+        }
+        socket.connect(addr).await
+    }
+
+    /// 按选项创建 UDP socket 并连接到 `addr`（QUIC 转发使用，忽略 `fastopen`）
+    pub async fn connect_udp(&self, addr: SocketAddr) -> io::Result<UdpSocket> {
+        let bind_ip = match self.bind_addr {
+            Some(ip) if ip.is_ipv4() != addr.is_ipv4() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("源地址 {} 与目标地址 {} 的地址族不同", ip, addr),
+                ));
+            }
+            Some(ip) => ip,
+            None if addr.is_ipv4() => IpAddr::from([0u8; 4]),
+            None => IpAddr::from([0u16; 8]),
+        };
+        let socket = UdpSocket::bind(SocketAddr::new(bind_ip, 0)).await?;
+        if let Some(interface) = &self.interface {
+            bind_device(&socket, interface)?;
+        }
+        if let Some(mark) = self.fwmark {
+            set_mark(&socket, mark)?;
+        }
+        socket.connect(addr).await?;
+        Ok(socket)
+    }
+}
+
+/// 把 socket 绑定到网络接口（SO_BINDTODEVICE）
+#[cfg(target_os = "linux")]
+fn bind_device<S: std::os::unix::io::AsRawFd>(socket: &S, interface: &str) -> io::Result<()> {
+    // SAFETY: fd 在 socket 的生命周期内有效，接口名的指针和长度来自同一个切片
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_BINDTODEVICE,
+            interface.as_ptr() as *const libc::c_void,
+            interface.len() as libc::socklen_t,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// 把 socket 绑定到网络接口（非 Linux 平台不支持）
+#[cfg(not(target_os = "linux"))]
+fn bind_device<S>(_socket: &S, _interface: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "当前平台不支持 SO_BINDTODEVICE"))
+}
+
+/// 设置 socket 的防火墙标记（SO_MARK，需要 CAP_NET_ADMIN）
+#[cfg(target_os = "linux")]
+fn set_mark<S: std::os::unix::io::AsRawFd>(socket: &S, mark: u32) -> io::Result<()> {
+    // SAFETY: fd 在 socket 的生命周期内有效，mark 的长度与 socklen 一致
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_MARK,
+            &mark as *const _ as *const libc::c_void,
+            std::mem::size_of_val(&mark) as libc::socklen_t,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// 设置 socket 的防火墙标记（非 Linux 平台不支持）
+#[cfg(not(target_os = "linux"))]
+fn set_mark<S>(_socket: &S, _mark: u32) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "当前平台不支持 SO_MARK"))
+}
+
+/// 连接前设置 TCP_FASTOPEN_CONNECT（需要 Linux 4.11+，且 net.ipv4.tcp_fastopen 开启客户端模式）
+///
+/// 有 cookie 时 connect 立即返回，SYN 推迟到第一次写入时携带数据发送；没有 cookie 时按普通握手连接并获取 cookie
+#[cfg(target_os = "linux")]
+fn enable_fastopen_connect(socket: &TcpSocket) -> io::Result<()> {
+    use crate::platform::{KernelFeature, PlatformInfo};
+    use std::os::unix::io::AsRawFd;
+
+    if !PlatformInfo::detect().supports(KernelFeature::TcpFastOpenConnect) {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "内核不支持 TCP_FASTOPEN_CONNECT（需要 4.11+）"));
+    }
+    const TCP_FASTOPEN_CONNECT: libc::c_int = 30; // Linux 特定常量
+    let enable: libc::c_int = 1;
+    // SAFETY: fd 在 socket 的生命周期内有效，enable 的长度与 socklen 一致
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            TCP_FASTOPEN_CONNECT,
+            &enable as *const _ as *const libc::c_void,
+            std::mem::size_of_val(&enable) as libc::socklen_t,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// 连接前设置 TCP_FASTOPEN_CONNECT（非 Linux 平台不支持）
+#[cfg(not(target_os = "linux"))]
+fn enable_fastopen_connect(_socket: &TcpSocket) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "当前平台不支持 TCP_FASTOPEN_CONNECT"))
+}
+
+/// 检查能否设置防火墙标记（平台支持且有 CAP_NET_ADMIN 权限）
+pub fn check_fwmark(mark: u32) -> io::Result<()> {
+    let socket = socket2::Socket::new(socket2::Domain::IPV4, socket2::Type::STREAM, None)?;
+    set_mark(&socket, mark)
+}
+
+/// 检查网络接口是否存在
+#[cfg(target_os = "linux")]
+pub fn check_interface(name: &str) -> io::Result<()> {
+    let c_name = std::ffi::CString::new(name).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    // SAFETY: c_name 是以 NUL 结尾的有效字符串
+    if unsafe { libc::if_nametoindex(c_name.as_ptr()) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// 检查网络接口是否存在（非 Linux 平台不支持按接口绑定）
+#[cfg(not(target_os = "linux"))]
+pub fn check_interface(_name: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "当前平台不支持 SO_BINDTODEVICE"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_connect_from_bind_addr() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let options = OutboundOptions { bind_addr: Some("127.0.0.2".parse().unwrap()), ..Default::default() };
+
+        let stream = options.connect(addr).await.unwrap();
+        let (_, peer) = listener.accept().await.unwrap();
+        assert_eq!(peer, stream.local_addr().unwrap());
+        assert_eq!(peer.ip(), options.bind_addr.unwrap());
+
+        // 地址族不同
+        let options = OutboundOptions { bind_addr: Some("::1".parse().unwrap()), ..Default::default() };
+        let err = options.connect(addr).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        // 规则中的选项优先
+        let global = OutboundOptions {
+            bind_addr: Some("192.0.2.1".parse().unwrap()),
+            interface: Some("wan1".to_string()),
+            fwmark: Some(0x10),
+            fastopen: Some(true),
+        };
+        let merged = OutboundOptions {
+            interface: global.interface.clone(),
+            fwmark: global.fwmark,
+            fastopen: global.fastopen,
+            ..options.clone()
+        };
+        assert_eq!(options.or(&global), merged);
+        assert_eq!(OutboundOptions::default().or(&global), global);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_connect_via_interface() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = OutboundOptions { interface: Some("lo".to_string()), ..Default::default() };
+        options.connect(listener.local_addr().unwrap()).await.unwrap();
+
+        assert!(check_interface("lo").is_ok());
+        assert!(check_interface("sni-proxy-none0").is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_connect_udp_with_options() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let options = OutboundOptions {
+            bind_addr: Some("127.0.0.2".parse().unwrap()),
+            interface: Some("lo".to_string()),
+            ..Default::default()
+        };
+        let socket = options.connect_udp(server.local_addr().unwrap()).await.unwrap();
+        assert_eq!(socket.local_addr().unwrap().ip(), "127.0.0.2".parse::<IpAddr>().unwrap());
+
+        let options = OutboundOptions { bind_addr: Some("::1".parse().unwrap()), ..Default::default() };
+        let err = options.connect_udp(server.local_addr().unwrap()).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[tokio::test]
+    async fn test_connect_with_fastopen() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // 不管有没有 TFO cookie，第一次写入的数据都能到达服务器
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = OutboundOptions { fastopen: Some(true), ..Default::default() };
+        for _ in 0..2 {
+            let mut stream = options.connect(listener.local_addr().unwrap()).await.unwrap();
+            stream.write_all(b"hello").await.unwrap();
+            let (mut server, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 5];
+            server.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hello");
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! 输出文件的权限和属主
+//!
+//! 日志、统计、持久化文件和每日报告包含客户端 IP、访问的域名等流量元数据。
+//! 配置权限后，这些文件在创建时就以指定的模式打开（`open(2)` 的 mode 参数），
+//! 写入任何数据之前再通过文件描述符设置模式和属主，不会出现权限过宽的窗口期
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::error::{Result, SniProxyError};
+
+static PERMISSIONS: OnceLock<OutputPermissions> = OnceLock::new();
+
+/// 输出文件的权限和属主（未设置的字段保持系统默认）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputPermissions {
+    /// 文件模式，例如 0o640
+    pub mode: Option<u32>,
+    /// 属主 UID
+    pub uid: Option<u32>,
+    /// 属组 GID
+    pub gid: Option<u32>,
+}
+
+impl OutputPermissions {
+    /// 解析八进制文件模式，例如 "0640" 或 "640"
+    pub fn parse_mode(mode: &str) -> Result<u32> {
+        let digits = mode.strip_prefix("0o").unwrap_or(mode);
+        match u32::from_str_radix(digits, 8) {
+            Ok(mode) if mode <= 0o7777 => Ok(mode),
+            _ => Err(SniProxyError::InvalidConfig(format!("无效的文件模式: {}（应为八进制，例如 0640）", mode))),
+        }
+    }
+
+    /// 按用户名或数字 UID 查找用户
+    #[cfg(unix)]
+    pub fn lookup_user(user: &str) -> Result<u32> {
+        if let Ok(uid) = user.parse() {
+            return Ok(uid);
+        }
+        let name = std::ffi::CString::new(user).map_err(|_| unknown("用户", user))?;
+        // SAFETY: 启动时单线程调用，返回的指针只在本次调用内读取
+        let entry = unsafe { libc::getpwnam(name.as_ptr()) };
+        if entry.is_null() {
+            return Err(unknown("用户", user));
+        }
+        Ok(unsafe { (*entry).pw_uid })
+    }
+
+    /// 按组名或数字 GID 查找用户组
+    #[cfg(unix)]
+    pub fn lookup_group(group: &str) -> Result<u32> {
+        if let Ok(gid) = group.parse() {
+            return Ok(gid);
+        }
+        let name = std::ffi::CString::new(group).map_err(|_| unknown("用户组", group))?;
+        // SAFETY: 同上
+        let entry = unsafe { libc::getgrnam(name.as_ptr()) };
+        if entry.is_null() {
+            return Err(unknown("用户组", group));
+        }
+        Ok(unsafe { (*entry).gr_gid })
+    }
+
+    /// 通过文件描述符设置模式和属主
+    fn apply(&self, file: &File) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            if let Some(mode) = self.mode {
+                file.set_permissions(std::fs::Permissions::from_mode(mode))?;
+            }
+            if self.uid.is_some() || self.gid.is_some() {
+                std::os::unix::fs::fchown(file, self.uid, self.gid)?;
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = file;
+        Ok(())
+    }
+}
+
+fn unknown(kind: &str, name: &str) -> SniProxyError {
+    SniProxyError::InvalidConfig(format!("{}不存在: {}", kind, name))
+}
+
+/// 设置输出文件的权限和属主（进程内只能设置一次，应在创建任何输出文件之前调用）
+pub fn set_output_permissions(permissions: OutputPermissions) -> Result<()> {
+    PERMISSIONS
+        .set(permissions)
+        .map_err(|_| SniProxyError::InvalidConfig("输出文件权限已经设置过".to_string()))
+}
+
+/// 按配置的权限打开文件
+pub fn open(path: impl AsRef<Path>, options: &mut OpenOptions) -> io::Result<File> {
+    let permissions = PERMISSIONS.get().copied().unwrap_or_default();
+    #[cfg(unix)]
+    if let Some(mode) = permissions.mode {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(mode);
+    }
+    let file = options.open(path)?;
+    // 已存在的文件不受 open 的 mode 参数影响，统一再设置一次
+    permissions.apply(&file)?;
+    Ok(file)
+}
+
+/// 创建（或截断）文件，替代 `File::create`
+pub fn create(path: impl AsRef<Path>) -> io::Result<File> {
+    open(path, OpenOptions::new().write(true).create(true).truncate(true))
+}
+
+/// 以追加方式打开文件（不存在时创建）
+pub fn append(path: impl AsRef<Path>) -> io::Result<File> {
+    open(path, OpenOptions::new().create(true).append(true))
+}
+
+/// 写入整个文件，替代 `std::fs::write`
+pub fn write(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    use std::io::Write;
+    create(path)?.write_all(contents.as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(OutputPermissions::parse_mode("0640").unwrap(), 0o640);
+        assert_eq!(OutputPermissions::parse_mode("600").unwrap(), 0o600);
+        assert_eq!(OutputPermissions::parse_mode("0o600").unwrap(), 0o600);
+        assert!(OutputPermissions::parse_mode("0890").is_err());
+        assert!(OutputPermissions::parse_mode("77777").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_apply_permissions() {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        let path = std::env::temp_dir().join(format!("sni-proxy-output-{}", std::process::id()));
+        let file = File::create(&path).unwrap();
+        let gid = file.metadata().unwrap().gid();
+
+        let permissions = OutputPermissions {
+            mode: Some(0o640),
+            uid: None,
+            gid: Some(gid),
+        };
+        permissions.apply(&file).unwrap();
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o7777, 0o640);
+        assert_eq!(metadata.gid(), gid);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(OutputPermissions::lookup_user("0").unwrap(), 0);
+        assert_eq!(OutputPermissions::lookup_user("root").unwrap(), 0);
+        assert_eq!(OutputPermissions::lookup_group("0").unwrap(), 0);
+        assert!(OutputPermissions::lookup_group("no-such-group-sni-proxy").is_err());
+    }
+}
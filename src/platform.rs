@@ -0,0 +1,254 @@
+use std::sync::OnceLock;
+
+/// 依赖较新内核的 socket 特性
+///
+/// 静态链接（musl）的二进制会被分发到内核版本各异的服务器上，
+/// 这些特性需要在运行时按内核版本判断，而不是在编译时假定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KernelFeature {
+    /// SO_REUSEPORT（Linux 3.9+）
+    ReusePort,
+    /// SO_INCOMING_CPU（Linux 3.19+）
+    IncomingCpu,
+    /// TCP_FASTOPEN_CONNECT（Linux 4.11+）
+    TcpFastOpenConnect,
+}
+
+impl KernelFeature {
+    /// 需要的最低内核版本
+    pub fn min_kernel_version(self) -> (u32, u32) {
+        match self {
+            KernelFeature::ReusePort => (3, 9),
+            KernelFeature::IncomingCpu => (3, 19),
+            KernelFeature::TcpFastOpenConnect => (4, 11),
+        }
+    }
+}
+
+/// 运行平台信息
+#[derive(Debug, Clone)]
+pub struct PlatformInfo {
+    /// C 运行库: "musl"、"gnu" 或其他
+    pub libc: &'static str,
+    /// 是否为静态链接构建
+    pub static_binary: bool,
+    /// 内核版本字符串（uname -r），非 Linux 或获取失败时为 None
+    pub kernel_release: Option<String>,
+    /// 解析后的内核主/次版本号
+    pub kernel_version: Option<(u32, u32)>,
+    /// 进程可以调度到的 CPU 编号（CPU 亲和性 / cpuset，启动时读取），不一定从 0 开始连续
+    pub affinity_cpus: Vec<usize>,
+    /// 容器（cgroup）的 CPU 配额，单位为 CPU 个数，没有限制或无法读取时为 None
+    pub cpu_quota: Option<f64>,
+}
+
+impl PlatformInfo {
+    /// 检测当前运行平台（结果会被缓存）
+    pub fn detect() -> &'static PlatformInfo {
+        static PLATFORM: OnceLock<PlatformInfo> = OnceLock::new();
+        PLATFORM.get_or_init(|| {
+            let kernel_release = kernel_release();
+            let kernel_version = kernel_release.as_deref().and_then(parse_kernel_version);
+            PlatformInfo {
+                libc: libc_flavor(),
+                static_binary: cfg!(target_feature = "crt-static"),
+                kernel_release,
+                kernel_version,
+                affinity_cpus: affinity_cpus(),
+                cpu_quota: cgroup_cpu_quota(),
+            }
+        })
+    }
+
+    /// 实际可用的 CPU 数：CPU 亲和性和 cgroup CPU 配额（向上取整）中较小的一个，至少为 1
+    ///
+    /// 容器中 CPU 核心数是宿主机的，按它创建工作线程、设置连接数上限会超出配额，线程被 CFS 限流
+    pub fn available_cpus(&self) -> usize {
+        let cpus = match self.cpu_quota {
+            Some(quota) => self.affinity_cpus.len().min(quota.ceil() as usize),
+            None => self.affinity_cpus.len(),
+        };
+        cpus.max(1)
+    }
+
+    /// 第 `index` 个 acceptor 使用的 CPU 编号：在进程可以调度到的 CPU 中轮流分配
+    pub fn acceptor_cpu(&self, index: usize) -> usize {
+        self.affinity_cpus[index % self.affinity_cpus.len()]
+    }
+
+    /// 当前内核是否支持指定特性
+    ///
+    /// 非 Linux 平台返回 false；Linux 上无法获取内核版本时假定支持（由 setsockopt 的结果兜底）
+    pub fn supports(&self, feature: KernelFeature) -> bool {
+        if !cfg!(target_os = "linux") {
+            return false;
+        }
+        match self.kernel_version {
+            Some(version) => version >= feature.min_kernel_version(),
+            None => true,
+        }
+    }
+}
+
+/// 实际可用的 CPU 数（见 [`PlatformInfo::available_cpus`]），替代 `num_cpus::get()` 用于按 CPU 数自适应的默认值
+pub fn available_cpus() -> usize {
+    PlatformInfo::detect().available_cpus()
+}
+
+/// 编译时确定的 C 运行库类型
+fn libc_flavor() -> &'static str {
+    if cfg!(target_env = "musl") {
+        "musl"
+    } else if cfg!(target_env = "gnu") {
+        "gnu"
+    } else if cfg!(target_env = "msvc") {
+        "msvc"
+    } else {
+        "unknown"
+    }
+}
+
+/// 通过 uname 获取内核版本字符串（musl 与 glibc 都支持）
+#[cfg(unix)]
+fn kernel_release() -> Option<String> {
+    let mut uts: libc::utsname = unsafe { std::mem::zeroed() };
+    if unsafe { libc::uname(&mut uts) } != 0 {
+        return None;
+    }
+    let release = unsafe { std::ffi::CStr::from_ptr(uts.release.as_ptr()) };
+    Some(release.to_string_lossy().into_owned())
+}
+
+#[cfg(not(unix))]
+fn kernel_release() -> Option<String> {
+    None
+}
+
+/// 进程可以调度到的 CPU 编号（sched_getaffinity），获取失败时按 0..CPU 数处理
+#[cfg(target_os = "linux")]
+fn affinity_cpus() -> Vec<usize> {
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    // SAFETY: set 的大小与传入的长度一致
+    if unsafe { libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) } != 0 {
+        return (0..num_cpus::get()).collect();
+    }
+    // SAFETY: set 已由 sched_getaffinity 填写
+    let cpus: Vec<usize> =
+        (0..libc::CPU_SETSIZE as usize).filter(|&cpu| unsafe { libc::CPU_ISSET(cpu, &set) }).collect();
+    if cpus.is_empty() {
+        return (0..num_cpus::get()).collect();
+    }
+    cpus
+}
+
+#[cfg(not(target_os = "linux"))]
+fn affinity_cpus() -> Vec<usize> {
+    (0..num_cpus::get()).collect()
+}
+
+/// 读取 cgroup CPU 配额：cgroup v2 的 cpu.max（逐级向上取最小值），或 cgroup v1 的 cpu.cfs_quota_us
+#[cfg(target_os = "linux")]
+fn cgroup_cpu_quota() -> Option<f64> {
+    use std::path::Path;
+
+    let cgroups = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+    for line in cgroups.lines() {
+        let mut fields = line.splitn(3, ':');
+        let (Some(_), Some(controllers), Some(path)) = (fields.next(), fields.next(), fields.next()) else {
+            continue;
+        };
+        if controllers.is_empty() {
+            // cgroup v2：容器内（cgroup 命名空间）路径为 "/"，对应挂载点根目录
+            let mut dir = Path::new("/sys/fs/cgroup").join(path.trim_start_matches('/'));
+            let mut quota: Option<f64> = None;
+            loop {
+                if let Some(limit) = std::fs::read_to_string(dir.join("cpu.max")).ok().and_then(|s| parse_cpu_max(&s)) {
+                    quota = Some(quota.map_or(limit, |quota| quota.min(limit)));
+                }
+                if dir == Path::new("/sys/fs/cgroup") || !dir.pop() {
+                    break;
+                }
+            }
+            return quota;
+        }
+        if controllers.split(',').any(|controller| controller == "cpu") {
+            let path = path.trim_start_matches('/');
+            for mount in ["/sys/fs/cgroup/cpu,cpuacct", "/sys/fs/cgroup/cpu"] {
+                for dir in [Path::new(mount).join(path), Path::new(mount).to_path_buf()] {
+                    let quota = std::fs::read_to_string(dir.join("cpu.cfs_quota_us"));
+                    let period = std::fs::read_to_string(dir.join("cpu.cfs_period_us"));
+                    if let (Ok(quota), Ok(period)) = (quota, period) {
+                        return parse_cfs_quota(&quota, &period);
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cgroup_cpu_quota() -> Option<f64> {
+    None
+}
+
+/// 解析 cgroup v2 的 cpu.max，例如 "150000 100000" → 1.5，"max 100000" → None
+fn parse_cpu_max(content: &str) -> Option<f64> {
+    let mut fields = content.split_whitespace();
+    let quota = fields.next()?;
+    let period = fields.next().unwrap_or("100000");
+    parse_cfs_quota(quota, period)
+}
+
+/// 解析 cgroup v1 的 cpu.cfs_quota_us 和 cpu.cfs_period_us（quota 为 -1 表示不限制）
+fn parse_cfs_quota(quota: &str, period: &str) -> Option<f64> {
+    let quota: i64 = quota.trim().parse().ok()?;
+    let period: i64 = period.trim().parse().ok()?;
+    (quota > 0 && period > 0).then(|| quota as f64 / period as f64)
+}
+
+/// 解析内核版本号，例如 "5.15.0-91-generic" → (5, 15)
+fn parse_kernel_version(release: &str) -> Option<(u32, u32)> {
+    let mut parts = release.split(|c: char| !c.is_ascii_digit());
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_kernel_version() {
+        assert_eq!(parse_kernel_version("5.15.0-91-generic"), Some((5, 15)));
+        assert_eq!(parse_kernel_version("3.10.0-1160.el7.x86_64"), Some((3, 10)));
+        assert_eq!(parse_kernel_version("6.1"), Some((6, 1)));
+        assert_eq!(parse_kernel_version("unknown"), None);
+    }
+
+    #[test]
+    fn test_parse_cpu_quota() {
+        assert_eq!(parse_cpu_max("150000 100000\n"), Some(1.5));
+        assert_eq!(parse_cpu_max("max 100000\n"), None);
+        assert_eq!(parse_cfs_quota("200000\n", "100000\n"), Some(2.0));
+        assert_eq!(parse_cfs_quota("-1\n", "100000\n"), None);
+    }
+
+    #[test]
+    fn test_supports() {
+        let old_kernel = PlatformInfo {
+            libc: "musl",
+            static_binary: true,
+            kernel_release: Some("3.10.0".to_string()),
+            kernel_version: Some((3, 10)),
+            affinity_cpus: vec![2, 3, 6, 7],
+            cpu_quota: Some(1.5),
+        };
+        assert_eq!(old_kernel.available_cpus(), 2);
+        assert_eq!((0..5).map(|index| old_kernel.acceptor_cpu(index)).collect::<Vec<_>>(), vec![2, 3, 6, 7, 2]);
+        assert_eq!(old_kernel.supports(KernelFeature::ReusePort), cfg!(target_os = "linux"));
+        assert!(!old_kernel.supports(KernelFeature::IncomingCpu));
+        assert!(!old_kernel.supports(KernelFeature::TcpFastOpenConnect));
+    }
+}
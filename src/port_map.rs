@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+
+/// 默认目标端口（HTTPS）
+pub const DEFAULT_TARGET_PORT: u16 = 443;
+
+/// 目标端口映射（按监听端口决定连接目标服务器的端口）
+///
+/// 例如监听 8443 转发到 443、监听 993 转发到 993（IMAP over TLS），
+/// 未配置映射的监听端口使用默认目标端口
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortMapping {
+    /// 默认目标端口
+    default_port: u16,
+    /// 监听端口 → 目标端口
+    by_listen_port: HashMap<u16, u16>,
+}
+
+impl Default for PortMapping {
+    fn default() -> Self {
+        Self::new(DEFAULT_TARGET_PORT)
+    }
+}
+
+impl PortMapping {
+    /// 创建端口映射（所有监听端口都转发到 `default_port`）
+    pub fn new(default_port: u16) -> Self {
+        Self {
+            default_port,
+            by_listen_port: HashMap::new(),
+        }
+    }
+
+    /// 添加监听端口到目标端口的映射
+    pub fn with_mapping(mut self, listen_port: u16, target_port: u16) -> Self {
+        self.by_listen_port.insert(listen_port, target_port);
+        self
+    }
+
+    /// 获取默认目标端口
+    pub fn default_port(&self) -> u16 {
+        self.default_port
+    }
+
+    /// 根据连接的监听端口获取目标端口
+    pub fn target_port(&self, listen_port: u16) -> u16 {
+        self.by_listen_port
+            .get(&listen_port)
+            .copied()
+            .unwrap_or(self.default_port)
+    }
+
+    /// 是否配置了端口映射
+    pub fn is_empty(&self) -> bool {
+        self.by_listen_port.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_port() {
+        let mapping = PortMapping::default();
+        assert_eq!(mapping.target_port(8443), 443);
+        assert!(mapping.is_empty());
+    }
+
+    #[test]
+    fn test_port_mapping() {
+        let mapping = PortMapping::new(8443)
+            .with_mapping(8443, 443)
+            .with_mapping(993, 993);
+
+        assert_eq!(mapping.target_port(8443), 443);
+        assert_eq!(mapping.target_port(993), 993);
+        assert_eq!(mapping.target_port(5223), 8443);
+    }
+}
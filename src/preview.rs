@@ -0,0 +1,200 @@
+//! 数据预览（诊断用）
+//!
+//! 对匹配规则的连接，以十六进制记录客户端和上游最先发送的若干字节，用于排查协议不匹配
+//! （例如明文 HTTP 发到了 TLS 端口、上游返回的不是 TLS）。默认关闭，且只记录握手部分：
+//! TLS 数据遇到第一个应用数据记录即停止，非 TLS 数据只记录第一行
+
+use log::info;
+use std::io::IoSlice;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::domain::DomainMatcher;
+use crate::ip_matcher::IpMatcher;
+use crate::tls::TLS_RECORD_HEADER_LEN;
+
+/// TLS 记录类型：change_cipher_spec / alert / handshake / application_data
+const CONTENT_CHANGE_CIPHER_SPEC: u8 = 0x14;
+const CONTENT_APPLICATION_DATA: u8 = 0x17;
+
+/// 数据预览规则：SNI 匹配 `domains` 或客户端 IP 匹配 `client_ips` 的连接会被记录
+///
+/// 无法解析 SNI 的连接（例如明文 HTTP）只能按客户端 IP 匹配
+#[derive(Debug)]
+pub struct BodyPreview {
+    domain_matcher: Option<DomainMatcher>,
+    ip_matcher: Option<IpMatcher>,
+    /// 每个方向最多记录的字节数
+    max_bytes: usize,
+}
+
+impl BodyPreview {
+    /// 创建预览规则（`domains` 支持通配符，`client_ips` 支持 CIDR）
+    pub fn new(domains: Vec<String>, client_ips: Vec<String>, max_bytes: usize) -> Self {
+        let ip_matcher = IpMatcher::new(client_ips);
+        Self {
+            domain_matcher: (!domains.is_empty()).then(|| DomainMatcher::new(domains)),
+            ip_matcher: (!ip_matcher.is_empty()).then_some(ip_matcher),
+            max_bytes,
+        }
+    }
+
+    /// 连接是否匹配预览规则
+    pub fn matches(&self, client_ip: IpAddr, sni: Option<&str>) -> bool {
+        self.ip_matcher.as_ref().is_some_and(|m| m.matches(client_ip))
+            || sni.is_some_and(|sni| self.domain_matcher.as_ref().is_some_and(|m| m.matches(sni)))
+    }
+
+    /// 记录客户端数据预览
+    pub(crate) fn log_client(&self, client_addr: SocketAddr, sni: Option<&str>, data: &[u8]) {
+        info!(
+            "🔍 客户端数据预览 {} (SNI: {}): {}",
+            client_addr,
+            sni.unwrap_or("-"),
+            format_preview(data, self.max_bytes)
+        );
+    }
+
+    /// 包装上游连接，记录上游最先返回的数据
+    pub(crate) fn wrap_upstream<S>(&self, inner: S, sni: &str) -> PreviewStream<S> {
+        PreviewStream {
+            inner,
+            label: sni.to_string(),
+            max_bytes: self.max_bytes,
+            logged: false,
+        }
+    }
+}
+
+/// 格式化数据预览：`<长度> 字节 | <十六进制> | <可打印字符>`，超出部分注明脱敏原因
+pub fn format_preview(data: &[u8], max_bytes: usize) -> String {
+    let (visible, reason) = visible_prefix(data);
+    let shown = &visible[..visible.len().min(max_bytes)];
+
+    let hex: Vec<String> = shown.iter().map(|b| format!("{:02x}", b)).collect();
+    let ascii: String = shown
+        .iter()
+        .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+        .collect();
+
+    let mut preview = format!("{} 字节 | {} | {}", data.len(), hex.join(" "), ascii);
+    let hidden = data.len() - shown.len();
+    if hidden > 0 {
+        let reason = if shown.len() < visible.len() { "超出预览长度" } else { reason };
+        preview.push_str(&format!(" | 省略 {} 字节（{}）", hidden, reason));
+    }
+    preview
+}
+
+/// 允许记录的前缀及其后数据被省略的原因
+fn visible_prefix(data: &[u8]) -> (&[u8], &'static str) {
+    if !looks_like_tls(data) {
+        // 非 TLS 数据只保留第一行（例如 HTTP 请求行），请求头中可能有 Cookie、Authorization
+        let end = data.iter().position(|&b| b == b'\n').map_or(data.len(), |i| i + 1);
+        return (&data[..end], "非 TLS 数据只显示第一行");
+    }
+
+    // TLS：保留握手阶段的记录，从第一个应用数据记录开始脱敏
+    let mut pos = 0;
+    while pos + TLS_RECORD_HEADER_LEN <= data.len() && data[pos] != CONTENT_APPLICATION_DATA {
+        pos += TLS_RECORD_HEADER_LEN + u16::from_be_bytes([data[pos + 3], data[pos + 4]]) as usize;
+    }
+    (&data[..pos.min(data.len())], "握手之后的数据已脱敏")
+}
+
+fn looks_like_tls(data: &[u8]) -> bool {
+    data.len() >= TLS_RECORD_HEADER_LEN
+        && (CONTENT_CHANGE_CIPHER_SPEC..=CONTENT_APPLICATION_DATA).contains(&data[0])
+        && data[1] == 0x03
+}
+
+/// 记录第一次读取到的数据的上游流包装
+pub(crate) struct PreviewStream<S> {
+    inner: S,
+    label: String,
+    max_bytes: usize,
+    logged: bool,
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for PreviewStream<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let this = &mut *self;
+        let filled_before = buf.filled().len();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if !this.logged && buf.filled().len() > filled_before {
+            this.logged = true;
+            info!(
+                "🔍 上游数据预览 {}: {}",
+                this.label,
+                format_preview(&buf.filled()[filled_before..], this.max_bytes)
+            );
+        }
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for PreviewStream<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preview_redacts_after_handshake() {
+        // 握手记录（5 字节负载）+ 应用数据记录
+        let mut data = vec![0x16, 0x03, 0x03, 0x00, 0x05, 1, 2, 3, 4, 5];
+        data.extend_from_slice(&[0x17, 0x03, 0x03, 0x00, 0x03, 0xaa, 0xbb, 0xcc]);
+
+        let preview = format_preview(&data, 64);
+        assert!(preview.starts_with("18 字节 | 16 03 03 00 05 01 02 03 04 05 |"));
+        assert!(!preview.contains("aa"));
+        assert!(preview.ends_with("省略 8 字节（握手之后的数据已脱敏）"));
+
+        let preview = format_preview(&data, 4);
+        assert!(preview.starts_with("18 字节 | 16 03 03 00 |"));
+        assert!(preview.ends_with("省略 14 字节（超出预览长度）"));
+    }
+
+    #[test]
+    fn test_preview_plaintext_first_line() {
+        let preview = format_preview(b"GET / HTTP/1.1\r\nCookie: secret\r\n\r\n", 64);
+        assert!(preview.contains("| GET / HTTP/1.1.. |"));
+        assert!(!preview.contains("secret"));
+        assert!(preview.ends_with("（非 TLS 数据只显示第一行）"));
+    }
+
+    #[test]
+    fn test_preview_matches() {
+        let preview = BodyPreview::new(vec!["*.example.com".to_string()], vec!["10.0.0.0/8".to_string()], 32);
+        assert!(preview.matches("10.1.2.3".parse().unwrap(), None));
+        assert!(preview.matches("192.0.2.1".parse().unwrap(), Some("www.example.com")));
+        assert!(!preview.matches("192.0.2.1".parse().unwrap(), Some("www.example.org")));
+        assert!(!preview.matches("192.0.2.1".parse().unwrap(), None));
+    }
+}
@@ -0,0 +1,129 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::SniProxyError;
+use crate::tuning::TcpTuning;
+
+/// 低内存模式的 TCP backlog
+pub const LOW_MEMORY_BACKLOG: i32 = 256;
+
+/// 低内存模式读取 Client Hello 的缓冲区大小（常见 Client Hello 在 2KB 以内）
+pub const LOW_MEMORY_HELLO_BUFFER_SIZE: usize = 4096;
+
+/// 低内存模式每个转发方向的缓冲区大小
+pub const LOW_MEMORY_RELAY_BUFFER_SIZE: usize = 4096;
+
+/// 低内存模式的 DNS 缓存容量
+pub const LOW_MEMORY_DNS_CACHE_CAPACITY: usize = 128;
+
+/// 低内存模式的最大并发连接数
+pub const LOW_MEMORY_MAX_CONNECTIONS: usize = 256;
+
+/// 内存配置预设
+///
+/// `LowMemory` 面向 OpenWrt 等小内存路由器：缩小 socket 缓冲区、Client Hello 与转发缓冲区、
+/// DNS 缓存容量和并发连接上限。预设之后再调用的单项设置（例如 `with_tcp_tuning`）会覆盖预设值
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MemoryProfile {
+    /// 默认配置（按 CPU 核心数自适应，面向服务器）
+    #[default]
+    Default,
+    /// 低内存配置
+    LowMemory,
+}
+
+impl MemoryProfile {
+    /// TCP 调优参数
+    ///
+    /// 低内存模式不设置 SO_RCVBUF / SO_SNDBUF，交给内核按可用内存自动调整
+    pub fn tcp_tuning(self) -> TcpTuning {
+        match self {
+            MemoryProfile::Default => TcpTuning::default(),
+            MemoryProfile::LowMemory => TcpTuning {
+                backlog: LOW_MEMORY_BACKLOG,
+                recv_buffer_size: 0,
+                send_buffer_size: 0,
+                ..TcpTuning::default()
+            },
+        }
+    }
+
+    /// Client Hello 缓冲区大小（`None` 表示按 CPU 核心数自适应）
+    pub fn hello_buffer_size(self) -> Option<usize> {
+        match self {
+            MemoryProfile::Default => None,
+            MemoryProfile::LowMemory => Some(LOW_MEMORY_HELLO_BUFFER_SIZE),
+        }
+    }
+
+    /// 每个转发方向的缓冲区大小
+    pub fn relay_buffer_size(self) -> usize {
+        match self {
+            MemoryProfile::Default => crate::proxy::DEFAULT_RELAY_BUFFER_SIZE,
+            MemoryProfile::LowMemory => LOW_MEMORY_RELAY_BUFFER_SIZE,
+        }
+    }
+
+    /// DNS 缓存容量（`None` 表示按 CPU 核心数自适应）
+    pub fn dns_cache_capacity(self) -> Option<usize> {
+        match self {
+            MemoryProfile::Default => None,
+            MemoryProfile::LowMemory => Some(LOW_MEMORY_DNS_CACHE_CAPACITY),
+        }
+    }
+
+    /// 最大并发连接数（`None` 表示按 CPU 核心数自适应）
+    pub fn max_connections(self) -> Option<usize> {
+        match self {
+            MemoryProfile::Default => None,
+            MemoryProfile::LowMemory => Some(LOW_MEMORY_MAX_CONNECTIONS),
+        }
+    }
+}
+
+impl FromStr for MemoryProfile {
+    type Err = SniProxyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "default" => Ok(MemoryProfile::Default),
+            "low_memory" | "low-memory" => Ok(MemoryProfile::LowMemory),
+            other => Err(SniProxyError::InvalidConfig(format!(
+                "无效的内存配置: {}，有效值: default, low_memory",
+                other
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for MemoryProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MemoryProfile::Default => write!(f, "default"),
+            MemoryProfile::LowMemory => write!(f, "low_memory"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_profile() {
+        assert_eq!("default".parse::<MemoryProfile>().unwrap(), MemoryProfile::Default);
+        assert_eq!("low_memory".parse::<MemoryProfile>().unwrap(), MemoryProfile::LowMemory);
+        assert_eq!("Low-Memory".parse::<MemoryProfile>().unwrap(), MemoryProfile::LowMemory);
+        assert!("tiny".parse::<MemoryProfile>().is_err());
+    }
+
+    #[test]
+    fn test_low_memory_settings() {
+        let profile = MemoryProfile::LowMemory;
+        assert_eq!(profile.tcp_tuning().recv_buffer_size, 0);
+        assert_eq!(profile.tcp_tuning().send_buffer_size, 0);
+        assert!(profile.relay_buffer_size() < MemoryProfile::Default.relay_buffer_size());
+        assert_eq!(MemoryProfile::Default.tcp_tuning(), TcpTuning::default());
+        assert_eq!(MemoryProfile::Default.max_connections(), None);
+    }
+}
@@ -1,72 +1,133 @@
-use anyhow::Result;
 use log::debug;
+use std::io::IoSlice;
 use std::net::IpAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio::net::TcpStream;
 
+use crate::error::Result;
 use crate::ip_traffic::IpTrafficTracker;
 use crate::metrics::Metrics;
+use crate::tuning::TcpTuning;
+
+#[cfg(target_os = "linux")]
+use crate::relay_timeout::RelayTimer;
+#[cfg(target_os = "linux")]
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+#[cfg(target_os = "linux")]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(target_os = "linux")]
+use std::sync::Arc;
+#[cfg(target_os = "linux")]
+use tokio::io::Interest;
+
+/// 默认每个转发方向的缓冲区大小（与 tokio `copy_bidirectional` 一致）
+pub const DEFAULT_RELAY_BUFFER_SIZE: usize = 8 * 1024;
 
 /// 优化 TCP socket 参数（流媒体专用）
 ///
-/// 为流媒体场景优化 TCP 参数：
+/// 等同于内置的 `streaming` 调优配置（见 [`TcpTuning::streaming`]）：
 /// - 更大的接收/发送缓冲区 (1MB)
 /// - TCP_NODELAY 避免 Nagle 算法延迟
 /// - TCP Fast Open 减少握手延迟
-#[allow(unused_variables)]
+#[deprecated(note = "使用 apply_tcp_tuning 和 TcpTuning::streaming() 或 TcpTuning::low_latency()，或在路由规则中选择调优配置")]
 pub fn optimize_tcp_for_streaming(stream: &TcpStream) -> Result<()> {
-    // 设置 TCP_NODELAY（禁用 Nagle 算法，减少延迟）
-    let _ = stream.set_nodelay(true);
-
-    #[cfg(unix)]
-    {
-        use std::os::unix::io::AsRawFd;
-        let fd = stream.as_raw_fd();
-
-        unsafe {
-            // 设置接收缓冲区为 1MB（流媒体需要大缓冲）
-            let rcvbuf_size: libc::c_int = 1024 * 1024; // 1MB
-            libc::setsockopt(
-                fd,
-                libc::SOL_SOCKET,
-                libc::SO_RCVBUF,
-                &rcvbuf_size as *const _ as *const libc::c_void,
-                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
-            );
+    apply_tcp_tuning(stream, &TcpTuning::streaming())
+}
 
-            // 设置发送缓冲区为 1MB
-            let sndbuf_size: libc::c_int = 1024 * 1024; // 1MB
-            libc::setsockopt(
-                fd,
-                libc::SOL_SOCKET,
-                libc::SO_SNDBUF,
-                &sndbuf_size as *const _ as *const libc::c_void,
-                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
-            );
+/// 按调优参数设置 TCP socket（见 [`TcpTuning::apply_to_stream`]）
+pub fn apply_tcp_tuning(stream: &TcpStream, tuning: &TcpTuning) -> Result<()> {
+    tuning.apply_to_stream(stream)?;
+    Ok(())
+}
 
-            // ⚡ 启用 TCP Fast Open（客户端模式）
-            // Linux 3.13+ 支持，节省 1 RTT
-            #[cfg(target_os = "linux")]
-            {
-                const TCP_FASTOPEN_CONNECT: libc::c_int = 30; // Linux 特定常量
-                let enable: libc::c_int = 1;
-                let result = libc::setsockopt(
-                    fd,
-                    libc::IPPROTO_TCP,
-                    TCP_FASTOPEN_CONNECT,
-                    &enable as *const _ as *const libc::c_void,
-                    std::mem::size_of::<libc::c_int>() as libc::socklen_t,
-                );
-
-                if result == 0 {
-                    debug!("✅ TCP Fast Open 已启用（客户端模式）");
-                } else {
-                    debug!("⚠️  TCP Fast Open 启用失败（可能系统不支持）");
-                }
+/// 带前缀数据的流
+///
+/// 读取时先返回已读取的前缀数据（例如 Client Hello），再从内部流读取；写入直接透传。
+/// 用于把 Client Hello 预先放入转发循环的缓冲区，省去转发前单独的一次 `write_all`：
+/// 返回前缀时会顺带非阻塞地读取内部流中已到达的数据，合并成一次写入发送给目标
+pub struct PrefixedStream<S> {
+    prefix: Vec<u8>,
+    offset: usize,
+    inner: S,
+}
+
+impl<S> PrefixedStream<S> {
+    /// 创建带前缀数据的流
+    pub fn new(prefix: Vec<u8>, inner: S) -> Self {
+        Self {
+            prefix,
+            offset: 0,
+            inner,
+        }
+    }
+
+    /// 获取内部流的引用
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// 取出内部流（未读完的前缀数据会被丢弃）
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for PrefixedStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = &mut *self;
+        if this.offset >= this.prefix.len() {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        }
+
+        let remaining = &this.prefix[this.offset..];
+        let n = remaining.len().min(buf.remaining());
+        buf.put_slice(&remaining[..n]);
+        this.offset += n;
+
+        if this.offset >= this.prefix.len() {
+            // 前缀已全部返回：释放内存，并尝试合并内部流中已到达的数据
+            this.prefix = Vec::new();
+            this.offset = 0;
+            if buf.remaining() > 0 {
+                // Pending / 错误 / EOF 都留给下一次读取处理，本次先返回前缀数据
+                let _ = Pin::new(&mut this.inner).poll_read(cx, buf);
             }
         }
+
+        Poll::Ready(Ok(()))
     }
+}
 
-    Ok(())
+impl<S: AsyncWrite + Unpin> AsyncWrite for PrefixedStream<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
 }
 
 /// 双向代理数据传输（流媒体优化版本）
@@ -76,17 +137,62 @@ pub fn optimize_tcp_for_streaming(stream: &TcpStream) -> Result<()> {
 /// 1. 使用 tokio::io::copy_bidirectional（内核级零拷贝）
 /// 2. 批量更新统计数据，减少原子操作开销
 /// 3. 避免手动缓冲区管理
-pub async fn proxy_data(
-    mut client_stream: TcpStream,
-    mut target_stream: TcpStream,
+///
+/// 客户端流可以是 [`PrefixedStream`]，此时前缀数据（Client Hello）随转发一起发送并计入上传流量
+///
+/// 返回（上传, 下载）字节数，转发出错时为 0（与统计一致）
+pub async fn proxy_data<C, T>(
+    client_stream: C,
+    target_stream: T,
+    metrics: Metrics,
+    client_ip: IpAddr,
+    ip_traffic_tracker: IpTrafficTracker,
+) -> Result<(u64, u64)>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    proxy_data_with_buffer_size(
+        client_stream,
+        target_stream,
+        DEFAULT_RELAY_BUFFER_SIZE,
+        metrics,
+        client_ip,
+        ip_traffic_tracker,
+    )
+    .await
+}
+
+/// 双向代理数据传输（指定每个方向的缓冲区大小）
+///
+/// 每个连接占用 `2 * buffer_size` 字节的转发缓冲区，低内存设备可以调小
+pub async fn proxy_data_with_buffer_size<C, T>(
+    mut client_stream: C,
+    mut target_stream: T,
+    buffer_size: usize,
     metrics: Metrics,
     client_ip: IpAddr,
     ip_traffic_tracker: IpTrafficTracker,
-) -> Result<()> {
-    // 使用 tokio 的零拷贝双向传输（性能最优）
-    match tokio::io::copy_bidirectional(&mut client_stream, &mut target_stream).await {
+) -> Result<(u64, u64)>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let buffer_size = buffer_size.max(1);
+    let result =
+        tokio::io::copy_bidirectional_with_sizes(&mut client_stream, &mut target_stream, buffer_size, buffer_size).await;
+    Ok(record_transfer(result, &metrics, client_ip, &ip_traffic_tracker))
+}
+
+/// 转发结束时批量更新统计（只在连接结束时更新一次），转发出错时返回 0
+pub(crate) fn record_transfer(
+    result: std::io::Result<(u64, u64)>,
+    metrics: &Metrics,
+    client_ip: IpAddr,
+    ip_traffic_tracker: &IpTrafficTracker,
+) -> (u64, u64) {
+    match result {
         Ok((client_to_target, target_to_client)) => {
-            // 批量更新统计（只在连接结束时更新一次）
             metrics.add_bytes_received(client_to_target);
             metrics.add_bytes_sent(target_to_client);
 
@@ -98,11 +204,299 @@ pub async fn proxy_data(
                 "数据传输完成: 上传 {} bytes, 下载 {} bytes",
                 client_to_target, target_to_client
             );
+            (client_to_target, target_to_client)
         }
         Err(e) => {
             debug!("数据传输结束: {}", e);
+            (0, 0)
+        }
+    }
+}
+
+/// splice 每次最多移动的字节数（默认管道容量）
+#[cfg(target_os = "linux")]
+const SPLICE_CHUNK: usize = 64 * 1024;
+
+/// 基于 splice(2) 的双向转发（仅 Linux）
+///
+/// 每个方向一个管道，数据在内核中从一个 socket 经管道移动到另一个 socket，不复制到用户态缓冲区，
+/// 多 Gbps 的流媒体连接上 CPU 占用更低。只能用于两端都是 TCP socket、不需要在用户态处理数据
+/// （限速、正文预览）的连接，其余连接使用 [`proxy_data_with_buffer_size`]
+#[cfg(target_os = "linux")]
+pub(crate) struct SpliceRelay {
+    upload: Pipe,
+    download: Pipe,
+    timer: Option<Arc<RelayTimer>>,
+    /// 检查上游第一次返回的数据（SNI 和统计）
+    tls_check: Option<(String, Metrics)>,
+}
+
+#[cfg(target_os = "linux")]
+impl SpliceRelay {
+    /// 创建两个方向的管道，失败时（例如文件描述符用完）调用方改用普通转发
+    pub(crate) fn new() -> std::io::Result<Self> {
+        Ok(Self { upload: Pipe::new()?, download: Pipe::new()?, timer: None, tls_check: None })
+    }
+
+    /// 转发计时器：有数据时刷新活动时间，超时后结束转发
+    pub(crate) fn with_timer(mut self, timer: Option<Arc<RelayTimer>>) -> Self {
+        self.timer = timer;
+        self
+    }
+
+    /// 检查上游第一次返回的数据是否为 TLS（见 [`crate::upstream_tls`]）
+    pub(crate) fn with_tls_check(mut self, sni: &str, metrics: Metrics) -> Self {
+        self.tls_check = Some((sni.to_string(), metrics));
+        self
+    }
+
+    /// 先把 `prefix`（Client Hello）发送给目标，再双向转发直到两个方向都结束或计时器超时，返回（上传, 下载）字节数
+    pub(crate) async fn relay(
+        &self,
+        prefix: &[u8],
+        client: &TcpStream,
+        target: &TcpStream,
+    ) -> std::io::Result<(u64, u64)> {
+        let uploaded = AtomicU64::new(0);
+        let downloaded = AtomicU64::new(0);
+        let timer = self.timer.as_deref();
+        let transfer = async {
+            write_all(target, prefix).await?;
+            uploaded.fetch_add(prefix.len() as u64, Ordering::Relaxed);
+            let tls_check = self.tls_check.as_ref().map(|(sni, metrics)| (sni.as_str(), metrics));
+            tokio::try_join!(
+                splice_one_way(client, target, &self.upload, &uploaded, timer, None),
+                splice_one_way(target, client, &self.download, &downloaded, timer, tls_check),
+            )
+        };
+        match timer {
+            Some(timer) => tokio::select! {
+                result = transfer => { result?; }
+                _ = timer.wait_expiry() => {}
+            },
+            None => {
+                transfer.await?;
+            }
         }
+        Ok((uploaded.into_inner(), downloaded.into_inner()))
     }
+}
+
+/// 双向代理数据传输（splice 版本，仅 Linux），统计与 [`proxy_data_with_buffer_size`] 一致
+#[cfg(target_os = "linux")]
+pub(crate) async fn splice_data(
+    relay: SpliceRelay,
+    prefix: &[u8],
+    client_stream: &TcpStream,
+    target_stream: &TcpStream,
+    metrics: Metrics,
+    client_ip: IpAddr,
+    ip_traffic_tracker: IpTrafficTracker,
+) -> Result<(u64, u64)> {
+    let result = relay.relay(prefix, client_stream, target_stream).await;
+    Ok(record_transfer(result, &metrics, client_ip, &ip_traffic_tracker))
+}
+
+/// 非阻塞管道（关闭时丢弃管道中残留的数据）
+#[cfg(target_os = "linux")]
+struct Pipe {
+    read: OwnedFd,
+    write: OwnedFd,
+}
 
+#[cfg(target_os = "linux")]
+impl Pipe {
+    fn new() -> std::io::Result<Self> {
+        let mut fds = [0 as libc::c_int; 2];
+        // SAFETY: fds 有两个元素，供 pipe2 写入读端和写端
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        // SAFETY: pipe2 成功时返回两个新打开的文件描述符，所有权交给 OwnedFd
+        Ok(unsafe { Self { read: OwnedFd::from_raw_fd(fds[0]), write: OwnedFd::from_raw_fd(fds[1]) } })
+    }
+}
+
+/// 在两个文件描述符之间移动最多 `len` 字节（其中一个必须是管道），返回 0 表示来源已关闭
+#[cfg(target_os = "linux")]
+fn splice(from: RawFd, to: RawFd, len: usize) -> std::io::Result<usize> {
+    // SAFETY: 两个 fd 在调用期间有效；socket 和管道不使用偏移量
+    let n = unsafe {
+        libc::splice(
+            from,
+            std::ptr::null_mut(),
+            to,
+            std::ptr::null_mut(),
+            len,
+            libc::SPLICE_F_MOVE | libc::SPLICE_F_NONBLOCK,
+        )
+    };
+    if n < 0 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(n as usize)
+    }
+}
+
+/// 单个方向的 splice 转发：来源 → 管道 → 目标，来源关闭后关闭目标的写方向（与 `copy_bidirectional` 一致）
+#[cfg(target_os = "linux")]
+async fn splice_one_way(
+    src: &TcpStream,
+    dst: &TcpStream,
+    pipe: &Pipe,
+    transferred: &AtomicU64,
+    timer: Option<&RelayTimer>,
+    tls_check: Option<(&str, &Metrics)>,
+) -> std::io::Result<()> {
+    use std::io::ErrorKind;
+
+    if let Some((sni, metrics)) = tls_check {
+        // 上游没有返回数据就关闭时不计数（与 UpstreamTlsCheck 一致）
+        let mut peeked = [0u8; 512];
+        let n = src.peek(&mut peeked).await?;
+        if n > 0 {
+            crate::upstream_tls::check_reply(sni, metrics, &peeked[..n]);
+        }
+    }
+    loop {
+        src.readable().await?;
+        let n = match src.try_io(Interest::READABLE, || splice(src.as_raw_fd(), pipe.write.as_raw_fd(), SPLICE_CHUNK)) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => continue,
+            Err(e) => return Err(e),
+        };
+        // 管道中的数据全部写出后再读取下一批
+        let mut pending = n;
+        while pending > 0 {
+            dst.writable().await?;
+            match dst.try_io(Interest::WRITABLE, || splice(pipe.read.as_raw_fd(), dst.as_raw_fd(), pending)) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(written) => pending -= written,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+        transferred.fetch_add(n as u64, Ordering::Relaxed);
+        if let Some(timer) = timer {
+            timer.touch();
+        }
+    }
+    match socket2::SockRef::from(dst).shutdown(std::net::Shutdown::Write) {
+        Err(e) if e.kind() != ErrorKind::NotConnected => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// 把数据全部写入 socket（只需要共享引用）
+#[cfg(target_os = "linux")]
+async fn write_all(stream: &TcpStream, mut data: &[u8]) -> std::io::Result<()> {
+    while !data.is_empty() {
+        stream.writable().await?;
+        match stream.try_write(data) {
+            Ok(0) => return Err(std::io::ErrorKind::WriteZero.into()),
+            Ok(n) => data = &data[n..],
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_prefixed_stream_reads_prefix_first() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        client.write_all(b" world").await.unwrap();
+        drop(client);
+
+        let mut stream = PrefixedStream::new(b"hello".to_vec(), server);
+        let mut data = Vec::new();
+        stream.read_to_end(&mut data).await.unwrap();
+        assert_eq!(data, b"hello world");
+    }
+
+    #[tokio::test]
+    async fn test_prefixed_stream_coalesces_available_data() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        client.write_all(b" world").await.unwrap();
+
+        // 前缀和内部流中已到达的数据在一次读取中返回
+        let mut stream = PrefixedStream::new(b"hello".to_vec(), server);
+        let mut buf = [0u8; 64];
+        let n = stream.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"hello world");
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn tcp_pair() -> (TcpStream, TcpStream) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let connecting = TcpStream::connect(listener.local_addr().unwrap());
+        let (connected, accepted) = tokio::join!(connecting, listener.accept());
+        (connected.unwrap(), accepted.unwrap().0)
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_splice_relay() {
+        let (mut client_app, client) = tcp_pair().await;
+        let (target, mut server_app) = tcp_pair().await;
+        let metrics = Metrics::new();
+        let relay = SpliceRelay::new().unwrap().with_tls_check("example.com", metrics.clone());
+        let relaying = tokio::spawn(async move { relay.relay(b"hello", &client, &target).await });
+
+        // 超过管道容量的数据分多次移动
+        let body = vec![0x5a; 4 * SPLICE_CHUNK + 1];
+        let sending = body.clone();
+        let writer = tokio::spawn(async move {
+            client_app.write_all(&sending).await.unwrap();
+            client_app.shutdown().await.unwrap();
+            client_app
+        });
+        let mut received = Vec::new();
+        server_app.read_to_end(&mut received).await.unwrap();
+        assert_eq!(&received[..5], b"hello");
+        assert!(received[5..] == body[..]);
+        let mut client_app = writer.await.unwrap();
+
+        // 上游返回的不是 TLS
+        server_app.write_all(b"HTTP/1.1 400 Bad Request\r\n").await.unwrap();
+        server_app.shutdown().await.unwrap();
+        let mut reply = Vec::new();
+        client_app.read_to_end(&mut reply).await.unwrap();
+        assert_eq!(reply, b"HTTP/1.1 400 Bad Request\r\n");
+        assert_eq!(relaying.await.unwrap().unwrap(), (5 + body.len() as u64, 26));
+        assert_eq!(metrics.snapshot().upstream_not_tls, 1);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_splice_relay_idle_timeout() {
+        use crate::relay_timeout::RelayExpiry;
+
+        let (_client_app, client) = tcp_pair().await;
+        let (target, _server_app) = tcp_pair().await;
+        let timer = RelayTimer::new(Some(std::time::Duration::from_millis(50)), None);
+        let relay = SpliceRelay::new().unwrap().with_timer(timer.clone());
+        assert_eq!(relay.relay(b"", &client, &target).await.unwrap(), (0, 0));
+        assert_eq!(timer.unwrap().expired(), Some(RelayExpiry::Idle));
+    }
+
+    #[tokio::test]
+    async fn test_prefixed_stream_small_buffer() {
+        let (client, server) = tokio::io::duplex(1024);
+        drop(client);
+
+        let mut stream = PrefixedStream::new(b"hello".to_vec(), server);
+        let mut buf = [0u8; 3];
+        assert_eq!(stream.read(&mut buf).await.unwrap(), 3);
+        assert_eq!(&buf, b"hel");
+        assert_eq!(stream.read(&mut buf).await.unwrap(), 2);
+        assert_eq!(&buf[..2], b"lo");
+        assert_eq!(stream.read(&mut buf).await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,83 @@
+use std::net::{IpAddr, SocketAddr};
+
+/// PROXY protocol v2 签名
+const V2_SIGNATURE: [u8; 12] = [0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A];
+
+/// 版本 2 + PROXY 命令
+const V2_VERSION_PROXY: u8 = 0x21;
+
+/// TCP over IPv4
+const V2_TCP4: u8 = 0x11;
+
+/// TCP over IPv6
+const V2_TCP6: u8 = 0x21;
+
+/// 生成 PROXY protocol v2 头部
+///
+/// `source` 为客户端地址，`destination` 为客户端连接的目标地址。
+/// 两者地址族不同时统一使用 IPv6 格式（IPv4 转换为 `::ffff:a.b.c.d`）
+pub fn encode_v2_header(source: SocketAddr, destination: SocketAddr) -> Vec<u8> {
+    let (src_ip, dst_ip) = (source.ip().to_canonical(), destination.ip().to_canonical());
+
+    let mut header = Vec::with_capacity(16 + 36);
+    header.extend_from_slice(&V2_SIGNATURE);
+    header.push(V2_VERSION_PROXY);
+
+    match (src_ip, dst_ip) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            header.push(V2_TCP4);
+            header.extend_from_slice(&12u16.to_be_bytes());
+            header.extend_from_slice(&src.octets());
+            header.extend_from_slice(&dst.octets());
+        }
+        (src, dst) => {
+            header.push(V2_TCP6);
+            header.extend_from_slice(&36u16.to_be_bytes());
+            header.extend_from_slice(&to_ipv6(src).octets());
+            header.extend_from_slice(&to_ipv6(dst).octets());
+        }
+    }
+
+    header.extend_from_slice(&source.port().to_be_bytes());
+    header.extend_from_slice(&destination.port().to_be_bytes());
+    header
+}
+
+fn to_ipv6(ip: IpAddr) -> std::net::Ipv6Addr {
+    match ip {
+        IpAddr::V4(v4) => v4.to_ipv6_mapped(),
+        IpAddr::V6(v6) => v6,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_v2_ipv4() {
+        let header = encode_v2_header(
+            "192.168.1.10:51000".parse().unwrap(),
+            "10.0.0.1:443".parse().unwrap(),
+        );
+        assert_eq!(header.len(), 28);
+        assert_eq!(&header[..12], &V2_SIGNATURE);
+        assert_eq!(&header[12..16], &[0x21, 0x11, 0x00, 0x0C]);
+        assert_eq!(&header[16..20], &[192, 168, 1, 10]);
+        assert_eq!(&header[20..24], &[10, 0, 0, 1]);
+        assert_eq!(&header[24..26], &51000u16.to_be_bytes());
+        assert_eq!(&header[26..28], &443u16.to_be_bytes());
+    }
+
+    #[test]
+    fn test_encode_v2_mixed_families() {
+        // 双栈监听时的 IPv4 映射地址按 IPv4 处理
+        let header = encode_v2_header("[::ffff:192.168.1.10]:1".parse().unwrap(), "10.0.0.1:443".parse().unwrap());
+        assert_eq!(header[13], V2_TCP4);
+
+        let header = encode_v2_header("192.168.1.10:1".parse().unwrap(), "[2001:db8::1]:443".parse().unwrap());
+        assert_eq!(header.len(), 16 + 36);
+        assert_eq!(&header[12..16], &[0x21, 0x21, 0x00, 0x24]);
+        assert_eq!(&header[16..32], &"::ffff:192.168.1.10".parse::<std::net::Ipv6Addr>().unwrap().octets());
+    }
+}
@@ -0,0 +1,465 @@
+//! QUIC v1 Initial 包解析
+//!
+//! 客户端 Initial 包使用由目标连接 ID（DCID）派生的公开密钥加密（RFC 9001 第 5.2 节），
+//! 代理可以解密其中的 CRYPTO 帧，重组 Client Hello 并解析 SNI，再按白名单决定是否转发
+
+use aes::cipher::{BlockEncrypt, KeyInit, KeyIvInit, StreamCipher};
+use aes::Aes128;
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::collections::BTreeMap;
+
+use crate::tls::parse_sni;
+
+/// QUIC v1 版本号
+pub const QUIC_VERSION_1: u32 = 0x0000_0001;
+
+/// QUIC v1 Initial 密钥派生使用的盐（RFC 9001 第 5.2 节）
+const INITIAL_SALT_V1: [u8; 20] = [
+    0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3, 0x4d, 0x17, 0x9a, 0xe6, 0xa4, 0xc8, 0x0c, 0xad, 0xcc, 0xbb,
+    0x7f, 0x0a,
+];
+
+/// AEAD 认证标签长度
+const TAG_LEN: usize = 16;
+
+/// 重组 Client Hello 时最多缓存的 CRYPTO 数据量
+const MAX_CRYPTO_DATA: usize = 16 * 1024;
+
+/// Client Hello 解析结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QuicSniffResult {
+    /// 已解析出 SNI
+    Sni(String),
+    /// Client Hello 尚不完整，需要后续的 Initial 包
+    NeedMore,
+    /// 不是 QUIC v1 客户端 Initial 包，或 Client Hello 无效 / 没有 SNI
+    Invalid,
+}
+
+/// 从 QUIC Initial 包中重组 Client Hello 并解析 SNI
+///
+/// 较大的 Client Hello（例如携带后量子密钥交换参数）会分布在多个 Initial 包中，
+/// 每个包可能包含多个乱序的 CRYPTO 帧，因此按偏移量缓存直到 Client Hello 完整
+#[derive(Debug, Default)]
+pub struct QuicSniffer {
+    crypto: BTreeMap<u64, Vec<u8>>,
+    buffered: usize,
+}
+
+impl QuicSniffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 处理一个客户端数据报
+    pub fn feed(&mut self, datagram: &[u8]) -> QuicSniffResult {
+        let Some(frames) = decrypt_initial_crypto_frames(datagram) else {
+            return QuicSniffResult::Invalid;
+        };
+
+        for (offset, data) in frames {
+            self.buffered += data.len();
+            if self.buffered > MAX_CRYPTO_DATA {
+                return QuicSniffResult::Invalid;
+            }
+            let entry = self.crypto.entry(offset).or_default();
+            if data.len() > entry.len() {
+                *entry = data;
+            }
+        }
+
+        let handshake = self.contiguous();
+        if handshake.len() < 4 {
+            return QuicSniffResult::NeedMore;
+        }
+        // 握手消息类型必须是 Client Hello
+        if handshake[0] != 0x01 {
+            return QuicSniffResult::Invalid;
+        }
+        let len = u32::from_be_bytes([0, handshake[1], handshake[2], handshake[3]]) as usize;
+        if 4 + len > MAX_CRYPTO_DATA {
+            return QuicSniffResult::Invalid;
+        }
+        if handshake.len() < 4 + len {
+            return QuicSniffResult::NeedMore;
+        }
+
+        // 补上 TLS 记录头，复用 TCP 路径的 SNI 解析
+        let mut record = Vec::with_capacity(5 + 4 + len);
+        record.extend_from_slice(&[0x16, 0x03, 0x01]);
+        record.extend_from_slice(&((4 + len) as u16).to_be_bytes());
+        record.extend_from_slice(&handshake[..4 + len]);
+        match parse_sni(&record) {
+            Some(sni) => QuicSniffResult::Sni(sni),
+            None => QuicSniffResult::Invalid,
+        }
+    }
+
+    /// 从偏移 0 开始的连续 CRYPTO 数据
+    fn contiguous(&self) -> Vec<u8> {
+        let mut out: Vec<u8> = Vec::new();
+        for (&offset, data) in &self.crypto {
+            let offset = offset as usize;
+            if offset > out.len() {
+                break;
+            }
+            let end = offset + data.len();
+            if end > out.len() {
+                out.extend_from_slice(&data[out.len() - offset..]);
+            }
+        }
+        out
+    }
+}
+
+/// 解析单个数据报中的 SNI（Client Hello 需要完整包含在该数据报中）
+pub fn parse_quic_sni(datagram: &[u8]) -> Option<String> {
+    match QuicSniffer::new().feed(datagram) {
+        QuicSniffResult::Sni(sni) => Some(sni),
+        _ => None,
+    }
+}
+
+/// 是否为 QUIC v1 长头部 Initial 包（不解密，用于快速过滤）
+pub fn is_quic_initial(datagram: &[u8]) -> bool {
+    datagram.len() >= 7
+        && datagram[0] & 0xf0 == 0xc0
+        && u32::from_be_bytes([datagram[1], datagram[2], datagram[3], datagram[4]]) == QUIC_VERSION_1
+}
+
+/// AES-128-GCM 的计数器模式部分（32 位大端计数器）
+type Aes128Ctr = ctr::Ctr32BE<Aes128>;
+
+/// TLS 1.3 的 HKDF-Expand-Label（RFC 8446 第 7.1 节），上下文为空
+fn hkdf_expand_label<const N: usize>(secret: &[u8], label: &str) -> [u8; N] {
+    let full_label = [b"tls13 ", label.as_bytes()].concat();
+    let mut info = Vec::with_capacity(4 + full_label.len());
+    info.extend_from_slice(&(N as u16).to_be_bytes());
+    info.push(full_label.len() as u8);
+    info.extend_from_slice(&full_label);
+    info.push(0);
+    let mut out = [0u8; N];
+    Hkdf::<Sha256>::from_prk(secret)
+        .expect("密钥长度不小于 SHA-256 输出长度")
+        .expand(&info, &mut out)
+        .expect("输出长度远小于 HKDF 上限");
+    out
+}
+
+/// 客户端 Initial 密钥的派生密钥（RFC 9001 第 5.2 节）
+fn client_initial_secret(dcid: &[u8]) -> [u8; 32] {
+    let (initial_secret, _) = Hkdf::<Sha256>::extract(Some(&INITIAL_SALT_V1), dcid);
+    hkdf_expand_label(&initial_secret, "client in")
+}
+
+/// 客户端 Initial 密钥
+struct InitialKeys {
+    key: [u8; 16],
+    iv: [u8; 12],
+    header_protection: Aes128,
+}
+
+impl InitialKeys {
+    fn client(dcid: &[u8]) -> Self {
+        let client_secret = client_initial_secret(dcid);
+        let hp: [u8; 16] = hkdf_expand_label(&client_secret, "quic hp");
+        Self {
+            key: hkdf_expand_label(&client_secret, "quic key"),
+            iv: hkdf_expand_label(&client_secret, "quic iv"),
+            header_protection: Aes128::new(&hp.into()),
+        }
+    }
+
+    /// 头部保护掩码（AES-ECB 加密采样）
+    fn header_mask(&self, sample: &[u8; 16]) -> [u8; 16] {
+        let mut block = (*sample).into();
+        self.header_protection.encrypt_block(&mut block);
+        block.into()
+    }
+
+    /// 按包号解密（或加密）负载，不计算认证标签；计数器从 `nonce || 0x00000002` 开始（`0x00000001` 用于标签）
+    fn apply_keystream(&self, packet_number: u64, data: &mut [u8]) {
+        let mut counter_block = [0u8; 16];
+        counter_block[..12].copy_from_slice(&self.iv);
+        for (n, p) in counter_block[4..12].iter_mut().zip(packet_number.to_be_bytes()) {
+            *n ^= p;
+        }
+        counter_block[15] = 2;
+        Aes128Ctr::new(&self.key.into(), &counter_block.into()).apply_keystream(data);
+    }
+}
+
+/// 简单的字节读取器
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        let b = *self.data.get(self.pos)?;
+        self.pos += 1;
+        Some(b)
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(len)?;
+        let out = self.data.get(self.pos..end)?;
+        self.pos = end;
+        Some(out)
+    }
+
+    /// QUIC 变长整数（RFC 9000 第 16 节）
+    fn varint(&mut self) -> Option<u64> {
+        let first = self.u8()?;
+        let len = 1usize << (first >> 6);
+        let mut value = (first & 0x3f) as u64;
+        for &b in self.bytes(len - 1)? {
+            value = (value << 8) | b as u64;
+        }
+        Some(value)
+    }
+}
+
+/// 解密数据报中的客户端 Initial 包，返回其中全部 CRYPTO 帧（偏移量, 数据）
+///
+/// 数据报中可能合并了多个 QUIC 包，遇到非 Initial 包时停止
+fn decrypt_initial_crypto_frames(datagram: &[u8]) -> Option<Vec<(u64, Vec<u8>)>> {
+    let mut frames = Vec::new();
+    let mut rest = datagram;
+
+    while is_quic_initial(rest) {
+        let (packet_len, payload) = decrypt_initial_packet(rest)?;
+        frames.extend(parse_crypto_frames(&payload)?);
+        rest = &rest[packet_len..];
+    }
+
+    if frames.is_empty() {
+        None
+    } else {
+        Some(frames)
+    }
+}
+
+/// 解密一个 Initial 包，返回（包长度, 明文负载）
+fn decrypt_initial_packet(packet: &[u8]) -> Option<(usize, Vec<u8>)> {
+    let mut reader = Reader::new(packet);
+    reader.bytes(5)?; // 首字节 + 版本号
+    let dcid_len = reader.u8()? as usize;
+    if dcid_len > 20 {
+        return None;
+    }
+    let dcid = reader.bytes(dcid_len)?;
+    let scid_len = reader.u8()? as usize;
+    reader.bytes(scid_len)?;
+    let token_len = reader.varint()? as usize;
+    reader.bytes(token_len)?;
+    let length = reader.varint()? as usize;
+    let pn_offset = reader.pos;
+    let packet_len = pn_offset.checked_add(length)?;
+    if packet_len > packet.len() {
+        return None;
+    }
+
+    // 去除头部保护：采样从包号起始位置之后 4 字节开始
+    let keys = InitialKeys::client(dcid);
+    let sample: [u8; 16] = packet.get(pn_offset + 4..pn_offset + 20)?.try_into().ok()?;
+    let mask = keys.header_mask(&sample);
+    let first = packet[0] ^ (mask[0] & 0x0f);
+    let pn_len = (first & 0x03) as usize + 1;
+    if length < pn_len + TAG_LEN {
+        return None;
+    }
+
+    let mut packet_number = 0u64;
+    for i in 0..pn_len {
+        packet_number = (packet_number << 8) | (packet[pn_offset + i] ^ mask[1 + i]) as u64;
+    }
+
+    // 认证标签不校验（目标服务器会校验），只解密负载
+    let mut payload = packet[pn_offset + pn_len..packet_len - TAG_LEN].to_vec();
+    keys.apply_keystream(packet_number, &mut payload);
+    Some((packet_len, payload))
+}
+
+/// 解析 Initial 包负载中的帧，提取 CRYPTO 帧
+///
+/// 客户端 Initial 包只会包含 PADDING、PING、ACK、CRYPTO 和 CONNECTION_CLOSE 帧
+fn parse_crypto_frames(payload: &[u8]) -> Option<Vec<(u64, Vec<u8>)>> {
+    let mut frames = Vec::new();
+    let mut reader = Reader::new(payload);
+
+    while !reader.is_empty() {
+        match reader.varint()? {
+            // PADDING / PING
+            0x00 | 0x01 => {}
+            // ACK / ACK_ECN
+            frame_type @ (0x02 | 0x03) => {
+                reader.varint()?; // Largest Acknowledged
+                reader.varint()?; // ACK Delay
+                let range_count = reader.varint()?;
+                reader.varint()?; // First ACK Range
+                for _ in 0..range_count {
+                    reader.varint()?; // Gap
+                    reader.varint()?; // ACK Range Length
+                }
+                if frame_type == 0x03 {
+                    for _ in 0..3 {
+                        reader.varint()?; // ECN Counts
+                    }
+                }
+            }
+            // CRYPTO
+            0x06 => {
+                let offset = reader.varint()?;
+                let len = reader.varint()? as usize;
+                frames.push((offset, reader.bytes(len)?.to_vec()));
+            }
+            // CONNECTION_CLOSE
+            0x1c => {
+                reader.varint()?; // Error Code
+                reader.varint()?; // Frame Type
+                let reason_len = reader.varint()? as usize;
+                reader.bytes(reason_len)?;
+            }
+            _ => return None,
+        }
+    }
+
+    Some(frames)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::tls::tests::client_hello;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// 按 RFC 9001 构造客户端 Initial 包（认证标签用零填充，解析时不校验）
+    fn build_initial(dcid: &[u8], packet_number: u32, frames: &[u8]) -> Vec<u8> {
+        let keys = InitialKeys::client(dcid);
+        let pn_len = 4;
+
+        // 负载填充到至少 1162 字节，使整个数据报达到 1200 字节
+        let mut payload = frames.to_vec();
+        payload.resize(payload.len().max(1162), 0);
+
+        let mut packet = vec![0xc0 | (pn_len as u8 - 1)];
+        packet.extend_from_slice(&QUIC_VERSION_1.to_be_bytes());
+        packet.push(dcid.len() as u8);
+        packet.extend_from_slice(dcid);
+        packet.push(0); // SCID 长度
+        packet.push(0); // Token 长度
+        let length = (pn_len + payload.len() + TAG_LEN) as u16;
+        packet.extend_from_slice(&(0x4000 | length).to_be_bytes());
+        let pn_offset = packet.len();
+        packet.extend_from_slice(&packet_number.to_be_bytes());
+
+        keys.apply_keystream(packet_number as u64, &mut payload);
+        packet.extend_from_slice(&payload);
+        packet.extend_from_slice(&[0u8; TAG_LEN]);
+
+        let sample: [u8; 16] = packet[pn_offset + 4..pn_offset + 20].try_into().unwrap();
+        let mask = keys.header_mask(&sample);
+        packet[0] ^= mask[0] & 0x0f;
+        for i in 0..pn_len {
+            packet[pn_offset + i] ^= mask[1 + i];
+        }
+        packet
+    }
+
+    /// CRYPTO 帧（偏移量和长度使用 2 字节变长整数）
+    fn crypto_frame(offset: usize, data: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0x06];
+        frame.extend_from_slice(&(0x4000 | offset as u16).to_be_bytes());
+        frame.extend_from_slice(&(0x4000 | data.len() as u16).to_be_bytes());
+        frame.extend_from_slice(data);
+        frame
+    }
+
+    /// Client Hello 握手消息（去掉 TLS 记录头）
+    fn handshake(sni: &str) -> Vec<u8> {
+        client_hello(sni)[5..].to_vec()
+    }
+
+    /// 包含完整 Client Hello 的客户端 Initial 数据报
+    pub(crate) fn build_client_initial(sni: &str) -> Vec<u8> {
+        build_initial(&hex("8394c8f03e515708"), 0, &crypto_frame(0, &handshake(sni)))
+    }
+
+    #[test]
+    fn test_initial_keys_rfc9001() {
+        // RFC 9001 附录 A.1
+        let dcid = hex("8394c8f03e515708");
+        let client_secret = client_initial_secret(&dcid);
+        assert_eq!(
+            client_secret.to_vec(),
+            hex("c00cf151ca5be075ed0ebfb5c80323c42d6b7db67881289af4008f1f6c357aea")
+        );
+        let keys = InitialKeys::client(&dcid);
+        assert_eq!(keys.key.to_vec(), hex("1f369613dd76d5467730efcbe3b1a22d"));
+        assert_eq!(keys.iv.to_vec(), hex("fa044b2f42a3fd3b46fb255c"));
+        assert_eq!(hkdf_expand_label::<16>(&client_secret, "quic hp").to_vec(), hex("9f50449e04a0e810283a1e9933adedd2"));
+
+        // RFC 9001 附录 A.2：头部保护掩码
+        let sample: [u8; 16] = hex("d1b1c98dd7689fb8ec11d242b123dc9b").try_into().unwrap();
+        assert_eq!(keys.header_mask(&sample)[..5].to_vec(), hex("437b9aec36"));
+    }
+
+    #[test]
+    fn test_parse_quic_sni() {
+        let dcid = hex("8394c8f03e515708");
+        let mut frames = vec![0x01]; // PING
+        frames.extend(crypto_frame(0, &handshake("example.com")));
+        let packet = build_initial(&dcid, 2, &frames);
+        assert_eq!(packet.len(), 1200);
+        assert_eq!(parse_quic_sni(&packet), Some("example.com".to_string()));
+    }
+
+    #[test]
+    fn test_client_hello_across_packets() {
+        let dcid = hex("0011223344556677");
+        let hello = handshake("www.example.com");
+        let (head, tail) = hello.split_at(hello.len() / 2);
+        let (head_a, head_b) = head.split_at(10);
+
+        let mut sniffer = QuicSniffer::new();
+        // 第一个包：乱序的两个 CRYPTO 帧，只覆盖前半部分
+        let mut frames = crypto_frame(head_a.len(), head_b);
+        frames.extend(crypto_frame(0, head_a));
+        assert_eq!(sniffer.feed(&build_initial(&dcid, 0, &frames)), QuicSniffResult::NeedMore);
+
+        // 第二个包补齐剩余部分
+        let frames = crypto_frame(head.len(), tail);
+        assert_eq!(
+            sniffer.feed(&build_initial(&dcid, 1, &frames)),
+            QuicSniffResult::Sni("www.example.com".to_string())
+        );
+    }
+
+    #[test]
+    fn test_not_quic_initial() {
+        assert!(!is_quic_initial(b"hello"));
+        assert_eq!(QuicSniffer::new().feed(&[0u8; 1200]), QuicSniffResult::Invalid);
+
+        // 版本号不是 v1
+        let mut packet = build_initial(&hex("0011223344556677"), 0, &crypto_frame(0, &handshake("a.com")));
+        packet[4] = 2;
+        assert_eq!(parse_quic_sni(&packet), None);
+    }
+}
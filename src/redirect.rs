@@ -0,0 +1,182 @@
+use log::{debug, info, warn};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::Semaphore;
+
+use crate::domain::DomainMatcher;
+use crate::error::{Result, SniProxyError};
+use crate::http::{parse_http_host, parse_request_target, read_request_head, RequestHead};
+use crate::route_table::SharedRouteTable;
+
+/// 同时处理的重定向连接数上限，超过时新连接直接关闭
+const MAX_REDIRECT_CONNECTIONS: usize = 1024;
+
+/// 读取请求头超时
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 重定向监听需要的白名单
+#[derive(Clone)]
+pub struct RedirectWhitelist {
+    /// 路由表（包括直连白名单和 SOCKS5 白名单）
+    pub routes: SharedRouteTable,
+    /// 域名黑名单（可选），匹配的域名不重定向
+    pub blacklist: Option<Arc<DomainMatcher>>,
+}
+
+impl RedirectWhitelist {
+    /// 域名是否可能被路由表允许（只按域名判断，HTTPS 连接建立时再按完整条件检查）
+    fn allows(&self, host: &str) -> bool {
+        !self.blacklist.as_ref().is_some_and(|m| m.matches(host)) && self.routes.load().may_allow(host)
+    }
+}
+
+/// 启动 HTTP → HTTPS 重定向监听
+///
+/// 对白名单域名返回 `301 Location: https://<host><path>`，其他请求返回 403；
+/// 每个连接只处理一个请求，同时处理的连接数不超过 [`MAX_REDIRECT_CONNECTIONS`]
+pub async fn run_redirect_server(listen_addr: SocketAddr, whitelist: RedirectWhitelist) -> Result<()> {
+    let listener = TcpListener::bind(listen_addr)
+        .await
+        .map_err(|source| SniProxyError::Bind { addr: listen_addr, source })?;
+    info!("✅ HTTP → HTTPS 重定向已启动: http://{}", listen_addr);
+
+    let whitelist = Arc::new(whitelist);
+    let permits = Arc::new(Semaphore::new(MAX_REDIRECT_CONNECTIONS));
+
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("重定向监听接受连接失败: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+
+        let Ok(permit) = Arc::clone(&permits).try_acquire_owned() else {
+            debug!("重定向连接数已达上限 {}，关闭 {}", MAX_REDIRECT_CONNECTIONS, peer);
+            continue;
+        };
+        let whitelist = Arc::clone(&whitelist);
+        tokio::spawn(async move {
+            if let Err(e) = handle_redirect_connection(stream, &whitelist).await {
+                debug!("重定向请求处理失败 ({}): {}", peer, e);
+            }
+            drop(permit);
+        });
+    }
+}
+
+/// 处理单个重定向连接
+async fn handle_redirect_connection<S>(mut stream: S, whitelist: &RedirectWhitelist) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let response = match read_request_head(&mut stream, READ_TIMEOUT).await? {
+        RequestHead::Complete(head) => redirect_response(&head, whitelist),
+        RequestHead::Invalid => plain_response(400, "Bad Request"),
+        RequestHead::TimedOut => plain_response(408, "Request Timeout"),
+    };
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// 根据请求头生成响应
+pub(crate) fn redirect_response(head: &[u8], whitelist: &RedirectWhitelist) -> String {
+    let (host, target) = match (parse_http_host(head), parse_request_target(head)) {
+        (Some(host), Some(target)) => (host, target),
+        _ => return plain_response(400, "Bad Request"),
+    };
+
+    if !whitelist.allows(&host) {
+        debug!("重定向拒绝: {} 不在白名单中", host);
+        return plain_response(403, "Forbidden");
+    }
+
+    let location = format!("https://{}{}", host, target);
+    debug!("重定向: http://{}{} → {}", host, target, location);
+    format!(
+        "HTTP/1.1 301 Moved Permanently\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        location
+    )
+}
+
+/// 不带重定向的简单响应
+pub(crate) fn plain_response(status: u16, reason: &str) -> String {
+    format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        reason.len(),
+        reason
+    )
+}
+
+/// TLS 端口收到明文 HTTP 请求时返回的 400 响应
+pub(crate) fn https_required_response() -> String {
+    const BODY: &str = "This port only accepts HTTPS (TLS) connections.\n";
+    format!(
+        "HTTP/1.1 400 Bad Request\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        BODY.len(),
+        BODY
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::route_table::RouteTable;
+    use arc_swap::ArcSwap;
+    use tokio::io::AsyncReadExt;
+
+    fn test_whitelist() -> RedirectWhitelist {
+        RedirectWhitelist {
+            routes: Arc::new(ArcSwap::from_pointee(RouteTable::from_whitelists(
+                vec!["*.example.com".to_string()],
+                vec!["example.org".to_string()],
+            ))),
+            blacklist: Some(Arc::new(DomainMatcher::new(vec!["ads.example.com".to_string()]))),
+        }
+    }
+
+    async fn request(data: &[u8]) -> String {
+        let (mut client, server) = tokio::io::duplex(4096);
+        let whitelist = test_whitelist();
+        let handle = tokio::spawn(async move { handle_redirect_connection(server, &whitelist).await });
+
+        client.write_all(data).await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        handle.await.unwrap().unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_redirect_whitelisted() {
+        let response = request(b"GET /a?b=1 HTTP/1.1\r\nHost: WWW.Example.com:80\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 301 "));
+        assert!(response.contains("Location: https://www.example.com/a?b=1\r\n"));
+
+        // SOCKS5 白名单中的域名同样重定向
+        let response = request(b"GET / HTTP/1.1\r\nHost: example.org\r\n\r\n").await;
+        assert!(response.contains("Location: https://example.org/\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_redirect_rejected() {
+        let response = request(b"GET / HTTP/1.1\r\nHost: evil.com\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 403 "));
+
+        // 黑名单优先于白名单通配符
+        let response = request(b"GET / HTTP/1.1\r\nHost: ads.example.com\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 403 "));
+
+        let response = request(b"GET / HTTP/1.1\r\nAccept: */*\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 400 "));
+    }
+}
@@ -0,0 +1,83 @@
+//! 拒绝连接的方式
+//!
+//! 客户端 IP、域名或 TLS 指纹不在白名单中时如何断开连接。默认直接关闭；
+//! 面对扫描器时可以改为发送 RST、返回 TLS alert，或者慢速读取拖住对方（tarpit）
+
+use std::time::Duration;
+
+use crate::error::{Result, SniProxyError};
+
+/// tarpit 默认保持连接的时长
+pub const DEFAULT_TARPIT_DURATION: Duration = Duration::from_secs(30);
+
+/// TLS alert 描述：access_denied（IP、指纹被拒绝）
+pub const ALERT_ACCESS_DENIED: u8 = 49;
+/// TLS alert 描述：unrecognized_name（域名不在白名单中）
+pub const ALERT_UNRECOGNIZED_NAME: u8 = 112;
+
+/// 拒绝连接的方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RejectionMode {
+    /// 正常关闭连接（FIN）
+    #[default]
+    Close,
+    /// 发送 RST（SO_LINGER 0），不进入 TIME_WAIT；Unix socket 连接退化为正常关闭
+    Reset,
+    /// 发送 fatal 级别的 TLS alert 后关闭
+    Alert,
+    /// 保持连接并每秒只读取一个字节，直到对方断开或超过指定时长
+    ///
+    /// 被拖住的连接仍占用并发连接数，时长不宜过长
+    Tarpit(Duration),
+}
+
+impl std::str::FromStr for RejectionMode {
+    type Err = SniProxyError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "close" => Ok(RejectionMode::Close),
+            "reset" => Ok(RejectionMode::Reset),
+            "alert" => Ok(RejectionMode::Alert),
+            "tarpit" => Ok(RejectionMode::Tarpit(DEFAULT_TARPIT_DURATION)),
+            _ => Err(SniProxyError::InvalidConfig(format!(
+                "无效的拒绝方式: {}（可选: close, reset, alert, tarpit）",
+                s
+            ))),
+        }
+    }
+}
+
+impl std::fmt::Display for RejectionMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RejectionMode::Close => write!(f, "close"),
+            RejectionMode::Reset => write!(f, "reset"),
+            RejectionMode::Alert => write!(f, "alert"),
+            RejectionMode::Tarpit(duration) => write!(f, "tarpit ({} 秒)", duration.as_secs()),
+        }
+    }
+}
+
+/// fatal 级别的 TLS alert 记录（TLS 1.2 记录版本）
+pub fn tls_alert(description: u8) -> [u8; 7] {
+    [0x15, 0x03, 0x03, 0x00, 0x02, 0x02, description]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rejection_mode() {
+        assert_eq!("close".parse::<RejectionMode>().unwrap(), RejectionMode::Close);
+        assert_eq!("RESET".parse::<RejectionMode>().unwrap(), RejectionMode::Reset);
+        assert_eq!("alert".parse::<RejectionMode>().unwrap(), RejectionMode::Alert);
+        assert_eq!(
+            "tarpit".parse::<RejectionMode>().unwrap(),
+            RejectionMode::Tarpit(DEFAULT_TARPIT_DURATION)
+        );
+        assert!("drop".parse::<RejectionMode>().is_err());
+        assert_eq!(tls_alert(ALERT_UNRECOGNIZED_NAME), [0x15, 0x03, 0x03, 0x00, 0x02, 0x02, 112]);
+    }
+}
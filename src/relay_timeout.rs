@@ -0,0 +1,257 @@
+//! 转发超时：空闲超时和最长连接时间
+//!
+//! 转发会一直持续到任一方关闭连接，对端掉线（没有 FIN/RST）或被遗弃的连接会永远占用并发连接许可。
+//! 客户端和上游两侧的流共享一个计时器：任一方向读写到数据都刷新活动时间，
+//! 两个方向超过空闲超时都没有数据，或者转发时间超过最长连接时间时，两侧的读取都返回 EOF，
+//! 转发按正常关闭流程结束（流量统计不丢失）
+
+use std::future::Future;
+use std::io::IoSlice;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{Instant, Sleep};
+
+/// 转发因超时结束的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RelayExpiry {
+    /// 两个方向都超过空闲超时没有数据
+    Idle,
+    /// 超过最长连接时间
+    Lifetime,
+}
+
+const NOT_EXPIRED: u8 = 0;
+const EXPIRED_IDLE: u8 = 1;
+const EXPIRED_LIFETIME: u8 = 2;
+
+/// 一个转发连接两个方向共享的计时器
+#[derive(Debug)]
+pub(crate) struct RelayTimer {
+    idle_timeout: Option<Duration>,
+    max_lifetime: Option<Duration>,
+    start: Instant,
+    /// 最后一次有数据时距 `start` 的毫秒数
+    last_active_ms: AtomicU64,
+    expired: AtomicU8,
+}
+
+impl RelayTimer {
+    /// 创建计时器，两个限制都没有配置时返回 None
+    pub(crate) fn new(idle_timeout: Option<Duration>, max_lifetime: Option<Duration>) -> Option<Arc<Self>> {
+        if idle_timeout.is_none() && max_lifetime.is_none() {
+            return None;
+        }
+        Some(Arc::new(Self {
+            idle_timeout,
+            max_lifetime,
+            start: Instant::now(),
+            last_active_ms: AtomicU64::new(0),
+            expired: AtomicU8::new(NOT_EXPIRED),
+        }))
+    }
+
+    /// 转发因超时结束的原因，还没有超时时为 None
+    pub(crate) fn expired(&self) -> Option<RelayExpiry> {
+        match self.expired.load(Ordering::Relaxed) {
+            EXPIRED_IDLE => Some(RelayExpiry::Idle),
+            EXPIRED_LIFETIME => Some(RelayExpiry::Lifetime),
+            _ => None,
+        }
+    }
+
+    /// 记录一次读写活动
+    pub(crate) fn touch(&self) {
+        let elapsed = self.start.elapsed().as_millis() as u64;
+        self.last_active_ms.fetch_max(elapsed, Ordering::Relaxed);
+    }
+
+    fn idle_deadline(&self) -> Option<Instant> {
+        let last_active = self.start + Duration::from_millis(self.last_active_ms.load(Ordering::Relaxed));
+        self.idle_timeout.map(|timeout| last_active + timeout)
+    }
+
+    fn lifetime_deadline(&self) -> Option<Instant> {
+        self.max_lifetime.map(|lifetime| self.start + lifetime)
+    }
+
+    /// 最近的超时时间（至少配置了一个限制）
+    fn deadline(&self) -> Instant {
+        match (self.idle_deadline(), self.lifetime_deadline()) {
+            (Some(idle), Some(lifetime)) => idle.min(lifetime),
+            (Some(deadline), None) | (None, Some(deadline)) => deadline,
+            (None, None) => unreachable!("RelayTimer 至少配置一个限制"),
+        }
+    }
+
+    /// 等到超时（空闲超时随活动推迟），返回前记录超时原因
+    pub(crate) async fn wait_expiry(&self) {
+        loop {
+            tokio::time::sleep_until(self.deadline()).await;
+            if self.check(Instant::now()) {
+                return;
+            }
+        }
+    }
+
+    /// 检查是否已经超时，超时时记录原因（只记录第一次）
+    fn check(&self, now: Instant) -> bool {
+        let expiry = if self.lifetime_deadline().is_some_and(|deadline| deadline <= now) {
+            EXPIRED_LIFETIME
+        } else if self.idle_deadline().is_some_and(|deadline| deadline <= now) {
+            EXPIRED_IDLE
+        } else {
+            return false;
+        };
+        let _ = self.expired.compare_exchange(NOT_EXPIRED, expiry, Ordering::Relaxed, Ordering::Relaxed);
+        true
+    }
+}
+
+/// 记录读写活动的流包装，超时后读取返回 EOF（没有计时器时直接透传）
+pub(crate) struct TimedStream<S> {
+    inner: S,
+    timer: Option<Arc<RelayTimer>>,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> TimedStream<S> {
+    pub(crate) fn new(inner: S, timer: Option<Arc<RelayTimer>>) -> Self {
+        Self { inner, timer, sleep: None }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for TimedStream<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let this = &mut *self;
+        let Some(timer) = &this.timer else {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        };
+        if timer.expired().is_some() {
+            return Poll::Ready(Ok(()));
+        }
+        if let Poll::Ready(result) = Pin::new(&mut this.inner).poll_read(cx, buf) {
+            timer.touch();
+            return Poll::Ready(result);
+        }
+        loop {
+            let deadline = timer.deadline();
+            let sleep = this.sleep.get_or_insert_with(|| Box::pin(tokio::time::sleep_until(deadline)));
+            if sleep.deadline() != deadline {
+                sleep.as_mut().reset(deadline);
+            }
+            ready!(sleep.as_mut().poll(cx));
+            if timer.check(Instant::now()) {
+                // 另一个方向的读取可能已经挂起，唤醒任务让它也读到 EOF
+                cx.waker().wake_by_ref();
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> TimedStream<S> {
+    /// 写入有进展时刷新活动时间；超时后仍写不出去（对端不再接收）时返回错误
+    fn on_write(&self, result: Poll<std::io::Result<usize>>) -> Poll<std::io::Result<usize>> {
+        let Some(timer) = &self.timer else {
+            return result;
+        };
+        match result {
+            Poll::Ready(Ok(written)) if written > 0 => timer.touch(),
+            Poll::Pending if timer.expired().is_some() => {
+                return Poll::Ready(Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "转发超时")));
+            }
+            _ => {}
+        }
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for TimedStream<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+        self.on_write(result)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<std::io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write_vectored(cx, bufs);
+        self.on_write(result)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+    use tokio::task::JoinHandle;
+
+    /// 通过两个带计时器的流转发，返回客户端、上游和转发任务
+    fn relay(timer: &Arc<RelayTimer>) -> (DuplexStream, DuplexStream, JoinHandle<std::io::Result<(u64, u64)>>) {
+        let (client, client_proxy) = tokio::io::duplex(1024);
+        let (upstream, upstream_proxy) = tokio::io::duplex(1024);
+        let mut client_side = TimedStream::new(client_proxy, Some(Arc::clone(timer)));
+        let mut upstream_side = TimedStream::new(upstream_proxy, Some(Arc::clone(timer)));
+        let task = tokio::spawn(async move { tokio::io::copy_bidirectional(&mut client_side, &mut upstream_side).await });
+        (client, upstream, task)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_idle_timeout_ends_relay() {
+        let timer = RelayTimer::new(Some(Duration::from_secs(30)), None).unwrap();
+        let (mut client, mut upstream, task) = relay(&timer);
+
+        // 有数据时刷新活动时间
+        let start = Instant::now();
+        tokio::time::sleep(Duration::from_secs(20)).await;
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        upstream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(timer.expired(), None);
+
+        // 两个方向都空闲 30 秒后正常结束，已转发的字节数保留
+        assert_eq!(task.await.unwrap().unwrap(), (4, 0));
+        assert_eq!(start.elapsed(), Duration::from_secs(50));
+        assert_eq!(timer.expired(), Some(RelayExpiry::Idle));
+        assert_eq!(client.read(&mut buf).await.unwrap(), 0);
+        assert_eq!(upstream.read(&mut buf).await.unwrap(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_max_lifetime_ends_active_relay() {
+        let timer = RelayTimer::new(Some(Duration::from_secs(30)), Some(Duration::from_secs(60))).unwrap();
+        let (mut client, mut upstream, task) = relay(&timer);
+
+        // 一直有数据也会在最长连接时间后结束
+        let start = Instant::now();
+        let mut buf = [0u8; 4];
+        for _ in 0..2 {
+            tokio::time::sleep(Duration::from_secs(25)).await;
+            client.write_all(b"ping").await.unwrap();
+            upstream.read_exact(&mut buf).await.unwrap();
+        }
+        let (sent, _) = task.await.unwrap().unwrap();
+        assert_eq!(sent, 8);
+        assert_eq!(start.elapsed(), Duration::from_secs(60));
+        assert_eq!(timer.expired(), Some(RelayExpiry::Lifetime));
+        assert!(RelayTimer::new(None, None).is_none());
+    }
+}
@@ -0,0 +1,390 @@
+//! 远程白名单
+//!
+//! 直连白名单、SOCKS5 白名单和 IP 白名单可以从 HTTP 地址获取（格式同白名单文件），便于集中管理多台代理的名单。
+//! 获取到的规则追加在配置文件和白名单文件中的规则之后，按固定周期刷新：请求带上一次响应的 ETag 和 Last-Modified
+//! （If-None-Match / If-Modified-Since），名单没有变化时服务器返回 304，不重新下载和重建路由表。
+//! 刷新失败（连接失败、超时、非 200 响应、规则无效）时继续使用上一次成功获取的规则。
+//! 配置了缓存目录时，每次成功获取的名单连同 ETag / Last-Modified 会写到磁盘，启动时获取失败则改用磁盘上的副本，
+//! 名单服务器不可用时重启的代理仍然可以启动；没有缓存副本时启动失败
+
+use log::{debug, info, warn};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::{timeout, MissedTickBehavior};
+
+use crate::domain::DomainMatcher;
+use crate::error::{Result, SniProxyError};
+use crate::http_client::{http_get, HttpUrl};
+use crate::whitelist_file::{ListKind, WhitelistReloader};
+
+/// 默认刷新周期
+pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(300);
+
+/// 单次获取的超时时间
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 响应大小上限
+const MAX_RESPONSE_LEN: usize = 16 * 1024 * 1024;
+
+/// 远程白名单配置（http:// 或 https://）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteWhitelists {
+    /// 直连白名单地址
+    pub direct: Option<HttpUrl>,
+    /// SOCKS5 白名单地址
+    pub socks5: Option<HttpUrl>,
+    /// IP 白名单地址
+    pub ip: Option<HttpUrl>,
+    /// 请求的 Authorization 头（可选），例如 `Bearer <token>`
+    pub authorization: Option<String>,
+    /// 刷新周期
+    pub refresh_interval: Duration,
+    /// 上一次成功获取的名单的缓存目录（可选）
+    pub cache_dir: Option<PathBuf>,
+}
+
+impl Default for RemoteWhitelists {
+    fn default() -> Self {
+        Self {
+            direct: None,
+            socks5: None,
+            ip: None,
+            authorization: None,
+            refresh_interval: DEFAULT_REFRESH_INTERVAL,
+            cache_dir: None,
+        }
+    }
+}
+
+impl RemoteWhitelists {
+    /// 是否没有配置任何地址
+    pub fn is_empty(&self) -> bool {
+        self.direct.is_none() && self.socks5.is_none() && self.ip.is_none()
+    }
+
+    /// 检查配置：Authorization 头不能通过明文 http:// 发送（回环地址除外）
+    pub fn validate(&self) -> Result<()> {
+        if self.authorization.is_none() {
+            return Ok(());
+        }
+        for url in [&self.direct, &self.socks5, &self.ip].into_iter().flatten() {
+            if !url.is_tls() && !url.is_loopback() {
+                return Err(SniProxyError::InvalidConfig(format!(
+                    "远程白名单地址 {} 不是 https://，不能发送 authorization",
+                    url
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 获取结果
+#[derive(Debug, PartialEq, Eq)]
+enum Fetched {
+    /// 名单没有变化（304）
+    NotModified,
+    /// 新的名单
+    Updated(Vec<String>),
+}
+
+/// 一个远程白名单的获取状态
+#[derive(Debug)]
+struct RemoteList {
+    kind: ListKind,
+    url: HttpUrl,
+    /// 上一次成功响应的 ETag
+    etag: Option<String>,
+    /// 上一次成功响应的 Last-Modified
+    last_modified: Option<String>,
+    /// 缓存文件（名单正文，同名的 .etag 文件保存 ETag 和 Last-Modified）
+    cache: Option<PathBuf>,
+}
+
+impl RemoteList {
+    fn new(kind: ListKind, url: HttpUrl, cache_dir: Option<&Path>) -> Self {
+        let cache = cache_dir.map(|dir| {
+            let name = match kind {
+                ListKind::Direct => "direct",
+                ListKind::Socks5 => "socks5",
+                ListKind::Ip => "ip",
+            };
+            dir.join(format!("remote-{}.txt", name))
+        });
+        Self { kind, url, etag: None, last_modified: None, cache }
+    }
+
+    /// 解析名单内容并校验规则
+    fn parse(&self, content: &str) -> Result<Vec<String>> {
+        let rules = self.kind.parse(content);
+        if self.kind != ListKind::Ip {
+            DomainMatcher::validate_rules(&rules)?;
+        }
+        Ok(rules)
+    }
+
+    /// 获取名单（带条件请求头），成功时记录新的 ETag 和 Last-Modified
+    async fn fetch(&mut self, authorization: Option<&str>) -> Result<Fetched> {
+        let mut headers = Vec::new();
+        if let Some(etag) = &self.etag {
+            headers.push(("If-None-Match", etag.as_str()));
+        }
+        if let Some(last_modified) = &self.last_modified {
+            headers.push(("If-Modified-Since", last_modified.as_str()));
+        }
+        if let Some(authorization) = authorization {
+            headers.push(("Authorization", authorization));
+        }
+        let response = timeout(FETCH_TIMEOUT, http_get(&self.url, &headers, MAX_RESPONSE_LEN))
+            .await
+            .map_err(|_| SniProxyError::Timeout { operation: "获取远程白名单" })??;
+
+        match response.status {
+            304 => return Ok(Fetched::NotModified),
+            200 => {}
+            status => {
+                return Err(SniProxyError::InvalidConfig(format!("{} 返回 HTTP {}", self.url, status)));
+            }
+        }
+        let content = std::str::from_utf8(&response.body)
+            .map_err(|_| SniProxyError::InvalidConfig(format!("{} 返回的内容不是 UTF-8 文本", self.url)))?;
+        let rules = self.parse(content)?;
+        self.etag = response.header("ETag").map(str::to_string);
+        self.last_modified = response.header("Last-Modified").map(str::to_string);
+        if let Err(e) = self.save_cache(content) {
+            warn!("⚠️  写入远程{}缓存失败: {}", self.kind.name(), e);
+        }
+        Ok(Fetched::Updated(rules))
+    }
+
+    /// 把成功获取的名单和条件请求头写入缓存（都先写临时文件再重命名，不会留下半个文件）
+    fn save_cache(&self, content: &str) -> std::io::Result<()> {
+        let Some(path) = &self.cache else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut meta = String::new();
+        if let Some(etag) = &self.etag {
+            meta.push_str(&format!("ETag: {}\n", etag));
+        }
+        if let Some(last_modified) = &self.last_modified {
+            meta.push_str(&format!("Last-Modified: {}\n", last_modified));
+        }
+        let tmp = path.with_extension("txt.tmp");
+        let meta_tmp = path.with_extension("etag.tmp");
+        crate::output_file::write(&tmp, content)?;
+        crate::output_file::write(&meta_tmp, meta)?;
+        // 先替换名单再替换条件请求头：中途失败时旧的 ETag 配新的名单只会让下一次请求重新下载，
+        // 反过来新的 ETag 配旧的名单会让服务器一直返回 304，旧名单永远不会更新
+        std::fs::rename(&tmp, path)?;
+        std::fs::rename(&meta_tmp, path.with_extension("etag"))
+    }
+
+    /// 从缓存读取上一次成功获取的名单，同时恢复条件请求头（没有缓存时返回 None）
+    fn load_cache(&mut self) -> Result<Option<Vec<String>>> {
+        let Some(path) = &self.cache else {
+            return Ok(None);
+        };
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let rules = self.parse(&content)?;
+        let meta = std::fs::read_to_string(path.with_extension("etag")).unwrap_or_default();
+        for line in meta.lines() {
+            match line.split_once(": ") {
+                Some(("ETag", etag)) => self.etag = Some(etag.to_string()),
+                Some(("Last-Modified", last_modified)) => self.last_modified = Some(last_modified.to_string()),
+                _ => {}
+            }
+        }
+        Ok(Some(rules))
+    }
+}
+
+/// 远程白名单的定期刷新
+#[derive(Debug)]
+pub(crate) struct RemoteRefresher {
+    lists: Vec<RemoteList>,
+    authorization: Option<String>,
+    refresh_interval: Duration,
+}
+
+impl RemoteRefresher {
+    /// 首次获取所有远程白名单，按直连、SOCKS5、IP 的顺序返回规则
+    ///
+    /// 获取失败时改用缓存的副本，没有缓存时返回错误
+    pub(crate) async fn load(config: &RemoteWhitelists) -> Result<(Self, [Option<Vec<String>>; 3])> {
+        config.validate()?;
+        let mut refresher = Self {
+            lists: Vec::new(),
+            authorization: config.authorization.clone(),
+            refresh_interval: config.refresh_interval,
+        };
+        let mut initial: [Option<Vec<String>>; 3] = Default::default();
+        let sources = [(ListKind::Direct, &config.direct), (ListKind::Socks5, &config.socks5), (ListKind::Ip, &config.ip)];
+        for ((kind, url), initial) in sources.into_iter().zip(&mut initial) {
+            let Some(url) = url else {
+                continue;
+            };
+            let mut list = RemoteList::new(kind, url.clone(), config.cache_dir.as_deref());
+            let rules = match list.fetch(config.authorization.as_deref()).await {
+                Ok(Fetched::Updated(rules)) => {
+                    info!("✅ {}: 从 {} 获取了 {} 条规则", kind.name(), url, rules.len());
+                    rules
+                }
+                Ok(Fetched::NotModified) => Vec::new(),
+                Err(e) => match list.load_cache() {
+                    Ok(Some(rules)) => {
+                        warn!(
+                            "⚠️  获取远程{} {} 失败: {}，使用缓存的 {} 条规则",
+                            kind.name(),
+                            url,
+                            e,
+                            rules.len()
+                        );
+                        rules
+                    }
+                    Ok(None) => {
+                        return Err(SniProxyError::InvalidConfig(format!("获取远程{} {} 失败: {}", kind.name(), url, e)));
+                    }
+                    Err(cache_error) => {
+                        return Err(SniProxyError::InvalidConfig(format!(
+                            "获取远程{} {} 失败: {}，读取缓存也失败: {}",
+                            kind.name(),
+                            url,
+                            e,
+                            cache_error
+                        )));
+                    }
+                },
+            };
+            *initial = Some(rules);
+            refresher.lists.push(list);
+        }
+        Ok((refresher, initial))
+    }
+}
+
+/// 定期刷新远程白名单，有变化时替换规则（获取失败时继续使用上一次成功获取的规则）
+pub(crate) async fn run_remote_whitelist_refresh(mut refresher: RemoteRefresher, reloader: Arc<Mutex<WhitelistReloader>>) {
+    let mut interval = tokio::time::interval(refresher.refresh_interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    interval.tick().await;
+    info!("✅ 远程白名单刷新已启用（{} 个地址，每 {:?}）", refresher.lists.len(), refresher.refresh_interval);
+
+    loop {
+        interval.tick().await;
+        for list in &mut refresher.lists {
+            match list.fetch(refresher.authorization.as_deref()).await {
+                Ok(Fetched::NotModified) => debug!("远程{} {} 没有变化", list.kind.name(), list.url),
+                Ok(Fetched::Updated(rules)) => {
+                    let source = list.url.to_string();
+                    reloader.lock().unwrap_or_else(|e| e.into_inner()).replace_remote(list.kind, &source, rules);
+                }
+                Err(e) => warn!("⚠️  刷新远程{} {} 失败: {}，继续使用上一次获取的规则", list.kind.name(), list.url, e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// 依次用给定的响应回答请求，返回收到的请求
+    async fn serve(responses: Vec<&'static str>) -> (HttpUrl, tokio::task::JoinHandle<Vec<String>>) {
+        let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://127.0.0.1:{}/whitelist.txt", server.local_addr().unwrap().port()).parse().unwrap();
+        let handle = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut stream, _) = server.accept().await.unwrap();
+                let mut request = vec![0u8; 4096];
+                let n = stream.read(&mut request).await.unwrap();
+                requests.push(String::from_utf8_lossy(&request[..n]).into_owned());
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    #[tokio::test]
+    async fn test_fetch_conditional() {
+        let (url, handle) = serve(vec![
+            "HTTP/1.0 200 OK\r\nETag: \"v1\"\r\nContent-Length: 21\r\n\r\n# 名单\nexample.com\n",
+            "HTTP/1.0 304 Not Modified\r\n\r\n",
+            "HTTP/1.0 500 Internal Server Error\r\n\r\n",
+            "HTTP/1.0 200 OK\r\nContent-Length: 100\r\n\r\ntruncated.com\n",
+        ])
+        .await;
+        let mut list = RemoteList::new(ListKind::Direct, url, None);
+        assert_eq!(list.fetch(Some("Bearer t")).await.unwrap(), Fetched::Updated(vec!["example.com".to_string()]));
+        assert_eq!(list.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(list.fetch(None).await.unwrap(), Fetched::NotModified);
+        assert!(list.fetch(None).await.is_err());
+        // 正文不完整时不使用
+        assert!(list.fetch(None).await.is_err());
+
+        let requests = handle.await.unwrap();
+        assert!(requests[0].starts_with("GET /whitelist.txt HTTP/1.0\r\n"));
+        assert!(requests[0].contains("Authorization: Bearer t\r\n"));
+        assert!(!requests[0].contains("If-None-Match"));
+        assert!(requests[1].contains("If-None-Match: \"v1\"\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_load_falls_back_to_cache() {
+        let dir = std::env::temp_dir().join(format!("sni-proxy-remote-cache-{}", std::process::id()));
+        let (url, handle) = serve(vec![
+            "HTTP/1.0 200 OK\r\nETag: \"v2\"\r\nContent-Length: 12\r\n\r\nexample.com\n",
+            "HTTP/1.0 503 Service Unavailable\r\n\r\n",
+        ])
+        .await;
+        let config = RemoteWhitelists {
+            direct: Some(url),
+            cache_dir: Some(dir.clone()),
+            ..Default::default()
+        };
+        let (_, rules) = RemoteRefresher::load(&config).await.unwrap();
+        assert_eq!(rules[0], Some(vec!["example.com".to_string()]));
+        assert!(dir.join("remote-direct.etag").exists());
+        assert!(!dir.join("remote-direct.txt.tmp").exists() && !dir.join("remote-direct.etag.tmp").exists());
+
+        // 名单服务器不可用时使用缓存，并恢复 ETag 用于之后的条件请求
+        let (refresher, rules) = RemoteRefresher::load(&config).await.unwrap();
+        assert_eq!(rules[0], Some(vec!["example.com".to_string()]));
+        assert_eq!(refresher.lists[0].etag.as_deref(), Some("\"v2\""));
+        handle.await.unwrap();
+
+        // 没有缓存时启动失败
+        std::fs::remove_dir_all(&dir).unwrap();
+        let (url, handle) = serve(vec!["HTTP/1.0 503 Service Unavailable\r\n\r\n"]).await;
+        let config = RemoteWhitelists { direct: Some(url), ..config };
+        assert!(RemoteRefresher::load(&config).await.is_err());
+        handle.await.unwrap();
+    }
+
+    #[test]
+    fn test_validate_authorization() {
+        let config = RemoteWhitelists {
+            direct: Some("https://lists.example.com/direct.txt".parse().unwrap()),
+            ip: Some("http://127.0.0.1:8080/ip.txt".parse().unwrap()),
+            authorization: Some("Bearer t".to_string()),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+
+        // 非回环的明文地址不能发送 Authorization
+        let plain = RemoteWhitelists { socks5: Some("http://lists.example.com/socks5.txt".parse().unwrap()), ..config };
+        assert!(plain.validate().is_err());
+        assert!(RemoteWhitelists { authorization: None, ..plain }.validate().is_ok());
+    }
+}
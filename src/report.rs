@@ -0,0 +1,574 @@
+//! 每日汇总报告
+//!
+//! 每天在指定时间（本地时间）把过去一天的流量、拒绝原因、流量最高的客户端 IP 和上游可用性
+//! 写成 Markdown 或 HTML 文件，并可选推送到 Webhook，替代人工翻日志做周报
+
+use chrono::{DateTime, Duration as ChronoDuration, Local, TimeZone, Timelike};
+use log::{error, info, warn};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::timeout;
+
+use crate::clock;
+use crate::disk;
+use crate::error::{Result, SniProxyError};
+use crate::events::{EventBus, ProxyEvent, RejectReason};
+use crate::http_client::{self, HttpUrl};
+use crate::ip_traffic::{IpTrafficSnapshot, IpTrafficTracker};
+use crate::metrics::{CounterSnapshot, Metrics, RouteLabel};
+
+/// Webhook 请求超时
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 报告格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// Markdown（默认）
+    #[default]
+    Markdown,
+    /// HTML
+    Html,
+}
+
+impl ReportFormat {
+    /// 报告文件扩展名
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ReportFormat::Markdown => "md",
+            ReportFormat::Html => "html",
+        }
+    }
+}
+
+impl FromStr for ReportFormat {
+    type Err = SniProxyError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "markdown" | "md" => Ok(ReportFormat::Markdown),
+            "html" => Ok(ReportFormat::Html),
+            _ => Err(SniProxyError::InvalidConfig(format!(
+                "无效的报告格式: {}（可选: markdown, html）",
+                s
+            ))),
+        }
+    }
+}
+
+/// 每日报告配置
+#[derive(Debug, Clone)]
+pub struct ReportConfig {
+    /// 报告输出目录
+    pub directory: PathBuf,
+    /// 报告格式
+    pub format: ReportFormat,
+    /// 每天生成报告的时间（本地时间，0-23 点）
+    pub hour: u32,
+    /// 报告中列出的客户端 IP 数量
+    pub top_ips: usize,
+    /// 报告生成后推送的 Webhook（可选）
+    pub webhook: Option<HttpUrl>,
+}
+
+/// 一个上游的不可用统计
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpstreamFailures {
+    pub host: String,
+    pub port: u16,
+    pub via_socks5: bool,
+    pub failures: u64,
+    pub last_error: String,
+}
+
+/// 报告中的一个表格：标题、表头、行
+type Section = (&'static str, Vec<&'static str>, Vec<Vec<String>>);
+
+/// 一个统计周期内的汇总报告
+#[derive(Debug, Clone)]
+pub struct DailyReport {
+    pub period_start: DateTime<Local>,
+    pub period_end: DateTime<Local>,
+    /// 周期内的计数器增量
+    pub totals: CounterSnapshot,
+    /// 按路由动作拆分的计数器增量
+    pub routes: Vec<(RouteLabel, CounterSnapshot)>,
+    /// 周期内流量最高的客户端 IP（未开启 IP 流量追踪时为空）
+    pub top_ips: Vec<IpTrafficSnapshot>,
+    /// 按原因统计的拒绝次数
+    pub rejections: Vec<(RejectReason, u64)>,
+    /// 上游不可用事件（按失败次数降序）
+    pub upstream_failures: Vec<UpstreamFailures>,
+    /// 并发连接数达到上限的次数
+    pub quota_exceeded: u64,
+    /// 订阅方处理过慢而丢失的事件数（拒绝和上游统计可能偏少）
+    pub lost_events: u64,
+}
+
+impl DailyReport {
+    /// 上游连接成功率（路由到上游的请求中没有触发上游不可用事件的比例，没有请求时为 None）
+    pub fn upstream_success_rate(&self) -> Option<f64> {
+        let attempts = self.totals.direct_requests + self.totals.socks5_requests;
+        let failures: u64 = self.upstream_failures.iter().map(|u| u.failures).sum();
+        (attempts > 0).then(|| attempts.saturating_sub(failures) as f64 / attempts as f64 * 100.0)
+    }
+
+    /// 按格式渲染报告
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Markdown => self.to_markdown(),
+            ReportFormat::Html => self.to_html(),
+        }
+    }
+
+    /// 报告各部分的表格（标题、表头、行），Markdown 和 HTML 共用
+    fn sections(&self) -> Vec<Section> {
+        let t = &self.totals;
+        let overview = vec![
+            vec!["总连接数".to_string(), t.total_connections.to_string()],
+            vec!["失败连接".to_string(), t.failed_connections.to_string()],
+            vec!["直连请求".to_string(), t.direct_requests.to_string()],
+            vec!["SOCKS5 请求".to_string(), t.socks5_requests.to_string()],
+            vec!["拒绝请求".to_string(), t.rejected_requests.to_string()],
+            vec!["接收流量".to_string(), format_bytes(t.bytes_received)],
+            vec!["发送流量".to_string(), format_bytes(t.bytes_sent)],
+            vec!["SNI 解析错误".to_string(), t.sni_parse_errors.to_string()],
+            vec!["明文 HTTP 请求".to_string(), t.plaintext_http_requests.to_string()],
+            vec!["连接超时".to_string(), t.connection_timeouts.to_string()],
+            vec!["空闲超时".to_string(), t.idle_timeouts.to_string()],
+            vec!["上游非 TLS 响应".to_string(), t.upstream_not_tls.to_string()],
+            vec!["并发上限排队".to_string(), self.quota_exceeded.to_string()],
+        ];
+
+        let routes = self
+            .routes
+            .iter()
+            .map(|(route, c)| {
+                vec![
+                    route.to_string(),
+                    c.total_connections.to_string(),
+                    format_bytes(c.bytes_received),
+                    format_bytes(c.bytes_sent),
+                    c.failed_connections.to_string(),
+                ]
+            })
+            .collect();
+
+        let top_ips = self
+            .top_ips
+            .iter()
+            .map(|ip| {
+                vec![
+                    ip.ip.to_string(),
+                    ip.connections.to_string(),
+                    format_bytes(ip.bytes_received),
+                    format_bytes(ip.bytes_sent),
+                ]
+            })
+            .collect();
+
+        let rejections = self
+            .rejections
+            .iter()
+            .map(|(reason, count)| vec![reason.to_string(), count.to_string()])
+            .collect();
+
+        let mut upstreams: Vec<Vec<String>> = self
+            .upstream_failures
+            .iter()
+            .map(|u| {
+                vec![
+                    format!("{}:{}", u.host, u.port),
+                    if u.via_socks5 { "socks5" } else { "direct" }.to_string(),
+                    u.failures.to_string(),
+                    u.last_error.clone(),
+                ]
+            })
+            .collect();
+        if let Some(rate) = self.upstream_success_rate() {
+            upstreams.insert(0, vec!["（全部）".to_string(), "-".to_string(), format!("成功率 {:.2}%", rate), String::new()]);
+        }
+
+        vec![
+            ("概览", vec!["指标", "数值"], overview),
+            ("按路由", vec!["路由", "连接数", "接收", "发送", "失败"], routes),
+            ("流量最高的客户端 IP", vec!["IP", "连接数", "接收", "发送"], top_ips),
+            ("拒绝原因", vec!["原因", "次数"], rejections),
+            ("上游可用性", vec!["上游", "出口", "失败次数", "最近错误"], upstreams),
+        ]
+    }
+
+    fn title(&self) -> String {
+        format!(
+            "SNI 代理每日报告 {} ~ {}",
+            self.period_start.format("%Y-%m-%d %H:%M"),
+            self.period_end.format("%Y-%m-%d %H:%M")
+        )
+    }
+
+    /// 渲染为 Markdown
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!("# {}\n", self.title());
+        if self.lost_events > 0 {
+            let _ = writeln!(out, "\n> ⚠️ 丢失 {} 个事件，拒绝原因和上游统计可能偏少", self.lost_events);
+        }
+        for (title, header, rows) in self.sections() {
+            let _ = writeln!(out, "\n## {}\n", title);
+            if rows.is_empty() {
+                out.push_str("无数据\n");
+                continue;
+            }
+            let _ = writeln!(out, "| {} |", header.join(" | "));
+            let _ = writeln!(out, "|{}", "---|".repeat(header.len()));
+            for row in rows {
+                let cells: Vec<String> = row.iter().map(|cell| cell.replace('|', "\\|")).collect();
+                let _ = writeln!(out, "| {} |", cells.join(" | "));
+            }
+        }
+        out
+    }
+
+    /// 渲染为 HTML
+    pub fn to_html(&self) -> String {
+        let title = escape_html(&self.title());
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{0}</title></head><body>\n<h1>{0}</h1>\n",
+            title
+        );
+        if self.lost_events > 0 {
+            let _ = writeln!(out, "<p>⚠️ 丢失 {} 个事件，拒绝原因和上游统计可能偏少</p>", self.lost_events);
+        }
+        for (title, header, rows) in self.sections() {
+            let _ = writeln!(out, "<h2>{}</h2>", title);
+            if rows.is_empty() {
+                out.push_str("<p>无数据</p>\n");
+                continue;
+            }
+            out.push_str("<table border=\"1\">\n<tr>");
+            for cell in header {
+                let _ = write!(out, "<th>{}</th>", cell);
+            }
+            out.push_str("</tr>\n");
+            for row in rows {
+                out.push_str("<tr>");
+                for cell in row {
+                    let _ = write!(out, "<td>{}</td>", escape_html(&cell));
+                }
+                out.push_str("</tr>\n");
+            }
+            out.push_str("</table>\n");
+        }
+        out.push_str("</body></html>\n");
+        out
+    }
+}
+
+/// 报告周期内的统计状态
+struct ReportCollector {
+    period_start: DateTime<Local>,
+    counters: CounterSnapshot,
+    routes: HashMap<RouteLabel, CounterSnapshot>,
+    ips: HashMap<IpAddr, IpTrafficSnapshot>,
+    rejections: HashMap<RejectReason, u64>,
+    upstream_failures: BTreeMap<(String, u16, bool), UpstreamFailures>,
+    quota_exceeded: u64,
+    lost_events: u64,
+}
+
+impl ReportCollector {
+    /// 以当前计数器为基线开始新的统计周期
+    fn start(metrics: &Metrics, ip_tracker: &IpTrafficTracker, now: DateTime<Local>) -> Self {
+        Self {
+            period_start: now,
+            counters: metrics.counters(),
+            routes: route_totals(metrics),
+            ips: ip_tracker.get_all_stats().into_iter().map(|s| (s.ip, s)).collect(),
+            rejections: HashMap::new(),
+            upstream_failures: BTreeMap::new(),
+            quota_exceeded: 0,
+            lost_events: 0,
+        }
+    }
+
+    fn record(&mut self, event: ProxyEvent) {
+        match event {
+            ProxyEvent::Rejected { reason, .. } => *self.rejections.entry(reason).or_default() += 1,
+            ProxyEvent::UpstreamDown {
+                host,
+                port,
+                via_socks5,
+                error,
+            } => {
+                let entry = self
+                    .upstream_failures
+                    .entry((host.clone(), port, via_socks5))
+                    .or_insert_with(|| UpstreamFailures {
+                        host,
+                        port,
+                        via_socks5,
+                        failures: 0,
+                        last_error: String::new(),
+                    });
+                entry.failures += 1;
+                entry.last_error = error;
+            }
+            ProxyEvent::QuotaExceeded { .. } => self.quota_exceeded += 1,
+            _ => {}
+        }
+    }
+
+    /// 生成报告
+    fn finish(self, metrics: &Metrics, ip_tracker: &IpTrafficTracker, top_ips: usize, now: DateTime<Local>) -> DailyReport {
+        let mut routes: Vec<(RouteLabel, CounterSnapshot)> = route_totals(metrics)
+            .into_iter()
+            .map(|(route, counters)| {
+                let delta = match self.routes.get(&route) {
+                    Some(earlier) => counters.since(earlier),
+                    None => counters,
+                };
+                (route, delta)
+            })
+            .filter(|(_, delta)| *delta != CounterSnapshot::default())
+            .collect();
+        routes.sort_by_key(|(route, _)| *route as u8);
+
+        // IP 流量按周期内的增量排序（追踪器中的数据是累计值）
+        let mut ips: Vec<IpTrafficSnapshot> = ip_tracker
+            .get_all_stats()
+            .into_iter()
+            .map(|current| match self.ips.get(&current.ip) {
+                Some(earlier) => IpTrafficSnapshot {
+                    ip: current.ip,
+                    bytes_received: current.bytes_received.saturating_sub(earlier.bytes_received),
+                    bytes_sent: current.bytes_sent.saturating_sub(earlier.bytes_sent),
+                    total_bytes: current.total_bytes.saturating_sub(earlier.total_bytes),
+                    connections: current.connections.saturating_sub(earlier.connections),
+                },
+                None => current,
+            })
+            .filter(|ip| ip.connections > 0 || ip.total_bytes > 0)
+            .collect();
+        ips.sort_by(|a, b| b.total_bytes.cmp(&a.total_bytes).then(b.connections.cmp(&a.connections)));
+        ips.truncate(top_ips);
+
+        let mut rejections: Vec<(RejectReason, u64)> = self.rejections.into_iter().collect();
+        rejections.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.to_string().cmp(&b.0.to_string())));
+
+        let mut upstream_failures: Vec<UpstreamFailures> = self.upstream_failures.into_values().collect();
+        upstream_failures.sort_by_key(|u| std::cmp::Reverse(u.failures));
+
+        DailyReport {
+            period_start: self.period_start,
+            period_end: now,
+            totals: metrics.counters().since(&self.counters),
+            routes,
+            top_ips: ips,
+            rejections,
+            upstream_failures,
+            quota_exceeded: self.quota_exceeded,
+            lost_events: self.lost_events,
+        }
+    }
+}
+
+/// 按路由动作合并各标签组合的计数器
+fn route_totals(metrics: &Metrics) -> HashMap<RouteLabel, CounterSnapshot> {
+    let mut routes: HashMap<RouteLabel, CounterSnapshot> = HashMap::new();
+    for (labels, counters) in metrics.series_snapshot() {
+        routes.entry(labels.route).or_default().add(&counters);
+    }
+    routes
+}
+
+/// 下一个报告时间点
+fn next_report_time(now: DateTime<Local>, hour: u32) -> DateTime<Local> {
+    let mut next = now.date_naive().and_hms_opt(hour, 0, 0).expect("hour 已在配置中验证");
+    if now.hour() >= hour {
+        next += ChronoDuration::days(1);
+    }
+    // 夏令时切换导致该时间不存在时顺延一小时
+    Local
+        .from_local_datetime(&next)
+        .earliest()
+        .or_else(|| Local.from_local_datetime(&(next + ChronoDuration::hours(1))).earliest())
+        .unwrap_or(now + ChronoDuration::days(1))
+}
+
+/// 每日报告任务：收集事件，每天在指定时间生成报告
+pub(crate) async fn run_daily_report(config: ReportConfig, metrics: Metrics, ip_tracker: IpTrafficTracker, events: EventBus) {
+    let mut receiver = events.subscribe();
+    let mut collector = ReportCollector::start(&metrics, &ip_tracker, clock::local_now());
+
+    loop {
+        let now = clock::local_now();
+        let wait = (next_report_time(now, config.hour) - now).to_std().unwrap_or_default();
+        let deadline = tokio::time::sleep(wait);
+        tokio::pin!(deadline);
+
+        loop {
+            tokio::select! {
+                _ = &mut deadline => break,
+                event = receiver.recv() => match event {
+                    Ok(event) => collector.record(event),
+                    Err(RecvError::Lagged(lost)) => collector.lost_events += lost,
+                    Err(RecvError::Closed) => {
+                        (&mut deadline).await;
+                        break;
+                    }
+                },
+            }
+        }
+
+        let now = clock::local_now();
+        let finished = std::mem::replace(&mut collector, ReportCollector::start(&metrics, &ip_tracker, now));
+        let report = finished.finish(&metrics, &ip_tracker, config.top_ips, now);
+        publish_report(&config, &report).await;
+    }
+}
+
+/// 写入报告文件并推送 Webhook
+async fn publish_report(config: &ReportConfig, report: &DailyReport) {
+    let content = report.render(config.format);
+    let path = config.directory.join(format!(
+        "sni-proxy-report-{}.{}",
+        report.period_start.format("%Y-%m-%d"),
+        config.format.extension()
+    ));
+    let written = disk::DAILY_REPORT.write(|| {
+        std::fs::create_dir_all(&config.directory)?;
+        crate::output_file::write(&path, &content)?;
+        info!("📊 每日报告已生成: {}", path.display());
+        Ok(())
+    });
+    if let Err(e) = written {
+        error!("写入每日报告 {} 失败: {}", path.display(), e);
+    }
+
+    if let Some(webhook) = &config.webhook {
+        // Webhook 统一推送 Markdown 文本，便于直接转发到 IM 机器人
+        let body = json!({ "title": report.title(), "text": report.to_markdown() }).to_string();
+        match timeout(WEBHOOK_TIMEOUT, post_webhook(webhook, &body)).await {
+            Ok(Ok(())) => info!("📊 每日报告已推送到 Webhook: {}", webhook),
+            Ok(Err(e)) => warn!("⚠️  推送每日报告到 Webhook 失败: {}", e),
+            Err(_) => warn!("⚠️  推送每日报告到 Webhook 超时"),
+        }
+    }
+}
+
+/// 以 JSON POST 到 Webhook，非 2xx 响应视为失败
+async fn post_webhook(webhook: &HttpUrl, body: &str) -> std::io::Result<()> {
+    http_client::http_post(webhook, "application/json", None, body).await
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.2} {}", value, UNITS[unit])
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::{ListenerLabel, MetricLabels, UpstreamLabel};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_next_report_time() {
+        let now = Local.with_ymd_and_hms(2026, 3, 10, 8, 30, 0).unwrap();
+        assert_eq!(next_report_time(now, 9), Local.with_ymd_and_hms(2026, 3, 10, 9, 0, 0).unwrap());
+        assert_eq!(next_report_time(now, 8), Local.with_ymd_and_hms(2026, 3, 11, 8, 0, 0).unwrap());
+        assert_eq!(next_report_time(now, 0), Local.with_ymd_and_hms(2026, 3, 11, 0, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn test_report_collects_period_deltas() {
+        let metrics = Metrics::new();
+        let ip_tracker = IpTrafficTracker::new(100, None, None);
+        let client: IpAddr = "192.0.2.1".parse().unwrap();
+        let direct = metrics.with_labels(MetricLabels {
+            listener: ListenerLabel::Tcp(443),
+            route: RouteLabel::Direct,
+            upstream: UpstreamLabel::Direct,
+        });
+
+        // 上一个周期的数据不计入报告
+        direct.inc_direct_requests();
+        ip_tracker.record_connection(client);
+
+        let now = Local::now();
+        let mut collector = ReportCollector::start(&metrics, &ip_tracker, now);
+        direct.inc_direct_requests();
+        direct.inc_direct_requests();
+        direct.add_bytes_sent(4096);
+        ip_tracker.record_connection(client);
+        ip_tracker.record_sent(client, 4096);
+        let client_addr = "192.0.2.1:5000".parse().unwrap();
+        for _ in 0..3 {
+            collector.record(ProxyEvent::Rejected {
+                client_addr,
+                host: None,
+                reason: RejectReason::SniParseError,
+            });
+        }
+        collector.record(ProxyEvent::UpstreamDown {
+            host: "down.example.com".to_string(),
+            port: 443,
+            via_socks5: false,
+            error: "connection refused".to_string(),
+        });
+
+        let report = collector.finish(&metrics, &ip_tracker, 10, now);
+        assert_eq!(report.totals.direct_requests, 2);
+        assert_eq!(report.routes.len(), 1);
+        assert_eq!(report.routes[0].1.bytes_sent, 4096);
+        assert_eq!(report.top_ips.len(), 1);
+        assert_eq!(report.top_ips[0].connections, 1);
+        assert_eq!(report.rejections, vec![(RejectReason::SniParseError, 3)]);
+        assert_eq!(report.upstream_failures[0].failures, 1);
+        assert_eq!(report.upstream_success_rate(), Some(50.0));
+
+        let markdown = report.to_markdown();
+        assert!(markdown.contains("| sni_parse_error | 3 |"));
+        assert!(markdown.contains("| down.example.com:443 | direct | 1 | connection refused |"));
+        let html = report.to_html();
+        assert!(html.contains("<td>192.0.2.1</td>"));
+    }
+
+    #[tokio::test]
+    async fn test_post_webhook() {
+        let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = server.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = server.accept().await.unwrap();
+            let mut request = vec![0u8; 4096];
+            let n = stream.read(&mut request).await.unwrap();
+            stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").await.unwrap();
+            String::from_utf8_lossy(&request[..n]).into_owned()
+        });
+
+        let webhook: HttpUrl = format!("http://127.0.0.1:{}/report", port).parse().unwrap();
+        post_webhook(&webhook, "{\"text\":\"ok\"}").await.unwrap();
+        let request = handle.await.unwrap();
+        assert!(request.starts_with("POST /report HTTP/1.1\r\n"));
+        assert!(request.ends_with("{\"text\":\"ok\"}"));
+    }
+}
@@ -0,0 +1,67 @@
+//! 上游连接重试
+//!
+//! 目标服务器或 SOCKS5 代理偶尔拒绝连接、重置或超时时，客户端看到的是连接直接断开。
+//! 开启后对这类临时错误按指数退避重试（DNS 失败、SOCKS5 认证失败等不会因重试而改变的错误不重试）
+
+use std::io;
+use std::time::Duration;
+
+/// 上游连接重试配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectRetryConfig {
+    /// 最多重试次数（不含第一次连接）
+    pub max_retries: u32,
+    /// 第一次重试前的等待时间，之后每次翻倍
+    pub initial_backoff: Duration,
+    /// 最长等待时间
+    pub max_backoff: Duration,
+}
+
+impl Default for ConnectRetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+impl ConnectRetryConfig {
+    /// 第 `retry` 次重试（从 0 开始）前的等待时间
+    pub fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(1u32.checked_shl(retry).unwrap_or(u32::MAX))
+            .min(self.max_backoff)
+    }
+}
+
+/// 是否为值得重试的临时连接错误（连接被拒绝、重置、中止或超时）
+pub fn is_transient_io(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::TimedOut
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let config = ConnectRetryConfig {
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+        };
+        let backoffs: Vec<_> = (0..4).map(|retry| config.backoff(retry).as_millis()).collect();
+        assert_eq!(backoffs, [100, 200, 400, 500]);
+        assert_eq!(config.backoff(40), Duration::from_millis(500));
+        assert!(is_transient_io(&io::Error::from(io::ErrorKind::ConnectionRefused)));
+        assert!(!is_transient_io(&io::Error::from(io::ErrorKind::PermissionDenied)));
+    }
+}
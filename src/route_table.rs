@@ -0,0 +1,447 @@
+//! 路由表
+//!
+//! 按顺序匹配的路由规则：每条规则由若干匹配条件（域名、客户端 IP、客户端国家、ALPN、生效时段）和一个动作
+//! （direct、socks5:<名称>、backend:<地址>、reject）组成，第一条所有条件都满足的规则决定连接的去向。
+//! 规则还可以带分类名称、带宽上限（匹配该规则的所有连接共享）、出站选项（源地址等）、TCP 调优参数、DSCP 标记和拥塞控制算法。
+//! 直连白名单和 SOCKS5 白名单也转换为路由规则（SOCKS5 在前），排在显式配置的规则之后
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+
+use crate::backend::BackendAddr;
+use crate::domain::DomainMatcher;
+use crate::error::{Result, SniProxyError};
+use crate::geoip::CountryCode;
+use crate::ip_matcher::IpMatcher;
+use crate::outbound::OutboundOptions;
+use crate::schedule::Schedule;
+use crate::socks5::Socks5Config;
+use crate::throttle::RateLimiter;
+use crate::tuning::TcpTuning;
+
+/// 默认 SOCKS5 上游的名称（`socks5` 配置块），动作 `socks5` 等同于 `socks5:default`
+pub const DEFAULT_SOCKS5_UPSTREAM: &str = "default";
+
+/// 按名称索引的 SOCKS5 上游
+pub type Socks5Upstreams = HashMap<String, Arc<Socks5Config>>;
+
+/// 可在运行中替换的路由表（白名单文件重新加载时整体替换，连接在路由时读取当时的版本）
+pub type SharedRouteTable = Arc<ArcSwap<RouteTable>>;
+
+/// 路由动作
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RouteAction {
+    /// 直接连接目标服务器
+    Direct,
+    /// 通过指定名称的 SOCKS5 上游连接
+    Socks5(String),
+    /// 转发到指定后端（不按 SNI 解析 DNS）
+    Backend(Arc<BackendAddr>),
+    /// 拒绝连接
+    Reject,
+}
+
+impl FromStr for RouteAction {
+    type Err = SniProxyError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (kind, arg) = match s.split_once(':') {
+            Some((kind, arg)) => (kind, Some(arg)),
+            None => (s, None),
+        };
+        match (kind.to_lowercase().as_str(), arg) {
+            ("direct", None) => Ok(RouteAction::Direct),
+            ("reject", None) => Ok(RouteAction::Reject),
+            ("socks5", None) => Ok(RouteAction::Socks5(DEFAULT_SOCKS5_UPSTREAM.to_string())),
+            ("socks5", Some(name)) if !name.is_empty() => Ok(RouteAction::Socks5(name.to_string())),
+            ("backend", Some(addr)) => Ok(RouteAction::Backend(Arc::new(addr.parse()?))),
+            _ => Err(SniProxyError::InvalidConfig(format!(
+                "无效的路由动作: {}（可选: direct, socks5, socks5:<名称>, backend:<host:port>, reject）",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for RouteAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteAction::Direct => write!(f, "direct"),
+            RouteAction::Socks5(name) => write!(f, "socks5:{}", name),
+            RouteAction::Backend(addr) => write!(f, "backend:{}", addr),
+            RouteAction::Reject => write!(f, "reject"),
+        }
+    }
+}
+
+/// 查询路由时已知的连接信息
+#[derive(Debug, Clone, Copy)]
+pub struct RouteQuery<'a> {
+    /// SNI（或 HTTP Host）
+    pub domain: &'a str,
+    /// 客户端 IP
+    pub client_ip: IpAddr,
+    /// 客户端所在国家（需要 GeoIP 数据库，查不到时为 None）
+    pub country: Option<CountryCode>,
+    /// 客户端提供的 ALPN 协议（明文 HTTP 和 QUIC 为空）
+    pub alpn: &'a [String],
+}
+
+/// 一条路由规则，未设置的条件视为匹配任意值
+#[derive(Debug, Clone)]
+pub struct RouteRule {
+    domains: Option<DomainMatcher>,
+    client_ips: Option<IpMatcher>,
+    alpn: Vec<String>,
+    client_countries: Vec<CountryCode>,
+    schedule: Option<Schedule>,
+    action: RouteAction,
+    /// 分类名称（由 categories 配置生成的规则）
+    category: Option<String>,
+    /// 带宽上限（匹配该规则的所有连接共享）
+    bandwidth_limit: Option<Arc<RateLimiter>>,
+    /// 出站选项（覆盖全局配置）
+    outbound: Option<OutboundOptions>,
+    /// TCP 调优参数（客户端和上游连接都设置，覆盖监听器和 route_tuning 的参数）
+    tuning: Option<Arc<TcpTuning>>,
+    /// DSCP 标记（客户端和上游连接都设置）
+    dscp: Option<u8>,
+    /// 拥塞控制算法（客户端和上游连接都设置）
+    congestion_control: Option<Arc<str>>,
+}
+
+impl RouteRule {
+    /// 匹配所有连接的规则
+    pub fn new(action: RouteAction) -> Self {
+        Self {
+            domains: None,
+            client_ips: None,
+            alpn: Vec::new(),
+            client_countries: Vec::new(),
+            schedule: None,
+            action,
+            category: None,
+            bandwidth_limit: None,
+            outbound: None,
+            tuning: None,
+            dscp: None,
+            congestion_control: None,
+        }
+    }
+
+    /// 域名条件（支持通配符和目标端口，与白名单规则格式相同）
+    pub fn with_domains(mut self, domains: Vec<String>) -> Self {
+        self.domains = Some(DomainMatcher::new(domains));
+        self
+    }
+
+    /// 客户端 IP 条件（IP 或 CIDR）
+    pub fn with_client_ips(mut self, client_ips: Vec<String>) -> Self {
+        self.client_ips = Some(IpMatcher::new(client_ips));
+        self
+    }
+
+    /// ALPN 条件：客户端提供的任一 ALPN 协议在列表中（不区分大小写）
+    pub fn with_alpn(mut self, alpn: Vec<String>) -> Self {
+        self.alpn = alpn.into_iter().map(|protocol| protocol.to_lowercase()).collect();
+        self
+    }
+
+    /// 客户端所在国家条件：国家在列表中（需要 GeoIP 数据库，查不到国家的客户端不匹配）
+    pub fn with_client_countries(mut self, countries: Vec<CountryCode>) -> Self {
+        self.client_countries = countries;
+        self
+    }
+
+    /// 生效时段条件：只在时段内匹配，时段外继续匹配之后的规则
+    pub fn with_schedule(mut self, schedule: Schedule) -> Self {
+        self.schedule = Some(schedule);
+        self
+    }
+
+    /// 分类名称（用于日志和管理接口）
+    pub fn with_category(mut self, category: impl Into<String>) -> Self {
+        self.category = Some(category.into());
+        self
+    }
+
+    /// 带宽上限（每秒字节数，上下行合计），匹配该规则的所有连接共享
+    pub fn with_bandwidth_limit(mut self, bytes_per_sec: u64) -> Self {
+        self.bandwidth_limit = Some(Arc::new(RateLimiter::new(bytes_per_sec)));
+        self
+    }
+
+    /// 出站选项（源地址等），规则中设置的选项覆盖全局配置
+    pub fn with_outbound(mut self, outbound: OutboundOptions) -> Self {
+        self.outbound = Some(outbound);
+        self
+    }
+
+    /// TCP 调优参数，设置在匹配该规则的客户端连接和上游连接上（例如 API 流量使用低延迟参数）
+    pub fn with_tuning(mut self, tuning: TcpTuning) -> Self {
+        self.tuning = Some(Arc::new(tuning));
+        self
+    }
+
+    /// DSCP 标记（0-63），设置在匹配该规则的客户端连接和上游连接上，供下游设备做 QoS
+    pub fn with_dscp(mut self, dscp: u8) -> Self {
+        self.dscp = Some(dscp & 0x3f);
+        self
+    }
+
+    /// 拥塞控制算法（例如 "bbr"），设置在匹配该规则的客户端连接和上游连接上
+    pub fn with_congestion_control(mut self, name: impl Into<Arc<str>>) -> Self {
+        self.congestion_control = Some(name.into());
+        self
+    }
+
+    /// 规则的动作
+    pub fn action(&self) -> &RouteAction {
+        &self.action
+    }
+
+    /// 分类名称
+    pub fn category(&self) -> Option<&str> {
+        self.category.as_deref()
+    }
+
+    /// 生效时段
+    pub fn schedule(&self) -> Option<&Schedule> {
+        self.schedule.as_ref()
+    }
+
+    /// 带宽上限
+    pub fn bandwidth_limit(&self) -> Option<&Arc<RateLimiter>> {
+        self.bandwidth_limit.as_ref()
+    }
+
+    /// 出站选项
+    pub fn outbound(&self) -> Option<&OutboundOptions> {
+        self.outbound.as_ref()
+    }
+
+    /// TCP 调优参数
+    pub fn tuning(&self) -> Option<&Arc<TcpTuning>> {
+        self.tuning.as_ref()
+    }
+
+    /// DSCP 标记
+    pub fn dscp(&self) -> Option<u8> {
+        self.dscp
+    }
+
+    /// 拥塞控制算法
+    pub fn congestion_control(&self) -> Option<&Arc<str>> {
+        self.congestion_control.as_ref()
+    }
+
+    /// 域名规则指定的目标端口
+    pub fn target_port(&self, domain: &str) -> Option<u16> {
+        self.domains.as_ref()?.target_port(domain)
+    }
+
+    /// 连接是否满足规则的全部条件
+    pub fn matches(&self, query: &RouteQuery<'_>) -> bool {
+        self.domains.as_ref().is_none_or(|m| m.matches(query.domain))
+            && self.client_ips.as_ref().is_none_or(|m| m.matches(query.client_ip.to_canonical()))
+            && (self.alpn.is_empty()
+                || query.alpn.iter().any(|protocol| self.alpn.iter().any(|p| p.eq_ignore_ascii_case(protocol))))
+            && (self.client_countries.is_empty() || query.country.is_some_and(|c| self.client_countries.contains(&c)))
+            && self.schedule.as_ref().is_none_or(Schedule::is_active)
+    }
+
+    /// 除域名外是否还有其他条件
+    fn has_extra_conditions(&self) -> bool {
+        self.client_ips.is_some() || !self.alpn.is_empty() || !self.client_countries.is_empty() || self.schedule.is_some()
+    }
+}
+
+/// 有序的路由规则列表
+#[derive(Debug, Clone, Default)]
+pub struct RouteTable {
+    rules: Vec<RouteRule>,
+    /// 末尾由白名单生成的规则数
+    whitelist_rules: usize,
+}
+
+impl RouteTable {
+    pub fn new(rules: Vec<RouteRule>) -> Self {
+        Self { rules, whitelist_rules: 0 }
+    }
+
+    /// 由直连白名单和 SOCKS5 白名单生成路由表（SOCKS5 白名单优先，空白名单不生成规则）
+    pub fn from_whitelists(direct_whitelist: Vec<String>, socks5_whitelist: Vec<String>) -> Self {
+        Self::default().with_whitelists(direct_whitelist, socks5_whitelist)
+    }
+
+    /// 保留显式配置的规则，重新生成白名单规则（白名单文件重新加载时使用）
+    pub fn with_whitelists(&self, direct_whitelist: Vec<String>, socks5_whitelist: Vec<String>) -> Self {
+        let mut rules = self.rules[..self.rules.len() - self.whitelist_rules].to_vec();
+        let explicit = rules.len();
+        if !socks5_whitelist.is_empty() {
+            let action = RouteAction::Socks5(DEFAULT_SOCKS5_UPSTREAM.to_string());
+            rules.push(RouteRule::new(action).with_domains(socks5_whitelist));
+        }
+        if !direct_whitelist.is_empty() {
+            rules.push(RouteRule::new(RouteAction::Direct).with_domains(direct_whitelist));
+        }
+        let whitelist_rules = rules.len() - explicit;
+        Self { rules, whitelist_rules }
+    }
+
+    /// 在现有规则之前插入规则
+    pub fn with_rules_first(mut self, rules: Vec<RouteRule>) -> Self {
+        self.rules.splice(0..0, rules);
+        self
+    }
+
+    /// 第一条匹配的规则
+    pub fn lookup(&self, query: &RouteQuery<'_>) -> Option<&RouteRule> {
+        self.rules.iter().find(|rule| rule.matches(query))
+    }
+
+    /// 是否存在允许访问该域名的客户端（HTTP 重定向只知道域名时使用）
+    ///
+    /// 按顺序检查域名匹配的规则：遇到非 reject 规则返回 true；
+    /// 没有其他条件的 reject 规则对所有客户端生效，返回 false
+    pub fn may_allow(&self, domain: &str) -> bool {
+        for rule in &self.rules {
+            if !rule.domains.as_ref().is_none_or(|m| m.matches(domain)) {
+                continue;
+            }
+            if rule.action != RouteAction::Reject {
+                return true;
+            }
+            if !rule.has_extra_conditions() {
+                return false;
+            }
+        }
+        false
+    }
+
+    /// 直连规则中的精确域名（去重，DNS 预取使用；SOCKS5 和固定后端的连接不在本地按域名解析）
+    pub fn direct_domains(&self) -> Vec<String> {
+        let domains: BTreeSet<&str> = self
+            .rules
+            .iter()
+            .filter(|rule| rule.action == RouteAction::Direct)
+            .filter_map(|rule| rule.domains.as_ref())
+            .flat_map(DomainMatcher::exact_domains)
+            .collect();
+        domains.into_iter().map(str::to_string).collect()
+    }
+
+    /// 引用的 SOCKS5 上游名称
+    pub fn socks5_upstreams(&self) -> impl Iterator<Item = &str> {
+        self.rules.iter().filter_map(|rule| match &rule.action {
+            RouteAction::Socks5(name) => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    /// 规则数量
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// 是否没有规则
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_action() {
+        assert_eq!("direct".parse::<RouteAction>().unwrap(), RouteAction::Direct);
+        assert_eq!("REJECT".parse::<RouteAction>().unwrap(), RouteAction::Reject);
+        assert_eq!("socks5".parse::<RouteAction>().unwrap(), RouteAction::Socks5("default".to_string()));
+        assert_eq!("socks5:eu".parse::<RouteAction>().unwrap(), RouteAction::Socks5("eu".to_string()));
+        let backend = "backend:10.0.0.5:8443".parse::<RouteAction>().unwrap();
+        assert_eq!(backend.to_string(), "backend:10.0.0.5:8443");
+        for invalid in ["allow", "socks5:", "backend:10.0.0.5", "direct:x"] {
+            assert!(invalid.parse::<RouteAction>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_ordered_lookup() {
+        let table = RouteTable::from_whitelists(strings(&["*.example.com", "example.org"]), strings(&["video.example.com"]))
+            .with_rules_first(vec![
+                RouteRule::new(RouteAction::Reject).with_domains(strings(&["admin.example.com"])),
+                RouteRule::new("socks5:eu".parse().unwrap())
+                    .with_domains(strings(&["*.example.com"]))
+                    .with_client_ips(strings(&["10.0.0.0/8"])),
+                RouteRule::new("backend:10.0.0.9:443".parse().unwrap()).with_alpn(strings(&["ACME-TLS/1"])),
+            ]);
+        assert_eq!(table.len(), 5);
+
+        let lookup = |domain: &str, ip: &str, alpn: &[&str]| {
+            let alpn = strings(alpn);
+            let query = RouteQuery { domain, client_ip: ip.parse().unwrap(), country: None, alpn: &alpn };
+            table.lookup(&query).map(|rule| rule.action().to_string())
+        };
+        assert_eq!(lookup("admin.example.com", "10.1.1.1", &[]).as_deref(), Some("reject"));
+        assert_eq!(lookup("www.example.com", "10.1.1.1", &["h2"]).as_deref(), Some("socks5:eu"));
+        assert_eq!(lookup("www.example.com", "::ffff:10.1.1.1", &[]).as_deref(), Some("socks5:eu"));
+        assert_eq!(lookup("www.example.com", "192.0.2.1", &["acme-tls/1"]).as_deref(), Some("backend:10.0.0.9:443"));
+        assert_eq!(lookup("video.example.com", "192.0.2.1", &[]).as_deref(), Some("socks5:default"));
+        assert_eq!(lookup("www.example.com", "192.0.2.1", &["h2"]).as_deref(), Some("direct"));
+        assert_eq!(lookup("other.com", "192.0.2.1", &[]), None);
+
+        assert!(table.may_allow("www.example.com"));
+        assert!(!table.may_allow("admin.example.com"));
+        // ALPN 规则不限域名
+        assert!(table.may_allow("other.com"));
+        assert!(!RouteTable::from_whitelists(strings(&["example.com"]), Vec::new()).may_allow("other.com"));
+        assert_eq!(table.socks5_upstreams().collect::<Vec<_>>(), ["eu", "default"]);
+        // 只有直连规则的精确域名需要预取
+        let prefetched = table.clone().with_rules_first(vec![RouteRule::new(RouteAction::Direct).with_domains(strings(&["+Example.org:8443"]))]);
+        assert_eq!(prefetched.direct_domains(), ["example.org"]);
+
+        // 重新生成白名单规则，显式规则不变
+        let reloaded = table.with_whitelists(strings(&["other.com"]), Vec::new());
+        assert_eq!(reloaded.len(), 4);
+        assert_eq!(lookup_in(&reloaded, "other.com").as_deref(), Some("direct"));
+        assert_eq!(lookup_in(&reloaded, "video.example.com"), None);
+        assert_eq!(lookup_in(&reloaded, "admin.example.com").as_deref(), Some("reject"));
+        assert_eq!(reloaded.with_whitelists(Vec::new(), Vec::new()).len(), 3);
+    }
+
+    #[test]
+    fn test_client_country_rule() {
+        let table = RouteTable::from_whitelists(Vec::new(), strings(&["*.example.com"])).with_rules_first(vec![
+            RouteRule::new(RouteAction::Direct).with_client_countries(vec!["US".parse().unwrap(), "CA".parse().unwrap()])
+        ]);
+        let lookup = |country: Option<&str>| {
+            let query = RouteQuery {
+                domain: "www.example.com",
+                client_ip: "192.0.2.1".parse().unwrap(),
+                country: country.map(|c| c.parse().unwrap()),
+                alpn: &[],
+            };
+            table.lookup(&query).map(|rule| rule.action().to_string())
+        };
+        assert_eq!(lookup(Some("US")).as_deref(), Some("direct"));
+        assert_eq!(lookup(Some("DE")).as_deref(), Some("socks5:default"));
+        // 查不到国家时不匹配国家条件
+        assert_eq!(lookup(None).as_deref(), Some("socks5:default"));
+    }
+
+    fn lookup_in(table: &RouteTable, domain: &str) -> Option<String> {
+        let query = RouteQuery { domain, client_ip: "192.0.2.1".parse().unwrap(), country: None, alpn: &[] };
+        table.lookup(&query).map(|rule| rule.action().to_string())
+    }
+}
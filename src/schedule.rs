@@ -0,0 +1,194 @@
+//! 生效时段
+//!
+//! 路由规则可以只在指定的时段生效，例如 `"mon-fri 09:00-18:00"`、`"sat,sun"`、`"22:00-06:00"`：
+//! 星期和时间段都可以省略（省略的部分不限），结束时间早于开始时间表示跨过午夜（按开始的那一天算星期）。
+//! 一个时段列表中任一时段生效即生效，按本地时间判断
+
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, Local, NaiveTime, Timelike};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::{Result, SniProxyError};
+
+const DAY_NAMES: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// 一个时段：星期（位图，周一为第 0 位）和一天中的时间段（分钟，左闭右开）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeWindow {
+    days: u8,
+    start: u32,
+    end: u32,
+}
+
+impl TimeWindow {
+    /// 给定时间是否在时段内
+    pub fn contains(&self, time: DateTime<Local>) -> bool {
+        let minute = time.hour() * 60 + time.minute();
+        let on_day = |time: DateTime<Local>| self.days & (1 << time.weekday().num_days_from_monday()) != 0;
+        if self.start < self.end {
+            on_day(time) && (self.start..self.end).contains(&minute)
+        } else if minute >= self.start {
+            // 跨过午夜：开始那天的晚上，或前一天开始的时段的凌晨部分
+            on_day(time)
+        } else {
+            minute < self.end && on_day(time - ChronoDuration::days(1))
+        }
+    }
+}
+
+fn parse_day(name: &str) -> Option<u32> {
+    DAY_NAMES.iter().position(|day| day.eq_ignore_ascii_case(name)).map(|i| i as u32)
+}
+
+fn parse_days(spec: &str) -> Option<u8> {
+    let mut days = 0u8;
+    for item in spec.split(',') {
+        let (first, last) = match item.split_once('-') {
+            Some((first, last)) => (parse_day(first)?, parse_day(last)?),
+            None => (parse_day(item)?, parse_day(item)?),
+        };
+        // 允许跨周，例如 fri-mon
+        let mut day = first;
+        loop {
+            days |= 1 << day;
+            if day == last {
+                break;
+            }
+            day = (day + 1) % 7;
+        }
+    }
+    Some(days)
+}
+
+fn parse_minute(time: &str) -> Option<u32> {
+    if time == "24:00" {
+        return Some(24 * 60);
+    }
+    let time = NaiveTime::parse_from_str(time, "%H:%M").ok()?;
+    Some(time.hour() * 60 + time.minute())
+}
+
+impl FromStr for TimeWindow {
+    type Err = SniProxyError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || {
+            SniProxyError::InvalidConfig(format!(
+                "无效的时段: {}（格式: [星期] [HH:MM-HH:MM]，例如 \"mon-fri 09:00-18:00\"、\"sat,sun\"）",
+                s
+            ))
+        };
+        let mut window = TimeWindow { days: 0x7f, start: 0, end: 24 * 60 };
+        let mut fields = 0;
+        for field in s.split_whitespace() {
+            fields += 1;
+            if let Some((start, end)) = field.split_once('-').filter(|_| field.contains(':')) {
+                window.start = parse_minute(start).filter(|&m| m < 24 * 60).ok_or_else(invalid)?;
+                window.end = parse_minute(end).ok_or_else(invalid)?;
+                if window.start == window.end {
+                    return Err(invalid());
+                }
+            } else {
+                window.days = parse_days(field).ok_or_else(invalid)?;
+            }
+        }
+        if fields == 0 || fields > 2 {
+            return Err(invalid());
+        }
+        Ok(window)
+    }
+}
+
+impl fmt::Display for TimeWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let days: Vec<&str> = (0..7).filter(|day| self.days & (1 << day) != 0).map(|day| DAY_NAMES[day]).collect();
+        write!(
+            f,
+            "{} {:02}:{:02}-{:02}:{:02}",
+            days.join(","),
+            self.start / 60,
+            self.start % 60,
+            self.end / 60,
+            self.end % 60
+        )
+    }
+}
+
+/// 生效时段列表，任一时段生效即生效
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    windows: Vec<TimeWindow>,
+}
+
+impl Schedule {
+    /// 解析时段列表（不能为空）
+    pub fn parse(windows: &[String]) -> Result<Self> {
+        if windows.is_empty() {
+            return Err(SniProxyError::InvalidConfig("生效时段列表不能为空".to_string()));
+        }
+        let windows = windows.iter().map(|window| window.parse()).collect::<Result<_>>()?;
+        Ok(Self { windows })
+    }
+
+    /// 给定时间是否在任一时段内
+    pub fn is_active_at(&self, time: DateTime<Local>) -> bool {
+        self.windows.iter().any(|window| window.contains(time))
+    }
+
+    /// 当前是否生效
+    ///
+    /// 按系统时间判断而不是 [`crate::clock`] 的不回退时间：时段对应的是真实的本地时间，
+    /// 系统时间被校正回退后应当立即按校正后的时间生效
+    pub fn is_active(&self) -> bool {
+        self.is_active_at(Local::now())
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let windows: Vec<String> = self.windows.iter().map(TimeWindow::to_string).collect();
+        write!(f, "{}", windows.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// 2024-01-01 是周一
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 1, day, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_time_window() {
+        let work: TimeWindow = "Mon-Fri 09:00-18:00".parse().unwrap();
+        assert!(work.contains(at(1, 9, 0)) && work.contains(at(5, 17, 59)));
+        assert!(!work.contains(at(1, 18, 0)) && !work.contains(at(6, 12, 0)));
+        assert_eq!(work.to_string(), "mon,tue,wed,thu,fri 09:00-18:00");
+
+        // 跨过午夜：周五 22:00 开始的时段持续到周六 06:00
+        let night: TimeWindow = "fri 22:00-06:00".parse().unwrap();
+        assert!(night.contains(at(5, 23, 0)) && night.contains(at(6, 5, 59)));
+        assert!(!night.contains(at(5, 5, 0)) && !night.contains(at(6, 22, 0)));
+
+        let weekend: TimeWindow = "sat,sun".parse().unwrap();
+        assert!(weekend.contains(at(7, 0, 0)) && !weekend.contains(at(1, 12, 0)));
+        let wrap: TimeWindow = "sat-mon".parse().unwrap();
+        assert!(wrap.contains(at(1, 8, 0)) && !wrap.contains(at(2, 8, 0)));
+        assert!("18:00-24:00".parse::<TimeWindow>().unwrap().contains(at(3, 23, 59)));
+
+        for invalid in ["", "weekdays", "09:00-09:00", "25:00-26:00", "mon 09:00 18:00", "mon-fri 9-18"] {
+            assert!(invalid.parse::<TimeWindow>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_schedule() {
+        let schedule = Schedule::parse(&["mon-fri 18:00-23:00".to_string(), "sat,sun".to_string()]).unwrap();
+        assert!(schedule.is_active_at(at(2, 20, 0)) && schedule.is_active_at(at(6, 10, 0)));
+        assert!(!schedule.is_active_at(at(2, 10, 0)));
+        assert!(Schedule::parse(&[]).is_err());
+    }
+}
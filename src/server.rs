@@ -1,54 +1,219 @@
-use anyhow::Result;
+use arc_swap::{ArcSwap, ArcSwapOption};
 use futures::FutureExt;
 use log::{debug, error, info, warn};
-use std::net::SocketAddr;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpListener, TcpStream};
-use tokio::time::timeout;
+use tokio::net::{TcpListener, UdpSocket};
 use tokio::sync::watch;
 
-use crate::dns::resolve_host_cached;
+use crate::accept_limit::{AcceptLimiter, AcceptRateConfig};
+use crate::acl::{Acl, AclRule};
+use crate::admin::{run_admin_server, AdminConfig, AdminState};
+use crate::backend::BackendAddr;
+use crate::ban::{BanConfig, BanTable};
+use crate::buffer_pool::BufferPool;
+use crate::burst::{BurstConfig, BurstLimiter};
+use crate::connection::{adaptive_hello_buffer_size, ConnectionContext, ConnectionHandler};
+use crate::dns::{run_dns_prefetch, DnsOptions, DnsPrefetchConfig, DnsRoute, DnsUpstream};
 use crate::domain::DomainMatcher;
+use crate::error::{Result, SniProxyError};
+use crate::events::{EventBus, ProxyEvent};
+use crate::fingerprint::FingerprintFilter;
+use crate::geoip::{run_geoip_update, GeoFilter, GeoIp, GeoIpUpdate, SharedGeoIp};
+use crate::handshake::{HandshakeConfig, HandshakeLimit, HandshakePermit};
+use crate::hello_corpus::HelloRecorder;
+use crate::http::PlaintextHttpAction;
+use crate::influx::{run_influx_exporter, InfluxConfig, InfluxTarget};
 use crate::domain_ip_tracker::DomainIpTracker;
-use crate::ip_matcher::IpMatcher;
+use crate::ip_matcher::{IpMatcher, SharedIpMatcher};
+use crate::ip_sni::IpSniPolicy;
 use crate::ip_traffic::IpTrafficTracker;
-use crate::metrics::{ConnectionGuard, Metrics};
-use crate::proxy::proxy_data;
-use crate::socks5::{connect_via_socks5, Socks5Config};
-use crate::tls::parse_sni;
+#[cfg(unix)]
+use crate::listener::bind_unix_listener;
+use crate::listener::{Accept, ClientStream, ListenAddr, ListenerProtocol, ListenerSpec};
+use crate::metrics::{ConnectionGuard, ListenerLabel, MetricLabels, Metrics, RouteLabel};
+use crate::outbound::OutboundOptions;
+use crate::platform::{KernelFeature, PlatformInfo};
+use crate::port_map::PortMapping;
+use crate::preview::BodyPreview;
+use crate::profile::MemoryProfile;
+use crate::proxy::DEFAULT_RELAY_BUFFER_SIZE;
+use crate::redirect::{run_redirect_server, RedirectWhitelist};
+use crate::rejection::RejectionMode;
+use crate::remote_whitelist::{run_remote_whitelist_refresh, RemoteRefresher, RemoteWhitelists};
+use crate::route_table::{RouteRule, RouteTable, SharedRouteTable, Socks5Upstreams, DEFAULT_SOCKS5_UPSTREAM};
+use crate::report::{run_daily_report, ReportConfig};
+use crate::retry::ConnectRetryConfig;
+use crate::sni_map::{PinnedIps, SniBackendMap};
+use crate::socks5::Socks5Config;
+use crate::throttle::ClientRateLimits;
+use crate::timeouts::TimeoutConfig;
+use crate::tls::NoSniAction;
+use crate::transparent::TransparentMode;
+use crate::tuning::{TcpTuning, TuningPolicy};
+use crate::udp_relay::{run_quic_relay, QuicRelayContext};
+use crate::whitelist_file::{run_whitelist_watcher, ListKind, WhitelistFiles, WhitelistReloader};
+
+/// 关闭时等待活跃连接结束的最长时间
+const SHUTDOWN_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// QUIC（HTTP/3）UDP 监听器
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct QuicListener {
+    addr: SocketAddr,
+    /// 目标端口（None 表示按端口映射）
+    target_port: Option<u16>,
+}
 
 /// SNI 代理服务器
 pub struct SniProxy {
-    /// 监听地址
-    listen_addr: SocketAddr,
-    /// 直连白名单域名匹配器
-    direct_matcher: Arc<DomainMatcher>,
-    /// SOCKS5 白名单域名匹配器
-    socks5_matcher: Option<Arc<DomainMatcher>>,
+    /// 监听地址（TCP 地址或 Unix socket 路径）
+    listen_addr: ListenAddr,
+    /// 额外监听地址（例如同时代理 993 等非 443 端口的 TLS 服务）
+    extra_listen_addrs: Vec<SocketAddr>,
+    /// 目标端口映射（按监听端口决定目标端口，默认 443）
+    port_mapping: Arc<PortMapping>,
+    /// IPv6 监听时是否同时接受 IPv4 连接（IPV6_V6ONLY = false）
+    dual_stack: bool,
+    /// 路由表（显式配置的路由规则在前，之后是由 SOCKS5 白名单和直连白名单生成的规则）
+    routes: SharedRouteTable,
     /// IP 白名单匹配器（可选）
-    ip_matcher: Option<Arc<IpMatcher>>,
+    ip_matcher: SharedIpMatcher,
+    /// 直连白名单、SOCKS5 白名单和 IP 白名单（配置的规则和运行中添加的规则，白名单文件和远程白名单中的规则追加在后面），
+    /// 修改后重建路由表和 IP 白名单
+    whitelists: Arc<Mutex<WhitelistReloader>>,
+    /// 白名单文件（运行中修改后自动重新加载）
+    whitelist_files: WhitelistFiles,
+    /// 远程白名单（定期刷新）
+    remote_whitelists: RemoteWhitelists,
     /// 最大并发连接数
     max_connections: usize,
-    /// SOCKS5 代理配置（可选）
-    socks5_config: Option<Arc<Socks5Config>>,
+    /// SOCKS5 上游（默认上游的名称为 `default`）
+    socks5_upstreams: Arc<Socks5Upstreams>,
     /// 性能监控指标
     metrics: Metrics,
     /// IP 流量追踪器
     ip_traffic_tracker: IpTrafficTracker,
     /// 域名-IP 追踪器
     domain_ip_tracker: DomainIpTracker,
+    /// 管理接口配置（可选）
+    admin_config: Option<AdminConfig>,
+    /// TCP 调优参数（默认参数，以及按监听端口、路由单独配置的参数）
+    tcp_tuning: Arc<TuningPolicy>,
+    /// acceptor 数量（每个 acceptor 一个 SO_REUSEPORT 监听 socket）
+    acceptors: usize,
+    /// 是否启用 SO_INCOMING_CPU 连接分流（仅 Linux）
+    incoming_cpu_steering: bool,
+    /// 透明代理模式（可选）
+    transparent_mode: Option<TransparentMode>,
+    /// 非 TLS 连接是否按 HTTP Host 头路由（转发到 80 端口）
+    http_sniffing: bool,
+    /// HTTP → HTTPS 重定向监听地址
+    http_redirect_addrs: Vec<SocketAddr>,
+    /// 运行时事件通道
+    events: EventBus,
+    /// 读取 Client Hello 的缓冲区大小（None 表示按 CPU 核心数自适应）
+    hello_buffer_size: Option<usize>,
+    /// 每个转发方向的缓冲区大小
+    relay_buffer_size: usize,
+    /// 显式配置的超时（覆盖按 CPU 核心数自适应的值）
+    timeouts: TimeoutConfig,
+    /// 上游连接重试（None 表示不重试）
+    connect_retry: Option<ConnectRetryConfig>,
+    direct_fallback_to_socks5: bool,
+    socks5_fallback_to_direct: bool,
+    /// 转发空闲超时（None 表示不限制）
+    idle_timeout: Option<Duration>,
+    /// 最长连接时间（None 表示不限制）
+    max_connection_lifetime: Option<Duration>,
+    splice_relay: bool,
+    /// 每个连接结束时输出访问记录
+    access_log: bool,
+    /// io_uring 转发线程数（None 表示使用 epoll 路径）
+    io_uring_workers: Option<usize>,
+    /// DNS 缓存容量（None 表示按 CPU 核心数自适应）
+    dns_cache_capacity: Option<usize>,
+    /// DNS 解析策略（超时、缓存有效期、过期结果）
+    dns_options: DnsOptions,
+    /// 自定义 DNS 上游（None 表示使用系统解析器）
+    dns_upstream: Option<DnsUpstream>,
+    /// 按域名选择 DNS 上游的规则
+    dns_routes: Vec<DnsRoute>,
+    /// DNS 静态解析（域名 → 地址）
+    dns_hosts: HashMap<String, Vec<IpAddr>>,
+    /// DNS 预取（None 表示不预取）
+    dns_prefetch: Option<DnsPrefetchConfig>,
+    /// 直连时发送 PROXY protocol v2 头部的域名匹配器（可选）
+    outbound: Arc<OutboundOptions>,
+    proxy_protocol_matcher: Option<Arc<DomainMatcher>>,
+    /// QUIC（HTTP/3）UDP 监听器
+    quic_listeners: Vec<QuicListener>,
+    /// Client Hello 采集器（可选）
+    hello_recorder: Option<HelloRecorder>,
+    /// 数据预览规则（可选，诊断用）
+    body_preview: Option<Arc<BodyPreview>>,
+    /// TLS 端口收到明文 HTTP 请求时的处理方式
+    plaintext_http: PlaintextHttpAction,
+    /// TLS 指纹过滤规则（可选）
+    fingerprint_filter: Option<Arc<FingerprintFilter>>,
+    /// IP、域名或指纹被拒绝时断开连接的方式
+    rejection_mode: RejectionMode,
+    /// Client Hello 没有 SNI 时的处理方式
+    no_sni_action: NoSniAction,
+    /// 不在任何白名单中的域名转发到的默认后端（可选）
+    default_backend: Option<Arc<BackendAddr>>,
+    /// SNI → 后端映射表（可选）
+    sni_backends: Option<Arc<SniBackendMap>>,
+    /// 域名 → 固定 IP（可选）
+    pinned_ips: Option<Arc<PinnedIps>>,
+    /// SNI 为 IP 地址时的处理方式（按监听端口）
+    ip_sni_policy: Arc<IpSniPolicy>,
+    /// SNI 为 IP 地址时允许的目标 IP / CIDR（可选）
+    ip_sni_matcher: Option<Arc<IpMatcher>>,
+    /// 重复连接退避（可选）
+    burst_limiter: Option<Arc<BurstLimiter>>,
+    /// 自动封禁（可选）
+    ban_table: Option<Arc<BanTable>>,
+    /// 新连接速率限制（可选）
+    accept_limiter: Option<Arc<AcceptLimiter>>,
+    /// 握手阶段保护（可选）
+    handshake_limit: Option<Arc<HandshakeLimit>>,
+    /// 按客户端 IP 的带宽限制（可选）
+    client_rate_limits: Option<Arc<ClientRateLimits>>,
+    /// 按客户端 IP 和域名组合检查的 ACL（可选）
+    acl: Option<Arc<Acl>>,
+    /// 域名黑名单（可选）
+    blacklist: Option<Arc<DomainMatcher>>,
+    /// 客户端 IP 黑名单（可选）
+    ip_blacklist: Option<Arc<IpMatcher>>,
+    /// GeoIP 数据库（可选）
+    geoip: Option<SharedGeoIp>,
+    /// GeoIP 数据库自动更新配置（可选）
+    geoip_update: Option<GeoIpUpdate>,
+    /// 按客户端所在国家允许或拒绝（可选）
+    geo_filter: Option<Arc<GeoFilter>>,
+    /// 每日汇总报告配置（可选）
+    report_config: Option<ReportConfig>,
+    /// InfluxDB 行协议导出配置（可选）
+    influx_config: Option<InfluxConfig>,
 }
 
 impl SniProxy {
     /// 创建新的 SNI 代理实例（仅直连白名单）
-    pub fn new(listen_addr: SocketAddr, direct_whitelist: Vec<String>) -> Self {
-        let direct_matcher = DomainMatcher::new(direct_whitelist);
+    pub fn new(listen_addr: impl Into<ListenAddr>, direct_whitelist: Vec<String>) -> Self {
+        let routes: SharedRouteTable = Arc::new(ArcSwap::from_pointee(RouteTable::from_whitelists(direct_whitelist.clone(), Vec::new())));
+        let ip_matcher: SharedIpMatcher = Arc::new(ArcSwapOption::empty());
+        let whitelists = WhitelistReloader::new(
+            [direct_whitelist, Vec::new(), Vec::new()],
+            Arc::clone(&routes),
+            Arc::clone(&ip_matcher),
+        );
 
         // 🚀 自适应最大连接数：根据 CPU 核心数动态调整
         // 经验值：每核心支持 500-1000 个并发连接
-        let num_cpus = num_cpus::get();
+        let num_cpus = crate::platform::available_cpus();
         let max_connections = if num_cpus <= 2 {
             // 小型服务器（1-2核）：500-1000 连接
             num_cpus * 500
@@ -61,61 +226,209 @@ impl SniProxy {
         };
 
         Self {
-            listen_addr,
-            direct_matcher: Arc::new(direct_matcher),
-            socks5_matcher: None,
-            ip_matcher: None,
+            listen_addr: listen_addr.into(),
+            extra_listen_addrs: Vec::new(),
+            port_mapping: Arc::new(PortMapping::default()),
+            dual_stack: false,
+            routes,
+            ip_matcher,
+            whitelists: Arc::new(Mutex::new(whitelists)),
+            whitelist_files: WhitelistFiles::default(),
+            remote_whitelists: RemoteWhitelists::default(),
             max_connections, // 自适应最大并发连接数
-            socks5_config: None,
+            socks5_upstreams: Arc::new(Socks5Upstreams::new()),
             metrics: Metrics::new(),
             ip_traffic_tracker: IpTrafficTracker::disabled(), // 默认禁用
             domain_ip_tracker: DomainIpTracker::disabled(), // 默认禁用
+            admin_config: None,
+            tcp_tuning: Arc::new(TuningPolicy::default()),
+            acceptors: 1,
+            incoming_cpu_steering: false,
+            transparent_mode: None,
+            http_sniffing: false,
+            http_redirect_addrs: Vec::new(),
+            events: EventBus::default(),
+            hello_buffer_size: None,
+            relay_buffer_size: DEFAULT_RELAY_BUFFER_SIZE,
+            timeouts: TimeoutConfig::default(),
+            connect_retry: None,
+            direct_fallback_to_socks5: false,
+            socks5_fallback_to_direct: false,
+            idle_timeout: None,
+            max_connection_lifetime: None,
+            splice_relay: false,
+            access_log: true,
+            io_uring_workers: None,
+            dns_cache_capacity: None,
+            dns_options: DnsOptions::default(),
+            dns_upstream: None,
+            dns_routes: Vec::new(),
+            dns_hosts: HashMap::new(),
+            dns_prefetch: None,
+            outbound: Arc::new(OutboundOptions::default()),
+            proxy_protocol_matcher: None,
+            quic_listeners: Vec::new(),
+            hello_recorder: None,
+            body_preview: None,
+            plaintext_http: PlaintextHttpAction::Close,
+            fingerprint_filter: None,
+            rejection_mode: RejectionMode::Close,
+            no_sni_action: NoSniAction::Reject,
+            default_backend: None,
+            sni_backends: None,
+            pinned_ips: None,
+            ip_sni_policy: Arc::new(IpSniPolicy::default()),
+            ip_sni_matcher: None,
+            burst_limiter: None,
+            ban_table: None,
+            accept_limiter: None,
+            handshake_limit: None,
+            client_rate_limits: None,
+            acl: None,
+            blacklist: None,
+            ip_blacklist: None,
+            geoip: None,
+            geoip_update: None,
+            geo_filter: None,
+            report_config: None,
+            influx_config: None,
         }
     }
 
     /// 创建新的 SNI 代理实例（同时支持直连和 SOCKS5 白名单）
     pub fn new_with_dual_whitelist(
-        listen_addr: SocketAddr,
+        listen_addr: impl Into<ListenAddr>,
         direct_whitelist: Vec<String>,
         socks5_whitelist: Vec<String>,
     ) -> Self {
-        let direct_matcher = DomainMatcher::new(direct_whitelist);
-        let socks5_matcher = if socks5_whitelist.is_empty() {
-            None
-        } else {
-            Some(Arc::new(DomainMatcher::new(socks5_whitelist)))
-        };
+        let routes: SharedRouteTable =
+            Arc::new(ArcSwap::from_pointee(RouteTable::from_whitelists(direct_whitelist.clone(), socks5_whitelist.clone())));
+        let ip_matcher: SharedIpMatcher = Arc::new(ArcSwapOption::empty());
+        let whitelists = WhitelistReloader::new(
+            [direct_whitelist, socks5_whitelist, Vec::new()],
+            Arc::clone(&routes),
+            Arc::clone(&ip_matcher),
+        );
 
         // 🚀 自适应最大连接数：根据 CPU 核心数动态调整
-        let num_cpus = num_cpus::get();
-        let max_connections = if num_cpus <= 2 {
-            num_cpus * 500
-        } else if num_cpus <= 8 {
+        let num_cpus = crate::platform::available_cpus();
+        let max_connections = if num_cpus <= 8 {
             num_cpus * 500
         } else {
             std::cmp::min(10000, num_cpus * 500)
         };
 
         Self {
-            listen_addr,
-            direct_matcher: Arc::new(direct_matcher),
-            socks5_matcher,
-            ip_matcher: None,
+            listen_addr: listen_addr.into(),
+            extra_listen_addrs: Vec::new(),
+            port_mapping: Arc::new(PortMapping::default()),
+            dual_stack: false,
+            routes,
+            ip_matcher,
+            whitelists: Arc::new(Mutex::new(whitelists)),
+            whitelist_files: WhitelistFiles::default(),
+            remote_whitelists: RemoteWhitelists::default(),
             max_connections, // 自适应最大并发连接数
-            socks5_config: None,
+            socks5_upstreams: Arc::new(Socks5Upstreams::new()),
             metrics: Metrics::new(),
             ip_traffic_tracker: IpTrafficTracker::disabled(), // 默认禁用
             domain_ip_tracker: DomainIpTracker::disabled(), // 默认禁用
+            admin_config: None,
+            tcp_tuning: Arc::new(TuningPolicy::default()),
+            acceptors: 1,
+            incoming_cpu_steering: false,
+            transparent_mode: None,
+            http_sniffing: false,
+            http_redirect_addrs: Vec::new(),
+            events: EventBus::default(),
+            hello_buffer_size: None,
+            relay_buffer_size: DEFAULT_RELAY_BUFFER_SIZE,
+            timeouts: TimeoutConfig::default(),
+            connect_retry: None,
+            direct_fallback_to_socks5: false,
+            socks5_fallback_to_direct: false,
+            idle_timeout: None,
+            max_connection_lifetime: None,
+            splice_relay: false,
+            access_log: true,
+            io_uring_workers: None,
+            dns_cache_capacity: None,
+            dns_options: DnsOptions::default(),
+            dns_upstream: None,
+            dns_routes: Vec::new(),
+            dns_hosts: HashMap::new(),
+            dns_prefetch: None,
+            outbound: Arc::new(OutboundOptions::default()),
+            proxy_protocol_matcher: None,
+            quic_listeners: Vec::new(),
+            hello_recorder: None,
+            body_preview: None,
+            plaintext_http: PlaintextHttpAction::Close,
+            fingerprint_filter: None,
+            rejection_mode: RejectionMode::Close,
+            no_sni_action: NoSniAction::Reject,
+            default_backend: None,
+            sni_backends: None,
+            pinned_ips: None,
+            ip_sni_policy: Arc::new(IpSniPolicy::default()),
+            ip_sni_matcher: None,
+            burst_limiter: None,
+            ban_table: None,
+            accept_limiter: None,
+            handshake_limit: None,
+            client_rate_limits: None,
+            acl: None,
+            blacklist: None,
+            ip_blacklist: None,
+            geoip: None,
+            geoip_update: None,
+            geo_filter: None,
+            report_config: None,
+            influx_config: None,
         }
     }
 
+    /// 设置双栈监听（仅对 IPv6 监听地址生效）
+    ///
+    /// 启用后，监听 `[::]:port` 时同时接受 IPv4 客户端（以 IPv4 映射地址呈现）；
+    /// 关闭时 IPv6 监听 socket 只接受 IPv6 连接
+    pub fn with_dual_stack(mut self, dual_stack: bool) -> Self {
+        self.dual_stack = dual_stack;
+        self
+    }
+
+    /// 添加额外监听地址（所有监听地址共享白名单和连接限制）
+    pub fn with_extra_listeners(mut self, listen_addrs: Vec<SocketAddr>) -> Self {
+        self.extra_listen_addrs = listen_addrs;
+        self
+    }
+
+    /// 设置目标端口映射（按监听端口决定目标服务器端口）
+    pub fn with_port_mapping(mut self, port_mapping: PortMapping) -> Self {
+        self.port_mapping = Arc::new(port_mapping);
+        self
+    }
+
     /// 设置 IP 白名单
-    pub fn with_ip_whitelist(mut self, ip_whitelist: Vec<String>) -> Self {
-        let ip_matcher = IpMatcher::new(ip_whitelist);
+    pub fn with_ip_whitelist(self, ip_whitelist: Vec<String>) -> Self {
+        let ip_matcher = IpMatcher::new(ip_whitelist.clone());
         // 只有在 IP 白名单不为空时才设置
         if !ip_matcher.is_empty() {
-            self.ip_matcher = Some(Arc::new(ip_matcher));
+            self.ip_matcher.store(Some(Arc::new(ip_matcher)));
         }
+        self.lock_whitelists().set_inline(ListKind::Ip, ip_whitelist);
+        self
+    }
+
+    /// 设置白名单文件（规则追加在配置的白名单之后，运行中修改后自动重新加载）
+    pub fn with_whitelist_files(mut self, files: WhitelistFiles) -> Self {
+        self.whitelist_files = files;
+        self
+    }
+
+    /// 设置远程白名单（规则追加在配置的白名单和白名单文件之后，定期刷新）
+    pub fn with_remote_whitelists(mut self, remote: RemoteWhitelists) -> Self {
+        self.remote_whitelists = remote;
         self
     }
 
@@ -125,9 +438,40 @@ impl SniProxy {
         self
     }
 
-    /// 设置 SOCKS5 代理配置
-    pub fn with_socks5(mut self, socks5_config: Socks5Config) -> Self {
-        self.socks5_config = Some(Arc::new(socks5_config));
+    /// 设置出站选项（源地址等），对所有直连和 SOCKS5 连接生效，路由规则中的选项覆盖这里的
+    pub fn with_outbound(mut self, outbound: OutboundOptions) -> Self {
+        self.outbound = Arc::new(outbound);
+        self
+    }
+
+    /// 设置需要发送 PROXY protocol v2 头部的域名
+    ///
+    /// 直连这些域名时，在 Client Hello 之前先发送 PROXY protocol v2 头部，
+    /// 下游（我们自己控制的）服务器据此获取客户端真实 IP；SOCKS5 出口不发送
+    pub fn with_proxy_protocol(mut self, domains: Vec<String>) -> Self {
+        self.proxy_protocol_matcher = if domains.is_empty() {
+            None
+        } else {
+            Some(Arc::new(DomainMatcher::new(domains)))
+        };
+        self
+    }
+
+    /// 设置 SOCKS5 代理配置（默认上游，SOCKS5 白名单和 `socks5` 路由动作使用）
+    pub fn with_socks5(self, socks5_config: Socks5Config) -> Self {
+        self.with_named_socks5(DEFAULT_SOCKS5_UPSTREAM, socks5_config)
+    }
+
+    /// 添加命名的 SOCKS5 上游（`socks5:<名称>` 路由动作使用）
+    pub fn with_named_socks5(mut self, name: impl Into<String>, socks5_config: Socks5Config) -> Self {
+        Arc::make_mut(&mut self.socks5_upstreams).insert(name.into(), Arc::new(socks5_config));
+        self
+    }
+
+    /// 设置路由规则（按顺序匹配，排在由白名单生成的规则之前）
+    pub fn with_routes(self, rules: Vec<RouteRule>) -> Self {
+        let routes = (**self.routes.load()).clone().with_rules_first(rules);
+        self.routes.store(Arc::new(routes));
         self
     }
 
@@ -156,11 +500,567 @@ impl SniProxy {
         self
     }
 
+    /// 设置默认 TCP 调优参数（backlog、socket 缓冲区、TCP_NODELAY、keepalive、TCP Fast Open）
+    pub fn with_tcp_tuning(mut self, tcp_tuning: TcpTuning) -> Self {
+        self.tcp_tuning = Arc::new((*self.tcp_tuning).clone().with_default(tcp_tuning));
+        self
+    }
+
+    /// 为某个路由的上游连接单独设置 TCP 调优参数
+    pub fn with_route_tuning(mut self, route: RouteLabel, tcp_tuning: TcpTuning) -> Self {
+        self.tcp_tuning = Arc::new((*self.tcp_tuning).clone().with_route(route, tcp_tuning));
+        self
+    }
+
+    /// 应用内存配置预设
+    ///
+    /// 会覆盖 TCP 调优参数、缓冲区大小、DNS 缓存容量和最大并发连接数，
+    /// 之后再调用的单项设置优先生效
+    pub fn with_memory_profile(mut self, profile: MemoryProfile) -> Self {
+        self.tcp_tuning = Arc::new((*self.tcp_tuning).clone().with_default(profile.tcp_tuning()));
+        self.hello_buffer_size = profile.hello_buffer_size();
+        self.relay_buffer_size = profile.relay_buffer_size();
+        self.dns_cache_capacity = profile.dns_cache_capacity();
+        if let Some(max_connections) = profile.max_connections() {
+            self.max_connections = max_connections;
+        }
+        self
+    }
+
+    /// 设置读取 Client Hello 的缓冲区大小
+    pub fn with_hello_buffer_size(mut self, size: usize) -> Self {
+        self.hello_buffer_size = Some(size.max(1));
+        self
+    }
+
+    /// 设置每个转发方向的缓冲区大小（每个连接占用两份）
+    pub fn with_relay_buffer_size(mut self, size: usize) -> Self {
+        self.relay_buffer_size = size.max(1);
+        self
+    }
+
+    /// 显式设置超时，覆盖按 CPU 核心数自适应的值（没有配置的项保持默认，`idle` 等同于 [`Self::with_idle_timeout`]）
+    pub fn with_timeouts(mut self, timeouts: TimeoutConfig) -> Self {
+        if let Some(idle) = timeouts.idle {
+            self.idle_timeout = Some(idle);
+        }
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// 上游连接（直连和 SOCKS5）遇到临时错误（拒绝、重置、超时）时按指数退避重试
+    pub fn with_connect_retry(mut self, config: ConnectRetryConfig) -> Self {
+        self.connect_retry = Some(config);
+        self
+    }
+
+    /// 直连白名单中的域名直连失败（DNS 解析失败、连接超时或被重置）时改用默认 SOCKS5 上游重新连接
+    ///
+    /// 需要配置 SOCKS5 上游（[`Self::with_socks5`]），否则不生效
+    pub fn with_direct_fallback_to_socks5(mut self, enabled: bool) -> Self {
+        self.direct_fallback_to_socks5 = enabled;
+        self
+    }
+
+    /// 通过 SOCKS5 连接失败（代理服务器无法连接或返回错误）时改用直连重新连接，
+    /// 避免代理服务器故障导致所有走 SOCKS5 的域名都无法访问
+    pub fn with_socks5_fallback_to_direct(mut self, enabled: bool) -> Self {
+        self.socks5_fallback_to_direct = enabled;
+        self
+    }
+
+    /// 条件允许时使用 splice(2) 转发（仅 Linux）：数据在内核中经管道移动，不复制到用户态缓冲区，
+    /// 大流量连接的 CPU 占用更低；限速和正文预览的连接仍使用用户态转发
+    pub fn with_splice_relay(mut self, enabled: bool) -> Self {
+        self.splice_relay = enabled;
+        self
+    }
+
+    /// 每个连接（TCP 和 QUIC）结束时输出一条 info 级别的访问记录（默认开启）
+    pub fn with_access_log(mut self, enabled: bool) -> Self {
+        self.access_log = enabled;
+        self
+    }
+
+    /// 用 io_uring 接受连接和转发数据（实验性，需要 `io-uring` 功能编译，仅 Linux 5.11+），
+    /// `workers` 为转发线程数；io_uring 不可用时使用默认的 epoll 路径
+    pub fn with_io_uring(mut self, workers: usize) -> Self {
+        self.io_uring_workers = Some(workers.max(1));
+        self
+    }
+
+    /// 设置转发空闲超时：两个方向超过该时长都没有数据时断开连接（TCP）
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// 设置最长连接时间：转发超过该时长的连接一律断开（TCP），避免遗弃的连接长期累积
+    pub fn with_max_connection_lifetime(mut self, lifetime: Duration) -> Self {
+        self.max_connection_lifetime = Some(lifetime);
+        self
+    }
+
+    /// 设置 DNS 缓存容量（全局缓存，在服务器启动时生效）
+    pub fn with_dns_cache_capacity(mut self, capacity: usize) -> Self {
+        self.dns_cache_capacity = Some(capacity.max(1));
+        self
+    }
+
+    /// 设置 DNS 解析策略（全局，在服务器启动时生效）
+    pub fn with_dns_options(mut self, options: DnsOptions) -> Self {
+        self.dns_options = options;
+        self
+    }
+
+    /// 设置自定义 DNS 上游（全局，在服务器启动时生效），不再使用系统解析器和 /etc/resolv.conf
+    pub fn with_dns_upstream(mut self, upstream: DnsUpstream) -> Self {
+        self.dns_upstream = Some(upstream);
+        self
+    }
+
+    /// 设置按域名选择 DNS 上游的规则（全局，在服务器启动时生效），后缀最长的规则优先，没有匹配的域名使用默认上游
+    pub fn with_dns_routes(mut self, routes: Vec<DnsRoute>) -> Self {
+        self.dns_routes = routes;
+        self
+    }
+
+    /// 启用 DNS 预取：启动时和之后每个周期在后台解析直连白名单中的精确域名
+    pub fn with_dns_prefetch(mut self, config: DnsPrefetchConfig) -> Self {
+        self.dns_prefetch = Some(config);
+        self
+    }
+
+    /// 设置 DNS 静态解析（全局，在服务器启动时生效），这些域名不查询 DNS
+    pub fn with_dns_hosts(mut self, hosts: HashMap<String, Vec<IpAddr>>) -> Self {
+        self.dns_hosts = hosts;
+        self
+    }
+
+    /// 设置 acceptor 数量（每个 acceptor 独立监听 socket，由内核通过 SO_REUSEPORT 分配连接）
+    pub fn with_acceptors(mut self, acceptors: usize) -> Self {
+        self.acceptors = acceptors.max(1);
+        self
+    }
+
+    /// 启用 SO_INCOMING_CPU 连接分流（仅 Linux）
+    ///
+    /// 每个 acceptor 运行在绑定到一个 CPU 的独立线程上（按进程可以调度到的 CPU 依次分配），
+    /// 内核把在该 CPU 上收到的连接交给它处理。通常与 `with_acceptors(可以调度到的 CPU 数)` 一起使用
+    pub fn with_incoming_cpu_steering(mut self, enabled: bool) -> Self {
+        self.incoming_cpu_steering = enabled;
+        self
+    }
+
+    /// 启用透明代理模式（作为 iptables/nftables REDIRECT 或 TPROXY 的目标）
+    ///
+    /// 仍然解析 SNI 做白名单判断，但直连时连接原始目标 IP:端口，不再重新解析 DNS
+    pub fn with_transparent_mode(mut self, mode: TransparentMode) -> Self {
+        self.transparent_mode = Some(mode);
+        self
+    }
+
+    /// 启用明文 HTTP 嗅探
+    ///
+    /// 开启后，首个数据包不是 TLS 记录时按 HTTP `Host` 头做白名单判断，
+    /// 并转发到目标的 80 端口；关闭时非 TLS 连接按 SNI 解析失败处理
+    pub fn with_http_sniffing(mut self, enabled: bool) -> Self {
+        self.http_sniffing = enabled;
+        self
+    }
+
+    /// 设置 TLS 端口收到明文 HTTP 请求时的处理方式（开启 HTTP 嗅探时不生效）
+    ///
+    /// 默认直接关闭连接；这类连接单独统计，不计入 SNI 解析错误
+    pub fn with_plaintext_http_response(mut self, action: PlaintextHttpAction) -> Self {
+        self.plaintext_http = action;
+        self
+    }
+
+    /// 启用 HTTP → HTTPS 重定向监听
+    ///
+    /// 在 `listen_addr`（通常为 80 端口）上对白名单域名返回 301 重定向到 https，
+    /// 代理主机无需再额外部署 Web 服务器。可以多次调用监听多个地址
+    pub fn with_http_redirect(mut self, listen_addr: SocketAddr) -> Self {
+        if !self.http_redirect_addrs.contains(&listen_addr) {
+            self.http_redirect_addrs.push(listen_addr);
+        }
+        self
+    }
+
+    /// 启用 QUIC（HTTP/3）监听
+    ///
+    /// 在 UDP `listen_addr` 上解密客户端 Initial 包解析 SNI，按白名单直连或通过 SOCKS5 UDP 关联转发，
+    /// 目标端口与 TCP 一样按端口映射决定。仅支持 QUIC v1，可以多次调用监听多个地址
+    pub fn with_quic_listener(self, listen_addr: SocketAddr) -> Self {
+        self.add_quic_listener(listen_addr, None)
+    }
+
+    /// 添加 QUIC 监听器，同一地址重复添加时只更新目标端口
+    fn add_quic_listener(mut self, addr: SocketAddr, target_port: Option<u16>) -> Self {
+        match self.quic_listeners.iter_mut().find(|listener| listener.addr == addr) {
+            Some(listener) => listener.target_port = target_port.or(listener.target_port),
+            None => self.quic_listeners.push(QuicListener { addr, target_port }),
+        }
+        self
+    }
+
+    /// 按入站协议添加监听器
+    ///
+    /// - `tls_sni`：与主监听地址相同时只应用规则，否则作为额外监听地址（Unix socket 只能作为主监听地址）
+    /// - `http`：HTTP → HTTPS 重定向监听
+    /// - `quic`：QUIC（HTTP/3）UDP 监听
+    /// - `socks5`：尚未支持
+    ///
+    /// 每个监听器单独生效，同一协议可以添加多个。监听器规则中的目标端口适用于 TCP 的 `tls_sni`
+    /// 和 `quic` 监听器，IP SNI 处理方式和 TCP 调优只适用于 `tls_sni` 监听器；
+    /// 应在端口映射和 IP SNI 处理方式设置之后调用，否则会被覆盖
+    pub fn with_listener(mut self, spec: ListenerSpec) -> Result<Self> {
+        let invalid = |msg: &str| Err(SniProxyError::InvalidConfig(format!("监听器 {} ({}): {}", spec.addr, spec.protocol, msg)));
+        match spec.protocol {
+            ListenerProtocol::TlsSni => {}
+            ListenerProtocol::Quic if spec.ip_sni_action.is_some() || spec.tuning.is_some() => {
+                return invalid("ip_sni_action 和 tuning 仅适用于 tls_sni 监听器");
+            }
+            ListenerProtocol::Quic => {}
+            _ if spec.has_rules() => return invalid("target_port、ip_sni_action 和 tuning 仅适用于 tls_sni 监听器"),
+            _ => {}
+        }
+
+        match (spec.protocol, &spec.addr) {
+            (ListenerProtocol::TlsSni, ListenAddr::Unix(_)) => {
+                if spec.addr != self.listen_addr {
+                    return invalid("Unix socket 只能作为主监听地址");
+                }
+                if spec.has_rules() {
+                    return invalid("Unix socket 监听器不支持监听器规则");
+                }
+            }
+            (ListenerProtocol::TlsSni, &ListenAddr::Tcp(addr)) => {
+                if spec.addr != self.listen_addr && !self.extra_listen_addrs.contains(&addr) {
+                    self.extra_listen_addrs.push(addr);
+                }
+                if let Some(target_port) = spec.target_port {
+                    let mapping = (*self.port_mapping).clone().with_mapping(addr.port(), target_port);
+                    self.port_mapping = Arc::new(mapping);
+                }
+                if let Some(action) = spec.ip_sni_action {
+                    let policy = (*self.ip_sni_policy).clone().with_listener(addr.port(), action);
+                    self.ip_sni_policy = Arc::new(policy);
+                }
+                if let Some(tuning) = spec.tuning {
+                    let policy = (*self.tcp_tuning).clone().with_listener(addr.port(), tuning);
+                    self.tcp_tuning = Arc::new(policy);
+                }
+            }
+            (ListenerProtocol::Http, &ListenAddr::Tcp(addr)) => {
+                if spec.addr == self.listen_addr || self.extra_listen_addrs.contains(&addr) {
+                    return invalid("不能与 tls_sni 监听地址相同");
+                }
+                self = self.with_http_redirect(addr);
+            }
+            (ListenerProtocol::Quic, &ListenAddr::Tcp(addr)) => {
+                self = self.add_quic_listener(addr, spec.target_port);
+            }
+            (ListenerProtocol::Socks5, _) => return invalid("暂不支持 socks5 入站协议"),
+            (_, ListenAddr::Unix(_)) => return invalid("该协议不支持 Unix socket"),
+        }
+        Ok(self)
+    }
+
+    /// 启用 Client Hello 采集（脱敏后写入语料文件，用于解析器回归测试）
+    pub fn with_hello_capture(mut self, recorder: HelloRecorder) -> Self {
+        self.hello_recorder = Some(recorder);
+        self
+    }
+
+    /// 启用数据预览（匹配规则的连接记录客户端和上游最先发送的数据，诊断协议不匹配）
+    pub fn with_body_preview(mut self, preview: BodyPreview) -> Self {
+        self.body_preview = Some(Arc::new(preview));
+        self
+    }
+
+    /// 启用 TLS 指纹（JA3 / JA4）计算和过滤，拒绝已知扫描器、爬虫的 TLS 栈
+    pub fn with_fingerprint_filter(mut self, filter: FingerprintFilter) -> Self {
+        self.fingerprint_filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// 设置 IP、域名或指纹被拒绝时断开连接的方式
+    ///
+    /// 默认直接关闭；`Reset` 发送 RST，`Alert` 发送 TLS alert，`Tarpit` 慢速读取拖住扫描器
+    pub fn with_rejection_mode(mut self, mode: RejectionMode) -> Self {
+        self.rejection_mode = mode;
+        self
+    }
+
+    /// 设置 Client Hello 没有 SNI 时的处理方式
+    ///
+    /// 默认拒绝；也可以转发到固定的后端地址，或在透明代理模式下转发到原始目标地址。
+    /// 兜底转发不经过域名白名单，IP 白名单仍然生效
+    pub fn with_no_sni_action(mut self, action: NoSniAction) -> Self {
+        self.no_sni_action = action;
+        self
+    }
+
+    /// 设置默认后端：不在任何白名单中的域名转发到这里，而不是拒绝
+    ///
+    /// 默认后端总是直连（不经过 SOCKS5），也不记录域名-IP
+    pub fn with_default_backend(mut self, backend: BackendAddr) -> Self {
+        self.default_backend = Some(Arc::new(backend));
+        self
+    }
+
+    /// 设置 SNI → 后端映射表：匹配的域名直接转发到映射的后端，不按 SNI 解析 DNS
+    ///
+    /// 映射表优先于直连和 SOCKS5 白名单，映射的域名无需再加入白名单
+    pub fn with_sni_backends(mut self, map: SniBackendMap) -> Self {
+        if !map.is_empty() {
+            self.sni_backends = Some(Arc::new(map));
+        }
+        self
+    }
+
+    /// 设置固定 IP：直连匹配的域名（包括映射表和默认后端的主机名）时使用配置的 IP，不经过 DNS 解析
+    ///
+    /// 配置了多个 IP 时按客户端 IP 选择，同一客户端总是连接同一个 IP
+    pub fn with_pinned_ips(mut self, pinned_ips: PinnedIps) -> Self {
+        if !pinned_ips.is_empty() {
+            self.pinned_ips = Some(Arc::new(pinned_ips));
+        }
+        self
+    }
+
+    /// 设置 SNI 为 IP 地址时的处理方式（可按监听端口分别配置，默认按域名白名单匹配）
+    pub fn with_ip_sni_policy(mut self, policy: IpSniPolicy) -> Self {
+        self.ip_sni_policy = Arc::new(policy);
+        self
+    }
+
+    /// 设置 SNI 为 IP 地址时允许的目标 IP / CIDR（处理方式为 `IpWhitelist` 时生效）
+    pub fn with_ip_sni_whitelist(mut self, ip_whitelist: Vec<String>) -> Self {
+        let ip_matcher = IpMatcher::new(ip_whitelist);
+        if !ip_matcher.is_empty() {
+            self.ip_sni_matcher = Some(Arc::new(ip_matcher));
+        }
+        self
+    }
+
+    /// 启用重复连接退避：同一客户端对同一 SNI 短时间内连接过多时指数退避
+    pub fn with_burst_backoff(mut self, config: BurstConfig) -> Self {
+        self.burst_limiter = Some(Arc::new(BurstLimiter::new(config)));
+        self
+    }
+
+    /// 启用自动封禁：客户端 IP 在窗口内失败的连接（被拒绝或无法解析 SNI）过多时临时封禁
+    pub fn with_auto_ban(mut self, config: BanConfig) -> Self {
+        self.ban_table = Some(Arc::new(BanTable::new(config)));
+        self
+    }
+
+    /// 限制所有监听器接受新连接的总速率，超过时立即关闭新连接（或排队等待）
+    pub fn with_accept_rate_limit(mut self, config: AcceptRateConfig) -> Self {
+        self.accept_limiter = Some(Arc::new(AcceptLimiter::new(config)));
+        self
+    }
+
+    /// 开启握手阶段保护：握手中（读取到 Client Hello 之前）的连接只占用单独的握手许可，
+    /// 数量达到上限时新连接立即关闭，Client Hello 使用配置的截止时间
+    pub fn with_handshake_limit(mut self, config: HandshakeConfig) -> Self {
+        self.handshake_limit = Some(Arc::new(HandshakeLimit::new(config)));
+        self
+    }
+
+    /// 按客户端 IP 限制带宽（TCP）：同一 IP 的所有连接共享上限，和路由规则的带宽上限同时生效
+    pub fn with_client_rate_limits(mut self, limits: ClientRateLimits) -> Self {
+        self.client_rate_limits = Some(Arc::new(limits));
+        self
+    }
+
+    /// 启用 ACL：按顺序匹配客户端 IP 和域名，第一条匹配的规则决定允许或拒绝，没有规则匹配时拒绝
+    pub fn with_acl(mut self, rules: Vec<AclRule>) -> Self {
+        self.acl = Some(Arc::new(Acl::new(rules)));
+        self
+    }
+
+    /// 设置域名黑名单（支持通配符），匹配的域名一律拒绝，优先于白名单、路由规则和 SNI 映射表
+    pub fn with_blacklist(mut self, domains: Vec<String>) -> Self {
+        self.blacklist = Some(Arc::new(DomainMatcher::new(domains)));
+        self
+    }
+
+    /// 设置客户端 IP 黑名单（IP 或 CIDR），匹配的客户端一律拒绝，优先于 IP 白名单，没有配置 IP 白名单时同样生效
+    pub fn with_ip_blacklist(mut self, ip_blacklist: Vec<String>) -> Self {
+        let ip_blacklist = IpMatcher::new(ip_blacklist);
+        self.ip_blacklist = (!ip_blacklist.is_empty()).then(|| Arc::new(ip_blacklist));
+        self
+    }
+
+    /// 设置 GeoIP 数据库（MaxMind GeoLite2 / GeoIP2 Country 或 City）
+    pub fn with_geoip(mut self, geoip: GeoIp) -> Self {
+        self.geoip = Some(Arc::new(ArcSwap::from_pointee(geoip)));
+        self
+    }
+
+    /// 定期下载 GeoIP 数据库，有更新时替换（需要同时用 [`Self::with_geoip`] 设置初始数据库）
+    pub fn with_geoip_update(mut self, update: GeoIpUpdate) -> Self {
+        self.geoip_update = Some(update);
+        self
+    }
+
+    /// 按客户端所在国家允许或拒绝连接（需要同时用 [`Self::with_geoip`] 设置数据库）
+    pub fn with_geo_filter(mut self, filter: GeoFilter) -> Self {
+        self.geo_filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// 启用每日汇总报告（流量、拒绝原因、流量最高的客户端 IP、上游可用性）
+    pub fn with_daily_report(mut self, config: ReportConfig) -> Self {
+        self.report_config = Some(config);
+        self
+    }
+
+    /// 启用 InfluxDB 行协议导出：定期写出按域名和客户端 IP 汇总的流量
+    pub fn with_influx_export(mut self, config: InfluxConfig) -> Self {
+        self.influx_config = Some(config);
+        self
+    }
+
+    /// 启用管理接口（HTTP + JSON，用于远程查看运行状态）
+    pub fn with_admin_api(mut self, admin_config: AdminConfig) -> Self {
+        self.admin_config = Some(admin_config);
+        self
+    }
+
     /// 获取监控指标
     pub fn metrics(&self) -> &Metrics {
         &self.metrics
     }
 
+    /// 获取 IP 流量追踪器
+    pub fn ip_traffic_tracker(&self) -> &IpTrafficTracker {
+        &self.ip_traffic_tracker
+    }
+
+    /// 获取域名-IP 追踪器
+    pub fn domain_ip_tracker(&self) -> &DomainIpTracker {
+        &self.domain_ip_tracker
+    }
+
+    /// 订阅运行时事件（连接建立、拒绝、上游不可用、连接数达到上限）
+    ///
+    /// 订阅方处理过慢时会收到 `RecvError::Lagged`，最旧的事件被丢弃
+    pub fn subscribe_events(&self) -> tokio::sync::broadcast::Receiver<ProxyEvent> {
+        self.events.subscribe()
+    }
+
+    fn lock_whitelists(&self) -> std::sync::MutexGuard<'_, WhitelistReloader> {
+        self.whitelists.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 运行中向直连白名单添加域名规则（格式同 `whitelist`，对新连接生效），返回是否新增（规则已存在时返回 false）
+    pub fn add_direct_domain(&self, rule: &str) -> Result<bool> {
+        self.lock_whitelists().add_rule(ListKind::Direct, rule)
+    }
+
+    /// 运行中从直连白名单删除域名规则，返回是否删除
+    ///
+    /// 只能删除配置的或运行中添加的规则，白名单文件和远程白名单中的规则需要在来源处删除
+    pub fn remove_direct_domain(&self, rule: &str) -> bool {
+        self.lock_whitelists().remove_rule(ListKind::Direct, rule)
+    }
+
+    /// 运行中向 SOCKS5 白名单添加域名规则，返回是否新增（没有配置 SOCKS5 上游时返回错误）
+    pub fn add_socks5_domain(&self, rule: &str) -> Result<bool> {
+        if self.socks5_upstreams.get(DEFAULT_SOCKS5_UPSTREAM).is_none() {
+            return Err(SniProxyError::InvalidConfig("未配置 SOCKS5 上游，不能添加 SOCKS5 白名单".to_string()));
+        }
+        self.lock_whitelists().add_rule(ListKind::Socks5, rule)
+    }
+
+    /// 运行中从 SOCKS5 白名单删除域名规则，返回是否删除（限制同 [`Self::remove_direct_domain`]）
+    pub fn remove_socks5_domain(&self, rule: &str) -> bool {
+        self.lock_whitelists().remove_rule(ListKind::Socks5, rule)
+    }
+
+    /// 运行中向 IP 白名单添加 IP 或 CIDR（对新连接生效），返回是否新增（规则已存在时返回 false）
+    ///
+    /// 没有配置 IP 白名单（即允许所有客户端）时返回错误，因为添加一条规则会拒绝其他所有客户端
+    pub fn add_allowed_ip(&self, rule: &str) -> Result<bool> {
+        self.lock_whitelists().add_rule(ListKind::Ip, rule)
+    }
+
+    /// 运行中从 IP 白名单删除 IP 或 CIDR，返回是否删除（已建立的连接不受影响）
+    ///
+    /// 限制同 [`Self::remove_direct_domain`]，另外不能删除最后一条规则（空的 IP 白名单会允许所有客户端）
+    pub fn remove_allowed_ip(&self, rule: &str) -> bool {
+        self.lock_whitelists().remove_rule(ListKind::Ip, rule)
+    }
+
+    /// 全部 TCP 监听地址（主监听地址在前，主监听为 Unix socket 时不包含）
+    fn listen_addrs(&self) -> Vec<SocketAddr> {
+        self.listen_addr
+            .as_tcp()
+            .into_iter()
+            .chain(self.extra_listen_addrs.iter().copied())
+            .collect()
+    }
+
+    /// 构建连接处理所需的共享状态
+    fn connection_context(&self, shutdown: watch::Receiver<bool>) -> ConnectionContext {
+        ConnectionContext {
+            routes: Arc::clone(&self.routes),
+            ip_matcher: Arc::clone(&self.ip_matcher),
+            socks5_upstreams: Arc::clone(&self.socks5_upstreams),
+            outbound: Arc::clone(&self.outbound),
+            proxy_protocol_matcher: self.proxy_protocol_matcher.clone(),
+            metrics: self.metrics.clone(),
+            ip_traffic_tracker: self.ip_traffic_tracker.clone(),
+            domain_ip_tracker: self.domain_ip_tracker.clone(),
+            tcp_tuning: Arc::clone(&self.tcp_tuning),
+            listen_addrs: self.listen_addrs().into(),
+            port_mapping: Arc::clone(&self.port_mapping),
+            transparent_mode: self.transparent_mode,
+            http_sniffing: self.http_sniffing,
+            hello_buffers: Arc::new(BufferPool::new(self.hello_buffer_size.unwrap_or_else(adaptive_hello_buffer_size))),
+            relay_buffer_size: self.relay_buffer_size,
+            timeouts: self.timeouts,
+            connect_retry: self.connect_retry,
+            direct_fallback_to_socks5: self.direct_fallback_to_socks5,
+            splice_relay: self.splice_relay,
+            access_log: self.access_log,
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            uring: None,
+            socks5_fallback_to_direct: self.socks5_fallback_to_direct,
+            idle_timeout: self.idle_timeout,
+            max_connection_lifetime: self.max_connection_lifetime,
+            events: self.events.clone(),
+            max_connections: self.max_connections,
+            shutdown,
+            hello_recorder: self.hello_recorder.clone(),
+            body_preview: self.body_preview.clone(),
+            plaintext_http: self.plaintext_http,
+            fingerprint_filter: self.fingerprint_filter.clone(),
+            rejection_mode: self.rejection_mode,
+            no_sni_action: self.no_sni_action,
+            default_backend: self.default_backend.clone(),
+            sni_backends: self.sni_backends.clone(),
+            pinned_ips: self.pinned_ips.clone(),
+            ip_sni_policy: Arc::clone(&self.ip_sni_policy),
+            ip_sni_matcher: self.ip_sni_matcher.clone(),
+            burst_limiter: self.burst_limiter.clone(),
+            ban_table: self.ban_table.clone(),
+            accept_limiter: self.accept_limiter.clone(),
+            handshake_limit: self.handshake_limit.clone(),
+            client_rate_limits: self.client_rate_limits.clone(),
+            acl: self.acl.clone(),
+            blacklist: self.blacklist.clone(),
+            ip_blacklist: self.ip_blacklist.clone(),
+            geoip: self.geoip.clone(),
+            geo_filter: self.geo_filter.clone(),
+        }
+    }
+
     /// 启动代理服务器
     ///
     /// # 参数
@@ -169,85 +1069,330 @@ impl SniProxy {
         self.run_with_shutdown(None).await
     }
 
-    /// 启动代理服务器（支持优雅关闭）
+    /// 创建监听 socket（设置 backlog、SO_REUSEPORT、TCP Fast Open 等选项）
     ///
     /// # 参数
-    /// * `shutdown_rx` - 可选的关闭信号接收器
-    pub async fn run_with_shutdown(&self, mut shutdown_rx: Option<watch::Receiver<bool>>) -> Result<()> {
-        // 创建 socket 并设置选项
+    /// * `listen_addr` - 监听地址
+    /// * `index` - 监听 socket 序号（多 acceptor 时每个 acceptor 一个 socket，共享同一端口）
+    fn bind_listener(
+        &self,
+        listen_addr: SocketAddr,
+        index: usize,
+        incoming_cpu_steering: bool,
+    ) -> Result<std::net::TcpListener> {
         use socket2::{Domain, Protocol, Socket, Type};
 
+        // 只在第一个 TCP 监听地址的第一个 socket 上打印配置信息，避免重复输出
+        let log_options = index == 0 && self.listen_addrs().first() == Some(&listen_addr);
+        let tuning = self.tcp_tuning.listener(Some(listen_addr.port()));
+
         // 手动创建 socket 以设置更大的 backlog
-        let socket = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP))?;
+        // 根据监听地址选择地址族（IPv4 / IPv6）
+        let socket = Socket::new(Domain::for_address(listen_addr), Type::STREAM, Some(Protocol::TCP))?;
+
+        // IPv6 监听：显式设置 IPV6_V6ONLY，不依赖系统默认值
+        if listen_addr.is_ipv6() {
+            socket.set_only_v6(!self.dual_stack)?;
+            if self.dual_stack && log_options {
+                info!("✅ 双栈监听已启用（同时接受 IPv4 和 IPv6 连接）");
+            }
+        }
 
         // ⚡ 优化：设置 socket 选项
         socket.set_reuse_address(true)?;
         socket.set_nonblocking(true)?;
 
-        // SO_REUSEPORT - 允许端口重用（Linux/macOS）
+        // SO_REUSEPORT - 允许端口重用（Linux/macOS），多 acceptor 依赖此选项
         #[cfg(any(target_os = "linux", target_os = "macos"))]
         {
             use std::os::unix::io::AsRawFd;
             unsafe {
                 let fd = socket.as_raw_fd();
-                const SO_REUSEPORT: libc::c_int = 15;
                 let reuse_port: libc::c_int = 1;
                 let _ = libc::setsockopt(
                     fd,
                     libc::SOL_SOCKET,
-                    SO_REUSEPORT,
+                    libc::SO_REUSEPORT,
                     &reuse_port as *const _ as *const libc::c_void,
                     std::mem::size_of_val(&reuse_port) as libc::socklen_t,
                 );
             }
         }
 
-        // ⚡ TCP Fast Open (服务端模式) - Linux 3.7+ 支持
-        // 允许客户端在 SYN 包中携带数据，节省 1 RTT
+        // TPROXY 模式：监听 socket 需要 IP_TRANSPARENT 才能接受发往非本机地址的连接
+        if self.transparent_mode == Some(TransparentMode::Tproxy) {
+            if let Err(source) = crate::transparent::set_ip_transparent(&socket, listen_addr.is_ipv6()) {
+                error!("TPROXY 模式需要 CAP_NET_ADMIN 权限");
+                return Err(SniProxyError::SocketOption { option: "IP_TRANSPARENT", source });
+            }
+        }
+
+        // ⚡ SO_INCOMING_CPU - 让内核把在某个 CPU 上收到的连接交给绑定到该 CPU 的 acceptor
         #[cfg(target_os = "linux")]
-        {
-            use std::os::unix::io::AsRawFd;
-            unsafe {
-                let fd = socket.as_raw_fd();
-                const TCP_FASTOPEN: libc::c_int = 23; // Linux TCP_FASTOPEN 常量
-                let queue_len: libc::c_int = 256; // TFO 队列长度
-                let result = libc::setsockopt(
-                    fd,
-                    libc::IPPROTO_TCP,
-                    TCP_FASTOPEN,
-                    &queue_len as *const _ as *const libc::c_void,
-                    std::mem::size_of_val(&queue_len) as libc::socklen_t,
-                );
+        if incoming_cpu_steering {
+            let cpu = PlatformInfo::detect().acceptor_cpu(index);
+            if let Err(e) = crate::affinity::set_incoming_cpu(&socket, cpu) {
+                warn!("⚠️  设置 SO_INCOMING_CPU={} 失败: {}", cpu, e);
+            }
+        }
 
-                if result == 0 {
-                    info!("✅ TCP Fast Open 已启用（服务端模式，队列: {}）", queue_len);
-                } else {
-                    warn!("⚠️  TCP Fast Open 启用失败（系统可能不支持）");
-                    warn!("   提示: 检查 /proc/sys/net/ipv4/tcp_fastopen");
-                }
+        // ⚡ TCP Fast Open (服务端模式)，允许客户端在 SYN 包中携带数据，节省 1 RTT
+        let fastopen = tuning.apply_to_listener(&socket);
+        if log_options && tuning.fastopen && cfg!(target_os = "linux") {
+            if fastopen {
+                info!("✅ TCP Fast Open 已启用（服务端模式，队列: {}）", tuning.fastopen_queue_len());
+            } else {
+                warn!("⚠️  TCP Fast Open 启用失败（系统可能不支持）");
+                warn!("   提示: 检查 /proc/sys/net/ipv4/tcp_fastopen");
             }
         }
 
-        // 绑定地址
-        let address = self.listen_addr.into();
-        socket.bind(&address)?;
+        // 绑定地址
+        let address = listen_addr.into();
+        socket
+            .bind(&address)
+            .map_err(|source| SniProxyError::Bind { addr: listen_addr, source })?;
+
+        // ⚡ 关键优化：设置大的 backlog（默认 128 → 4096，可通过 tuning 配置）
+        // 这样可以让更多连接在队列中等待，避免 accept 慢
+        socket.listen(tuning.backlog)?;
+
+        if log_options {
+            info!("✅ TCP backlog 设置为 {}（提升高并发性能）", tuning.backlog);
+            tuning.warn_if_exceeds_kernel_limits();
+        } else if index == 0 && tuning != self.tcp_tuning.default_tuning() {
+            info!("✅ 监听地址 {} 使用单独的 TCP 调优参数（backlog: {}）", listen_addr, tuning.backlog);
+            tuning.warn_if_exceeds_kernel_limits();
+        }
+
+        // 转换为标准库的 TcpListener
+        Ok(socket.into())
+    }
+
+    /// 创建 QUIC 监听的 UDP socket（IPv6 地址与 TCP 监听一样按 `dual_stack` 设置 IPV6_V6ONLY）
+    fn bind_quic_socket(&self, listen_addr: SocketAddr) -> std::io::Result<UdpSocket> {
+        use socket2::{Domain, Protocol, Socket, Type};
 
-        // ⚡ 关键优化：设置大的 backlog（默认 128 → 4096）
-        // 这样可以让更多连接在队列中等待，避免 accept 慢
-        socket.listen(4096)?;
+        let socket = Socket::new(Domain::for_address(listen_addr), Type::DGRAM, Some(Protocol::UDP))?;
+        if listen_addr.is_ipv6() {
+            socket.set_only_v6(!self.dual_stack)?;
+        }
+        socket.set_nonblocking(true)?;
+        socket.bind(&listen_addr.into())?;
+        UdpSocket::from_std(socket.into())
+    }
 
-        info!("✅ TCP backlog 设置为 4096（提升高并发性能）");
+    /// 启动代理服务器（支持优雅关闭）
+    ///
+    /// # 参数
+    /// * `shutdown_rx` - 可选的关闭信号接收器
+    pub async fn run_with_shutdown(&self, shutdown_rx: Option<watch::Receiver<bool>>) -> Result<()> {
+        // SO_INCOMING_CPU / SO_REUSEPORT 依赖内核版本，运行时检测（静态构建可能运行在旧内核上）
+        let platform = PlatformInfo::detect();
+        let incoming_cpu_steering = self.incoming_cpu_steering && platform.supports(KernelFeature::IncomingCpu);
+        if self.incoming_cpu_steering && !incoming_cpu_steering {
+            warn!("⚠️  当前平台或内核不支持 SO_INCOMING_CPU，已忽略 CPU 分流配置");
+        }
+        let acceptors = if self.acceptors > 1 && !platform.supports(KernelFeature::ReusePort) {
+            warn!("⚠️  当前平台或内核不支持 SO_REUSEPORT，只使用 1 个 acceptor");
+            1
+        } else {
+            self.acceptors
+        };
 
-        // 转换为标准库的 TcpListener
-        let std_listener: std::net::TcpListener = socket.into();
+        if self.geo_filter.is_some() && self.geoip.is_none() {
+            return Err(SniProxyError::InvalidConfig("国家限制需要 GeoIP 数据库（with_geoip）".to_string()));
+        }
+        if self.geoip_update.is_some() && self.geoip.is_none() {
+            return Err(SniProxyError::InvalidConfig("GeoIP 数据库自动更新需要初始数据库（with_geoip）".to_string()));
+        }
 
-        // 转换为 Tokio 的 TcpListener
-        let listener = TcpListener::from_std(std_listener)?;
+        // 先创建全部监听 socket，任何一个失败都直接返回，不留下已启动的 acceptor
+        let listen_addrs = self.listen_addrs();
+        let mut listeners = Vec::with_capacity(acceptors * listen_addrs.len());
+        for &listen_addr in &listen_addrs {
+            for index in 0..acceptors {
+                listeners.push((index, self.bind_listener(listen_addr, index, incoming_cpu_steering)?));
+            }
+        }
+        #[cfg(unix)]
+        let unix_listener = match &self.listen_addr {
+            ListenAddr::Unix(path) => Some(bind_unix_listener(path)?),
+            ListenAddr::Tcp(_) => None,
+        };
+        #[cfg(not(unix))]
+        if let ListenAddr::Unix(path) = &self.listen_addr {
+            return Err(SniProxyError::InvalidConfig(format!("当前平台不支持 Unix socket 监听: {}", path.display())));
+        }
+        let mut quic_sockets = Vec::with_capacity(self.quic_listeners.len());
+        for listener in &self.quic_listeners {
+            let socket = self
+                .bind_quic_socket(listener.addr)
+                .map_err(|source| SniProxyError::Bind { addr: listener.addr, source })?;
+            quic_sockets.push((socket, listener.target_port));
+        }
 
         info!("SNI 代理服务器启动在 {}", self.listen_addr);
+        for listen_addr in &self.extra_listen_addrs {
+            info!("额外监听地址: {}", listen_addr);
+        }
+        for listen_addr in &listen_addrs {
+            let target_port = self.port_mapping.target_port(listen_addr.port());
+            if target_port != self.port_mapping.default_port() {
+                info!("端口映射: {} → 目标端口 {}", listen_addr.port(), target_port);
+            }
+        }
         info!("最大并发连接数: {}", self.max_connections);
+        if let Some(capacity) = self.dns_cache_capacity {
+            crate::dns::set_dns_cache_capacity(capacity).await;
+            info!("DNS 缓存容量: {}", capacity);
+        }
+        crate::dns::set_dns_options(self.dns_options);
+        if self.dns_options != DnsOptions::default() {
+            info!(
+                "DNS 解析超时: {:?}，缓存有效期: {}，过期结果: {}，地址族: {}",
+                self.dns_options.timeout,
+                self.dns_options.cache_ttl.map_or("永不过期".to_string(), |ttl| format!("{:?}", ttl)),
+                if self.dns_options.serve_stale { "刷新失败时使用" } else { "不使用" },
+                self.dns_options.family
+            );
+        }
+        crate::dns::set_dns_upstream(self.dns_upstream.as_ref())?;
+        if let Some(upstream) = &self.dns_upstream {
+            info!(
+                "✅ 自定义 DNS 上游（{}）: {:?}，搜索域: {:?}，单次查询超时: {:?}，查询次数: {}",
+                upstream.transport, upstream.nameservers, upstream.search, upstream.query_timeout, upstream.attempts
+            );
+        }
+        crate::dns::set_dns_routes(&self.dns_routes)?;
+        for route in &self.dns_routes {
+            match &route.upstream {
+                Some(upstream) => info!("✅ DNS 路由: {:?} → {}: {:?}", route.domains, upstream.transport, upstream.nameservers),
+                None => info!("✅ DNS 路由: {:?} → 系统解析器", route.domains),
+            }
+        }
+        if !self.dns_hosts.is_empty() {
+            info!("✅ DNS 静态解析: {} 个域名", self.dns_hosts.len());
+        }
+        crate::dns::set_dns_hosts(self.dns_hosts.clone());
+        if let Some(mode) = self.transparent_mode {
+            info!("✅ 透明代理模式已启用（{}）", mode);
+        }
+        if self.http_sniffing {
+            info!("✅ HTTP Host 路由已启用（非 TLS 连接转发到 80 端口）");
+        } else if self.plaintext_http != PlaintextHttpAction::Close {
+            info!("✅ 明文 HTTP 请求处理方式: {}", self.plaintext_http);
+        }
+        if self.fingerprint_filter.is_some() {
+            info!("✅ TLS 指纹（JA3 / JA4）过滤已启用");
+        }
+        if self.burst_limiter.is_some() {
+            info!("✅ 重复连接退避已启用");
+        }
+        if self.ban_table.is_some() {
+            info!("✅ 自动封禁已启用");
+        }
+        if self.accept_limiter.is_some() {
+            info!("✅ 新连接速率限制已启用");
+        }
+        if let Some(limit) = &self.handshake_limit {
+            let config = limit.config();
+            info!(
+                "✅ 握手阶段保护已启用: 最多 {} 个握手中的连接，Client Hello 截止时间 {:?}",
+                config.max_handshaking, config.hello_timeout
+            );
+        }
+        if self.client_rate_limits.is_some() {
+            info!("✅ 按客户端 IP 的带宽限制已启用");
+        }
+        if let Some(read) = self.timeouts.client_hello_read {
+            info!("✅ 读取 Client Hello 超时: {:?}", read);
+        }
+        if let Some(connect) = self.timeouts.connect {
+            info!("✅ 连接目标服务器超时: {:?}", connect);
+        }
+        if let Some(socks5) = self.timeouts.socks5_handshake {
+            info!("✅ SOCKS5 连接超时: {:?}", socks5);
+        }
+        if let Some(bind_addr) = self.outbound.bind_addr {
+            info!("✅ 出站源地址: {}", bind_addr);
+        }
+        if let Some(interface) = &self.outbound.interface {
+            info!("✅ 出站网络接口: {}", interface);
+        }
+        if let Some(mark) = self.outbound.fwmark {
+            info!("✅ 出站连接 fwmark: {:#x}", mark);
+        }
+        if self.outbound.fastopen == Some(true) {
+            info!("✅ SOCKS5 代理连接使用 TCP Fast Open（客户端模式，直连不使用）");
+        }
+        if let Some(retry) = self.connect_retry {
+            info!("✅ 上游连接重试: 最多 {} 次（退避 {:?} - {:?}）", retry.max_retries, retry.initial_backoff, retry.max_backoff);
+        }
+        if self.direct_fallback_to_socks5 {
+            match self.socks5_upstreams.get(DEFAULT_SOCKS5_UPSTREAM) {
+                Some(socks5) => info!("✅ 直连失败时改用 SOCKS5 ({}) 重新连接", socks5.addr),
+                None => warn!("⚠️  未配置 SOCKS5 上游，直连失败回退不生效"),
+            }
+        }
+        if self.socks5_fallback_to_direct {
+            info!("✅ SOCKS5 连接失败时改用直连");
+        }
+        if let Some(timeout) = self.idle_timeout {
+            info!("✅ 转发空闲超时: {:?}", timeout);
+        }
+        if self.splice_relay {
+            if cfg!(target_os = "linux") {
+                info!("✅ splice 转发已启用（限速和正文预览的连接仍使用用户态转发）");
+            } else {
+                warn!("⚠️  当前平台不支持 splice，使用用户态转发");
+            }
+        }
+        if self.io_uring_workers.is_some() && !cfg!(all(feature = "io-uring", target_os = "linux")) {
+            warn!("⚠️  未启用 io-uring 功能或当前平台不是 Linux，使用 epoll");
+        }
+        if let Some(lifetime) = self.max_connection_lifetime {
+            info!("✅ 最长连接时间: {:?}", lifetime);
+        }
+        if let Some(acl) = &self.acl {
+            info!("✅ ACL: {} 条规则（客户端 IP 和域名一起匹配）", acl.len());
+        }
+        if let Some(blacklist) = &self.blacklist {
+            info!("✅ 域名黑名单: {} 条规则", blacklist.get_patterns().len());
+        }
+        if let Some(ip_blacklist) = &self.ip_blacklist {
+            info!("✅ IP 黑名单: {} 条规则", ip_blacklist.len());
+        }
+        if let Some(geoip) = &self.geoip {
+            let geoip = geoip.load();
+            info!("✅ GeoIP 数据库: {}（构建于 {}）", geoip.database_type(), geoip.build_date());
+        }
+        if let Some(filter) = &self.geo_filter {
+            info!("✅ 国家限制: 允许 {} 个国家，拒绝 {} 个国家", filter.allow_len(), filter.deny_len());
+        }
+        if let Some(map) = &self.sni_backends {
+            info!("✅ SNI 映射表: {} 条规则", map.len());
+        }
+        if let Some(pinned) = &self.pinned_ips {
+            info!("✅ 固定 IP: {} 条规则", pinned.len());
+        }
+        if let Some(backend) = &self.default_backend {
+            info!("✅ 默认后端: {}（不在白名单中的域名转发到这里）", backend);
+        }
+        if self.no_sni_action != NoSniAction::Reject {
+            info!("✅ 没有 SNI 的连接: {}", self.no_sni_action);
+        }
+        if *self.ip_sni_policy != IpSniPolicy::default() {
+            info!("✅ SNI 为 IP 地址的连接已单独处理（默认: {}）", self.ip_sni_policy.action(None));
+        }
+        if self.rejection_mode != RejectionMode::Close {
+            info!("✅ 拒绝连接方式: {}", self.rejection_mode);
+        }
+        if acceptors > 1 {
+            info!("✅ 多 acceptor 已启用（{} 个 SO_REUSEPORT 监听 socket）", acceptors);
+        }
 
-        if let Some(socks5) = &self.socks5_config {
+        if let Some(socks5) = self.socks5_upstreams.get(DEFAULT_SOCKS5_UPSTREAM) {
             info!("使用 SOCKS5 出口: {}", socks5.addr);
             if socks5.username.is_some() {
                 info!("SOCKS5 认证: 启用");
@@ -255,6 +1400,60 @@ impl SniProxy {
         } else {
             info!("直接连接到目标服务器（未配置 SOCKS5）");
         }
+        for (name, socks5) in self.socks5_upstreams.iter().filter(|(name, _)| *name != DEFAULT_SOCKS5_UPSTREAM) {
+            info!("SOCKS5 上游 {}: {}", name, socks5.addr);
+        }
+        // 加载白名单文件和远程白名单，监视文件修改并定期刷新远程白名单
+        if !self.whitelist_files.is_empty() || !self.remote_whitelists.is_empty() {
+            let (refresher, remote) = RemoteRefresher::load(&self.remote_whitelists).await?;
+            self.lock_whitelists().load(self.whitelist_files.clone(), remote)?;
+            if !self.whitelist_files.is_empty() {
+                tokio::spawn(run_whitelist_watcher(Arc::clone(&self.whitelists), self.whitelist_files.reload_interval));
+            }
+            if !self.remote_whitelists.is_empty() {
+                tokio::spawn(run_remote_whitelist_refresh(refresher, Arc::clone(&self.whitelists)));
+            }
+        }
+        info!("路由表: {} 条规则", self.routes.load().len());
+        if let Some(prefetch) = self.dns_prefetch {
+            let routes = Arc::clone(&self.routes);
+            tokio::spawn(run_dns_prefetch(prefetch, move || routes.load().direct_domains(), self.metrics.clone()));
+        }
+        if let (Some(geoip), Some(update)) = (&self.geoip, &self.geoip_update) {
+            tokio::spawn(run_geoip_update(Arc::clone(geoip), update.clone()));
+        }
+
+        // 启动管理接口（仅在配置时）
+        if let Some(ref admin_config) = self.admin_config {
+            let admin_config = admin_config.clone();
+            let admin_state = AdminState {
+                proxy_listen_addr: self.listen_addr.clone(),
+                socks5_enabled: !self.socks5_upstreams.is_empty(),
+                metrics: self.metrics.clone(),
+                ip_traffic_tracker: self.ip_traffic_tracker.clone(),
+                domain_ip_tracker: self.domain_ip_tracker.clone(),
+                // 管理接口只读取路由配置，不需要关闭信号
+                routing: self.connection_context(watch::channel(false).1),
+            };
+            tokio::spawn(async move {
+                if let Err(e) = run_admin_server(admin_config, admin_state).await {
+                    error!("管理接口启动失败: {}", e);
+                }
+            });
+        }
+
+        // 启动 HTTP → HTTPS 重定向监听（仅在配置时）
+        for &redirect_addr in &self.http_redirect_addrs {
+            let whitelist = RedirectWhitelist {
+                routes: Arc::clone(&self.routes),
+                blacklist: self.blacklist.clone(),
+            };
+            tokio::spawn(async move {
+                if let Err(e) = run_redirect_server(redirect_addr, whitelist).await {
+                    error!("HTTP 重定向监听启动失败: {}", e);
+                }
+            });
+        }
 
         // 使用信号量限制并发连接数
         let semaphore = Arc::new(tokio::sync::Semaphore::new(self.max_connections));
@@ -269,6 +1468,31 @@ impl SniProxy {
             }
         });
 
+        // 启动每日报告任务（仅在配置时）
+        if let Some(ref report_config) = self.report_config {
+            info!(
+                "✅ 每日报告已启用（每天 {:02}:00 生成，目录: {}）",
+                report_config.hour,
+                report_config.directory.display()
+            );
+            tokio::spawn(run_daily_report(
+                report_config.clone(),
+                self.metrics.clone(),
+                self.ip_traffic_tracker.clone(),
+                self.events.clone(),
+            ));
+        }
+
+        // 启动 InfluxDB 导出任务（仅在配置时）
+        if let Some(ref influx_config) = self.influx_config {
+            let target = match &influx_config.target {
+                InfluxTarget::File(path) => path.display().to_string(),
+                InfluxTarget::Http(url) => url.to_string(),
+            };
+            info!("✅ InfluxDB 行协议导出已启用（每 {:?} 写入 {}）", influx_config.interval, target);
+            tokio::spawn(run_influx_exporter(influx_config.clone(), self.events.clone()));
+        }
+
         // 启动后台任务：每分钟打印 IP 流量统计（仅在启用时）
         if self.ip_traffic_tracker.is_enabled() {
             let ip_traffic_tracker_clone = self.ip_traffic_tracker.clone();
@@ -321,128 +1545,337 @@ impl SniProxy {
             info!("✅ 域名-IP 追踪定期保存已启用（每 1 分钟）");
         }
 
-        loop {
-            use std::time::Instant;
-
-            // 如果提供了关闭信号，使用 select! 监听关闭和新连接
-            let should_shutdown = if let Some(ref mut rx) = shutdown_rx {
+        // 通知额外 acceptor 停止接受新连接；发送端被丢弃时（本函数返回）acceptor 线程退出
+        let (stop_tx, stop_rx) = watch::channel(false);
+        let ctx = self.connection_context(stop_rx.clone());
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        let ctx = ConnectionContext { uring: self.start_uring(), ..ctx };
+        let mut inline_listener = None;
+
+        // Unix socket 监听在独立任务中运行
+        #[cfg(unix)]
+        if let Some(listener) = unix_listener {
+            let semaphore = Arc::clone(&semaphore);
+            let ctx = ctx.clone();
+            let mut stop_rx = stop_rx.clone();
+            tokio::spawn(async move {
                 tokio::select! {
-                    // 监听关闭信号
-                    _ = rx.changed() => {
-                        if *rx.borrow() {
-                            info!("🛑 收到关闭信号，停止接受新连接");
-                            // 等待活跃连接完成（最多 30 秒）
-                            info!("⏳ 等待活跃连接完成...");
-                            let wait_start = Instant::now();
-
-                            // 使用循环检查活跃连接数
-                            for _ in 0..30 {
-                                let active = self.metrics.get_active_connections();
-                                if active == 0 {
-                                    info!("✅ 所有连接已关闭");
-                                    break;
-                                }
-                                info!("⏳ 等待 {} 个活跃连接关闭...", active);
-                                tokio::time::sleep(Duration::from_secs(1)).await;
-                            }
-
-                            let final_active = self.metrics.get_active_connections();
-                            if final_active > 0 {
-                                warn!("⚠️  超时：仍有 {} 个连接未关闭，强制退出", final_active);
-                            }
-
-                            info!("⏱️  关闭耗时: {:?}", wait_start.elapsed());
-
-                            // 保存 IP 流量统计数据
-                            if self.ip_traffic_tracker.is_enabled() {
-                                info!("💾 保存 IP 流量统计数据...");
-                                self.ip_traffic_tracker.save_to_persistence_file();
-                            }
-
-                            // 保存域名-IP 映射数据
-                            if self.domain_ip_tracker.is_enabled() {
-                                info!("💾 保存域名-IP 映射数据...");
-                                if let Err(e) = self.domain_ip_tracker.save_to_file() {
-                                    error!("保存域名-IP 映射失败: {}", e);
-                                }
-                            }
-
-                            // 打印最终统计
-                            info!("📊 最终统计:");
-                            self.metrics.print_summary();
-
-                            return Ok(());
-                        }
-                        false
-                    }
-                    // 监听新连接
-                    accept_result = listener.accept() => {
-                        match accept_result {
-                            Ok((client_stream, client_addr)) => {
-                                handle_new_connection(
-                                    client_stream,
-                                    client_addr,
-                                    &semaphore,
-                                    &self,
-                                    Instant::now(),
-                                ).await;
-                                false
-                            }
-                            Err(e) => {
-                                error!("接受连接失败: {}", e);
-                                tokio::time::sleep(Duration::from_millis(100)).await;
-                                false
-                            }
-                        }
-                    }
+                    _ = accept_loop(listener, &semaphore, &ctx) => {}
+                    _ = stop_rx.wait_for(|&stop| stop) => {}
                 }
+            });
+        }
+
+        // 启动 QUIC 监听（仅在配置时）
+        for (socket, target_port) in quic_sockets {
+            let listen_port = socket.local_addr()?.port();
+            let quic_ctx = QuicRelayContext {
+                routes: Arc::clone(&self.routes),
+                ip_matcher: Arc::clone(&self.ip_matcher),
+                acl: self.acl.clone(),
+                blacklist: self.blacklist.clone(),
+                ip_blacklist: self.ip_blacklist.clone(),
+                ban_table: self.ban_table.clone(),
+                geoip: self.geoip.clone(),
+                geo_filter: self.geo_filter.clone(),
+                socks5_upstreams: Arc::clone(&self.socks5_upstreams),
+                outbound: Arc::clone(&self.outbound),
+                pinned_ips: self.pinned_ips.clone(),
+                metrics: self.metrics.with_labels(MetricLabels::listener(ListenerLabel::Quic(listen_port))),
+                events: self.events.clone(),
+                target_port: target_port.unwrap_or_else(|| self.port_mapping.target_port(listen_port)),
+                max_sessions: self.max_connections,
+                idle_timeout: self.idle_timeout,
+                max_lifetime: self.max_connection_lifetime,
+                access_log: self.access_log,
+            };
+            info!("✅ QUIC（HTTP/3）监听已启动: udp://{}", socket.local_addr()?);
+            tokio::spawn(run_quic_relay(socket, quic_ctx, stop_rx.clone()));
+        }
+
+        for (index, std_listener) in listeners {
+            // io_uring 接受的连接在转发阶段交给 io_uring 转发线程；SO_INCOMING_CPU 分流时仍使用绑核的 acceptor
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            if ctx.uring.is_some() && !incoming_cpu_steering {
+                match spawn_uring_acceptor(&std_listener, Arc::clone(&semaphore), ctx.clone(), stop_rx.clone()) {
+                    Ok(()) => continue,
+                    Err(e) => warn!("⚠️  io_uring 接受连接初始化失败，使用 epoll: {}", e),
+                }
+            }
+            if incoming_cpu_steering {
+                // 每个 acceptor 独占一个绑定到对应 CPU 的线程，连接在该线程上处理
+                let cpu = PlatformInfo::detect().acceptor_cpu(index);
+                spawn_pinned_acceptor(std_listener, cpu, Arc::clone(&semaphore), ctx.clone(), stop_rx.clone())?;
+            } else if inline_listener.is_none() {
+                // 第一个 acceptor 在当前任务中运行（与 acceptor_cpu 绑核配置保持一致）
+                inline_listener = Some(TcpListener::from_std(std_listener)?);
             } else {
-                // 没有关闭信号，直接 accept
-                match listener.accept().await {
-                    Ok((client_stream, client_addr)) => {
-                        handle_new_connection(
-                            client_stream,
-                            client_addr,
-                            &semaphore,
-                            &self,
-                            Instant::now(),
-                        ).await;
-                        false
+                let listener = TcpListener::from_std(std_listener)?;
+                let semaphore = Arc::clone(&semaphore);
+                let ctx = ctx.clone();
+                let mut stop_rx = stop_rx.clone();
+                tokio::spawn(async move {
+                    tokio::select! {
+                        _ = accept_loop(listener, &semaphore, &ctx) => {}
+                        _ = stop_rx.wait_for(|&stop| stop) => {}
                     }
-                    Err(e) => {
-                        error!("接受连接失败: {}", e);
-                        tokio::time::sleep(Duration::from_millis(100)).await;
-                        false
-                    }
-                }
-            };
+                });
+            }
+        }
+
+        if incoming_cpu_steering {
+            info!("✅ SO_INCOMING_CPU 分流已启用（{} 个 acceptor 线程各自绑定 CPU）", acceptors);
+        }
 
-            if should_shutdown {
-                break;
+        match inline_listener {
+            Some(listener) => {
+                tokio::select! {
+                    _ = accept_loop(listener, &semaphore, &ctx) => {}
+                    _ = wait_for_shutdown(shutdown_rx) => {}
+                }
             }
+            None => wait_for_shutdown(shutdown_rx).await,
+        }
+
+        info!("🛑 收到关闭信号，停止接受新连接");
+        let _ = stop_tx.send(true);
+        if let ListenAddr::Unix(path) = &self.listen_addr {
+            let _ = std::fs::remove_file(path);
         }
 
+        // 等待活跃连接完成（最多 30 秒）
+        info!("⏳ 等待活跃连接完成...");
+        let wait_start = tokio::time::Instant::now();
+
+        let final_active = drain_connections(&self.metrics, SHUTDOWN_DRAIN_TIMEOUT).await;
+        if final_active > 0 {
+            warn!("⚠️  超时：仍有 {} 个连接未关闭，强制退出", final_active);
+        }
+
+        info!("⏱️  关闭耗时: {:?}", wait_start.elapsed());
+
+        self.save_final_stats();
+
         Ok(())
     }
+
+    /// 启动 io_uring 转发线程，未开启或创建失败时返回 None（使用 epoll 路径）
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    fn start_uring(&self) -> Option<Arc<crate::uring::UringRuntime>> {
+        let workers = self.io_uring_workers?;
+        match crate::uring::UringRuntime::start(workers) {
+            Ok(runtime) => {
+                info!("✅ io_uring 数据路径已启用（{} 个转发线程）", runtime.workers());
+                Some(Arc::new(runtime))
+            }
+            Err(e) => {
+                warn!("⚠️  io_uring 不可用，使用 epoll: {}", e);
+                None
+            }
+        }
+    }
+
+    /// 保存追踪数据并打印最终统计（关闭时调用）
+    fn save_final_stats(&self) {
+        // 保存 IP 流量统计数据
+        if self.ip_traffic_tracker.is_enabled() {
+            info!("💾 保存 IP 流量统计数据...");
+            self.ip_traffic_tracker.save_to_persistence_file();
+        }
+
+        // 保存域名-IP 映射数据
+        if self.domain_ip_tracker.is_enabled() {
+            info!("💾 保存域名-IP 映射数据...");
+            if let Err(e) = self.domain_ip_tracker.save_to_file() {
+                error!("保存域名-IP 映射失败: {}", e);
+            }
+        }
+
+        // 打印最终统计
+        info!("📊 最终统计:");
+        self.metrics.print_summary();
+    }
+}
+
+/// 等待活跃连接结束（每秒检查一次），返回超时后仍未结束的连接数
+async fn drain_connections(metrics: &Metrics, drain_timeout: Duration) -> usize {
+    let deadline = tokio::time::Instant::now() + drain_timeout;
+
+    // 使用循环检查活跃连接数
+    loop {
+        let active = metrics.get_active_connections();
+        if active == 0 {
+            info!("✅ 所有连接已关闭");
+            return 0;
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return active;
+        }
+        info!("⏳ 等待 {} 个活跃连接关闭...", active);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+/// 等待关闭信号（未提供接收器或发送端已丢弃时永不返回）
+async fn wait_for_shutdown(shutdown_rx: Option<watch::Receiver<bool>>) {
+    if let Some(mut rx) = shutdown_rx {
+        if rx.wait_for(|&shutdown| shutdown).await.is_ok() {
+            return;
+        }
+    }
+    std::future::pending::<()>().await
+}
+
+/// 持续接受新连接
+async fn accept_loop<L: Accept>(
+    listener: L,
+    semaphore: &Arc<tokio::sync::Semaphore>,
+    ctx: &ConnectionContext,
+) {
+    use std::time::Instant;
+
+    loop {
+        match listener.accept().await {
+            Ok((client_stream, client_addr)) => {
+                // 记录处理该连接网络包的 CPU，用于验证 SO_INCOMING_CPU 分流效果
+                if let Some(cpu) = client_stream.incoming_cpu() {
+                    ctx.metrics.inc_connections_on_cpu(cpu);
+                }
+
+                handle_new_connection(
+                    client_stream,
+                    client_addr,
+                    semaphore,
+                    ctx,
+                    Instant::now(),
+                ).await;
+            }
+            Err(e) => {
+                error!("接受连接失败: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }
+    }
+}
+
+/// 在绑定到指定 CPU 的独立线程上运行 acceptor
+///
+/// 线程使用单线程 Tokio 运行时，accept 到的连接也在该线程上处理，
+/// 使连接从网卡中断、协议栈到代理转发都留在同一个 CPU 上，减少缓存抖动
+fn spawn_pinned_acceptor(
+    std_listener: std::net::TcpListener,
+    cpu: usize,
+    semaphore: Arc<tokio::sync::Semaphore>,
+    ctx: ConnectionContext,
+    mut stop_rx: watch::Receiver<bool>,
+) -> Result<()> {
+    std::thread::Builder::new()
+        .name(format!("sni-acceptor-{}", cpu))
+        .spawn(move || {
+            if let Err(e) = crate::affinity::pin_current_thread(&[cpu]) {
+                warn!("⚠️  acceptor 线程绑定 CPU {} 失败: {}", cpu, e);
+            }
+
+            let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                Ok(runtime) => runtime,
+                Err(e) => {
+                    error!("创建 acceptor 运行时失败 (CPU {}): {}", cpu, e);
+                    return;
+                }
+            };
+
+            runtime.block_on(async move {
+                let listener = match TcpListener::from_std(std_listener) {
+                    Ok(listener) => listener,
+                    Err(e) => {
+                        error!("acceptor 监听 socket 初始化失败 (CPU {}): {}", cpu, e);
+                        return;
+                    }
+                };
+
+                tokio::select! {
+                    _ = accept_loop(listener, &semaphore, &ctx) => {}
+                    _ = stop_rx.wait_for(|&stop| stop) => {}
+                }
+
+                // 停止接受新连接后继续处理已建立的连接，直到主流程完成等待并退出
+                while stop_rx.changed().await.is_ok() {}
+            });
+        })?;
+
+    Ok(())
+}
+
+/// 用 io_uring 接受 `std_listener` 上的连接（使用复制的监听 socket，失败时调用方继续使用原来的）
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+fn spawn_uring_acceptor(
+    std_listener: &std::net::TcpListener,
+    semaphore: Arc<tokio::sync::Semaphore>,
+    ctx: ConnectionContext,
+    mut stop_rx: watch::Receiver<bool>,
+) -> Result<()> {
+    let listener = crate::uring::UringAcceptor::new(std_listener.try_clone()?)?;
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = accept_loop(listener, &semaphore, &ctx) => {}
+            _ = stop_rx.wait_for(|&stop| stop) => {}
+        }
+    });
+    Ok(())
 }
 
 /// 处理新连接的辅助函数
-async fn handle_new_connection(
-    client_stream: TcpStream,
+async fn handle_new_connection<S: ClientStream>(
+    client_stream: S,
     client_addr: SocketAddr,
     semaphore: &Arc<tokio::sync::Semaphore>,
-    proxy: &SniProxy,
+    ctx: &ConnectionContext,
     accept_start: std::time::Instant,
 ) {
     let accept_elapsed = accept_start.elapsed();
 
+    // 新连接速率超过上限：立即关闭（或排队等待令牌），不占用并发连接许可
+    if let Some(limiter) = &ctx.accept_limiter {
+        if !limiter.admit().await {
+            debug!("新连接速率超过上限，关闭来自 {} 的连接", client_addr);
+            ctx.metrics.inc_overload_rejections();
+            return;
+        }
+    }
+
+    // 开启握手阶段保护：握手阶段只占用握手许可，读取到 Client Hello 后再获取并发连接许可
+    let handshake_permit = match &ctx.handshake_limit {
+        Some(limit) => match limit.try_acquire(semaphore) {
+            Some(permit) => Some(permit),
+            None => {
+                debug!("握手中的连接数已达上限，关闭来自 {} 的连接", client_addr);
+                ctx.metrics.inc_slow_handshake_drops();
+                return;
+            }
+        },
+        None => None,
+    };
+
+    // 并发连接数已满：新连接需要排队等待许可
+    if handshake_permit.is_none() && semaphore.available_permits() == 0 {
+        debug!("并发连接数已达上限 {}，{} 等待许可", ctx.max_connections, client_addr);
+        ctx.events.emit(|| ProxyEvent::QuotaExceeded {
+            client_addr,
+            limit: ctx.max_connections,
+        });
+    }
+
     // ⏱️ 测量获取 permit 耗时
     let permit_start = std::time::Instant::now();
-    let permit = match semaphore.clone().acquire_owned().await {
-        Ok(p) => p,
-        Err(e) => {
-            error!("获取连接许可失败: {}", e);
-            return;
+    let permit = if handshake_permit.is_some() {
+        None
+    } else {
+        match semaphore.clone().acquire_owned().await {
+            Ok(p) => Some(p),
+            Err(e) => {
+                error!("获取连接许可失败: {}", e);
+                return;
+            }
         }
     };
     let permit_elapsed = permit_start.elapsed();
@@ -458,30 +1891,20 @@ async fn handle_new_connection(
     debug!("接受来自 {} 的新连接 (accept: {:?}, permit: {:?})",
            client_addr, accept_elapsed, permit_elapsed);
 
-    let direct_matcher = Arc::clone(&proxy.direct_matcher);
-    let socks5_matcher = proxy.socks5_matcher.clone();
-    let ip_matcher = proxy.ip_matcher.clone();
-    let socks5_config = proxy.socks5_config.clone();
-    let metrics = proxy.metrics.clone();
-    let ip_traffic_tracker = proxy.ip_traffic_tracker.clone();
-    let domain_ip_tracker = proxy.domain_ip_tracker.clone();
+    let ctx = ctx.clone();
+    let metrics = ctx.metrics.clone();
 
     // 使用 catch_unwind 捕获 panic
     tokio::spawn(async move {
-        // 持有许可直到连接处理完成
+        // 持有许可直到连接处理完成（开启握手阶段保护时在读取到 Client Hello 后获取）
         let _permit = permit;
 
         // 捕获 panic 以防止任务崩溃
         let result = std::panic::AssertUnwindSafe(handle_connection(
             client_stream,
             client_addr,
-            direct_matcher,
-            socks5_matcher,
-            ip_matcher,
-            socks5_config,
-            metrics.clone(),
-            ip_traffic_tracker,
-            domain_ip_tracker,
+            ctx,
+            handshake_permit,
         ))
         .catch_unwind()
         .await;
@@ -505,244 +1928,240 @@ async fn handle_new_connection(
 /// ⚡ 优化版本: 更快的超时和更大的缓冲区
 /// 支持分流: 直连白名单和 SOCKS5 白名单
 /// 支持 IP 白名单: 只有在白名单中的 IP 才允许连接
-async fn handle_connection(
-    mut client_stream: TcpStream,
+///
+/// 具体处理流程见 [`ConnectionHandler`] 状态机，这里只负责与 socket 相关的准备工作
+async fn handle_connection<S: ClientStream>(
+    client_stream: S,
     client_addr: SocketAddr,
-    direct_matcher: Arc<DomainMatcher>,
-    socks5_matcher: Option<Arc<DomainMatcher>>,
-    ip_matcher: Option<Arc<IpMatcher>>,
-    socks5_config: Option<Arc<Socks5Config>>,
-    metrics: Metrics,
-    ip_traffic_tracker: IpTrafficTracker,
-    domain_ip_tracker: DomainIpTracker,
+    mut ctx: ConnectionContext,
+    handshake_permit: Option<HandshakePermit>,
 ) -> Result<()> {
-    use std::time::Instant;
-    let start_time = Instant::now();
+    // 按接受连接的监听器标记指标（Unix socket 没有本地端口）
+    let listener = client_stream.local_port().map_or(ListenerLabel::Unix, ListenerLabel::Tcp);
+    ctx.metrics = ctx.metrics.with_labels(MetricLabels::listener(listener));
 
     // 使用 ConnectionGuard 自动管理连接计数
-    let _guard = ConnectionGuard::new(metrics.clone());
+    let _guard = ConnectionGuard::new(ctx.metrics.clone());
 
-    let client_ip = client_addr.ip();
+    // ⚡ 流媒体优化：按接受连接的监听器设置 TCP 参数（缓冲区、TCP_NODELAY、keepalive）
+    client_stream.apply_tuning(ctx.tcp_tuning.listener(client_stream.local_port()));
 
-    // 检查 IP 白名单（如果配置了）
-    let ip_in_whitelist = if let Some(ref ip_matcher) = ip_matcher {
-        if !ip_matcher.matches(client_ip) {
-            let rejected = metrics.get_rejected_requests() + 1;
-            warn!("❌ IP {} 不在白名单中，拒绝连接 | 累计拒绝: {}", client_ip, rejected);
-            metrics.inc_rejected_requests();
-            return Ok(());
-        }
-        debug!("✅ IP {} 通过白名单检查 (来自 {})", client_ip, client_addr);
-        true
-    } else {
-        false
+    // 透明代理：读取连接的原始目标地址（客户端直接连接代理时为 None）
+    let original_dst = ctx
+        .transparent_mode
+        .and_then(|mode| client_stream.original_destination(mode, &ctx.listen_addrs));
+    if let Some(dst) = original_dst {
+        debug!("透明代理原始目标: {} (来自 {})", dst, client_addr);
+    }
+
+    // 透明代理时使用原始目标端口，否则按监听端口映射（默认 443）
+    let target_port = match original_dst {
+        Some(dst) => dst.port(),
+        None => match client_stream.local_port() {
+            Some(local_port) => ctx.port_mapping.target_port(local_port),
+            None => ctx.port_mapping.default_port(),
+        },
     };
 
-    // 如果 IP 在白名单中，记录连接（用于流量统计）
-    if ip_in_whitelist {
-        ip_traffic_tracker.record_connection(client_ip);
+    let reason = ConnectionHandler::new(client_stream, client_addr, target_port, ctx)
+        .with_original_dst(original_dst)
+        .with_handshake_permit(handshake_permit)
+        .run()
+        .await;
+    debug!("连接 {} 结束: {}", client_addr, reason);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::duplex;
+    use tokio::time::Instant;
+
+    fn test_proxy() -> SniProxy {
+        SniProxy::new("127.0.0.1:0".parse::<SocketAddr>().unwrap(), vec!["example.com".to_string()])
     }
 
-    // ⚡ 流媒体优化：设置 TCP 参数（1MB 缓冲区 + TCP_NODELAY）
-    let _ = crate::proxy::optimize_tcp_for_streaming(&client_stream);
+    #[test]
+    fn test_with_listener_dispatch() {
+        use crate::ip_sni::IpSniAction;
+
+        let tls = |addr: &str| ListenerSpec::new(addr.parse::<ListenAddr>().unwrap(), ListenerProtocol::TlsSni);
+        let proxy = test_proxy()
+            .with_listener(ListenerSpec {
+                target_port: Some(993),
+                ip_sni_action: Some(IpSniAction::Reject),
+                ..tls("0.0.0.0:993")
+            })
+            .unwrap()
+            .with_listener(tls("127.0.0.1:0"))
+            .unwrap()
+            .with_listener(ListenerSpec::new("0.0.0.0:443".parse::<SocketAddr>().unwrap(), ListenerProtocol::Quic))
+            .unwrap()
+            .with_listener(ListenerSpec {
+                target_port: Some(443),
+                ..ListenerSpec::new("0.0.0.0:8443".parse::<SocketAddr>().unwrap(), ListenerProtocol::Quic)
+            })
+            .unwrap()
+            .with_listener(ListenerSpec::new("0.0.0.0:80".parse::<SocketAddr>().unwrap(), ListenerProtocol::Http))
+            .unwrap()
+            .with_listener(ListenerSpec::new("0.0.0.0:8080".parse::<SocketAddr>().unwrap(), ListenerProtocol::Http))
+            .unwrap();
+
+        // 与主监听地址相同的 tls_sni 监听器不重复添加
+        assert_eq!(proxy.extra_listen_addrs, vec!["0.0.0.0:993".parse::<SocketAddr>().unwrap()]);
+        assert_eq!(proxy.port_mapping.target_port(993), 993);
+        assert_eq!(proxy.ip_sni_policy.action(Some(993)), IpSniAction::Reject);
+        // 同一协议的多个监听器各自生效，QUIC 监听器可以单独指定目标端口
+        assert_eq!(
+            proxy.quic_listeners,
+            vec![
+                QuicListener { addr: "0.0.0.0:443".parse().unwrap(), target_port: None },
+                QuicListener { addr: "0.0.0.0:8443".parse().unwrap(), target_port: Some(443) },
+            ]
+        );
+        assert_eq!(
+            proxy.http_redirect_addrs,
+            vec!["0.0.0.0:80".parse::<SocketAddr>().unwrap(), "0.0.0.0:8080".parse().unwrap()]
+        );
+
+        let socks5 = ListenerSpec::new("0.0.0.0:1080".parse::<SocketAddr>().unwrap(), ListenerProtocol::Socks5);
+        assert!(test_proxy().with_listener(socks5).is_err());
+        let quic_with_ip_sni = ListenerSpec {
+            ip_sni_action: Some(IpSniAction::Reject),
+            ..ListenerSpec::new("0.0.0.0:443".parse::<SocketAddr>().unwrap(), ListenerProtocol::Quic)
+        };
+        assert!(test_proxy().with_listener(quic_with_ip_sni).is_err());
+        let http_with_rules = ListenerSpec {
+            target_port: Some(443),
+            ..ListenerSpec::new("0.0.0.0:80".parse::<SocketAddr>().unwrap(), ListenerProtocol::Http)
+        };
+        assert!(test_proxy().with_listener(http_with_rules).is_err());
+        assert!(test_proxy().with_listener(tls("unix:/run/other.sock")).is_err());
+    }
 
-    // ⚡ 自适应缓冲区大小：根据系统资源调整
-    // TLS Client Hello 通常 < 4KB，但保留余量
-    // 小型服务器（1-2核）：16KB（节省内存）
-    // 中型服务器（4-8核）：32KB（平衡）
-    // 大型服务器（16+核）：64KB（高性能）
-    let num_cpus = num_cpus::get();
-    let buffer_size = if num_cpus <= 2 {
-        16384  // 16KB
-    } else if num_cpus <= 8 {
-        32768  // 32KB
-    } else {
-        65536  // 64KB
-    };
-    let mut buffer = vec![0u8; buffer_size];
-
-    // ⚡ 自适应超时配置：根据服务器规模调整
-    // 小型服务器：更短超时，快速失败，节省资源
-    // 大型服务器：更长超时，容忍网络抖动
-    let read_timeout_secs = if num_cpus <= 2 {
-        2  // 小型服务器：2秒
-    } else if num_cpus <= 8 {
-        3  // 中型服务器：3秒
-    } else {
-        5  // 大型服务器：5秒
-    };
+    #[tokio::test(start_paused = true)]
+    async fn test_drain_waits_for_connections() {
+        let metrics = Metrics::new();
 
-    // ⚡ 优化：读取 Client Hello 超时自适应
-    let read_start = Instant::now();
-    let n = match timeout(Duration::from_secs(read_timeout_secs), client_stream.read(&mut buffer)).await {
-        Ok(Ok(n)) => n,
-        Ok(Err(e)) => {
-            warn!("读取客户端数据失败: {}", e);
-            metrics.inc_failed_connections();
-            return Ok(());
-        }
-        Err(_) => {
-            warn!("读取客户端数据超时");
-            metrics.inc_connection_timeouts();
-            metrics.inc_failed_connections();
-            return Ok(());
+        // 200 个连接在 1-20 秒内陆续结束
+        for i in 0..200u64 {
+            let guard = ConnectionGuard::new(metrics.clone());
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_secs(i % 20 + 1)).await;
+                drop(guard);
+            });
         }
-    };
 
-    if n == 0 {
-        debug!("客户端连接已关闭");
-        return Ok(());
+        let start = Instant::now();
+        assert_eq!(drain_connections(&metrics, SHUTDOWN_DRAIN_TIMEOUT).await, 0);
+        // 每秒检查一次：最后一个连接在第 20 秒结束，最迟在下一次检查时返回
+        assert!(start.elapsed() >= Duration::from_secs(20));
+        assert!(start.elapsed() <= Duration::from_secs(21));
     }
 
-    buffer.truncate(n);
-    debug!("⏱️  读取 Client Hello 耗时: {:?}", read_start.elapsed());
+    #[tokio::test(start_paused = true)]
+    async fn test_drain_deadline() {
+        let metrics = Metrics::new();
+        let _stuck: Vec<ConnectionGuard> = (0..150).map(|_| ConnectionGuard::new(metrics.clone())).collect();
 
-    // 解析 SNI
-    let sni = match parse_sni(&buffer) {
-        Some(domain) => {
-            debug!("解析到 SNI: {}", domain);
-            domain
+        let start = Instant::now();
+        assert_eq!(drain_connections(&metrics, SHUTDOWN_DRAIN_TIMEOUT).await, 150);
+        assert_eq!(start.elapsed(), SHUTDOWN_DRAIN_TIMEOUT);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_shutdown_releases_handshaking_connections() {
+        const CONNECTIONS: usize = 300;
+        let proxy = test_proxy();
+        let (stop_tx, stop_rx) = watch::channel(false);
+        let ctx = proxy.connection_context(stop_rx);
+        let semaphore = Arc::new(tokio::sync::Semaphore::new(CONNECTIONS + 10));
+
+        // 客户端建立连接后一直不发送 Client Hello
+        let mut clients = Vec::with_capacity(CONNECTIONS);
+        for i in 0..CONNECTIONS {
+            let (client, server) = duplex(1024);
+            clients.push(client);
+            let client_addr = SocketAddr::from(([127, 0, 0, 1], 10000 + i as u16));
+            handle_new_connection(server, client_addr, &semaphore, &ctx, std::time::Instant::now()).await;
         }
-        None => {
-            warn!("无法解析 SNI，拒绝连接");
-            metrics.inc_sni_parse_errors();
-            metrics.inc_failed_connections();
-            return Ok(());
+        while proxy.metrics.get_active_connections() < CONNECTIONS {
+            tokio::task::yield_now().await;
         }
-    };
+        assert_eq!(semaphore.available_permits(), 10);
+
+        let start = Instant::now();
+        stop_tx.send(true).unwrap();
+        assert_eq!(drain_connections(&proxy.metrics, SHUTDOWN_DRAIN_TIMEOUT).await, 0);
+        // 握手阶段的连接收到关闭信号后立即结束，不需要等到读取超时
+        assert!(start.elapsed() <= Duration::from_secs(1));
+        assert_eq!(semaphore.available_permits(), CONNECTIONS + 10);
+        assert_eq!(proxy.metrics.get_total_connections(), CONNECTIONS as u64);
+    }
 
-    // 检查白名单并决定连接方式
-    // ⚡ 延迟优化：减少热路径日志，只在 debug 模式或失败时输出
-    let use_socks5 = if let Some(ref socks5_matcher) = socks5_matcher {
-        // 优先检查 SOCKS5 白名单
-        if socks5_matcher.matches(&sni) {
-            debug!("域名 {} 匹配 SOCKS5 白名单", sni);
-            metrics.inc_socks5_requests();
-            true
-        } else if direct_matcher.matches(&sni) {
-            debug!("域名 {} 匹配直连白名单", sni);
-            metrics.inc_direct_requests();
-            false
-        } else {
-            let rejected = metrics.get_rejected_requests() + 1;
-            warn!("❌ 域名 {} 不在任何白名单中，拒绝连接 | 累计拒绝: {}", sni, rejected);
-            metrics.inc_rejected_requests();
-            return Ok(());
+    #[tokio::test(start_paused = true)]
+    async fn test_handshake_limit_protects_connection_permits() {
+        let proxy = test_proxy().with_handshake_limit(HandshakeConfig {
+            max_handshaking: 5,
+            hello_timeout: Duration::from_secs(1),
+        });
+        let (_stop_tx, stop_rx) = watch::channel(false);
+        let ctx = proxy.connection_context(stop_rx);
+        let semaphore = Arc::new(tokio::sync::Semaphore::new(2));
+
+        // 慢速客户端只占用握手名额，名额用完后新连接立即关闭
+        let mut clients = Vec::new();
+        for i in 0..7 {
+            let (client, server) = duplex(1024);
+            clients.push(client);
+            let client_addr = SocketAddr::from(([127, 0, 0, 1], 10000 + i));
+            handle_new_connection(server, client_addr, &semaphore, &ctx, std::time::Instant::now()).await;
         }
-    } else {
-        // 如果没有 SOCKS5 白名单，只检查直连白名单
-        if direct_matcher.matches(&sni) {
-            debug!("域名 {} 匹配白名单，使用直连", sni);
-            metrics.inc_direct_requests();
-            false
-        } else {
-            let rejected = metrics.get_rejected_requests() + 1;
-            warn!("❌ 域名 {} 不在白名单中，拒绝连接 | 累计拒绝: {}", sni, rejected);
-            metrics.inc_rejected_requests();
-            return Ok(());
+        while proxy.metrics.get_active_connections() < 5 {
+            tokio::task::yield_now().await;
         }
-    };
+        assert_eq!(semaphore.available_permits(), 2);
+        assert_eq!(proxy.metrics.snapshot().slow_handshake_drops, 2);
 
-    // 连接到目标服务器
-    let connect_start = Instant::now();
-    let target_stream = if use_socks5 && socks5_config.is_some() {
-        // 通过 SOCKS5 连接
-        let socks5 = socks5_config.as_ref().unwrap();
-        debug!("通过 SOCKS5 连接到 {}:443", sni);
-        match connect_via_socks5(&sni, 443, socks5.as_ref()).await {
-            Ok(stream) => {
-                debug!("⏱️  SOCKS5 连接 {} 耗时: {:?}", sni, connect_start.elapsed());
-                // 记录通过 SOCKS5 的域名（无法获取实际解析的 IP）
-                domain_ip_tracker.record_socks5(&sni);
-                stream
-            },
-            Err(e) => {
-                error!("通过 SOCKS5 连接到 {}:443 失败: {} (耗时 {:?})", sni, e, connect_start.elapsed());
-                metrics.inc_socks5_errors();
-                metrics.inc_failed_connections();
-                return Ok(());
-            }
+        // 超过 Client Hello 截止时间后断开
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        while proxy.metrics.get_active_connections() > 0 {
+            tokio::task::yield_now().await;
         }
-    } else {
-        // 直接连接
-        // ⚡ 先解析 DNS，获取 IP 地址，用于域名-IP 追踪
-        let resolved_ips = match resolve_host_cached(&sni).await {
-            Ok(ips) => {
-                // 记录域名和所有解析出的 IP
-                for ip in &ips {
-                    domain_ip_tracker.record(&sni, *ip);
-                }
-                ips
-            },
-            Err(e) => {
-                error!("DNS 解析失败 {}: {}", sni, e);
-                metrics.inc_failed_connections();
-                return Ok(());
-            }
-        };
+        assert_eq!(proxy.metrics.snapshot().slow_handshake_drops, 7);
+        assert_eq!(semaphore.available_permits(), 2);
+    }
 
-        // ⚡ 自适应连接超时：根据服务器规模调整
-        let connect_timeout_secs = if num_cpus <= 2 {
-            3  // 小型服务器：3秒（快速失败）
-        } else if num_cpus <= 8 {
-            5  // 中型服务器：5秒
-        } else {
-            8  // 大型服务器：8秒（容忍慢网络）
-        };
+    #[tokio::test(start_paused = true)]
+    async fn test_final_stats_saved_on_shutdown() {
+        let dir = std::env::temp_dir().join(format!("sni-proxy-shutdown-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let traffic_file = dir.join("traffic.json");
+        let domain_file = dir.join("domains.txt");
+
+        let proxy = test_proxy()
+            .with_ip_traffic_tracking(100, None, Some(traffic_file.to_string_lossy().into_owned()))
+            .with_domain_ip_tracking(Some(domain_file.to_string_lossy().into_owned()));
+        proxy.domain_ip_tracker.record("example.com", "93.184.216.34".parse().unwrap());
+        let proxy = Arc::new(proxy);
+
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let server = tokio::spawn({
+            let proxy = Arc::clone(&proxy);
+            async move { proxy.run_with_shutdown(Some(shutdown_rx)).await }
+        });
+        tokio::task::yield_now().await;
 
-        // 尝试连接到第一个 IP
-        let target_addr = (resolved_ips[0], 443);
-        match timeout(
-            Duration::from_secs(connect_timeout_secs),
-            TcpStream::connect(target_addr)
-        ).await {
-            Ok(Ok(stream)) => stream,
-            Ok(Err(e)) => {
-                error!("连接到目标服务器 {}:{} 失败: {}", resolved_ips[0], 443, e);
-                metrics.inc_failed_connections();
-                return Ok(());
-            }
-            Err(_) => {
-                error!("连接到目标服务器 {}:{} 超时", resolved_ips[0], 443);
-                metrics.inc_connection_timeouts();
-                metrics.inc_failed_connections();
-                return Ok(());
-            }
-        }
-    };
+        let start = Instant::now();
+        shutdown_tx.send(true).unwrap();
+        server.await.unwrap().unwrap();
 
-    // ⚡ 流媒体优化：设置目标连接的 TCP 参数
-    let mut target_stream = target_stream;
-    let _ = crate::proxy::optimize_tcp_for_streaming(&target_stream);
-
-    // ⚡ 延迟优化：只在 debug 模式记录成功连接
-    debug!("✅ 连接到 {}:443 成功 (耗时: {:?})", sni, connect_start.elapsed());
-
-    // 转发 Client Hello
-    if let Err(e) = target_stream.write_all(&buffer).await {
-        error!("转发 Client Hello 失败: {}", e);
-        return Ok(());
-    }
-
-    // 双向转发数据
-    let proxy_start = Instant::now();
-    if let Err(e) = proxy_data(
-        client_stream,
-        target_stream,
-        metrics.clone(),
-        client_ip,
-        ip_traffic_tracker.clone(),
-    )
-    .await
-    {
-        debug!("数据转发结束: {}", e);
-    }
-
-    // ⚡ 延迟优化：性能统计只在 debug 模式输出
-    debug!("⏱️  {} 总耗时: {:?} (连接: {:?}, 转发: {:?})",
-          sni,
-          start_time.elapsed(),
-          connect_start.elapsed(),
-          proxy_start.elapsed());
-    Ok(())
+        // 没有活跃连接时不等待
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(traffic_file.exists());
+        assert!(std::fs::read_to_string(&domain_file).unwrap().contains("example.com"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
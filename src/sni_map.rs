@@ -0,0 +1,103 @@
+//! 按域名查表
+//!
+//! - SNI → 后端映射表：指定的 SNI（或 HTTP Host）直接转发到配置的后端地址，而不是按 SNI 做 DNS 解析，
+//!   例如 `internal.example.com → 10.0.0.5:8443`，用于按域名分发到内部服务（虚拟主机路由）
+//! - 固定 IP：直连指定域名时使用配置的 IP，不经过 DNS 解析，例如把 CDN 域名固定到某个边缘节点
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use crate::backend::BackendAddr;
+
+/// SNI → 后端映射表
+pub type SniBackendMap = DomainMap<Arc<BackendAddr>>;
+
+/// 域名 → 固定 IP 表
+pub type PinnedIps = DomainMap<Arc<[IpAddr]>>;
+
+/// 域名到值的映射，支持精确匹配和通配符（`*.example.com`）
+#[derive(Debug, Clone)]
+pub struct DomainMap<T> {
+    /// 精确匹配的域名（小写）
+    exact: HashMap<String, T>,
+    /// 通配符后缀（去掉 "*."），按长度降序排列，更具体的规则优先
+    wildcard: Vec<(String, T)>,
+}
+
+impl<T> Default for DomainMap<T> {
+    fn default() -> Self {
+        Self {
+            exact: HashMap::new(),
+            wildcard: Vec::new(),
+        }
+    }
+}
+
+impl<T> DomainMap<T> {
+    /// 创建映射表（域名不区分大小写）
+    pub fn new(entries: impl IntoIterator<Item = (String, T)>) -> Self {
+        let mut map = Self::default();
+        for (domain, value) in entries {
+            let domain = domain.to_lowercase();
+            match domain.strip_prefix("*.") {
+                Some(suffix) if !suffix.is_empty() => map.wildcard.push((suffix.to_string(), value)),
+                Some(_) => {}
+                None => {
+                    map.exact.insert(domain, value);
+                }
+            }
+        }
+        map.wildcard.sort_by_key(|(suffix, _)| std::cmp::Reverse(suffix.len()));
+        map
+    }
+
+    /// 查找域名对应的值（精确匹配优先）
+    pub fn lookup(&self, domain: &str) -> Option<&T> {
+        let domain = domain.to_lowercase();
+        if let Some(value) = self.exact.get(&domain) {
+            return Some(value);
+        }
+        self.wildcard
+            .iter()
+            .find(|(suffix, _)| {
+                domain.len() > suffix.len() + 1
+                    && domain.ends_with(suffix.as_str())
+                    && domain.as_bytes()[domain.len() - suffix.len() - 1] == b'.'
+            })
+            .map(|(_, value)| value)
+    }
+
+    /// 映射规则数量
+    pub fn len(&self) -> usize {
+        self.exact.len() + self.wildcard.len()
+    }
+
+    /// 是否没有映射规则
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let backend = |addr: &str| Arc::new(addr.parse::<BackendAddr>().unwrap());
+        let map = SniBackendMap::new([
+            ("internal.example.com".to_string(), backend("10.0.0.5:8443")),
+            ("*.example.com".to_string(), backend("10.0.0.6:443")),
+            ("*.svc.example.com".to_string(), backend("10.0.0.7:443")),
+        ]);
+        assert_eq!(map.len(), 3);
+
+        let port = |sni: &str| map.lookup(sni).map(|backend| backend.port);
+        assert_eq!(port("INTERNAL.example.com"), Some(8443));
+        assert_eq!(port("www.example.com"), Some(443));
+        assert_eq!(map.lookup("api.svc.example.com").unwrap().host, "10.0.0.7");
+        assert_eq!(port("example.com"), None);
+        assert_eq!(port("notexample.com"), None);
+    }
+}
@@ -1,4 +1,3 @@
-use anyhow::Result;
 use log::{debug, info};
 use std::net::SocketAddr;
 use std::time::Duration;
@@ -6,8 +5,11 @@ use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
 use tokio::time::timeout;
 
+use crate::error::{Result, SniProxyError};
+use crate::outbound::OutboundOptions;
+
 /// SOCKS5 代理配置
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Socks5Config {
     /// SOCKS5 代理服务器地址
     pub addr: SocketAddr,
@@ -32,20 +34,82 @@ pub async fn connect_via_socks5(
     target_host: &str,
     target_port: u16,
     socks5_config: &Socks5Config,
+) -> Result<TcpStream> {
+    connect_via_socks5_with(target_host, target_port, socks5_config, &OutboundOptions::default()).await
+}
+
+/// 同 [`connect_via_socks5`]，连接 SOCKS5 服务器时使用指定的出站选项（源地址等）
+pub async fn connect_via_socks5_with(
+    target_host: &str,
+    target_port: u16,
+    socks5_config: &Socks5Config,
+    outbound: &OutboundOptions,
 ) -> Result<TcpStream> {
     info!("通过 SOCKS5 连接到 {}:{}", target_host, target_port);
 
+    let mut socks5_stream = open_session(socks5_config, outbound).await?;
+
+    // ============ 步骤 6: 发送连接请求 ============
+    // 构建连接请求：
+    // +----+-----+-------+------+----------+----------+
+    // |VER | CMD |  RSV  | ATYP | DST.ADDR | DST.PORT |
+    // +----+-----+-------+------+----------+----------+
+    // | 1  |  1  | X'00' |  1   | Variable |    2     |
+    // +----+-----+-------+------+----------+----------+
+    // CMD:
+    //   o  CONNECT X'01'
+    //   o  BIND X'02'
+    //   o  UDP ASSOCIATE X'03'
+    // ATYP:
+    //   o  IPv4 address: X'01'
+    //   o  DOMAINNAME: X'03'
+    //   o  IPv6 address: X'04'
+
+    let mut connect_request = Vec::new();
+    connect_request.push(5u8);   // SOCKS 版本 5
+    connect_request.push(1u8);   // 连接命令 (CONNECT)
+    connect_request.push(0u8);   // 保留字段
+
+    // ⚡ 优化：直接使用域名，让 SOCKS5 服务器解析 DNS
+    if target_host.len() > 255 {
+        return Err(SniProxyError::InvalidTarget(format!("域名太长: {}", target_host)));
+    }
+    connect_request.push(0x03);  // 域名类型
+    connect_request.push(target_host.len() as u8);  // 域名长度
+    connect_request.extend_from_slice(target_host.as_bytes());  // 域名
+
+    // 目标端口（网络字节序）
+    connect_request.extend_from_slice(&target_port.to_be_bytes());
+
+    // 发送连接请求
+    match timeout(
+        Duration::from_secs(5),
+        socks5_stream.write_all(&connect_request)
+    ).await {
+        Ok(Ok(())) => debug!("已发送 SOCKS5 连接请求"),
+        Ok(Err(e)) => return Err(SniProxyError::Socks5Io { stage: "发送 SOCKS5 连接请求", source: e }),
+        Err(_) => return Err(SniProxyError::Timeout { operation: "发送 SOCKS5 连接请求" }),
+    }
+
+    read_reply(&mut socks5_stream).await?;
+
+    info!("✅ 通过 SOCKS5 成功连接到 {}:{}", target_host, target_port);
+    Ok(socks5_stream)
+}
+
+/// 连接到 SOCKS5 服务器并完成握手和认证（步骤 1-5）
+async fn open_session(socks5_config: &Socks5Config, outbound: &OutboundOptions) -> Result<TcpStream> {
     // ============ 步骤 1: 连接到 SOCKS5 服务器 ============
     let mut socks5_stream = match timeout(
         Duration::from_secs(5),
-        TcpStream::connect(&socks5_config.addr)
+        outbound.connect(socks5_config.addr)
     ).await {
         Ok(Ok(stream)) => stream,
         Ok(Err(e)) => {
-            return Err(anyhow::anyhow!("无法连接到 SOCKS5 服务器 {}: {}", socks5_config.addr, e));
+            return Err(SniProxyError::Socks5Connect { addr: socks5_config.addr, source: e });
         }
         Err(_) => {
-            return Err(anyhow::anyhow!("连接到 SOCKS5 服务器 {} 超时", socks5_config.addr));
+            return Err(SniProxyError::Socks5ConnectTimeout { addr: socks5_config.addr });
         }
     };
 
@@ -101,8 +165,8 @@ pub async fn connect_via_socks5(
         socks5_stream.write_all(&request)
     ).await {
         Ok(Ok(())) => debug!("已发送 SOCKS5 握手请求"),
-        Ok(Err(e)) => return Err(anyhow::anyhow!("写入 SOCKS5 握手请求失败: {}", e)),
-        Err(_) => return Err(anyhow::anyhow!("写入 SOCKS5 握手请求超时")),
+        Ok(Err(e)) => return Err(SniProxyError::Socks5Io { stage: "写入 SOCKS5 握手请求", source: e }),
+        Err(_) => return Err(SniProxyError::Timeout { operation: "写入 SOCKS5 握手请求" }),
     }
 
     // ============ 步骤 4: 读取握手响应 ============
@@ -114,12 +178,12 @@ pub async fn connect_via_socks5(
         Ok(Ok(n)) => {
             debug!("读取握手响应成功，字节数: {}", n)
         },
-        Ok(Err(e)) => return Err(anyhow::anyhow!("读取 SOCKS5 握手响应失败: {}", e)),
-        Err(_) => return Err(anyhow::anyhow!("读取 SOCKS5 握手响应超时")),
+        Ok(Err(e)) => return Err(SniProxyError::Socks5Io { stage: "读取 SOCKS5 握手响应", source: e }),
+        Err(_) => return Err(SniProxyError::Timeout { operation: "读取 SOCKS5 握手响应" }),
     }
 
     if response[0] != 5 {
-        return Err(anyhow::anyhow!("无效的 SOCKS5 响应: 版本错误"));
+        return Err(SniProxyError::Socks5Protocol("版本错误".to_string()));
     }
 
     debug!("SOCKS5 握手成功，选择的认证方法: {}", response[1]);
@@ -142,8 +206,8 @@ pub async fn connect_via_socks5(
                 socks5_stream.write_all(&auth_request)
             ).await {
                 Ok(Ok(())) => debug!("已发送认证请求"),
-                Ok(Err(e)) => return Err(anyhow::anyhow!("发送认证请求失败: {}", e)),
-                Err(_) => return Err(anyhow::anyhow!("发送认证请求超时")),
+                Ok(Err(e)) => return Err(SniProxyError::Socks5Io { stage: "发送认证请求", source: e }),
+                Err(_) => return Err(SniProxyError::Timeout { operation: "发送认证请求" }),
             }
 
             // 读取认证响应
@@ -153,61 +217,24 @@ pub async fn connect_via_socks5(
                 socks5_stream.read_exact(&mut auth_response)
             ).await {
                 Ok(Ok(_)) => {},
-                Ok(Err(e)) => return Err(anyhow::anyhow!("读取认证响应失败: {}", e)),
-                Err(_) => return Err(anyhow::anyhow!("读取认证响应超时")),
+                Ok(Err(e)) => return Err(SniProxyError::Socks5Io { stage: "读取认证响应", source: e }),
+                Err(_) => return Err(SniProxyError::Timeout { operation: "读取认证响应" }),
             }
 
             if auth_response[1] != 0 {
-                return Err(anyhow::anyhow!("SOCKS5 认证失败"));
+                return Err(SniProxyError::Socks5AuthFailed);
             }
             debug!("SOCKS5 认证成功");
         }
     } else if response[1] != 0 {
-        return Err(anyhow::anyhow!("不支持的认证方法: {}", response[1]));
-    }
-
-    // ============ 步骤 6: 发送连接请求 ============
-    // 构建连接请求：
-    // +----+-----+-------+------+----------+----------+
-    // |VER | CMD |  RSV  | ATYP | DST.ADDR | DST.PORT |
-    // +----+-----+-------+------+----------+----------+
-    // | 1  |  1  | X'00' |  1   | Variable |    2     |
-    // +----+-----+-------+------+----------+----------+
-    // CMD:
-    //   o  CONNECT X'01'
-    //   o  BIND X'02'
-    //   o  UDP ASSOCIATE X'03'
-    // ATYP:
-    //   o  IPv4 address: X'01'
-    //   o  DOMAINNAME: X'03'
-    //   o  IPv6 address: X'04'
-
-    let mut connect_request = Vec::new();
-    connect_request.push(5u8);   // SOCKS 版本 5
-    connect_request.push(1u8);   // 连接命令 (CONNECT)
-    connect_request.push(0u8);   // 保留字段
-
-    // ⚡ 优化：直接使用域名，让 SOCKS5 服务器解析 DNS
-    if target_host.len() > 255 {
-        return Err(anyhow::anyhow!("域名太长: {}", target_host));
+        return Err(SniProxyError::Socks5Protocol(format!("不支持的认证方法: {}", response[1])));
     }
-    connect_request.push(0x03);  // 域名类型
-    connect_request.push(target_host.len() as u8);  // 域名长度
-    connect_request.extend_from_slice(target_host.as_bytes());  // 域名
 
-    // 目标端口（网络字节序）
-    connect_request.extend_from_slice(&target_port.to_be_bytes());
-
-    // 发送连接请求
-    match timeout(
-        Duration::from_secs(5),
-        socks5_stream.write_all(&connect_request)
-    ).await {
-        Ok(Ok(())) => debug!("已发送 SOCKS5 连接请求"),
-        Ok(Err(e)) => return Err(anyhow::anyhow!("发送 SOCKS5 连接请求失败: {}", e)),
-        Err(_) => return Err(anyhow::anyhow!("发送 SOCKS5 连接请求超时")),
-    }
+    Ok(socks5_stream)
+}
 
+/// 读取 CONNECT / UDP ASSOCIATE 响应（步骤 7-8），返回服务器绑定的地址（域名类型时为 None）
+async fn read_reply(socks5_stream: &mut TcpStream) -> Result<Option<SocketAddr>> {
     // ============ 步骤 7: 读取连接响应 ============
     let mut response = [0u8; 4];
     match timeout(
@@ -215,31 +242,23 @@ pub async fn connect_via_socks5(
         socks5_stream.read_exact(&mut response)
     ).await {
         Ok(Ok(_)) => {},
-        Ok(Err(e)) => return Err(anyhow::anyhow!("读取 SOCKS5 连接响应失败: {}", e)),
-        Err(_) => return Err(anyhow::anyhow!("读取 SOCKS5 连接响应超时")),
+        Ok(Err(e)) => return Err(SniProxyError::Socks5Io { stage: "读取 SOCKS5 连接响应", source: e }),
+        Err(_) => return Err(SniProxyError::Timeout { operation: "读取 SOCKS5 连接响应" }),
     }
 
     if response[0] != 5 {
-        return Err(anyhow::anyhow!("无效的 SOCKS5 响应: 版本错误"));
+        return Err(SniProxyError::Socks5Protocol("版本错误".to_string()));
     }
 
     // 检查状态码
     match response[1] {
         0 => debug!("SOCKS5 连接成功"),
-        1 => return Err(anyhow::anyhow!("SOCKS5: 一般 SOCKS 服务器故障")),
-        2 => return Err(anyhow::anyhow!("SOCKS5: 连接规则集不允许的连接")),
-        3 => return Err(anyhow::anyhow!("SOCKS5: 网络无法访问")),
-        4 => return Err(anyhow::anyhow!("SOCKS5: 主机无法访问")),
-        5 => return Err(anyhow::anyhow!("SOCKS5: 连接被拒绝")),
-        6 => return Err(anyhow::anyhow!("SOCKS5: TTL 过期")),
-        7 => return Err(anyhow::anyhow!("SOCKS5: 不支持的命令")),
-        8 => return Err(anyhow::anyhow!("SOCKS5: 不支持的地址类型")),
-        code => return Err(anyhow::anyhow!("SOCKS5: 未知错误代码 {}", code)),
+        code => return Err(SniProxyError::Socks5Rejected { code }),
     }
 
     // ============ 步骤 8: 读取剩余的响应数据 ============
     // 根据地址类型读取相应的数据
-    match response[3] {
+    let bound = match response[3] {
         1 => {
             // IPv4: 需要读 4 个字节 IP + 2 个字节端口
             let mut addr_data = [0u8; 6];
@@ -248,13 +267,15 @@ pub async fn connect_via_socks5(
                 socks5_stream.read_exact(&mut addr_data)
             ).await {
                 Ok(Ok(_)) => {},
-                Ok(Err(e)) => return Err(anyhow::anyhow!("读取地址数据失败: {}", e)),
-                Err(_) => return Err(anyhow::anyhow!("读取地址数据超时")),
+                Ok(Err(e)) => return Err(SniProxyError::Socks5Io { stage: "读取地址数据", source: e }),
+                Err(_) => return Err(SniProxyError::Timeout { operation: "读取地址数据" }),
             }
             debug!("SOCKS5 连接响应 - IPv4 地址: {}.{}.{}.{}, 端口: {}",
                 addr_data[0], addr_data[1], addr_data[2], addr_data[3],
                 u16::from_be_bytes([addr_data[4], addr_data[5]])
             );
+            let ip = std::net::Ipv4Addr::new(addr_data[0], addr_data[1], addr_data[2], addr_data[3]);
+            Ok(Some(SocketAddr::new(ip.into(), u16::from_be_bytes([addr_data[4], addr_data[5]]))))
         }
         4 => {
             // IPv6: 需要读 16 个字节 IP + 2 个字节端口
@@ -264,12 +285,15 @@ pub async fn connect_via_socks5(
                 socks5_stream.read_exact(&mut addr_data)
             ).await {
                 Ok(Ok(_)) => {},
-                Ok(Err(e)) => return Err(anyhow::anyhow!("读取地址数据失败: {}", e)),
-                Err(_) => return Err(anyhow::anyhow!("读取地址数据超时")),
+                Ok(Err(e)) => return Err(SniProxyError::Socks5Io { stage: "读取地址数据", source: e }),
+                Err(_) => return Err(SniProxyError::Timeout { operation: "读取地址数据" }),
             }
             debug!("SOCKS5 连接响应 - IPv6 地址, 端口: {}",
                 u16::from_be_bytes([addr_data[16], addr_data[17]])
             );
+            let octets: [u8; 16] = addr_data[..16].try_into().unwrap();
+            let ip = std::net::Ipv6Addr::from(octets);
+            Ok(Some(SocketAddr::new(ip.into(), u16::from_be_bytes([addr_data[16], addr_data[17]]))))
         }
         3 => {
             // 域名: 需要读 1 个字节长度 + N 个字节域名 + 2 个字节端口
@@ -279,8 +303,8 @@ pub async fn connect_via_socks5(
                 socks5_stream.read_exact(&mut len_buf)
             ).await {
                 Ok(Ok(_)) => {},
-                Ok(Err(e)) => return Err(anyhow::anyhow!("读取域名长度失败: {}", e)),
-                Err(_) => return Err(anyhow::anyhow!("读取域名长度超时")),
+                Ok(Err(e)) => return Err(SniProxyError::Socks5Io { stage: "读取域名长度", source: e }),
+                Err(_) => return Err(SniProxyError::Timeout { operation: "读取域名长度" }),
             }
 
             let domain_len = len_buf[0] as usize;
@@ -290,19 +314,162 @@ pub async fn connect_via_socks5(
                 socks5_stream.read_exact(&mut domain_data)
             ).await {
                 Ok(Ok(_)) => {},
-                Ok(Err(e)) => return Err(anyhow::anyhow!("读取域名数据失败: {}", e)),
-                Err(_) => return Err(anyhow::anyhow!("读取域名数据超时")),
+                Ok(Err(e)) => return Err(SniProxyError::Socks5Io { stage: "读取域名数据", source: e }),
+                Err(_) => return Err(SniProxyError::Timeout { operation: "读取域名数据" }),
             }
 
             let domain = String::from_utf8_lossy(&domain_data[..domain_len]);
             let port = u16::from_be_bytes([domain_data[domain_len], domain_data[domain_len + 1]]);
             debug!("SOCKS5 连接响应 - 域名: {}, 端口: {}", domain, port);
+            Ok(None)
         }
         atyp => {
-            return Err(anyhow::anyhow!("不支持的地址类型: {}", atyp));
+            return Err(SniProxyError::Socks5Protocol(format!("不支持的地址类型: {}", atyp)));
         }
+    };
+
+    bound
+}
+
+/// SOCKS5 UDP 关联
+///
+/// 控制连接关闭后服务器会释放关联，因此需要在转发期间一直持有 `control`
+#[derive(Debug)]
+pub struct Socks5UdpAssociation {
+    /// 控制连接
+    pub control: TcpStream,
+    /// 服务器的 UDP 中继地址
+    pub relay_addr: SocketAddr,
+}
+
+/// 通过 SOCKS5 建立 UDP 关联（UDP ASSOCIATE）
+///
+/// 请求中的客户端地址填 0.0.0.0:0，由服务器按第一个数据报的来源确定；控制连接按 `outbound` 建立
+pub async fn udp_associate_via_socks5(
+    socks5_config: &Socks5Config,
+    outbound: &OutboundOptions,
+) -> Result<Socks5UdpAssociation> {
+    info!("通过 SOCKS5 建立 UDP 关联: {}", socks5_config.addr);
+
+    let mut socks5_stream = open_session(socks5_config, outbound).await?;
+
+    let request = [5u8, 3, 0, 1, 0, 0, 0, 0, 0, 0];
+    match timeout(
+        Duration::from_secs(5),
+        socks5_stream.write_all(&request)
+    ).await {
+        Ok(Ok(())) => debug!("已发送 SOCKS5 UDP 关联请求"),
+        Ok(Err(e)) => return Err(SniProxyError::Socks5Io { stage: "发送 SOCKS5 UDP 关联请求", source: e }),
+        Err(_) => return Err(SniProxyError::Timeout { operation: "发送 SOCKS5 UDP 关联请求" }),
     }
 
-    info!("✅ 通过 SOCKS5 成功连接到 {}:{}", target_host, target_port);
-    Ok(socks5_stream)
+    let relay_addr = match read_reply(&mut socks5_stream).await? {
+        // 服务器返回未指定地址时，中继地址与 SOCKS5 服务器相同
+        Some(addr) if addr.ip().is_unspecified() => SocketAddr::new(socks5_config.addr.ip(), addr.port()),
+        Some(addr) => addr,
+        None => {
+            return Err(SniProxyError::Socks5Protocol("UDP 中继地址不支持域名类型".to_string()));
+        }
+    };
+
+    info!("✅ SOCKS5 UDP 关联已建立，中继地址: {}", relay_addr);
+    Ok(Socks5UdpAssociation { control: socks5_stream, relay_addr })
+}
+
+/// 为发往 SOCKS5 UDP 中继的数据报添加请求头（RFC 1928 第 7 节）
+pub fn encode_udp_datagram(target_host: &str, target_port: u16, payload: &[u8]) -> Result<Vec<u8>> {
+    if target_host.len() > 255 {
+        return Err(SniProxyError::InvalidTarget(format!("域名太长: {}", target_host)));
+    }
+    let mut datagram = Vec::with_capacity(7 + target_host.len() + payload.len());
+    datagram.extend_from_slice(&[0, 0, 0, 0x03, target_host.len() as u8]);
+    datagram.extend_from_slice(target_host.as_bytes());
+    datagram.extend_from_slice(&target_port.to_be_bytes());
+    datagram.extend_from_slice(payload);
+    Ok(datagram)
+}
+
+/// 去掉 SOCKS5 UDP 中继返回的数据报头，返回负载
+///
+/// 不支持分片（FRAG 不为 0 的数据报直接丢弃）
+pub fn decode_udp_datagram(datagram: &[u8]) -> Option<&[u8]> {
+    if datagram.len() < 4 || datagram[2] != 0 {
+        return None;
+    }
+    let header_len = match datagram[3] {
+        1 => 4 + 4 + 2,
+        4 => 4 + 16 + 2,
+        3 => 4 + 1 + *datagram.get(4)? as usize + 2,
+        _ => return None,
+    };
+    datagram.get(header_len..)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_socks5_rejected_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = Socks5Config {
+            addr: listener.local_addr().unwrap(),
+            username: None,
+            password: None,
+        };
+
+        // 模拟 SOCKS5 服务器：接受无认证握手，然后以 "连接被拒绝"（REP = 5）响应
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut greeting = [0u8; 3];
+            stream.read_exact(&mut greeting).await.unwrap();
+            stream.write_all(&[5, 0]).await.unwrap();
+            let mut request = [0u8; 256];
+            let _ = stream.read(&mut request).await.unwrap();
+            stream.write_all(&[5, 5, 0, 1, 0, 0, 0, 0, 0, 0]).await.unwrap();
+        });
+
+        let err = connect_via_socks5("example.com", 443, &config).await.unwrap_err();
+        assert!(matches!(err, SniProxyError::Socks5Rejected { code: 5 }));
+    }
+
+    #[tokio::test]
+    async fn test_udp_associate_unspecified_relay_addr() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = Socks5Config {
+            addr: listener.local_addr().unwrap(),
+            username: None,
+            password: None,
+        };
+
+        // 模拟 SOCKS5 服务器：返回 0.0.0.0:40000 作为中继地址
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut greeting = [0u8; 3];
+            stream.read_exact(&mut greeting).await.unwrap();
+            stream.write_all(&[5, 0]).await.unwrap();
+            let mut request = [0u8; 10];
+            stream.read_exact(&mut request).await.unwrap();
+            assert_eq!(request[1], 3);
+            let port = 40000u16.to_be_bytes();
+            stream.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, port[0], port[1]]).await.unwrap();
+            let _ = stream.read(&mut [0u8; 1]).await;
+        });
+
+        let association = udp_associate_via_socks5(&config, &OutboundOptions::default()).await.unwrap();
+        assert_eq!(association.relay_addr, "127.0.0.1:40000".parse().unwrap());
+    }
+
+    #[test]
+    fn test_udp_datagram_header() {
+        let datagram = encode_udp_datagram("example.com", 443, b"payload").unwrap();
+        assert_eq!(&datagram[..5], &[0, 0, 0, 3, 11]);
+        assert_eq!(decode_udp_datagram(&datagram), Some(&b"payload"[..]));
+
+        let ipv4 = [0, 0, 0, 1, 1, 2, 3, 4, 0, 53, 0xaa];
+        assert_eq!(decode_udp_datagram(&ipv4), Some(&[0xaa][..]));
+        // 分片数据报不支持
+        assert_eq!(decode_udp_datagram(&[0, 0, 1, 1, 1, 2, 3, 4, 0, 53]), None);
+    }
 }
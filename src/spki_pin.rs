@@ -0,0 +1,125 @@
+//! SPKI 公钥指纹
+//!
+//! DNS-over-TLS 服务器可以按公钥指纹认证（RFC 7858 的 SPKI pinning）：指纹是证书中
+//! SubjectPublicKeyInfo（DER 编码）的 SHA-256 摘要，写成 `sha256/<Base64>`，与 HPKP 和 Stubby 的格式相同，
+//! 可以用 `openssl x509 -pubkey -noout | openssl pkey -pubin -outform der | openssl dgst -sha256 -binary | base64` 计算。
+//! 配置了指纹时只检查服务器证书的公钥，不再按 CA 校验证书链，自签名证书的服务器也可以使用
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::{Certificate, ClientConfig, ServerName};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::SystemTime;
+use x509_parser::certificate::X509Certificate;
+use x509_parser::prelude::FromDer;
+
+use crate::error::{Result, SniProxyError};
+
+/// 公钥指纹（SHA-256 摘要的 Base64 编码）
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SpkiPin(String);
+
+impl SpkiPin {
+    /// 计算 SubjectPublicKeyInfo（DER）的指纹
+    pub fn of(spki: &[u8]) -> Self {
+        SpkiPin(BASE64.encode(Sha256::digest(spki)))
+    }
+
+    /// 计算证书（DER）中公钥的指纹，证书格式不对时返回 None
+    pub fn of_certificate(cert: &[u8]) -> Option<Self> {
+        let (_, cert) = X509Certificate::from_der(cert).ok()?;
+        Some(Self::of(cert.public_key().raw))
+    }
+}
+
+impl FromStr for SpkiPin {
+    type Err = SniProxyError;
+
+    /// 解析 `sha256/<Base64>`（前缀可省略）
+    fn from_str(s: &str) -> Result<Self> {
+        let digest = s.strip_prefix("sha256/").unwrap_or(s);
+        let valid = BASE64.decode(digest).is_ok_and(|digest| digest.len() == 32);
+        if !valid {
+            return Err(SniProxyError::InvalidConfig(format!(
+                "无效的 SPKI 指纹: {}（格式: sha256/<SHA-256 摘要的 Base64 编码>）",
+                s
+            )));
+        }
+        Ok(SpkiPin(digest.to_string()))
+    }
+}
+
+impl fmt::Display for SpkiPin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "sha256/{}", self.0)
+    }
+}
+
+/// 按公钥指纹校验服务器证书
+///
+/// 只认服务器证书（证书链第一个）本身的公钥：不校验证书链时，中间证书可以由任何人附带，不能作为认证依据。
+/// 握手签名仍然由 rustls 用服务器证书的公钥验证，对方必须持有对应的私钥
+struct PinVerifier {
+    pins: Vec<SpkiPin>,
+}
+
+impl ServerCertVerifier for PinVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        match SpkiPin::of_certificate(&end_entity.0) {
+            Some(pin) if self.pins.contains(&pin) => Ok(ServerCertVerified::assertion()),
+            Some(pin) => Err(rustls::Error::General(format!("服务器证书的公钥指纹 {} 不在配置的指纹中", pin))),
+            None => Err(rustls::Error::General("无法解析服务器证书".to_string())),
+        }
+    }
+}
+
+/// 按公钥指纹认证服务器的 TLS 客户端配置
+pub(crate) fn client_config(pins: Vec<SpkiPin>) -> Arc<ClientConfig> {
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(PinVerifier { pins }))
+        .with_no_client_auth();
+    Arc::new(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 自签名 P-256 证书（CN=dns.test），指纹由 openssl 计算
+    const CERT: &[u8] = include_bytes!("../testdata/dot_server_cert.der");
+    const CERT_PIN: &str = "sha256/QOvhR+XERAxjk8m/VB9WM6yX/Nta305FfWFVs0EfZYQ=";
+
+    fn verify(pins: &[&str], cert: &[u8]) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        let verifier = PinVerifier { pins: pins.iter().map(|pin| pin.parse().unwrap()).collect() };
+        let name = ServerName::try_from("dns.test").unwrap();
+        verifier.verify_server_cert(&Certificate(cert.to_vec()), &[], &name, &mut std::iter::empty(), &[], SystemTime::now())
+    }
+
+    #[test]
+    fn test_certificate_pin() {
+        let pin = SpkiPin::of_certificate(CERT).unwrap();
+        assert_eq!(pin.to_string(), CERT_PIN);
+        assert_eq!(CERT_PIN.trim_start_matches("sha256/").parse::<SpkiPin>().unwrap(), pin);
+
+        let other = "sha256/AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+        assert!(verify(&[other, CERT_PIN], CERT).is_ok());
+        assert!(verify(&[other], CERT).is_err());
+        assert!(verify(&[CERT_PIN], &CERT[..CERT.len() / 2]).is_err());
+
+        assert!("sha256/abc=".parse::<SpkiPin>().is_err());
+        assert!("sha1/QOvhR+XERAxjk8m/VB9WM6yX/Nta305FfWFVs0EfZYQ=".parse::<SpkiPin>().is_err());
+    }
+}
@@ -0,0 +1,249 @@
+//! 带宽限制
+//!
+//! 令牌桶限速：令牌按速率补充，最多积累一秒的量；每次转发数据后扣除相应的令牌（允许暂时透支），
+//! 令牌为负时暂停该方向的读写，直到按速率补回。多个连接共享同一个限速器时共享带宽上限，
+//! 平均速率不超过上限，单次突发不超过一秒的量加一个转发缓冲区。
+//! 按客户端 IP 限速时同一 IP 的所有连接共享一个限速器（该 IP 没有连接时释放）
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::IoSlice;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{Instant, Sleep};
+
+use crate::ip_matcher::IpMatcher;
+
+/// 令牌桶限速器（上下行合计）
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// 每秒字节数
+    rate: u64,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// 可用字节数（为负表示透支）
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    /// 创建每秒 `bytes_per_sec` 字节的限速器（至少 1）
+    pub fn new(bytes_per_sec: u64) -> Self {
+        let rate = bytes_per_sec.max(1);
+        Self {
+            rate,
+            bucket: Mutex::new(Bucket { tokens: rate as f64, updated: Instant::now() }),
+        }
+    }
+
+    /// 每秒字节数
+    pub fn rate(&self) -> u64 {
+        self.rate
+    }
+
+    /// 扣除已转发的字节数
+    fn consume(&self, bytes: usize) {
+        let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+        bucket.tokens -= bytes as f64;
+    }
+
+    /// 令牌透支时需要等待的时间
+    fn delay(&self) -> Option<Duration> {
+        let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate as f64).min(self.rate as f64);
+        bucket.updated = now;
+        (bucket.tokens < 0.0).then(|| Duration::from_secs_f64(-bucket.tokens / self.rate as f64))
+    }
+}
+
+/// 按客户端 IP 限速：每个 IP 的速率由第一个匹配的网段决定，都不匹配时使用默认速率
+#[derive(Debug)]
+pub struct ClientRateLimits {
+    default: Option<u64>,
+    ranges: Vec<(IpMatcher, u64)>,
+    /// 每个客户端 IP 的限速器（由该 IP 的连接持有）
+    limiters: Mutex<HashMap<IpAddr, Weak<RateLimiter>>>,
+}
+
+impl ClientRateLimits {
+    /// 创建按客户端 IP 的限速，`default_bytes_per_sec` 为 None 时只限制配置了网段的客户端
+    pub fn new(default_bytes_per_sec: Option<u64>) -> Self {
+        Self { default: default_bytes_per_sec, ranges: Vec::new(), limiters: Mutex::new(HashMap::new()) }
+    }
+
+    /// 为网段（IP 或 CIDR）内的每个客户端 IP 单独设置速率，先添加的网段优先
+    pub fn with_range(mut self, client_ips: Vec<String>, bytes_per_sec: u64) -> Self {
+        self.ranges.push((IpMatcher::new(client_ips), bytes_per_sec));
+        self
+    }
+
+    /// 客户端 IP 的速率（每秒字节数），不限速时为 None
+    pub fn rate_for(&self, client_ip: IpAddr) -> Option<u64> {
+        self.ranges
+            .iter()
+            .find(|(matcher, _)| matcher.matches(client_ip))
+            .map(|&(_, rate)| rate)
+            .or(self.default)
+    }
+
+    /// 客户端 IP 的限速器（同一 IP 的连接共享），不限速时为 None
+    pub fn limiter(&self, client_ip: IpAddr) -> Option<Arc<RateLimiter>> {
+        let rate = self.rate_for(client_ip)?;
+        let mut limiters = self.limiters.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(limiter) = limiters.get(&client_ip).and_then(Weak::upgrade) {
+            return Some(limiter);
+        }
+        // 清理已经没有连接的 IP，避免表无限增长（表的大小每翻一倍清理一次）
+        if limiters.len() >= 1024 && limiters.len().is_power_of_two() {
+            limiters.retain(|_, limiter| limiter.strong_count() > 0);
+        }
+        let limiter = Arc::new(RateLimiter::new(rate));
+        limiters.insert(client_ip, Arc::downgrade(&limiter));
+        Some(limiter)
+    }
+}
+
+/// 等待令牌补足（没有透支时立即返回）
+fn poll_tokens(limiter: &RateLimiter, delay: &mut Option<Pin<Box<Sleep>>>, cx: &mut Context<'_>) -> Poll<()> {
+    loop {
+        if let Some(sleep) = delay {
+            ready!(sleep.as_mut().poll(cx));
+            *delay = None;
+        }
+        match limiter.delay() {
+            Some(wait) => *delay = Some(Box::pin(tokio::time::sleep(wait))),
+            None => return Poll::Ready(()),
+        }
+    }
+}
+
+/// 对读写都限速的流包装（没有限速器时直接透传）
+pub(crate) struct Throttled<S> {
+    inner: S,
+    limiter: Option<Arc<RateLimiter>>,
+    read_delay: Option<Pin<Box<Sleep>>>,
+    write_delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> Throttled<S> {
+    pub(crate) fn new(inner: S, limiter: Option<Arc<RateLimiter>>) -> Self {
+        Self { inner, limiter, read_delay: None, write_delay: None }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Throttled<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let this = &mut *self;
+        let Some(limiter) = &this.limiter else {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        };
+        ready!(poll_tokens(limiter, &mut this.read_delay, cx));
+        let filled_before = buf.filled().len();
+        let result = ready!(Pin::new(&mut this.inner).poll_read(cx, buf));
+        limiter.consume(buf.filled().len() - filled_before);
+        Poll::Ready(result)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Throttled<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        let this = &mut *self;
+        let Some(limiter) = &this.limiter else {
+            return Pin::new(&mut this.inner).poll_write(cx, buf);
+        };
+        ready!(poll_tokens(limiter, &mut this.write_delay, cx));
+        let result = ready!(Pin::new(&mut this.inner).poll_write(cx, buf));
+        if let Ok(written) = result {
+            limiter.consume(written);
+        }
+        Poll::Ready(result)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = &mut *self;
+        let Some(limiter) = &this.limiter else {
+            return Pin::new(&mut this.inner).poll_write_vectored(cx, bufs);
+        };
+        ready!(poll_tokens(limiter, &mut this.write_delay, cx));
+        let result = ready!(Pin::new(&mut this.inner).poll_write_vectored(cx, bufs));
+        if let Ok(written) = result {
+            limiter.consume(written);
+        }
+        Poll::Ready(result)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test(start_paused = true)]
+    async fn test_throttled_read_rate() {
+        let limiter = Arc::new(RateLimiter::new(1000));
+        let (mut upstream, proxy_side) = tokio::io::duplex(64 * 1024);
+        let mut stream = Throttled::new(proxy_side, Some(Arc::clone(&limiter)));
+        upstream.write_all(&[0u8; 5000]).await.unwrap();
+        drop(upstream);
+
+        // 第一秒的量可以立即读取，之后按每秒 1000 字节
+        let start = tokio::time::Instant::now();
+        let mut data = Vec::new();
+        stream.read_to_end(&mut data).await.unwrap();
+        assert_eq!(data.len(), 5000);
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_secs(3) && elapsed <= Duration::from_secs(5), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn test_client_rate_limits() {
+        let limits = ClientRateLimits::new(Some(1000)).with_range(vec!["10.1.0.0/16".to_string()], 5000);
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert_eq!(limits.rate_for(ip("10.1.2.3")), Some(5000));
+        assert_eq!(limits.rate_for(ip("192.0.2.1")), Some(1000));
+        assert_eq!(ClientRateLimits::new(None).rate_for(ip("192.0.2.1")), None);
+
+        // 同一 IP 的连接共享限速器，没有连接后释放
+        let first = limits.limiter(ip("10.1.2.3")).unwrap();
+        assert!(Arc::ptr_eq(&first, &limits.limiter(ip("10.1.2.3")).unwrap()));
+        assert!(!Arc::ptr_eq(&first, &limits.limiter(ip("10.1.2.4")).unwrap()));
+        assert_eq!(first.rate(), 5000);
+        drop(first);
+        assert_eq!(limits.limiter(ip("10.1.2.3")).unwrap().rate(), 5000);
+    }
+
+    #[tokio::test]
+    async fn test_unthrottled_passthrough() {
+        let (mut upstream, proxy_side) = tokio::io::duplex(1024);
+        let mut stream = Throttled::new(proxy_side, None);
+        stream.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        upstream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+    }
+}
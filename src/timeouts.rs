@@ -0,0 +1,19 @@
+//! 连接超时配置
+//!
+//! 默认按 CPU 核心数自适应选择读取 Client Hello 和连接目标服务器的超时（见 `ConnectionTimeouts::adaptive`），
+//! 同一份配置在不同机器上表现不同。这里配置的超时覆盖自适应的值，没有配置的项保持原来的行为
+
+use std::time::Duration;
+
+/// 显式配置的超时（None 表示使用默认行为）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeoutConfig {
+    /// 读取完整 Client Hello 的截止时间（默认按 CPU 核心数 2-5 秒）
+    pub client_hello_read: Option<Duration>,
+    /// 直连目标服务器的超时（默认按 CPU 核心数 3-8 秒）
+    pub connect: Option<Duration>,
+    /// 通过 SOCKS5 连接的总超时，包括连接代理服务器、认证和 CONNECT 请求（默认每一步各 5 秒）
+    pub socks5_handshake: Option<Duration>,
+    /// 转发空闲超时（默认不限制）
+    pub idle: Option<Duration>,
+}
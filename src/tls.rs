@@ -1,6 +1,81 @@
+/// TLS 记录头长度
+pub const TLS_RECORD_HEADER_LEN: usize = 5;
+
+/// 握手记录的总长度（记录头 + 负载）
+///
+/// 数据不是 TLS 握手记录或不足一个记录头时返回 None
+pub fn handshake_record_len(data: &[u8]) -> Option<usize> {
+    if data.len() < TLS_RECORD_HEADER_LEN || data[0] != 0x16 {
+        return None;
+    }
+    Some(TLS_RECORD_HEADER_LEN + u16::from_be_bytes([data[3], data[4]]) as usize)
+}
+
+/// server_name 扩展
+pub const EXT_SERVER_NAME: u16 = 0;
+/// supported_groups 扩展（原 elliptic_curves）
+const EXT_SUPPORTED_GROUPS: u16 = 10;
+/// ec_point_formats 扩展
+const EXT_EC_POINT_FORMATS: u16 = 11;
+/// signature_algorithms 扩展
+const EXT_SIGNATURE_ALGORITHMS: u16 = 13;
+/// application_layer_protocol_negotiation 扩展
+pub const EXT_ALPN: u16 = 16;
+/// supported_versions 扩展
+const EXT_SUPPORTED_VERSIONS: u16 = 43;
+
+/// 从 Client Hello 中解析出的信息
+///
+/// 一次解析得到路由（SNI、ALPN）、指纹和访问日志需要的全部字段，避免重复解析
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientHelloInfo {
+    /// Server Name Indication（没有或无法解析 server_name 扩展时为 None）
+    pub sni: Option<String>,
+    /// Client Hello 中的 legacy_version（TLS 1.3 客户端固定为 0x0303）
+    pub legacy_version: u16,
+    /// supported_versions 扩展中的版本列表（按客户端顺序，包含 GREASE）
+    pub supported_versions: Vec<u16>,
+    /// ALPN 协议列表（按客户端偏好顺序）
+    pub alpn: Vec<String>,
+    /// Cipher Suites（按客户端顺序，包含 GREASE）
+    pub cipher_suites: Vec<u16>,
+    /// 扩展类型（按出现顺序，包含 GREASE）
+    pub extensions: Vec<u16>,
+    /// supported_groups 扩展中的椭圆曲线 / 密钥交换组（按客户端顺序，包含 GREASE）
+    pub supported_groups: Vec<u16>,
+    /// ec_point_formats 扩展中的点格式
+    pub ec_point_formats: Vec<u8>,
+    /// signature_algorithms 扩展中的签名算法（按客户端顺序）
+    pub signature_algorithms: Vec<u16>,
+}
+
+impl ClientHelloInfo {
+    /// 客户端支持的最高 TLS 版本（忽略 GREASE；没有 supported_versions 扩展时为 legacy_version）
+    pub fn tls_version(&self) -> u16 {
+        self.supported_versions
+            .iter()
+            .copied()
+            .filter(|&version| !is_grease(version))
+            .max()
+            .unwrap_or(self.legacy_version)
+    }
+}
+
+/// GREASE 值（RFC 8701）：0x0a0a、0x1a1a … 0xfafa
+pub fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && value >> 8 == value & 0xff
+}
+
 /// 从 TLS Client Hello 中解析 SNI（优化版本）
 #[inline]
 pub fn parse_sni(data: &[u8]) -> Option<String> {
+    parse_client_hello(data)?.sni
+}
+
+/// 解析 TLS Client Hello
+///
+/// 记录头、握手头或固定字段不完整时返回 None；扩展部分遇到格式错误时停止解析，返回已解析的字段
+pub fn parse_client_hello(data: &[u8]) -> Option<ClientHelloInfo> {
     // 最小 TLS Client Hello 大小检查
     if data.len() < 43 {
         return None;
@@ -39,10 +114,14 @@ pub fn parse_sni(data: &[u8]) -> Option<String> {
         return None;
     }
 
-    // 跳过 TLS 版本 (2 字节)
+    // 读取 TLS 版本 (2 字节)
     if pos + 2 > data.len() {
         return None;
     }
+    let mut info = ClientHelloInfo {
+        legacy_version: u16::from_be_bytes([data[pos], data[pos + 1]]),
+        ..ClientHelloInfo::default()
+    };
     pos += 2;
 
     // 跳过随机数 (32 字节)
@@ -71,10 +150,11 @@ pub fn parse_sni(data: &[u8]) -> Option<String> {
     let cipher_suites_len = u16::from_be_bytes([data[pos], data[pos + 1]]) as usize;
     pos += 2;
 
-    // 跳过 Cipher Suites
+    // 读取 Cipher Suites
     if pos + cipher_suites_len > data.len() {
         return None;
     }
+    info.cipher_suites = read_u16_list(&data[pos..pos + cipher_suites_len]);
     pos += cipher_suites_len;
 
     // 读取 Compression Methods 长度
@@ -90,9 +170,9 @@ pub fn parse_sni(data: &[u8]) -> Option<String> {
     }
     pos += compression_methods_len;
 
-    // 检查是否有 Extensions
+    // 没有 Extensions（TLS 1.2 及以前允许省略）
     if pos + 2 > data.len() {
-        return None;
+        return Some(info);
     }
 
     // 读取 Extensions 长度
@@ -105,24 +185,80 @@ pub fn parse_sni(data: &[u8]) -> Option<String> {
     }
 
     // 遍历 Extensions
+    let mut seen_server_name = false;
     while pos + 4 <= extensions_end {
         let ext_type = u16::from_be_bytes([data[pos], data[pos + 1]]);
         let ext_len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
         pos += 4;
 
         if pos + ext_len > extensions_end {
-            return None;
+            break;
         }
-
-        // SNI Extension (type = 0)
-        if ext_type == 0 {
-            return parse_sni_extension(&data[pos..pos + ext_len]);
+        info.extensions.push(ext_type);
+
+        let ext = &data[pos..pos + ext_len];
+        match ext_type {
+            // SNI Extension：只使用第一个（重复的 server_name 扩展不合法）
+            EXT_SERVER_NAME if !seen_server_name => {
+                seen_server_name = true;
+                info.sni = parse_sni_extension(ext);
+            }
+            EXT_ALPN => info.alpn = parse_alpn_extension(ext),
+            EXT_SUPPORTED_GROUPS => info.supported_groups = read_u16_vector(ext),
+            EXT_SIGNATURE_ALGORITHMS => info.signature_algorithms = read_u16_vector(ext),
+            EXT_EC_POINT_FORMATS => {
+                if let Some((&len, formats)) = ext.split_first() {
+                    info.ec_point_formats = formats[..(len as usize).min(formats.len())].to_vec();
+                }
+            }
+            EXT_SUPPORTED_VERSIONS => {
+                if let Some((&len, versions)) = ext.split_first() {
+                    info.supported_versions = read_u16_list(&versions[..(len as usize).min(versions.len())]);
+                }
+            }
+            _ => {}
         }
 
         pos += ext_len;
     }
 
-    None
+    Some(info)
+}
+
+/// 按大端序读取 u16 列表（忽略末尾不足 2 字节的部分）
+fn read_u16_list(data: &[u8]) -> Vec<u16> {
+    data.chunks_exact(2).map(|chunk| u16::from_be_bytes([chunk[0], chunk[1]])).collect()
+}
+
+/// 读取带 2 字节长度前缀的 u16 列表
+fn read_u16_vector(data: &[u8]) -> Vec<u16> {
+    if data.len() < 2 {
+        return Vec::new();
+    }
+    let len = u16::from_be_bytes([data[0], data[1]]) as usize;
+    read_u16_list(&data[2..(2 + len).min(data.len())])
+}
+
+/// 解析 ALPN Extension（协议名不是合法 UTF-8 时忽略该项）
+fn parse_alpn_extension(data: &[u8]) -> Vec<String> {
+    let mut protocols = Vec::new();
+    if data.len() < 2 {
+        return protocols;
+    }
+    let list_end = (2 + u16::from_be_bytes([data[0], data[1]]) as usize).min(data.len());
+    let mut pos = 2;
+    while pos < list_end {
+        let len = data[pos] as usize;
+        pos += 1;
+        if pos + len > list_end {
+            break;
+        }
+        if let Ok(protocol) = std::str::from_utf8(&data[pos..pos + len]) {
+            protocols.push(protocol.to_string());
+        }
+        pos += len;
+    }
+    protocols
 }
 
 /// 解析 SNI Extension（优化版本）
@@ -163,10 +299,84 @@ fn parse_sni_extension(data: &[u8]) -> Option<String> {
     String::from_utf8(data[pos..pos + name_len].to_vec()).ok()
 }
 
+/// Client Hello 没有 SNI 时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NoSniAction {
+    /// 拒绝连接（计为 SNI 解析错误）
+    #[default]
+    Reject,
+    /// 转发到固定的后端地址
+    Backend(std::net::SocketAddr),
+    /// 透明代理模式下转发到原始目标地址，非透明代理的连接仍然拒绝
+    OriginalDst,
+}
+
+impl std::fmt::Display for NoSniAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NoSniAction::Reject => write!(f, "reject"),
+            NoSniAction::Backend(addr) => write!(f, "backend ({})", addr),
+            NoSniAction::OriginalDst => write!(f, "original_dst"),
+        }
+    }
+}
+
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
 
+    /// 构造带 SNI 扩展的最小 TLS Client Hello（测试用）
+    pub(crate) fn client_hello(sni: &str) -> Vec<u8> {
+        client_hello_with_extensions(sni, &[])
+    }
+
+    /// 构造带 SNI 扩展和额外扩展的 TLS Client Hello（测试用）
+    pub(crate) fn client_hello_with_extensions(sni: &str, extra: &[(u16, &[u8])]) -> Vec<u8> {
+        let name = sni.as_bytes();
+
+        // server_name 扩展：list_len(2) + type(1) + name_len(2) + name
+        let mut sni_ext = Vec::new();
+        sni_ext.extend_from_slice(&((name.len() + 3) as u16).to_be_bytes());
+        sni_ext.push(0x00);
+        sni_ext.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        sni_ext.extend_from_slice(name);
+
+        let all: Vec<(u16, &[u8])> = std::iter::once((0u16, sni_ext.as_slice())).chain(extra.iter().copied()).collect();
+        client_hello_from_extensions(&all)
+    }
+
+    /// 构造没有 SNI 扩展的 TLS Client Hello（测试用）
+    pub(crate) fn client_hello_without_sni() -> Vec<u8> {
+        client_hello_from_extensions(&[(23, &[])])
+    }
+
+    fn client_hello_from_extensions(all: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut extensions = Vec::new();
+        for &(ext_type, ext) in all {
+            extensions.extend_from_slice(&ext_type.to_be_bytes());
+            extensions.extend_from_slice(&(ext.len() as u16).to_be_bytes());
+            extensions.extend_from_slice(ext);
+        }
+
+        let mut body = Vec::new();
+        body.extend_from_slice(&[0x03, 0x03]); // TLS 1.2
+        body.extend_from_slice(&[0u8; 32]); // 随机数
+        body.push(0); // Session ID
+        body.extend_from_slice(&[0x00, 0x02, 0x13, 0x01]); // Cipher Suites
+        body.extend_from_slice(&[0x01, 0x00]); // Compression Methods
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(&extensions);
+
+        let mut handshake = vec![0x01];
+        handshake.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        handshake.extend_from_slice(&body);
+
+        let mut record = vec![0x16, 0x03, 0x01];
+        record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+        record.extend_from_slice(&handshake);
+        record
+    }
+
     #[test]
     fn test_parse_sni() {
         // 这是一个简化的测试，实际的 TLS Client Hello 会更复杂
@@ -175,4 +385,56 @@ mod tests {
         let result = parse_sni(&data);
         assert!(result.is_none());
     }
+
+    #[test]
+    fn test_handshake_record_len() {
+        let hello = client_hello("www.example.com");
+        assert_eq!(handshake_record_len(&hello), Some(hello.len()));
+        assert_eq!(handshake_record_len(&hello[..4]), None);
+        assert_eq!(handshake_record_len(b"GET / HTTP/1.1"), None);
+    }
+
+    #[test]
+    fn test_parse_client_hello() {
+        // ALPN: h2, http/1.1
+        let alpn = b"\x00\x0c\x02h2\x08http/1.1";
+        // supported_versions: GREASE, TLS 1.3, TLS 1.2
+        let versions = [0x06, 0x3a, 0x3a, 0x03, 0x04, 0x03, 0x03];
+        let hello = client_hello_with_extensions("www.example.com", &[(0x4a4a, &[]), (16, alpn), (43, &versions)]);
+
+        let info = parse_client_hello(&hello).unwrap();
+        assert_eq!(info.sni.as_deref(), Some("www.example.com"));
+        assert_eq!(info.legacy_version, 0x0303);
+        assert_eq!(info.supported_versions, vec![0x3a3a, 0x0304, 0x0303]);
+        assert_eq!(info.tls_version(), 0x0304);
+        assert_eq!(info.alpn, vec!["h2".to_string(), "http/1.1".to_string()]);
+        assert_eq!(info.cipher_suites, vec![0x1301]);
+        assert_eq!(info.extensions, vec![0, 0x4a4a, 16, 43]);
+
+        // supported_groups、ec_point_formats、signature_algorithms
+        let groups = [0x00, 0x04, 0x00, 0x1d, 0x00, 0x17];
+        let sig_algs = [0x00, 0x04, 0x04, 0x03, 0x08, 0x04];
+        let hello = client_hello_with_extensions("a.com", &[(10, &groups), (11, &[0x01, 0x00]), (13, &sig_algs)]);
+        let info = parse_client_hello(&hello).unwrap();
+        assert_eq!(info.supported_groups, vec![0x001d, 0x0017]);
+        assert_eq!(info.ec_point_formats, vec![0x00]);
+        assert_eq!(info.signature_algorithms, vec![0x0403, 0x0804]);
+
+        // 没有 supported_versions 扩展时使用 legacy_version
+        assert_eq!(parse_client_hello(&client_hello("a.com")).unwrap().tls_version(), 0x0303);
+        assert_eq!(parse_client_hello(&hello[..60]), None);
+    }
+
+    #[test]
+    fn test_is_grease() {
+        assert!(is_grease(0x0a0a));
+        assert!(is_grease(0xfafa));
+        assert!(!is_grease(0x0a1a));
+        assert!(!is_grease(0x1301));
+    }
+
+    #[test]
+    fn test_parse_sni_client_hello() {
+        assert_eq!(parse_sni(&client_hello("www.example.com")), Some("www.example.com".to_string()));
+    }
 }
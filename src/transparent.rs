@@ -0,0 +1,203 @@
+use std::net::SocketAddr;
+use tokio::net::TcpStream;
+
+use crate::error::{Result, SniProxyError};
+
+/// 透明代理模式
+///
+/// 代理作为 iptables/nftables 的 REDIRECT 或 TPROXY 目标运行时，
+/// 客户端无需任何配置，连接的原始目标地址从 socket 中读取
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransparentMode {
+    /// `-j REDIRECT`：通过 SO_ORIGINAL_DST 读取 NAT 前的目标地址
+    Redirect,
+    /// `-j TPROXY`：目标地址保持不变，即已接受连接的本地地址（监听 socket 需要 IP_TRANSPARENT）
+    Tproxy,
+}
+
+impl std::str::FromStr for TransparentMode {
+    type Err = SniProxyError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "redirect" => Ok(TransparentMode::Redirect),
+            "tproxy" => Ok(TransparentMode::Tproxy),
+            _ => Err(SniProxyError::InvalidConfig(format!(
+                "无效的透明代理模式: {}（可选: redirect, tproxy）",
+                s
+            ))),
+        }
+    }
+}
+
+impl std::fmt::Display for TransparentMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransparentMode::Redirect => write!(f, "redirect"),
+            TransparentMode::Tproxy => write!(f, "tproxy"),
+        }
+    }
+}
+
+/// 获取连接的原始目标地址
+///
+/// 返回 `None` 表示连接没有经过透明代理规则（客户端直接连接到代理），
+/// 此时应按普通 SNI 代理处理，避免连接回代理自身
+pub fn original_destination(
+    stream: &TcpStream,
+    mode: TransparentMode,
+    listen_addrs: &[SocketAddr],
+) -> Option<SocketAddr> {
+    let local_addr = stream.local_addr().ok()?;
+    let original = match mode {
+        TransparentMode::Redirect => {
+            let original = so_original_dst(stream, local_addr.is_ipv6()).ok()?;
+            // 未经 NAT 的连接，conntrack 返回的就是本地地址
+            if original == local_addr {
+                return None;
+            }
+            original
+        }
+        TransparentMode::Tproxy => local_addr,
+    };
+
+    if listen_addrs.iter().any(|&listen_addr| is_listen_addr(original, listen_addr)) {
+        return None;
+    }
+
+    // 双栈监听时 IPv4 目标以 ::ffff:a.b.c.d 形式出现
+    Some(SocketAddr::new(original.ip().to_canonical(), original.port()))
+}
+
+/// 判断地址是否就是代理自身的监听地址
+fn is_listen_addr(addr: SocketAddr, listen_addr: SocketAddr) -> bool {
+    addr.port() == listen_addr.port()
+        && (listen_addr.ip().is_unspecified() || addr.ip().to_canonical() == listen_addr.ip().to_canonical())
+}
+
+/// 读取 SO_ORIGINAL_DST（iptables/nftables REDIRECT 或 DNAT 之前的目标地址）
+#[cfg(target_os = "linux")]
+fn so_original_dst(stream: &TcpStream, ipv6: bool) -> std::io::Result<SocketAddr> {
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6};
+    use std::os::unix::io::AsRawFd;
+
+    let fd = stream.as_raw_fd();
+    unsafe {
+        if ipv6 {
+            let mut addr: libc::sockaddr_in6 = std::mem::zeroed();
+            let mut len = std::mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t;
+            if libc::getsockopt(
+                fd,
+                libc::SOL_IPV6,
+                libc::IP6T_SO_ORIGINAL_DST,
+                &mut addr as *mut _ as *mut libc::c_void,
+                &mut len,
+            ) != 0
+            {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(SocketAddr::V6(SocketAddrV6::new(
+                Ipv6Addr::from(addr.sin6_addr.s6_addr),
+                u16::from_be(addr.sin6_port),
+                addr.sin6_flowinfo,
+                addr.sin6_scope_id,
+            )))
+        } else {
+            let mut addr: libc::sockaddr_in = std::mem::zeroed();
+            let mut len = std::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t;
+            if libc::getsockopt(
+                fd,
+                libc::SOL_IP,
+                libc::SO_ORIGINAL_DST,
+                &mut addr as *mut _ as *mut libc::c_void,
+                &mut len,
+            ) != 0
+            {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(SocketAddr::V4(SocketAddrV4::new(
+                Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)),
+                u16::from_be(addr.sin_port),
+            )))
+        }
+    }
+}
+
+/// 读取 SO_ORIGINAL_DST（非 Linux 平台不支持）
+#[cfg(not(target_os = "linux"))]
+fn so_original_dst(_stream: &TcpStream, _ipv6: bool) -> std::io::Result<SocketAddr> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "当前平台不支持 SO_ORIGINAL_DST",
+    ))
+}
+
+/// 为监听 socket 设置 IP_TRANSPARENT / IPV6_TRANSPARENT（TPROXY 模式必需，需要 CAP_NET_ADMIN）
+#[cfg(target_os = "linux")]
+pub fn set_ip_transparent<S: std::os::unix::io::AsRawFd>(socket: &S, ipv6: bool) -> std::io::Result<()> {
+    let (level, name) = if ipv6 {
+        (libc::SOL_IPV6, libc::IPV6_TRANSPARENT)
+    } else {
+        (libc::SOL_IP, libc::IP_TRANSPARENT)
+    };
+    let enable: libc::c_int = 1;
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            &enable as *const _ as *const libc::c_void,
+            std::mem::size_of_val(&enable) as libc::socklen_t,
+        )
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// 为监听 socket 设置 IP_TRANSPARENT（非 Linux 平台不支持）
+#[cfg(not(target_os = "linux"))]
+pub fn set_ip_transparent<S>(_socket: &S, _ipv6: bool) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "当前平台不支持 IP_TRANSPARENT",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_transparent_mode() {
+        assert_eq!("redirect".parse::<TransparentMode>().unwrap(), TransparentMode::Redirect);
+        assert_eq!("TPROXY".parse::<TransparentMode>().unwrap(), TransparentMode::Tproxy);
+        assert!("nat".parse::<TransparentMode>().is_err());
+    }
+
+    #[test]
+    fn test_is_listen_addr() {
+        let wildcard: SocketAddr = "0.0.0.0:443".parse().unwrap();
+        let specific: SocketAddr = "10.0.0.1:443".parse().unwrap();
+
+        assert!(is_listen_addr("10.0.0.1:443".parse().unwrap(), wildcard));
+        assert!(is_listen_addr("10.0.0.1:443".parse().unwrap(), specific));
+        assert!(is_listen_addr("[::ffff:10.0.0.1]:443".parse().unwrap(), specific));
+        assert!(!is_listen_addr("1.1.1.1:443".parse().unwrap(), specific));
+        assert!(!is_listen_addr("10.0.0.1:8443".parse().unwrap(), wildcard));
+    }
+
+    #[tokio::test]
+    async fn test_direct_connection_is_not_transparent() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let listen_addr = listener.local_addr().unwrap();
+        let client = TcpStream::connect(listen_addr).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+
+        // 直接连接到代理的连接不应被当作透明代理流量（否则会连回代理自身）
+        assert_eq!(original_destination(&server, TransparentMode::Redirect, &[listen_addr]), None);
+        assert_eq!(original_destination(&server, TransparentMode::Tproxy, &[listen_addr]), None);
+        drop(client);
+    }
+}
@@ -0,0 +1,561 @@
+use log::{debug, warn};
+use socket2::{SockRef, Socket, TcpKeepalive};
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::{Result, SniProxyError};
+use crate::metrics::RouteLabel;
+
+/// 默认 TCP backlog（监听队列长度）
+pub const DEFAULT_BACKLOG: i32 = 4096;
+
+/// 默认 socket 缓冲区大小（1MB，适合流媒体）
+pub const DEFAULT_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// 低延迟调优配置的 socket 缓冲区大小（64KB）
+const LOW_LATENCY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// TCP Fast Open 服务端队列长度
+const FASTOPEN_QUEUE_LEN: libc::c_int = 256;
+
+/// 内置调优配置名称（`tuning_profiles` 中的同名配置覆盖内置配置）
+pub const BUILTIN_PROFILES: &[&str] = &["streaming", "low_latency"];
+
+/// TCP 调优参数
+///
+/// 默认值与流媒体优化一致：backlog 4096，收发缓冲区各 1MB，启用 TCP_NODELAY 和 TCP Fast Open。
+/// 缓冲区大小为 0 表示不设置，使用系统默认值（内核自动调整）。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcpTuning {
+    /// 监听 socket 的 backlog
+    pub backlog: i32,
+    /// SO_RCVBUF 大小（字节），0 表示使用系统默认值
+    pub recv_buffer_size: usize,
+    /// SO_SNDBUF 大小（字节），0 表示使用系统默认值
+    pub send_buffer_size: usize,
+    /// 是否设置 TCP_NODELAY（禁用 Nagle 算法）
+    pub nodelay: bool,
+    /// TCP keepalive 空闲时间，None 表示不启用
+    pub keepalive: Option<Duration>,
+    /// keepalive 探测间隔（TCP_KEEPINTVL，仅 Linux），None 表示使用系统默认值
+    pub keepalive_interval: Option<Duration>,
+    /// keepalive 探测次数（TCP_KEEPCNT，仅 Linux），None 表示使用系统默认值
+    pub keepalive_count: Option<u32>,
+    /// TCP_USER_TIMEOUT（仅 Linux）：已发送的数据超过该时长没有被确认时断开连接，None 表示使用系统默认值
+    pub user_timeout: Option<Duration>,
+    /// 是否设置 TCP_QUICKACK（仅 Linux）：立即确认收到的数据，不等待延迟确认
+    ///
+    /// 内核在连接进入稳定收发后会回到延迟确认，这里只影响连接建立初期（请求-响应的第一个来回）
+    pub quickack: bool,
+    /// 是否启用 TCP Fast Open（监听 socket 为服务端模式，上游连接为客户端模式，仅 Linux）
+    pub fastopen: bool,
+    /// 拥塞控制算法（TCP_CONGESTION，仅 Linux），例如 "bbr"，None 表示使用系统默认值
+    pub congestion_control: Option<String>,
+}
+
+impl Default for TcpTuning {
+    fn default() -> Self {
+        Self {
+            backlog: DEFAULT_BACKLOG,
+            recv_buffer_size: DEFAULT_BUFFER_SIZE,
+            send_buffer_size: DEFAULT_BUFFER_SIZE,
+            nodelay: true,
+            keepalive: None,
+            keepalive_interval: None,
+            keepalive_count: None,
+            user_timeout: None,
+            quickack: false,
+            fastopen: true,
+            congestion_control: None,
+        }
+    }
+}
+
+impl TcpTuning {
+    /// 内置 `streaming` 调优配置（与默认值相同）：1MB 收发缓冲区、TCP_NODELAY、TCP Fast Open
+    pub fn streaming() -> Self {
+        Self::default()
+    }
+
+    /// 内置 `low_latency` 调优配置（API 等请求-响应流量）：64KB 收发缓冲区、TCP_NODELAY、TCP_QUICKACK
+    pub fn low_latency() -> Self {
+        Self {
+            recv_buffer_size: LOW_LATENCY_BUFFER_SIZE,
+            send_buffer_size: LOW_LATENCY_BUFFER_SIZE,
+            nodelay: true,
+            quickack: true,
+            ..Self::default()
+        }
+    }
+
+    /// 按名称查找内置调优配置（见 [`BUILTIN_PROFILES`]）
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "streaming" => Some(Self::streaming()),
+            "low_latency" => Some(Self::low_latency()),
+            _ => None,
+        }
+    }
+
+    /// 检查配置是否超出内核限制，并打印警告
+    ///
+    /// 超出限制的值不会报错，内核会静默截断：
+    /// - backlog 受 `net.core.somaxconn` 限制
+    /// - SO_RCVBUF / SO_SNDBUF 受 `net.core.rmem_max` / `net.core.wmem_max` 限制
+    pub fn warn_if_exceeds_kernel_limits(&self) {
+        let checks = [
+            ("/proc/sys/net/core/somaxconn", self.backlog.max(0) as u64, "backlog"),
+            ("/proc/sys/net/core/rmem_max", self.recv_buffer_size as u64, "recv_buffer_size"),
+            ("/proc/sys/net/core/wmem_max", self.send_buffer_size as u64, "send_buffer_size"),
+        ];
+
+        for (path, value, name) in checks {
+            if value == 0 {
+                continue;
+            }
+            if let Some(limit) = read_kernel_limit(path) {
+                if value > limit {
+                    warn!(
+                        "⚠️  {} = {} 超过内核限制 {} = {}，实际值会被截断",
+                        name, value, path, limit
+                    );
+                }
+            }
+        }
+    }
+
+    /// 设置已建立连接的 socket（客户端连接和上游连接）
+    ///
+    /// - 接收/发送缓冲区（为 0 时保持系统默认值）
+    /// - TCP_NODELAY 避免 Nagle 算法延迟，TCP_QUICKACK 避免延迟确认
+    /// - TCP keepalive 及时发现失效的空闲连接
+    /// - TCP_USER_TIMEOUT 让有未确认数据的连接在失效路径上及时断开（内核默认要重传十几分钟）
+    /// - TCP Fast Open（客户端模式）减少握手延迟
+    /// - 拥塞控制算法（例如 BBR，长距离高带宽链路上吞吐量更高）
+    pub fn apply_to_stream<'s>(&self, stream: impl Into<SockRef<'s>>) -> io::Result<()> {
+        let socket = stream.into();
+        socket.set_nodelay(self.nodelay)?;
+        if self.recv_buffer_size > 0 {
+            socket.set_recv_buffer_size(self.recv_buffer_size.min(libc::c_int::MAX as usize))?;
+        }
+        if self.send_buffer_size > 0 {
+            socket.set_send_buffer_size(self.send_buffer_size.min(libc::c_int::MAX as usize))?;
+        }
+        if let Some(idle) = self.keepalive {
+            let keepalive = TcpKeepalive::new().with_time(idle);
+            // 探测间隔和次数决定对端失联后多久断开：idle + interval × count
+            #[cfg(target_os = "linux")]
+            let keepalive = match self.keepalive_interval {
+                Some(interval) => keepalive.with_interval(interval),
+                None => keepalive,
+            };
+            socket.set_tcp_keepalive(&keepalive)?;
+            #[cfg(target_os = "linux")]
+            if let Some(count) = self.keepalive_count {
+                set_tcp_option(&socket, libc::TCP_KEEPCNT, count.min(libc::c_int::MAX as u32) as libc::c_int)?;
+            }
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(timeout) = self.user_timeout {
+            let millis = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+            set_tcp_option(&socket, libc::TCP_USER_TIMEOUT, millis)?;
+        }
+        #[cfg(target_os = "linux")]
+        if self.quickack {
+            set_tcp_option(&socket, libc::TCP_QUICKACK, 1)?;
+        }
+
+        // ⚡ TCP_FASTOPEN_CONNECT 需要 Linux 4.11+，旧内核上跳过，避免每个连接都做一次失败的系统调用
+        #[cfg(target_os = "linux")]
+        if self.fastopen
+            && crate::platform::PlatformInfo::detect().supports(crate::platform::KernelFeature::TcpFastOpenConnect)
+        {
+            const TCP_FASTOPEN_CONNECT: libc::c_int = 30; // Linux 特定常量
+            if set_tcp_option(&socket, TCP_FASTOPEN_CONNECT, 1).is_ok() {
+                debug!("✅ TCP Fast Open 已启用（客户端模式）");
+            } else {
+                debug!("⚠️  TCP Fast Open 启用失败（可能系统不支持）");
+            }
+        }
+        if let Some(ref name) = self.congestion_control {
+            set_congestion_control(&*socket, name)?;
+        }
+        Ok(())
+    }
+
+    /// 设置监听 socket（在 bind 之前调用；backlog 在 listen 时使用）
+    ///
+    /// 返回 TCP Fast Open（服务端模式）是否启用成功
+    pub fn apply_to_listener(&self, socket: &Socket) -> bool {
+        // ⚡ TCP Fast Open (服务端模式) - Linux 3.7+ 支持
+        // 允许客户端在 SYN 包中携带数据，节省 1 RTT
+        #[cfg(target_os = "linux")]
+        if self.fastopen {
+            const TCP_FASTOPEN: libc::c_int = 23; // Linux TCP_FASTOPEN 常量
+            return set_tcp_option(&SockRef::from(socket), TCP_FASTOPEN, FASTOPEN_QUEUE_LEN).is_ok();
+        }
+        let _ = socket;
+        false
+    }
+
+    /// TCP Fast Open 服务端队列长度（用于日志）
+    pub fn fastopen_queue_len(&self) -> i32 {
+        FASTOPEN_QUEUE_LEN
+    }
+}
+
+/// 设置 IPPROTO_TCP 层的整数选项
+#[cfg(target_os = "linux")]
+fn set_tcp_option(socket: &SockRef<'_>, option: libc::c_int, value: libc::c_int) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    // SAFETY: fd 在 socket 的生命周期内有效，value 的长度与 socklen 一致
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            option,
+            &value as *const _ as *const libc::c_void,
+            std::mem::size_of_val(&value) as libc::socklen_t,
+        )
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// 解析 DSCP：名称（`EF`、`AF11`-`AF43`、`CS0`-`CS7`、`LE`）或 0-63 的数字
+pub fn parse_dscp(s: &str) -> Result<u8> {
+    let name = s.trim().to_ascii_uppercase();
+    let dscp = match name.as_bytes() {
+        b"EF" => Some(46),
+        b"LE" => Some(1),
+        [b'A', b'F', class @ b'1'..=b'4', drop @ b'1'..=b'3'] => Some((class - b'0') * 8 + (drop - b'0') * 2),
+        [b'C', b'S', class @ b'0'..=b'7'] => Some((class - b'0') * 8),
+        _ => name.parse::<u8>().ok().filter(|dscp| *dscp < 64),
+    };
+    dscp.ok_or_else(|| {
+        SniProxyError::InvalidConfig(format!("无效的 DSCP: {}（可选: EF、AF11-AF43、CS0-CS7、LE 或 0-63）", s))
+    })
+}
+
+/// 设置 socket 的 DSCP 标记，供下游设备做 QoS
+///
+/// 设置 socket 的拥塞控制算法（TCP_CONGESTION）
+///
+/// 算法需要已加载（见 `net.ipv4.tcp_available_congestion_control`），没有 CAP_NET_ADMIN 时
+/// 只能使用 `net.ipv4.tcp_allowed_congestion_control` 中的算法
+#[cfg(target_os = "linux")]
+pub fn set_congestion_control<'s>(stream: impl Into<SockRef<'s>>, name: &str) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    let socket = stream.into();
+    // SAFETY: fd 在 socket 的生命周期内有效，name 在调用期间有效且长度与 socklen 一致
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_CONGESTION,
+            name.as_ptr() as *const libc::c_void,
+            name.len() as libc::socklen_t,
+        )
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// 设置 socket 的拥塞控制算法（非 Linux 平台不支持）
+#[cfg(not(target_os = "linux"))]
+pub fn set_congestion_control<'s>(_stream: impl Into<SockRef<'s>>, _name: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "当前平台不支持 TCP_CONGESTION"))
+}
+
+/// 检查能否使用拥塞控制算法（算法已加载且当前进程有权限使用），失败时错误中附带允许使用的算法
+pub fn check_congestion_control(name: &str) -> io::Result<()> {
+    let socket = Socket::new(socket2::Domain::IPV4, socket2::Type::STREAM, None)?;
+    set_congestion_control(&socket, name).map_err(|e| {
+        match std::fs::read_to_string("/proc/sys/net/ipv4/tcp_allowed_congestion_control") {
+            Ok(allowed) => io::Error::new(e.kind(), format!("{}（可用: {}）", e, allowed.trim())),
+            Err(_) => e,
+        }
+    })
+}
+
+/// IPv4 设置 IP_TOS，IPv6 设置 IPV6_TCLASS（双栈 socket 上的 IPv4 连接使用 IP_TOS，两个都设置）；
+/// DSCP 占 TOS 字节的高 6 位，低 2 位 ECN 保持为 0
+pub fn set_dscp<'s>(stream: impl Into<SockRef<'s>>, dscp: u8) -> io::Result<()> {
+    let socket = stream.into();
+    let tos = u32::from(dscp & 0x3f) << 2;
+    if !socket.local_addr()?.is_ipv6() {
+        return socket.set_tos(tos);
+    }
+    let _ = socket.set_tos(tos);
+    set_tclass_v6(&socket, tos as libc::c_int)
+}
+
+/// 设置 IPV6_TCLASS
+#[cfg(unix)]
+fn set_tclass_v6(socket: &SockRef<'_>, tclass: libc::c_int) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    // SAFETY: fd 在 socket 的生命周期内有效，tclass 的长度与 socklen 一致
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_IPV6,
+            libc::IPV6_TCLASS,
+            &tclass as *const _ as *const libc::c_void,
+            std::mem::size_of_val(&tclass) as libc::socklen_t,
+        )
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// 设置 IPV6_TCLASS（非 Unix 平台不支持）
+#[cfg(not(unix))]
+fn set_tclass_v6(_socket: &SockRef<'_>, _tclass: libc::c_int) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "当前平台不支持 IPV6_TCLASS"))
+}
+
+/// 按监听端口和路由选择调优参数
+///
+/// - 监听 socket 和客户端连接：使用监听端口的参数，未单独配置时使用默认参数
+/// - 上游连接：优先使用路由的参数，其次是客户端所在监听端口的参数，最后是默认参数
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TuningPolicy {
+    default: Arc<TcpTuning>,
+    by_listen_port: HashMap<u16, Arc<TcpTuning>>,
+    by_route: HashMap<RouteLabel, Arc<TcpTuning>>,
+}
+
+impl TuningPolicy {
+    /// 所有监听端口和路由都使用 `default`
+    pub fn new(default: TcpTuning) -> Self {
+        Self {
+            default: Arc::new(default),
+            by_listen_port: HashMap::new(),
+            by_route: HashMap::new(),
+        }
+    }
+
+    /// 替换默认参数（保留按监听端口和路由的配置）
+    pub fn with_default(mut self, default: TcpTuning) -> Self {
+        self.default = Arc::new(default);
+        self
+    }
+
+    /// 为某个监听端口单独设置参数
+    pub fn with_listener(mut self, listen_port: u16, tuning: TcpTuning) -> Self {
+        self.by_listen_port.insert(listen_port, Arc::new(tuning));
+        self
+    }
+
+    /// 为某个路由的上游连接单独设置参数
+    pub fn with_route(mut self, route: RouteLabel, tuning: TcpTuning) -> Self {
+        self.by_route.insert(route, Arc::new(tuning));
+        self
+    }
+
+    /// 默认参数
+    pub fn default_tuning(&self) -> &TcpTuning {
+        &self.default
+    }
+
+    /// 监听 socket 和客户端连接的参数（Unix socket 没有端口，使用默认参数）
+    pub fn listener(&self, listen_port: Option<u16>) -> &TcpTuning {
+        listen_port
+            .and_then(|port| self.by_listen_port.get(&port))
+            .unwrap_or(&self.default)
+    }
+
+    /// 上游连接的参数
+    pub fn upstream(&self, route: RouteLabel, listen_port: Option<u16>) -> &TcpTuning {
+        match self.by_route.get(&route) {
+            Some(tuning) => tuning,
+            None => self.listener(listen_port),
+        }
+    }
+}
+
+/// 读取内核参数（仅 Linux 可用，其他平台返回 None）
+fn read_kernel_limit(path: &str) -> Option<u64> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_tuning() {
+        let tuning = TcpTuning::default();
+        assert_eq!(tuning.backlog, 4096);
+        assert_eq!(tuning.recv_buffer_size, 1024 * 1024);
+        assert_eq!(tuning.send_buffer_size, 1024 * 1024);
+        assert!(tuning.nodelay);
+        assert_eq!(tuning.keepalive, None);
+        assert_eq!((tuning.keepalive_interval, tuning.keepalive_count), (None, None));
+        assert_eq!(tuning.user_timeout, None);
+        assert!(!tuning.quickack);
+        assert!(tuning.fastopen);
+    }
+
+    #[test]
+    fn test_builtin_profiles() {
+        assert_eq!(TcpTuning::builtin("streaming"), Some(TcpTuning::default()));
+        let low_latency = TcpTuning::builtin("low_latency").unwrap();
+        assert!(low_latency.nodelay && low_latency.quickack);
+        assert_eq!(low_latency.recv_buffer_size, 64 * 1024);
+        assert!(BUILTIN_PROFILES.iter().all(|name| TcpTuning::builtin(name).is_some()));
+        assert_eq!(TcpTuning::builtin("bulk"), None);
+    }
+
+    #[test]
+    fn test_read_kernel_limit_missing_file() {
+        assert_eq!(read_kernel_limit("/nonexistent/sysctl"), None);
+    }
+
+    #[test]
+    fn test_parse_dscp() {
+        assert_eq!(parse_dscp("AF41").unwrap(), 34);
+        assert_eq!(parse_dscp("af11").unwrap(), 10);
+        assert_eq!(parse_dscp("EF").unwrap(), 46);
+        assert_eq!(parse_dscp("CS6").unwrap(), 48);
+        assert_eq!(parse_dscp("26").unwrap(), 26);
+        for invalid in ["AF51", "AF14", "CS8", "64", "AF", ""] {
+            assert!(parse_dscp(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_set_dscp() {
+        for addr in ["127.0.0.1:0", "[::1]:0"] {
+            let Ok(listener) = tokio::net::TcpListener::bind(addr).await else {
+                continue; // 没有 IPv6 时跳过
+            };
+            let client = tokio::net::TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+            set_dscp(&client, 34).unwrap();
+            if listener.local_addr().unwrap().is_ipv4() {
+                assert_eq!(SockRef::from(&client).tos().unwrap(), 34 << 2);
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_set_congestion_control() {
+        use std::os::unix::io::AsRawFd;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = tokio::net::TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        // reno 总是可用
+        let tuning = TcpTuning { congestion_control: Some("reno".to_string()), ..TcpTuning::default() };
+        tuning.apply_to_stream(&client).unwrap();
+        let mut name = [0u8; 16];
+        let mut len = name.len() as libc::socklen_t;
+        // SAFETY: name 和 len 在调用期间有效
+        unsafe {
+            libc::getsockopt(
+                client.as_raw_fd(),
+                libc::IPPROTO_TCP,
+                libc::TCP_CONGESTION,
+                name.as_mut_ptr() as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        assert!(name.starts_with(b"reno\0"));
+
+        assert!(check_congestion_control("reno").is_ok());
+        assert!(set_congestion_control(&client, "sni-proxy-none").is_err());
+        assert!(check_congestion_control("sni-proxy-none").unwrap_err().to_string().contains("reno"));
+    }
+
+    #[test]
+    fn test_policy_selection() {
+        let bulk = TcpTuning {
+            nodelay: false,
+            ..TcpTuning::default()
+        };
+        let interactive = TcpTuning {
+            keepalive: Some(Duration::from_secs(30)),
+            ..TcpTuning::default()
+        };
+        let policy = TuningPolicy::new(TcpTuning::default())
+            .with_listener(993, interactive.clone())
+            .with_route(RouteLabel::Socks5, bulk.clone());
+
+        assert_eq!(policy.listener(Some(443)), &TcpTuning::default());
+        assert_eq!(policy.listener(Some(993)), &interactive);
+        assert_eq!(policy.listener(None), &TcpTuning::default());
+        assert_eq!(policy.upstream(RouteLabel::Socks5, Some(993)), &bulk);
+        assert_eq!(policy.upstream(RouteLabel::Direct, Some(993)), &interactive);
+        assert_eq!(policy.upstream(RouteLabel::Direct, Some(443)), &TcpTuning::default());
+
+        let policy = policy.with_default(bulk.clone());
+        assert_eq!(policy.listener(Some(443)), &bulk);
+        assert_eq!(policy.listener(Some(993)), &interactive);
+    }
+
+    #[tokio::test]
+    async fn test_apply_to_stream() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = tokio::net::TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+
+        let tuning = TcpTuning {
+            recv_buffer_size: 64 * 1024,
+            send_buffer_size: 0,
+            nodelay: false,
+            keepalive: Some(Duration::from_secs(45)),
+            ..TcpTuning::default()
+        };
+        tuning.apply_to_stream(&client).unwrap();
+        let socket = SockRef::from(&client);
+        assert!(!socket.nodelay().unwrap());
+        assert!(socket.keepalive().unwrap());
+        assert!(socket.recv_buffer_size().unwrap() >= 64 * 1024);
+
+        #[cfg(target_os = "linux")]
+        {
+            let tuning = TcpTuning {
+                keepalive: Some(Duration::from_secs(60)),
+                keepalive_interval: Some(Duration::from_secs(10)),
+                keepalive_count: Some(3),
+                user_timeout: Some(Duration::from_secs(30)),
+                ..TcpTuning::default()
+            };
+            tuning.apply_to_stream(&client).unwrap();
+            let get = |option| {
+                use std::os::unix::io::AsRawFd;
+                let mut value: libc::c_int = 0;
+                let mut len = std::mem::size_of_val(&value) as libc::socklen_t;
+                // SAFETY: value 和 len 在调用期间有效
+                unsafe {
+                    libc::getsockopt(
+                        client.as_raw_fd(),
+                        libc::IPPROTO_TCP,
+                        option,
+                        &mut value as *mut _ as *mut libc::c_void,
+                        &mut len,
+                    )
+                };
+                value
+            };
+            assert_eq!((get(libc::TCP_KEEPIDLE), get(libc::TCP_KEEPINTVL), get(libc::TCP_KEEPCNT)), (60, 10, 3));
+            assert_eq!(get(libc::TCP_USER_TIMEOUT), 30_000);
+
+            TcpTuning::low_latency().apply_to_stream(&client).unwrap();
+            assert_eq!(get(libc::TCP_QUICKACK), 1);
+        }
+
+        TcpTuning::default().apply_to_stream(&client).unwrap();
+        assert!(socket.nodelay().unwrap());
+    }
+}
@@ -0,0 +1,801 @@
+use log::{debug, error, info, warn};
+use lru::LruCache;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::{mpsc, watch};
+use tokio::time::timeout;
+
+use crate::acl::{Acl, AclAction};
+use crate::ban::BanTable;
+use crate::dns::resolve_host_cached;
+use crate::domain::DomainMatcher;
+use crate::error::{Result, SniProxyError};
+use crate::events::{EventBus, ProxyEvent, RejectReason};
+use crate::geoip::{GeoFilter, SharedGeoIp};
+use crate::ip_matcher::{IpMatcher, SharedIpMatcher};
+use crate::metrics::{ConnectionGuard, MetricLabels, Metrics, RouteLabel, UpstreamLabel};
+use crate::outbound::OutboundOptions;
+use crate::quic::{is_quic_initial, QuicSniffResult, QuicSniffer};
+use crate::route_table::{RouteAction, RouteQuery, SharedRouteTable, Socks5Upstreams};
+use crate::sni_map::PinnedIps;
+use crate::socks5::{decode_udp_datagram, encode_udp_datagram, udp_associate_via_socks5, Socks5Config};
+
+/// UDP 数据报最大长度
+const MAX_DATAGRAM_SIZE: usize = 65535;
+
+/// 等待 Client Hello 完整期间每个客户端最多缓存的数据报数
+const MAX_PENDING_DATAGRAMS: usize = 8;
+
+/// 等待 Client Hello 完整的超时
+const PENDING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 同时等待 Client Hello 的客户端数上限（不超过 `max_sessions`）
+const MAX_PENDING_SESSIONS: usize = 256;
+
+/// 拒绝记录的数量上限
+const MAX_REJECTED_ENTRIES: usize = 4096;
+
+/// 被拒绝的客户端地址在此期间内的数据报直接丢弃（避免重复解析和日志刷屏）
+const REJECT_TTL: Duration = Duration::from_secs(10);
+
+/// 没有配置转发空闲超时时的会话空闲超时（QUIC 默认 max_idle_timeout 通常为 30 秒，再留一些余量）
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 会话清理间隔
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 每个会话转发队列的容量（队列满时丢弃数据报，由 QUIC 自行重传）
+const SESSION_QUEUE_SIZE: usize = 256;
+
+/// QUIC 转发所需的共享状态
+#[derive(Clone)]
+pub(crate) struct QuicRelayContext {
+    pub(crate) routes: SharedRouteTable,
+    pub(crate) ip_matcher: SharedIpMatcher,
+    pub(crate) acl: Option<Arc<Acl>>,
+    pub(crate) blacklist: Option<Arc<DomainMatcher>>,
+    pub(crate) ip_blacklist: Option<Arc<IpMatcher>>,
+    pub(crate) ban_table: Option<Arc<BanTable>>,
+    pub(crate) geoip: Option<SharedGeoIp>,
+    pub(crate) geo_filter: Option<Arc<GeoFilter>>,
+    pub(crate) socks5_upstreams: Arc<Socks5Upstreams>,
+    /// 全局出站选项（路由规则中的选项优先，TCP Fast Open 不适用）
+    pub(crate) outbound: Arc<OutboundOptions>,
+    pub(crate) pinned_ips: Option<Arc<PinnedIps>>,
+    pub(crate) metrics: Metrics,
+    pub(crate) events: EventBus,
+    /// 目标端口（按 UDP 监听端口做端口映射）
+    pub(crate) target_port: u16,
+    /// 最大并发会话数
+    pub(crate) max_sessions: usize,
+    /// 会话空闲超时（None 时使用 [`DEFAULT_IDLE_TIMEOUT`]）
+    pub(crate) idle_timeout: Option<Duration>,
+    /// 会话最长持续时间
+    pub(crate) max_lifetime: Option<Duration>,
+    /// 会话结束时输出访问记录
+    pub(crate) access_log: bool,
+}
+
+/// 会话的路由结果
+struct SessionRoute {
+    /// SOCKS5 上游，None 表示直连
+    socks5: Option<Arc<Socks5Config>>,
+    outbound: Arc<OutboundOptions>,
+}
+
+/// 正在重组 Client Hello 的客户端
+struct PendingSession {
+    sniffer: QuicSniffer,
+    datagrams: Vec<Vec<u8>>,
+    created: Instant,
+}
+
+/// 客户端会话状态（按客户端地址区分）
+///
+/// 三类状态分开计数：只有已建立的会话占用 `max_sessions`，等待 Client Hello 的客户端和拒绝记录
+/// 各有自己的上限，伪造源地址的 Initial 包洪水不会挤占正常客户端的名额
+struct SessionTable {
+    /// 正在重组 Client Hello
+    pending: HashMap<SocketAddr, PendingSession>,
+    /// 已建立转发，数据报交给会话任务发送
+    established: HashMap<SocketAddr, mpsc::Sender<Vec<u8>>>,
+    /// 已拒绝的客户端及拒绝记录的过期时间（超过上限时淘汰最早的记录）
+    rejected: LruCache<SocketAddr, Instant>,
+}
+
+impl SessionTable {
+    fn new() -> Self {
+        Self {
+            pending: HashMap::new(),
+            established: HashMap::new(),
+            rejected: LruCache::new(NonZeroUsize::new(MAX_REJECTED_ENTRIES).unwrap()),
+        }
+    }
+
+    /// 记录被拒绝的客户端，`REJECT_TTL` 内的数据报直接丢弃
+    fn reject(&mut self, client_addr: SocketAddr) {
+        self.rejected.put(client_addr, Instant::now() + REJECT_TTL);
+    }
+
+    /// 清理超时的待定会话、过期的拒绝记录和已结束的会话
+    fn cleanup(&mut self, metrics: &Metrics) {
+        let now = Instant::now();
+        self.pending.retain(|client_addr, pending| {
+            let keep = now.duration_since(pending.created) < PENDING_TIMEOUT;
+            if !keep {
+                debug!("等待 {} 的 QUIC Client Hello 超时", client_addr);
+                metrics.inc_connection_timeouts();
+            }
+            keep
+        });
+        self.established.retain(|_, sender| !sender.is_closed());
+        // 拒绝记录的有效期相同，最早写入的记录最先过期
+        while let Some((_, &until)) = self.rejected.peek_lru() {
+            if now < until {
+                break;
+            }
+            self.rejected.pop_lru();
+        }
+    }
+}
+
+/// 上游 UDP 连接
+enum Upstream {
+    Direct(UdpSocket),
+    /// 通过 SOCKS5 UDP 中继转发，控制连接需在会话期间保持
+    Socks5 {
+        socket: UdpSocket,
+        _control: TcpStream,
+        host: String,
+        port: u16,
+    },
+}
+
+impl Upstream {
+    async fn send(&self, payload: &[u8]) -> Result<()> {
+        match self {
+            Upstream::Direct(socket) => socket.send(payload).await?,
+            Upstream::Socks5 { socket, host, port, .. } => socket.send(&encode_udp_datagram(host, *port, payload)?).await?,
+        };
+        Ok(())
+    }
+
+    /// 接收上游数据报，返回负载在 `buffer` 中的范围
+    async fn recv(&self, buffer: &mut [u8]) -> Result<std::ops::Range<usize>> {
+        loop {
+            match self {
+                Upstream::Direct(socket) => return Ok(0..socket.recv(buffer).await?),
+                Upstream::Socks5 { socket, .. } => {
+                    let n = socket.recv(buffer).await?;
+                    // 无法解析的中继数据报直接丢弃
+                    if let Some(payload) = decode_udp_datagram(&buffer[..n]) {
+                        return Ok(n - payload.len()..n);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 运行 QUIC 监听
+///
+/// 从客户端 Initial 包解析 SNI，按白名单直连或通过 SOCKS5 UDP 关联转发该客户端的全部数据报。
+/// `stop_rx` 收到停止信号时退出
+pub(crate) async fn run_quic_relay(socket: UdpSocket, ctx: QuicRelayContext, mut stop_rx: watch::Receiver<bool>) {
+    let socket = Arc::new(socket);
+    let mut sessions = SessionTable::new();
+    let mut buffer = vec![0u8; MAX_DATAGRAM_SIZE];
+    let mut cleanup = tokio::time::interval(CLEANUP_INTERVAL);
+
+    loop {
+        tokio::select! {
+            result = socket.recv_from(&mut buffer) => {
+                match result {
+                    Ok((n, client_addr)) => {
+                        handle_datagram(&socket, &ctx, &mut sessions, client_addr, &buffer[..n]);
+                    }
+                    Err(e) => {
+                        warn!("QUIC 监听接收数据报失败: {}", e);
+                    }
+                }
+            }
+            _ = cleanup.tick() => sessions.cleanup(&ctx.metrics),
+            _ = stop_rx.wait_for(|&stop| stop) => break,
+        }
+    }
+
+    debug!("QUIC 监听已停止，丢弃 {} 个会话", sessions.pending.len() + sessions.established.len());
+}
+
+/// 处理一个客户端数据报
+fn handle_datagram(
+    socket: &Arc<UdpSocket>,
+    ctx: &QuicRelayContext,
+    sessions: &mut SessionTable,
+    client_addr: SocketAddr,
+    datagram: &[u8],
+) {
+    if let Some(sender) = sessions.established.get(&client_addr) {
+        match sender.try_send(datagram.to_vec()) {
+            Ok(()) => return,
+            Err(mpsc::error::TrySendError::Full(_)) => return,
+            // 会话已结束（空闲超时或上游出错），按新会话处理
+            Err(mpsc::error::TrySendError::Closed(_)) => {
+                sessions.established.remove(&client_addr);
+            }
+        }
+    }
+    if let Some(&until) = sessions.rejected.peek(&client_addr) {
+        if Instant::now() < until {
+            return;
+        }
+        sessions.rejected.pop(&client_addr);
+    }
+
+    // 双栈监听时 IPv4 客户端的地址是 ::ffff:a.b.c.d，统一转换后再做 IP 检查
+    let client_ip = client_addr.ip().to_canonical();
+
+    if !sessions.pending.contains_key(&client_addr) {
+        // 新会话的第一个数据报必须是 Initial 包，其他数据报（例如过期连接的短头部包）直接丢弃
+        if !is_quic_initial(datagram) {
+            return;
+        }
+
+        if ctx.ban_table.as_ref().is_some_and(|table| table.check(client_ip, Instant::now()).is_some()) {
+            debug!("IP {} 处于封禁期间，丢弃 QUIC 连接", client_ip);
+            ctx.metrics.inc_rejected_requests();
+            ctx.metrics.inc_ban_rejections();
+            ctx.events.emit(|| ProxyEvent::Rejected {
+                client_addr,
+                host: None,
+                reason: RejectReason::IpBanned,
+            });
+            sessions.reject(client_addr);
+            return;
+        }
+
+        if ctx.ip_blacklist.as_ref().is_some_and(|m| m.matches(client_ip)) {
+            warn!("❌ IP {} 在黑名单中，拒绝 QUIC 连接", client_ip);
+            ctx.metrics.inc_rejected_requests();
+            ctx.events.emit(|| ProxyEvent::Rejected {
+                client_addr,
+                host: None,
+                reason: RejectReason::IpBlacklisted,
+            });
+            record_failure(ctx, client_ip);
+            sessions.reject(client_addr);
+            return;
+        }
+
+        if let Some(ip_matcher) = &*ctx.ip_matcher.load() {
+            if !ip_matcher.matches(client_ip) {
+                warn!("❌ IP {} 不在白名单中，拒绝 QUIC 连接", client_ip);
+                ctx.metrics.inc_rejected_requests();
+                ctx.events.emit(|| ProxyEvent::Rejected {
+                    client_addr,
+                    host: None,
+                    reason: RejectReason::IpNotAllowed,
+                });
+                record_failure(ctx, client_ip);
+                sessions.reject(client_addr);
+                return;
+            }
+        }
+
+        if let (Some(geo_filter), Some(geoip)) = (&ctx.geo_filter, &ctx.geoip) {
+            if let Some(country) = geoip.load().country(client_ip).filter(|&country| !geo_filter.allows(Some(country))) {
+                warn!("❌ IP {} 所在国家 {} 不被允许，拒绝 QUIC 连接", client_ip, country);
+                ctx.metrics.inc_rejected_requests();
+                ctx.metrics.inc_geo_rejections();
+                ctx.events.emit(|| ProxyEvent::Rejected {
+                    client_addr,
+                    host: None,
+                    reason: RejectReason::GeoBlocked,
+                });
+                record_failure(ctx, client_ip);
+                sessions.reject(client_addr);
+                return;
+            }
+        }
+
+        if sessions.established.len() >= ctx.max_sessions {
+            debug!("QUIC 会话数达到上限 {}，丢弃来自 {} 的数据报", ctx.max_sessions, client_addr);
+            ctx.events.emit(|| ProxyEvent::QuotaExceeded { client_addr, limit: ctx.max_sessions });
+            return;
+        }
+        let max_pending = MAX_PENDING_SESSIONS.min(ctx.max_sessions);
+        if sessions.pending.len() >= max_pending {
+            debug!("等待 Client Hello 的 QUIC 客户端数达到上限 {}，丢弃来自 {} 的数据报", max_pending, client_addr);
+            return;
+        }
+
+        sessions.pending.insert(
+            client_addr,
+            PendingSession {
+                sniffer: QuicSniffer::new(),
+                datagrams: Vec::new(),
+                created: Instant::now(),
+            },
+        );
+    }
+
+    let Some(PendingSession { sniffer, datagrams, .. }) = sessions.pending.get_mut(&client_addr) else {
+        return;
+    };
+
+    if datagrams.len() >= MAX_PENDING_DATAGRAMS {
+        return;
+    }
+    datagrams.push(datagram.to_vec());
+
+    // 客户端可能在 Client Hello 之后紧接着发送 0-RTT 包，这些包只缓存、不解析
+    if !is_quic_initial(datagram) {
+        return;
+    }
+
+    let sni = match sniffer.feed(datagram) {
+        QuicSniffResult::Sni(sni) => sni,
+        QuicSniffResult::NeedMore => return,
+        QuicSniffResult::Invalid => {
+            warn!("无法从 {} 的 QUIC Initial 包中解析 SNI", client_addr);
+            ctx.metrics.inc_sni_parse_errors();
+            ctx.events.emit(|| ProxyEvent::Rejected {
+                client_addr,
+                host: None,
+                reason: RejectReason::SniParseError,
+            });
+            sessions.pending.remove(&client_addr);
+            record_failure(ctx, client_ip);
+            sessions.reject(client_addr);
+            return;
+        }
+    };
+
+    let Some(PendingSession { datagrams, .. }) = sessions.pending.remove(&client_addr) else {
+        return;
+    };
+
+    if ctx.blacklist.as_ref().is_some_and(|m| m.matches(&sni)) {
+        let rejected = ctx.metrics.get_rejected_requests() + 1;
+        warn!("❌ 域名 {} 在黑名单中，拒绝连接（QUIC）| 累计拒绝: {}", sni, rejected);
+        let labels = ctx.metrics.labels().unwrap_or_default();
+        ctx.metrics.with_labels(MetricLabels { route: RouteLabel::Rejected, ..labels }).inc_rejected_requests();
+        ctx.events.emit(|| ProxyEvent::Rejected {
+            client_addr,
+            host: Some(sni.clone()),
+            reason: RejectReason::DomainBlacklisted,
+        });
+        record_failure(ctx, client_ip);
+        sessions.reject(client_addr);
+        return;
+    }
+
+    if ctx.acl.as_ref().is_some_and(|acl| acl.check(client_ip, &sni) == AclAction::Deny) {
+        let rejected = ctx.metrics.get_rejected_requests() + 1;
+        warn!("❌ ACL 拒绝 {} 访问 {}（QUIC）| 累计拒绝: {}", client_ip, sni, rejected);
+        let labels = ctx.metrics.labels().unwrap_or_default();
+        ctx.metrics.with_labels(MetricLabels { route: RouteLabel::Rejected, ..labels }).inc_rejected_requests();
+        ctx.events.emit(|| ProxyEvent::Rejected {
+            client_addr,
+            host: Some(sni.clone()),
+            reason: RejectReason::AclDenied,
+        });
+        record_failure(ctx, client_ip);
+        sessions.reject(client_addr);
+        return;
+    }
+
+    let route = match route(ctx, &sni, client_ip) {
+        Some(route) => route,
+        None => {
+            ctx.events.emit(|| ProxyEvent::Rejected {
+                client_addr,
+                host: Some(sni.clone()),
+                reason: RejectReason::DomainNotAllowed,
+            });
+            record_failure(ctx, client_ip);
+            sessions.reject(client_addr);
+            return;
+        }
+    };
+
+    // 等待 Client Hello 期间其他客户端可能已经占满名额
+    if sessions.established.len() >= ctx.max_sessions {
+        debug!("QUIC 会话数达到上限 {}，丢弃来自 {} 的数据报", ctx.max_sessions, client_addr);
+        ctx.events.emit(|| ProxyEvent::QuotaExceeded { client_addr, limit: ctx.max_sessions });
+        return;
+    }
+
+    let (sender, receiver) = mpsc::channel(SESSION_QUEUE_SIZE);
+    for datagram in datagrams {
+        let _ = sender.try_send(datagram);
+    }
+    sessions.established.insert(client_addr, sender);
+
+    let socket = Arc::clone(socket);
+    let ctx = ctx.clone();
+    tokio::spawn(async move {
+        run_session(socket, ctx, client_addr, sni, route, receiver).await;
+    });
+}
+
+/// 被拒绝的客户端计入自动封禁，达到阈值时封禁客户端 IP
+fn record_failure(ctx: &QuicRelayContext, client_ip: IpAddr) {
+    let Some(ban_table) = &ctx.ban_table else {
+        return;
+    };
+    if let Some(duration) = ban_table.record_failure(client_ip, Instant::now()) {
+        warn!("🚫 IP {} 失败的 QUIC 连接过多，封禁 {:?}", client_ip, duration);
+    }
+}
+
+/// 按路由表决定路由，返回 None 表示拒绝
+///
+/// QUIC 没有 ALPN 信息，带 ALPN 条件的规则不会匹配；backend 路由只适用于 TCP，按拒绝处理
+fn route(ctx: &QuicRelayContext, sni: &str, client_ip: IpAddr) -> Option<SessionRoute> {
+    let with_route = |route| {
+        let labels = ctx.metrics.labels().unwrap_or_default();
+        ctx.metrics.with_labels(MetricLabels { route, ..labels })
+    };
+    let country = ctx.geoip.as_ref().and_then(|geoip| geoip.load().country(client_ip));
+    let query = RouteQuery { domain: sni, client_ip, country, alpn: &[] };
+    let routes = ctx.routes.load();
+    let rule = routes.lookup(&query);
+    let outbound = match rule.and_then(|rule| rule.outbound()) {
+        Some(outbound) => Arc::new(outbound.or(&ctx.outbound)),
+        None => Arc::clone(&ctx.outbound),
+    };
+    match rule.map(|rule| rule.action()) {
+        Some(RouteAction::Socks5(name)) => {
+            debug!("QUIC 域名 {} 匹配 SOCKS5 路由: {}", sni, name);
+            with_route(RouteLabel::Socks5).inc_socks5_requests();
+            Some(SessionRoute { socks5: ctx.socks5_upstreams.get(name).cloned(), outbound })
+        }
+        Some(RouteAction::Direct) => {
+            debug!("QUIC 域名 {} 匹配直连路由", sni);
+            with_route(RouteLabel::Direct).inc_direct_requests();
+            Some(SessionRoute { socks5: None, outbound })
+        }
+        action => {
+            let rejected = ctx.metrics.get_rejected_requests() + 1;
+            if let Some(RouteAction::Backend(backend)) = action {
+                warn!("❌ QUIC 不支持 backend 路由（{} → {}），拒绝连接 | 累计拒绝: {}", sni, backend, rejected);
+            } else {
+                warn!("❌ QUIC 域名 {} 不在白名单中，拒绝连接 | 累计拒绝: {}", sni, rejected);
+            }
+            with_route(RouteLabel::Rejected).inc_rejected_requests();
+            None
+        }
+    }
+}
+
+/// 连接上游并转发一个客户端会话，直到空闲超时、超过最长持续时间或出错
+async fn run_session(
+    socket: Arc<UdpSocket>,
+    mut ctx: QuicRelayContext,
+    client_addr: SocketAddr,
+    sni: String,
+    route: SessionRoute,
+    mut receiver: mpsc::Receiver<Vec<u8>>,
+) {
+    let SessionRoute { socks5, outbound } = route;
+    let via_socks5 = socks5.is_some();
+    let (route, upstream) = if via_socks5 {
+        (RouteLabel::Socks5, UpstreamLabel::Socks5)
+    } else {
+        (RouteLabel::Direct, UpstreamLabel::Direct)
+    };
+    let labels = ctx.metrics.labels().unwrap_or_default();
+    ctx.metrics = ctx.metrics.with_labels(MetricLabels { route, upstream, ..labels });
+    let _guard = ConnectionGuard::new(ctx.metrics.clone());
+    let port = ctx.target_port;
+    let session_start = Instant::now();
+
+    let client_ip = client_addr.ip().to_canonical();
+    let upstream = match connect_upstream(&ctx, &sni, client_ip, socks5.as_deref(), &outbound).await {
+        Ok(upstream) => upstream,
+        Err(e) => {
+            error!("QUIC 连接到 {}:{} 失败: {}", sni, port, e);
+            if via_socks5 {
+                ctx.metrics.inc_socks5_errors();
+            }
+            ctx.metrics.inc_failed_connections();
+            ctx.events.emit(|| ProxyEvent::UpstreamDown {
+                host: sni.clone(),
+                port,
+                via_socks5,
+                error: e.to_string(),
+            });
+            return;
+        }
+    };
+
+    info!("✅ QUIC 会话已建立: {} → {}:{}{}", client_addr, sni, port, if via_socks5 { "（SOCKS5）" } else { "" });
+
+    let mut buffer = vec![0u8; MAX_DATAGRAM_SIZE];
+    let (mut sent, mut received) = (0u64, 0u64);
+    let reason;
+    let idle_timeout = ctx.idle_timeout.unwrap_or(DEFAULT_IDLE_TIMEOUT);
+    let lifetime = async {
+        match ctx.max_lifetime {
+            Some(lifetime) => tokio::time::sleep(lifetime).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(lifetime);
+
+    loop {
+        // 内层返回 Some(原因) 表示会话结束
+        let result = timeout(idle_timeout, async {
+            tokio::select! {
+                _ = &mut lifetime => Ok(Some("max_lifetime")),
+                datagram = receiver.recv() => match datagram {
+                    Some(datagram) => {
+                        upstream.send(&datagram).await?;
+                        sent += datagram.len() as u64;
+                        Ok::<_, SniProxyError>(None)
+                    }
+                    None => Ok(Some("closed")),
+                },
+                result = upstream.recv(&mut buffer) => {
+                    let range = result?;
+                    received += range.len() as u64;
+                    socket.send_to(&buffer[range], client_addr).await?;
+                    Ok(None)
+                }
+            }
+        })
+        .await;
+
+        reason = match result {
+            Ok(Ok(None)) => continue,
+            Ok(Ok(Some(reason))) => {
+                if reason == "max_lifetime" {
+                    info!(
+                        "⏱️  QUIC 会话 {} → {} 超过最长连接时间 {:?}，已断开",
+                        client_addr,
+                        sni,
+                        ctx.max_lifetime.unwrap_or_default()
+                    );
+                }
+                reason
+            }
+            Ok(Err(e)) => {
+                debug!("QUIC 会话 {} → {} 转发失败: {}", client_addr, sni, e);
+                "relay_error"
+            }
+            Err(_) => {
+                debug!("QUIC 会话 {} → {} 空闲超时", client_addr, sni);
+                ctx.metrics.inc_idle_timeouts();
+                "idle_timeout"
+            }
+        };
+        break;
+    }
+
+    ctx.metrics.add_bytes_received(sent);
+    ctx.metrics.add_bytes_sent(received);
+    ctx.events.emit(|| ProxyEvent::ConnectionClosed {
+        client_addr,
+        host: sni.clone(),
+        bytes_received: sent,
+        bytes_sent: received,
+    });
+
+    if !ctx.access_log {
+        return;
+    }
+    // 访问记录：直连时为 DNS 解析后的目标地址，SOCKS5 由代理服务器解析
+    let upstream_desc = match &socks5 {
+        Some(socks5) => format!("socks5 ({})", socks5.addr),
+        None => "direct".to_string(),
+    };
+    let target = match &upstream {
+        Upstream::Direct(socket) => socket.peer_addr().ok(),
+        Upstream::Socks5 { .. } => None,
+    };
+    info!(
+        "📋 {} → {} (QUIC) | 路由: {} | 上游: {} | 目标: {} | 上传: {} B | 下载: {} B | 耗时: {:?} | 结束: {}",
+        client_addr,
+        sni,
+        route,
+        upstream_desc,
+        target.map_or_else(|| format!("{}:{}", sni, port), |addr| addr.to_string()),
+        sent,
+        received,
+        session_start.elapsed(),
+        reason
+    );
+}
+
+/// 建立到目标的 UDP 连接（直连时使用固定 IP 或解析 DNS，SOCKS5 时建立 UDP 关联）
+async fn connect_upstream(
+    ctx: &QuicRelayContext,
+    sni: &str,
+    client_ip: IpAddr,
+    socks5: Option<&Socks5Config>,
+    outbound: &OutboundOptions,
+) -> Result<Upstream> {
+    if let Some(socks5) = socks5 {
+        let association = udp_associate_via_socks5(socks5, outbound).await?;
+        let socket = outbound.connect_udp(association.relay_addr).await?;
+        return Ok(Upstream::Socks5 {
+            socket,
+            _control: association.control,
+            host: sni.to_string(),
+            port: ctx.target_port,
+        });
+    }
+
+    let ip = match ctx.pinned_ips.as_ref().and_then(|pinned| pinned.lookup(sni)) {
+        // 与 TCP 一样按客户端 IP 选择固定 IP，使同一客户端总是连接同一个 IP
+        Some(ips) => {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            std::hash::Hash::hash(&client_ip, &mut hasher);
+            ips[(std::hash::Hasher::finish(&hasher) % ips.len() as u64) as usize]
+        }
+        None => resolve_host_cached(sni).await?[0],
+    };
+    let socket = outbound.connect_udp(SocketAddr::new(ip, ctx.target_port)).await?;
+    Ok(Upstream::Direct(socket))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quic::tests::build_client_initial;
+    use crate::route_table::RouteTable;
+    use arc_swap::{ArcSwap, ArcSwapOption};
+
+    fn test_context(whitelist: &[&str]) -> QuicRelayContext {
+        QuicRelayContext {
+            routes: Arc::new(ArcSwap::from_pointee(RouteTable::from_whitelists(
+                whitelist.iter().map(|s| s.to_string()).collect(),
+                Vec::new(),
+            ))),
+            ip_matcher: Arc::new(ArcSwapOption::empty()),
+            acl: None,
+            blacklist: None,
+            ip_blacklist: None,
+            ban_table: None,
+            geoip: None,
+            geo_filter: None,
+            socks5_upstreams: Arc::new(Socks5Upstreams::new()),
+            outbound: Arc::new(OutboundOptions::default()),
+            pinned_ips: None,
+            metrics: Metrics::new(),
+            events: EventBus::default(),
+            target_port: 0,
+            max_sessions: 16,
+            idle_timeout: None,
+            max_lifetime: None,
+            access_log: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_relay_to_upstream() {
+        // 模拟上游 QUIC 服务器：原样回显
+        let upstream = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut ctx = test_context(&["localhost"]);
+        ctx.target_port = upstream.local_addr().unwrap().port();
+
+        let listener = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let listen_addr = listener.local_addr().unwrap();
+        let (_stop_tx, stop_rx) = watch::channel(false);
+        tokio::spawn(run_quic_relay(listener, ctx, stop_rx));
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let initial = build_client_initial("localhost");
+        client.send_to(&initial, listen_addr).await.unwrap();
+
+        let mut buffer = vec![0u8; MAX_DATAGRAM_SIZE];
+        let (n, relay_addr) = upstream.recv_from(&mut buffer).await.unwrap();
+        assert_eq!(&buffer[..n], &initial[..]);
+
+        upstream.send_to(b"reply", relay_addr).await.unwrap();
+        let (n, _) = client.recv_from(&mut buffer).await.unwrap();
+        assert_eq!(&buffer[..n], b"reply");
+    }
+
+    #[tokio::test]
+    async fn test_rejected_entries_capped() {
+        let ctx = test_context(&["example.com"]);
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let mut sessions = SessionTable::new();
+        let initial = build_client_initial("evil.com");
+
+        // 伪造源地址的洪水：拒绝记录不超过上限，也不占用会话名额
+        for i in 0..MAX_REJECTED_ENTRIES as u32 + 100 {
+            let client_addr = SocketAddr::new(IpAddr::from((0x0a00_0000 + i).to_be_bytes()), 443);
+            handle_datagram(&socket, &ctx, &mut sessions, client_addr, &initial);
+        }
+        assert_eq!(sessions.rejected.len(), MAX_REJECTED_ENTRIES);
+        assert!(sessions.pending.is_empty() && sessions.established.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_ipv4_mapped_client() {
+        // 双栈监听时 IPv4 客户端的地址是 ::ffff:127.0.0.1，应按 IPv4 白名单匹配
+        let ctx = test_context(&["localhost"]);
+        ctx.ip_matcher.store(Some(Arc::new(IpMatcher::new(vec!["127.0.0.1".to_string()]))));
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let mut sessions = SessionTable::new();
+
+        let client_addr: SocketAddr = "[::ffff:127.0.0.1]:5000".parse().unwrap();
+        handle_datagram(&socket, &ctx, &mut sessions, client_addr, &build_client_initial("localhost"));
+        assert_eq!(sessions.rejected.len(), 0);
+        assert!(sessions.established.contains_key(&client_addr));
+    }
+
+    #[tokio::test]
+    async fn test_session_max_lifetime() {
+        // 没有到达空闲超时，会话也在最长持续时间到达后结束
+        let upstream = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut ctx = test_context(&["localhost"]);
+        ctx.target_port = upstream.local_addr().unwrap().port();
+        ctx.max_lifetime = Some(Duration::from_millis(200));
+        let mut events = ctx.events.subscribe();
+
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let mut sessions = SessionTable::new();
+        let client_addr: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        handle_datagram(&socket, &ctx, &mut sessions, client_addr, &build_client_initial("localhost"));
+
+        let closed = timeout(Duration::from_secs(5), async {
+            loop {
+                if let ProxyEvent::ConnectionClosed { host, .. } = events.recv().await.unwrap() {
+                    return host;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(closed, "localhost");
+        assert!(sessions.established[&client_addr].is_closed());
+    }
+
+    #[tokio::test]
+    async fn test_rejections_recorded_in_ban_table() {
+        let mut ctx = test_context(&["example.com"]);
+        ctx.ban_table = Some(Arc::new(BanTable::new(crate::ban::BanConfig {
+            window: Duration::from_secs(60),
+            max_failures: 2,
+            ban_duration: Duration::from_secs(60),
+        })));
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let mut sessions = SessionTable::new();
+
+        for port in [5000, 5001] {
+            let client_addr = SocketAddr::from(([127, 0, 0, 1], port));
+            handle_datagram(&socket, &ctx, &mut sessions, client_addr, &build_client_initial("evil.com"));
+        }
+        let ban_table = ctx.ban_table.as_ref().unwrap();
+        assert!(ban_table.check("127.0.0.1".parse().unwrap(), Instant::now()).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_rejected_domain() {
+        let ctx = test_context(&["example.com"]);
+        let mut events = ctx.events.subscribe();
+        let listener = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let listen_addr = listener.local_addr().unwrap();
+        let (_stop_tx, stop_rx) = watch::channel(false);
+        tokio::spawn(run_quic_relay(listener, ctx, stop_rx));
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.send_to(&build_client_initial("evil.com"), listen_addr).await.unwrap();
+
+        match events.recv().await.unwrap() {
+            ProxyEvent::Rejected { host, reason, .. } => {
+                assert_eq!(host.as_deref(), Some("evil.com"));
+                assert_eq!(reason, RejectReason::DomainNotAllowed);
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+}
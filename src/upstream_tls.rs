@@ -0,0 +1,243 @@
+//! 上游 TLS 握手检查（被动）
+//!
+//! 转发 TLS 连接时观察上游最先返回的数据，只看记录头和握手消息类型（不解密、不缓存、不改变转发的数据），
+//! 确认上游确实在回应 TLS 握手。路由到错误的端口或服务时，上游常常返回 HTTP 错误页或其他协议的数据，
+//! 连接本身却是成功的；这类“连上了但不是 TLS”的情况单独计数并记录日志
+
+use log::{debug, warn};
+use std::fmt;
+use std::io::IoSlice;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::metrics::Metrics;
+use crate::tls::TLS_RECORD_HEADER_LEN;
+
+/// TLS 记录类型
+const CONTENT_ALERT: u8 = 0x15;
+const CONTENT_HANDSHAKE: u8 = 0x16;
+
+/// 握手消息类型 ServerHello（HelloRetryRequest 也使用该类型）
+const HANDSHAKE_SERVER_HELLO: u8 = 0x02;
+
+/// HTTP 状态行最多记录的长度
+const MAX_STATUS_LINE: usize = 64;
+
+/// 上游对 TLS 握手的第一份响应
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpstreamReply {
+    /// TLS 握手记录
+    Handshake {
+        /// 第一个握手消息是否为 ServerHello
+        server_hello: bool,
+        /// 首批数据中完整的 TLS 记录数
+        records: usize,
+        /// 首批数据中完整 TLS 记录的总字节数（ServerHello、证书等）
+        bytes: usize,
+    },
+    /// TLS alert（例如上游不认识该 SNI、不支持客户端的协议版本）
+    Alert {
+        /// alert 描述码（数据不完整时为 None）
+        description: Option<u8>,
+    },
+    /// HTTP 响应（状态行）
+    Http(String),
+    /// 其他数据
+    Other,
+}
+
+impl UpstreamReply {
+    /// 是否是 TLS 记录（握手或 alert）
+    pub fn is_tls(&self) -> bool {
+        matches!(self, UpstreamReply::Handshake { .. } | UpstreamReply::Alert { .. })
+    }
+}
+
+impl fmt::Display for UpstreamReply {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpstreamReply::Handshake { server_hello: true, records, bytes } => {
+                write!(f, "ServerHello（首批 {} 个记录，{} 字节）", records, bytes)
+            }
+            UpstreamReply::Handshake { server_hello: false, records, bytes } => {
+                write!(f, "非 ServerHello 的握手消息（首批 {} 个记录，{} 字节）", records, bytes)
+            }
+            UpstreamReply::Alert { description: Some(description) } => write!(f, "TLS alert {}", description),
+            UpstreamReply::Alert { description: None } => write!(f, "TLS alert"),
+            UpstreamReply::Http(status_line) => write!(f, "HTTP 响应 \"{}\"", status_line),
+            UpstreamReply::Other => write!(f, "非 TLS 数据"),
+        }
+    }
+}
+
+/// 按上游最先返回的数据判断响应类型（data 不能为空）
+pub fn classify(data: &[u8]) -> UpstreamReply {
+    let is_record =
+        |content_type: u8| data.first() == Some(&content_type) && data.get(1).is_none_or(|&major| major == 0x03);
+
+    if is_record(CONTENT_HANDSHAKE) {
+        let (records, bytes) = complete_records(data);
+        return UpstreamReply::Handshake {
+            server_hello: data.get(TLS_RECORD_HEADER_LEN).is_none_or(|&t| t == HANDSHAKE_SERVER_HELLO),
+            records,
+            bytes,
+        };
+    }
+    if is_record(CONTENT_ALERT) {
+        return UpstreamReply::Alert { description: data.get(TLS_RECORD_HEADER_LEN + 1).copied() };
+    }
+    if data.starts_with(b"HTTP/") {
+        let end = data
+            .iter()
+            .take(MAX_STATUS_LINE)
+            .position(|&b| b == b'\r' || b == b'\n')
+            .unwrap_or(data.len().min(MAX_STATUS_LINE));
+        return UpstreamReply::Http(String::from_utf8_lossy(&data[..end]).into_owned());
+    }
+    UpstreamReply::Other
+}
+
+/// 数据中完整 TLS 记录的数量和总字节数
+fn complete_records(data: &[u8]) -> (usize, usize) {
+    let mut records = 0;
+    let mut pos = 0;
+    while pos + TLS_RECORD_HEADER_LEN <= data.len() {
+        let end = pos + TLS_RECORD_HEADER_LEN + u16::from_be_bytes([data[pos + 3], data[pos + 4]]) as usize;
+        if end > data.len() {
+            break;
+        }
+        records += 1;
+        pos = end;
+    }
+    (records, pos)
+}
+
+/// 检查上游第一次返回的数据的流包装（之后的读取直接透传）
+pub(crate) struct UpstreamTlsCheck<S> {
+    inner: S,
+    sni: String,
+    metrics: Metrics,
+    checked: bool,
+}
+
+impl<S> UpstreamTlsCheck<S> {
+    /// `enabled` 为 false 时（客户端发送的不是 TLS 握手）不做检查
+    pub(crate) fn new(inner: S, sni: &str, metrics: Metrics, enabled: bool) -> Self {
+        Self {
+            inner,
+            sni: if enabled { sni.to_string() } else { String::new() },
+            metrics,
+            checked: !enabled,
+        }
+    }
+
+    fn check(&mut self, data: &[u8]) {
+        self.checked = true;
+        check_reply(&self.sni, &self.metrics, data);
+    }
+}
+
+/// 按上游第一次返回的数据（data 不能为空）计数并记录日志
+pub(crate) fn check_reply(sni: &str, metrics: &Metrics, data: &[u8]) {
+    match classify(data) {
+        reply @ UpstreamReply::Handshake { .. } => debug!("上游 {} TLS 握手: {}", sni, reply),
+        reply @ UpstreamReply::Alert { .. } => {
+            metrics.inc_upstream_tls_alerts();
+            warn!("⚠️  上游 {} 拒绝了 TLS 握手: {}", sni, reply);
+        }
+        reply => {
+            metrics.inc_upstream_not_tls();
+            warn!("⚠️  上游 {} 没有回应 TLS 握手: {}（可能路由到了错误的端口或服务）", sni, reply);
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for UpstreamTlsCheck<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let this = &mut *self;
+        let filled_before = buf.filled().len();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        // 上游没有返回数据就关闭时不计数：客户端提前断开时上游也会随之关闭
+        if !this.checked && buf.filled().len() > filled_before {
+            let filled = buf.filled();
+            this.check(&filled[filled_before..]);
+        }
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for UpstreamTlsCheck<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn test_classify() {
+        // ServerHello 记录（4 字节负载）+ 不完整的证书记录
+        let mut data = vec![0x16, 0x03, 0x03, 0x00, 0x04, 0x02, 0x00, 0x00, 0x00];
+        data.extend_from_slice(&[0x16, 0x03, 0x03, 0x10, 0x00, 0x0b]);
+        assert_eq!(classify(&data), UpstreamReply::Handshake { server_hello: true, records: 1, bytes: 9 });
+
+        assert_eq!(
+            classify(&[0x15, 0x03, 0x03, 0x00, 0x02, 0x02, 0x70]),
+            UpstreamReply::Alert { description: Some(0x70) }
+        );
+        assert_eq!(
+            classify(b"HTTP/1.1 400 Bad Request\r\nServer: nginx\r\n\r\n"),
+            UpstreamReply::Http("HTTP/1.1 400 Bad Request".to_string())
+        );
+        assert_eq!(classify(b"SSH-2.0-OpenSSH_9.6\r\n"), UpstreamReply::Other);
+        assert_eq!(classify(&[0x16, 0x01]), UpstreamReply::Other);
+        assert!(classify(&[0x16]).is_tls());
+    }
+
+    #[tokio::test]
+    async fn test_check_counts_first_read_only() {
+        let metrics = Metrics::new();
+        let (mut upstream, proxy_side) = tokio::io::duplex(1024);
+        let mut stream = UpstreamTlsCheck::new(proxy_side, "example.com", metrics.clone(), true);
+
+        upstream.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n").await.unwrap();
+        let mut buf = [0u8; 64];
+        let n = stream.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"HTTP/1.1 400 Bad Request\r\n\r\n");
+        upstream.write_all(b"more").await.unwrap();
+        assert_eq!(stream.read(&mut buf).await.unwrap(), 4);
+        assert_eq!(metrics.snapshot().upstream_not_tls, 1);
+
+        // 客户端不是 TLS 时不检查
+        let (mut upstream, proxy_side) = tokio::io::duplex(1024);
+        let mut stream = UpstreamTlsCheck::new(proxy_side, "example.com", metrics.clone(), false);
+        upstream.write_all(b"HTTP/1.1 200 OK\r\n\r\n").await.unwrap();
+        assert!(stream.read(&mut buf).await.unwrap() > 0);
+        assert_eq!(metrics.snapshot().upstream_not_tls, 1);
+        assert_eq!(metrics.snapshot().upstream_tls_alerts, 0);
+    }
+}
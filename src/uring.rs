@@ -0,0 +1,593 @@
+//! io_uring 数据路径（实验性，需要 `io-uring` 功能，仅 Linux 5.11+）
+//!
+//! 连接数很多时，epoll 路径的每次读写都是一次系统调用，外加一次 epoll_wait 唤醒。
+//! io_uring 把读写请求放进与内核共享的提交队列，一次 io_uring_enter 可以提交和收割多个请求：
+//! - 接受连接：每个监听 socket 由一个线程保持多个 accept 请求在队列中，accept 到的连接交给 Tokio 运行时读取 Client Hello
+//! - 转发数据：握手完成后连接从 epoll 中注销，交给 io_uring 工作线程（tokio-uring）双向转发
+//!
+//! 只有 io_uring 接受的连接才会用 io_uring 转发；创建 io_uring 失败（内核不支持或被 seccomp 禁止）时
+//! 使用原来的 epoll 路径
+
+use io_uring::{opcode, types, IoUring};
+use log::{debug, error};
+use std::cell::Cell;
+use std::io;
+use std::net::{IpAddr, Shutdown, SocketAddr};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot, Mutex};
+
+use crate::error::Result;
+use crate::ip_traffic::IpTrafficTracker;
+use crate::listener::{Accept, ClientStream};
+use crate::metrics::Metrics;
+use crate::relay_timeout::RelayTimer;
+use crate::transparent::TransparentMode;
+use crate::tuning::TcpTuning;
+
+/// 每个监听 socket 同时在队列中的 accept 请求数
+const ACCEPT_DEPTH: usize = 16;
+
+/// 停止 accept 线程的 eventfd 读取请求的 user_data
+const STOP_TOKEN: u64 = u64::MAX;
+
+/// 退出时取消请求（AsyncCancel）的 user_data
+const CANCEL_TOKEN: u64 = u64::MAX - 1;
+
+/// 转发工作线程
+pub(crate) struct UringRuntime {
+    workers: Vec<mpsc::UnboundedSender<RelayJob>>,
+    next: AtomicUsize,
+}
+
+impl UringRuntime {
+    /// 启动 `workers` 个转发线程，任一线程创建 io_uring 失败时返回错误（调用方使用 epoll 路径）
+    pub(crate) fn start(workers: usize) -> io::Result<Self> {
+        let mut senders = Vec::with_capacity(workers.max(1));
+        for index in 0..workers.max(1) {
+            let (tx, rx) = mpsc::unbounded_channel();
+            let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+            std::thread::Builder::new()
+                .name(format!("sni-uring-{}", index))
+                .spawn(move || run_worker(rx, ready_tx))?;
+            ready_rx.recv().map_err(|_| io::Error::other("io_uring 转发线程意外退出"))??;
+            senders.push(tx);
+        }
+        Ok(Self { workers: senders, next: AtomicUsize::new(0) })
+    }
+
+    /// 工作线程数
+    pub(crate) fn workers(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// 把连接交给一个工作线程（轮流分配）转发，返回（上传, 下载）字节数
+    pub(crate) async fn relay(&self, relay: UringRelay, client: TcpStream, target: TcpStream) -> io::Result<(u64, u64)> {
+        // into_std 把连接从 Tokio 的 epoll 中注销；io_uring 的读写请求需要阻塞模式的 socket
+        let client = client.into_std()?;
+        let target = target.into_std()?;
+        client.set_nonblocking(false)?;
+        target.set_nonblocking(false)?;
+
+        let (done, result) = oneshot::channel();
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.workers.len();
+        self.workers[index]
+            .send(RelayJob { relay, client, target, done })
+            .map_err(|_| io::Error::other("io_uring 转发线程已退出"))?;
+        result.await.map_err(|_| io::Error::other("io_uring 转发任务被取消"))?
+    }
+}
+
+/// 交给工作线程的转发任务
+struct RelayJob {
+    relay: UringRelay,
+    client: std::net::TcpStream,
+    target: std::net::TcpStream,
+    done: oneshot::Sender<io::Result<(u64, u64)>>,
+}
+
+/// 工作线程：在 tokio-uring 运行时中为每个任务启动一个本地任务
+fn run_worker(mut jobs: mpsc::UnboundedReceiver<RelayJob>, ready: std::sync::mpsc::Sender<io::Result<()>>) {
+    let runtime = match tokio_uring::Runtime::new(&tokio_uring::builder()) {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return;
+        }
+    };
+    let _ = ready.send(Ok(()));
+    runtime.block_on(async move {
+        while let Some(job) = jobs.recv().await {
+            tokio_uring::spawn(async move {
+                let RelayJob { relay, client, target, done } = job;
+                let _ = done.send(relay.run(client, target).await);
+            });
+        }
+    });
+}
+
+/// 一个连接的 io_uring 转发参数（与 [`crate::proxy::SpliceRelay`] 对应）
+pub(crate) struct UringRelay {
+    prefix: Vec<u8>,
+    buffer_size: usize,
+    timer: Option<Arc<RelayTimer>>,
+    tls_check: Option<(String, Metrics)>,
+}
+
+impl UringRelay {
+    /// `prefix`（Client Hello）在转发前发送给目标，每个方向使用 `buffer_size` 字节的缓冲区
+    pub(crate) fn new(prefix: Vec<u8>, buffer_size: usize) -> Self {
+        Self { prefix, buffer_size: buffer_size.max(1), timer: None, tls_check: None }
+    }
+
+    /// 转发计时器：有数据时刷新活动时间，超时后结束转发
+    pub(crate) fn with_timer(mut self, timer: Option<Arc<RelayTimer>>) -> Self {
+        self.timer = timer;
+        self
+    }
+
+    /// 检查上游第一次返回的数据是否为 TLS（见 [`crate::upstream_tls`]）
+    pub(crate) fn with_tls_check(mut self, sni: &str, metrics: Metrics) -> Self {
+        self.tls_check = Some((sni.to_string(), metrics));
+        self
+    }
+
+    /// 在工作线程上双向转发直到两个方向都结束或计时器超时
+    async fn run(self, client: std::net::TcpStream, target: std::net::TcpStream) -> io::Result<(u64, u64)> {
+        let UringRelay { prefix, buffer_size, timer, tls_check } = self;
+        let client = tokio_uring::net::TcpStream::from_std(client);
+        let target = tokio_uring::net::TcpStream::from_std(target);
+        let uploaded = Cell::new(0);
+        let downloaded = Cell::new(0);
+        let timer = timer.as_deref();
+        let transfer = async {
+            let prefix_len = prefix.len() as u64;
+            let (result, _) = target.write_all(prefix).await;
+            result?;
+            uploaded.set(prefix_len);
+            let tls_check = tls_check.as_ref().map(|(sni, metrics)| (sni.as_str(), metrics));
+            tokio::try_join!(
+                copy_one_way(&client, &target, buffer_size, &uploaded, timer, None),
+                copy_one_way(&target, &client, buffer_size, &downloaded, timer, tls_check),
+            )
+        };
+        match timer {
+            Some(timer) => tokio::select! {
+                result = transfer => { result?; }
+                _ = timer.wait_expiry() => {}
+            },
+            None => {
+                transfer.await?;
+            }
+        }
+        Ok((uploaded.get(), downloaded.get()))
+    }
+}
+
+/// 单向转发：读取到 EOF 后关闭 `dst` 的写方向
+async fn copy_one_way(
+    src: &tokio_uring::net::TcpStream,
+    dst: &tokio_uring::net::TcpStream,
+    buffer_size: usize,
+    transferred: &Cell<u64>,
+    timer: Option<&RelayTimer>,
+    mut tls_check: Option<(&str, &Metrics)>,
+) -> io::Result<()> {
+    let mut buf = Vec::with_capacity(buffer_size);
+    loop {
+        let (result, read) = src.read(buf).await;
+        let n = result?;
+        if n == 0 {
+            break;
+        }
+        // 上游没有返回数据就关闭时不计数（与 UpstreamTlsCheck 一致）
+        if let Some((sni, metrics)) = tls_check.take() {
+            crate::upstream_tls::check_reply(sni, metrics, &read[..n]);
+        }
+        let (result, written) = dst.write_all(tokio_uring::buf::IoBuf::slice(read, ..n)).await;
+        result?;
+        buf = written.into_inner();
+        transferred.set(transferred.get() + n as u64);
+        if let Some(timer) = timer {
+            timer.touch();
+        }
+    }
+    match dst.shutdown(Shutdown::Write) {
+        Err(e) if e.kind() != io::ErrorKind::NotConnected => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// 双向代理数据传输（io_uring 版本），统计与 [`crate::proxy::proxy_data_with_buffer_size`] 一致
+pub(crate) async fn uring_data(
+    runtime: &UringRuntime,
+    relay: UringRelay,
+    client_stream: TcpStream,
+    target_stream: TcpStream,
+    metrics: Metrics,
+    client_ip: IpAddr,
+    ip_traffic_tracker: IpTrafficTracker,
+) -> Result<(u64, u64)> {
+    let result = runtime.relay(relay, client_stream, target_stream).await;
+    Ok(crate::proxy::record_transfer(result, &metrics, client_ip, &ip_traffic_tracker))
+}
+
+/// 用 io_uring 接受连接的监听 socket
+pub(crate) struct UringAcceptor {
+    accepted: Mutex<mpsc::Receiver<io::Result<(std::net::TcpStream, SocketAddr)>>>,
+    /// 写入后 accept 线程退出并关闭监听 socket
+    stop: OwnedFd,
+}
+
+impl UringAcceptor {
+    /// 创建 io_uring 并启动 accept 线程，失败时调用方使用 epoll 路径
+    pub(crate) fn new(listener: std::net::TcpListener) -> io::Result<Self> {
+        let ring = IoUring::new(ACCEPT_DEPTH as u32 * 2)?;
+        // SAFETY: eventfd 返回新的文件描述符（失败时返回 -1）
+        let stop = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+        if stop < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: stop 是刚创建的、只属于这里的文件描述符
+        let stop = unsafe { OwnedFd::from_raw_fd(stop) };
+        let thread_stop = stop.try_clone()?;
+        let (tx, rx) = mpsc::channel(ACCEPT_DEPTH);
+        std::thread::Builder::new()
+            .name("sni-uring-accept".to_string())
+            .spawn(move || {
+                // 监听 socket 只由 io_uring 使用，accept 请求在没有新连接时挂起而不是返回 EAGAIN
+                let result = listener.set_nonblocking(false).and_then(|()| run_acceptor(ring, &listener, &thread_stop, &tx));
+                if let Err(e) = result {
+                    error!("io_uring accept 线程退出: {}", e);
+                    let _ = tx.blocking_send(Err(e));
+                }
+            })?;
+        Ok(Self { accepted: Mutex::new(rx), stop })
+    }
+}
+
+impl Drop for UringAcceptor {
+    fn drop(&mut self) {
+        let value: u64 = 1;
+        // SAFETY: stop 是有效的 eventfd，写入 8 字节
+        unsafe { libc::write(self.stop.as_raw_fd(), &value as *const u64 as *const libc::c_void, 8) };
+    }
+}
+
+impl Accept for UringAcceptor {
+    type Stream = UringStream;
+
+    async fn accept(&self) -> io::Result<(UringStream, SocketAddr)> {
+        let accepted = self.accepted.lock().await.recv().await;
+        let (stream, addr) = accepted.unwrap_or_else(|| Err(io::Error::other("io_uring accept 线程已退出")))?;
+        stream.set_nonblocking(true)?;
+        Ok((UringStream(Some(TcpStream::from_std(stream)?)), addr))
+    }
+}
+
+/// accept 线程：保持 [`ACCEPT_DEPTH`] 个 accept 请求在队列中，直到 `stop` 可读或接收端被丢弃
+fn run_acceptor(
+    ring: IoUring,
+    listener: &std::net::TcpListener,
+    stop: &OwnedFd,
+    accepted: &mpsc::Sender<io::Result<(std::net::TcpStream, SocketAddr)>>,
+) -> io::Result<()> {
+    let mut ring = AcceptRing::new(ring, types::Fd(listener.as_raw_fd()));
+    ring.push_stop(types::Fd(stop.as_raw_fd()))?;
+    for slot in 0..ACCEPT_DEPTH {
+        ring.push_accept(slot)?;
+    }
+
+    let mut completed = Vec::with_capacity(ACCEPT_DEPTH + 1);
+    let mut stopping = false;
+    while !stopping {
+        ring.submit_and_wait()?;
+        ring.reap(&mut completed);
+        for (token, result) in completed.drain(..) {
+            if token == STOP_TOKEN {
+                debug!("io_uring accept 线程停止");
+                stopping = true;
+                continue;
+            }
+            let slot = token as usize;
+            let connection = if result < 0 {
+                Err(io::Error::from_raw_os_error(-result))
+            } else {
+                // SAFETY: accept 成功时 result 是新连接的文件描述符，addrs[slot] 已由内核填写
+                let stream = unsafe { std::net::TcpStream::from_raw_fd(result) };
+                ring.peer_addr(slot).map(|addr| (stream, addr)).ok_or_else(|| io::Error::other("无法解析客户端地址"))
+            };
+            // 停止后同一批中已经 accept 的连接直接关闭
+            if stopping || accepted.blocking_send(connection).is_err() {
+                stopping = true;
+                continue;
+            }
+            ring.push_accept(slot)?;
+        }
+    }
+    Ok(())
+}
+
+/// accept 线程的 io_uring 和内核会写入的缓冲区
+///
+/// 缓冲区在还有请求排队时不能释放：丢弃时先取消并收割所有未完成的请求（[`AcceptRing::drain`]），
+/// 之后才释放缓冲区和关闭 ring，所有返回路径（包括出错）都经过这里
+struct AcceptRing {
+    ring: IoUring,
+    listener: types::Fd,
+    /// 每个 accept 请求的客户端地址缓冲区
+    addrs: Box<[(libc::sockaddr_storage, libc::socklen_t)]>,
+    /// eventfd 读取请求的缓冲区
+    stop_buf: Box<[u8; 8]>,
+    /// 还在队列中的 accept 请求
+    accepting: [bool; ACCEPT_DEPTH],
+    /// eventfd 读取请求是否还在队列中
+    stop_pending: bool,
+}
+
+impl AcceptRing {
+    fn new(ring: IoUring, listener: types::Fd) -> Self {
+        Self {
+            ring,
+            listener,
+            // SAFETY: sockaddr_storage 全零是合法值
+            addrs: vec![(unsafe { std::mem::zeroed() }, 0); ACCEPT_DEPTH].into_boxed_slice(),
+            stop_buf: Box::new([0u8; 8]),
+            accepting: [false; ACCEPT_DEPTH],
+            stop_pending: false,
+        }
+    }
+
+    /// 提交 eventfd 读取请求，eventfd 可读时以 [`STOP_TOKEN`] 完成
+    fn push_stop(&mut self, stop: types::Fd) -> io::Result<()> {
+        let entry = opcode::Read::new(stop, self.stop_buf.as_mut_ptr(), 8).build().user_data(STOP_TOKEN);
+        // SAFETY: stop_buf 在请求完成或被取消收割之前不会释放（见 Drop）
+        unsafe { self.ring.submission().push(&entry) }.map_err(|_| io::Error::other("io_uring 提交队列已满"))?;
+        self.stop_pending = true;
+        Ok(())
+    }
+
+    /// 提交一个 accept 请求，客户端地址写入 `addrs[slot]`
+    fn push_accept(&mut self, slot: usize) -> io::Result<()> {
+        let (storage, len) = &mut self.addrs[slot];
+        *len = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+        let entry = opcode::Accept::new(self.listener, storage as *mut _ as *mut libc::sockaddr, len)
+            .flags(libc::SOCK_CLOEXEC)
+            .build()
+            .user_data(slot as u64);
+        // SAFETY: addrs 在请求完成或被取消收割之前不会移动或释放（见 Drop）
+        unsafe { self.ring.submission().push(&entry) }.map_err(|_| io::Error::other("io_uring 提交队列已满"))?;
+        self.accepting[slot] = true;
+        Ok(())
+    }
+
+    fn submit_and_wait(&mut self) -> io::Result<()> {
+        loop {
+            match self.ring.submit_and_wait(1) {
+                Ok(_) => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// 收割完成队列，记录哪些请求已经完成（取消请求本身的完成事件不返回）
+    fn reap(&mut self, completed: &mut Vec<(u64, i32)>) {
+        for entry in self.ring.completion() {
+            match entry.user_data() {
+                CANCEL_TOKEN => continue,
+                STOP_TOKEN => self.stop_pending = false,
+                slot => self.accepting[slot as usize] = false,
+            }
+            completed.push((entry.user_data(), entry.result()));
+        }
+    }
+
+    /// `addrs[slot]` 中的客户端地址（accept 请求成功完成后调用）
+    fn peer_addr(&self, slot: usize) -> Option<SocketAddr> {
+        let (storage, len) = self.addrs[slot];
+        // SAFETY: 内核已按 accept 的约定填写 storage 和 len，len 不超过 sockaddr_storage 的大小
+        unsafe { socket2::SockAddr::new(storage, len) }.as_socket()
+    }
+
+    /// 取消所有还在队列中的请求并等待它们完成，取消前已经 accept 的连接直接关闭
+    fn drain(&mut self) -> io::Result<()> {
+        let pending: Vec<u64> = (0..ACCEPT_DEPTH)
+            .filter(|&slot| self.accepting[slot])
+            .map(|slot| slot as u64)
+            .chain(self.stop_pending.then_some(STOP_TOKEN))
+            .collect();
+        for token in pending {
+            let entry = opcode::AsyncCancel::new(token).build().user_data(CANCEL_TOKEN);
+            // SAFETY: 取消请求不引用任何缓冲区
+            unsafe { self.ring.submission().push(&entry) }.map_err(|_| io::Error::other("io_uring 提交队列已满"))?;
+        }
+        let mut completed = Vec::with_capacity(ACCEPT_DEPTH + 1);
+        while self.stop_pending || self.accepting.contains(&true) {
+            self.submit_and_wait()?;
+            self.reap(&mut completed);
+            for (token, result) in completed.drain(..) {
+                if token != STOP_TOKEN && result >= 0 {
+                    // SAFETY: accept 成功返回的新文件描述符，只属于这里
+                    drop(unsafe { OwnedFd::from_raw_fd(result) });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for AcceptRing {
+    fn drop(&mut self) {
+        if let Err(e) = self.drain() {
+            // 无法确认内核不再写入时宁可泄漏缓冲区，也不能让内核写入已释放的内存
+            error!("取消 io_uring accept 请求失败: {}，泄漏地址缓冲区", e);
+            Box::leak(std::mem::take(&mut self.addrs));
+            Box::leak(std::mem::take(&mut self.stop_buf));
+        }
+    }
+}
+
+/// io_uring 接受的客户端连接
+///
+/// 读取 Client Hello 等握手阶段和普通 TCP 连接一样经过 Tokio；进入转发阶段时取出底层连接交给 io_uring 工作线程，
+/// 之后的读写返回 `NotConnected`
+pub(crate) struct UringStream(Option<TcpStream>);
+
+impl UringStream {
+    fn inner(self: Pin<&mut Self>) -> io::Result<Pin<&mut TcpStream>> {
+        self.get_mut().0.as_mut().map(Pin::new).ok_or_else(|| io::ErrorKind::NotConnected.into())
+    }
+}
+
+impl AsyncRead for UringStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.inner() {
+            Ok(inner) => inner.poll_read(cx, buf),
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+impl AsyncWrite for UringStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.inner() {
+            Ok(inner) => inner.poll_write(cx, buf),
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.inner() {
+            Ok(inner) => inner.poll_flush(cx),
+            Err(_) => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.inner() {
+            Ok(inner) => inner.poll_shutdown(cx),
+            Err(_) => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+impl ClientStream for UringStream {
+    fn apply_tuning(&self, tuning: &TcpTuning) {
+        if let Some(stream) = &self.0 {
+            stream.apply_tuning(tuning);
+        }
+    }
+
+    fn original_destination(&self, mode: TransparentMode, listen_addrs: &[SocketAddr]) -> Option<SocketAddr> {
+        self.0.as_ref()?.original_destination(mode, listen_addrs)
+    }
+
+    fn local_port(&self) -> Option<u16> {
+        self.0.as_ref()?.local_port()
+    }
+
+    fn incoming_cpu(&self) -> Option<usize> {
+        self.0.as_ref()?.incoming_cpu()
+    }
+
+    fn reset_on_close(&self) -> io::Result<()> {
+        self.0.as_ref().map_or(Ok(()), |stream| stream.reset_on_close())
+    }
+
+    fn set_dscp(&self, dscp: u8) -> io::Result<()> {
+        self.0.as_ref().map_or(Ok(()), |stream| stream.set_dscp(dscp))
+    }
+
+    fn set_congestion_control(&self, name: &str) -> io::Result<()> {
+        self.0.as_ref().map_or(Ok(()), |stream| stream.set_congestion_control(name))
+    }
+
+    fn as_tcp_stream(&self) -> Option<&TcpStream> {
+        self.0.as_ref()
+    }
+
+    fn take_tcp_stream(&mut self) -> Option<TcpStream> {
+        self.0.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// 返回一对已连接的 TCP 连接（本端, 对端）
+    async fn tcp_pair(listener: &TcpListener) -> (TcpStream, TcpStream) {
+        let connecting = TcpStream::connect(listener.local_addr().unwrap());
+        let (accepted, connected) = tokio::join!(listener.accept(), connecting);
+        (accepted.unwrap().0, connected.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_uring_relay() {
+        let runtime = UringRuntime::start(2).unwrap();
+        assert_eq!(runtime.workers(), 2);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (client, mut client_peer) = tcp_pair(&listener).await;
+        let (target, mut target_peer) = tcp_pair(&listener).await;
+
+        let metrics = Metrics::new();
+        let relay = UringRelay::new(b"hello".to_vec(), 1024).with_tls_check("example.com", metrics.clone());
+        let relaying = tokio::spawn(async move { runtime.relay(relay, client, target).await });
+
+        let body = vec![7u8; 64 * 1024 + 1];
+        client_peer.write_all(&body).await.unwrap();
+        client_peer.shutdown().await.unwrap();
+        let mut received = Vec::new();
+        let mut buf = vec![0u8; body.len() + 5];
+        while received.len() < buf.len() {
+            let n = target_peer.read(&mut buf).await.unwrap();
+            assert_ne!(n, 0);
+            received.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(&received[..5], b"hello");
+        assert_eq!(&received[5..], &body[..]);
+
+        target_peer.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n").await.unwrap();
+        drop(target_peer);
+        let mut reply = Vec::new();
+        client_peer.read_to_end(&mut reply).await.unwrap();
+        assert!(reply.starts_with(b"HTTP/1.1 400"));
+
+        let (uploaded, downloaded) = relaying.await.unwrap().unwrap();
+        assert_eq!((uploaded, downloaded), (body.len() as u64 + 5, reply.len() as u64));
+        assert_eq!(metrics.snapshot().upstream_not_tls, 1);
+    }
+
+    #[tokio::test]
+    async fn test_uring_accept() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let acceptor = UringAcceptor::new(listener).unwrap();
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let (mut stream, peer) = acceptor.accept().await.unwrap();
+        assert_eq!(peer, client.local_addr().unwrap());
+        assert_eq!(stream.local_port(), Some(addr.port()));
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        // 取出底层连接后不能再读写
+        assert!(stream.take_tcp_stream().is_some());
+        assert_eq!(stream.read(&mut buf).await.unwrap_err().kind(), io::ErrorKind::NotConnected);
+
+        // 停止后监听 socket 被关闭
+        drop(acceptor);
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert!(TcpStream::connect(addr).await.is_err());
+    }
+}
@@ -0,0 +1,645 @@
+//! 白名单文件
+//!
+//! 直连白名单、SOCKS5 白名单和 IP 白名单可以额外从文件加载：每行一条规则，空行和 `#` 开头的行忽略，
+//! 文件中的规则追加在配置文件中的规则之后。运行中监视这些文件（监视所在目录，编辑器先写临时文件再改名也能发现），
+//! 也可以按固定周期重新读取（文件变化通知不可靠时，例如网络文件系统），
+//! 修改后重新构建路由表和 IP 匹配器并整体替换，日志中输出新增和删除的规则；
+//! 已建立的连接不受影响，新连接使用新的规则。文件无法读取或规则无效时保留之前的规则。
+//! 域名列表（直连白名单、SOCKS5 白名单和黑名单文件）还可以直接使用常见的公开列表格式，见 [`parse_domain_list`]。
+//! 从 HTTP 地址获取的规则（见 [`crate::remote_whitelist`]）也在这里合并，追加在文件中的规则之后
+
+use log::{error, info, warn};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::MissedTickBehavior;
+
+use crate::domain::DomainMatcher;
+use crate::error::{Result, SniProxyError};
+use crate::ip_matcher::{IpMatcher, SharedIpMatcher};
+use crate::route_table::SharedRouteTable;
+
+/// 收到文件变化后等待的时间，合并编辑器保存时的多次写入
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// 重新加载日志中最多逐条列出的变化
+const MAX_LOGGED_CHANGES: usize = 20;
+
+/// 白名单文件路径
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WhitelistFiles {
+    /// 直连白名单文件
+    pub direct: Option<PathBuf>,
+    /// SOCKS5 白名单文件
+    pub socks5: Option<PathBuf>,
+    /// IP 白名单文件
+    pub ip: Option<PathBuf>,
+    /// 定期重新读取文件的周期（可选），用于文件变化通知不可靠的场景，例如网络文件系统或容器挂载的配置卷
+    pub reload_interval: Option<Duration>,
+}
+
+impl WhitelistFiles {
+    /// 是否没有配置任何文件
+    pub fn is_empty(&self) -> bool {
+        self.direct.is_none() && self.socks5.is_none() && self.ip.is_none()
+    }
+}
+
+/// 解析白名单文件内容：每行一条规则，忽略空行和 `#` 开头的注释行
+pub fn parse_list(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// hosts 文件中不作为域名规则的本机名称
+const HOSTS_LOCAL_NAMES: [&str; 10] = [
+    "localhost",
+    "localhost.localdomain",
+    "local",
+    "broadcasthost",
+    "ip6-localhost",
+    "ip6-loopback",
+    "ip6-localnet",
+    "ip6-mcastprefix",
+    "ip6-allnodes",
+    "ip6-allrouters",
+];
+
+/// 解析域名列表文件内容，每行可以是以下格式之一（可以混用）：
+///
+/// - 普通规则（同配置文件中的 `whitelist`）：`example.com`、`*.example.com`、`~正则`
+/// - dnsmasq：`address=/example.com/0.0.0.0`、`server=/example.com/1.1.1.1`（`ipset=`、`nftset=`、`local=` 同理，
+///   可以列出多个域名），转换为主域名及所有子域名规则 `+example.com`
+/// - AdGuard / Adblock Plus：`||example.com^`，转换为 `+example.com`；带 `$` 修饰符的条件规则和 `@@` 例外规则忽略
+/// - hosts：`0.0.0.0 example.com www.example.com`，每个主机名转换为精确匹配规则（跳过 `localhost` 等本机名称）
+///
+/// 空行、`#` 和 `!` 开头的注释行以及 `[Adblock Plus 2.0]` 这样的文件头忽略，重复的规则只保留第一条
+pub fn parse_domain_list(content: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut rules = Vec::new();
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with(['#', '!', '[']) {
+            continue;
+        }
+        for rule in parse_domain_line(line) {
+            if seen.insert(rule.clone()) {
+                rules.push(rule);
+            }
+        }
+    }
+    rules
+}
+
+/// 解析域名列表中的一行
+fn parse_domain_line(line: &str) -> Vec<String> {
+    let subdomains = |domain: &str| {
+        let domain = domain.trim_start_matches("*.").trim_start_matches('.');
+        is_plain_domain(domain).then(|| format!("+{}", domain))
+    };
+
+    // dnsmasq: key=/domain1/domain2/value
+    if let Some((key, value)) = line.split_once('=') {
+        if matches!(key.trim(), "address" | "server" | "local" | "ipset" | "nftset") {
+            let Some(value) = value.trim().strip_prefix('/') else {
+                return Vec::new();
+            };
+            let mut parts: Vec<&str> = value.split('/').collect();
+            parts.pop();
+            return parts.into_iter().filter(|&domain| domain != "#").filter_map(subdomains).collect();
+        }
+    }
+
+    // AdGuard / Adblock Plus: ||domain^
+    if let Some(rest) = line.strip_prefix("||") {
+        return rest.strip_suffix('^').and_then(subdomains).into_iter().collect();
+    }
+    if line.starts_with("@@") {
+        return Vec::new();
+    }
+
+    // hosts: IP hostname... [# 注释]
+    let fields: Vec<&str> = line.split('#').next().unwrap_or("").split_whitespace().collect();
+    if fields.len() >= 2 && fields[0].parse::<IpAddr>().is_ok() {
+        return fields[1..]
+            .iter()
+            .copied()
+            .filter(|name| !HOSTS_LOCAL_NAMES.contains(name) && name.parse::<IpAddr>().is_err() && is_plain_domain(name))
+            .map(str::to_string)
+            .collect();
+    }
+
+    vec![line.to_string()]
+}
+
+/// 是否是不含通配符和其他语法的普通域名
+fn is_plain_domain(domain: &str) -> bool {
+    !domain.is_empty() && domain.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_'))
+}
+
+/// 读取文件内容
+fn read_file(path: &Path) -> Result<String> {
+    std::fs::read_to_string(path)
+        .map_err(|e| SniProxyError::InvalidConfig(format!("读取白名单文件 {} 失败: {}", path.display(), e)))
+}
+
+/// 读取并解析白名单文件（每行一条规则，用于 IP 白名单）
+pub fn load_list(path: &Path) -> Result<Vec<String>> {
+    Ok(parse_list(&read_file(path)?))
+}
+
+/// 读取并解析域名列表文件（支持的格式见 [`parse_domain_list`]）
+pub fn load_domain_list(path: &Path) -> Result<Vec<String>> {
+    Ok(parse_domain_list(&read_file(path)?))
+}
+
+/// 两个版本之间新增和删除的规则（保持文件中的顺序）
+fn diff<'a>(old: &'a [String], new: &'a [String]) -> (Vec<&'a str>, Vec<&'a str>) {
+    let old_set: HashSet<&str> = old.iter().map(String::as_str).collect();
+    let new_set: HashSet<&str> = new.iter().map(String::as_str).collect();
+    let added = new.iter().map(String::as_str).filter(|rule| !old_set.contains(rule)).collect();
+    let removed = old.iter().map(String::as_str).filter(|rule| !new_set.contains(rule)).collect();
+    (added, removed)
+}
+
+/// 白名单类别
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ListKind {
+    Direct,
+    Socks5,
+    Ip,
+}
+
+impl ListKind {
+    /// 按类别解析列表内容：域名列表支持常见的列表格式，IP 白名单每行一条规则
+    pub(crate) fn parse(self, content: &str) -> Vec<String> {
+        match self {
+            ListKind::Direct | ListKind::Socks5 => parse_domain_list(content),
+            ListKind::Ip => parse_list(content),
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            ListKind::Direct => "直连白名单",
+            ListKind::Socks5 => "SOCKS5 白名单",
+            ListKind::Ip => "IP 白名单",
+        }
+    }
+}
+
+/// 一个白名单：配置文件中的规则 + 文件中的规则 + 远程获取的规则
+#[derive(Debug)]
+struct ListSource {
+    kind: ListKind,
+    /// 配置文件中的规则和运行中通过 API 添加的规则
+    inline: Vec<String>,
+    /// 白名单文件（可选）
+    path: Option<PathBuf>,
+    /// 当前从文件加载的规则
+    loaded: Vec<String>,
+    /// 当前从远程地址获取的规则
+    remote: Vec<String>,
+}
+
+impl ListSource {
+    fn new(kind: ListKind, inline: Vec<String>, path: Option<PathBuf>, remote: Vec<String>) -> Self {
+        Self { kind, inline, path, loaded: Vec::new(), remote }
+    }
+
+    fn path_display(&self) -> String {
+        self.path.as_deref().map(|path| path.display().to_string()).unwrap_or_default()
+    }
+
+    /// 生效的全部规则
+    fn rules(&self) -> Vec<String> {
+        self.inline.iter().chain(&self.loaded).chain(&self.remote).cloned().collect()
+    }
+
+    /// 读取并检查文件中的规则（没有配置文件时返回 None）
+    fn read(&self) -> Option<Result<Vec<String>>> {
+        let path = self.path.as_deref()?;
+        Some(read_file(path).and_then(|content| {
+            let rules = self.kind.parse(&content);
+            if self.kind != ListKind::Ip {
+                DomainMatcher::validate_rules(&rules)?;
+            }
+            Ok(rules)
+        }))
+    }
+
+    /// 替换文件（`remote` 为 false）或远程地址中的规则并记录变化，返回是否有变化
+    fn replace(&mut self, remote: bool, source: &str, rules: Vec<String>) -> bool {
+        let (current, other) = if remote { (&mut self.remote, &self.loaded) } else { (&mut self.loaded, &self.remote) };
+        if rules == *current {
+            return false;
+        }
+        if self.kind == ListKind::Ip && self.inline.is_empty() && other.is_empty() && rules.is_empty() {
+            // 空的 IP 白名单表示不检查客户端 IP，不能因为名单被清空而放开所有客户端
+            warn!("⚠️  {} 为空，继续使用之前的 IP 白名单（要关闭 IP 白名单请修改配置文件）", source);
+            return false;
+        }
+
+        let (added, removed) = diff(current, &rules);
+        info!(
+            "🔄 {}: {} 已重新加载，{} 条规则（新增 {}，删除 {}）",
+            self.kind.name(),
+            source,
+            rules.len(),
+            added.len(),
+            removed.len()
+        );
+        let changes = added.iter().map(|rule| ('+', rule)).chain(removed.iter().map(|rule| ('-', rule)));
+        for (sign, rule) in changes.clone().take(MAX_LOGGED_CHANGES) {
+            info!("  {} {}", sign, rule);
+        }
+        if changes.count() > MAX_LOGGED_CHANGES {
+            info!("  ... 还有 {} 条变化", added.len() + removed.len() - MAX_LOGGED_CHANGES);
+        }
+        *current = rules;
+        true
+    }
+}
+
+/// 白名单的加载和重新加载：配置的规则、运行中通过 API 增删的规则、白名单文件和远程白名单
+#[derive(Debug)]
+pub(crate) struct WhitelistReloader {
+    lists: [ListSource; 3],
+    routes: SharedRouteTable,
+    ip_matcher: SharedIpMatcher,
+}
+
+impl WhitelistReloader {
+    /// 使用配置的直连白名单、SOCKS5 白名单和 IP 白名单创建（不修改路由表和 IP 白名单）
+    pub(crate) fn new(inline: [Vec<String>; 3], routes: SharedRouteTable, ip_matcher: SharedIpMatcher) -> Self {
+        let [direct, socks5, ip] = inline;
+        Self {
+            lists: [
+                ListSource::new(ListKind::Direct, direct, None, Vec::new()),
+                ListSource::new(ListKind::Socks5, socks5, None, Vec::new()),
+                ListSource::new(ListKind::Ip, ip, None, Vec::new()),
+            ],
+            routes,
+            ip_matcher,
+        }
+    }
+
+    /// 替换配置的规则（不修改路由表和 IP 白名单）
+    pub(crate) fn set_inline(&mut self, kind: ListKind, rules: Vec<String>) {
+        self.list_mut(kind).inline = rules;
+    }
+
+    fn list_mut(&mut self, kind: ListKind) -> &mut ListSource {
+        let index = match kind {
+            ListKind::Direct => 0,
+            ListKind::Socks5 => 1,
+            ListKind::Ip => 2,
+        };
+        &mut self.lists[index]
+    }
+
+    /// 首次加载文件，和首次获取的远程规则一起替换路由表和 IP 白名单
+    ///
+    /// 文件无法读取、规则无效，或 IP 白名单文件和远程地址都没有规则（会放开所有客户端）时返回错误
+    pub(crate) fn load(&mut self, files: WhitelistFiles, remote: [Option<Vec<String>>; 3]) -> Result<()> {
+        let [direct_remote, socks5_remote, ip_remote] = remote;
+        let ip_configured = files.ip.is_some() || ip_remote.is_some();
+        let routes_configured = files.direct.is_some() || files.socks5.is_some() || direct_remote.is_some() || socks5_remote.is_some();
+        let sources = [(files.direct, direct_remote), (files.socks5, socks5_remote), (files.ip, ip_remote)];
+        for (list, (path, remote)) in self.lists.iter_mut().zip(sources) {
+            list.path = path;
+            list.remote = remote.unwrap_or_default();
+            if let Some(rules) = list.read() {
+                list.loaded = rules?;
+                info!("✅ {}: 从 {} 加载了 {} 条规则", list.kind.name(), list.path_display(), list.loaded.len());
+            }
+        }
+        if ip_configured && self.lists[2].rules().is_empty() {
+            return Err(SniProxyError::InvalidConfig(
+                "IP 白名单文件和远程地址中没有任何规则（空的 IP 白名单会允许所有客户端）".to_string(),
+            ));
+        }
+        self.apply(routes_configured, ip_configured);
+        Ok(())
+    }
+
+    /// 运行中添加一条规则（追加在配置的规则之后），返回是否新增（规则已存在时返回 false）
+    pub(crate) fn add_rule(&mut self, kind: ListKind, rule: &str) -> Result<bool> {
+        let rule = rule.trim();
+        if rule.is_empty() {
+            return Err(SniProxyError::InvalidConfig(format!("{}规则不能为空", kind.name())));
+        }
+        if kind != ListKind::Ip {
+            DomainMatcher::validate_rules(&[rule.to_string()])?;
+        } else if !IpMatcher::is_valid_pattern(rule) {
+            return Err(SniProxyError::InvalidConfig(format!("无效的 IP 或 CIDR: {}", rule)));
+        }
+        let list = self.list_mut(kind);
+        if list.inline.iter().any(|existing| existing == rule) {
+            return Ok(false);
+        }
+        if kind == ListKind::Ip && list.rules().is_empty() {
+            // 空的 IP 白名单表示不检查客户端 IP，添加一条规则会拒绝其他所有客户端
+            return Err(SniProxyError::InvalidConfig(
+                "未配置 IP 白名单（允许所有客户端），不能在运行中添加规则".to_string(),
+            ));
+        }
+        list.inline.push(rule.to_string());
+        info!("🔄 {}: 添加了规则 {}", kind.name(), rule);
+        self.apply(kind != ListKind::Ip, kind == ListKind::Ip);
+        Ok(true)
+    }
+
+    /// 运行中删除一条配置的或通过 API 添加的规则，返回是否删除
+    ///
+    /// 白名单文件和远程地址中的规则需要在来源处删除，否则下次重新加载时又会生效
+    pub(crate) fn remove_rule(&mut self, kind: ListKind, rule: &str) -> bool {
+        let rule = rule.trim();
+        let list = self.list_mut(kind);
+        let Some(index) = list.inline.iter().position(|existing| existing == rule) else {
+            return false;
+        };
+        if kind == ListKind::Ip && list.rules().len() == 1 {
+            // 空的 IP 白名单表示不检查客户端 IP
+            warn!("⚠️  不能删除 IP 白名单中的最后一条规则 {}（要关闭 IP 白名单请修改配置文件）", rule);
+            return false;
+        }
+        list.inline.remove(index);
+        info!("🔄 {}: 删除了规则 {}", kind.name(), rule);
+        self.apply(kind != ListKind::Ip, kind == ListKind::Ip);
+        true
+    }
+
+    /// 需要监视的文件
+    fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.lists.iter().filter_map(|list| list.path.as_deref())
+    }
+
+    /// 重新读取所有文件，有变化时替换规则，返回是否有变化
+    fn reload(&mut self) -> bool {
+        let mut changed = [false; 3];
+        for (list, changed) in self.lists.iter_mut().zip(&mut changed) {
+            let rules = match list.read() {
+                None => continue,
+                Some(Ok(rules)) => rules,
+                Some(Err(e)) => {
+                    warn!("⚠️  重新加载{}失败: {}，继续使用之前的规则", list.kind.name(), e);
+                    continue;
+                }
+            };
+            let source = list.path_display();
+            *changed = list.replace(false, &source, rules);
+        }
+        let [direct, socks5, ip] = changed;
+        self.apply(direct || socks5, ip);
+        changed.contains(&true)
+    }
+
+    /// 替换一个白名单中远程获取的规则，有变化时重建，返回是否有变化
+    pub(crate) fn replace_remote(&mut self, kind: ListKind, source: &str, rules: Vec<String>) -> bool {
+        if !self.list_mut(kind).replace(true, source, rules) {
+            return false;
+        }
+        self.apply(kind != ListKind::Ip, kind == ListKind::Ip);
+        true
+    }
+
+    /// 用当前规则重建路由表（`routes_changed` 为 true 时）和 IP 白名单（`ip_changed` 为 true 时）
+    fn apply(&self, routes_changed: bool, ip_changed: bool) {
+        let [direct, socks5, ip] = &self.lists;
+        if routes_changed {
+            let table = self.routes.load().with_whitelists(direct.rules(), socks5.rules());
+            self.routes.store(Arc::new(table));
+        }
+        if ip_changed {
+            let matcher = IpMatcher::new(ip.rules());
+            self.ip_matcher.store((!matcher.is_empty()).then(|| Arc::new(matcher)));
+        }
+    }
+}
+
+/// 监视白名单文件，修改后重新加载（监视器无法创建时只记录错误，之前加载的规则继续生效）
+pub(crate) async fn run_whitelist_watcher(reloader: Arc<Mutex<WhitelistReloader>>, reload_interval: Option<Duration>) {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut watcher = match notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let _ = tx.send(event);
+    }) {
+        Ok(watcher) => Some(watcher),
+        Err(e) if reload_interval.is_some() => {
+            warn!("⚠️  无法监视白名单文件: {}，只按周期重新加载", e);
+            None
+        }
+        Err(e) => {
+            error!("无法监视白名单文件: {}，修改后需要重启才能生效", e);
+            return;
+        }
+    };
+
+    // 监视文件所在的目录：编辑器保存时常常用新文件替换原文件，直接监视文件会丢失之后的修改
+    let (file_names, dirs) = {
+        let reloader = reloader.lock().unwrap_or_else(|e| e.into_inner());
+        let file_names: HashSet<_> = reloader.paths().filter_map(|path| path.file_name().map(|n| n.to_owned())).collect();
+        let mut dirs: Vec<PathBuf> = reloader
+            .paths()
+            .map(|path| match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                _ => PathBuf::from("."),
+            })
+            .collect();
+        dirs.sort();
+        dirs.dedup();
+        (file_names, dirs)
+    };
+    if let Some(watcher) = &mut watcher {
+        for dir in &dirs {
+            if let Err(e) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+                error!("无法监视目录 {}: {}，其中的白名单文件修改后需要重启才能生效", dir.display(), e);
+            }
+        }
+    }
+    let mut timer = reload_interval.map(|period| {
+        let mut timer = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        timer
+    });
+    match reload_interval {
+        Some(period) => info!("✅ 白名单文件监视已启用（{} 个文件，另外每 {:?} 重新读取）", file_names.len(), period),
+        None => info!("✅ 白名单文件监视已启用（{} 个文件）", file_names.len()),
+    }
+
+    let relevant = |event: &notify::Result<notify::Event>| match event {
+        Ok(event) => {
+            !matches!(event.kind, EventKind::Access(_))
+                && event
+                    .paths
+                    .iter()
+                    .any(|path| path.file_name().is_some_and(|name| file_names.contains(name)))
+        }
+        Err(e) => {
+            warn!("⚠️  白名单文件监视出错: {}", e);
+            false
+        }
+    };
+
+    loop {
+        tokio::select! {
+            event = rx.recv(), if watcher.is_some() => {
+                let Some(event) = event else {
+                    return;
+                };
+                if !relevant(&event) {
+                    continue;
+                }
+                tokio::time::sleep(RELOAD_DEBOUNCE).await;
+                while rx.try_recv().is_ok() {}
+            }
+            // 周期重新读取：文件没有变化时不重建
+            _ = async { timer.as_mut().expect("已配置重新读取周期").tick().await }, if timer.is_some() => {}
+        }
+        reloader.lock().unwrap_or_else(|e| e.into_inner()).reload();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::route_table::{RouteQuery, RouteTable};
+    use arc_swap::{ArcSwap, ArcSwapOption};
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_list() {
+        let rules = parse_list("# 注释\nexample.com\n\n  *.example.org  \r\n#disabled.com\n~^api\\d+\\.example\\.net$\n");
+        assert_eq!(rules, ["example.com", "*.example.org", "~^api\\d+\\.example\\.net$"]);
+        let (old, new) = (strings(&["a.com", "b.com"]), strings(&["b.com", "c.com"]));
+        assert_eq!(diff(&old, &new), (vec!["c.com"], vec!["a.com"]));
+    }
+
+    #[test]
+    fn test_parse_domain_list() {
+        let content = "\
+[Adblock Plus 2.0]
+! Title: 示例列表
+||ads.example.com^
+||cond.example.com^$third-party
+@@||allowed.example.com^
+address=/dnsmasq.example.com/0.0.0.0
+server=/a.example.org/b.example.org/1.1.1.1
+address=/#/0.0.0.0
+127.0.0.1 localhost
+0.0.0.0 tracker.example.net www.tracker.example.net # 注释
+192.0.2.1
+plain.example.com:8443
+~^cdn\\d+\\.example\\.com$
+||ads.example.com^
+";
+        assert_eq!(
+            parse_domain_list(content),
+            [
+                "+ads.example.com",
+                "+dnsmasq.example.com",
+                "+a.example.org",
+                "+b.example.org",
+                "tracker.example.net",
+                "www.tracker.example.net",
+                "192.0.2.1",
+                "plain.example.com:8443",
+                "~^cdn\\d+\\.example\\.com$",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reload() {
+        let dir = std::env::temp_dir().join(format!("sni-proxy-whitelist-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let direct_file = dir.join("direct.txt");
+        let ip_file = dir.join("ip.txt");
+        std::fs::write(&direct_file, "a.example.com\n").unwrap();
+        std::fs::write(&ip_file, "10.0.0.0/8\n").unwrap();
+
+        let routes: SharedRouteTable = Arc::new(ArcSwap::from_pointee(RouteTable::from_whitelists(strings(&["inline.com"]), Vec::new())));
+        let ip_matcher: SharedIpMatcher = Arc::new(ArcSwapOption::empty());
+        let files = WhitelistFiles {
+            direct: Some(direct_file.clone()),
+            ip: Some(ip_file.clone()),
+            ..Default::default()
+        };
+        let mut reloader =
+            WhitelistReloader::new([strings(&["inline.com"]), Vec::new(), Vec::new()], Arc::clone(&routes), Arc::clone(&ip_matcher));
+        reloader.load(files, [Some(strings(&["remote.example.com"])), None, None]).unwrap();
+
+        let allowed = |domain: &str| {
+            let query = RouteQuery { domain, client_ip: "10.0.0.1".parse().unwrap(), country: None, alpn: &[] };
+            routes.load().lookup(&query).is_some()
+        };
+        let ip_allowed = |ip: &str| ip_matcher.load().as_ref().is_some_and(|m| m.matches(ip.parse().unwrap()));
+        assert!(allowed("inline.com") && allowed("a.example.com") && allowed("remote.example.com") && !allowed("b.example.com"));
+        assert!(ip_allowed("10.1.2.3") && !ip_allowed("192.0.2.1"));
+
+        std::fs::write(&direct_file, "b.example.com\n").unwrap();
+        assert!(reloader.reload());
+        assert!(allowed("inline.com") && !allowed("a.example.com") && allowed("b.example.com"));
+        assert!(!reloader.reload());
+
+        // 远程规则和文件规则分别替换
+        assert!(reloader.replace_remote(ListKind::Direct, "http://example.com/", strings(&["c.example.com"])));
+        assert!(!reloader.replace_remote(ListKind::Direct, "http://example.com/", strings(&["c.example.com"])));
+        assert!(allowed("b.example.com") && allowed("c.example.com") && !allowed("remote.example.com"));
+
+        // 无效的规则和清空的 IP 白名单不生效
+        std::fs::write(&direct_file, "~(unclosed\n").unwrap();
+        std::fs::write(&ip_file, "# 暂时清空\n").unwrap();
+        assert!(!reloader.reload());
+        assert!(allowed("b.example.com"));
+        assert!(ip_allowed("10.1.2.3"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_add_remove_rule() {
+        let routes: SharedRouteTable = Arc::new(ArcSwap::from_pointee(RouteTable::from_whitelists(strings(&["inline.com"]), Vec::new())));
+        let ip_matcher: SharedIpMatcher = Arc::new(ArcSwapOption::empty());
+        let mut whitelists = WhitelistReloader::new(
+            [strings(&["inline.com"]), Vec::new(), strings(&["10.0.0.1"])],
+            Arc::clone(&routes),
+            Arc::clone(&ip_matcher),
+        );
+        let allowed = |domain: &str| {
+            let query = RouteQuery { domain, client_ip: "10.0.0.1".parse().unwrap(), country: None, alpn: &[] };
+            routes.load().lookup(&query).is_some()
+        };
+
+        assert!(whitelists.add_rule(ListKind::Direct, " *.added.com ").unwrap());
+        assert!(!whitelists.add_rule(ListKind::Direct, "*.added.com").unwrap());
+        assert!(whitelists.add_rule(ListKind::Direct, "~(unclosed").is_err());
+        assert!(allowed("api.added.com") && allowed("inline.com"));
+
+        assert!(whitelists.remove_rule(ListKind::Direct, "inline.com"));
+        assert!(!whitelists.remove_rule(ListKind::Direct, "inline.com"));
+        assert!(!allowed("inline.com") && allowed("api.added.com"));
+
+        // 不能删除 IP 白名单中的最后一条规则
+        assert!(!whitelists.remove_rule(ListKind::Ip, "10.0.0.1"));
+        assert!(whitelists.add_rule(ListKind::Ip, "192.168.0.0/16").unwrap());
+        assert!(whitelists.add_rule(ListKind::Ip, "192.168.0.0/33").is_err());
+        assert!(ip_matcher.load().as_ref().unwrap().matches("192.168.1.1".parse().unwrap()));
+        assert!(whitelists.remove_rule(ListKind::Ip, "10.0.0.1"));
+        assert!(!ip_matcher.load().as_ref().unwrap().matches("10.0.0.1".parse().unwrap()));
+
+        // 没有配置 IP 白名单时不能添加（否则会拒绝其他所有客户端）
+        let mut open = WhitelistReloader::new(Default::default(), Arc::clone(&routes), Arc::new(ArcSwapOption::empty()));
+        assert!(open.add_rule(ListKind::Ip, "10.0.0.1").is_err());
+    }
+}